@@ -0,0 +1,42 @@
+// Fingerprints the files in static/ for src/assets.rs: each one is embedded
+// in the binary under a name carrying a hash of its contents, e.g. app.css as
+// app.3f2a1b9c.css, so changing a file changes its URL.
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    println!("cargo:rerun-if-changed=static");
+    let dir = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("static");
+    let mut names: Vec<String> = fs::read_dir(&dir)
+        .expect("static/ directory")
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+
+    let mut out = String::from("// Generated by build.rs from static/\n\npub const ASSETS: &[Asset] = &[\n");
+    for name in names {
+        let path = dir.join(&name);
+        let hash = fnv1a(&fs::read(&path).unwrap()) as u32;
+        let fingerprinted = match name.rsplit_once('.') {
+            Some((stem, ext)) => format!("{}.{:08x}.{}", stem, hash, ext),
+            None => format!("{}.{:08x}", name, hash),
+        };
+        out.push_str(&format!(
+            "    Asset {{ name: {:?}, path: {:?}, bytes: include_bytes!({:?}) }},\n",
+            name,
+            fingerprinted,
+            path.display().to_string()
+        ));
+    }
+    out.push_str("];\n");
+    fs::write(Path::new(&env::var("OUT_DIR").unwrap()).join("assets.rs"), out).unwrap();
+}
+
+// 64-bit FNV-1a: the same on every build and toolchain, which is all a cache
+// key needs
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
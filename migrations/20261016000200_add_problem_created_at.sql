@@ -0,0 +1,2 @@
+-- SQLite cannot ADD COLUMN with a CURRENT_TIMESTAMP default; inserts set it explicitly
+ALTER TABLE problems ADD COLUMN created_at DATETIME;
@@ -0,0 +1,15 @@
+CREATE TABLE entity_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    entity_type TEXT NOT NULL, -- 'log_item' or 'problem'
+    entity_id INTEGER NOT NULL,
+    course_id INTEGER,
+    action TEXT NOT NULL, -- 'update', 'delete' or 'revert'
+    before_json TEXT, -- NULL when the entity did not exist (reverting a delete)
+    after_json TEXT, -- NULL when the entity was deleted
+    user_id INTEGER,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX idx_entity_history_entity ON entity_history(entity_type, entity_id);
+CREATE INDEX idx_entity_history_course ON entity_history(course_id);
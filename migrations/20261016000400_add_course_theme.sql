@@ -0,0 +1,2 @@
+ALTER TABLE courses ADD COLUMN public_theme TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE courses ADD COLUMN banner_url TEXT;
@@ -0,0 +1,10 @@
+-- Per-course ordering and visibility of log item kinds. Courses without rows
+-- fall back to the built-in order with every kind enabled.
+CREATE TABLE log_item_kinds (
+    course_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    PRIMARY KEY (course_id, kind),
+    FOREIGN KEY (course_id) REFERENCES courses(id)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE courses ADD COLUMN units REAL;
+ALTER TABLE courses ADD COLUMN grade TEXT; -- letter grade (A-, B+, ...) or P/NP
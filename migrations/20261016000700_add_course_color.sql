@@ -0,0 +1 @@
+ALTER TABLE courses ADD COLUMN color TEXT; -- #rrggbb, NULL uses the default gray
@@ -0,0 +1,13 @@
+-- Spaced-repetition state per user per problem for the keyboard review flow.
+-- Problems without a row are new and due immediately.
+CREATE TABLE study_cards (
+    user_id INTEGER NOT NULL,
+    problem_id INTEGER NOT NULL,
+    interval_days REAL NOT NULL DEFAULT 0,
+    reviews INTEGER NOT NULL DEFAULT 0,
+    last_grade INTEGER,
+    due_at DATETIME NOT NULL,
+    PRIMARY KEY (user_id, problem_id),
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (problem_id) REFERENCES problems(id)
+);
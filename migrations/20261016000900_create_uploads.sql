@@ -0,0 +1,8 @@
+-- Content-addressed upload files (uploads/<ab>/<rest of sha256>.<ext>) and how
+-- many problems/courses reference each one. Legacy uuid-named files have no row.
+CREATE TABLE uploads (
+    path TEXT PRIMARY KEY NOT NULL,
+    size INTEGER NOT NULL,
+    ref_count INTEGER NOT NULL DEFAULT 0,
+    created_at DATETIME
+);
@@ -0,0 +1,3 @@
+-- What the uploaded file was called and its MIME type; the stored name is a content hash
+ALTER TABLE problems ADD COLUMN original_filename TEXT;
+ALTER TABLE problems ADD COLUMN mime_type TEXT;
@@ -0,0 +1,22 @@
+-- Background work queue drained by the worker in src/jobs.rs. Finished jobs are
+-- deleted; jobs that keep failing stay behind with their last error.
+CREATE TABLE jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    run_after DATETIME NOT NULL,
+    last_error TEXT,
+    created_at DATETIME
+);
+CREATE INDEX idx_jobs_run_after ON jobs (run_after);
+
+-- Title/domain/favicon for URLs found in log item descriptions
+CREATE TABLE link_previews (
+    url TEXT PRIMARY KEY NOT NULL,
+    domain TEXT NOT NULL,
+    title TEXT,
+    favicon_url TEXT,
+    status TEXT NOT NULL DEFAULT 'pending', -- pending | ok | failed
+    fetched_at DATETIME
+);
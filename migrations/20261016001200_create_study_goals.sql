@@ -0,0 +1,27 @@
+-- One row per graded card in the keyboard review flow. study_cards only keeps
+-- the latest state, so goal progress is counted from this log.
+CREATE TABLE study_reviews (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    problem_id INTEGER NOT NULL,
+    grade INTEGER NOT NULL,
+    reviewed_at DATETIME NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (problem_id) REFERENCES problems(id)
+);
+CREATE INDEX idx_study_reviews_user ON study_reviews (user_id, reviewed_at);
+
+-- "Redo <target> problems in <category> by <deadline>"; progress counts the
+-- distinct problems in the category reviewed since the goal was set
+CREATE TABLE study_goals (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    course_id INTEGER NOT NULL,
+    category_id INTEGER NOT NULL,
+    target INTEGER NOT NULL,
+    deadline DATE,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (course_id) REFERENCES courses(id),
+    FOREIGN KEY (category_id) REFERENCES categories(id)
+);
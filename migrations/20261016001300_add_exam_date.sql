@@ -0,0 +1,2 @@
+-- When the exam takes place (YYYY-MM-DD); drives the dashboard countdown
+ALTER TABLE exams ADD COLUMN exam_date DATE;
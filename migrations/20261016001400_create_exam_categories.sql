@@ -0,0 +1,9 @@
+-- Categories an exam covers, set by hand on the exam page. Exams without any
+-- rows fall back to the categories of their attached problems.
+CREATE TABLE exam_categories (
+    exam_id INTEGER NOT NULL,
+    category_id INTEGER NOT NULL,
+    PRIMARY KEY (exam_id, category_id),
+    FOREIGN KEY (exam_id) REFERENCES exams(id),
+    FOREIGN KEY (category_id) REFERENCES categories(id)
+);
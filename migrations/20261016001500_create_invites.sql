@@ -0,0 +1,15 @@
+-- The first account is the instance admin; it manages invite codes
+ALTER TABLE users ADD COLUMN is_admin INTEGER NOT NULL DEFAULT 0;
+UPDATE users SET is_admin = 1 WHERE id = (SELECT MIN(id) FROM users);
+
+-- Single-use registration codes for `invite_only` instances. used_at is set
+-- when a code is claimed, used_by once the account exists.
+CREATE TABLE invites (
+    code TEXT PRIMARY KEY NOT NULL,
+    created_by INTEGER NOT NULL,
+    created_at DATETIME NOT NULL,
+    used_by INTEGER,
+    used_at DATETIME,
+    FOREIGN KEY (created_by) REFERENCES users(id),
+    FOREIGN KEY (used_by) REFERENCES users(id)
+);
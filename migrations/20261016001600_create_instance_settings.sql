@@ -0,0 +1,5 @@
+-- Instance-wide settings chosen in the first-run setup wizard
+CREATE TABLE instance_settings (
+    key TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Worked solution written in the app (Markdown with $…$ / $$…$$ math)
+ALTER TABLE problems ADD COLUMN answer TEXT;
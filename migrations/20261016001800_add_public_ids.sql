@@ -0,0 +1,68 @@
+-- Random, non-sequential ids for public pages and the API, so URLs don't reveal
+-- how many rows exist or let anyone step through them. Integer ids stay the
+-- primary keys everywhere else.
+--
+-- Values are UUIDv7 (48-bit millisecond timestamp, then random bits). SQLite
+-- can't ADD COLUMN with a non-constant default, so existing rows are filled
+-- here and a trigger fills new ones; inserts that already set public_id (a
+-- history revert bringing a row back) keep theirs.
+
+ALTER TABLE courses ADD COLUMN public_id TEXT;
+UPDATE courses SET public_id = lower(
+    substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 1, 8) || '-' ||
+    substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 9, 4) || '-7' ||
+    substr(hex(randomblob(2)), 2, 3) || '-' ||
+    substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2, 3) || '-' ||
+    hex(randomblob(6))
+);
+CREATE UNIQUE INDEX idx_courses_public_id ON courses(public_id);
+CREATE TRIGGER courses_public_id AFTER INSERT ON courses WHEN NEW.public_id IS NULL
+BEGIN
+    UPDATE courses SET public_id = lower(
+        substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 1, 8) || '-' ||
+        substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 9, 4) || '-7' ||
+        substr(hex(randomblob(2)), 2, 3) || '-' ||
+        substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2, 3) || '-' ||
+        hex(randomblob(6))
+    ) WHERE id = NEW.id;
+END;
+
+ALTER TABLE log_items ADD COLUMN public_id TEXT;
+UPDATE log_items SET public_id = lower(
+    substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 1, 8) || '-' ||
+    substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 9, 4) || '-7' ||
+    substr(hex(randomblob(2)), 2, 3) || '-' ||
+    substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2, 3) || '-' ||
+    hex(randomblob(6))
+);
+CREATE UNIQUE INDEX idx_log_items_public_id ON log_items(public_id);
+CREATE TRIGGER log_items_public_id AFTER INSERT ON log_items WHEN NEW.public_id IS NULL
+BEGIN
+    UPDATE log_items SET public_id = lower(
+        substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 1, 8) || '-' ||
+        substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 9, 4) || '-7' ||
+        substr(hex(randomblob(2)), 2, 3) || '-' ||
+        substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2, 3) || '-' ||
+        hex(randomblob(6))
+    ) WHERE id = NEW.id;
+END;
+
+ALTER TABLE problems ADD COLUMN public_id TEXT;
+UPDATE problems SET public_id = lower(
+    substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 1, 8) || '-' ||
+    substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 9, 4) || '-7' ||
+    substr(hex(randomblob(2)), 2, 3) || '-' ||
+    substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2, 3) || '-' ||
+    hex(randomblob(6))
+);
+CREATE UNIQUE INDEX idx_problems_public_id ON problems(public_id);
+CREATE TRIGGER problems_public_id AFTER INSERT ON problems WHEN NEW.public_id IS NULL
+BEGIN
+    UPDATE problems SET public_id = lower(
+        substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 1, 8) || '-' ||
+        substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 9, 4) || '-7' ||
+        substr(hex(randomblob(2)), 2, 3) || '-' ||
+        substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2, 3) || '-' ||
+        hex(randomblob(6))
+    ) WHERE id = NEW.id;
+END;
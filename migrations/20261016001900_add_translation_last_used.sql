@@ -0,0 +1,5 @@
+-- When a cached translation was last read, so the job runner can drop ones
+-- nobody has looked at in a while (see translate::prune)
+ALTER TABLE translations ADD COLUMN last_used_at DATETIME;
+UPDATE translations SET last_used_at = COALESCE(created_at, CURRENT_TIMESTAMP);
+CREATE INDEX idx_translations_last_used ON translations(last_used_at);
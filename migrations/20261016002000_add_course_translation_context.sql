@@ -0,0 +1,6 @@
+-- What the LLM is told about a course when translating its content
+-- (see Course::translation_context). An empty subject falls back to the
+-- course code and title.
+ALTER TABLE courses ADD COLUMN translation_subject TEXT;
+ALTER TABLE courses ADD COLUMN translation_level TEXT NOT NULL DEFAULT 'undergraduate';
+ALTER TABLE courses ADD COLUMN translation_style TEXT NOT NULL DEFAULT 'academic';
@@ -0,0 +1,2 @@
+-- Ask search engines to stay away from a course's public pages
+ALTER TABLE courses ADD COLUMN discourage_indexing INTEGER NOT NULL DEFAULT 0;
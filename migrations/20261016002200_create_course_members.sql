@@ -0,0 +1,15 @@
+-- Join codes let a whole class add themselves to a course at /join. Every
+-- member gets the course's join_role at the time they joined.
+ALTER TABLE courses ADD COLUMN join_code TEXT;
+ALTER TABLE courses ADD COLUMN join_role TEXT NOT NULL DEFAULT 'viewer';
+CREATE UNIQUE INDEX idx_courses_join_code ON courses (join_code);
+
+CREATE TABLE course_members (
+    course_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    role TEXT NOT NULL,
+    joined_at DATETIME NOT NULL,
+    PRIMARY KEY (course_id, user_id),
+    FOREIGN KEY (course_id) REFERENCES courses(id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
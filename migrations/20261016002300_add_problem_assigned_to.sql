@@ -0,0 +1,3 @@
+-- Who in a shared course is writing up the solution to a problem
+ALTER TABLE problems ADD COLUMN assigned_to INTEGER REFERENCES users(id);
+CREATE INDEX idx_problems_assigned_to ON problems (assigned_to);
@@ -0,0 +1,32 @@
+-- Per-course discussion board. A thread's opening text is its first post.
+CREATE TABLE discussion_threads (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    course_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY (course_id) REFERENCES courses(id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+CREATE INDEX idx_discussion_threads_course ON discussion_threads (course_id);
+
+CREATE TABLE discussion_posts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    thread_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    body TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY (thread_id) REFERENCES discussion_threads(id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+CREATE INDEX idx_discussion_posts_thread ON discussion_posts (thread_id);
+
+-- The newest post each user has seen per thread; anything newer is unread
+CREATE TABLE discussion_reads (
+    user_id INTEGER NOT NULL,
+    thread_id INTEGER NOT NULL,
+    last_read_post_id INTEGER NOT NULL,
+    PRIMARY KEY (user_id, thread_id),
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (thread_id) REFERENCES discussion_threads(id)
+);
@@ -0,0 +1,10 @@
+-- Weekly activity email. A row means the user opted in; unsubscribing deletes it.
+CREATE TABLE digest_subscriptions (
+    user_id INTEGER PRIMARY KEY,
+    email TEXT NOT NULL,
+    weekday INTEGER NOT NULL DEFAULT 1, -- 1 = Monday … 7 = Sunday
+    hour INTEGER NOT NULL DEFAULT 8, -- server local time
+    unsubscribe_token TEXT NOT NULL UNIQUE,
+    last_sent_at DATETIME,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
@@ -0,0 +1,158 @@
+-- ON DELETE actions for the rows that belong to a problem, exam or discussion
+-- thread, so deleting the parent takes them along instead of every delete
+-- handler cascading by hand (and failing on the tables it forgot, such as a
+-- problem's study cards). A problem's assignee is cleared if the user goes.
+--
+-- SQLite can't change a table's constraints, so each table is rebuilt. The
+-- children of `problems` are set aside first: dropping a parent deletes its
+-- rows, which would otherwise cascade into (or be refused by) the children.
+-- Rows already pointing at nothing are not copied over, since they would fail
+-- the constraints. AUTOINCREMENT counters are carried over so ids of deleted
+-- rows are never handed out again (history reverts re-insert by id).
+
+CREATE TABLE sequence_backup AS SELECT name, seq FROM sqlite_sequence WHERE name IN ('problems', 'study_reviews', 'discussion_posts');
+
+CREATE TABLE problem_categories_backup AS SELECT * FROM problem_categories;
+CREATE TABLE study_cards_backup AS SELECT * FROM study_cards;
+CREATE TABLE study_reviews_backup AS SELECT * FROM study_reviews;
+DROP TABLE problem_categories;
+DROP TABLE study_cards;
+DROP TABLE study_reviews;
+
+-- Problems
+CREATE TABLE problems_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    log_item_id INTEGER,
+    exam_id INTEGER,
+    description TEXT NOT NULL,
+    notes TEXT,
+    image_url TEXT,
+    solution_link TEXT,
+    is_incorrect INTEGER NOT NULL DEFAULT 0,
+    created_at DATETIME,
+    original_filename TEXT,
+    mime_type TEXT,
+    answer TEXT,
+    public_id TEXT,
+    assigned_to INTEGER,
+    FOREIGN KEY (log_item_id) REFERENCES log_items(id) ON DELETE CASCADE,
+    FOREIGN KEY (exam_id) REFERENCES exams(id) ON DELETE CASCADE,
+    FOREIGN KEY (assigned_to) REFERENCES users(id) ON DELETE SET NULL
+);
+INSERT INTO problems_new (id, log_item_id, exam_id, description, notes, image_url, solution_link, is_incorrect, created_at, original_filename, mime_type, answer, public_id, assigned_to)
+    SELECT id, log_item_id, exam_id, description, notes, image_url, solution_link, is_incorrect, created_at, original_filename, mime_type, answer, public_id,
+        CASE WHEN assigned_to IN (SELECT id FROM users) THEN assigned_to END
+    FROM problems
+    WHERE (log_item_id IS NULL OR log_item_id IN (SELECT id FROM log_items))
+      AND (exam_id IS NULL OR exam_id IN (SELECT id FROM exams));
+DROP TABLE problems;
+ALTER TABLE problems_new RENAME TO problems;
+
+CREATE UNIQUE INDEX idx_problems_public_id ON problems(public_id);
+CREATE INDEX idx_problems_assigned_to ON problems (assigned_to);
+CREATE TRIGGER problems_public_id AFTER INSERT ON problems WHEN NEW.public_id IS NULL
+BEGIN
+    UPDATE problems SET public_id = lower(
+        substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 1, 8) || '-' ||
+        substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 9, 4) || '-7' ||
+        substr(hex(randomblob(2)), 2, 3) || '-' ||
+        substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2, 3) || '-' ||
+        hex(randomblob(6))
+    ) WHERE id = NEW.id;
+END;
+
+-- Children of problems
+CREATE TABLE problem_categories (
+    problem_id INTEGER NOT NULL,
+    category_id INTEGER NOT NULL,
+    PRIMARY KEY (problem_id, category_id),
+    FOREIGN KEY (problem_id) REFERENCES problems(id) ON DELETE CASCADE,
+    FOREIGN KEY (category_id) REFERENCES categories(id) ON DELETE CASCADE
+);
+INSERT INTO problem_categories (problem_id, category_id)
+    SELECT problem_id, category_id FROM problem_categories_backup
+    WHERE problem_id IN (SELECT id FROM problems) AND category_id IN (SELECT id FROM categories);
+DROP TABLE problem_categories_backup;
+
+CREATE TABLE study_cards (
+    user_id INTEGER NOT NULL,
+    problem_id INTEGER NOT NULL,
+    interval_days REAL NOT NULL DEFAULT 0,
+    reviews INTEGER NOT NULL DEFAULT 0,
+    last_grade INTEGER,
+    due_at DATETIME NOT NULL,
+    PRIMARY KEY (user_id, problem_id),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (problem_id) REFERENCES problems(id) ON DELETE CASCADE
+);
+INSERT INTO study_cards (user_id, problem_id, interval_days, reviews, last_grade, due_at)
+    SELECT user_id, problem_id, interval_days, reviews, last_grade, due_at FROM study_cards_backup
+    WHERE user_id IN (SELECT id FROM users) AND problem_id IN (SELECT id FROM problems);
+DROP TABLE study_cards_backup;
+
+CREATE TABLE study_reviews (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    problem_id INTEGER NOT NULL,
+    grade INTEGER NOT NULL,
+    reviewed_at DATETIME NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (problem_id) REFERENCES problems(id) ON DELETE CASCADE
+);
+INSERT INTO study_reviews (id, user_id, problem_id, grade, reviewed_at)
+    SELECT id, user_id, problem_id, grade, reviewed_at FROM study_reviews_backup
+    WHERE user_id IN (SELECT id FROM users) AND problem_id IN (SELECT id FROM problems);
+DROP TABLE study_reviews_backup;
+CREATE INDEX idx_study_reviews_user ON study_reviews (user_id, reviewed_at);
+
+-- Exam categories
+CREATE TABLE exam_categories_new (
+    exam_id INTEGER NOT NULL,
+    category_id INTEGER NOT NULL,
+    PRIMARY KEY (exam_id, category_id),
+    FOREIGN KEY (exam_id) REFERENCES exams(id) ON DELETE CASCADE,
+    FOREIGN KEY (category_id) REFERENCES categories(id) ON DELETE CASCADE
+);
+INSERT INTO exam_categories_new (exam_id, category_id)
+    SELECT exam_id, category_id FROM exam_categories
+    WHERE exam_id IN (SELECT id FROM exams) AND category_id IN (SELECT id FROM categories);
+DROP TABLE exam_categories;
+ALTER TABLE exam_categories_new RENAME TO exam_categories;
+
+-- Discussion posts and read markers
+CREATE TABLE discussion_posts_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    thread_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    body TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY (thread_id) REFERENCES discussion_threads(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+INSERT INTO discussion_posts_new (id, thread_id, user_id, body, created_at)
+    SELECT id, thread_id, user_id, body, created_at FROM discussion_posts
+    WHERE thread_id IN (SELECT id FROM discussion_threads) AND user_id IN (SELECT id FROM users);
+DROP TABLE discussion_posts;
+ALTER TABLE discussion_posts_new RENAME TO discussion_posts;
+CREATE INDEX idx_discussion_posts_thread ON discussion_posts (thread_id);
+
+CREATE TABLE discussion_reads_new (
+    user_id INTEGER NOT NULL,
+    thread_id INTEGER NOT NULL,
+    last_read_post_id INTEGER NOT NULL,
+    PRIMARY KEY (user_id, thread_id),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (thread_id) REFERENCES discussion_threads(id) ON DELETE CASCADE
+);
+INSERT INTO discussion_reads_new (user_id, thread_id, last_read_post_id)
+    SELECT user_id, thread_id, last_read_post_id FROM discussion_reads
+    WHERE user_id IN (SELECT id FROM users) AND thread_id IN (SELECT id FROM discussion_threads);
+DROP TABLE discussion_reads;
+ALTER TABLE discussion_reads_new RENAME TO discussion_reads;
+
+-- Put the AUTOINCREMENT counters back where they were
+UPDATE sqlite_sequence SET seq = max(seq, (SELECT b.seq FROM sequence_backup b WHERE b.name = sqlite_sequence.name))
+    WHERE name IN (SELECT name FROM sequence_backup);
+INSERT INTO sqlite_sequence (name, seq)
+    SELECT name, seq FROM sequence_backup WHERE name NOT IN (SELECT name FROM sqlite_sequence);
+DROP TABLE sequence_backup;
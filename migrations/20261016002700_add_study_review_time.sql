@@ -0,0 +1,3 @@
+-- How long a card was on screen before it was graded, in milliseconds. Left
+-- NULL for reviews recorded before this and for implausibly long ones.
+ALTER TABLE study_reviews ADD COLUMN time_spent_ms INTEGER;
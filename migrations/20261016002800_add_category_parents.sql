@@ -0,0 +1,17 @@
+-- Nested categories: "微积分/泰勒级数" is 泰勒级数 with 微积分 as its parent.
+-- Deleting a parent lifts its children to the top level.
+ALTER TABLE categories ADD COLUMN parent_id INTEGER REFERENCES categories(id) ON DELETE SET NULL;
+CREATE INDEX idx_categories_parent ON categories (parent_id);
+
+-- Each category's full path from the top ("微积分/泰勒级数") and its depth
+-- (0 at the top). Built downwards from the top level, so a parent loop made
+-- by hand just leaves those categories out; readers fall back to the name.
+CREATE VIEW category_paths AS
+WITH RECURSIVE paths (id, path, depth) AS (
+    SELECT id, name, 0 FROM categories WHERE parent_id IS NULL
+    UNION ALL
+    SELECT c.id, p.path || '/' || c.name, p.depth + 1
+    FROM categories c
+    JOIN paths p ON c.parent_id = p.id
+)
+SELECT id, path, depth FROM paths;
@@ -0,0 +1,11 @@
+-- Per-user topic names for categories, so "DP" in one course and "动态规划"
+-- in another can be studied together. A category whose name has no alias is
+-- its own topic.
+CREATE TABLE category_aliases (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    alias TEXT NOT NULL COLLATE NOCASE,
+    topic TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    UNIQUE (user_id, alias)
+);
@@ -0,0 +1,16 @@
+-- Related problems. A 'variant' link is symmetric and stored once, lower id
+-- first; a 'prerequisite' link says linked_problem_id should be done before
+-- problem_id, so a run of them orders a topic's problems by difficulty.
+CREATE TABLE problem_links (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    problem_id INTEGER NOT NULL,
+    linked_problem_id INTEGER NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('variant', 'prerequisite')),
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (problem_id) REFERENCES problems(id) ON DELETE CASCADE,
+    FOREIGN KEY (linked_problem_id) REFERENCES problems(id) ON DELETE CASCADE,
+    UNIQUE (problem_id, linked_problem_id, kind),
+    CHECK (problem_id != linked_problem_id)
+);
+
+CREATE INDEX idx_problem_links_linked ON problem_links(linked_problem_id);
@@ -0,0 +1,12 @@
+-- Timestamped notes on a log item's lecture video, shown as links that open
+-- the video at that point.
+CREATE TABLE log_item_bookmarks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    log_item_id INTEGER NOT NULL,
+    seconds INTEGER NOT NULL CHECK (seconds >= 0),
+    note TEXT NOT NULL DEFAULT '',
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (log_item_id) REFERENCES log_items(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_log_item_bookmarks_log_item ON log_item_bookmarks(log_item_id, seconds);
@@ -0,0 +1,5 @@
+-- Progress on log items used as tasks (homework, labs). NULL for items that
+-- aren't tracked, which is every item until its status is first set.
+ALTER TABLE log_items ADD COLUMN status TEXT CHECK (status IN ('todo', 'in_progress', 'done', 'submitted'));
+
+CREATE INDEX idx_log_items_status ON log_items(status) WHERE status IS NOT NULL;
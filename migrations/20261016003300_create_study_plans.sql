@@ -0,0 +1,21 @@
+-- A day-by-day plan of problems to review before an exam. Each entry is also
+-- written into study_cards as the problem's due date, so the ordinary review
+-- queue serves the plan; entries left undone on a past day are moved forward.
+CREATE TABLE study_plans (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    exam_id INTEGER NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (exam_id) REFERENCES exams(id) ON DELETE CASCADE,
+    UNIQUE (user_id, exam_id)
+);
+
+CREATE TABLE study_plan_entries (
+    plan_id INTEGER NOT NULL,
+    problem_id INTEGER NOT NULL,
+    day DATE NOT NULL,
+    PRIMARY KEY (plan_id, problem_id),
+    FOREIGN KEY (plan_id) REFERENCES study_plans(id) ON DELETE CASCADE,
+    FOREIGN KEY (problem_id) REFERENCES problems(id) ON DELETE CASCADE
+);
@@ -0,0 +1,4 @@
+-- How hard a problem has turned out to be in review, 0 (easy) to 100, from
+-- the grades and answer times in study_reviews. Recomputed nightly by the
+-- recalibrate_difficulty job; NULL until a problem has enough reviews.
+ALTER TABLE problems ADD COLUMN difficulty INTEGER;
@@ -0,0 +1,26 @@
+-- A course can continue an earlier one, e.g. 数学分析(二) after 数学分析(一).
+-- Deleting the earlier course just unlinks it.
+ALTER TABLE courses ADD COLUMN continues_course_id INTEGER REFERENCES courses(id) ON DELETE SET NULL;
+
+-- Which category of the continuing course each category of the previous course
+-- carries over into, so reviews recorded under the old one still count toward
+-- the new one's topic mastery.
+CREATE TABLE category_carryovers (
+    previous_category_id INTEGER PRIMARY KEY,
+    category_id INTEGER NOT NULL,
+    FOREIGN KEY (previous_category_id) REFERENCES categories(id) ON DELETE CASCADE,
+    FOREIGN KEY (category_id) REFERENCES categories(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_category_carryovers_category ON category_carryovers(category_id);
+
+-- Every category paired with itself and with each earlier category carried
+-- into it, following carryovers back through any number of semesters.
+CREATE VIEW category_lineage (category_id, ancestor_id) AS
+WITH RECURSIVE lineage (category_id, ancestor_id) AS (
+    SELECT id, id FROM categories
+    UNION
+    SELECT l.category_id, cc.previous_category_id
+    FROM lineage l JOIN category_carryovers cc ON cc.category_id = l.ancestor_id
+)
+SELECT category_id, ancestor_id FROM lineage;
@@ -0,0 +1,19 @@
+-- Requests served per UTC day, for the admin stats page. Counted in memory
+-- and added here every few minutes by the flush_request_counts job.
+CREATE TABLE request_counts (
+    day DATE PRIMARY KEY,
+    requests INTEGER NOT NULL DEFAULT 0
+);
+
+-- One row per OpenRouter call, with the token counts and cost (in US
+-- dollars) OpenRouter reported for it.
+CREATE TABLE llm_usage (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    model TEXT NOT NULL,
+    prompt_tokens INTEGER NOT NULL DEFAULT 0,
+    completion_tokens INTEGER NOT NULL DEFAULT 0,
+    cost REAL NOT NULL DEFAULT 0,
+    called_at DATETIME NOT NULL
+);
+
+CREATE INDEX idx_llm_usage_called_at ON llm_usage (called_at);
@@ -0,0 +1,9 @@
+-- Optional account email. It only counts (for logging in with it) once the
+-- link sent to it has been opened; email_token is that link's token until then.
+ALTER TABLE users ADD COLUMN email TEXT;
+ALTER TABLE users ADD COLUMN email_verified BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN email_token TEXT;
+
+-- Several accounts may claim an address, but only one can verify it
+CREATE UNIQUE INDEX idx_users_verified_email ON users (email COLLATE NOCASE) WHERE email_verified = 1;
+CREATE UNIQUE INDEX idx_users_email_token ON users (email_token);
@@ -0,0 +1,5 @@
+-- A semester's wrapped summary can be published at /wrapped/<wrapped_slug>
+ALTER TABLE semesters ADD COLUMN wrapped_published INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE semesters ADD COLUMN wrapped_slug TEXT;
+
+CREATE UNIQUE INDEX idx_semesters_wrapped_slug ON semesters (wrapped_slug) WHERE wrapped_slug IS NOT NULL;
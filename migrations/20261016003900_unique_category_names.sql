@@ -0,0 +1,38 @@
+-- One category per name at each level of a course, so two problems saved at
+-- the same moment with the same new category share it instead of each making
+-- their own. Top-level categories have no parent, which a unique index would
+-- count as all different, hence the COALESCE.
+--
+-- Duplicates made before this are merged into the oldest category with the
+-- same path and depth. Going by path also merges the children of merged
+-- parents ("微积分/泰勒级数" made twice at once).
+CREATE TEMP TABLE category_merges AS
+SELECT id AS duplicate_id, keep_id
+FROM (
+    SELECT c.id, (
+        SELECT MIN(k.id)
+        FROM categories k
+        JOIN category_paths kp ON kp.id = k.id
+        WHERE k.course_id = c.course_id AND kp.path = p.path AND kp.depth = p.depth
+    ) AS keep_id
+    FROM categories c
+    JOIN category_paths p ON p.id = c.id
+)
+WHERE id != keep_id;
+
+INSERT OR IGNORE INTO problem_categories (problem_id, category_id)
+    SELECT pc.problem_id, m.keep_id FROM problem_categories pc JOIN category_merges m ON pc.category_id = m.duplicate_id;
+INSERT OR IGNORE INTO exam_categories (exam_id, category_id)
+    SELECT ec.exam_id, m.keep_id FROM exam_categories ec JOIN category_merges m ON ec.category_id = m.duplicate_id;
+INSERT OR IGNORE INTO category_carryovers (previous_category_id, category_id)
+    SELECT m.keep_id, cc.category_id FROM category_carryovers cc JOIN category_merges m ON cc.previous_category_id = m.duplicate_id;
+UPDATE category_carryovers SET category_id = (SELECT keep_id FROM category_merges WHERE duplicate_id = category_id)
+    WHERE category_id IN (SELECT duplicate_id FROM category_merges);
+UPDATE study_goals SET category_id = (SELECT keep_id FROM category_merges WHERE duplicate_id = category_id)
+    WHERE category_id IN (SELECT duplicate_id FROM category_merges);
+UPDATE categories SET parent_id = (SELECT keep_id FROM category_merges WHERE duplicate_id = parent_id)
+    WHERE parent_id IN (SELECT duplicate_id FROM category_merges);
+DELETE FROM categories WHERE id IN (SELECT duplicate_id FROM category_merges);
+DROP TABLE category_merges;
+
+CREATE UNIQUE INDEX idx_categories_course_parent_name ON categories (course_id, COALESCE(parent_id, 0), name);
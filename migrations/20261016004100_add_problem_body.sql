@@ -0,0 +1,3 @@
+-- The problem itself, typed in (Markdown with $…$ / $$…$$ math), for problems
+-- without a screenshot
+ALTER TABLE problems ADD COLUMN body TEXT;
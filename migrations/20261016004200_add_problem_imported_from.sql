@@ -0,0 +1,2 @@
+-- The link a problem's image was imported from, for problems added by URL
+ALTER TABLE problems ADD COLUMN imported_from TEXT;
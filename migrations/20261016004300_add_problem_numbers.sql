@@ -0,0 +1,37 @@
+-- Problems are numbered per course (P-001, P-002, …) in the order they were
+-- added, so they can be referred to in handwritten notes.
+-- courses.next_problem_number is the number the course's next problem gets;
+-- the trigger takes it within the insert itself, so two problems added at
+-- the same moment can't share one. Numbers aren't reused after a delete, and
+-- inserts that already set a number (a history revert bringing a problem
+-- back) keep theirs.
+
+ALTER TABLE courses ADD COLUMN next_problem_number INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE problems ADD COLUMN number INTEGER;
+
+UPDATE problems SET number = (
+    SELECT numbered.n FROM (
+        SELECT p.id, ROW_NUMBER() OVER (PARTITION BY COALESCE(l.course_id, e.course_id) ORDER BY p.id) AS n
+        FROM problems p
+        LEFT JOIN log_items l ON p.log_item_id = l.id
+        LEFT JOIN exams e ON p.exam_id = e.id
+        WHERE COALESCE(l.course_id, e.course_id) IS NOT NULL
+    ) numbered
+    WHERE numbered.id = problems.id
+);
+UPDATE courses SET next_problem_number = 1 + (
+    SELECT COUNT(*) FROM problems p
+    LEFT JOIN log_items l ON p.log_item_id = l.id
+    LEFT JOIN exams e ON p.exam_id = e.id
+    WHERE COALESCE(l.course_id, e.course_id) = courses.id
+);
+
+CREATE TRIGGER problems_number AFTER INSERT ON problems WHEN NEW.number IS NULL
+BEGIN
+    UPDATE courses SET next_problem_number = next_problem_number + 1
+    WHERE id = COALESCE((SELECT course_id FROM log_items WHERE id = NEW.log_item_id), (SELECT course_id FROM exams WHERE id = NEW.exam_id));
+    UPDATE problems SET number = (
+        SELECT next_problem_number - 1 FROM courses
+        WHERE id = COALESCE((SELECT course_id FROM log_items WHERE id = NEW.log_item_id), (SELECT course_id FROM exams WHERE id = NEW.exam_id))
+    ) WHERE id = NEW.id;
+END;
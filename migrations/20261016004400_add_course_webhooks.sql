@@ -0,0 +1,14 @@
+-- A course's webhook (e.g. a Discord channel) is told when the course is
+-- published and when it gets new content; see webhook.rs. webhook_announced
+-- is set once the "published" message has gone out for the current
+-- publication, and the two ids are the newest log item and problem already
+-- announced. Content from before this migration counts as announced.
+ALTER TABLE courses ADD COLUMN webhook_url TEXT;
+ALTER TABLE courses ADD COLUMN webhook_announced INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE courses ADD COLUMN webhook_log_item_id INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE courses ADD COLUMN webhook_problem_id INTEGER NOT NULL DEFAULT 0;
+
+UPDATE courses SET
+    webhook_announced = is_published,
+    webhook_log_item_id = (SELECT COALESCE(MAX(id), 0) FROM log_items),
+    webhook_problem_id = (SELECT COALESCE(MAX(id), 0) FROM problems);
@@ -0,0 +1,12 @@
+-- Free-form tags on log items ("重点", "需要复习", …), separate from the
+-- categories problems are filed under. Names are stored normalized like
+-- category names and compared without regard to ASCII case; within a
+-- course, the first spelling typed is reused.
+CREATE TABLE log_item_tags (
+    log_item_id INTEGER NOT NULL,
+    name TEXT NOT NULL COLLATE NOCASE,
+    PRIMARY KEY (log_item_id, name),
+    FOREIGN KEY (log_item_id) REFERENCES log_items(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_log_item_tags_name ON log_item_tags(name);
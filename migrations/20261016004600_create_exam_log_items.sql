@@ -0,0 +1,12 @@
+-- The log items an exam covers (its "exam scope"), e.g. the lectures and
+-- homework up to the midterm. Study mode can be filtered to the problems
+-- from them.
+CREATE TABLE exam_log_items (
+    exam_id INTEGER NOT NULL,
+    log_item_id INTEGER NOT NULL,
+    PRIMARY KEY (exam_id, log_item_id),
+    FOREIGN KEY (exam_id) REFERENCES exams(id) ON DELETE CASCADE,
+    FOREIGN KEY (log_item_id) REFERENCES log_items(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_exam_log_items_log_item ON exam_log_items(log_item_id);
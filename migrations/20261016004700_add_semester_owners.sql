@@ -0,0 +1,9 @@
+-- Semesters belong to the account that created them, and their courses, log
+-- items, exams and problems with them; see access.rs. Everything from before
+-- this goes to the first account, the admin who ran /setup. Semesters made
+-- before there was any account (`zhixi seed` on a fresh database) are left
+-- without an owner until /setup claims them.
+ALTER TABLE semesters ADD COLUMN user_id INTEGER REFERENCES users(id);
+UPDATE semesters SET user_id = (SELECT MIN(id) FROM users);
+
+CREATE INDEX idx_semesters_user ON semesters(user_id);
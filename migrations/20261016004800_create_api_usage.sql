@@ -0,0 +1,8 @@
+-- Requests each account made to the JSON API per UTC day, counted against
+-- the daily quota in the site settings (see api_limits.rs)
+CREATE TABLE api_usage (
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    day TEXT NOT NULL,
+    requests INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (user_id, day)
+);
@@ -0,0 +1,16 @@
+-- Reports visitors file against problems on public pages, reviewed by the
+-- admin at /admin/reports
+CREATE TABLE content_reports (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    course_id INTEGER NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    problem_id INTEGER NOT NULL REFERENCES problems(id) ON DELETE CASCADE,
+    reason TEXT NOT NULL,
+    details TEXT,
+    -- open, dismissed or unpublished
+    status TEXT NOT NULL DEFAULT 'open',
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    resolved_at DATETIME,
+    resolved_by INTEGER REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX idx_content_reports_status ON content_reports(status, created_at);
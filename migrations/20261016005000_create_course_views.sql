@@ -0,0 +1,9 @@
+-- Daily totals of views of each course's public pages (see page_views.rs).
+-- Only the counts are kept; visitors are told apart in memory.
+CREATE TABLE course_views (
+    course_id INTEGER NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    day TEXT NOT NULL,
+    views INTEGER NOT NULL DEFAULT 0,
+    visitors INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (course_id, day)
+);
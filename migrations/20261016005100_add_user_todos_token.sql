@@ -0,0 +1,5 @@
+-- Token in the link task apps subscribe to /todos.ics with (see todos.rs);
+-- NULL until the account makes one
+ALTER TABLE users ADD COLUMN todos_token TEXT;
+
+CREATE UNIQUE INDEX idx_users_todos_token ON users(todos_token) WHERE todos_token IS NOT NULL;
@@ -0,0 +1,377 @@
+-- ON DELETE actions for everything that belongs to a semester or course, so
+-- deleting a course is one DELETE FROM courses (and a semester one DELETE
+-- FROM semesters) instead of a handler emptying each table by hand. Course
+-- history goes with its course: there is nothing left to revert into.
+--
+-- As in 20261016002600, each table is rebuilt, since SQLite can't change a
+-- table's constraints. Dropping a table deletes its rows, which would cascade
+-- into the tables below it (problems, discussion posts, ...), so those are
+-- set aside first and put back at the end. Foreign keys are deferred to the
+-- commit, so a parent can be dropped and re-filled while its children wait.
+-- Rows already pointing at nothing are not copied over, and the views and
+-- trigger that name the rebuilt tables are recreated once they exist again.
+-- AUTOINCREMENT counters are carried over so ids are never handed out again.
+
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TABLE sequence_backup AS SELECT name, seq FROM sqlite_sequence;
+
+DROP VIEW category_lineage;
+DROP VIEW category_paths;
+DROP TRIGGER problems_number;
+
+-- Set aside what the rebuilds would cascade into
+CREATE TABLE problems_backup AS SELECT * FROM problems;
+CREATE TABLE problem_categories_backup AS SELECT * FROM problem_categories;
+CREATE TABLE study_cards_backup AS SELECT * FROM study_cards;
+CREATE TABLE study_reviews_backup AS SELECT * FROM study_reviews;
+CREATE TABLE problem_links_backup AS SELECT * FROM problem_links;
+CREATE TABLE study_plans_backup AS SELECT * FROM study_plans;
+CREATE TABLE study_plan_entries_backup AS SELECT * FROM study_plan_entries;
+CREATE TABLE content_reports_backup AS SELECT * FROM content_reports;
+CREATE TABLE log_item_bookmarks_backup AS SELECT * FROM log_item_bookmarks;
+CREATE TABLE log_item_tags_backup AS SELECT * FROM log_item_tags;
+CREATE TABLE exam_log_items_backup AS SELECT * FROM exam_log_items;
+CREATE TABLE exam_categories_backup AS SELECT * FROM exam_categories;
+CREATE TABLE category_carryovers_backup AS SELECT * FROM category_carryovers;
+CREATE TABLE discussion_posts_backup AS SELECT * FROM discussion_posts;
+CREATE TABLE discussion_reads_backup AS SELECT * FROM discussion_reads;
+CREATE TABLE course_views_backup AS SELECT * FROM course_views;
+
+DELETE FROM study_plan_entries;
+DELETE FROM study_plans;
+DELETE FROM problem_links;
+DELETE FROM study_reviews;
+DELETE FROM study_cards;
+DELETE FROM problem_categories;
+DELETE FROM content_reports;
+DELETE FROM problems;
+DELETE FROM log_item_bookmarks;
+DELETE FROM log_item_tags;
+DELETE FROM exam_log_items;
+DELETE FROM exam_categories;
+DELETE FROM category_carryovers;
+DELETE FROM discussion_reads;
+DELETE FROM discussion_posts;
+DELETE FROM course_views;
+
+-- Courses, first: their children still have no ON DELETE action, so
+-- dropping the table leaves them waiting for the rows to come back
+CREATE TABLE courses_backup AS SELECT * FROM courses;
+DROP TABLE courses;
+CREATE TABLE courses (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    semester_id INTEGER NOT NULL,
+    code TEXT NOT NULL,
+    title TEXT NOT NULL,
+    is_published INTEGER NOT NULL DEFAULT 0,
+    public_slug TEXT,
+    show_lecture_links INTEGER NOT NULL DEFAULT 0,
+    public_theme TEXT NOT NULL DEFAULT 'default',
+    banner_url TEXT,
+    units REAL,
+    grade TEXT,
+    color TEXT,
+    public_id TEXT,
+    translation_subject TEXT,
+    translation_level TEXT NOT NULL DEFAULT 'undergraduate',
+    translation_style TEXT NOT NULL DEFAULT 'academic',
+    discourage_indexing INTEGER NOT NULL DEFAULT 0,
+    join_code TEXT,
+    join_role TEXT NOT NULL DEFAULT 'viewer',
+    continues_course_id INTEGER REFERENCES courses(id) ON DELETE SET NULL,
+    next_problem_number INTEGER NOT NULL DEFAULT 1,
+    webhook_url TEXT,
+    webhook_announced INTEGER NOT NULL DEFAULT 0,
+    webhook_log_item_id INTEGER NOT NULL DEFAULT 0,
+    webhook_problem_id INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (semester_id) REFERENCES semesters(id) ON DELETE CASCADE
+);
+INSERT INTO courses (id, semester_id, code, title, is_published, public_slug, show_lecture_links, public_theme, banner_url, units, grade, color, public_id, translation_subject, translation_level, translation_style, discourage_indexing, join_code, join_role, continues_course_id, next_problem_number, webhook_url, webhook_announced, webhook_log_item_id, webhook_problem_id)
+    SELECT id, semester_id, code, title, is_published, public_slug, show_lecture_links, public_theme, banner_url, units, grade, color, public_id, translation_subject, translation_level, translation_style, discourage_indexing, join_code, join_role,
+        CASE WHEN continues_course_id IN (SELECT id FROM courses_backup WHERE semester_id IN (SELECT id FROM semesters)) THEN continues_course_id END,
+        next_problem_number, webhook_url, webhook_announced, webhook_log_item_id, webhook_problem_id
+    FROM courses_backup
+    WHERE semester_id IN (SELECT id FROM semesters);
+DROP TABLE courses_backup;
+
+CREATE UNIQUE INDEX idx_courses_public_slug ON courses(public_slug) WHERE public_slug IS NOT NULL;
+CREATE UNIQUE INDEX idx_courses_public_id ON courses(public_id);
+CREATE UNIQUE INDEX idx_courses_join_code ON courses (join_code);
+CREATE TRIGGER courses_public_id AFTER INSERT ON courses WHEN NEW.public_id IS NULL
+BEGIN
+    UPDATE courses SET public_id = lower(
+        substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 1, 8) || '-' ||
+        substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 9, 4) || '-7' ||
+        substr(hex(randomblob(2)), 2, 3) || '-' ||
+        substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2, 3) || '-' ||
+        hex(randomblob(6))
+    ) WHERE id = NEW.id;
+END;
+
+-- Log items
+CREATE TABLE log_items_backup AS SELECT * FROM log_items;
+DROP TABLE log_items;
+CREATE TABLE log_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    course_id INTEGER NOT NULL,
+    kind TEXT NOT NULL, -- 'Lecture', 'Lab', 'Discussion', 'Homework', 'Midterm', 'Quiz', 'Other'
+    title TEXT NOT NULL,
+    description TEXT,
+    link TEXT,
+    date TEXT, -- ISO8601 date string
+    public_id TEXT,
+    status TEXT CHECK (status IN ('todo', 'in_progress', 'done', 'submitted')),
+    FOREIGN KEY (course_id) REFERENCES courses(id) ON DELETE CASCADE
+);
+INSERT INTO log_items (id, course_id, kind, title, description, link, date, public_id, status)
+    SELECT id, course_id, kind, title, description, link, date, public_id, status FROM log_items_backup
+    WHERE course_id IN (SELECT id FROM courses);
+DROP TABLE log_items_backup;
+
+CREATE UNIQUE INDEX idx_log_items_public_id ON log_items(public_id);
+CREATE INDEX idx_log_items_status ON log_items(status) WHERE status IS NOT NULL;
+CREATE TRIGGER log_items_public_id AFTER INSERT ON log_items WHEN NEW.public_id IS NULL
+BEGIN
+    UPDATE log_items SET public_id = lower(
+        substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 1, 8) || '-' ||
+        substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 9, 4) || '-7' ||
+        substr(hex(randomblob(2)), 2, 3) || '-' ||
+        substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2, 3) || '-' ||
+        hex(randomblob(6))
+    ) WHERE id = NEW.id;
+END;
+
+-- Categories
+CREATE TABLE categories_backup AS SELECT * FROM categories;
+DROP TABLE categories;
+CREATE TABLE categories (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    course_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    parent_id INTEGER REFERENCES categories(id) ON DELETE SET NULL,
+    FOREIGN KEY (course_id) REFERENCES courses(id) ON DELETE CASCADE
+);
+INSERT INTO categories (id, course_id, name, parent_id)
+    SELECT id, course_id, name,
+        CASE WHEN parent_id IN (SELECT id FROM categories_backup WHERE course_id IN (SELECT id FROM courses)) THEN parent_id END
+    FROM categories_backup
+    WHERE course_id IN (SELECT id FROM courses);
+DROP TABLE categories_backup;
+
+CREATE INDEX idx_categories_parent ON categories (parent_id);
+CREATE UNIQUE INDEX idx_categories_course_parent_name ON categories (course_id, COALESCE(parent_id, 0), name COLLATE NOCASE);
+
+-- Exams
+CREATE TABLE exams_backup AS SELECT * FROM exams;
+DROP TABLE exams;
+CREATE TABLE exams (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    course_id INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    semester TEXT,
+    link TEXT,
+    exam_date DATE,
+    FOREIGN KEY (course_id) REFERENCES courses(id) ON DELETE CASCADE
+);
+INSERT INTO exams (id, course_id, title, semester, link, exam_date)
+    SELECT id, course_id, title, semester, link, exam_date FROM exams_backup
+    WHERE course_id IN (SELECT id FROM courses);
+DROP TABLE exams_backup;
+
+-- Discussion threads
+CREATE TABLE discussion_threads_backup AS SELECT * FROM discussion_threads;
+DROP TABLE discussion_threads;
+CREATE TABLE discussion_threads (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    course_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY (course_id) REFERENCES courses(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+INSERT INTO discussion_threads (id, course_id, user_id, title, created_at)
+    SELECT id, course_id, user_id, title, created_at FROM discussion_threads_backup
+    WHERE course_id IN (SELECT id FROM courses);
+DROP TABLE discussion_threads_backup;
+CREATE INDEX idx_discussion_threads_course ON discussion_threads (course_id);
+
+-- Per-course settings and membership
+CREATE TABLE study_presets_backup AS SELECT * FROM study_presets;
+DROP TABLE study_presets;
+CREATE TABLE study_presets (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    course_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    filters TEXT NOT NULL, -- JSON-encoded StudyFilters
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (course_id) REFERENCES courses(id) ON DELETE CASCADE
+);
+INSERT INTO study_presets (id, user_id, course_id, name, filters)
+    SELECT id, user_id, course_id, name, filters FROM study_presets_backup
+    WHERE course_id IN (SELECT id FROM courses);
+DROP TABLE study_presets_backup;
+
+CREATE TABLE log_item_kinds_backup AS SELECT * FROM log_item_kinds;
+DROP TABLE log_item_kinds;
+CREATE TABLE log_item_kinds (
+    course_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    PRIMARY KEY (course_id, kind),
+    FOREIGN KEY (course_id) REFERENCES courses(id) ON DELETE CASCADE
+);
+INSERT INTO log_item_kinds (course_id, kind, position, enabled)
+    SELECT course_id, kind, position, enabled FROM log_item_kinds_backup
+    WHERE course_id IN (SELECT id FROM courses);
+DROP TABLE log_item_kinds_backup;
+
+CREATE TABLE course_members_backup AS SELECT * FROM course_members;
+DROP TABLE course_members;
+CREATE TABLE course_members (
+    course_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    role TEXT NOT NULL,
+    joined_at DATETIME NOT NULL,
+    PRIMARY KEY (course_id, user_id),
+    FOREIGN KEY (course_id) REFERENCES courses(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+INSERT INTO course_members (course_id, user_id, role, joined_at)
+    SELECT course_id, user_id, role, joined_at FROM course_members_backup
+    WHERE course_id IN (SELECT id FROM courses);
+DROP TABLE course_members_backup;
+
+CREATE TABLE study_goals_backup AS SELECT * FROM study_goals;
+DROP TABLE study_goals;
+CREATE TABLE study_goals (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    course_id INTEGER NOT NULL,
+    category_id INTEGER NOT NULL,
+    target INTEGER NOT NULL,
+    deadline DATE,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (course_id) REFERENCES courses(id) ON DELETE CASCADE,
+    FOREIGN KEY (category_id) REFERENCES categories(id) ON DELETE CASCADE
+);
+INSERT INTO study_goals (id, user_id, course_id, category_id, target, deadline, created_at)
+    SELECT id, user_id, course_id, category_id, target, deadline, created_at FROM study_goals_backup
+    WHERE course_id IN (SELECT id FROM courses) AND category_id IN (SELECT id FROM categories);
+DROP TABLE study_goals_backup;
+
+-- Edit history had no foreign key on its course at all
+CREATE TABLE entity_history_backup AS SELECT * FROM entity_history;
+DROP TABLE entity_history;
+CREATE TABLE entity_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    entity_type TEXT NOT NULL, -- 'log_item' or 'problem'
+    entity_id INTEGER NOT NULL,
+    course_id INTEGER,
+    action TEXT NOT NULL, -- 'update', 'delete' or 'revert'
+    before_json TEXT, -- NULL when the entity did not exist (reverting a delete)
+    after_json TEXT, -- NULL when the entity was deleted
+    user_id INTEGER,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (course_id) REFERENCES courses(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+INSERT INTO entity_history (id, entity_type, entity_id, course_id, action, before_json, after_json, user_id, created_at)
+    SELECT id, entity_type, entity_id, course_id, action, before_json, after_json, user_id, created_at FROM entity_history_backup
+    WHERE course_id IS NULL OR course_id IN (SELECT id FROM courses);
+DROP TABLE entity_history_backup;
+CREATE INDEX idx_entity_history_entity ON entity_history(entity_type, entity_id);
+CREATE INDEX idx_entity_history_course ON entity_history(course_id);
+
+-- Put back what was set aside, parents first
+INSERT INTO problems SELECT * FROM problems_backup
+    WHERE (log_item_id IS NULL OR log_item_id IN (SELECT id FROM log_items))
+      AND (exam_id IS NULL OR exam_id IN (SELECT id FROM exams));
+INSERT INTO problem_categories SELECT * FROM problem_categories_backup
+    WHERE problem_id IN (SELECT id FROM problems) AND category_id IN (SELECT id FROM categories);
+INSERT INTO study_cards SELECT * FROM study_cards_backup
+    WHERE problem_id IN (SELECT id FROM problems);
+INSERT INTO study_reviews SELECT * FROM study_reviews_backup
+    WHERE problem_id IN (SELECT id FROM problems);
+INSERT INTO problem_links SELECT * FROM problem_links_backup
+    WHERE problem_id IN (SELECT id FROM problems) AND linked_problem_id IN (SELECT id FROM problems);
+INSERT INTO study_plans SELECT * FROM study_plans_backup
+    WHERE exam_id IN (SELECT id FROM exams);
+INSERT INTO study_plan_entries SELECT * FROM study_plan_entries_backup
+    WHERE plan_id IN (SELECT id FROM study_plans) AND problem_id IN (SELECT id FROM problems);
+INSERT INTO content_reports SELECT * FROM content_reports_backup
+    WHERE course_id IN (SELECT id FROM courses) AND problem_id IN (SELECT id FROM problems);
+INSERT INTO log_item_bookmarks SELECT * FROM log_item_bookmarks_backup
+    WHERE log_item_id IN (SELECT id FROM log_items);
+INSERT INTO log_item_tags SELECT * FROM log_item_tags_backup
+    WHERE log_item_id IN (SELECT id FROM log_items);
+INSERT INTO exam_log_items SELECT * FROM exam_log_items_backup
+    WHERE exam_id IN (SELECT id FROM exams) AND log_item_id IN (SELECT id FROM log_items);
+INSERT INTO exam_categories SELECT * FROM exam_categories_backup
+    WHERE exam_id IN (SELECT id FROM exams) AND category_id IN (SELECT id FROM categories);
+INSERT INTO category_carryovers SELECT * FROM category_carryovers_backup
+    WHERE previous_category_id IN (SELECT id FROM categories) AND category_id IN (SELECT id FROM categories);
+INSERT INTO discussion_posts SELECT * FROM discussion_posts_backup
+    WHERE thread_id IN (SELECT id FROM discussion_threads);
+INSERT INTO discussion_reads SELECT * FROM discussion_reads_backup
+    WHERE thread_id IN (SELECT id FROM discussion_threads);
+INSERT INTO course_views SELECT * FROM course_views_backup
+    WHERE course_id IN (SELECT id FROM courses);
+
+DROP TABLE problems_backup;
+DROP TABLE problem_categories_backup;
+DROP TABLE study_cards_backup;
+DROP TABLE study_reviews_backup;
+DROP TABLE problem_links_backup;
+DROP TABLE study_plans_backup;
+DROP TABLE study_plan_entries_backup;
+DROP TABLE content_reports_backup;
+DROP TABLE log_item_bookmarks_backup;
+DROP TABLE log_item_tags_backup;
+DROP TABLE exam_log_items_backup;
+DROP TABLE exam_categories_backup;
+DROP TABLE category_carryovers_backup;
+DROP TABLE discussion_posts_backup;
+DROP TABLE discussion_reads_backup;
+DROP TABLE course_views_backup;
+
+-- The views and trigger as they were
+CREATE VIEW category_paths AS
+WITH RECURSIVE paths (id, path, depth) AS (
+    SELECT id, name, 0 FROM categories WHERE parent_id IS NULL
+    UNION ALL
+    SELECT c.id, p.path || '/' || c.name, p.depth + 1
+    FROM categories c
+    JOIN paths p ON c.parent_id = p.id
+)
+SELECT id, path, depth FROM paths;
+
+CREATE VIEW category_lineage (category_id, ancestor_id) AS
+WITH RECURSIVE lineage (category_id, ancestor_id) AS (
+    SELECT id, id FROM categories
+    UNION
+    SELECT l.category_id, cc.previous_category_id
+    FROM lineage l JOIN category_carryovers cc ON cc.category_id = l.ancestor_id
+)
+SELECT category_id, ancestor_id FROM lineage;
+
+CREATE TRIGGER problems_number AFTER INSERT ON problems WHEN NEW.number IS NULL
+BEGIN
+    UPDATE courses SET next_problem_number = next_problem_number + 1
+    WHERE id = COALESCE((SELECT course_id FROM log_items WHERE id = NEW.log_item_id), (SELECT course_id FROM exams WHERE id = NEW.exam_id));
+    UPDATE problems SET number = (
+        SELECT next_problem_number - 1 FROM courses
+        WHERE id = COALESCE((SELECT course_id FROM log_items WHERE id = NEW.log_item_id), (SELECT course_id FROM exams WHERE id = NEW.exam_id))
+    ) WHERE id = NEW.id;
+END;
+
+-- Put the AUTOINCREMENT counters back where they were
+UPDATE sqlite_sequence SET seq = max(seq, (SELECT b.seq FROM sequence_backup b WHERE b.name = sqlite_sequence.name))
+    WHERE name IN (SELECT name FROM sequence_backup);
+INSERT INTO sqlite_sequence (name, seq)
+    SELECT name, seq FROM sequence_backup WHERE name NOT IN (SELECT name FROM sqlite_sequence);
+DROP TABLE sequence_backup;
@@ -0,0 +1,16 @@
+-- Where each problem came from, for lists that show or link to it: its
+-- course, the log item or exam it was added to, and the link to that on the
+-- course page. A log item's problem that was also put on an exam keeps the
+-- log item as its source; `exam_title` names the exam. Queries join this
+-- instead of each spelling out the COALESCEs.
+CREATE VIEW problem_sources AS
+SELECT
+    p.id AS problem_id,
+    COALESCE(l.course_id, e.course_id) AS course_id,
+    COALESCE(l.kind, 'Exam') AS source_kind,
+    COALESCE(l.title, e.title, '') AS source_title,
+    COALESCE('/courses/' || l.course_id || '#log-' || l.id, '/courses/' || e.course_id || '/exams#exam-' || e.id, '') AS source_url,
+    CASE WHEN l.id IS NOT NULL THEN e.title END AS exam_title
+FROM problems p
+LEFT JOIN log_items l ON p.log_item_id = l.id
+LEFT JOIN exams e ON p.exam_id = e.id;
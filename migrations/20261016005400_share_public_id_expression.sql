@@ -0,0 +1,31 @@
+-- The public_id triggers each carried their own copy of the UUIDv7
+-- expression (see add_public_ids for the format). It now lives in this one
+-- view, which the triggers read from. A view is evaluated afresh wherever it
+-- is read, so every read is a new id. Tables rebuilt later recreate their
+-- trigger the same way.
+
+CREATE VIEW new_public_id (value) AS SELECT lower(
+    substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 1, 8) || '-' ||
+    substr(printf('%012x', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)), 9, 4) || '-7' ||
+    substr(hex(randomblob(2)), 2, 3) || '-' ||
+    substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2, 3) || '-' ||
+    hex(randomblob(6))
+);
+
+DROP TRIGGER courses_public_id;
+CREATE TRIGGER courses_public_id AFTER INSERT ON courses WHEN NEW.public_id IS NULL
+BEGIN
+    UPDATE courses SET public_id = (SELECT value FROM new_public_id) WHERE id = NEW.id;
+END;
+
+DROP TRIGGER log_items_public_id;
+CREATE TRIGGER log_items_public_id AFTER INSERT ON log_items WHEN NEW.public_id IS NULL
+BEGIN
+    UPDATE log_items SET public_id = (SELECT value FROM new_public_id) WHERE id = NEW.id;
+END;
+
+DROP TRIGGER problems_public_id;
+CREATE TRIGGER problems_public_id AFTER INSERT ON problems WHEN NEW.public_id IS NULL
+BEGIN
+    UPDATE problems SET public_id = (SELECT value FROM new_public_id) WHERE id = NEW.id;
+END;
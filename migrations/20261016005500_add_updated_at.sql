@@ -0,0 +1,110 @@
+-- When public pages last changed, for their Last-Modified (see
+-- public_cache.rs). Semesters, courses, log items and problems carry an
+-- updated_at that triggers keep current, so every writer counts, whatever
+-- route it came in through. Changes that leave no row of their own behind (a
+-- deletion, a renamed category or exam, the course's kinds) touch the course
+-- instead. Rows from before this migration count as changed now.
+
+ALTER TABLE semesters ADD COLUMN updated_at TEXT;
+ALTER TABLE courses ADD COLUMN updated_at TEXT;
+ALTER TABLE log_items ADD COLUMN updated_at TEXT;
+ALTER TABLE problems ADD COLUMN updated_at TEXT;
+UPDATE semesters SET updated_at = CURRENT_TIMESTAMP;
+UPDATE courses SET updated_at = CURRENT_TIMESTAMP;
+UPDATE log_items SET updated_at = CURRENT_TIMESTAMP;
+UPDATE problems SET updated_at = CURRENT_TIMESTAMP;
+
+CREATE TRIGGER semesters_inserted_at AFTER INSERT ON semesters
+BEGIN
+    UPDATE semesters SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER semesters_updated_at AFTER UPDATE ON semesters WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE semesters SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER courses_inserted_at AFTER INSERT ON courses
+BEGIN
+    UPDATE courses SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER courses_updated_at AFTER UPDATE ON courses WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE courses SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+    UPDATE semesters SET updated_at = CURRENT_TIMESTAMP WHERE id = OLD.semester_id AND OLD.semester_id IS NOT NEW.semester_id;
+END;
+
+CREATE TRIGGER courses_deleted_at AFTER DELETE ON courses
+BEGIN
+    UPDATE semesters SET updated_at = CURRENT_TIMESTAMP WHERE id = OLD.semester_id;
+END;
+
+CREATE TRIGGER log_items_inserted_at AFTER INSERT ON log_items
+BEGIN
+    UPDATE log_items SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER log_items_updated_at AFTER UPDATE ON log_items WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE log_items SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+    UPDATE courses SET updated_at = CURRENT_TIMESTAMP WHERE id = OLD.course_id AND OLD.course_id IS NOT NEW.course_id;
+END;
+
+CREATE TRIGGER log_items_deleted_at AFTER DELETE ON log_items
+BEGIN
+    UPDATE courses SET updated_at = CURRENT_TIMESTAMP WHERE id = OLD.course_id;
+END;
+
+CREATE TRIGGER problems_inserted_at AFTER INSERT ON problems
+BEGIN
+    UPDATE problems SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER problems_updated_at AFTER UPDATE ON problems WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE problems SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER problems_deleted_at AFTER DELETE ON problems
+BEGIN
+    UPDATE courses SET updated_at = CURRENT_TIMESTAMP
+    WHERE id IN (SELECT course_id FROM log_items WHERE id = OLD.log_item_id UNION SELECT course_id FROM exams WHERE id = OLD.exam_id);
+END;
+
+-- Category names show next to each problem
+CREATE TRIGGER problem_categories_inserted_at AFTER INSERT ON problem_categories
+BEGIN
+    UPDATE problems SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.problem_id;
+END;
+
+CREATE TRIGGER problem_categories_deleted_at AFTER DELETE ON problem_categories
+BEGIN
+    UPDATE problems SET updated_at = CURRENT_TIMESTAMP WHERE id = OLD.problem_id;
+END;
+
+CREATE TRIGGER categories_updated_at AFTER UPDATE ON categories
+BEGIN
+    UPDATE courses SET updated_at = CURRENT_TIMESTAMP WHERE id IN (OLD.course_id, NEW.course_id);
+END;
+
+-- Exam titles name the source of their problems
+CREATE TRIGGER exams_updated_at AFTER UPDATE ON exams
+BEGIN
+    UPDATE courses SET updated_at = CURRENT_TIMESTAMP WHERE id IN (OLD.course_id, NEW.course_id);
+END;
+
+CREATE TRIGGER exams_deleted_at AFTER DELETE ON exams
+BEGIN
+    UPDATE courses SET updated_at = CURRENT_TIMESTAMP WHERE id = OLD.course_id;
+END;
+
+CREATE TRIGGER log_item_kinds_inserted_at AFTER INSERT ON log_item_kinds
+BEGIN
+    UPDATE courses SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.course_id;
+END;
+
+CREATE TRIGGER log_item_kinds_deleted_at AFTER DELETE ON log_item_kinds
+BEGIN
+    UPDATE courses SET updated_at = CURRENT_TIMESTAMP WHERE id = OLD.course_id;
+END;
@@ -0,0 +1,145 @@
+use rocket::http::{Method, Status};
+use rocket::Request;
+use rocket_db_pools::sqlx::{self, SqliteConnection, SqlitePool};
+use rocket_db_pools::Database;
+use crate::db::Db;
+
+// ========== Record Access ==========
+//
+// A semester belongs to the account that created it, and so does everything
+// filed under it: its courses and their log items, exams, problems and
+// discussions. Someone who joined a course with its join code shares that
+// course, but not the rest of the semester, and with the join code's role:
+// a viewer reads the course, an editor can also change it.
+//
+// AuthUser checks this for every signed-in route that names a record by its
+// leading segment, `/courses/<id>/…`, `/problems/<id>` or the API's
+// `/courses/<public_id>/…`: another account's record is a 404, the same as
+// one that doesn't exist, and anything but a GET from a viewer is a 403,
+// apart from the few things a viewer does for themselves (MEMBER_WRITES).
+// Lists across courses filter with COURSES instead.
+
+/// The ids of the courses a user can open, for `course_id IN (…)` with the
+/// user id bound once.
+pub const COURSES: &str = r#"
+    SELECT c.id FROM courses c JOIN semesters s ON c.semester_id = s.id
+    WHERE ? IN (SELECT s.user_id UNION ALL SELECT m.user_id FROM course_members m WHERE m.course_id = c.id)
+"#;
+
+/// The ids of the courses a user can change: their own, and those they joined
+/// as an editor. The user id is bound once.
+pub const EDITABLE_COURSES: &str = r#"
+    SELECT c.id FROM courses c JOIN semesters s ON c.semester_id = s.id
+    WHERE ? IN (SELECT s.user_id UNION ALL SELECT m.user_id FROM course_members m WHERE m.course_id = c.id AND m.role = 'editor')
+"#;
+
+/// Writes a viewer may make in a course they can open: their own study
+/// presets, goals and plans, and taking part in its discussions. Handlers
+/// check authorship where it matters.
+const MEMBER_WRITES: &[&str] = &[
+    "/courses/<id>/study/presets",
+    "/courses/<id>/study/goals",
+    "/courses/<id>/discussions",
+    "/courses/<id>/members/<user_id>",
+    "/discussions/<id>",
+    "/discussions/<id>/posts",
+    "/discussion_posts/<id>",
+    "/exams/<id>/plan",
+];
+
+/// Whether `user_id` can open `course_id`.
+pub async fn can_open_course(db: &mut SqliteConnection, user_id: i64, course_id: i64) -> bool {
+    sqlx::query_scalar(&format!("SELECT ? IN ({})", COURSES))
+        .bind(course_id)
+        .bind(user_id)
+        .fetch_one(&mut *db)
+        .await
+        .unwrap_or(false)
+}
+
+/// Whether `user_id` can change `course_id`.
+pub async fn can_edit_course(db: &mut SqliteConnection, user_id: i64, course_id: i64) -> bool {
+    sqlx::query_scalar(&format!("SELECT ? IN ({})", EDITABLE_COURSES))
+        .bind(course_id)
+        .bind(user_id)
+        .fetch_one(&mut *db)
+        .await
+        .unwrap_or(false)
+}
+
+/// The course a problem is filed under, through its log item or exam.
+pub async fn problem_course(db: &mut SqliteConnection, problem_id: i64) -> Option<i64> {
+    sqlx::query_scalar::<_, Option<i64>>(PROBLEM_COURSE)
+        .bind(problem_id)
+        .fetch_optional(&mut *db)
+        .await
+        .unwrap_or(None)
+        .flatten()
+}
+
+const PROBLEM_COURSE: &str =
+    "SELECT COALESCE(l.course_id, e.course_id) FROM problems p LEFT JOIN log_items l ON p.log_item_id = l.id LEFT JOIN exams e ON p.exam_id = e.id WHERE p.id = ?";
+
+// The course of the record a route names, by the segment in front of its id.
+// None for routes whose records aren't filed under a course.
+fn course_query(resource: &str, param: &str) -> Option<&'static str> {
+    let query = match (resource, param) {
+        ("courses", "<id>") => "SELECT id FROM courses WHERE id = ?",
+        ("courses", "<public_id>") => "SELECT id FROM courses WHERE public_id = ?",
+        ("logs", "<id>") => "SELECT course_id FROM log_items WHERE id = ?",
+        ("logs", "<public_id>") => "SELECT course_id FROM log_items WHERE public_id = ?",
+        ("exams", "<id>") => "SELECT course_id FROM exams WHERE id = ?",
+        ("problems", "<id>") => PROBLEM_COURSE,
+        ("problem-links", "<id>") => {
+            "SELECT COALESCE(l.course_id, e.course_id) FROM problem_links pl JOIN problems p ON pl.problem_id = p.id LEFT JOIN log_items l ON p.log_item_id = l.id LEFT JOIN exams e ON p.exam_id = e.id WHERE pl.id = ?"
+        }
+        ("bookmarks", "<id>") => "SELECT l.course_id FROM log_item_bookmarks b JOIN log_items l ON b.log_item_id = l.id WHERE b.id = ?",
+        ("discussions", "<id>") => "SELECT course_id FROM discussion_threads WHERE id = ?",
+        ("discussion_posts", "<id>") => "SELECT t.course_id FROM discussion_posts p JOIN discussion_threads t ON p.thread_id = t.id WHERE p.id = ?",
+        ("history", "<id>") => "SELECT course_id FROM entity_history WHERE id = ?",
+        _ => return None,
+    };
+    Some(query)
+}
+
+/// Whether `user_id` may use the route `request` was matched to: Err with
+/// the status to answer instead. Ids that don't exist pass, so the route
+/// answers for them as it always has.
+pub async fn allowed(request: &Request<'_>, user_id: i64) -> Result<(), Status> {
+    let Some(route) = request.route() else { return Ok(()) };
+    let path = route.uri.unmounted_origin.path();
+    let mut segments = path.as_str().trim_start_matches('/').split('/');
+    let (Some(resource), Some(param)) = (segments.next(), segments.next()) else { return Ok(()) };
+    let Some(Ok(value)) = request.param::<&str>(1) else { return Ok(()) };
+    let Some(pool) = Db::fetch(request.rocket()) else { return Err(Status::NotFound) };
+    let pool: &SqlitePool = pool;
+
+    if (resource, param) == ("semesters", "<id>") {
+        let owner: Option<Option<i64>> = sqlx::query_scalar("SELECT user_id FROM semesters WHERE id = ?")
+            .bind(value)
+            .fetch_optional(pool)
+            .await
+            .unwrap_or(None);
+        return match owner {
+            Some(owner) if owner != Some(user_id) => Err(Status::NotFound),
+            _ => Ok(()),
+        };
+    }
+
+    let Some(query) = course_query(resource, param) else { return Ok(()) };
+    let course_id: Option<Option<i64>> = sqlx::query_scalar(query)
+        .bind(value)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None);
+    let Some(course_id) = course_id.flatten() else { return Ok(()) };
+    let Ok(mut conn) = pool.acquire().await else { return Err(Status::NotFound) };
+    if !can_open_course(&mut conn, user_id, course_id).await {
+        return Err(Status::NotFound);
+    }
+    let reads = request.method() == Method::Get || MEMBER_WRITES.contains(&path.as_str());
+    if !reads && !can_edit_course(&mut conn, user_id, course_id).await {
+        return Err(Status::Forbidden);
+    }
+    Ok(())
+}
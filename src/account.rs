@@ -0,0 +1,85 @@
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx::{self, SqliteConnection, SqlitePool};
+use crate::config::{AppConfig, EmailConfig};
+use crate::{email, jobs, settings};
+
+// ========== Account Email ==========
+//
+// Users can add an email address at /account. Saving it mails a link to
+// /account/verify/<token>; once that is opened the address is verified and
+// works in place of the username at login. An address can be verified by
+// only one account. Changing it starts over with a new link.
+
+pub const JOB_KIND: &str = "send_email_verification";
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct VerificationJob {
+    email: EmailConfig,
+    /// For the link in the email
+    base_url: String,
+    user_id: i64,
+}
+
+/// Queue the verification email for the address `user_id` just saved. False
+/// when there is no mail server to send it with.
+pub async fn queue_verification(db: &mut SqliteConnection, config: &AppConfig, base_url: String, user_id: i64) -> bool {
+    if !email::is_configured(&config.email) {
+        return false;
+    }
+    let job = VerificationJob { email: config.email.clone(), base_url, user_id };
+    jobs::enqueue(db, JOB_KIND, &serde_json::to_string(&job).unwrap()).await;
+    true
+}
+
+/// Job handler: mail the link, unless the address was verified or changed
+/// away from in the meantime.
+pub async fn send_verification(pool: &SqlitePool, payload: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let job: VerificationJob = serde_json::from_str(payload)?;
+    let mut conn = pool.acquire().await?;
+    let pending = sqlx::query_as::<_, (String, String, String)>(
+        "SELECT username, email, email_token FROM users WHERE id = ? AND email_verified = 0 AND email IS NOT NULL AND email_token IS NOT NULL"
+    )
+    .bind(job.user_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some((username, address, token)) = pending else { return Ok(()) };
+
+    let site_name = settings::instance_name(&mut conn).await;
+    let body = verification_body(&site_name, &username, &verify_url(&job.base_url, &token));
+    email::send(&job.email, &address, &format!("{} 邮箱验证", site_name), &body, &[]).await?;
+    Ok(())
+}
+
+pub fn verify_url(base_url: &str, token: &str) -> String {
+    format!("{}/account/verify/{}", base_url, token)
+}
+
+fn verification_body(site_name: &str, username: &str, url: &str) -> String {
+    format!(
+        "{}，你好：\n\n你在 {} 的账号添加了这个邮箱。打开下面的链接完成验证，之后就可以用它代替用户名登录：\n\n{}\n\n如果这不是你的操作，忽略这封邮件即可。\n",
+        username, site_name, url
+    )
+}
+
+/// Verify the address waiting on `token`. False for an unknown token, or
+/// when another account has verified the same address first.
+pub async fn verify(db: &mut SqliteConnection, token: &str) -> bool {
+    sqlx::query("UPDATE users SET email_verified = 1, email_token = NULL WHERE email_token = ?")
+        .bind(token)
+        .execute(&mut *db)
+        .await
+        .is_ok_and(|result| result.rows_affected() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verification_body() {
+        let body = verification_body("智习", "alice", &verify_url("https://zhixi.example.com", "3f2a9c01"));
+        assert!(body.starts_with("alice，你好："));
+        assert!(body.contains("\n\nhttps://zhixi.example.com/account/verify/3f2a9c01\n\n"));
+    }
+}
@@ -0,0 +1,600 @@
+use rocket::data::{Data, Limits, ToByteUnit};
+use rocket::http::{ContentType, Status};
+use rocket::serde::json::Json;
+use rocket::State;
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::Connection;
+use rocket_db_pools::sqlx::{self, FromRow};
+use std::path::PathBuf;
+use uuid::Uuid;
+use crate::access;
+use crate::db::Db;
+use crate::models::*;
+use crate::auth::AuthUser;
+use crate::category_cache::CategoryCache;
+use crate::config::AppConfig;
+use crate::queries;
+use crate::routes::insert_log_problem;
+use crate::review;
+use crate::scan;
+use crate::uploads::{self, SavedUpload};
+
+// ========== Chunked Uploads ==========
+//
+// Phones on flaky connections upload a screenshot as numbered chunks:
+//   POST /api/v1/logs/<public id>/problems?upload_id=<uuid>&chunk=<n>&total=<m>
+// Chunks may arrive in any order and be retried. Once all `total` chunks are
+// present they are assembled into upload storage and go through the same problem
+// creation path as the HTML form. An optional `filename` (sent with the last
+// chunk) is kept as the problem's original filename and determines its type.
+// Together the chunks may not exceed the `file` limit that caps uploads through
+// the form; an upload that grows past it is dropped with 413.
+// `GET /api/v1/uploads/<upload_id>` reports which chunks the server already has
+// so a client can resume.
+
+// Chunks are staged in this subdirectory of upload storage
+const CHUNK_DIR: &str = ".chunks";
+const MAX_CHUNKS: u32 = 1024;
+
+#[derive(FromForm)]
+struct ChunkMeta {
+    upload_id: String,
+    chunk: u32,
+    total: u32,
+    notes: Option<String>,
+    categories: Option<String>,
+    solution_link: Option<String>,
+    answer: Option<String>,
+    filename: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct UploadStatus {
+    upload_id: String,
+    received: Vec<u32>,
+    complete: bool,
+    problem: Option<ProblemWithCategories>,
+}
+
+// Upload ids name directories on disk, so only accept canonical UUIDs
+async fn chunk_dir(db: &mut Connection<Db>, upload_id: &str) -> Option<PathBuf> {
+    let id = Uuid::parse_str(upload_id).ok()?;
+    Some(uploads::dir(db).await.join(CHUNK_DIR).join(id.hyphenated().to_string()))
+}
+
+async fn received_chunks(dir: &PathBuf) -> Vec<u32> {
+    let mut received = Vec::new();
+    if let Ok(mut entries) = tokio::fs::read_dir(dir).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if let Some(n) = entry.file_name().to_str().and_then(|n| n.parse::<u32>().ok()) {
+                received.push(n);
+            }
+        }
+    }
+    received.sort_unstable();
+    received
+}
+
+// The size of the chunks received so far
+async fn received_bytes(dir: &PathBuf) -> u64 {
+    let mut total = 0;
+    if let Ok(mut entries) = tokio::fs::read_dir(dir).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if let Ok(metadata) = entry.metadata().await {
+                total += metadata.len();
+            }
+        }
+    }
+    total
+}
+
+#[post("/logs/<public_id>/problems?<meta..>", data = "<data>")]
+#[allow(clippy::too_many_arguments)]
+async fn upload_problem_chunk(mut db: Connection<Db>, _user: AuthUser, config: &State<AppConfig>, category_cache: &State<CategoryCache>, limits: &Limits, public_id: &str, meta: ChunkMeta, data: Data<'_>) -> Result<Json<UploadStatus>, Status> {
+    let dir = chunk_dir(&mut db, &meta.upload_id).await.ok_or(Status::BadRequest)?;
+    if meta.total == 0 || meta.total > MAX_CHUNKS || meta.chunk >= meta.total {
+        return Err(Status::BadRequest);
+    }
+
+    let log_item = queries::fetch_log_item_by_public_id(&mut db, public_id).await.ok_or(Status::NotFound)?;
+
+    tokio::fs::create_dir_all(&dir).await.map_err(|_| Status::InternalServerError)?;
+
+    // Write to a temp name first so a dropped connection never leaves a truncated chunk
+    let part_path = dir.join(format!("{}.part", meta.chunk));
+    let file = data.open(8.mebibytes())
+        .into_file(&part_path)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    if !file.is_complete() {
+        let _ = tokio::fs::remove_file(&part_path).await;
+        return Err(Status::PayloadTooLarge);
+    }
+    tokio::fs::rename(&part_path, dir.join(meta.chunk.to_string()))
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    let max_bytes = limits.get("file").unwrap_or(Limits::FILE).as_u64();
+    if received_bytes(&dir).await > max_bytes {
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        return Err(Status::PayloadTooLarge);
+    }
+
+    let received = received_chunks(&dir).await;
+    if received.len() < meta.total as usize {
+        return Ok(Json(UploadStatus { upload_id: meta.upload_id, received, complete: false, problem: None }));
+    }
+
+    // Claim assembly by renaming the directory; a concurrent final chunk loses the race
+    let assembling = dir.with_extension("assembling");
+    if tokio::fs::rename(&dir, &assembling).await.is_err() {
+        return Ok(Json(UploadStatus { upload_id: meta.upload_id, received, complete: false, problem: None }));
+    }
+
+    // Chunks arriving at the same time can each pass the check above
+    let mut assembled = Vec::new();
+    for n in 0..meta.total {
+        let bytes = tokio::fs::read(assembling.join(n.to_string()))
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+        if (assembled.len() + bytes.len()) as u64 > max_bytes {
+            let _ = tokio::fs::remove_dir_all(&assembling).await;
+            return Err(Status::PayloadTooLarge);
+        }
+        assembled.extend_from_slice(&bytes);
+    }
+    let original_filename = meta.filename.as_deref().and_then(uploads::clean_filename);
+    let mime_type = original_filename.as_deref()
+        .and_then(|name| name.rsplit_once('.'))
+        .and_then(|(_, ext)| ContentType::from_extension(ext))
+        .map(|ct| format!("{}/{}", ct.top(), ct.sub()));
+    // Refused by the deployment's upload scan; the chunks are dropped either way
+    if scan::check(&config.upload_scan, &assembled, mime_type.as_deref()).await.is_err() {
+        let _ = tokio::fs::remove_dir_all(&assembling).await;
+        return Err(Status::UnprocessableEntity);
+    }
+    let ext = uploads::extension_for(&assembled, mime_type.as_deref(), original_filename.as_deref());
+    let url = uploads::store(&mut db, &assembled, &ext)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let _ = tokio::fs::remove_dir_all(&assembling).await;
+
+    let upload = SavedUpload { url, original_filename, mime_type, imported_from: None };
+    let problem = insert_log_problem(&mut db, category_cache, &log_item, Some(upload), None, meta.notes, meta.solution_link, meta.answer, meta.categories.as_deref()).await;
+
+    Ok(Json(UploadStatus { upload_id: meta.upload_id, received, complete: true, problem: Some(problem) }))
+}
+
+#[get("/uploads/<upload_id>")]
+async fn upload_status(mut db: Connection<Db>, _user: AuthUser, upload_id: &str) -> Result<Json<UploadStatus>, Status> {
+    let dir = chunk_dir(&mut db, upload_id).await.ok_or(Status::BadRequest)?;
+    let received = received_chunks(&dir).await;
+    Ok(Json(UploadStatus { upload_id: upload_id.to_string(), received, complete: false, problem: None }))
+}
+
+// ========== Problem Queries ==========
+//
+// `GET /api/v1/courses/<public id>/problems` lists a course's problem bank for
+// external tools. Problems and log items are identified by their public ids. Filters combine with AND; repeated `category` or `kind`
+// parameters match any of the given values:
+//   ?category=极限&category=导数   category names
+//   ?kind=Homework&kind=Exam       log item kind, or "Exam" for exam problems
+//   ?incorrect=true                only problems marked incorrect
+//   ?from=2026-09-01&to=2026-09-30 log item date (creation date for exam problems)
+//   ?has_solution=false            only problems with neither a solution link nor an answer
+//   ?fields=id,image_url           only return these keys
+
+const PROBLEM_FIELDS: &[&str] = &[
+    "id", "number", "log_item_id", "exam_id", "description", "notes", "image_url", "body", "solution_link", "answer",
+    "original_filename", "mime_type", "is_incorrect", "categories", "source_kind", "source_title", "source_url", "date", "created_at",
+];
+
+#[derive(FromForm)]
+struct ProblemFilter {
+    category: Vec<String>,
+    kind: Vec<String>,
+    incorrect: Option<bool>,
+    from: Option<String>,
+    to: Option<String>,
+    has_solution: Option<bool>,
+    fields: Option<String>,
+}
+
+#[derive(FromRow)]
+struct ProblemRow {
+    public_id: String,
+    number: Option<i64>,
+    log_item_public_id: Option<String>,
+    exam_id: Option<i64>,
+    description: String,
+    notes: Option<String>,
+    image_url: Option<String>,
+    body: Option<String>,
+    solution_link: Option<String>,
+    answer: Option<String>,
+    original_filename: Option<String>,
+    mime_type: Option<String>,
+    is_incorrect: bool,
+    category_names: Option<String>,
+    source_kind: String,
+    source_title: String,
+    source_url: String,
+    date: Option<String>,
+    created_at: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct ApiProblem {
+    id: String,
+    /// Within the course, shown as P-042
+    number: Option<i64>,
+    log_item_id: Option<String>,
+    exam_id: Option<i64>,
+    description: String,
+    notes: Option<String>,
+    image_url: Option<String>,
+    body: Option<String>,
+    solution_link: Option<String>,
+    answer: Option<String>,
+    original_filename: Option<String>,
+    mime_type: Option<String>,
+    is_incorrect: bool,
+    categories: Vec<String>,
+    source_kind: String,
+    source_title: String,
+    source_url: String,
+    date: Option<String>,
+    created_at: Option<String>,
+}
+
+impl From<ProblemRow> for ApiProblem {
+    fn from(row: ProblemRow) -> ApiProblem {
+        let categories = row.category_names
+            .map(|names| names.split(',').map(String::from).collect())
+            .unwrap_or_default();
+        ApiProblem {
+            id: row.public_id,
+            number: row.number,
+            log_item_id: row.log_item_public_id,
+            exam_id: row.exam_id,
+            description: row.description,
+            notes: row.notes,
+            image_url: row.image_url,
+            body: row.body,
+            solution_link: row.solution_link,
+            answer: row.answer,
+            original_filename: row.original_filename,
+            mime_type: row.mime_type,
+            is_incorrect: row.is_incorrect,
+            categories,
+            source_kind: row.source_kind,
+            source_title: row.source_title,
+            source_url: row.source_url,
+            date: row.date,
+            created_at: row.created_at,
+        }
+    }
+}
+
+fn parse_date(value: &Option<String>) -> Result<Option<String>, Status> {
+    match value.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+        Some(v) => chrono::NaiveDate::parse_from_str(v, "%Y-%m-%d")
+            .map(|d| Some(d.format("%Y-%m-%d").to_string()))
+            .map_err(|_| Status::BadRequest),
+        None => Ok(None),
+    }
+}
+
+#[get("/courses/<public_id>/problems?<filter..>")]
+async fn list_course_problems(mut db: Connection<Db>, _user: AuthUser, public_id: &str, filter: ProblemFilter) -> Result<Json<Vec<serde_json::Value>>, Status> {
+    let fields: Option<Vec<String>> = filter.fields.as_deref().map(|f| {
+        f.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+    });
+    if let Some(fields) = &fields {
+        if fields.iter().any(|f| !PROBLEM_FIELDS.contains(&f.as_str())) {
+            return Err(Status::BadRequest);
+        }
+    }
+    let from = parse_date(&filter.from)?;
+    let to = parse_date(&filter.to)?;
+
+    let id = queries::fetch_course_by_public_id(&mut db, public_id).await.ok_or(Status::NotFound)?.id;
+
+    let mut query = String::from(
+        r#"
+        SELECT
+            p.public_id, p.number, l.public_id as log_item_public_id, p.exam_id, p.description, p.notes, p.image_url, p.body, p.solution_link, p.answer, p.original_filename, p.mime_type, p.is_incorrect,
+            GROUP_CONCAT(c.name) as category_names,
+            src.source_kind, src.source_title, src.source_url,
+            COALESCE(l.date, date(p.created_at)) as date,
+            p.created_at
+        FROM problems p
+        JOIN problem_sources src ON src.problem_id = p.id
+        LEFT JOIN log_items l ON p.log_item_id = l.id
+        LEFT JOIN exams e ON p.exam_id = e.id
+        LEFT JOIN problem_categories pc ON p.id = pc.problem_id
+        LEFT JOIN categories c ON pc.category_id = c.id
+        WHERE (l.course_id = ? OR e.course_id = ?)
+        "#
+    );
+
+    let has_exam = filter.kind.iter().any(|k| k == "Exam");
+    let log_kinds: Vec<&String> = filter.kind.iter().filter(|k| *k != "Exam").collect();
+    let kind_placeholders = vec!["?"; log_kinds.len()].join(", ");
+    if has_exam && !log_kinds.is_empty() {
+        query.push_str(&format!(" AND (l.kind IN ({}) OR p.exam_id IS NOT NULL)", kind_placeholders));
+    } else if has_exam {
+        query.push_str(" AND p.exam_id IS NOT NULL");
+    } else if !log_kinds.is_empty() {
+        query.push_str(&format!(" AND l.kind IN ({})", kind_placeholders));
+    }
+
+    if !filter.category.is_empty() {
+        query.push_str(&format!(
+            " AND p.id IN (SELECT pc2.problem_id FROM problem_categories pc2 JOIN categories c2 ON pc2.category_id = c2.id WHERE c2.name IN ({}))",
+            vec!["?"; filter.category.len()].join(", ")
+        ));
+    }
+    if filter.incorrect.is_some() {
+        query.push_str(" AND p.is_incorrect = ?");
+    }
+    if from.is_some() {
+        query.push_str(" AND COALESCE(l.date, date(p.created_at)) >= ?");
+    }
+    if to.is_some() {
+        query.push_str(" AND COALESCE(l.date, date(p.created_at)) <= ?");
+    }
+    match filter.has_solution {
+        Some(true) => query.push_str(" AND (COALESCE(p.solution_link, '') != '' OR p.answer IS NOT NULL)"),
+        Some(false) => query.push_str(" AND COALESCE(p.solution_link, '') = '' AND p.answer IS NULL"),
+        None => {}
+    }
+
+    query.push_str(" GROUP BY p.id ORDER BY p.id");
+
+    let mut q = sqlx::query_as::<_, ProblemRow>(&query)
+        .bind(id)
+        .bind(id);
+    for k in &log_kinds {
+        q = q.bind(*k);
+    }
+    for c in &filter.category {
+        q = q.bind(c);
+    }
+    if let Some(incorrect) = filter.incorrect {
+        q = q.bind(incorrect);
+    }
+    if let Some(from) = &from {
+        q = q.bind(from);
+    }
+    if let Some(to) = &to {
+        q = q.bind(to);
+    }
+    let rows = q
+        .fetch_all(&mut **db)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    let problems = rows
+        .into_iter()
+        .map(|row| {
+            let mut value = serde_json::to_value(ApiProblem::from(row)).unwrap();
+            if let (Some(fields), Some(object)) = (&fields, value.as_object_mut()) {
+                object.retain(|key, _| fields.contains(key));
+            }
+            value
+        })
+        .collect();
+
+    Ok(Json(problems))
+}
+
+// ========== Study Session ==========
+//
+// Drives the full-screen keyboard review pages (/courses/<id>/study/review and
+// the cross-course /study/review).
+// `GET /api/v1/study/session?course_id=<public id>` returns the first due card plus
+// the image URL of the card after it, so the client can prefetch it while the
+// user is still looking at the current one. `POST /api/v1/study/answer` records
+// a 1–4 grade, reschedules the card and returns the next session state in the
+// same round trip. Without `course_id` the queue spans every course; `topic`
+// narrows it to problems in a category with that name or aliased to it (see
+// /study/topics), so equivalent categories in different courses are one queue. The page
+// also sends how long the card was on screen before the grade, which feeds
+// the study page's time per category.
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct StudySession {
+    remaining: i64,
+    card: Option<ProblemWithCategories>,
+    next_image_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct StudyAnswer {
+    problem_id: String,
+    grade: u8,
+    course_id: Option<String>,
+    topic: Option<String>,
+    /// From the card appearing to the grade
+    time_spent_ms: Option<i64>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct StudyAnswerResult {
+    problem_id: String,
+    interval_days: f64,
+    due_at: String,
+    session: StudySession,
+}
+
+const MAX_TIME_SPENT_MS: i64 = 30 * 60 * 1000;
+
+// Due cards first (earliest due first), then problems never reviewed, from
+// the courses the user can open (access::COURSES goes last)
+const DUE_PROBLEMS: &str = r#"
+    FROM problems p
+    JOIN problem_sources src ON src.problem_id = p.id
+    LEFT JOIN log_items l ON p.log_item_id = l.id
+    LEFT JOIN exams e ON p.exam_id = e.id
+    LEFT JOIN study_cards s ON s.problem_id = p.id AND s.user_id = ?
+    WHERE (? IS NULL OR COALESCE(l.course_id, e.course_id) = ?)
+      AND (? IS NULL OR p.id IN (
+          SELECT pc.problem_id FROM problem_categories pc
+          JOIN categories c ON pc.category_id = c.id
+          LEFT JOIN category_aliases a ON a.user_id = ? AND a.alias = c.name
+          WHERE COALESCE(a.topic, c.name) = ? COLLATE NOCASE))
+      AND (s.due_at IS NULL OR s.due_at <= datetime('now'))
+      AND COALESCE(l.course_id, e.course_id) IN
+"#;
+
+async fn study_session(db: &mut Connection<Db>, user_id: i64, course_id: Option<i64>, topic: Option<&str>) -> StudySession {
+    let remaining: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) {} ({})", DUE_PROBLEMS, access::COURSES))
+        .bind(user_id)
+        .bind(course_id)
+        .bind(course_id)
+        .bind(topic)
+        .bind(user_id)
+        .bind(topic)
+        .bind(user_id)
+        .fetch_one(&mut ***db)
+        .await
+        .unwrap_or(0);
+
+    let query = format!(
+        r#"
+        SELECT
+            p.id, p.log_item_id, p.exam_id, p.description, p.notes, p.image_url, p.body, p.solution_link, p.answer, p.original_filename, p.mime_type, p.public_id, p.number,
+            (SELECT GROUP_CONCAT(c.name) FROM problem_categories pc JOIN categories c ON pc.category_id = c.id WHERE pc.problem_id = p.id) as category_names,
+            src.source_kind, src.source_title, src.source_url
+        {} ({})
+        ORDER BY s.due_at IS NULL, s.due_at, p.id
+        LIMIT 2
+        "#,
+        DUE_PROBLEMS,
+        access::COURSES
+    );
+    let mut cards = sqlx::query_as::<_, ProblemWithCategories>(&query)
+        .bind(user_id)
+        .bind(course_id)
+        .bind(course_id)
+        .bind(topic)
+        .bind(user_id)
+        .bind(topic)
+        .bind(user_id)
+        .fetch_all(&mut ***db)
+        .await
+        .unwrap_or_default()
+        .into_iter();
+
+    let card = cards.next();
+    let next_image_url = cards.next().filter(|p| p.is_image()).and_then(|p| p.image_url);
+    StudySession { remaining, card, next_image_url }
+}
+
+// A course's public id to its row id; None (every course) stays None. Courses
+// the user can't open are as good as missing.
+async fn study_course_id(db: &mut Connection<Db>, user_id: i64, public_id: Option<&str>) -> Result<Option<i64>, Status> {
+    let Some(public_id) = public_id else { return Ok(None) };
+    let course = queries::fetch_course_by_public_id(db, public_id).await.ok_or(Status::NotFound)?;
+    if !access::can_open_course(db, user_id, course.id).await {
+        return Err(Status::NotFound);
+    }
+    Ok(Some(course.id))
+}
+
+#[get("/study/session?<course_id>&<topic>")]
+async fn get_study_session(mut db: Connection<Db>, user: AuthUser, course_id: Option<&str>, topic: Option<&str>) -> Result<Json<StudySession>, Status> {
+    let course_id = study_course_id(&mut db, user.id, course_id).await?;
+    Ok(Json(study_session(&mut db, user.id, course_id, topic).await))
+}
+
+#[post("/study/answer", data = "<answer>")]
+async fn answer_study_card(mut db: Connection<Db>, user: AuthUser, answer: Json<StudyAnswer>) -> Result<Json<StudyAnswerResult>, Status> {
+    let answer = answer.into_inner();
+
+    let problem_id: i64 = sqlx::query_scalar(&format!(
+        r#"
+        SELECT p.id FROM problems p
+        LEFT JOIN log_items l ON p.log_item_id = l.id
+        LEFT JOIN exams e ON p.exam_id = e.id
+        WHERE p.public_id = ? AND COALESCE(l.course_id, e.course_id) IN ({})
+        "#,
+        access::COURSES
+    ))
+    .bind(&answer.problem_id)
+    .bind(user.id)
+    .fetch_optional(&mut **db)
+    .await
+    .unwrap_or(None)
+    .ok_or(Status::NotFound)?;
+    let course_id = study_course_id(&mut db, user.id, answer.course_id.as_deref()).await?;
+
+    let current: f64 = sqlx::query_scalar("SELECT interval_days FROM study_cards WHERE user_id = ? AND problem_id = ?")
+        .bind(user.id)
+        .bind(problem_id)
+        .fetch_optional(&mut **db)
+        .await
+        .unwrap_or(None)
+        .unwrap_or(0.0);
+    let interval_days = review::next_interval(current, answer.grade).ok_or(Status::BadRequest)?;
+
+    let delay = if answer.grade == 1 {
+        chrono::Duration::minutes(review::AGAIN_DELAY_MINUTES)
+    } else {
+        chrono::Duration::seconds((interval_days * 86400.0) as i64)
+    };
+    let now = chrono::Utc::now();
+    let due_at = now.checked_add_signed(delay).unwrap_or(now + chrono::Duration::days(review::MAX_INTERVAL_DAYS as i64))
+        .format("%Y-%m-%d %H:%M:%S").to_string();
+
+    sqlx::query(
+        r#"
+        INSERT INTO study_cards (user_id, problem_id, interval_days, reviews, last_grade, due_at)
+        VALUES (?, ?, ?, 1, ?, ?)
+        ON CONFLICT (user_id, problem_id) DO UPDATE SET
+            interval_days = excluded.interval_days,
+            reviews = reviews + 1,
+            last_grade = excluded.last_grade,
+            due_at = excluded.due_at
+        "#,
+    )
+    .bind(user.id)
+    .bind(problem_id)
+    .bind(interval_days)
+    .bind(answer.grade)
+    .bind(&due_at)
+    .execute(&mut **db)
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    // Study goals count progress from this log. A card left open for longer
+    // than anyone spends on one problem was walked away from; its time is dropped.
+    let time_spent_ms = answer.time_spent_ms.filter(|ms| (1..=MAX_TIME_SPENT_MS).contains(ms));
+    sqlx::query("INSERT INTO study_reviews (user_id, problem_id, grade, reviewed_at, time_spent_ms) VALUES (?, ?, ?, CURRENT_TIMESTAMP, ?)")
+        .bind(user.id)
+        .bind(problem_id)
+        .bind(answer.grade)
+        .bind(time_spent_ms)
+        .execute(&mut **db)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    let session = study_session(&mut db, user.id, course_id, answer.topic.as_deref()).await;
+    Ok(Json(StudyAnswerResult { problem_id: answer.problem_id, interval_days, due_at, session }))
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![
+        upload_problem_chunk,
+        upload_status,
+        list_course_problems,
+        get_study_session,
+        answer_study_card
+    ]
+}
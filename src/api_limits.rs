@@ -0,0 +1,211 @@
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::{Build, Request, Response, Rocket};
+use rocket_db_pools::sqlx::{self, SqliteConnection, SqlitePool};
+use rocket_db_pools::Database;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use crate::db::Db;
+use crate::settings;
+
+// ========== API Limits ==========
+//
+// The admin can cap how hard each account may use the JSON API under
+// /api/v1, the chunked screenshot uploads phones capture with included: so
+// many requests a minute and so many a (UTC) day, both set on the site
+// settings page and unlimited when left empty. A script stuck in a loop then
+// gets 429s instead of keeping a small instance's SQLite busy. The API signs
+// in with the session cookie, so the limits are per account.
+//
+// AuthUser counts a request once it knows who is asking. This minute's
+// counts are kept in memory, in the `MinuteWindows` the `RateLimitHeaders`
+// fairing manages, and start over when the server restarts; daily counts are
+// kept in `api_usage`. Responses carry the RateLimit-* headers of the IETF
+// draft for whichever limit is closer to running out, and a refusal says
+// when to come back in Retry-After.
+//
+// Telegram capture (telegram.rs) isn't counted. Only chats the admin listed
+// in the config reach an account, and Telegram keeps resending an update
+// that gets anything but 200, so a 429 would only bring more requests.
+
+pub const PER_MINUTE: &str = "api_requests_per_minute";
+pub const PER_DAY: &str = "api_requests_per_day";
+
+/// Requests per user in the current minute. Every window starts on the
+/// minute, so they are all dropped together when the next one begins.
+#[derive(Default)]
+pub struct MinuteWindows(Mutex<Minute>);
+
+#[derive(Default)]
+struct Minute {
+    /// Minutes since the epoch
+    minute: u64,
+    /// User id -> requests in it
+    requests: BTreeMap<i64, u32>,
+}
+
+impl Minute {
+    fn count(&mut self, minute: u64, user_id: i64) -> u32 {
+        if self.minute != minute {
+            self.minute = minute;
+            self.requests.clear();
+        }
+        let used = self.requests.entry(user_id).or_default();
+        *used += 1;
+        *used
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    pub per_minute: Option<u32>,
+    pub per_day: Option<u32>,
+}
+
+pub async fn limits(db: &mut SqliteConnection) -> Limits {
+    let get = |value: Option<String>| value.and_then(|v| v.parse().ok()).filter(|n| *n > 0);
+    Limits {
+        per_minute: get(settings::get(db, PER_MINUTE).await),
+        per_day: get(settings::get(db, PER_DAY).await),
+    }
+}
+
+pub async fn set_limits(db: &mut SqliteConnection, limits: Limits) {
+    let value = |limit: Option<u32>| limit.map(|n| n.to_string()).unwrap_or_default();
+    settings::set(db, PER_MINUTE, &value(limits.per_minute)).await;
+    settings::set(db, PER_DAY, &value(limits.per_day)).await;
+}
+
+// Where a request leaves its caller under one limit
+#[derive(Debug, Clone, PartialEq)]
+struct Standing {
+    limit: u32,
+    remaining: u32,
+    /// Seconds until the window starts over
+    reset: u64,
+    refused: bool,
+}
+
+impl Standing {
+    fn new(limit: u32, used: u32, reset: u64) -> Standing {
+        Standing { limit, remaining: limit.saturating_sub(used), reset, refused: used > limit }
+    }
+}
+
+// What the response headers report: a refusal, else the limit with the
+// fewest requests left, and every limit in the policy
+struct Report {
+    closest: Standing,
+    policy: String,
+}
+
+/// Count a request by `user_id` if it is an API request; false once that
+/// puts it over a limit. A request is counted once, however often this is
+/// asked about it.
+pub async fn allowed(request: &Request<'_>, user_id: i64) -> bool {
+    if !request.uri().path().starts_with("/api/") {
+        return true;
+    }
+    let report = request.local_cache_async(async { count(request, user_id).await }).await;
+    match report {
+        Some(report) => !report.closest.refused,
+        None => true,
+    }
+}
+
+async fn count(request: &Request<'_>, user_id: i64) -> Option<Report> {
+    let pool: &SqlitePool = Db::fetch(request.rocket())?;
+    let mut conn = pool.acquire().await.ok()?;
+    let limits = limits(&mut conn).await;
+    let now = chrono::Utc::now().timestamp() as u64;
+
+    let mut standings = Vec::new();
+    let mut policy = Vec::new();
+    if let Some(limit) = limits.per_minute {
+        let windows = request.rocket().state::<MinuteWindows>()?;
+        let used = windows.0.lock().unwrap().count(now / 60, user_id);
+        standings.push(Standing::new(limit, used, 60 - now % 60));
+        policy.push(format!("{};w=60", limit));
+    }
+    if let Some(limit) = limits.per_day {
+        // Requests the minute limit already refused don't use up the day
+        let used = if standings.iter().any(|s| s.refused) {
+            0
+        } else {
+            sqlx::query_scalar::<_, i64>(
+                "INSERT INTO api_usage (user_id, day, requests) VALUES (?, date('now'), 1) ON CONFLICT (user_id, day) DO UPDATE SET requests = requests + 1 RETURNING requests"
+            )
+            .bind(user_id)
+            .fetch_one(&mut *conn)
+            .await
+            .ok()?
+        };
+        standings.push(Standing::new(limit, used as u32, 86400 - now % 86400));
+        policy.push(format!("{};w=86400", limit));
+    }
+
+    let closest = closest(standings)?;
+    Some(Report { closest, policy: policy.join(", ") })
+}
+
+fn closest(standings: Vec<Standing>) -> Option<Standing> {
+    standings.into_iter().min_by_key(|s| (!s.refused, s.remaining))
+}
+
+/// Fairing that holds the minute windows and adds the rate limit headers to
+/// API responses.
+pub struct RateLimitHeaders;
+
+#[rocket::async_trait]
+impl Fairing for RateLimitHeaders {
+    fn info(&self) -> Info {
+        Info { name: "API Rate Limit Headers", kind: Kind::Ignite | Kind::Response }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        Ok(rocket.manage(MinuteWindows::default()))
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let Some(report) = req.local_cache(|| None::<Report>) else { return };
+        let standing = &report.closest;
+        res.set_header(Header::new("RateLimit-Limit", standing.limit.to_string()));
+        res.set_header(Header::new("RateLimit-Remaining", standing.remaining.to_string()));
+        res.set_header(Header::new("RateLimit-Reset", standing.reset.to_string()));
+        res.set_header(Header::new("RateLimit-Policy", report.policy.clone()));
+        if standing.refused {
+            res.set_header(Header::new("Retry-After", standing.reset.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest() {
+        let minute = Standing::new(60, 10, 30);
+        let day = Standing::new(1000, 995, 3600);
+        assert_eq!((minute.remaining, minute.refused), (50, false));
+        assert_eq!(closest(vec![minute.clone(), day.clone()]), Some(day));
+
+        // A refusal is reported even with more left elsewhere
+        let refused = Standing::new(5, 6, 12);
+        assert_eq!((refused.remaining, refused.refused), (0, true));
+        assert_eq!(closest(vec![Standing::new(10, 10, 1), refused.clone()]), Some(refused));
+        assert_eq!(closest(Vec::new()), None);
+    }
+
+    #[test]
+    fn test_minute_count() {
+        let mut minute = Minute::default();
+        assert_eq!(minute.count(100, 1), 1);
+        assert_eq!(minute.count(100, 1), 2);
+        assert_eq!(minute.count(100, 2), 1);
+
+        // The next minute starts everyone over and forgets the last
+        assert_eq!(minute.count(101, 2), 1);
+        assert_eq!(minute.requests.len(), 1);
+    }
+}
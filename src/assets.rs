@@ -0,0 +1,77 @@
+use rocket::http::{ContentType, Header};
+use rocket::Route;
+use std::borrow::Cow;
+use crate::dev;
+
+// ========== Static Assets ==========
+//
+// The CSS and JS the layouts share live in static/. build.rs embeds each file
+// in the binary under a name carrying a hash of its contents, and templates
+// link them through `url`, e.g. `{{ crate::assets::url("app.css") }}` gives
+// "/static/app.0e12688d.css". A changed file gets a new URL, so browsers may
+// keep what they fetched for a year without ever running stale code against
+// newer pages. In dev mode they're read from disk instead, and not cached;
+// see dev.rs.
+
+/// A file from static/, as generated by build.rs
+pub struct Asset {
+    pub name: &'static str,
+    pub path: &'static str,
+    pub bytes: &'static [u8],
+}
+
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+const CACHE_FOREVER: &str = "public, max-age=31536000, immutable";
+const NO_STORE: &str = "no-store";
+
+/// The fingerprinted URL for `name`, a file in static/. Panics for files
+/// that aren't there, which the template snapshots would catch.
+pub fn url(name: &str) -> String {
+    let asset = ASSETS.iter().find(|asset| asset.name == name)
+        .unwrap_or_else(|| panic!("no static asset named {}", name));
+    format!("/static/{}", asset.path)
+}
+
+#[derive(Responder)]
+pub struct AssetResponse {
+    bytes: Cow<'static, [u8]>,
+    content_type: ContentType,
+    cache_control: Header<'static>,
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![serve]
+}
+
+// Only fingerprinted names resolve: the plain name has no URL to cache under
+#[get("/<path>")]
+fn serve(path: &str, dev: dev::Enabled) -> Option<AssetResponse> {
+    let asset = ASSETS.iter().find(|asset| asset.path == path)?;
+    let content_type = path.rsplit_once('.')
+        .and_then(|(_, ext)| ContentType::from_extension(ext))
+        .unwrap_or(ContentType::Binary);
+    Some(match dev {
+        dev::Enabled(true) => AssetResponse {
+            bytes: Cow::Owned(from_disk(asset)),
+            content_type,
+            cache_control: Header::new("Cache-Control", NO_STORE),
+        },
+        dev::Enabled(false) => AssetResponse {
+            bytes: Cow::Borrowed(asset.bytes),
+            content_type,
+            cache_control: Header::new("Cache-Control", CACHE_FOREVER),
+        },
+    })
+}
+
+// The file as it is now, or as built if it has gone missing
+#[cfg(debug_assertions)]
+fn from_disk(asset: &Asset) -> Vec<u8> {
+    dev::read_static(asset.name).unwrap_or_else(|| asset.bytes.to_vec())
+}
+
+#[cfg(not(debug_assertions))]
+fn from_disk(asset: &Asset) -> Vec<u8> {
+    asset.bytes.to_vec()
+}
@@ -1,25 +1,76 @@
 use rocket::request::{Outcome, Request, FromRequest};
-use rocket::http::Status;
+use rocket::http::{Cookie, CookieJar, SameSite, Status};
+use crate::access;
+use crate::api_limits;
+use crate::config::{AppConfig, SameSitePolicy, SessionConfig, DEFAULT_COOKIE_NAME};
 
 #[derive(Debug, Clone)]
 pub struct AuthUser {
     pub id: i64,
 }
 
+// Signed in, and allowed to see the record the route names (see access.rs);
+// someone else's record is a 404, and a write to a course joined as a viewer
+// a 403. API requests over the admin's limits are a 429 (see api_limits.rs).
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for AuthUser {
     type Error = ();
 
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        match request.cookies().get_private("user_id") {
+        match request.cookies().get_private(cookie_name(request)) {
             Some(cookie) => {
-                match cookie.value().parse::<i64>() {
-                    Ok(id) => Outcome::Success(AuthUser { id }),
-                    Err(_) => Outcome::Forward(Status::Unauthorized),
+                let Ok(id) = cookie.value().parse::<i64>() else {
+                    return Outcome::Forward(Status::Unauthorized);
+                };
+                if let Err(status) = access::allowed(request, id).await {
+                    return Outcome::Error((status, ()));
                 }
+                if !api_limits::allowed(request, id).await {
+                    return Outcome::Error((Status::TooManyRequests, ()));
+                }
+                Outcome::Success(AuthUser { id })
             },
             None => Outcome::Forward(Status::Unauthorized),
         }
     }
 }
 
+// ========== Session Cookie ==========
+//
+// The login is a private (encrypted) cookie holding the user id. Its name and
+// attributes come from `[session]` in Rocket.toml, so an instance on a
+// subdomain or behind a TLS-terminating proxy can set them to match.
+
+/// Name of the login cookie the request should carry.
+pub fn cookie_name<'a>(request: &'a Request<'_>) -> &'a str {
+    request.rocket().state::<AppConfig>().map_or(DEFAULT_COOKIE_NAME, |config| config.session.cookie_name.as_str())
+}
+
+/// Log `user_id` in.
+pub fn sign_in(cookies: &CookieJar<'_>, session: &SessionConfig, user_id: i64) {
+    let same_site = match session.same_site {
+        SameSitePolicy::Strict => SameSite::Strict,
+        SameSitePolicy::Lax => SameSite::Lax,
+        SameSitePolicy::None => SameSite::None,
+    };
+    let mut cookie = Cookie::build((session.cookie_name.clone(), user_id.to_string()))
+        .same_site(same_site)
+        .build();
+    // Left unset otherwise, so Rocket still marks it secure when serving TLS itself
+    if session.secure || session.same_site == SameSitePolicy::None {
+        cookie.set_secure(true);
+    }
+    if let Some(domain) = &session.domain {
+        cookie.set_domain(domain.clone());
+    }
+    cookies.add_private(cookie);
+}
+
+pub fn sign_out(cookies: &CookieJar<'_>, session: &SessionConfig) {
+    // Removal only matches a cookie set with the same domain
+    let mut cookie = Cookie::from(session.cookie_name.clone());
+    if let Some(domain) = &session.domain {
+        cookie.set_domain(domain.clone());
+    }
+    cookies.remove_private(cookie);
+}
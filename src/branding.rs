@@ -0,0 +1,79 @@
+use rocket::fairing::AdHoc;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_db_pools::sqlx::SqliteConnection;
+use rocket_db_pools::Database;
+use std::sync::RwLock;
+use crate::db::Db;
+use crate::dev;
+use crate::settings;
+
+// ========== Branding ==========
+//
+// The instance's name, logo and footer line, shown by both page layouts.
+// They live in `instance_settings` and are read once at startup into managed
+// state; every page template gets a copy through the `Branding` request guard,
+// and saving /admin/site updates the state in place. The guard also fills in
+// whether dev mode is on (see dev.rs), since both layouts need that too.
+
+#[derive(Debug, Clone)]
+pub struct Branding {
+    pub name: String,
+    pub logo_url: Option<String>,
+    pub footer: Option<String>,
+    /// Not a setting: true when the server runs in dev mode
+    pub dev_mode: bool,
+}
+
+impl Default for Branding {
+    fn default() -> Self {
+        Branding { name: settings::DEFAULT_INSTANCE_NAME.to_string(), logo_url: None, footer: None, dev_mode: false }
+    }
+}
+
+impl Branding {
+    pub async fn load(db: &mut SqliteConnection) -> Branding {
+        Branding {
+            name: settings::instance_name(db).await,
+            logo_url: settings::get(db, settings::LOGO_URL).await,
+            footer: settings::get(db, settings::FOOTER_TEXT).await,
+            dev_mode: false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct BrandingState(RwLock<Branding>);
+
+impl BrandingState {
+    pub fn get(&self) -> Branding {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, branding: Branding) {
+        *self.0.write().unwrap() = branding;
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Branding {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let mut branding = request.rocket().state::<BrandingState>().map(BrandingState::get).unwrap_or_default();
+        branding.dev_mode = dev::enabled(request);
+        Outcome::Success(branding)
+    }
+}
+
+/// Fairing that loads the saved branding. Attach after the migrations.
+pub fn loader() -> AdHoc {
+    AdHoc::on_ignite("Branding", |rocket| async {
+        let mut branding = Branding::default();
+        if let Some(db) = Db::fetch(&rocket) {
+            if let Ok(mut conn) = db.acquire().await {
+                branding = Branding::load(&mut conn).await;
+            }
+        }
+        rocket.manage(BrandingState(RwLock::new(branding)))
+    })
+}
@@ -0,0 +1,147 @@
+use rocket_db_pools::sqlx::{self, SqliteConnection, SqlitePool};
+use unicode_normalization::UnicodeNormalization;
+use crate::category_cache::CategoryCache;
+use crate::db;
+
+// ========== Category Names ==========
+//
+// "DP", "dp " and "ＤＰ" are the same category. Names are stored through
+// `normalize_name` and compared without regard to ASCII case: lookups use
+// COLLATE NOCASE, and so does the unique index on (course, parent, name).
+// The first spelling typed is the one shown.
+//
+// Categories made before this may still differ by width or whitespace.
+// `zhixi normalize-categories` renames them and merges the ones that turn
+// out to be the same; see `normalize_existing`.
+
+/// A category name as stored: NFKC, so fullwidth letters, digits and spaces
+/// become their plain forms, with whitespace runs collapsed to one space and
+/// none at either end.
+pub fn normalize_name(name: &str) -> String {
+    let name: String = name.nfkc().collect();
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Refile everything under category `from` to `into` and delete `from`. Its
+/// children move under `into`.
+pub async fn merge(db: &mut SqliteConnection, category_cache: &CategoryCache, from: i64, into: i64) -> sqlx::Result<()> {
+    let course_id: Option<i64> = sqlx::query_scalar("SELECT course_id FROM categories WHERE id = ?")
+        .bind(from)
+        .fetch_optional(&mut *db)
+        .await?;
+    // (statement, whether it takes `into` before `from`)
+    let statements = [
+        ("INSERT OR IGNORE INTO problem_categories (problem_id, category_id) SELECT problem_id, ? FROM problem_categories WHERE category_id = ?", true),
+        ("INSERT OR IGNORE INTO exam_categories (exam_id, category_id) SELECT exam_id, ? FROM exam_categories WHERE category_id = ?", true),
+        ("INSERT OR IGNORE INTO category_carryovers (previous_category_id, category_id) SELECT ?, category_id FROM category_carryovers WHERE previous_category_id = ?", true),
+        ("UPDATE category_carryovers SET category_id = ? WHERE category_id = ?", true),
+        ("UPDATE study_goals SET category_id = ? WHERE category_id = ?", true),
+        ("UPDATE categories SET parent_id = ? WHERE parent_id = ?", true),
+        ("DELETE FROM categories WHERE id = ?", false),
+    ];
+    for (sql, takes_into) in statements {
+        let query = if takes_into { sqlx::query(sql).bind(into) } else { sqlx::query(sql) };
+        query.bind(from).execute(&mut *db).await?;
+    }
+    if let Some(course_id) = course_id {
+        category_cache.invalidate(course_id);
+    }
+    Ok(())
+}
+
+/// What `normalize_existing` changed
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    pub renamed: usize,
+    pub merged: usize,
+}
+
+/// Normalize every category name, merging categories that end up with the
+/// same name under the same parent into the oldest of them. Parents go
+/// before their children, so children of merged parents are compared with
+/// their new siblings. All or nothing.
+pub async fn normalize_existing(db: &mut SqliteConnection, category_cache: &CategoryCache) -> sqlx::Result<Report> {
+    let mut tx = db::begin_write(db).await?;
+    let mut report = Report::default();
+    let mut changed_courses = Vec::new();
+
+    let ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM category_paths ORDER BY depth, id")
+        .fetch_all(&mut *tx)
+        .await?;
+    for id in ids {
+        // None once merged into a category handled earlier
+        let row = sqlx::query_as::<_, (i64, Option<i64>, String)>("SELECT course_id, parent_id, name FROM categories WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let Some((course_id, parent_id, name)) = row else { continue };
+        let normalized = normalize_name(&name);
+        if normalized.is_empty() {
+            continue;
+        }
+
+        let same: Option<i64> = sqlx::query_scalar(
+            "SELECT id FROM categories WHERE course_id = ? AND parent_id IS ? AND name = ? COLLATE NOCASE AND id != ? ORDER BY id LIMIT 1"
+        )
+        .bind(course_id)
+        .bind(parent_id)
+        .bind(&normalized)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let keep = match same {
+            Some(other) if other < id => {
+                merge(&mut tx, category_cache, id, other).await?;
+                report.merged += 1;
+                changed_courses.push(course_id);
+                continue;
+            }
+            Some(other) => {
+                merge(&mut tx, category_cache, other, id).await?;
+                report.merged += 1;
+                changed_courses.push(course_id);
+                id
+            }
+            None => id,
+        };
+        if normalized != name {
+            sqlx::query("UPDATE categories SET name = ? WHERE id = ?")
+                .bind(&normalized)
+                .bind(keep)
+                .execute(&mut *tx)
+                .await?;
+            report.renamed += 1;
+            changed_courses.push(course_id);
+        }
+    }
+
+    tx.commit().await?;
+    // Again, in case a course was loaded back before the commit
+    for course_id in changed_courses {
+        category_cache.invalidate(course_id);
+    }
+    Ok(report)
+}
+
+/// `zhixi normalize-categories`: run `normalize_existing` and say what it did.
+pub async fn run(pool: &SqlitePool, category_cache: &CategoryCache) {
+    let mut conn = pool.acquire().await.expect("database connection");
+    match normalize_existing(&mut conn, category_cache).await {
+        Ok(report) => println!("Renamed {} categories, merged {} duplicates.", report.renamed, report.merged),
+        Err(e) => eprintln!("Nothing changed: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name("ＤＰ"), "DP");
+        assert_eq!(normalize_name("  dynamic \t programming "), "dynamic programming");
+        assert_eq!(normalize_name("泰勒\u{3000}级数"), "泰勒 级数");
+        assert_eq!(normalize_name("极限"), "极限");
+        assert_eq!(normalize_name(" \u{3000}"), "");
+    }
+}
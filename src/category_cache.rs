@@ -0,0 +1,47 @@
+use rocket_db_pools::sqlx::SqliteConnection;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use crate::models::Category;
+use crate::queries;
+
+// ========== Category Cache ==========
+//
+// Nearly every course page and log item partial lists the course's
+// categories, so they are kept in managed state per course after the first
+// load. Every write to `categories` must `invalidate` the course: creating
+// one (`find_or_create_category`), renaming or merging (categories.rs) and
+// deleting the course or semester they belong to. The next read goes back to
+// the database. Seeding only adds categories to a course it just made, which
+// nothing can have cached yet.
+
+#[derive(Default)]
+pub struct CategoryCache {
+    courses: RwLock<HashMap<i64, Vec<Category>>>,
+    // Bumped on every invalidation so a load that raced one isn't cached
+    generation: AtomicU64,
+}
+
+impl CategoryCache {
+    pub async fn get(&self, db: &mut SqliteConnection, course_id: i64) -> Vec<Category> {
+        let cached = self.courses.read().unwrap().get(&course_id).cloned();
+        if let Some(categories) = cached {
+            return categories;
+        }
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let categories = queries::fetch_categories(db, course_id).await;
+        let mut courses = self.courses.write().unwrap();
+        if self.generation.load(Ordering::Acquire) == generation {
+            courses.insert(course_id, categories.clone());
+        }
+        categories
+    }
+
+    pub fn invalidate(&self, course_id: i64) {
+        let mut courses = self.courses.write().unwrap();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        courses.remove(&course_id);
+    }
+}
+
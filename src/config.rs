@@ -0,0 +1,205 @@
+use rocket::serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// App-level settings read from Rocket.toml / ROCKET_* env vars.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AppConfig {
+    /// Absolute base URL (e.g. "https://zhixi.example.com") used where links must
+    /// be absolute, such as feeds. Falls back to the request's Host header.
+    #[serde(default)]
+    pub public_url: Option<String>,
+    /// Cross-origin access to the JSON API. Off unless origins are listed.
+    #[serde(default)]
+    pub cors: CorsConfig,
+    /// Let more people register after the first (admin) account, each with a
+    /// single-use invite code the admin generates at /invites.
+    #[serde(default)]
+    pub invite_only: bool,
+    /// Checks every uploaded file must pass before it is stored. Off by default.
+    #[serde(default)]
+    pub upload_scan: UploadScanConfig,
+    /// How long cached LLM translations are kept.
+    #[serde(default)]
+    pub translation_cache: TranslationCacheConfig,
+    /// How long rendered public course pages are served from memory.
+    #[serde(default)]
+    pub public_cache: PublicCacheConfig,
+    /// Outgoing mail for the weekly digests. Off unless an SMTP host is set.
+    #[serde(default)]
+    pub email: EmailConfig,
+    /// Capture by forwarding to a Telegram bot. Off unless a secret is set.
+    #[serde(default)]
+    pub telegram: TelegramConfig,
+    /// Attributes of the login cookie.
+    #[serde(default)]
+    pub session: SessionConfig,
+    /// Fetches of URLs people paste in, such as link previews.
+    #[serde(default)]
+    pub outbound: OutboundConfig,
+}
+
+/// `[default.cors]` in Rocket.toml. Only applies to routes under /api/.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct CorsConfig {
+    /// Exact origins such as "https://tool.example.com", or "*" for any origin.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    /// Let browsers send the session cookie along. Ignored for "*".
+    pub allow_credentials: bool,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+            allowed_headers: vec!["Content-Type".to_string()],
+            allow_credentials: false,
+        }
+    }
+}
+
+/// `[default.upload_scan]` in Rocket.toml. Applies to screenshots, handouts
+/// and course banners alike; see scan.rs.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct UploadScanConfig {
+    /// Largest accepted file in bytes.
+    pub max_bytes: Option<u64>,
+    /// MIME types such as "image/png", or "image/*" for a whole family. Empty
+    /// accepts any type.
+    pub allowed_types: Vec<String>,
+    /// Path of a clamd socket (e.g. "/run/clamav/clamd.ctl") to scan with.
+    pub clamav_socket: Option<String>,
+}
+
+/// `[default.translation_cache]` in Rocket.toml. Pruned daily by the job
+/// runner; see translate::prune.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct TranslationCacheConfig {
+    /// Drop translations that haven't been read for this many days. 0 keeps
+    /// them regardless of age.
+    pub max_age_days: u32,
+    /// Keep at most this many, dropping the least recently used first.
+    pub max_entries: Option<u64>,
+}
+
+impl Default for TranslationCacheConfig {
+    fn default() -> Self {
+        TranslationCacheConfig { max_age_days: 180, max_entries: None }
+    }
+}
+
+/// `[default.public_cache]` in Rocket.toml; see public_cache.rs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct PublicCacheConfig {
+    /// Also the Cache-Control max-age. 0 turns caching off.
+    pub ttl_secs: u64,
+}
+
+impl Default for PublicCacheConfig {
+    fn default() -> Self {
+        PublicCacheConfig { ttl_secs: 60 }
+    }
+}
+
+/// `[default.email]` in Rocket.toml; see email.rs.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct EmailConfig {
+    /// SMTP server that relays for this host, usually the local MTA.
+    pub smtp_host: Option<String>,
+    pub smtp_port: u16,
+    /// Envelope sender and From address.
+    pub from: String,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        EmailConfig { smtp_host: None, smtp_port: 25, from: "zhixi@localhost".to_string() }
+    }
+}
+
+/// `[default.telegram]` in Rocket.toml; see telegram.rs.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct TelegramConfig {
+    /// From @BotFather. Needed to download forwarded photos and files.
+    pub bot_token: Option<String>,
+    /// The `secret_token` the webhook was registered with; Telegram sends it
+    /// back with every update.
+    pub secret_token: Option<String>,
+    /// Chats whose messages are filed, each into the courses of the user it
+    /// belongs to. Anything else is ignored.
+    pub chats: Vec<TelegramChat>,
+}
+
+/// A `[[default.telegram.chats]]` entry: messages from `chat_id` are filed
+/// as `username`, into courses they can edit.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TelegramChat {
+    pub chat_id: i64,
+    pub username: String,
+}
+
+pub const DEFAULT_COOKIE_NAME: &str = "user_id";
+
+/// `[default.session]` in Rocket.toml; see auth.rs. The defaults suit an
+/// instance served on its own host.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct SessionConfig {
+    pub cookie_name: String,
+    /// Only send the cookie over HTTPS. Rocket does this by itself when it
+    /// terminates TLS; behind a proxy that does, it can't tell and this is
+    /// needed.
+    pub secure: bool,
+    /// "lax", "strict" or "none". "none" implies `secure`, as browsers
+    /// require.
+    pub same_site: SameSitePolicy,
+    /// Parent domain such as "example.com" to share the login with its
+    /// subdomains. Unset, the cookie belongs to the exact host that set it.
+    pub domain: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum SameSitePolicy {
+    Strict,
+    Lax,
+    None,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig { cookie_name: DEFAULT_COOKIE_NAME.to_string(), secure: false, same_site: SameSitePolicy::Lax, domain: None }
+    }
+}
+
+/// `[default.outbound]` in Rocket.toml; see outbound.rs.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct OutboundConfig {
+    /// Host names or addresses to fetch from even though they aren't public,
+    /// such as a course site on the instance's own network.
+    pub allow_hosts: Vec<String>,
+}
+
+impl AppConfig {
+    pub fn public_cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.public_cache.ttl_secs)
+    }
+
+    pub fn base_url(&self, host: Option<&str>) -> String {
+        match &self.public_url {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => format!("http://{}", host.unwrap_or("localhost")),
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Method, Status};
+use rocket::{Request, Response};
+use std::io::Cursor;
+use crate::config::AppConfig;
+
+// ========== CORS ==========
+//
+// Adds Access-Control-* headers to responses under /api/ when the request's
+// Origin is in `cors.allowed_origins`. The HTML app stays same-origin: other
+// paths never get CORS headers. Preflight OPTIONS requests have no route of
+// their own, so their 404 is turned into an empty 204 here.
+
+pub struct Cors;
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info { name: "API CORS", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        if !req.uri().path().starts_with("/api/") {
+            return;
+        }
+        let Some(config) = req.rocket().state::<AppConfig>() else { return };
+        let Some(origin) = req.headers().get_one("Origin") else { return };
+
+        let cors = &config.cors;
+        let any_origin = cors.allowed_origins.iter().any(|o| o == "*");
+        if !any_origin && !cors.allowed_origins.iter().any(|o| o == origin) {
+            return;
+        }
+
+        if any_origin {
+            res.set_header(Header::new("Access-Control-Allow-Origin", "*"));
+        } else {
+            res.set_header(Header::new("Access-Control-Allow-Origin", origin.to_string()));
+            res.set_header(Header::new("Vary", "Origin"));
+            if cors.allow_credentials {
+                res.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+            }
+        }
+        // So scripts can pace themselves; see api_limits.rs
+        res.set_header(Header::new("Access-Control-Expose-Headers", "RateLimit-Limit, RateLimit-Remaining, RateLimit-Reset, RateLimit-Policy, Retry-After"));
+
+        if req.method() == Method::Options && res.status() == Status::NotFound {
+            res.set_status(Status::NoContent);
+            res.set_sized_body(0, Cursor::new(""));
+            res.remove_header("Content-Type");
+            res.set_header(Header::new("Access-Control-Allow-Methods", cors.allowed_methods.join(", ")));
+            res.set_header(Header::new("Access-Control-Allow-Headers", cors.allowed_headers.join(", ")));
+            res.set_header(Header::new("Access-Control-Max-Age", "86400"));
+        }
+    }
+}
@@ -1,5 +1,205 @@
-use rocket_db_pools::{sqlx, Database};
+use rocket::figment::Figment;
+use rocket::serde::Deserialize;
+use rocket_db_pools::sqlx::pool::PoolConnection;
+use rocket_db_pools::sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
+use rocket_db_pools::sqlx::{self, Connection as _, Sqlite, SqliteConnection, Transaction};
+use rocket_db_pools::{Config, Database, Error, Pool};
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::query_stats;
 
 #[derive(Database)]
 #[database("sqlite_logs")]
-pub struct Db(sqlx::SqlitePool);
+pub struct Db(SqlitePool);
+
+// ========== Connection Pool ==========
+//
+// rocket_db_pools' stock SQLite pool only lets Rocket.toml set the pool size
+// and timeouts. This one reads the same `[default.databases.sqlite_logs]`
+// table plus the pragmas below, which are applied to every new connection:
+//
+//   max_connections = 16     pool size (default: 4 per worker)
+//   busy_timeout_ms = 5000   how long a writer waits for the lock
+//   journal_mode = "wal"     wal | delete | truncate | persist | memory | off
+//   synchronous = "normal"   off | normal | full | extra
+//   query_stats = false      count queries per request (debug builds only,
+//                            see query_stats.rs)
+//   key = "…"                SQLCipher key; needs the `sqlcipher` feature
+//
+// Foreign keys are always enforced, so deletes cascade as the migrations
+// declare (ON DELETE CASCADE / SET NULL) instead of leaving rows behind.
+//
+// SQLite allows one writer at a time. Without a busy timeout a second writer
+// fails at once with "database is locked", which is what bursts of HTMX edits
+// ran into; with one it queues behind the first. WAL additionally lets reads
+// carry on while a write is in progress.
+//
+// The busy timeout doesn't cover a transaction that reads before it writes:
+// if another connection commits in between, its snapshot is stale and the
+// write fails with SQLITE_BUSY straight away, since waiting couldn't help.
+// Transactions that write therefore start with `begin_write`, which takes
+// the write lock up front (like `BEGIN IMMEDIATE`, which sqlx 0.7 can't
+// issue), so they queue behind each other from the start.
+//
+// Built with `--features sqlcipher`, SQLite is SQLCipher and a `key` encrypts
+// the whole file. Setting a key on a plain build is refused rather than
+// quietly writing exam material unencrypted. `zhixi rekey` (see `rekey`)
+// encrypts an existing database or changes its key.
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+struct Pragmas {
+    busy_timeout_ms: u64,
+    journal_mode: String,
+    synchronous: String,
+    query_stats: bool,
+    key: Option<String>,
+}
+
+impl Default for Pragmas {
+    fn default() -> Self {
+        Pragmas {
+            busy_timeout_ms: 5000,
+            journal_mode: "wal".to_string(),
+            synchronous: "normal".to_string(),
+            query_stats: false,
+            key: None,
+        }
+    }
+}
+
+impl Pragmas {
+    fn apply(&self, options: SqliteConnectOptions) -> Result<SqliteConnectOptions, sqlx::Error> {
+        let journal_mode = SqliteJournalMode::from_str(&self.journal_mode)?;
+        let synchronous = SqliteSynchronous::from_str(&self.synchronous)?;
+        let options = options
+            .foreign_keys(true)
+            .busy_timeout(Duration::from_millis(self.busy_timeout_ms))
+            .journal_mode(journal_mode)
+            .synchronous(synchronous);
+        match &self.key {
+            // sqlx sends `key` before every other pragma, as SQLCipher requires
+            Some(key) if cfg!(feature = "sqlcipher") => Ok(options.pragma("key", quote(key))),
+            Some(_) => Err(sqlx::Error::Configuration("a database key is set, but zhixi was built without the sqlcipher feature".into())),
+            None => Ok(options),
+        }
+    }
+}
+
+/// Begin a transaction that holds the write lock from its first statement,
+/// waiting up to the busy timeout for another writer to finish. Use it for
+/// every transaction that writes; see the note at the top of this file.
+pub async fn begin_write(conn: &mut SqliteConnection) -> sqlx::Result<Transaction<'_, Sqlite>> {
+    let mut tx = conn.begin().await?;
+    // Writes nothing, but a write statement takes the lock even so.
+    // sqlite_sequence exists as soon as any AUTOINCREMENT table does.
+    sqlx::query("UPDATE sqlite_sequence SET seq = seq WHERE 0").execute(&mut *tx).await?;
+    Ok(tx)
+}
+
+// A string as an SQL literal
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// `zhixi rekey`: copy the database into a new file encrypted with `new_key`
+/// (an empty key writes it unencrypted) and put that in place of the old one.
+/// `figment` is the database's config, for the file's path. Closes `pool`;
+/// the server must not be running.
+pub async fn rekey(figment: &Figment, pool: &sqlx::SqlitePool, new_key: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if !cfg!(feature = "sqlcipher") {
+        return Err("zhixi was built without the sqlcipher feature".into());
+    }
+    let config: Config = figment.extract()?;
+    let path = SqliteConnectOptions::from_str(&config.url)?.get_filename().to_path_buf();
+    let staging = PathBuf::from(format!("{}.rekey", path.display()));
+    let _ = std::fs::remove_file(&staging);
+
+    let mut conn = pool.acquire().await?;
+    sqlx::query(&format!("ATTACH DATABASE {} AS rekeyed KEY {}", quote(&staging.to_string_lossy()), quote(new_key)))
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("SELECT sqlcipher_export('rekeyed')").fetch_all(&mut *conn).await?;
+    sqlx::query("DETACH DATABASE rekeyed").execute(&mut *conn).await?;
+    drop(conn);
+    pool.close().await;
+
+    // Everything in the old WAL is in the copy; left next to it, SQLite
+    // would replay it into the new file
+    std::fs::rename(&staging, &path)?;
+    for suffix in ["-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+    }
+    Ok(path)
+}
+
+pub struct SqlitePool(sqlx::SqlitePool);
+
+impl Deref for SqlitePool {
+    type Target = sqlx::SqlitePool;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl Pool for SqlitePool {
+    type Connection = PoolConnection<Sqlite>;
+    type Error = Error<sqlx::Error>;
+
+    async fn init(figment: &Figment) -> Result<Self, Self::Error> {
+        let config: Config = figment.extract().map_err(Error::Config)?;
+        let pragmas: Pragmas = figment.extract().map_err(Error::Config)?;
+
+        let options = SqliteConnectOptions::from_str(&config.url)
+            .map(|o| o.create_if_missing(true))
+            .and_then(|o| pragmas.apply(o))
+            .map_err(Error::Init)?;
+
+        let mut pool_options = SqlitePoolOptions::new()
+            .max_connections(config.max_connections as u32)
+            .min_connections(config.min_connections.unwrap_or_default())
+            .acquire_timeout(Duration::from_secs(config.connect_timeout))
+            .idle_timeout(config.idle_timeout.map(Duration::from_secs));
+        if pragmas.query_stats && cfg!(debug_assertions) {
+            query_stats::enable();
+            pool_options = pool_options.after_connect(|conn, _| Box::pin(query_stats::watch(conn)));
+        }
+
+        let pool = pool_options
+            .connect_with(options)
+            .await
+            .map_err(Error::Init)?;
+
+        Ok(SqlitePool(pool))
+    }
+
+    async fn get(&self) -> Result<Self::Connection, Self::Error> {
+        self.0.acquire().await.map_err(Error::Get)
+    }
+
+    async fn close(&self) {
+        self.0.close().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("it's"), "'it''s'");
+    }
+
+    #[cfg(not(feature = "sqlcipher"))]
+    #[test]
+    fn test_key_needs_sqlcipher() {
+        let pragmas = Pragmas { key: Some("secret".to_string()), ..Pragmas::default() };
+        assert!(pragmas.apply(SqliteConnectOptions::new()).is_err());
+        assert!(Pragmas::default().apply(SqliteConnectOptions::new()).is_ok());
+    }
+}
@@ -0,0 +1,125 @@
+use rocket::fairing::AdHoc;
+#[cfg(debug_assertions)]
+use rocket::http::ContentType;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, Route};
+#[cfg(debug_assertions)]
+use rocket::State;
+#[cfg(debug_assertions)]
+use std::path::Path;
+
+// ========== Dev Mode ==========
+//
+// For working on templates and front-end code. Askama compiles templates
+// into the binary, so they can't be read from disk at run time: a template
+// change always means a rebuild. Dev mode makes that loop hands-off instead.
+// Run the server under a watcher that rebuilds and restarts it on changes,
+//
+//     ROCKET_PROFILE=dev cargo watch -w src -w templates -w migrations -x run
+//
+// and every page polls /dev/build, reloading itself once a new build answers.
+// The files in static/ are different: dev mode serves them from disk on
+// every request instead of from the binary, so a CSS or JS edit needs no
+// rebuild and just reloads the page. The front-end libraries are also loaded
+// unminified, so errors and the debugger show readable code.
+//
+// It is on only in debug builds started with the `dev` profile; release
+// builds leave its routes out, whatever the profile.
+
+pub const PROFILE: &str = "dev";
+
+/// Identifies the running build; a restart gives a new one
+pub struct DevMode {
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    build: String,
+}
+
+/// Whether pages for this request are served in dev mode
+pub fn enabled(request: &Request<'_>) -> bool {
+    request.rocket().state::<DevMode>().is_some()
+}
+
+/// Request guard with `enabled`, for routes that answer differently in dev
+/// mode. `&State<DevMode>` would keep the server from starting without it.
+pub struct Enabled(pub bool);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Enabled {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Enabled(enabled(request)))
+    }
+}
+
+/// Fairing that turns dev mode on when the profile asks for it.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("Dev mode", |rocket| async {
+        if !cfg!(debug_assertions) || rocket.figment().profile() != PROFILE {
+            return rocket;
+        }
+        let build = chrono::Utc::now().timestamp_millis().to_string();
+        println!("Dev mode: pages reload when the server restarts on a new build.");
+        rocket.manage(DevMode { build }).mount("/dev", routes())
+    })
+}
+
+#[cfg(debug_assertions)]
+fn routes() -> Vec<Route> {
+    routes![build, reload_script]
+}
+
+#[cfg(not(debug_assertions))]
+fn routes() -> Vec<Route> {
+    Vec::new()
+}
+
+#[cfg(debug_assertions)]
+const STATIC_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/static");
+
+/// `name` from static/ as it is on disk now
+#[cfg(debug_assertions)]
+pub fn read_static(name: &str) -> Option<Vec<u8>> {
+    std::fs::read(Path::new(STATIC_DIR).join(name)).ok()
+}
+
+// The build and the last change to static/, so editing a stylesheet reloads
+// pages just as a rebuild does
+#[cfg(debug_assertions)]
+fn current(dev: &DevMode) -> String {
+    let modified = std::fs::read_dir(STATIC_DIR)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok()?.metadata().ok()?.modified().ok())
+        .max()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_millis());
+    format!("{}-{}", dev.build, modified)
+}
+
+#[cfg(debug_assertions)]
+#[get("/build")]
+fn build(dev: &State<DevMode>) -> String {
+    current(dev)
+}
+
+// Included by both layouts in dev mode. Fetch errors just mean the server is
+// still restarting.
+#[cfg(debug_assertions)]
+#[get("/reload.js")]
+fn reload_script(dev: &State<DevMode>) -> (ContentType, String) {
+    let script = format!(
+        r#"(() => {{
+    const build = '{}';
+    setInterval(() => {{
+        fetch('/dev/build', {{ cache: 'no-store' }})
+            .then((res) => res.ok ? res.text() : build)
+            .then((current) => {{ if (current !== build) location.reload(); }})
+            .catch(() => {{}});
+    }}, 1000);
+}})();
+"#,
+        current(dev)
+    );
+    (ContentType::JavaScript, script)
+}
@@ -0,0 +1,148 @@
+use chrono::{Duration as ChronoDuration, Local, NaiveTime};
+use rocket_db_pools::sqlx::{self, SqlitePool};
+use std::collections::HashMap;
+use std::time::Duration;
+use crate::db;
+use crate::jobs;
+
+// ========== Problem Difficulty ==========
+//
+// An empirical difficulty for each problem from everyone's reviews of it: how
+// often it was graded again/hard, and how long answering it took next to the
+// course's other problems. The time part is relative, so scores are all
+// recomputed together by a job that runs at startup and then nightly, rather
+// than on each review. Study mode sorts and filters on the stored score.
+
+pub const JOB_KIND: &str = "recalibrate_difficulty";
+
+/// Problems with fewer reviews than this are left unrated.
+pub const MIN_REVIEWS: i64 = 2;
+
+/// Share of the score that comes from grades when answer times are known.
+const GRADE_WEIGHT: f64 = 0.7;
+
+/// Local time the nightly run is queued for.
+const RUN_AT_HOUR: u32 = 3;
+
+/// The study filter levels, with the score range each covers.
+pub const LEVELS: &[(&str, &str, i64, i64)] = &[("easy", "简单", 0, 33), ("medium", "中等", 34, 66), ("hard", "困难", 67, 100)];
+
+/// 0–100 from the average miss rate (1 for "again", 0 for "easy") and the
+/// problem's average answer time over its course's, when known. Twice the
+/// course average or slower counts as fully hard, half or faster as easy.
+pub fn score(miss_rate: f64, time_ratio: Option<f64>) -> i64 {
+    let combined = match time_ratio {
+        Some(ratio) => GRADE_WEIGHT * miss_rate + (1.0 - GRADE_WEIGHT) * ((ratio - 0.5) / 1.5).clamp(0.0, 1.0),
+        None => miss_rate,
+    };
+    (combined.clamp(0.0, 1.0) * 100.0).round() as i64
+}
+
+/// "简单", "中等" or "困难"
+pub fn label(score: i64) -> &'static str {
+    LEVELS.iter().find(|(_, _, low, high)| (*low..=*high).contains(&score)).map_or("困难", |(_, label, _, _)| *label)
+}
+
+/// The score range of a filter level
+pub fn bounds(level: &str) -> Option<(i64, i64)> {
+    LEVELS.iter().find(|(key, _, _, _)| *key == level).map(|(_, _, low, high)| (*low, *high))
+}
+
+// How long until the next RUN_AT_HOUR o'clock
+fn until_next_run() -> Duration {
+    let now = Local::now().naive_local();
+    let mut next = now.date().and_time(NaiveTime::from_hms_opt(RUN_AT_HOUR, 0, 0).unwrap());
+    if next <= now {
+        next += ChronoDuration::days(1);
+    }
+    (next - now).to_std().unwrap_or(Duration::from_secs(24 * 60 * 60))
+}
+
+/// Replace any queued recalibration with one that runs now. Called once at
+/// startup; each run queues the next for the coming night.
+pub async fn schedule(pool: &SqlitePool) {
+    let Ok(mut conn) = pool.acquire().await else { return };
+    let _ = sqlx::query("DELETE FROM jobs WHERE kind = ?")
+        .bind(JOB_KIND)
+        .execute(&mut *conn)
+        .await;
+    jobs::enqueue(&mut conn, JOB_KIND, "").await;
+}
+
+// (problem, course, reviews, miss rate, average answer time if any were timed)
+type ReviewStats = (i64, Option<i64>, i64, f64, Option<f64>);
+
+/// Job handler: rescore every problem.
+pub async fn recalibrate(pool: &SqlitePool, payload: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.acquire().await?;
+    jobs::enqueue_after(&mut conn, JOB_KIND, payload, until_next_run()).await;
+
+    let rows: Vec<ReviewStats> = sqlx::query_as(
+        r#"
+        SELECT
+            r.problem_id, COALESCE(l.course_id, e.course_id) as course_id, COUNT(*) as reviews,
+            AVG((4 - r.grade) / 3.0) as miss_rate, AVG(r.time_spent_ms) as average_ms
+        FROM study_reviews r
+        JOIN problems p ON r.problem_id = p.id
+        LEFT JOIN log_items l ON p.log_item_id = l.id
+        LEFT JOIN exams e ON p.exam_id = e.id
+        GROUP BY r.problem_id
+        "#,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    // Each timed problem counts once towards its course's average
+    let mut course_times: HashMap<Option<i64>, (f64, f64)> = HashMap::new();
+    for (_, course_id, _, _, average_ms) in &rows {
+        if let Some(ms) = average_ms {
+            let entry = course_times.entry(*course_id).or_default();
+            entry.0 += ms;
+            entry.1 += 1.0;
+        }
+    }
+
+    let mut conn = pool.acquire().await?;
+    let mut tx = db::begin_write(&mut conn).await?;
+    sqlx::query("UPDATE problems SET difficulty = NULL").execute(&mut *tx).await?;
+    for (problem_id, course_id, reviews, miss_rate, average_ms) in rows {
+        if reviews < MIN_REVIEWS {
+            continue;
+        }
+        let time_ratio = average_ms.zip(course_times.get(&course_id)).and_then(|(ms, (total, count))| {
+            let course_average = total / count;
+            (course_average > 0.0).then_some(ms / course_average)
+        });
+        sqlx::query("UPDATE problems SET difficulty = ? WHERE id = ?")
+            .bind(score(miss_rate, time_ratio))
+            .bind(problem_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score() {
+        assert_eq!(score(0.0, None), 0);
+        assert_eq!(score(1.0, None), 100);
+        assert_eq!(score(0.5, Some(1.25)), 50);
+        // Time only moves the score within its share
+        assert_eq!(score(0.0, Some(4.0)), 30);
+        assert_eq!(score(1.0, Some(0.2)), 70);
+    }
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(label(0), "简单");
+        assert_eq!(label(50), "中等");
+        assert_eq!(label(67), "困难");
+        assert_eq!(bounds("hard"), Some((67, 100)));
+        assert_eq!(bounds("impossible"), None);
+    }
+}
@@ -0,0 +1,251 @@
+use chrono::{Datelike, Local, NaiveDate, Timelike};
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx::{self, SqliteConnection, SqlitePool};
+use std::time::Duration;
+use crate::config::{AppConfig, EmailConfig};
+use crate::models::DigestSubscription;
+use crate::{access, email, jobs, settings};
+
+// ========== Weekly Digests ==========
+//
+// Users who opt in at /digest get one email a week, on the weekday and hour
+// they picked, covering every course they can open: log items dated in the past seven days,
+// problems added in that time, exams and log items coming up in the next seven
+// days (less tasks already marked done or submitted), and review cards that are due. An hourly job sends whatever digests
+// are due; one that fails to send is tried again on the next run that day.
+// Each email links to /digest/unsubscribe/<token>, which works signed out.
+
+pub const JOB_KIND: &str = "send_digests";
+
+const RUN_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct JobConfig {
+    email: EmailConfig,
+    /// For the links in the email; set `public_url` so these aren't localhost.
+    base_url: String,
+}
+
+/// (course code, title, date)
+pub type DigestItem = (String, String, String);
+
+pub struct Digest {
+    pub site_name: String,
+    pub username: String,
+    pub today: NaiveDate,
+    pub past_items: Vec<DigestItem>,
+    /// (course code, problems added)
+    pub new_problems: Vec<(String, i64)>,
+    pub upcoming: Vec<DigestItem>,
+    /// (course id, course code, cards due)
+    pub due_reviews: Vec<(i64, String, i64)>,
+}
+
+const COURSE_OF_PROBLEM: &str = r#"
+    FROM problems p
+    LEFT JOIN log_items l ON p.log_item_id = l.id
+    LEFT JOIN exams e ON p.exam_id = e.id
+    JOIN courses c ON c.id = COALESCE(l.course_id, e.course_id)
+"#;
+
+impl Digest {
+    pub async fn build(db: &mut SqliteConnection, user_id: i64, today: NaiveDate) -> Digest {
+        let week_ago = (today - chrono::Duration::days(7)).to_string();
+        let week_ahead = (today + chrono::Duration::days(7)).to_string();
+        let today_str = today.to_string();
+
+        let username: String = sqlx::query_scalar("SELECT username FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&mut *db)
+            .await
+            .unwrap_or(None)
+            .unwrap_or_default();
+
+        let past_items = sqlx::query_as::<_, DigestItem>(&format!(
+            "SELECT c.code, l.title, l.date FROM log_items l JOIN courses c ON l.course_id = c.id WHERE l.date >= ? AND l.date < ? AND c.id IN ({}) ORDER BY l.date, l.id",
+            access::COURSES
+        ))
+        .bind(&week_ago)
+        .bind(&today_str)
+        .bind(user_id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+
+        let new_problems = sqlx::query_as::<_, (String, i64)>(&format!(
+            "SELECT c.code, COUNT(*) {} WHERE p.created_at >= datetime('now', '-7 days') AND c.id IN ({}) GROUP BY c.id ORDER BY c.code",
+            COURSE_OF_PROBLEM,
+            access::COURSES
+        ))
+        .bind(user_id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+
+        let upcoming = sqlx::query_as::<_, DigestItem>(&format!(
+            r#"
+            SELECT c.code, e.title, e.exam_date AS date FROM exams e JOIN courses c ON e.course_id = c.id
+            WHERE e.exam_date >= ? AND e.exam_date <= ? AND c.id IN ({courses})
+            UNION ALL
+            SELECT c.code, l.title, l.date FROM log_items l JOIN courses c ON l.course_id = c.id
+            WHERE l.date >= ? AND l.date <= ? AND (l.status IS NULL OR l.status IN ('todo', 'in_progress')) AND c.id IN ({courses})
+            ORDER BY date
+            "#,
+            courses = access::COURSES
+        ))
+        .bind(&today_str)
+        .bind(&week_ahead)
+        .bind(user_id)
+        .bind(&today_str)
+        .bind(&week_ahead)
+        .bind(user_id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+
+        let due_reviews = sqlx::query_as::<_, (i64, String, i64)>(&format!(
+            "SELECT c.id, c.code, COUNT(*) {} JOIN study_cards s ON s.problem_id = p.id WHERE s.user_id = ? AND s.due_at <= datetime('now') AND c.id IN ({}) GROUP BY c.id ORDER BY c.code",
+            COURSE_OF_PROBLEM,
+            access::COURSES
+        ))
+        .bind(user_id)
+        .bind(user_id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+
+        let site_name = settings::instance_name(db).await;
+        Digest { site_name, username, today, past_items, new_problems, upcoming, due_reviews }
+    }
+
+    pub fn subject(&self) -> String {
+        format!("{} 每周摘要（{}）", self.site_name, self.today)
+    }
+
+    /// The plain-text body. Empty sections are left out.
+    pub fn render(&self, base_url: &str, unsubscribe_url: &str) -> String {
+        let mut out = format!("{}，你好：\n\n以下是 {} 过去一周的动态。\n", self.username, self.site_name);
+        let mut empty = true;
+
+        if !self.past_items.is_empty() {
+            out.push_str("\n本周记录\n");
+            for (code, title, date) in &self.past_items {
+                out.push_str(&format!("  · {} {}（{}）\n", code, title, date));
+            }
+            empty = false;
+        }
+        if !self.new_problems.is_empty() {
+            out.push_str("\n新增题目\n");
+            for (code, count) in &self.new_problems {
+                out.push_str(&format!("  · {}：{} 道\n", code, count));
+            }
+            empty = false;
+        }
+        if !self.upcoming.is_empty() {
+            out.push_str("\n接下来 7 天\n");
+            for (code, title, date) in &self.upcoming {
+                out.push_str(&format!("  · {} {}（{}）\n", code, title, date));
+            }
+            empty = false;
+        }
+        if !self.due_reviews.is_empty() {
+            out.push_str("\n待复习\n");
+            for (id, code, count) in &self.due_reviews {
+                out.push_str(&format!("  · {}：{} 道 {}/courses/{}/study/review\n", code, count, base_url, id));
+            }
+            empty = false;
+        }
+        if empty {
+            out.push_str("\n这一周没有新动态。\n");
+        }
+
+        out.push_str(&format!("\n——\n不想再收到这封邮件？退订：{}\n", unsubscribe_url));
+        out
+    }
+}
+
+/// Replace any queued digest job with one that runs now, if mail is set up.
+/// Called once at startup; each run queues the next.
+pub async fn schedule(pool: &SqlitePool, config: &AppConfig) {
+    let Ok(mut conn) = pool.acquire().await else { return };
+    let _ = sqlx::query("DELETE FROM jobs WHERE kind = ?")
+        .bind(JOB_KIND)
+        .execute(&mut *conn)
+        .await;
+    if !email::is_configured(&config.email) {
+        return;
+    }
+    let job = JobConfig { email: config.email.clone(), base_url: config.base_url(None) };
+    jobs::enqueue(&mut conn, JOB_KIND, &serde_json::to_string(&job).unwrap()).await;
+}
+
+/// Job handler: send every digest due this hour. Send failures are logged
+/// rather than failing the job, so one bad address doesn't hold up the rest.
+pub async fn send_due(pool: &SqlitePool, payload: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let job: JobConfig = serde_json::from_str(payload)?;
+    let mut conn = pool.acquire().await?;
+    jobs::enqueue_after(&mut conn, JOB_KIND, payload, RUN_INTERVAL).await;
+
+    let now = Local::now();
+    let due = sqlx::query_as::<_, DigestSubscription>(
+        "SELECT * FROM digest_subscriptions WHERE weekday = ? AND hour <= ? AND (last_sent_at IS NULL OR last_sent_at < datetime('now', '-1 day'))"
+    )
+    .bind(now.weekday().number_from_monday() as i64)
+    .bind(now.hour() as i64)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for subscription in due {
+        let digest = Digest::build(&mut conn, subscription.user_id, now.date_naive()).await;
+        let unsubscribe_url = unsubscribe_url(&job.base_url, &subscription.unsubscribe_token);
+        let body = digest.render(&job.base_url, &unsubscribe_url);
+        let headers = [("List-Unsubscribe", format!("<{}>", unsubscribe_url))];
+        match email::send(&job.email, &subscription.email, &digest.subject(), &body, &headers).await {
+            Ok(()) => {
+                sqlx::query("UPDATE digest_subscriptions SET last_sent_at = CURRENT_TIMESTAMP WHERE user_id = ?")
+                    .bind(subscription.user_id)
+                    .execute(&mut *conn)
+                    .await?;
+            }
+            Err(e) => eprintln!("weekly digest to {} failed: {}", subscription.email, e),
+        }
+    }
+    Ok(())
+}
+
+pub fn unsubscribe_url(base_url: &str, token: &str) -> String {
+    format!("{}/digest/unsubscribe/{}", base_url, token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest() -> Digest {
+        Digest {
+            site_name: "智习".to_string(),
+            username: "alice".to_string(),
+            today: NaiveDate::from_ymd_opt(2026, 10, 12).unwrap(),
+            past_items: vec![("MATH 1A".to_string(), "Homework 3".to_string(), "2026-10-09".to_string())],
+            new_problems: vec![("MATH 1A".to_string(), 4)],
+            upcoming: vec![],
+            due_reviews: vec![(1, "MATH 1A".to_string(), 7)],
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let text = digest().render("https://zhixi.example.com", "https://zhixi.example.com/digest/unsubscribe/t");
+        assert!(text.starts_with("alice，你好："));
+        assert!(text.contains("本周记录\n  · MATH 1A Homework 3（2026-10-09）\n"));
+        assert!(text.contains("  · MATH 1A：4 道\n"));
+        assert!(!text.contains("接下来 7 天"));
+        assert!(text.contains("  · MATH 1A：7 道 https://zhixi.example.com/courses/1/study/review\n"));
+        assert!(text.ends_with("退订：https://zhixi.example.com/digest/unsubscribe/t\n"));
+
+        let quiet = Digest { past_items: vec![], new_problems: vec![], due_reviews: vec![], ..digest() };
+        assert!(quiet.render("", "").contains("这一周没有新动态。"));
+        assert_eq!(quiet.subject(), "智习 每周摘要（2026-10-12）");
+    }
+}
@@ -0,0 +1,167 @@
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use crate::config::EmailConfig;
+
+// ========== Outgoing Email ==========
+//
+// Plain-text mail handed to an SMTP relay set under `[default.email]`:
+//
+//   smtp_host = "localhost"
+//   smtp_port = 25
+//   from = "zhixi@example.com"
+//
+// There is no TLS or authentication; the relay is expected to be the local
+// MTA (or one on a trusted network) that accepts mail from this host and
+// takes care of delivery. Bodies are sent as UTF-8 with 8BITMIME, which every
+// current MTA supports.
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn is_configured(config: &EmailConfig) -> bool {
+    config.smtp_host.is_some()
+}
+
+/// A bare address such as "someone@example.com". Anything that could smuggle
+/// extra headers or SMTP commands in is refused.
+pub fn is_valid_address(address: &str) -> bool {
+    let Some((local, domain)) = address.rsplit_once('@') else { return false };
+    !local.is_empty()
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && address.len() <= 254
+        && !address.chars().any(|c| c.is_whitespace() || c.is_control() || "<>()[]\\,;:\"".contains(c))
+}
+
+/// Send one message. `headers` are extra header lines such as List-Unsubscribe.
+pub async fn send(config: &EmailConfig, to: &str, subject: &str, body: &str, headers: &[(&str, String)]) -> io::Result<()> {
+    let Some(host) = &config.smtp_host else {
+        return Err(io::Error::other("no SMTP host configured"));
+    };
+    if !is_valid_address(to) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid recipient {:?}", to)));
+    }
+    let message = message(&config.from, to, subject, body, headers);
+    match tokio::time::timeout(SMTP_TIMEOUT, deliver(host, config.smtp_port, &config.from, to, &message)).await {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, format!("SMTP session with {} timed out", host))),
+    }
+}
+
+async fn deliver(host: &str, port: u16, from: &str, to: &str, message: &str) -> io::Result<()> {
+    let mut stream = BufReader::new(TcpStream::connect((host, port)).await?);
+    expect(&mut stream, 220).await?;
+    command(&mut stream, "EHLO zhixi", 250).await?;
+    command(&mut stream, &format!("MAIL FROM:<{}> BODY=8BITMIME", from), 250).await?;
+    command(&mut stream, &format!("RCPT TO:<{}>", to), 250).await?;
+    command(&mut stream, "DATA", 354).await?;
+    stream.get_mut().write_all(message.as_bytes()).await?;
+    command(&mut stream, ".", 250).await?;
+    command(&mut stream, "QUIT", 221).await?;
+    Ok(())
+}
+
+async fn command(stream: &mut BufReader<TcpStream>, line: &str, code: u16) -> io::Result<()> {
+    stream.get_mut().write_all(format!("{}\r\n", line).as_bytes()).await?;
+    expect(stream, code).await
+}
+
+// Read one (possibly multi-line) reply and check its code. Continuation lines
+// have a '-' after the code, the last one a space.
+async fn expect(stream: &mut BufReader<TcpStream>, code: u16) -> io::Result<()> {
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "SMTP server closed the connection"));
+        }
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+        return match line.get(..3).and_then(|c| c.parse::<u16>().ok()) {
+            Some(got) if got == code => Ok(()),
+            _ => Err(io::Error::other(format!("unexpected SMTP reply: {}", line.trim_end()))),
+        };
+    }
+}
+
+// The DATA section: headers, a blank line, then the body with CRLF line ends
+// and leading dots doubled. The terminating "." is sent separately.
+fn message(from: &str, to: &str, subject: &str, body: &str, headers: &[(&str, String)]) -> String {
+    let mut out = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n",
+        from,
+        to,
+        encode_header(subject),
+        chrono::Local::now().to_rfc2822(),
+    );
+    for (name, value) in headers {
+        out.push_str(&format!("{}: {}\r\n", name, value.replace(['\r', '\n'], " ")));
+    }
+    out.push_str("\r\n");
+    for line in body.lines() {
+        if line.starts_with('.') {
+            out.push('.');
+        }
+        out.push_str(line);
+        out.push_str("\r\n");
+    }
+    out
+}
+
+// RFC 2047 encoded-word for header text that isn't plain ASCII
+fn encode_header(text: &str) -> String {
+    let text = text.replace(['\r', '\n'], " ");
+    if text.is_ascii() {
+        text
+    } else {
+        format!("=?UTF-8?B?{}?=", base64(text.as_bytes()))
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(encode_header("Weekly"), "Weekly");
+        assert_eq!(encode_header("周报"), "=?UTF-8?B?5ZGo5oql?=");
+    }
+
+    #[test]
+    fn test_message_body() {
+        let message = message("a@example.com", "b@example.com", "Hi", "one\n.two\n", &[("List-Unsubscribe", "<https://x/u>".to_string())]);
+        assert!(message.contains("\r\nList-Unsubscribe: <https://x/u>\r\n\r\none\r\n..two\r\n"));
+    }
+
+    #[test]
+    fn test_is_valid_address() {
+        assert!(is_valid_address("alice@example.com"));
+        assert!(!is_valid_address("alice"));
+        assert!(!is_valid_address("alice@localhost"));
+        assert!(!is_valid_address("alice@example.com\r\nBcc: x@example.com"));
+        assert!(!is_valid_address("<alice@example.com>"));
+    }
+}
@@ -0,0 +1,100 @@
+use rocket::serde::Serialize;
+
+// ========== Problem Embeds ==========
+//
+// A published course's problems can be shown on other sites (a course forum,
+// a Notion page) through `/p/<slug>/problems/<id>/embed`, a small page made to
+// sit in an iframe. `/oembed` turns a link to a problem into that iframe for
+// sites that speak oEmbed (https://oembed.com).
+
+pub const DEFAULT_WIDTH: u32 = 560;
+pub const DEFAULT_HEIGHT: u32 = 420;
+
+/// The course slug and problem public id a public problem link points at:
+/// either the embed page or the problem's anchor on the problems page.
+pub fn parse_problem_url(url: &str) -> Option<(String, String)> {
+    let (_, rest) = url.split_once("/p/")?;
+    let (path, fragment) = match rest.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (rest, None),
+    };
+    let path = path.split('?').next().unwrap_or(path).trim_end_matches('/');
+    let parts: Vec<&str> = path.split('/').collect();
+
+    let (slug, id) = match (parts.as_slice(), fragment) {
+        ([slug, "problems", id, "embed"], _) => (*slug, *id),
+        ([slug, "problems"] | [slug, "zh", "problems"], Some(fragment)) => (*slug, fragment.strip_prefix("problem-")?),
+        _ => return None,
+    };
+    (!slug.is_empty() && !id.is_empty()).then(|| (slug.to_string(), id.to_string()))
+}
+
+/// The iframe's size, shrunk to fit the consumer's maxwidth and maxheight.
+pub fn frame_size(maxwidth: Option<u32>, maxheight: Option<u32>) -> (u32, u32) {
+    (
+        maxwidth.map_or(DEFAULT_WIDTH, |w| w.min(DEFAULT_WIDTH)),
+        maxheight.map_or(DEFAULT_HEIGHT, |h| h.min(DEFAULT_HEIGHT)),
+    )
+}
+
+/// An oEmbed "rich" response
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct OEmbed {
+    pub version: &'static str,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub title: String,
+    pub provider_name: String,
+    pub provider_url: String,
+    pub html: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl OEmbed {
+    pub fn rich(title: String, provider_name: String, provider_url: String, embed_url: &str, (width, height): (u32, u32)) -> Self {
+        let html = format!(
+            r#"<iframe src="{}" width="{}" height="{}" title="{}" style="border: 0" loading="lazy"></iframe>"#,
+            escape_attribute(embed_url),
+            width,
+            height,
+            escape_attribute(&title)
+        );
+        OEmbed { version: "1.0", kind: "rich", title, provider_name, provider_url, html, width, height }
+    }
+}
+
+fn escape_attribute(s: &str) -> String {
+    s.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_problem_url() {
+        let expected = Some(("math-1a".to_string(), "0199a3b2".to_string()));
+        assert_eq!(parse_problem_url("https://zhixi.example/p/math-1a/problems/0199a3b2/embed"), expected);
+        assert_eq!(parse_problem_url("https://zhixi.example/p/math-1a/problems/0199a3b2/embed/?x=1"), expected);
+        assert_eq!(parse_problem_url("https://zhixi.example/p/math-1a/problems#problem-0199a3b2"), expected);
+        assert_eq!(parse_problem_url("/p/math-1a/zh/problems#problem-0199a3b2"), expected);
+        assert_eq!(parse_problem_url("https://zhixi.example/p/math-1a/problems"), None);
+        assert_eq!(parse_problem_url("https://zhixi.example/p/math-1a/problems#category-1"), None);
+        assert_eq!(parse_problem_url("https://zhixi.example/courses/1"), None);
+    }
+
+    #[test]
+    fn test_rich() {
+        assert_eq!(frame_size(None, None), (DEFAULT_WIDTH, DEFAULT_HEIGHT));
+        assert_eq!(frame_size(Some(400), Some(1000)), (400, DEFAULT_HEIGHT));
+
+        let embed = OEmbed::rich("MATH 1A — \"极限\"".to_string(), "智习".to_string(), "https://zhixi.example".to_string(), "https://zhixi.example/p/a/problems/1/embed", (400, 300));
+        assert_eq!(
+            embed.html,
+            r#"<iframe src="https://zhixi.example/p/a/problems/1/embed" width="400" height="300" title="MATH 1A — &quot;极限&quot;" style="border: 0" loading="lazy"></iframe>"#
+        );
+        assert_eq!(embed.kind, "rich");
+    }
+}
@@ -0,0 +1,204 @@
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx::{self, SqliteConnection};
+use std::collections::BTreeSet;
+use crate::models::*;
+use crate::queries;
+use crate::uploads;
+use crate::zip::ZipWriter;
+
+// ========== Semester Export ==========
+//
+// `GET /semesters/<id>/export` archives one term as a zip:
+//
+//   manifest.json                  format version, the semester, course list
+//   courses/<course id>.json       one CourseExport per course
+//   uploads/<stored path>          every screenshot, handout and banner
+//
+// Upload URLs in the JSON ("/uploads/ab/cd….png") are kept as they are, so
+// each one names its file inside the archive once the leading slash is
+// dropped. Rows keep their database ids; references between them (problem to
+// log item, category links) use those ids and only hold within one archive.
+// Per-user data (preferences, study cards, goals) is not part of a course and
+// is left out.
+
+pub const FORMAT: &str = "zhixi-semester-export";
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: String,
+    pub semester: Semester,
+    pub courses: Vec<ManifestCourse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ManifestCourse {
+    pub id: i64,
+    pub code: String,
+    pub path: String,
+}
+
+/// Everything that belongs to one course.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CourseExport {
+    pub course: Course,
+    pub kinds: Vec<LogItemKind>,
+    pub categories: Vec<Category>,
+    pub log_items: Vec<LogItem>,
+    pub exams: Vec<Exam>,
+    pub problems: Vec<Problem>,
+    /// (problem id, category id)
+    pub problem_categories: Vec<(i64, i64)>,
+    /// (exam id, category id)
+    pub exam_categories: Vec<(i64, i64)>,
+}
+
+impl CourseExport {
+    fn upload_urls(&self) -> impl Iterator<Item = &str> {
+        self.problems
+            .iter()
+            .filter_map(|p| p.image_url.as_deref())
+            .chain(self.course.banner_url.as_deref())
+            .filter(|url| url.starts_with("/uploads/"))
+    }
+}
+
+pub async fn course_export(db: &mut SqliteConnection, course: Course) -> CourseExport {
+    let kinds = sqlx::query_as::<_, LogItemKind>("SELECT * FROM log_item_kinds WHERE course_id = ? ORDER BY position")
+        .bind(course.id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+
+    let categories = queries::fetch_categories(db, course.id).await;
+
+    let log_items = sqlx::query_as::<_, LogItem>("SELECT * FROM log_items WHERE course_id = ? ORDER BY id")
+        .bind(course.id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+
+    let exams = sqlx::query_as::<_, Exam>("SELECT * FROM exams WHERE course_id = ? ORDER BY id")
+        .bind(course.id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+
+    let problems = sqlx::query_as::<_, Problem>(
+        r#"
+        SELECT p.* FROM problems p
+        LEFT JOIN log_items l ON p.log_item_id = l.id
+        LEFT JOIN exams e ON p.exam_id = e.id
+        WHERE (l.course_id = ? OR e.course_id = ?)
+        ORDER BY p.id
+        "#
+    )
+    .bind(course.id)
+    .bind(course.id)
+    .fetch_all(&mut *db)
+    .await
+    .unwrap_or_default();
+
+    let problem_categories = sqlx::query_as::<_, (i64, i64)>(
+        "SELECT pc.problem_id, pc.category_id FROM problem_categories pc JOIN categories c ON pc.category_id = c.id WHERE c.course_id = ? ORDER BY pc.problem_id"
+    )
+    .bind(course.id)
+    .fetch_all(&mut *db)
+    .await
+    .unwrap_or_default();
+
+    let exam_categories = sqlx::query_as::<_, (i64, i64)>(
+        "SELECT ec.exam_id, ec.category_id FROM exam_categories ec JOIN exams e ON ec.exam_id = e.id WHERE e.course_id = ? ORDER BY ec.exam_id"
+    )
+    .bind(course.id)
+    .fetch_all(&mut *db)
+    .await
+    .unwrap_or_default();
+
+    CourseExport { course, kinds, categories, log_items, exams, problems, problem_categories, exam_categories }
+}
+
+/// The zip for one semester, or None if it doesn't exist. Built in memory;
+/// upload files that have gone missing from disk are skipped.
+pub async fn semester_bundle(db: &mut SqliteConnection, semester_id: i64) -> Option<Vec<u8>> {
+    let semester = sqlx::query_as::<_, Semester>("SELECT * FROM semesters WHERE id = ?")
+        .bind(semester_id)
+        .fetch_optional(&mut *db)
+        .await
+        .unwrap_or(None)?;
+
+    let courses = sqlx::query_as::<_, Course>("SELECT * FROM courses WHERE semester_id = ? ORDER BY id")
+        .bind(semester_id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+
+    let mut zip = ZipWriter::new();
+    let mut manifest_courses = Vec::new();
+    let mut upload_urls = BTreeSet::new();
+    for course in courses {
+        let export = course_export(db, course).await;
+        let path = format!("courses/{}.json", export.course.id);
+        zip.add(&path, serde_json::to_string_pretty(&export).unwrap().as_bytes());
+        upload_urls.extend(export.upload_urls().map(String::from));
+        manifest_courses.push(ManifestCourse { id: export.course.id, code: export.course.code, path });
+    }
+
+    add_uploads(db, &mut zip, &upload_urls).await;
+
+    let manifest = Manifest {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        semester,
+        courses: manifest_courses,
+    };
+    zip.add("manifest.json", serde_json::to_string_pretty(&manifest).unwrap().as_bytes());
+
+    Some(zip.finish())
+}
+
+// Each upload URL's file under uploads/; ones missing from disk are skipped
+async fn add_uploads(db: &mut SqliteConnection, zip: &mut ZipWriter, upload_urls: &BTreeSet<String>) {
+    let root = uploads::dir(db).await;
+    for url in upload_urls {
+        let Some(path) = uploads::stored_path(url) else { continue };
+        if let Ok(bytes) = tokio::fs::read(root.join(path)).await {
+            zip.add(&format!("uploads/{}", path), &bytes);
+        }
+    }
+}
+
+// ========== Static Site Export ==========
+//
+// `GET /courses/<id>/export/site` keeps a finished course readable without
+// zhixi. The zip holds
+//
+//   index.html                     the log and exams with every problem
+//   uploads/<stored path>          the screenshots and attachments it shows
+//
+// The page has its styles inline and no scripts, and links uploads by
+// relative path, so it opens straight from the unpacked folder. Text is
+// shown as typed: Markdown and math aren't rendered without the app.
+
+/// How the site's page links an upload URL: "/uploads/ab/cd.png" becomes
+/// "uploads/ab/cd.png"; anything else is left as it is.
+pub fn site_url(url: &str) -> String {
+    match uploads::stored_path(url) {
+        Some(path) => format!("uploads/{}", path),
+        None => url.to_string(),
+    }
+}
+
+/// The site zip for a rendered `index_html` and the uploads it links
+pub async fn site_bundle(db: &mut SqliteConnection, index_html: &str, upload_urls: &BTreeSet<String>) -> Vec<u8> {
+    let mut zip = ZipWriter::new();
+    zip.add("index.html", index_html.as_bytes());
+    add_uploads(db, &mut zip, upload_urls).await;
+    zip.finish()
+}
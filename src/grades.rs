@@ -0,0 +1,105 @@
+use crate::models::Course;
+
+// ========== Semester Units / GPA ==========
+
+/// Grade points on the 4.0 scale for a letter grade.
+/// Pass/fail and unrecognized grades return None and are left out of the GPA.
+pub fn grade_points(grade: &str) -> Option<f64> {
+    let points = match grade.trim().to_ascii_uppercase().as_str() {
+        "A+" | "A" => 4.0,
+        "A-" => 3.7,
+        "B+" => 3.3,
+        "B" => 3.0,
+        "B-" => 2.7,
+        "C+" => 2.3,
+        "C" => 2.0,
+        "C-" => 1.7,
+        "D+" => 1.3,
+        "D" => 1.0,
+        "D-" => 0.7,
+        "F" => 0.0,
+        _ => return None,
+    };
+    Some(points)
+}
+
+pub struct SemesterSummary {
+    pub total_units: f64,
+    pub graded_units: f64,
+    pub gpa: Option<f64>,
+}
+
+/// Total units across all courses, and a unit-weighted GPA over the courses
+/// that have both units and a letter grade.
+pub fn summarize(courses: &[Course]) -> SemesterSummary {
+    let mut total_units = 0.0;
+    let mut graded_units = 0.0;
+    let mut weighted_points = 0.0;
+
+    for course in courses {
+        let Some(units) = course.units else { continue };
+        total_units += units;
+        if let Some(points) = course.grade.as_deref().and_then(grade_points) {
+            graded_units += units;
+            weighted_points += units * points;
+        }
+    }
+
+    let gpa = if graded_units > 0.0 { Some(weighted_points / graded_units) } else { None };
+    SemesterSummary { total_units, graded_units, gpa }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn course(units: Option<f64>, grade: Option<&str>) -> Course {
+        Course {
+            id: 0,
+            semester_id: 0,
+            code: String::new(),
+            title: String::new(),
+            is_published: false,
+            public_slug: None,
+            show_lecture_links: false,
+            discourage_indexing: false,
+            public_theme: "default".to_string(),
+            banner_url: None,
+            units,
+            grade: grade.map(|g| g.to_string()),
+            color: None,
+            public_id: String::new(),
+            translation_subject: None,
+            translation_level: "undergraduate".to_string(),
+            translation_style: "academic".to_string(),
+            join_code: None,
+            join_role: "viewer".to_string(),
+            continues_course_id: None,
+            webhook_url: None,
+        }
+    }
+
+    #[test]
+    fn test_grade_points() {
+        assert_eq!(grade_points("A"), Some(4.0));
+        assert_eq!(grade_points(" b+ "), Some(3.3));
+        assert_eq!(grade_points("F"), Some(0.0));
+        assert_eq!(grade_points("P"), None);
+    }
+
+    #[test]
+    fn test_summarize() {
+        let courses = vec![
+            course(Some(4.0), Some("A")),
+            course(Some(3.0), Some("B")),
+            course(Some(2.0), Some("P")),
+            course(None, Some("A")),
+        ];
+        let summary = summarize(&courses);
+        assert_eq!(summary.total_units, 9.0);
+        assert_eq!(summary.graded_units, 7.0);
+        assert!((summary.gpa.unwrap() - 25.0 / 7.0).abs() < 1e-9);
+
+        assert!(summarize(&[course(Some(4.0), None)]).gpa.is_none());
+    }
+}
@@ -0,0 +1,3338 @@
+// ========== Route Integration Tests ==========
+//
+// Each test gets its own Rocket instance backed by a fresh SQLite file in the
+// temp dir with all migrations applied, and talks to it through Rocket's
+// local client (which keeps cookies between requests).
+
+use rocket::figment::Figment;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use rocket_db_pools::{sqlx, Database};
+use std::path::PathBuf;
+use uuid::Uuid;
+use crate::db::{self, Db};
+
+struct TestApp {
+    client: Client,
+    db_path: PathBuf,
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", self.db_path.display(), suffix));
+        }
+    }
+}
+
+impl TestApp {
+    async fn new() -> TestApp {
+        TestApp::configured(|figment| figment).await
+    }
+
+    /// A fresh app with extra configuration merged on top of the defaults.
+    async fn configured(configure: impl FnOnce(Figment) -> Figment) -> TestApp {
+        let db_path = std::env::temp_dir().join(format!("zhixi-test-{}.db", Uuid::new_v4()));
+        let figment = configure(rocket::Config::figment()
+            .merge(("databases.sqlite_logs.url", format!("sqlite:{}", db_path.display())))
+            .merge(("log_level", "off")));
+        let client = Client::tracked(crate::rocket().configure(figment))
+            .await
+            .expect("valid rocket instance");
+        TestApp { client, db_path }
+    }
+
+    /// A client already logged in as `alice`.
+    async fn authenticated() -> TestApp {
+        TestApp::new().await.signed_in().await
+    }
+
+    /// Log in as `alice`, the instance admin. The user is inserted directly with a
+    /// cheap bcrypt cost so tests don't pay for DEFAULT_COST on every run.
+    async fn signed_in(self) -> TestApp {
+        let hash = bcrypt::hash("pw", 4).unwrap();
+        sqlx::query("INSERT INTO users (username, password_hash, is_admin) VALUES ('alice', ?, 1)")
+            .bind(hash)
+            .execute(self.pool())
+            .await
+            .unwrap();
+        let location = self.post_form("/login", "username=alice&password=pw").await.headers().get_one("Location").map(String::from);
+        assert_eq!(location.as_deref(), Some("/"));
+        self
+    }
+
+    fn pool(&self) -> &sqlx::SqlitePool {
+        Db::fetch(self.client.rocket()).expect("database attached")
+    }
+
+    async fn get(&self, uri: &str) -> LocalResponse<'_> {
+        self.client.get(uri.to_string()).dispatch().await
+    }
+
+    async fn post_form(&self, uri: &str, body: &str) -> LocalResponse<'_> {
+        self.client
+            .post(uri.to_string())
+            .header(ContentType::Form)
+            .body(body)
+            .dispatch()
+            .await
+    }
+
+    async fn post_multipart(&self, uri: &str, fields: &[(&str, &str)], file: (&str, &[u8])) -> LocalResponse<'_> {
+        self.post_file(uri, fields, file.0, ("shot.png", "image/png"), file.1).await
+    }
+
+    /// Multipart POST with one file part named `field`, sent as `(filename, content type)`.
+    async fn post_file(&self, uri: &str, fields: &[(&str, &str)], field: &str, file: (&str, &str), bytes: &[u8]) -> LocalResponse<'_> {
+        let boundary = "zhixi-test-boundary";
+        let mut body = Vec::new();
+        for (name, value) in fields {
+            body.extend_from_slice(format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n", boundary, name, value).as_bytes());
+        }
+        body.extend_from_slice(format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+            boundary, field, file.0, file.1
+        ).as_bytes());
+        body.extend_from_slice(bytes);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+        self.client
+            .post(uri.to_string())
+            .header(ContentType::new("multipart", "form-data").with_params(("boundary", boundary)))
+            .body(body)
+            .dispatch()
+            .await
+    }
+
+    async fn delete(&self, uri: &str) -> LocalResponse<'_> {
+        self.client.delete(uri.to_string()).dispatch().await
+    }
+
+    async fn scalar(&self, sql: &str) -> i64 {
+        sqlx::query_scalar(sql).fetch_one(self.pool()).await.unwrap()
+    }
+
+    /// The public id of row `id` in courses, log_items or problems.
+    async fn public_id(&self, table: &str, id: i64) -> String {
+        sqlx::query_scalar(&format!("SELECT public_id FROM {} WHERE id = ?", table))
+            .bind(id)
+            .fetch_one(self.pool())
+            .await
+            .unwrap()
+    }
+}
+
+#[rocket::async_test]
+async fn test_session_cookie_config() {
+    let app = TestApp::configured(|figment| {
+        figment
+            .merge(("session.cookie_name", "zhixi_session"))
+            .merge(("session.same_site", "strict"))
+            .merge(("session.domain", "example.com"))
+    })
+    .await;
+    let response = app.post_form("/register", "username=alice&password=pw").await;
+    let cookie = response.headers().get_one("Set-Cookie").unwrap().to_string();
+    assert!(cookie.starts_with("zhixi_session="));
+    assert!(cookie.contains("SameSite=Strict") && cookie.contains("Domain=example.com") && !cookie.contains("Secure"));
+    assert_eq!(app.get("/dashboard").await.status(), Status::Ok);
+    // Logging out removes the cookie for the same domain
+    app.post_form("/logout", "").await;
+    assert_eq!(app.get("/dashboard").await.status(), Status::Unauthorized);
+
+    let app = TestApp::configured(|figment| figment.merge(("session.same_site", "none"))).await;
+    let response = app.post_form("/register", "username=alice&password=pw").await;
+    let cookie = response.headers().get_one("Set-Cookie").unwrap().to_string();
+    assert!(cookie.starts_with("user_id=") && cookie.contains("SameSite=None") && cookie.contains("Secure"));
+}
+
+#[rocket::async_test]
+async fn test_register_login_logout() {
+    let app = TestApp::new().await;
+
+    assert_eq!(app.get("/dashboard").await.status(), Status::Unauthorized);
+    assert_eq!(app.get("/").await.headers().get_one("Location"), Some("/setup"));
+
+    // First user can register and is logged in straight away
+    let response = app.post_form("/register", "username=alice&password=pw").await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(app.get("/dashboard").await.status(), Status::Ok);
+
+    app.post_form("/logout", "").await;
+    assert_eq!(app.get("/dashboard").await.status(), Status::Unauthorized);
+
+    // Registration closes once a user exists: the form is re-rendered instead of redirecting
+    assert_eq!(app.post_form("/register", "username=bob&password=pw").await.status(), Status::Ok);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM users").await, 1);
+
+    assert_eq!(app.post_form("/login", "username=alice&password=wrong").await.status(), Status::Ok);
+    assert_eq!(app.get("/dashboard").await.status(), Status::Unauthorized);
+
+    app.post_form("/login", "username=alice&password=pw").await;
+    assert_eq!(app.get("/dashboard").await.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn test_account_email_login() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/account/email", "email=not-an-address").await;
+    assert!(app.get("/account").await.into_string().await.unwrap().contains("请输入有效的邮箱地址。"));
+
+    // Saved without a mail server, but unverified until the link is opened
+    app.post_form("/account/email", "email=Alice%40Example.com").await;
+    assert!(app.get("/account").await.into_string().await.unwrap().contains("暂时无法验证"));
+    app.post_form("/logout", "").await;
+    assert_eq!(app.post_form("/login", "username=alice%40example.com&password=pw").await.status(), Status::Ok);
+
+    let token: String = sqlx::query_scalar("SELECT email_token FROM users WHERE username = 'alice'").fetch_one(app.pool()).await.unwrap();
+    assert!(app.get(&format!("/account/verify/{}", token)).await.into_string().await.unwrap().contains("确认验证"));
+    assert!(app.post_form(&format!("/account/verify/{}", token), "").await.into_string().await.unwrap().contains("邮箱已验证"));
+    assert!(app.post_form(&format!("/account/verify/{}", token), "").await.into_string().await.unwrap().contains("无法验证"));
+
+    let response = app.post_form("/login", "username=alice%40example.com&password=pw").await;
+    assert_eq!(response.headers().get_one("Location"), Some("/"));
+    assert!(app.get("/account").await.into_string().await.unwrap().contains("已验证"));
+
+    // A verified address belongs to one account
+    let hash = bcrypt::hash("pw", 4).unwrap();
+    sqlx::query("INSERT INTO users (username, password_hash, is_admin) VALUES ('bob', ?, 0)").bind(hash).execute(app.pool()).await.unwrap();
+    app.post_form("/logout", "").await;
+    app.post_form("/login", "username=bob&password=pw").await;
+    app.post_form("/account/email", "email=alice%40example.com").await;
+    assert!(app.get("/account").await.into_string().await.unwrap().contains("这个邮箱已被其他账号使用。"));
+
+    // Clearing the address stops it working for login
+    app.post_form("/logout", "").await;
+    app.post_form("/login", "username=alice&password=pw").await;
+    app.post_form("/account/email", "email=").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM users WHERE email IS NOT NULL").await, 0);
+}
+
+#[rocket::async_test]
+async fn test_invite_only_registration() {
+    let app = TestApp::configured(|figment| figment.merge(("invite_only", true))).await.signed_in().await;
+    assert!(app.get("/dashboard").await.into_string().await.unwrap().contains("/invites"));
+
+    assert_eq!(app.post_form("/invites", "").await.status(), Status::Ok);
+    app.post_form("/invites", "").await;
+    let codes: Vec<String> = sqlx::query_scalar("SELECT code FROM invites ORDER BY rowid").fetch_all(app.pool()).await.unwrap();
+    assert_eq!(codes.len(), 2);
+    app.delete(&format!("/invites/{}", codes[1])).await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM invites").await, 1);
+    app.post_form("/logout", "").await;
+
+    let page = app.get(&format!("/register?code={}", codes[0])).await.into_string().await.unwrap();
+    assert!(page.contains("invite_code") && page.contains(&codes[0]));
+
+    let page = app.post_form("/register", "username=bob&password=pw&invite_code=nope").await.into_string().await.unwrap();
+    assert!(page.contains("Invalid or already used invite code"));
+    let response = app.post_form("/register", &format!("username=bob&password=pw&invite_code={}", codes[0])).await;
+    assert_eq!(response.headers().get_one("Location"), Some("/"));
+    assert_eq!(app.scalar("SELECT used_by FROM invites").await, 2);
+    assert_eq!(app.scalar("SELECT is_admin FROM users WHERE username = 'bob'").await, 0);
+
+    // Not an admin, and the code is spent
+    assert_eq!(app.get("/invites").await.status(), Status::Forbidden);
+    app.post_form("/logout", "").await;
+    let page = app.post_form("/register", &format!("username=carol&password=pw&invite_code={}", codes[0])).await.into_string().await.unwrap();
+    assert!(page.contains("Invalid or already used invite code"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM users").await, 2);
+}
+
+#[rocket::async_test]
+async fn test_registration_closed_by_default() {
+    let app = TestApp::new().await;
+    assert_eq!(app.get("/register").await.headers().get_one("Location"), Some("/setup"));
+    let response = app.post_form("/register", "username=alice&password=pw").await;
+    assert_eq!(response.headers().get_one("Location"), Some("/"));
+    assert_eq!(app.scalar("SELECT is_admin FROM users").await, 1);
+
+    app.post_form("/logout", "").await;
+    assert_eq!(app.get("/register").await.headers().get_one("Location"), Some("/login"));
+    let page = app.post_form("/register", "username=bob&password=pw").await.into_string().await.unwrap();
+    assert!(page.contains("Registration is closed."));
+}
+
+#[rocket::async_test]
+async fn test_first_run_setup() {
+    let app = TestApp::new().await;
+    for page in ["/", "/login", "/register"] {
+        assert_eq!(app.get(page).await.headers().get_one("Location"), Some("/setup"), "{}", page);
+    }
+    assert_eq!(app.get("/setup").await.status(), Status::Ok);
+
+    let page = app.post_form("/setup", "username=+&password=pw").await.into_string().await.unwrap();
+    assert!(page.contains("Username and password are required"));
+
+    let response = app.post_form("/setup", "username=alice&password=pw&instance_name=%E6%95%B0%E5%AD%A6&upload_dir=uploads&translation_api_key=sk-test").await;
+    assert_eq!(response.headers().get_one("Location"), Some("/"));
+    assert_eq!(app.get("/dashboard").await.status(), Status::Ok);
+    assert_eq!(app.scalar("SELECT is_admin FROM users WHERE username = 'alice'").await, 1);
+    let settings: Vec<(String, String)> = sqlx::query_as("SELECT key, value FROM instance_settings ORDER BY key")
+        .fetch_all(app.pool())
+        .await
+        .unwrap();
+    assert_eq!(settings, vec![
+        ("instance_name".to_string(), "数学".to_string()),
+        ("translation_api_key".to_string(), "sk-test".to_string()),
+        ("upload_dir".to_string(), "uploads".to_string()),
+    ]);
+
+    // Once there is an account the wizard is gone for good
+    app.post_form("/logout", "").await;
+    assert_eq!(app.get("/setup").await.headers().get_one("Location"), Some("/login"));
+    app.post_form("/setup", "username=mallory&password=pw").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM users").await, 1);
+    assert!(app.get("/login").await.into_string().await.unwrap().contains("数学"));
+}
+
+#[rocket::async_test]
+async fn test_site_branding() {
+    let app = TestApp::authenticated().await;
+    assert!(app.get("/dashboard").await.into_string().await.unwrap().contains("<title>智习</title>"));
+
+    let png: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    let response = app.post_file("/admin/site", &[("instance_name", "数学系自习室"), ("footer_text", "数学系 2026")], "logo", ("logo.png", "image/png"), png).await;
+    assert_eq!(response.headers().get_one("Location"), Some("/admin/site"));
+    let page = app.get("/dashboard").await.into_string().await.unwrap();
+    assert!(page.contains("<title>数学系自习室</title>") && page.contains("数学系 2026"));
+    let logo = sqlx::query_scalar::<_, String>("SELECT value FROM instance_settings WHERE key = 'logo_url'").fetch_one(app.pool()).await.unwrap();
+    assert!(page.contains(&logo));
+
+    // The logo is public even though no course uses it
+    app.post_form("/logout", "").await;
+    assert_eq!(app.get(&logo).await.status(), Status::Ok);
+    assert!(app.get("/login").await.into_string().await.unwrap().contains("数学系自习室"));
+    app.post_form("/login", "username=alice&password=pw").await;
+
+    // Clearing the fields goes back to the defaults and frees the logo
+    app.post_file("/admin/site", &[("instance_name", ""), ("footer_text", ""), ("remove_logo", "on")], "logo", ("", "application/octet-stream"), b"").await;
+    let page = app.get("/dashboard").await.into_string().await.unwrap();
+    assert!(page.contains("<title>智习</title>") && !page.contains("数学系 2026"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM uploads").await, 0);
+
+    // Admins only
+    sqlx::query("UPDATE users SET is_admin = 0").execute(app.pool()).await.unwrap();
+    assert_eq!(app.get("/admin/site").await.status(), Status::Forbidden);
+    assert_eq!(app.post_form("/admin/site", "instance_name=x").await.status(), Status::Forbidden);
+}
+
+#[rocket::async_test]
+async fn test_integrity_check_and_repair() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/semesters/1/courses", "code=D&title=Gone").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1&description=极限").await;
+    app.post_form("/courses/2/logs", "kind=Homework&title=Orphan").await;
+    app.post_multipart("/logs/1/problems", &[("categories", "limits")], ("screenshot", b"integrity kept")).await;
+    app.post_multipart("/logs/2/problems", &[], ("screenshot", b"integrity orphan")).await;
+    assert!(app.get("/admin/integrity").await.into_string().await.unwrap().contains("没有发现问题"));
+
+    // Drift the app itself wouldn't produce: the course goes without its log
+    // items, a tag points nowhere, a screenshot disappears from disk
+    let mut conn = app.pool().acquire().await.unwrap();
+    for sql in [
+        "PRAGMA foreign_keys = OFF",
+        "DELETE FROM courses WHERE id = 2",
+        "INSERT INTO problem_categories (problem_id, category_id) VALUES (1, 99)",
+        "INSERT INTO problems (log_item_id, description, is_incorrect, image_url, created_at) VALUES (1, 'p', 1, '/uploads/00/missing.png', CURRENT_TIMESTAMP)",
+        "INSERT INTO translations (source_text, translated_text) VALUES ('极限', 'Limits'), ('旧文本', 'Old text')",
+        "PRAGMA foreign_keys = ON",
+    ] {
+        sqlx::query(sql).execute(&mut *conn).await.unwrap();
+    }
+    drop(conn);
+
+    let page = app.get("/admin/integrity").await.into_string().await.unwrap();
+    assert!(page.contains("所属课程已删除的记录：1 处") && page.contains("记录 #2 Orphan（课程 #2）"));
+    assert!(page.contains("指向已删除错题或分类的分类标记：1 处"));
+    assert!(page.contains("截图文件缺失的错题：1 处") && page.contains("/uploads/00/missing.png"));
+    assert!(page.contains("不再被使用的翻译缓存：1 处") && page.contains("「旧文本」"));
+
+    let response = app.post_form("/admin/integrity", "").await;
+    assert_eq!(response.headers().get_one("Location"), Some("/admin/integrity"));
+    let page = app.get("/admin/integrity").await.into_string().await.unwrap();
+    assert!(page.contains("已修复 4 处。") && page.contains("没有发现问题"));
+
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM log_items").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 2);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems WHERE image_url IS NULL").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problem_categories").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM translations").await, 1);
+    // The orphaned problem's screenshot was released with it
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM uploads").await, 1);
+
+    let image_url: String = sqlx::query_scalar("SELECT image_url FROM problems WHERE image_url IS NOT NULL").fetch_one(app.pool()).await.unwrap();
+    let _ = std::fs::remove_file(image_url.trim_start_matches('/'));
+
+    sqlx::query("UPDATE users SET is_admin = 0").execute(app.pool()).await.unwrap();
+    assert_eq!(app.get("/admin/integrity").await.status(), Status::Forbidden);
+    assert_eq!(app.post_form("/admin/integrity", "").await.status(), Status::Forbidden);
+}
+
+#[rocket::async_test]
+async fn test_admin_stats() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    let mut conn = app.pool().acquire().await.unwrap();
+    crate::stats::record_llm_call(&mut conn, "google/gemini-2.5-flash", &serde_json::json!({"prompt_tokens": 900, "completion_tokens": 100, "cost": 0.25})).await;
+    crate::stats::record_llm_call(&mut conn, "google/gemini-2.5-flash", &serde_json::json!({})).await;
+    drop(conn);
+
+    let page = app.get("/admin/stats").await.into_string().await.unwrap();
+    assert!(page.contains("alice <span class=\"text-xs text-industrial-500\">管理员</span>"));
+    assert!(page.contains("共 2 次调用，1000 tokens，$0.25"));
+    // Today's column of the cost chart
+    assert!(page.contains("$0.25\">"));
+
+    sqlx::query("UPDATE users SET is_admin = 0").execute(app.pool()).await.unwrap();
+    assert_eq!(app.get("/admin/stats").await.status(), Status::Forbidden);
+}
+
+#[rocket::async_test]
+async fn test_translation_cache_transfer() {
+    let source = TestApp::authenticated().await;
+    sqlx::query("INSERT INTO translations (source_text, translated_text, last_used_at) VALUES ('极限', 'Limits', CURRENT_TIMESTAMP), ('泰勒级数', 'Taylor series', CURRENT_TIMESTAMP)")
+        .execute(source.pool())
+        .await
+        .unwrap();
+    let response = source.get("/admin/translations/export").await;
+    assert!(response.headers().get_one("Content-Disposition").unwrap().starts_with("attachment"));
+    let export = response.into_bytes().await.unwrap();
+
+    let app = TestApp::authenticated().await;
+    sqlx::query("INSERT INTO translations (source_text, translated_text, last_used_at) VALUES ('极限', 'Limit', CURRENT_TIMESTAMP)")
+        .execute(app.pool())
+        .await
+        .unwrap();
+    let response = app.post_file("/admin/translations/import", &[], "file", ("translations.json", "application/json"), &export).await;
+    assert_eq!(response.headers().get_one("Location"), Some("/admin/site"));
+    let body = app.get("/admin/site").await.into_string().await.unwrap();
+    assert!(body.contains("新增 1 条，覆盖 0 条，保留原有 1 条"), "{}", body);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM translations WHERE source_text = '极限' AND translated_text = 'Limit'").await, 1);
+
+    // A glossary, replacing what is already cached
+    let glossary = r#"{"极限": "Limits", "导数": "Derivatives", "空": ""}"#;
+    app.post_file("/admin/translations/import", &[("replace", "on")], "file", ("glossary.json", "application/json"), glossary.as_bytes()).await;
+    let body = app.get("/admin/site").await.into_string().await.unwrap();
+    assert!(body.contains("新增 1 条，覆盖 1 条，保留原有 0 条。另有 1 条为空，已跳过。"), "{}", body);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM translations").await, 3);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM translations WHERE translated_text = 'Limits'").await, 1);
+
+    app.post_file("/admin/translations/import", &[], "file", ("notes.json", "application/json"), b"[1, 2, 3]").await;
+    assert!(app.get("/admin/site").await.into_string().await.unwrap().contains("文件不是翻译缓存导出或词汇表"));
+}
+
+#[rocket::async_test]
+async fn test_connection_pragmas() {
+    let app = TestApp::new().await;
+    let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode").fetch_one(app.pool()).await.unwrap();
+    assert_eq!(journal_mode, "wal");
+    assert_eq!(app.scalar("PRAGMA busy_timeout").await, 5000);
+    assert_eq!(app.scalar("PRAGMA synchronous").await, 1);
+    assert_eq!(app.scalar("PRAGMA foreign_keys").await, 1);
+
+    let app = TestApp::configured(|figment| figment
+        .merge(("databases.sqlite_logs.busy_timeout_ms", 250))
+        .merge(("databases.sqlite_logs.synchronous", "full")))
+        .await;
+    assert_eq!(app.scalar("PRAGMA busy_timeout").await, 250);
+    assert_eq!(app.scalar("PRAGMA synchronous").await, 2);
+}
+
+// Transactions that read before they write, all at once: each waits its
+// turn for the write lock instead of failing with SQLITE_BUSY
+#[rocket::async_test]
+async fn test_concurrent_write_transactions() {
+    let app = TestApp::new().await;
+    let writes: Vec<_> = (0..16).map(|i| {
+        let pool = app.pool().clone();
+        tokio::spawn(async move {
+            let mut conn = pool.acquire().await?;
+            let mut tx = db::begin_write(&mut conn).await?;
+            let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM semesters").fetch_one(&mut *tx).await?;
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            sqlx::query("INSERT INTO semesters (name) VALUES (?)")
+                .bind(format!("{} after {}", i, count))
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await
+        })
+    }).collect();
+    for write in writes {
+        let result = write.await.unwrap();
+        assert!(result.is_ok(), "{:?}", result);
+    }
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM semesters").await, 16);
+    // Each saw every semester committed before it
+    assert_eq!(app.scalar("SELECT COUNT(DISTINCT substr(name, instr(name, 'after'))) FROM semesters").await, 16);
+}
+
+#[rocket::async_test]
+async fn test_course_units_validation() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+
+    // Adding a course and editing its grade refuse the same values, with the same message
+    for units in ["-1", "400", "NaN"] {
+        let res = app.post_form("/semesters/1/courses", &format!("code=C&title=T&units={}", units)).await;
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+        assert_eq!(res.headers().get_one("HX-Retarget"), Some("#form-error"));
+        assert_eq!(res.into_string().await.unwrap(), "学分需要在 0 到 30 之间。");
+    }
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM courses").await, 0);
+
+    assert_eq!(app.post_form("/semesters/1/courses", "code=C&title=T&units=4").await.status(), Status::Ok);
+    let res = app.post_form("/courses/1/grade", "units=-2&grade=A").await;
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+    assert_eq!(res.into_string().await.unwrap(), "学分需要在 0 到 30 之间。");
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM courses WHERE units = 4").await, 1);
+
+    assert_eq!(app.post_form("/courses/1/grade", "units=&grade=A").await.status(), Status::SeeOther);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM courses WHERE units IS NULL AND grade = 'A'").await, 1);
+}
+
+#[rocket::async_test]
+async fn test_semester_course_log_crud() {
+    let app = TestApp::authenticated().await;
+
+    let response = app.post_form("/semesters", "name=2026+Fall").await;
+    assert_eq!(response.status(), Status::Ok);
+    assert!(app.get("/dashboard").await.into_string().await.unwrap().contains("2026 Fall"));
+
+    app.post_form("/semesters/1/courses", "code=MATH1A&title=Calculus").await;
+    let semester_page = app.get("/semesters/1").await.into_string().await.unwrap();
+    assert!(semester_page.contains("MATH1A"));
+
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1&date=2026-09-01").await;
+    let course_page = app.get("/courses/1").await.into_string().await.unwrap();
+    assert!(course_page.contains("HW1"));
+
+    let response = app.post_form("/logs/1", "kind=Quiz&title=Quiz+1&date=2026-09-02").await;
+    let card = response.into_string().await.unwrap();
+    assert!(card.contains("Quiz 1"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM log_items WHERE kind = 'Quiz'").await, 1);
+
+    assert_eq!(app.delete("/logs/1").await.status(), Status::Ok);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM log_items").await, 0);
+}
+
+#[rocket::async_test]
+async fn test_course_log_timeline_view() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1&date=2026-09-01").await;
+    sqlx::query("INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP), (1, 'q', 1, CURRENT_TIMESTAMP)")
+        .execute(app.pool())
+        .await
+        .unwrap();
+
+    assert!(!app.get("/courses/1").await.into_string().await.unwrap().contains("log-timeline"));
+    let page = app.get("/courses/1?view=timeline").await.into_string().await.unwrap();
+    assert!(page.contains("log-timeline") && page.contains("2026 年 9 月") && page.contains("2 道错题"));
+
+    // The choice is remembered until switched back; unknown views are ignored
+    assert!(app.get("/courses/1").await.into_string().await.unwrap().contains("log-timeline"));
+    assert!(app.get("/courses/1?view=bogus").await.into_string().await.unwrap().contains("log-timeline"));
+    app.get("/courses/1?view=list").await;
+    assert!(!app.get("/courses/1").await.into_string().await.unwrap().contains("log-timeline"));
+}
+
+#[rocket::async_test]
+async fn test_dev_mode() {
+    // Off unless the profile asks for it
+    let app = TestApp::authenticated().await;
+    assert_eq!(app.get("/dev/build").await.status(), Status::NotFound);
+    assert!(!app.get("/dashboard").await.into_string().await.unwrap().contains("/dev/reload.js"));
+
+    let app = TestApp::configured(|figment| figment.select(crate::dev::PROFILE)).await.signed_in().await;
+    let build = app.get("/dev/build").await.into_string().await.unwrap();
+    assert!(app.get("/dev/reload.js").await.into_string().await.unwrap().contains(&format!("const build = '{}';", build)));
+    let page = app.get("/dashboard").await.into_string().await.unwrap();
+    assert!(page.contains("/dev/reload.js") && page.contains("htmx.org@1.9.10/dist/htmx.js"));
+
+    // Static files come from disk, uncached
+    let response = app.get(&crate::assets::url("app.css")).await;
+    assert_eq!(response.headers().get_one("Cache-Control"), Some("no-store"));
+    assert_eq!(response.into_bytes().await.unwrap(), std::fs::read("static/app.css").unwrap());
+}
+
+#[rocket::async_test]
+async fn test_static_assets() {
+    let app = TestApp::new().await;
+    let url = crate::assets::url("app.css");
+    assert!(url.starts_with("/static/app.") && url.ends_with(".css") && url != "/static/app.css");
+
+    let response = app.get(&url).await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::CSS));
+    assert_eq!(response.headers().get_one("Cache-Control"), Some("public, max-age=31536000, immutable"));
+    assert!(response.into_string().await.unwrap().contains(".empty-state"));
+    assert_eq!(app.get("/static/app.css").await.status(), Status::NotFound);
+
+    let app = app.signed_in().await;
+    assert!(app.get("/dashboard").await.into_string().await.unwrap().contains(&url));
+}
+
+#[rocket::async_test]
+async fn test_print_log_week() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    assert_eq!(app.get("/courses/1/logs/week/1/print").await.status(), Status::NotFound);
+
+    // 2020-09-01 is a Tuesday, so week 2 starts on Monday 09-07
+    app.post_form("/courses/1/logs", "kind=Lecture&title=L1&date=2020-09-01").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1&date=2020-09-08").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW2&date=2020-09-20").await;
+    app.post_form("/courses/1/logs", "kind=Other&title=Undated").await;
+    sqlx::query("INSERT INTO problems (log_item_id, description, notes, is_incorrect, created_at) VALUES (2, 'p', 'sign error', 1, CURRENT_TIMESTAMP)")
+        .execute(app.pool())
+        .await
+        .unwrap();
+
+    let page = app.get("/courses/1/logs/week/2/print").await.into_string().await.unwrap();
+    assert!(page.contains("HW1") && page.contains("P-001") && page.contains("sign error") && page.contains("9月7日 – 9月13日"));
+    assert!(!page.contains("L1") && !page.contains("Undated"));
+    assert!(app.get("/courses/1/logs/week/3/print").await.into_string().await.unwrap().contains("HW2"));
+    assert_eq!(app.get("/courses/1/logs/week/4/print").await.status(), Status::NotFound);
+    assert_eq!(app.get("/courses/1/logs/week/0/print").await.status(), Status::NotFound);
+
+    // The course is over, so the course log links its last week
+    assert!(app.get("/courses/1").await.into_string().await.unwrap().contains("/courses/1/logs/week/3/print"));
+}
+
+#[rocket::async_test]
+async fn test_problem_crud() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+
+    let response = app
+        .post_multipart("/logs/1/problems", &[("notes", "sign error"), ("categories", "limits, derivatives")], ("screenshot", b"not really a png"))
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.into_string().await.unwrap().contains("sign error"));
+
+    let image_url: String = sqlx::query_scalar("SELECT image_url FROM problems WHERE id = 1")
+        .fetch_one(app.pool())
+        .await
+        .unwrap();
+    let image_path = image_url.trim_start_matches('/').to_string();
+    assert!(std::path::Path::new(&image_path).exists());
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problem_categories WHERE problem_id = 1").await, 2);
+
+    let listing = app.get("/logs/1/problems").await.into_string().await.unwrap();
+    assert!(listing.contains("derivatives"));
+
+    let response = app.post_form("/problems/1", "notes=fixed&solution_link=&categories=limits").await;
+    assert!(response.into_string().await.unwrap().contains("fixed"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problem_categories WHERE problem_id = 1").await, 1);
+
+    let page = app.get("/problems/1/view").await.into_string().await.unwrap();
+    assert!(page.contains(&image_url) && page.contains("fixed") && page.contains("HW1"));
+    assert_eq!(app.get("/problems/99/view").await.status(), Status::NotFound);
+
+    // The row's delete button swaps in the empty response; the tags and the
+    // screenshot go with the problem
+    let response = app.delete("/problems/1").await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().await.unwrap_or_default(), "");
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 0);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problem_categories").await, 0);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM uploads").await, 0);
+    assert!(!std::path::Path::new(&image_path).exists());
+}
+
+#[rocket::async_test]
+async fn test_semester_rename_and_delete() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=FA25").await;
+    assert!(app.get("/semesters/1/edit").await.into_string().await.unwrap().contains("value=\"FA25\""));
+    assert_eq!(app.post_form("/semesters/1", "name=+").await.status(), Status::BadRequest);
+    let row = app.post_form("/semesters/1", "name=+2025+Fall+").await.into_string().await.unwrap();
+    assert!(row.contains(">2025 Fall</span>") && row.contains("semester-row"));
+
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_form("/courses/1/exams", "title=Midterm&exam_date=2026-10-01").await;
+    app.post_form("/courses/1/discussions", "title=Q&body=x").await;
+    let bytes = format!("screenshot {}", Uuid::new_v4());
+    app.post_multipart("/logs/1/problems", &[("notes", "n"), ("categories", "极限")], ("screenshot", bytes.as_bytes())).await;
+    let url: String = sqlx::query_scalar("SELECT image_url FROM problems").fetch_one(app.pool()).await.unwrap();
+    let path = url.trim_start_matches('/').to_string();
+    assert!(std::path::Path::new(&path).exists());
+
+    assert_eq!(app.delete("/semesters/1").await.status(), Status::Ok);
+    for table in ["semesters", "courses", "log_items", "exams", "problems", "problem_categories", "categories", "discussion_threads", "uploads"] {
+        assert_eq!(app.scalar(&format!("SELECT COUNT(*) FROM {}", table)).await, 0, "{}", table);
+    }
+    assert!(!std::path::Path::new(&path).exists());
+    assert!(!app.get("/dashboard").await.into_string().await.unwrap().contains("2025 Fall"));
+}
+
+#[rocket::async_test]
+async fn test_course_edit_and_delete() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/semesters/1/courses", "code=KEEP&title=Kept").await;
+    assert!(app.get("/courses/1/edit").await.into_string().await.unwrap().contains("value=\"C\""));
+    assert_eq!(app.post_form("/courses/1/edit", "code=+&title=T").await.status(), Status::BadRequest);
+    let card = app.post_form("/courses/1/edit", "code=MATH+1B&title=+Calculus+").await.into_string().await.unwrap();
+    assert!(card.contains(">MATH 1B</span>") && card.contains(">Calculus</span>") && card.contains("course-card"));
+
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_form("/courses/2/logs", "kind=Homework&title=HW1").await;
+    let bytes = format!("screenshot {}", Uuid::new_v4());
+    app.post_multipart("/logs/1/problems", &[("categories", "极限")], ("screenshot", bytes.as_bytes())).await;
+    let url: String = sqlx::query_scalar("SELECT image_url FROM problems").fetch_one(app.pool()).await.unwrap();
+    let path = url.trim_start_matches('/').to_string();
+
+    assert_eq!(app.delete("/courses/1").await.status(), Status::Ok);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM courses WHERE id = 1").await, 0);
+    for table in ["problems", "categories", "uploads"] {
+        assert_eq!(app.scalar(&format!("SELECT COUNT(*) FROM {}", table)).await, 0, "{}", table);
+    }
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM log_items").await, 1);
+    assert!(!std::path::Path::new(&path).exists());
+    assert_eq!(app.get("/courses/1/card").await.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_recategorize_problems() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    for (body, categories) in [("q1", "极限, 导数"), ("q2", "积分"), ("q3", "DP")] {
+        app.post_file("/logs/1/problems", &[("body", body), ("categories", categories)], "screenshot", ("", "application/octet-stream"), b"").await;
+    }
+    let paths = |id: i64| {
+        let pool = app.pool().clone();
+        async move {
+            sqlx::query_scalar::<_, String>("SELECT cp.path FROM problem_categories pc JOIN category_paths cp ON cp.id = pc.category_id WHERE pc.problem_id = ? ORDER BY cp.path")
+                .bind(id)
+                .fetch_all(&pool)
+                .await
+                .unwrap()
+        }
+    };
+
+    let csv = app.get("/courses/1/problems/categories.csv").await.into_string().await.unwrap();
+    assert!(csv.starts_with("\u{feff}number,source,categories\r\n"));
+    assert!(csv.contains("P-001,HW1,\"极限, 导数\"\r\nP-002,HW1,积分\r\n"));
+
+    // The upload is only previewed: P-001 loses 导数, P-002 loses everything,
+    // a changed case is no change, and unknown numbers are skipped
+    let edited = "number,source,categories\r\nP-001,HW1,\"极限, 导数/链式法则\"\r\n2,,\r\nP-003,HW1,dp\r\nP-099,,积分\r\n";
+    let res = app.post_file("/courses/1/problems/recategorize", &[], "csv", ("categories.csv", "text/csv"), edited.as_bytes()).await;
+    let preview = res.into_string().await.unwrap();
+    assert!(preview.contains("2 道错题的分类会改变，1 道不变") && preview.contains("第 5 行：没有错题 P-099"));
+    assert_eq!(paths(1).await, vec!["导数", "极限"]);
+
+    let res = app.post_form("/courses/1/problems/recategorize", "apply=true").await;
+    assert!(res.into_string().await.unwrap().contains("已修改 2 道错题的分类"));
+    assert_eq!(paths(1).await, vec!["导数/链式法则", "极限"]);
+    assert!(paths(2).await.is_empty());
+    assert_eq!(paths(3).await, vec!["DP"]);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM entity_history WHERE entity_type = 'problem' AND action = 'update'").await, 2);
+    // Confirming again has nothing left to apply
+    let res = app.post_form("/courses/1/problems/recategorize", "apply=true").await;
+    assert_eq!(res.headers().get_one("Location"), Some("/courses/1/settings"));
+
+    // A problem edited after the preview stops the whole change
+    let edited = "number,categories\nP-002,积分\nP-003,树\n";
+    app.post_file("/courses/1/problems/recategorize", &[], "csv", ("categories.csv", "text/csv"), edited.as_bytes()).await;
+    sqlx::query("DELETE FROM problem_categories WHERE problem_id = 3").execute(app.pool()).await.unwrap();
+    let res = app.post_form("/courses/1/problems/recategorize", "apply=true").await;
+    assert_eq!(res.headers().get_one("Location"), Some("/courses/1/settings"));
+    assert!(paths(2).await.is_empty() && paths(3).await.is_empty());
+
+    let res = app.post_file("/courses/1/problems/recategorize", &[], "csv", ("categories.csv", "text/csv"), b"id,tags\n1,x\n").await;
+    assert_eq!(res.headers().get_one("Location"), Some("/courses/1/settings"));
+}
+
+#[rocket::async_test]
+async fn test_import_notes() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+
+    let res = app.post_file("/courses/1/import/notes", &[], "archive", ("notes.zip", "application/zip"), b"not a zip").await;
+    assert_eq!(res.headers().get_one("Location"), Some("/courses/1/settings"));
+
+    let image = format!("note image {}", Uuid::new_v4());
+    let mut zip = crate::zip::ZipWriter::new();
+    zip.add("Notes/HW3.md", "---\nkind: 作业\ndate: 2026-09-14\ncategories: 极限\n---\n# HW3\n\n符号写反了。\n\n![第 2 题](img/q2.png)\n![](img/missing.png)\n".as_bytes());
+    zip.add("Notes/img/q2.png", image.as_bytes());
+    zip.add("Notes/Week 1 0123456789abcdef0123456789abcdef.md", b"Lecture notes");
+    let res = app.post_file("/courses/1/import/notes", &[], "archive", ("notes.zip", "application/zip"), &zip.finish()).await;
+    assert_eq!(res.headers().get_one("Location"), Some("/courses/1"));
+
+    let items: Vec<(String, String, Option<String>, Option<String>)> = sqlx::query_as("SELECT kind, title, date, description FROM log_items ORDER BY id")
+        .fetch_all(app.pool())
+        .await
+        .unwrap();
+    assert_eq!(items, [
+        ("Homework".to_string(), "HW3".to_string(), Some("2026-09-14".to_string()), Some("符号写反了。\n\n![](img/missing.png)".to_string())),
+        ("Other".to_string(), "Week 1".to_string(), None, Some("Lecture notes".to_string())),
+    ]);
+
+    let (notes, filename): (Option<String>, Option<String>) = sqlx::query_as("SELECT notes, original_filename FROM problems WHERE log_item_id = 1")
+        .fetch_one(app.pool())
+        .await
+        .unwrap();
+    assert_eq!((notes.as_deref(), filename.as_deref()), (Some("第 2 题"), Some("q2.png")));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problem_categories pc JOIN categories c ON pc.category_id = c.id WHERE c.name = '极限'").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM uploads").await, 1);
+}
+
+#[rocket::async_test]
+async fn test_text_problem() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+
+    // A file input left empty, as browsers send it, and nothing typed
+    let res = app.post_file("/logs/1/problems", &[("body", "  ")], "screenshot", ("", "application/octet-stream"), b"").await;
+    assert!(res.into_string().await.unwrap().contains("请上传截图或输入题目。"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 0);
+
+    let res = app.post_file("/logs/1/problems", &[("body", "求 $\\int_0^1 x^2 dx$")], "screenshot", ("", "application/octet-stream"), b"").await;
+    assert!(res.into_string().await.unwrap().contains("求 $\\int_0^1 x^2 dx$"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems WHERE image_url IS NULL AND body IS NOT NULL").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM uploads").await, 0);
+
+    let res = app.post_form("/problems/1", "body=%E6%B1%82%E5%AF%BC&notes=&solution_link=&categories=").await;
+    assert!(res.into_string().await.unwrap().contains("求导"));
+    assert!(app.get("/problems/1/view").await.into_string().await.unwrap().contains("求导"));
+}
+
+/// Serve `body` as `content_type` to every request on a local port; the URL
+/// of `/<name>` on it.
+async fn serve_file(name: &str, content_type: &'static str, body: &'static [u8]) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/{}", listener.local_addr().unwrap(), name);
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request).await;
+            let head = format!("HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", content_type, body.len());
+            let _ = stream.write_all(head.as_bytes()).await;
+            let _ = stream.write_all(body).await;
+        }
+    });
+    url
+}
+
+/// Answer the next `count` requests on a local port with 204; the URL to
+/// send them to and, once all have come in, their bodies.
+async fn capture_posts(count: usize) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let handle = tokio::spawn(async move {
+        let mut bodies = Vec::new();
+        for _ in 0..count {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            let body = loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).into_owned();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head.lines()
+                        .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap()))
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break body.to_string();
+                    }
+                }
+                if n == 0 {
+                    break String::new();
+                }
+            };
+            socket.write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n").await.unwrap();
+            bodies.push(body);
+        }
+        bodies
+    });
+    (url, handle)
+}
+
+#[rocket::async_test]
+async fn test_exam_problems() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/exams", "title=Midterm").await;
+
+    let res = app.post_file("/exams/1/problems", &[("body", " ")], "screenshot", ("", "application/octet-stream"), b"").await;
+    assert!(res.into_string().await.unwrap().contains("请上传截图或输入题目。"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 0);
+
+    let res = app.post_file("/exams/1/problems", &[("body", "证明根号二是无理数"), ("categories", "proofs")], "screenshot", ("", "application/octet-stream"), b"").await;
+    let row = res.into_string().await.unwrap();
+    assert!(row.contains("证明根号二是无理数") && row.contains("proofs"));
+    let res = app.post_multipart("/exams/1/problems", &[("notes", "sign error")], ("screenshot", b"exam screenshot")).await;
+    assert_eq!(res.status(), Status::Ok);
+
+    let listing = app.get("/exams/1/problems").await.into_string().await.unwrap();
+    assert!(listing.contains("证明根号二是无理数") && listing.contains("sign error"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems WHERE exam_id = 1 AND log_item_id IS NULL").await, 2);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems WHERE image_url IS NULL AND body IS NOT NULL").await, 1);
+
+    // The exam takes its problems with it, and their screenshot
+    assert_eq!(app.delete("/exams/1").await.status(), Status::Ok);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 0);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM uploads").await, 0);
+}
+
+#[rocket::async_test]
+async fn test_exam_from_study_preset() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_form("/courses/1/logs", "kind=Lecture&title=L1").await;
+    for (log, body) in [(1, "hw-a"), (1, "hw-b"), (1, "hw-c"), (2, "lecture")] {
+        app.post_file(&format!("/logs/{}/problems", log), &[("body", body)], "screenshot", ("", "application/octet-stream"), b"").await;
+    }
+    app.post_form("/courses/1/exams", "title=Midterm").await;
+    app.post_form("/problems/2/exam", "exam_id=1").await;
+    app.post_form("/courses/1/study/presets", "name=Homework&source=Homework").await;
+    assert!(app.get("/courses/1/exams").await.into_string().await.unwrap().contains("从筛选预设「Homework」选题"));
+
+    // The preset's problems go on the new exam, except one already on another
+    app.post_form("/courses/1/exams", "title=Practice&preset_id=1").await;
+    let listing = app.get("/exams/2/problems").await.into_string().await.unwrap();
+    assert!(listing.contains("hw-a") && listing.contains("hw-c"));
+    assert!(!listing.contains("hw-b") && !listing.contains("lecture"));
+    assert_eq!(app.scalar("SELECT exam_id FROM problems WHERE id = 2").await, 1);
+
+    // Someone else's preset adds nothing
+    sqlx::query("INSERT INTO users (username, password_hash) VALUES ('bob', 'x')").execute(app.pool()).await.unwrap();
+    sqlx::query("INSERT INTO study_presets (user_id, course_id, name, filters) VALUES (2, 1, 'theirs', '{}')").execute(app.pool()).await.unwrap();
+    app.post_form("/courses/1/exams", "title=Other&preset_id=2").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems WHERE exam_id = 3").await, 0);
+}
+
+#[rocket::async_test]
+async fn test_attach_problem_to_exam() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/semesters/1/courses", "code=D&title=U").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_form("/courses/1/exams", "title=Midterm").await;
+    app.post_form("/courses/2/exams", "title=Other").await;
+    app.post_form("/courses/1/exams", "title=Final").await;
+    app.post_file("/logs/1/problems", &[("body", "证明根号二是无理数")], "screenshot", ("", "application/octet-stream"), b"").await;
+    app.post_file("/exams/1/problems", &[("body", "exam only")], "screenshot", ("", "application/octet-stream"), b"").await;
+
+    // Only a log item's problem, and only onto an exam of its own course
+    assert_eq!(app.post_form("/problems/1/exam", "exam_id=2").await.status(), Status::BadRequest);
+    assert_eq!(app.post_form("/problems/2/exam", "exam_id=1").await.status(), Status::BadRequest);
+    assert_eq!(app.post_form("/problems/1/exam", "exam_id=1").await.status(), Status::SeeOther);
+
+    // It keeps its log item as its source, and shows on the exam too
+    let problem = app.get("/problems/1/view").await.into_string().await.unwrap();
+    assert!(problem.contains("HW1") && problem.contains("已收入「Midterm」") && problem.contains("移出考卷"));
+    let listing = app.get("/exams/1/problems").await.into_string().await.unwrap();
+    assert!(listing.contains("证明根号二是无理数") && listing.contains("考卷 · Midterm") && listing.contains("exam only"));
+
+    // Putting it on another exam doesn't quietly take it off this one
+    assert_eq!(app.post_form("/problems/1/exam", "exam_id=3").await.status(), Status::Conflict);
+    assert_eq!(app.post_form("/problems/1/exam", "exam_id=1").await.status(), Status::SeeOther);
+    assert_eq!(app.scalar("SELECT exam_id FROM problems WHERE id = 1").await, 1);
+
+    app.post_form("/problems/1/exam", "exam_id=").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems WHERE exam_id IS NULL").await, 1);
+    assert_eq!(app.post_form("/problems/1/exam", "exam_id=3").await.status(), Status::SeeOther);
+    app.post_form("/problems/1/exam", "exam_id=").await;
+
+    // An exam in a course the user can't open is as good as missing
+    for sql in [
+        "INSERT INTO users (username, password_hash) VALUES ('bob', 'x')",
+        "INSERT INTO semesters (name, user_id) VALUES ('B', 2)",
+        "INSERT INTO courses (semester_id, code, title) VALUES (2, 'B', 'B')",
+        "INSERT INTO exams (course_id, title) VALUES (3, 'Bob')",
+    ] {
+        sqlx::query(sql).execute(app.pool()).await.unwrap();
+    }
+    assert_eq!(app.post_form("/problems/1/exam", "exam_id=4").await.status(), Status::NotFound);
+
+    // Deleting the exam takes its own problems but not the ones put on it
+    app.post_form("/problems/1/exam", "exam_id=1").await;
+    assert_eq!(app.delete("/exams/1").await.status(), Status::Ok);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems WHERE id = 1 AND log_item_id = 1 AND exam_id IS NULL").await, 1);
+}
+
+#[rocket::async_test]
+async fn test_import_problem_from_url() {
+    // The files are served locally, which is refused unless allowed
+    let app = TestApp::configured(|figment| figment.merge(("outbound.allow_hosts", vec!["127.0.0.1"]))).await.signed_in().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+
+    let png = serve_file("q3.png", "application/octet-stream", b"\x89PNG\r\n\x1a\nurl import").await;
+    let res = app.post_form("/logs/1/problems/url", &format!("image_link={}&categories=limits", png)).await;
+    assert!(res.into_string().await.unwrap().contains("q3.png"));
+    let (image_url, mime_type, imported_from): (String, String, String) =
+        sqlx::query_as("SELECT image_url, mime_type, imported_from FROM problems WHERE id = 1")
+            .fetch_one(app.pool())
+            .await
+            .unwrap();
+    assert_eq!((mime_type.as_str(), imported_from.as_str()), ("image/png", png.as_str()));
+    assert!(image_url.ends_with(".png"));
+    assert!(app.get("/problems/1/view").await.into_string().await.unwrap().contains(&format!("导入自 {}", png)));
+
+    // A web page, and a file that only claims to be an image
+    let page = serve_file("q4", "text/html; charset=utf-8", b"<html></html>").await;
+    let res = app.post_form("/logs/1/problems/url", &format!("image_link={}", page)).await;
+    assert!(res.into_string().await.unwrap().contains("text/html"));
+    let fake = serve_file("q5.png", "image/png", b"MZ\x90\0not an image").await;
+    let res = app.post_form("/logs/1/problems/url", &format!("image_link={}", fake)).await;
+    assert!(res.into_string().await.unwrap().contains("上传失败"));
+    let res = app.post_form("/logs/1/problems/url", "image_link=ftp://example.com/q6.png").await;
+    assert!(res.into_string().await.unwrap().contains("http://"));
+
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM uploads").await, 1);
+
+    let _ = std::fs::remove_file(image_url.trim_start_matches('/'));
+
+    // Without the allowance, the instance's own network is off limits
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    let res = app.post_form("/logs/1/problems/url", &format!("image_link={}", png)).await;
+    assert!(res.into_string().await.unwrap().contains("内网"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 0);
+}
+
+#[rocket::async_test]
+async fn test_problem_numbers() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/semesters/1/courses", "code=D&title=U").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_form("/courses/2/logs", "kind=Homework&title=HW1").await;
+    sqlx::query(
+        "INSERT INTO problems (log_item_id, description, notes, is_incorrect, created_at) VALUES (1, 'p', 'sign error', 1, CURRENT_TIMESTAMP), (2, 'p', NULL, 1, CURRENT_TIMESTAMP), (1, 'p', NULL, 1, CURRENT_TIMESTAMP)",
+    )
+    .execute(app.pool())
+    .await
+    .unwrap();
+
+    // Each course counts from 1, and a deleted problem's number isn't reused
+    let numbers: Vec<i64> = sqlx::query_scalar("SELECT number FROM problems ORDER BY id").fetch_all(app.pool()).await.unwrap();
+    assert_eq!(numbers, vec![1, 1, 2]);
+    app.delete("/problems/3").await;
+    sqlx::query("INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP)").execute(app.pool()).await.unwrap();
+    assert_eq!(app.scalar("SELECT number FROM problems WHERE id = 4").await, 3);
+    assert!(app.get("/problems/4/view").await.into_string().await.unwrap().contains("错题 P-003"));
+
+    let list = app.get("/courses/1/study/problems?q=p-003").await.into_string().await.unwrap();
+    assert!(list.contains("/problems/4/view") && !list.contains("/problems/1/view"));
+    let list = app.get("/courses/1/study/problems?q=Sign").await.into_string().await.unwrap();
+    assert!(list.contains("/problems/1/view") && !list.contains("/problems/4/view"));
+
+    // The search isn't remembered with the other filters
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM user_preferences WHERE value LIKE '%Sign%'").await, 0);
+}
+
+#[rocket::async_test]
+async fn test_nested_categories() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    sqlx::query("INSERT INTO categories (course_id, name) VALUES (1, '线代/矩阵')").execute(app.pool()).await.unwrap();
+
+    let row = app.post_multipart("/logs/1/problems", &[("categories", "微积分/泰勒级数")], ("screenshot", b"nested 1")).await.into_string().await.unwrap();
+    assert!(row.contains("微积分 › 泰勒级数"));
+    app.post_multipart("/logs/1/problems", &[("categories", "微积分 ／ 泰勒级数、微积分")], ("screenshot", b"nested 2")).await;
+    app.post_multipart("/logs/1/problems", &[("categories", "DP")], ("screenshot", b"nested 3")).await;
+    // Made before nesting existed: the slash is part of the name
+    app.post_multipart("/logs/1/problems", &[("categories", "线代/矩阵")], ("screenshot", b"nested 4")).await;
+
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM categories").await, 4);
+    assert_eq!(app.scalar("SELECT parent_id FROM categories WHERE name = '泰勒级数'").await, app.scalar("SELECT id FROM categories WHERE name = '微积分'").await);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problem_categories WHERE problem_id = 4 AND category_id = 1").await, 1);
+    assert!(app.get("/logs/1").await.into_string().await.unwrap().contains(r#"<option value="微积分/泰勒级数">"#));
+
+    // Selecting the parent also matches problems filed under its children
+    let parent = app.scalar("SELECT id FROM categories WHERE name = '微积分'").await;
+    let list = app.get(&format!("/courses/1/study/problems?category={}", parent)).await.into_string().await.unwrap();
+    assert!(list.contains("/problems/1/view") && list.contains("/problems/2/view"));
+    assert!(!list.contains("/problems/3/view") && !list.contains("/problems/4/view"));
+    let child = app.scalar("SELECT id FROM categories WHERE name = '泰勒级数'").await;
+    let list = app.get(&format!("/courses/1/study/problems?category={}", child)).await.into_string().await.unwrap();
+    assert!(list.contains("/problems/1/view") && list.contains("/problems/2/view"));
+}
+
+#[rocket::async_test]
+async fn test_concurrent_category_creation() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+
+    // Saved at the same moment with the same new category: both share one
+    tokio::join!(
+        app.post_multipart("/logs/1/problems", &[("categories", "微积分/泰勒级数")], ("screenshot", b"race 1")),
+        app.post_multipart("/logs/1/problems", &[("categories", "微积分/泰勒级数")], ("screenshot", b"race 2")),
+    );
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 2);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM categories").await, 2);
+    assert_eq!(app.scalar("SELECT COUNT(DISTINCT category_id) FROM problem_categories").await, 1);
+
+    // Top-level names are unique too, despite the NULL parent
+    assert!(sqlx::query("INSERT INTO categories (course_id, name) VALUES (1, '微积分')").execute(app.pool()).await.is_err());
+
+    let urls: Vec<String> = sqlx::query_scalar("SELECT image_url FROM problems").fetch_all(app.pool()).await.unwrap();
+    for url in urls {
+        let _ = std::fs::remove_file(url.trim_start_matches('/'));
+    }
+}
+
+#[rocket::async_test]
+async fn test_category_name_normalization() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+
+    // Width, case and spacing don't make a new category; the first spelling stays
+    app.post_multipart("/logs/1/problems", &[("categories", "DP")], ("screenshot", b"normalize 1")).await;
+    let row = app.post_multipart("/logs/1/problems", &[("categories", "ｄｐ , 图论\u{3000}/ 最短路, dp")], ("screenshot", b"normalize 2")).await.into_string().await.unwrap();
+    assert!(row.contains("DP") && row.contains("图论 › 最短路"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM categories").await, 3);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problem_categories WHERE category_id = 1").await, 2);
+
+    // Made before normalization: only `zhixi normalize-categories` tidies these
+    for sql in [
+        "INSERT INTO categories (course_id, name) VALUES (1, '  Graphs'), (1, 'ＧＲＡＰＨＳ')",
+        "INSERT INTO problem_categories (problem_id, category_id) VALUES (1, 5)",
+    ] {
+        sqlx::query(sql).execute(app.pool()).await.unwrap();
+    }
+    assert!(app.get("/logs/1").await.into_string().await.unwrap().contains(r#"<option value="ＧＲＡＰＨＳ">"#));
+    let category_cache = app.client.rocket().state::<crate::category_cache::CategoryCache>().unwrap();
+    let mut conn = app.pool().acquire().await.unwrap();
+    let report = crate::categories::normalize_existing(&mut conn, category_cache).await.unwrap();
+    assert_eq!(report, crate::categories::Report { renamed: 1, merged: 1 });
+    // The cached list goes with them
+    let page = app.get("/logs/1").await.into_string().await.unwrap();
+    assert!(page.contains(r#"<option value="Graphs">"#) && !page.contains("ＧＲＡＰＨＳ"));
+    let names: Vec<String> = sqlx::query_scalar("SELECT name FROM categories ORDER BY id").fetch_all(app.pool()).await.unwrap();
+    assert_eq!(names, ["DP", "图论", "最短路", "Graphs"]);
+    assert_eq!(app.scalar("SELECT category_id FROM problem_categories WHERE problem_id = 1 AND category_id > 3").await, 4);
+    assert_eq!(crate::categories::normalize_existing(&mut conn, category_cache).await.unwrap(), Default::default());
+
+    let urls: Vec<String> = sqlx::query_scalar("SELECT image_url FROM problems").fetch_all(app.pool()).await.unwrap();
+    for url in urls {
+        let _ = std::fs::remove_file(url.trim_start_matches('/'));
+    }
+}
+
+#[rocket::async_test]
+async fn test_category_cache_invalidation() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    assert!(!app.get("/logs/1").await.into_string().await.unwrap().contains(r#"<option value="series">"#));
+
+    // A category created while adding a problem shows up in the next render
+    app.post_multipart("/logs/1/problems", &[("categories", "series")], ("screenshot", b"cache test")).await;
+    assert!(app.get("/logs/1").await.into_string().await.unwrap().contains(r#"<option value="series">"#));
+    assert!(app.get("/courses/1/study").await.into_string().await.unwrap().contains("series"));
+
+    let image_url: String = sqlx::query_scalar("SELECT image_url FROM problems").fetch_one(app.pool()).await.unwrap();
+    let _ = std::fs::remove_file(image_url.trim_start_matches('/'));
+}
+
+#[rocket::async_test]
+async fn test_upload_scan_rejects_disallowed_files() {
+    let app = TestApp::configured(|figment| figment
+        .merge(("upload_scan.allowed_types", ["image/*"]))
+        .merge(("upload_scan.max_bytes", 1024)))
+        .await
+        .signed_in()
+        .await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+
+    // Not a PNG whatever the Content-Type says
+    let res = app.post_multipart("/logs/1/problems", &[], ("screenshot", b"MZ\x90\0not an image")).await;
+    assert_eq!(res.status(), Status::Ok);
+    let html = res.into_string().await.unwrap();
+    assert!(html.contains("上传失败"));
+    assert!(html.contains("image/png"));
+
+    let res = app.post_file("/logs/1/problems", &[], "screenshot", ("big.png", "image/png"), &[0x89; 2048]).await;
+    assert!(res.into_string().await.unwrap().contains("文件过大"));
+
+    let res = app.post_file("/courses/1/settings", &[], "banner", ("banner.pdf", "application/pdf"), b"%PDF-1.7").await;
+    assert_eq!(res.status(), Status::SeeOther);
+    assert!(app.get("/courses/1/settings").await.into_string().await.unwrap().contains("application/pdf"));
+
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 0);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM uploads").await, 0);
+
+    // A real PNG header gets through
+    let res = app.post_multipart("/logs/1/problems", &[], ("screenshot", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")).await;
+    assert!(!res.into_string().await.unwrap().contains("上传失败"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 1);
+
+    let image_url: String = sqlx::query_scalar("SELECT image_url FROM problems").fetch_one(app.pool()).await.unwrap();
+    let _ = std::fs::remove_file(image_url.trim_start_matches('/'));
+}
+
+#[rocket::async_test]
+async fn test_oversized_bodies_get_a_readable_413() {
+    let app = TestApp::configured(|figment| figment
+        .merge(("limits.file", 1024))
+        .merge(("limits.form", 256)))
+        .await
+        .signed_in()
+        .await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+
+    let res = app.post_file("/logs/1/problems", &[], "screenshot", ("big.png", "image/png"), &[0x89; 2048]).await;
+    assert_eq!(res.status(), Status::PayloadTooLarge);
+    assert_eq!(res.into_string().await.unwrap(), "文件过大，上限为 1 KB。");
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 0);
+
+    // Chunked uploads through the API are held to the same limit as a whole
+    let log = app.public_id("log_items", 1).await;
+    let upload_id = Uuid::new_v4();
+    let chunk = |n: u32| app.client
+        .post(format!("/api/v1/logs/{}/problems?upload_id={}&chunk={}&total=3", log, upload_id, n))
+        .body(vec![0x89; 600])
+        .dispatch();
+    assert_eq!(chunk(0).await.status(), Status::Ok);
+    assert_eq!(chunk(1).await.status(), Status::PayloadTooLarge);
+    let status = app.get(&format!("/api/v1/uploads/{}", upload_id)).await.into_string().await.unwrap();
+    assert!(status.contains("\"received\":[]"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 0);
+
+    // HTMX requests get the upload error fragment
+    let res = app.client
+        .post("/semesters/1/courses")
+        .header(ContentType::Form)
+        .header(Header::new("HX-Request", "true"))
+        .body(format!("code=D&title={}", "x".repeat(512)))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::PayloadTooLarge);
+    let html = res.into_string().await.unwrap();
+    assert!(html.contains("upload-error"));
+    assert!(html.contains("提交的内容过长，上限为 256 B。"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM courses").await, 1);
+}
+
+#[rocket::async_test]
+async fn test_semester_export_bundle() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_multipart("/logs/1/problems", &[("categories", "series")], ("screenshot", b"export test")).await;
+    let image_url: String = sqlx::query_scalar("SELECT image_url FROM problems").fetch_one(app.pool()).await.unwrap();
+
+    let res = app.get("/semesters/1/export").await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(res.content_type(), Some(ContentType::ZIP));
+    assert!(res.headers().get_one("Content-Disposition").unwrap().starts_with("attachment"));
+    let bytes = res.into_bytes().await.unwrap();
+    assert!(bytes.starts_with(b"PK\x03\x04"));
+
+    // Entries are stored uncompressed, so names and contents show up verbatim
+    let contains = |needle: &[u8]| bytes.windows(needle.len()).any(|w| w == needle);
+    assert!(contains(b"manifest.json"));
+    assert!(contains(br#""format": "zhixi-semester-export""#));
+    assert!(contains(b"courses/1.json"));
+    assert!(contains(br#""title": "HW1""#));
+    assert!(contains(br#""name": "series""#));
+    assert!(contains(format!("uploads/{}", image_url.trim_start_matches("/uploads/")).as_bytes()));
+    assert!(contains(b"export test"));
+
+    assert_eq!(app.get("/semesters/99/export").await.status(), Status::NotFound);
+
+    let _ = std::fs::remove_file(image_url.trim_start_matches('/'));
+}
+
+#[rocket::async_test]
+async fn test_course_site_export() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_multipart("/logs/1/problems", &[("notes", "sign error")], ("screenshot", b"site test")).await;
+    app.post_form("/courses/1/exams", "title=Final").await;
+    let image_url: String = sqlx::query_scalar("SELECT image_url FROM problems").fetch_one(app.pool()).await.unwrap();
+    let path = image_url.trim_start_matches("/uploads/");
+
+    let res = app.get("/courses/1/export/site").await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(res.content_type(), Some(ContentType::ZIP));
+    assert!(res.headers().get_one("Content-Disposition").unwrap().contains("zhixi-course-1-site.zip"));
+    let bytes = res.into_bytes().await.unwrap();
+
+    // The page links the upload inside the bundle, which carries the file itself
+    let contains = |needle: &[u8]| bytes.windows(needle.len()).any(|w| w == needle);
+    assert!(contains(b"index.html"));
+    assert!(contains("<span class=\"kind\">作业</span>HW1".as_bytes()));
+    assert!(contains("<span class=\"kind\">考卷</span>Final".as_bytes()));
+    assert!(contains(b"sign error"));
+    assert!(contains(format!("\"uploads/{}\"", path).as_bytes()));
+    assert!(!contains(format!("\"/uploads/{}", path).as_bytes()));
+    assert!(contains(b"site test"));
+    assert!(!contains(b"<script"));
+
+    assert_eq!(app.get("/courses/99/export/site").await.status(), Status::NotFound);
+
+    let _ = std::fs::remove_file(image_url.trim_start_matches('/'));
+}
+
+#[rocket::async_test]
+async fn test_public_ids() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_form("/courses/1/logs", "kind=Quiz&title=Q1").await;
+
+    let (first, second) = (app.public_id("log_items", 1).await, app.public_id("log_items", 2).await);
+    assert_ne!(first, second);
+    assert_eq!(Uuid::parse_str(&first).unwrap().get_version_num(), 7);
+    assert_eq!(Uuid::parse_str(&app.public_id("courses", 1).await).unwrap().get_version_num(), 7);
+
+    // A deleted log item restored from history comes back under the same public id
+    app.delete("/logs/1").await;
+    let entry = app.scalar("SELECT id FROM entity_history WHERE entity_type = 'log_item' AND action = 'delete'").await;
+    app.post_form(&format!("/history/{}/revert", entry), "").await;
+    assert_eq!(app.public_id("log_items", 1).await, first);
+}
+
+#[rocket::async_test]
+async fn test_revert_problem_round_trip() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    // Marked correct, rated and dated, none of which the edit form touches
+    sqlx::query("INSERT INTO problems (log_item_id, description, is_incorrect, difficulty, created_at) VALUES (1, 'p', 0, 40, '2026-01-02 03:04:05')")
+        .execute(app.pool())
+        .await
+        .unwrap();
+    app.post_form("/problems/1", "notes=first&categories=DP,Graphs").await;
+    let row = "SELECT is_incorrect || '|' || difficulty || '|' || created_at || '|' || notes || '|' || (SELECT group_concat(category_id) FROM (SELECT category_id FROM problem_categories WHERE problem_id = 1 ORDER BY category_id)) FROM problems WHERE id = 1";
+    let original: String = sqlx::query_scalar(row).fetch_one(app.pool()).await.unwrap();
+    assert_eq!(original, "0|40|2026-01-02 03:04:05|first|1,2");
+
+    // A renamed category is still the one the problem had
+    sqlx::query("UPDATE categories SET name = 'Graph theory' WHERE id = 2").execute(app.pool()).await.unwrap();
+    app.post_form("/problems/1", "notes=second&categories=Strings").await;
+    let update = app.scalar("SELECT MAX(id) FROM entity_history WHERE action = 'update'").await;
+    app.post_form(&format!("/history/{}/revert", update), "").await;
+    assert_eq!(sqlx::query_scalar::<_, String>(row).fetch_one(app.pool()).await.unwrap(), original);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM categories WHERE name = 'Graphs'").await, 0);
+
+    app.delete("/problems/1").await;
+    let delete = app.scalar("SELECT id FROM entity_history WHERE action = 'delete'").await;
+    app.post_form(&format!("/history/{}/revert", delete), "").await;
+    assert_eq!(sqlx::query_scalar::<_, String>(row).fetch_one(app.pool()).await.unwrap(), original);
+}
+
+#[rocket::async_test]
+async fn test_study_list_batches() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    for _ in 0..45 {
+        sqlx::query("INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP)")
+            .execute(app.pool())
+            .await
+            .unwrap();
+    }
+
+    let first = app.get("/courses/1/study/problems?sort=newest").await.into_string().await.unwrap();
+    assert_eq!(first.matches("/problems/").count(), 30);
+    assert!(first.contains("/problems/45/view") && first.contains("study/problems?offset=30"));
+
+    let second = app.get("/courses/1/study/problems?sort=newest&offset=30").await.into_string().await.unwrap();
+    assert_eq!(second.matches("/problems/").count(), 15);
+    assert!(second.contains("/problems/1/view") && !second.contains("offset="));
+
+    assert!(!app.get("/courses/1/study/problems?offset=90").await.into_string().await.unwrap().contains("empty-state"));
+}
+
+#[rocket::async_test]
+async fn test_study_manifest() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_form("/courses/1/logs", "kind=Lab&title=Lab1").await;
+    for (log, image, mime) in [(1, Some("/uploads/a.png"), None), (1, None, None), (1, Some("/uploads/b.pdf"), Some("application/pdf")), (2, Some("/uploads/c.png"), Some("image/png")), (1, Some("/uploads/d.png"), None)] {
+        sqlx::query("INSERT INTO problems (log_item_id, image_url, mime_type, description, is_incorrect, created_at) VALUES (?, ?, ?, 'p', 1, CURRENT_TIMESTAMP)")
+            .bind(log)
+            .bind(image)
+            .bind(mime)
+            .execute(app.pool())
+            .await
+            .unwrap();
+    }
+
+    let urls: Vec<String> = app.get("/courses/1/study/manifest").await.into_json().await.unwrap();
+    assert_eq!(urls, ["/uploads/a.png?h=768", "/uploads/c.png?h=768", "/uploads/d.png?h=768"]);
+
+    let urls: Vec<String> = app.get("/courses/1/study/manifest?sort=newest&offset=1").await.into_json().await.unwrap();
+    assert_eq!(urls, ["/uploads/c.png?h=768", "/uploads/a.png?h=768"]);
+
+    let urls: Vec<String> = app.get("/courses/1/study/manifest?source=Homework&limit=2").await.into_json().await.unwrap();
+    assert_eq!(urls, ["/uploads/a.png?h=768"]);
+
+    let urls: Vec<String> = app.get("/courses/1/study/manifest?offset=5").await.into_json().await.unwrap();
+    assert!(urls.is_empty());
+}
+
+#[rocket::async_test]
+async fn test_focus_suggestions() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=Old").await;
+    app.post_form("/semesters/1/courses", "code=OLD&title=T").await;
+    app.post_form("/semesters", "name=New").await;
+    app.post_form("/semesters/2/courses", "code=NEW&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW0").await;
+    app.post_form("/courses/2/logs", "kind=Homework&title=HW1").await;
+    // (category, incorrect flags, days since last review)
+    let categories: [(&str, &[i64], Option<i64>); 6] = [
+        ("recent", &[1, 1], Some(0)),
+        ("never", &[1, 0], None),
+        ("correct", &[0, 0], None),
+        ("all wrong", &[1], None),
+        ("stale", &[1, 0, 0, 0], Some(40)),
+        ("old semester", &[1], None),
+    ];
+    for (i, (name, flags, reviewed)) in categories.iter().enumerate() {
+        let course_id = if *name == "old semester" { 1 } else { 2 };
+        let category_id = sqlx::query("INSERT INTO categories (course_id, name) VALUES (?, ?)")
+            .bind(course_id)
+            .bind(name)
+            .execute(app.pool())
+            .await
+            .unwrap()
+            .last_insert_rowid();
+        for flag in flags.iter() {
+            let problem_id = sqlx::query("INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (?, 'p', ?, CURRENT_TIMESTAMP)")
+                .bind(course_id)
+                .bind(flag)
+                .execute(app.pool())
+                .await
+                .unwrap()
+                .last_insert_rowid();
+            sqlx::query("INSERT INTO problem_categories (problem_id, category_id) VALUES (?, ?)")
+                .bind(problem_id)
+                .bind(category_id)
+                .execute(app.pool())
+                .await
+                .unwrap();
+            if let Some(days) = reviewed {
+                sqlx::query("INSERT INTO study_reviews (user_id, problem_id, grade, reviewed_at) VALUES (1, ?, 3, datetime('now', ?))")
+                    .bind(problem_id)
+                    .bind(format!("-{} days", days))
+                    .execute(app.pool())
+                    .await
+                    .unwrap();
+            }
+        }
+        assert_eq!(category_id, i as i64 + 1);
+    }
+
+    let body = app.get("/dashboard").await.into_string().await.unwrap();
+    let position = |id: i64| body.find(&format!("/study/focus/{}\"", id));
+    // Never-reviewed and long-unreviewed categories outrank one reviewed today
+    let order = [4, 2, 5].map(|id| position(id).expect("suggested"));
+    assert!(order[0] < order[1] && order[1] < order[2]);
+    for id in [1, 3, 6] {
+        assert_eq!(position(id), None);
+    }
+    assert!(body.contains("1/1 题做错（100%）· 从未复习"));
+    assert!(body.contains("1/4 题做错（25%）· 40 天没复习"));
+
+    let response = app.get("/courses/2/study/focus/5").await;
+    assert_eq!(response.headers().get_one("Location"), Some("/courses/2/study"));
+    let filters: String = sqlx::query_scalar("SELECT value FROM user_preferences WHERE key = 'study_filters:2'")
+        .fetch_one(app.pool())
+        .await
+        .unwrap();
+    assert!(filters.contains(r#""sources":[],"categories":[5]"#));
+}
+
+#[rocket::async_test]
+async fn test_deletes_cascade() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_form("/courses/1/exams", "title=Midterm").await;
+    for (log_item_id, exam_id) in [(Some(1), None), (Some(1), None), (None, Some(1))] {
+        sqlx::query("INSERT INTO problems (log_item_id, exam_id, description, is_incorrect, created_at) VALUES (?, ?, 'p', 1, CURRENT_TIMESTAMP)")
+            .bind(log_item_id)
+            .bind(exam_id)
+            .execute(app.pool())
+            .await
+            .unwrap();
+    }
+    for sql in [
+        "INSERT INTO categories (course_id, name) VALUES (1, 'limits')",
+        "INSERT INTO problem_categories (problem_id, category_id) VALUES (1, 1), (2, 1), (3, 1)",
+        "INSERT INTO exam_categories (exam_id, category_id) VALUES (1, 1)",
+        "INSERT INTO study_cards (user_id, problem_id, due_at) VALUES (1, 1, CURRENT_TIMESTAMP), (1, 2, CURRENT_TIMESTAMP)",
+        "INSERT INTO study_reviews (user_id, problem_id, grade, reviewed_at) VALUES (1, 2, 3, CURRENT_TIMESTAMP)",
+    ] {
+        sqlx::query(sql).execute(app.pool()).await.unwrap();
+    }
+
+    // A problem with review state used to be refused by its study card
+    assert_eq!(app.delete("/problems/1").await.status(), Status::Ok);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM study_cards").await, 1);
+
+    assert_eq!(app.delete("/logs/1").await.status(), Status::Ok);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM study_cards").await, 0);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM study_reviews").await, 0);
+
+    assert_eq!(app.delete("/exams/1").await.status(), Status::Ok);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 0);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problem_categories").await, 0);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM exam_categories").await, 0);
+    // History still has everything needed to bring them back
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM entity_history WHERE action = 'delete' AND entity_type = 'problem'").await, 3);
+
+    // The course takes everything of its own along, history included
+    for sql in [
+        "INSERT INTO study_presets (user_id, course_id, name, filters) VALUES (1, 1, 'p', '{}')",
+        "INSERT INTO study_goals (user_id, course_id, category_id, target, created_at) VALUES (1, 1, 1, 5, CURRENT_TIMESTAMP)",
+        "INSERT OR IGNORE INTO log_item_kinds (course_id, kind, position) VALUES (1, 'Seminar', 9)",
+        "INSERT INTO course_members (course_id, user_id, role, joined_at) VALUES (1, 1, 'viewer', CURRENT_TIMESTAMP)",
+    ] {
+        sqlx::query(sql).execute(app.pool()).await.unwrap();
+    }
+    app.post_form("/courses/1/discussions", "title=Q&body=x").await;
+    assert_eq!(app.delete("/courses/1").await.status(), Status::Ok);
+    for table in ["categories", "study_presets", "study_goals", "log_item_kinds", "course_members", "discussion_threads", "discussion_posts", "entity_history"] {
+        assert_eq!(app.scalar(&format!("SELECT COUNT(*) FROM {}", table)).await, 0, "{}", table);
+    }
+}
+
+#[rocket::async_test]
+async fn test_problem_answer() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    sqlx::query("INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP)")
+        .execute(app.pool())
+        .await
+        .unwrap();
+
+    // A blank answer is stored as none at all
+    app.post_form("/problems/1", "notes=n&solution_link=&answer=+&categories=").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems WHERE answer IS NULL").await, 1);
+
+    let row = app.post_form("/problems/1", "notes=n&solution_link=&answer=%24x%5E2%24+%E2%86%92+**2x**&categories=").await.into_string().await.unwrap();
+    assert!(row.contains("answer-body") && row.contains("$x^2$ → **2x**"));
+
+    // Hidden behind a toggle in the study list, and part of the review card
+    let study = app.get("/courses/1/study/problems").await.into_string().await.unwrap();
+    assert!(study.contains("<summary") && study.contains("$x^2$ → **2x**"));
+    let course = app.public_id("courses", 1).await;
+    let session: serde_json::Value = app.get(&format!("/api/v1/study/session?course_id={}", course)).await.into_json().await.unwrap();
+    assert_eq!(session["card"]["answer"], "$x^2$ → **2x**");
+
+    let listed: serde_json::Value = app.get(&format!("/api/v1/courses/{}/problems?has_solution=true&fields=id,answer", course)).await.into_json().await.unwrap();
+    assert_eq!(listed, serde_json::json!([{"id": app.public_id("problems", 1).await, "answer": "$x^2$ → **2x**"}]));
+}
+
+#[rocket::async_test]
+async fn test_api_problem_filters() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1&date=2026-09-01").await;
+    app.post_form("/courses/1/logs", "kind=Quiz&title=Q1&date=2026-09-20").await;
+
+    let pool = app.pool();
+    for (log_item_id, solution_link, is_incorrect) in [(1, Some("https://example.com/s1"), 1), (1, None, 0), (2, None, 1)] {
+        sqlx::query("INSERT INTO problems (log_item_id, description, solution_link, is_incorrect, created_at) VALUES (?, 'p', ?, ?, CURRENT_TIMESTAMP)")
+            .bind(log_item_id)
+            .bind(solution_link)
+            .bind(is_incorrect)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+    sqlx::query("INSERT INTO categories (course_id, name) VALUES (1, 'limits')").execute(pool).await.unwrap();
+    sqlx::query("INSERT INTO problem_categories (problem_id, category_id) VALUES (3, 1)").execute(pool).await.unwrap();
+
+    // Problems come back under their public ids; map them to row ids for readability
+    async fn ids(app: &TestApp, query: &str) -> Vec<i64> {
+        let course = app.public_id("courses", 1).await;
+        let response = app.get(&format!("/api/v1/courses/{}/problems{}", course, query)).await;
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        let mut ids = Vec::new();
+        for p in body.as_array().unwrap() {
+            let id: i64 = sqlx::query_scalar("SELECT id FROM problems WHERE public_id = ?")
+                .bind(p["id"].as_str().unwrap())
+                .fetch_one(app.pool())
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+        ids
+    }
+
+    assert_eq!(ids(&app, "").await, vec![1, 2, 3]);
+    assert_eq!(ids(&app, "?kind=Quiz").await, vec![3]);
+    assert_eq!(ids(&app, "?kind=Homework&kind=Exam").await, vec![1, 2]);
+    assert_eq!(ids(&app, "?category=limits").await, vec![3]);
+    assert_eq!(ids(&app, "?incorrect=true").await, vec![1, 3]);
+    assert_eq!(ids(&app, "?has_solution=true").await, vec![1]);
+    assert_eq!(ids(&app, "?from=2026-09-10&to=2026-09-30").await, vec![3]);
+    assert_eq!(ids(&app, "?incorrect=true&has_solution=false").await, vec![3]);
+
+    let course = app.public_id("courses", 1).await;
+    let body = app.get(&format!("/api/v1/courses/{}/problems?category=limits&fields=id,log_item_id,categories", course)).await.into_string().await.unwrap();
+    assert_eq!(body, format!(r#"[{{"categories":["limits"],"id":"{}","log_item_id":"{}"}}]"#, app.public_id("problems", 3).await, app.public_id("log_items", 2).await));
+
+    assert_eq!(app.get(&format!("/api/v1/courses/{}/problems?fields=id,password", course)).await.status(), Status::BadRequest);
+    assert_eq!(app.get(&format!("/api/v1/courses/{}/problems?from=last-week", course)).await.status(), Status::BadRequest);
+    // Row ids are not accepted in API URLs
+    assert_eq!(app.get("/api/v1/courses/1/problems").await.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_study_session_api() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    for image in ["/uploads/a.png", "/uploads/b.png"] {
+        sqlx::query("INSERT INTO problems (log_item_id, description, image_url, is_incorrect, created_at) VALUES (1, 'p', ?, 1, CURRENT_TIMESTAMP)")
+            .bind(image)
+            .execute(app.pool())
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(app.get("/courses/1/study/review").await.status(), Status::Ok);
+
+    async fn json(response: LocalResponse<'_>) -> serde_json::Value {
+        serde_json::from_str(&response.into_string().await.unwrap()).unwrap()
+    }
+    async fn answer<'a>(app: &'a TestApp, body: &str) -> LocalResponse<'a> {
+        app.client.post("/api/v1/study/answer").header(ContentType::JSON).body(body).dispatch().await
+    }
+
+    let course = app.public_id("courses", 1).await;
+    let (p1, p2) = (app.public_id("problems", 1).await, app.public_id("problems", 2).await);
+    let session = json(app.get(&format!("/api/v1/study/session?course_id={}", course)).await).await;
+    assert_eq!(session["remaining"], 2);
+    assert_eq!(session["card"]["public_id"], p1.as_str());
+    assert_eq!(session["next_image_url"], "/uploads/b.png");
+
+    let result = json(answer(&app, &format!(r#"{{"problem_id": "{}", "grade": 3, "course_id": "{}"}}"#, p1, course)).await).await;
+    assert_eq!(result["interval_days"], 1.0);
+    assert_eq!(result["session"]["remaining"], 1);
+    assert_eq!(result["session"]["card"]["public_id"], p2.as_str());
+    assert!(result["session"]["next_image_url"].is_null());
+
+    // "Again" still takes the card out of the queue for a few minutes
+    let result = json(answer(&app, &format!(r#"{{"problem_id": "{}", "grade": 1}}"#, p2)).await).await;
+    assert_eq!(result["interval_days"], 0.0);
+    assert_eq!(result["session"]["remaining"], 0);
+    assert!(result["session"]["card"].is_null());
+    assert_eq!(app.scalar("SELECT reviews FROM study_cards WHERE problem_id = 2").await, 1);
+
+    // An easy card keeps growing until it comes back once a year
+    for _ in 0..20 {
+        let response = answer(&app, &format!(r#"{{"problem_id": "{}", "grade": 4}}"#, p1)).await;
+        assert_eq!(response.status(), Status::Ok);
+    }
+    let result = json(answer(&app, &format!(r#"{{"problem_id": "{}", "grade": 4}}"#, p1)).await).await;
+    assert_eq!(result["interval_days"], crate::review::MAX_INTERVAL_DAYS);
+
+    assert_eq!(answer(&app, &format!(r#"{{"problem_id": "{}", "grade": 5}}"#, p1)).await.status(), Status::BadRequest);
+    assert_eq!(answer(&app, r#"{"problem_id": "0199a3b2-6c00-7c30-ae21-4f5c8d9b1a01", "grade": 3}"#).await.status(), Status::NotFound);
+    assert_eq!(app.get("/api/v1/study/session?course_id=1").await.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_api_limits() {
+    let app = TestApp::authenticated().await;
+    let uri = "/api/v1/uploads/0199a3b2-6c00-7c30-ae21-4f5c8d9b1a01";
+    assert!(app.get(uri).await.headers().get_one("RateLimit-Limit").is_none());
+
+    app.post_form("/admin/api_limits", "per_minute=100&per_day=2").await;
+    assert!(app.get("/admin/site").await.into_string().await.unwrap().contains("value=\"100\""));
+    let res = app.get(uri).await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(res.headers().get_one("RateLimit-Limit"), Some("2"));
+    assert_eq!(res.headers().get_one("RateLimit-Remaining"), Some("1"));
+    assert_eq!(res.headers().get_one("RateLimit-Policy"), Some("100;w=60, 2;w=86400"));
+    app.get(uri).await;
+    let res = app.get(uri).await;
+    assert_eq!(res.status(), Status::TooManyRequests);
+    assert_eq!(res.headers().get_one("RateLimit-Remaining"), Some("0"));
+    assert!(res.headers().get_one("Retry-After").is_some());
+    assert_eq!(app.scalar("SELECT requests FROM api_usage WHERE user_id = 1").await, 3);
+
+    // Pages aren't counted
+    assert_eq!(app.get("/dashboard").await.status(), Status::Ok);
+    app.post_form("/admin/api_limits", "per_minute=&per_day=").await;
+    assert_eq!(app.get(uri).await.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn test_category_aliases() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=ALG&title=T").await;
+    app.post_form("/semesters/1/courses", "code=OPT&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_form("/courses/2/logs", "kind=Homework&title=HW1").await;
+    for sql in [
+        "INSERT INTO categories (course_id, name) VALUES (1, 'DP'), (2, '动态规划'), (1, 'proofs')",
+        "INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP), (2, 'p', 1, CURRENT_TIMESTAMP), (1, 'p', 1, CURRENT_TIMESTAMP)",
+        "INSERT INTO problem_categories (problem_id, category_id) VALUES (1, 1), (2, 2), (3, 3)",
+    ] {
+        sqlx::query(sql).execute(app.pool()).await.unwrap();
+    }
+    const TOPIC: &str = "%E5%8A%A8%E6%80%81%E8%A7%84%E5%88%92";
+    async fn remaining(app: &TestApp, topic: &str) -> i64 {
+        let body = app.get(&format!("/api/v1/study/session?topic={}", topic)).await.into_string().await.unwrap();
+        serde_json::from_str::<serde_json::Value>(&body).unwrap()["remaining"].as_i64().unwrap()
+    }
+
+    assert_eq!(remaining(&app, TOPIC).await, 1);
+    assert_eq!(remaining(&app, "dp").await, 1);
+
+    // Matched case-insensitively against category names in every course
+    let res = app.post_form("/study/aliases", &format!("alias=dp&topic={}", TOPIC)).await;
+    assert_eq!(res.status(), Status::SeeOther);
+    assert_eq!(remaining(&app, TOPIC).await, 2);
+    assert_eq!(remaining(&app, "DP").await, 0);
+
+    let page = app.get("/study/topics").await.into_string().await.unwrap();
+    assert!(page.contains("已将「dp」归入「动态规划」。"));
+    assert!(page.contains("2 题 · 跨课程"));
+    assert!(page.contains(&format!("/study/review?topic={}", TOPIC)));
+    let review = app.get(&format!("/study/review?topic={}", TOPIC)).await.into_string().await.unwrap();
+    assert!(review.contains(r#"data-topic="动态规划""#));
+
+    let body = format!(r#"{{"problem_id": "{}", "grade": 3, "topic": "动态规划"}}"#, app.public_id("problems", 1).await);
+    let result = app.client.post("/api/v1/study/answer").header(ContentType::JSON).body(body).dispatch().await.into_string().await.unwrap();
+    let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+    assert_eq!(result["session"]["remaining"], 1);
+    assert_eq!(result["session"]["card"]["public_id"], app.public_id("problems", 2).await.as_str());
+
+    // Re-pointing replaces; empty fields are refused
+    app.post_form("/study/aliases", "alias=DP&topic=dynamic").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM category_aliases").await, 1);
+    app.post_form("/study/aliases", "alias=&topic=x").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM category_aliases").await, 1);
+
+    app.delete(&format!("/study/aliases/{}", app.scalar("SELECT id FROM category_aliases").await)).await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM category_aliases").await, 0);
+    assert_eq!(remaining(&app, TOPIC).await, 1);
+}
+
+#[rocket::async_test]
+async fn test_study_time_per_category() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    for sql in [
+        "INSERT INTO categories (course_id, name) VALUES (1, 'proofs'), (1, 'limits')",
+        "INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP), (1, 'p', 1, CURRENT_TIMESTAMP), (1, 'p', 1, CURRENT_TIMESTAMP)",
+        "INSERT INTO problem_categories (problem_id, category_id) VALUES (1, 1), (2, 1), (3, 2)",
+    ] {
+        sqlx::query(sql).execute(app.pool()).await.unwrap();
+    }
+    async fn answer(app: &TestApp, problem: &str, grade: i64, time: &str) {
+        let body = format!(r#"{{"problem_id": "{}", "grade": {}{}}}"#, problem, grade, time);
+        let response = app.client.post("/api/v1/study/answer").header(ContentType::JSON).body(body).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+    }
+    let (p1, p2, p3) = (app.public_id("problems", 1).await, app.public_id("problems", 2).await, app.public_id("problems", 3).await);
+    answer(&app, &p1, 3, r#", "time_spent_ms": 120000"#).await;
+    answer(&app, &p2, 3, r#", "time_spent_ms": 90000"#).await;
+    // Left open for hours, and sent by an older page without a time
+    answer(&app, &p1, 2, r#", "time_spent_ms": 10000000"#).await;
+    answer(&app, &p2, 3, "").await;
+    for _ in 0..4 {
+        answer(&app, &p3, 3, r#", "time_spent_ms": 10000"#).await;
+    }
+    answer(&app, &p3, 1, r#", "time_spent_ms": 30000"#).await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM study_reviews WHERE time_spent_ms IS NULL").await, 2);
+
+    let body = app.get("/courses/1/study").await.into_string().await.unwrap();
+    // proofs: 105 s when correct, against a course average of about 42 s
+    assert!(body.contains(">1 分 45 秒 · 偏慢<"), "{}", body);
+    // limits: "again" counts toward the average but not the time when correct
+    assert!(body.contains(r#"title="5 次复习，平均 14 秒""#));
+    assert!(body.contains(">10 秒<"));
+    let position = |name: &str| body.find(&format!("truncate\">{}</span>", name)).expect("listed");
+    assert!(position("proofs") < position("limits"));
+}
+
+#[rocket::async_test]
+async fn test_problem_difficulty() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    for sql in [
+        "INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP), (1, 'p', 1, CURRENT_TIMESTAMP), (1, 'p', 1, CURRENT_TIMESTAMP)",
+        // 1 is always forgotten and slow, 2 always easy and quick, 3 seen once
+        "INSERT INTO study_reviews (user_id, problem_id, grade, reviewed_at, time_spent_ms) VALUES
+            (1, 1, 1, CURRENT_TIMESTAMP, 60000), (1, 1, 1, CURRENT_TIMESTAMP, 60000),
+            (1, 2, 4, CURRENT_TIMESTAMP, 10000), (1, 2, 4, CURRENT_TIMESTAMP, 10000),
+            (1, 3, 3, CURRENT_TIMESTAMP, 20000)",
+    ] {
+        sqlx::query(sql).execute(app.pool()).await.unwrap();
+    }
+
+    crate::difficulty::recalibrate(app.pool(), "").await.unwrap();
+    assert_eq!(app.scalar("SELECT difficulty FROM problems WHERE id = 1").await, 100);
+    assert_eq!(app.scalar("SELECT difficulty FROM problems WHERE id = 2").await, 0);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems WHERE difficulty IS NULL").await, 1);
+    // The next run waits for the night
+    assert!(app.scalar("SELECT COUNT(*) FROM jobs WHERE kind = 'recalibrate_difficulty' AND run_after > datetime('now')").await >= 1);
+
+    async fn list(app: &TestApp, query: &str) -> String {
+        app.get(&format!("/courses/1/study/problems?{}", query)).await.into_string().await.unwrap()
+    }
+    let page = list(&app, "sort=hardest").await;
+    let position = |id: i64| page.find(&format!("/problems/{}/view", id)).expect("listed");
+    assert!(position(1) < position(2) && position(2) < position(3));
+    assert!(page.contains(">困难</span>") && page.contains(">简单</span>"));
+
+    let page = list(&app, "difficulty=hard").await;
+    assert!(page.contains("/problems/1/view") && !page.contains("/problems/2/view") && !page.contains("/problems/3/view"));
+    let page = list(&app, "difficulty=unrated").await;
+    assert!(!page.contains("/problems/1/view") && page.contains("/problems/3/view"));
+    let page = list(&app, "difficulty=impossible").await;
+    assert!(page.contains("/problems/2/view"));
+}
+
+#[rocket::async_test]
+async fn test_study_goals() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    for sql in [
+        "INSERT INTO categories (course_id, name) VALUES (1, 'DP')",
+        "INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP)",
+        "INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP)",
+        "INSERT INTO problem_categories (problem_id, category_id) VALUES (1, 1)",
+    ] {
+        sqlx::query(sql).execute(app.pool()).await.unwrap();
+    }
+
+    assert_eq!(app.post_form("/courses/1/study/goals", "category_id=99&target=2").await.status(), Status::BadRequest);
+    assert_eq!(app.post_form("/courses/1/study/goals", "category_id=1&target=0").await.status(), Status::BadRequest);
+    let goal = app.post_form("/courses/1/study/goals", "category_id=1&target=2&deadline=").await.into_string().await.unwrap();
+    assert!(goal.contains("0 / 2"));
+
+    // Reviewing the same problem twice counts once; problem 2 is outside the category
+    let (p1, p2) = (app.public_id("problems", 1).await, app.public_id("problems", 2).await);
+    for (problem, grade) in [(&p1, 1), (&p1, 3), (&p2, 3)] {
+        let body = format!(r#"{{"problem_id": "{}", "grade": {}}}"#, problem, grade);
+        app.client.post("/api/v1/study/answer").header(ContentType::JSON).body(body).dispatch().await;
+    }
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM study_reviews").await, 3);
+    let page = app.get("/courses/1/study").await.into_string().await.unwrap();
+    assert!(page.contains("1 / 2") && page.contains("width: 50%"));
+
+    app.delete("/study/goals/1").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM study_goals").await, 0);
+}
+
+#[rocket::async_test]
+async fn test_exam_countdown() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=MATH1A&title=T").await;
+
+    let date = |days| (chrono::Local::now().date_naive() + chrono::Duration::days(days)).format("%Y-%m-%d").to_string();
+    app.post_form("/courses/1/exams", &format!("title=Midterm+2&exam_date={}", date(3))).await;
+    app.post_form("/courses/1/exams", &format!("title=Midterm+1&exam_date={}", date(-10))).await;
+    app.post_form("/courses/1/exams", "title=Old+Final&semester=2024&exam_date=").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM exams WHERE exam_date IS NULL").await, 1);
+
+    let dashboard = app.get("/dashboard").await.into_string().await.unwrap();
+    assert!(dashboard.contains("Midterm 2") && dashboard.contains(">3</div>"));
+    assert!(!dashboard.contains("Midterm 1") && !dashboard.contains("Old Final"));
+
+    // Study link narrows the course's study filters to the exam's categories
+    for sql in [
+        "INSERT INTO categories (course_id, name) VALUES (1, 'Limits'), (1, 'Series')",
+        "INSERT INTO problems (exam_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP)",
+        "INSERT INTO problem_categories (problem_id, category_id) VALUES (1, 2)",
+    ] {
+        sqlx::query(sql).execute(app.pool()).await.unwrap();
+    }
+    let response = app.get("/exams/1/study").await;
+    assert_eq!(response.headers().get_one("Location"), Some("/courses/1/study"));
+    let filters: String = sqlx::query_scalar("SELECT value FROM user_preferences WHERE key = 'study_filters:1'")
+        .fetch_one(app.pool())
+        .await
+        .unwrap();
+    assert!(filters.contains(r#""categories":[2]"#));
+    assert_eq!(app.get("/exams/99/study").await.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_exam_coverage() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=A&title=T").await;
+    app.post_form("/semesters/1/courses", "code=B&title=T").await;
+    sqlx::query("INSERT INTO categories (course_id, name) VALUES (1, 'Limits'), (1, 'Series'), (1, 'Vectors'), (2, 'Other course')")
+        .execute(app.pool())
+        .await
+        .unwrap();
+    app.post_form("/courses/1/exams", "title=Final").await;
+    for sql in [
+        "INSERT INTO problems (exam_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP)",
+        "INSERT INTO problem_categories (problem_id, category_id) VALUES (1, 1)",
+    ] {
+        sqlx::query(sql).execute(app.pool()).await.unwrap();
+    }
+
+    // Another course's category is dropped
+    let response = app.post_form("/exams/1/categories", "category=2&category=3&category=4").await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM exam_categories").await, 2);
+    assert!(app.get("/courses/1/exams").await.into_string().await.unwrap().contains("考试范围"));
+
+    // The mapping wins over the attached problem's category
+    app.get("/exams/1/study").await;
+    let filters: String = sqlx::query_scalar("SELECT value FROM user_preferences WHERE key = 'study_filters:1'")
+        .fetch_one(app.pool())
+        .await
+        .unwrap();
+    assert!(filters.contains(r#""categories":[2,3]"#));
+
+    app.post_form("/exams/1/categories", "").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM exam_categories").await, 0);
+    assert_eq!(app.post_form("/exams/99/categories", "category=1").await.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_exam_log_item_scope() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=A&title=T").await;
+    app.post_form("/semesters/1/courses", "code=B&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW2").await;
+    app.post_form("/courses/2/logs", "kind=Homework&title=Other").await;
+    app.post_form("/courses/1/exams", "title=Midterm").await;
+    sqlx::query("INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP), (2, 'p', 1, CURRENT_TIMESTAMP)")
+        .execute(app.pool())
+        .await
+        .unwrap();
+    assert!(!app.get("/courses/1/study").await.into_string().await.unwrap().contains(r#"name="exam""#));
+
+    // Another course's log item is dropped
+    let response = app.post_form("/exams/1/log-items", "log_item=1&log_item=3").await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM exam_log_items").await, 1);
+    assert!(app.get("/courses/1/exams").await.into_string().await.unwrap().contains("考试范围内的记录 (1)"));
+
+    let list = app.get("/courses/1/study/problems?exam=1").await.into_string().await.unwrap();
+    assert!(list.contains("/problems/1/view") && !list.contains("/problems/2/view"));
+    let list = app.get("/courses/1/study/problems?exam=").await.into_string().await.unwrap();
+    assert!(list.contains("/problems/1/view") && list.contains("/problems/2/view"));
+
+    // With a scope, the exam's study link filters by it
+    app.get("/exams/1/study").await;
+    let filters: String = sqlx::query_scalar("SELECT value FROM user_preferences WHERE key = 'study_filters:1'")
+        .fetch_one(app.pool())
+        .await
+        .unwrap();
+    assert!(filters.contains(r#""exam":1"#));
+    assert!(app.get("/courses/1/study").await.into_string().await.unwrap().contains(r#"<option value="1" selected>Midterm</option>"#));
+
+    app.delete("/logs/1").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM exam_log_items").await, 0);
+    assert_eq!(app.post_form("/exams/99/log-items", "log_item=2").await.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_exam_study_plan() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=A&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    let date = |days| (chrono::Local::now().date_naive() + chrono::Duration::days(days)).format("%Y-%m-%d").to_string();
+    app.post_form("/courses/1/exams", &format!("title=Final&exam_date={}", date(3))).await;
+    app.post_form("/courses/1/exams", "title=Undated").await;
+    for sql in [
+        "INSERT INTO categories (course_id, name) VALUES (1, 'Limits'), (1, 'Series')",
+        "INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP), (1, 'p', 1, CURRENT_TIMESTAMP), (1, 'p', 1, CURRENT_TIMESTAMP), (1, 'p', 1, CURRENT_TIMESTAMP), (1, 'p', 1, CURRENT_TIMESTAMP), (1, 'p', 1, CURRENT_TIMESTAMP), (1, 'p', 1, CURRENT_TIMESTAMP)",
+        "INSERT INTO problem_categories (problem_id, category_id) VALUES (1, 1), (2, 1), (3, 1), (4, 1), (5, 1), (6, 1), (7, 2)",
+    ] {
+        sqlx::query(sql).execute(app.pool()).await.unwrap();
+    }
+    // Problem 6 is already well learned: not due again until after the exam
+    sqlx::query("INSERT INTO study_cards (user_id, problem_id, interval_days, reviews, due_at) VALUES (1, 6, 30, 3, ?)")
+        .bind(format!("{} 00:00:00", date(30)))
+        .execute(app.pool())
+        .await
+        .unwrap();
+
+    // Five problems over three days: 2, 2, 1
+    let response = app.post_form("/exams/1/plan", "category=1").await;
+    assert_eq!(response.headers().get_one("Location"), Some("/exams/1/plan"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM study_plan_entries").await, 5);
+    let on_day = |problem_id: i64, days: i64| format!("SELECT COUNT(*) FROM study_plan_entries WHERE problem_id = {} AND day = '{}'", problem_id, date(days));
+    assert_eq!(app.scalar(&on_day(2, 0)).await, 1);
+    assert_eq!(app.scalar(&on_day(3, 1)).await, 1);
+    assert_eq!(app.scalar(&on_day(5, 2)).await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM study_cards WHERE problem_id != 6").await, 5);
+    assert!(app.get("/exams/1/plan").await.into_string().await.unwrap().contains("已完成 0 / 5"));
+
+    // A review after the plan was made ticks the problem off
+    sqlx::query("INSERT INTO study_reviews (user_id, problem_id, grade, reviewed_at) VALUES (1, 1, 3, CURRENT_TIMESTAMP)")
+        .execute(app.pool())
+        .await
+        .unwrap();
+    assert!(app.get("/exams/1/plan").await.into_string().await.unwrap().contains("已完成 1 / 5"));
+
+    // Pretend a day went by: the undone problem from yesterday moves to the
+    // lightest day left, and the done one stays put
+    sqlx::query("UPDATE study_plan_entries SET day = date(day, '-1 day')").execute(app.pool()).await.unwrap();
+    let page = app.get("/exams/1/plan").await.into_string().await.unwrap();
+    assert!(page.contains("有 1 道题没按计划完成"));
+    assert_eq!(app.scalar(&on_day(1, -1)).await, 1);
+    assert_eq!(app.scalar(&on_day(2, 2)).await, 1);
+    assert!(!app.get("/exams/1/plan").await.into_string().await.unwrap().contains("没按计划完成"));
+
+    // Regenerating replaces the plan
+    app.post_form("/exams/1/plan", "category=2").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM study_plans").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM study_plan_entries").await, 1);
+
+    app.post_form("/exams/2/plan", "").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM study_plans WHERE exam_id = 2").await, 0);
+    assert_eq!(app.get("/exams/99/plan").await.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_course_continuation() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=A1&title=T").await;
+    app.post_form("/semesters/1/courses", "code=A2&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_form("/courses/2/logs", "kind=Homework&title=HW1").await;
+    for sql in [
+        "INSERT INTO categories (course_id, name) VALUES (1, 'limits'), (1, 'series'), (2, 'limits')",
+        "INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP), (1, 'p', 1, CURRENT_TIMESTAMP), (2, 'p', 1, CURRENT_TIMESTAMP)",
+        "INSERT INTO problem_categories (problem_id, category_id) VALUES (1, 1), (2, 2), (3, 3)",
+    ] {
+        sqlx::query(sql).execute(app.pool()).await.unwrap();
+    }
+
+    // limits matches the existing category, series is created
+    let response = app.post_form("/courses/2/continues", "continues_course_id=1").await;
+    assert_eq!(response.headers().get_one("Location"), Some("/courses/2/settings"));
+    assert_eq!(app.scalar("SELECT continues_course_id FROM courses WHERE id = 2").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM category_carryovers WHERE previous_category_id = 1 AND category_id = 3").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM categories WHERE course_id = 2 AND name = 'series'").await, 1);
+    assert!(app.get("/courses/2/settings").await.into_string().await.unwrap().contains("name=\"carryover[2]\""));
+
+    // A course can't continue one that continues it
+    app.post_form("/courses/1/continues", "continues_course_id=2").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM courses WHERE id = 1 AND continues_course_id IS NULL").await, 1);
+
+    async fn list(app: &TestApp, query: &str) -> String {
+        app.get(&format!("/courses/2/study/problems?{}", query)).await.into_string().await.unwrap()
+    }
+    let page = list(&app, "").await;
+    assert!(page.contains("/problems/3/view") && !page.contains("/problems/1/view"));
+    assert!(app.get("/courses/2/study").await.into_string().await.unwrap().contains("包括前序课程 A1"));
+    let page = list(&app, "previous=on").await;
+    assert!(page.contains("/problems/1/view") && page.contains("/problems/2/view") && page.contains("/problems/3/view"));
+    let page = list(&app, "previous=on&category=3").await;
+    assert!(page.contains("/problems/1/view") && !page.contains("/problems/2/view"));
+
+    // A review of last semester's limits problem counts toward this one's goal
+    app.post_form("/courses/2/study/goals", "category_id=3&target=1").await;
+    let body = format!(r#"{{"problem_id": "{}", "grade": 3}}"#, app.public_id("problems", 1).await);
+    app.client.post("/api/v1/study/answer").header(ContentType::JSON).body(body).dispatch().await;
+    assert!(app.get("/courses/2/study").await.into_string().await.unwrap().contains("1 / 1"));
+
+    app.post_form("/courses/2/carryovers", "carryover[2]=").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM category_carryovers").await, 1);
+    app.post_form("/courses/2/continues", "continues_course_id=").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM category_carryovers").await, 0);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM courses WHERE continues_course_id IS NOT NULL").await, 0);
+}
+
+#[rocket::async_test]
+async fn test_api_cors() {
+    let app = TestApp::configured(|figment| {
+        figment
+            .merge(("cors.allowed_origins", ["https://tool.example.com"]))
+            .merge(("cors.allow_credentials", true))
+    })
+    .await;
+
+    let get = |uri: &'static str, origin: &'static str| app.client.get(uri).header(Header::new("Origin", origin)).dispatch();
+
+    let response = get("/api/v1/study/session", "https://tool.example.com").await;
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), Some("https://tool.example.com"));
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Credentials"), Some("true"));
+
+    let response = get("/api/v1/study/session", "https://evil.example.com").await;
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), None);
+
+    // The HTML app never opts in to cross-origin requests
+    let response = get("/login", "https://tool.example.com").await;
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), None);
+
+    let response = app
+        .client
+        .options("/api/v1/study/answer")
+        .header(Header::new("Origin", "https://tool.example.com"))
+        .header(Header::new("Access-Control-Request-Method", "POST"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Methods"), Some("GET, POST"));
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Headers"), Some("Content-Type"));
+
+    // Off by default
+    let app = TestApp::new().await;
+    let response = app.client.get("/api/v1/study/session").header(Header::new("Origin", "https://tool.example.com")).dispatch().await;
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), None);
+}
+
+#[rocket::async_test]
+async fn test_upload_access() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_multipart("/logs/1/problems", &[("notes", "n")], ("screenshot", b"pixels")).await;
+
+    let image_url: String = sqlx::query_scalar("SELECT image_url FROM problems WHERE id = 1")
+        .fetch_one(app.pool())
+        .await
+        .unwrap();
+    let response = app.get(&image_url).await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_bytes().await.unwrap(), b"pixels");
+
+    // A leaked URL alone is not enough, signed in to another account or not...
+    app.post_form("/logout", "").await;
+    assert_eq!(app.get(&image_url).await.status(), Status::NotFound);
+    sqlx::query("INSERT INTO users (username, password_hash, is_admin) VALUES ('bob', ?, 0)")
+        .bind(bcrypt::hash("pw", 4).unwrap())
+        .execute(app.pool())
+        .await
+        .unwrap();
+    app.post_form("/login", "username=bob&password=pw").await;
+    assert_eq!(app.get(&image_url).await.status(), Status::NotFound);
+
+    // ...unless the course is published and shows the problem anyway
+    sqlx::query("UPDATE courses SET is_published = 1, public_slug = 'c' WHERE id = 1").execute(app.pool()).await.unwrap();
+    assert_eq!(app.get(&image_url).await.status(), Status::Ok);
+    app.post_form("/logout", "").await;
+    assert_eq!(app.get(&image_url).await.status(), Status::Ok);
+
+    assert_eq!(app.get("/uploads/.chunks").await.status(), Status::NotFound);
+    assert_eq!(app.get("/uploads/../Cargo.toml").await.status(), Status::NotFound);
+
+    let _ = std::fs::remove_file(image_url.trim_start_matches('/'));
+}
+
+#[rocket::async_test]
+async fn test_upload_type_from_contents() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+
+    // A page claiming to be HTML is only ever downloaded
+    let html = format!("<script>alert('{}')</script>", Uuid::new_v4());
+    app.post_file("/logs/1/problems", &[], "screenshot", ("x.html", "text/html"), html.as_bytes()).await;
+    // and a PNG is shown as a PNG, whatever it was sent as
+    let png = [&b"\x89PNG\r\n\x1a\n"[..], Uuid::new_v4().as_bytes()].concat();
+    app.post_file("/logs/1/problems", &[], "screenshot", ("y.html", "text/html"), &png).await;
+    let urls: Vec<String> = sqlx::query_scalar("SELECT image_url FROM problems ORDER BY id").fetch_all(app.pool()).await.unwrap();
+    assert!(urls[1].ends_with(".png"));
+
+    let response = app.get(&urls[0]).await;
+    assert!(response.headers().get_one("Content-Disposition").unwrap().starts_with("attachment"));
+    assert_eq!(response.headers().get_one("X-Content-Type-Options"), Some("nosniff"));
+    assert!(response.headers().get_one("Content-Security-Policy").unwrap().contains("sandbox"));
+    let response = app.get(&urls[1]).await;
+    assert_eq!(response.content_type(), Some(ContentType::PNG));
+    assert!(response.headers().get_one("Content-Disposition").unwrap().starts_with("inline"));
+    assert_eq!(response.headers().get_one("X-Content-Type-Options"), Some("nosniff"));
+
+    app.delete("/problems/1").await;
+    app.delete("/problems/2").await;
+}
+
+#[rocket::async_test]
+async fn test_upload_serving_stays_inside_upload_dir() {
+    let app = TestApp::authenticated().await;
+    std::fs::create_dir_all("uploads").unwrap();
+    let link = format!("uploads/{}.png", Uuid::new_v4());
+    std::os::unix::fs::symlink(std::fs::canonicalize("Cargo.toml").unwrap(), &link).unwrap();
+
+    assert_eq!(app.get(&format!("/{}", link)).await.status(), Status::NotFound);
+    assert_eq!(app.get("/uploads/%2E%2E/Cargo.toml").await.status(), Status::NotFound);
+    assert_eq!(app.get("/uploads/..%2FCargo.toml").await.status(), Status::NotFound);
+    assert_eq!(app.get("/uploads/%2Fetc%2Fpasswd").await.status(), Status::NotFound);
+
+    let _ = std::fs::remove_file(&link);
+}
+
+#[rocket::async_test]
+async fn test_shard_uploads() {
+    let app = TestApp::authenticated().await;
+    let root = std::env::temp_dir().join(format!("zhixi-uploads-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("0a1b-old.png"), b"pixels").unwrap();
+    std::fs::write(root.join("x"), b"too short to shard").unwrap();
+    sqlx::query("INSERT OR REPLACE INTO instance_settings (key, value) VALUES ('upload_dir', ?)")
+        .bind(root.to_string_lossy().to_string())
+        .execute(app.pool())
+        .await
+        .unwrap();
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    sqlx::query("INSERT INTO problems (log_item_id, description, image_url) VALUES (1, 'p', '/uploads/0a1b-old.png')").execute(app.pool()).await.unwrap();
+    sqlx::query("INSERT INTO entity_history (entity_type, entity_id, course_id, action, before_json) VALUES ('problem', 1, 1, 'delete', '{\"image_url\":\"/uploads/0a1b-old.png\"}')")
+        .execute(app.pool())
+        .await
+        .unwrap();
+
+    let mut conn = app.pool().acquire().await.unwrap();
+    let report = crate::uploads::shard_existing(&mut conn).await.unwrap();
+    assert_eq!(report, crate::uploads::ShardReport { moved: 1, skipped: 0 });
+    assert!(root.join("0a/1b-old.png").exists() && !root.join("0a1b-old.png").exists() && root.join("x").exists());
+    let image_url: String = sqlx::query_scalar("SELECT image_url FROM problems WHERE id = 1").fetch_one(app.pool()).await.unwrap();
+    assert_eq!(image_url, "/uploads/0a/1b-old.png");
+    let history: String = sqlx::query_scalar("SELECT before_json FROM entity_history").fetch_one(app.pool()).await.unwrap();
+    assert!(history.contains("/uploads/0a/1b-old.png"));
+
+    // Old links find the file in its new place, and a second run has nothing to do
+    assert_eq!(app.get("/uploads/0a1b-old.png").await.into_bytes().await.unwrap(), b"pixels");
+    assert_eq!(crate::uploads::shard_existing(&mut conn).await.unwrap(), crate::uploads::ShardReport::default());
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[rocket::async_test]
+async fn test_upload_dedup() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+
+    // Unique bytes per run so parallel tests sharing uploads/ never touch the same file
+    let bytes = format!("screenshot {}", Uuid::new_v4());
+    for _ in 0..2 {
+        app.post_multipart("/logs/1/problems", &[("notes", "n")], ("screenshot", bytes.as_bytes())).await;
+    }
+
+    let urls: Vec<String> = sqlx::query_scalar("SELECT image_url FROM problems ORDER BY id")
+        .fetch_all(app.pool())
+        .await
+        .unwrap();
+    assert_eq!(urls[0], urls[1]);
+    let path = urls[0].trim_start_matches('/').to_string();
+    assert!(path.starts_with("uploads/") && path.matches('/').count() == 2);
+    assert_eq!(app.scalar("SELECT ref_count FROM uploads").await, 2);
+
+    app.delete("/problems/1").await;
+    assert!(std::path::Path::new(&path).exists());
+    assert_eq!(app.scalar("SELECT ref_count FROM uploads").await, 1);
+
+    app.delete("/logs/1").await;
+    assert!(!std::path::Path::new(&path).exists());
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM uploads").await, 0);
+}
+
+#[rocket::async_test]
+async fn test_upload_variants() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    let bytes = format!("screenshot {}", Uuid::new_v4());
+    app.post_multipart("/logs/1/problems", &[], ("screenshot", bytes.as_bytes())).await;
+
+    let url: String = sqlx::query_scalar("SELECT image_url FROM problems WHERE id = 1")
+        .fetch_one(app.pool())
+        .await
+        .unwrap();
+    let (shard, name) = url.trim_start_matches("/uploads/").split_once('/').unwrap();
+    let stem = name.trim_end_matches(".png");
+
+    // A cached variant is served as is; it isn't reachable by its own path
+    let cached = std::path::PathBuf::from(format!("uploads/.variants/{}/{}-200x0.png", shard, stem));
+    std::fs::create_dir_all(cached.parent().unwrap()).unwrap();
+    std::fs::write(&cached, "small").unwrap();
+    assert_eq!(app.get(&format!("{}?w=200", url)).await.into_string().await.unwrap(), "small");
+    assert_eq!(app.get(&format!("/uploads/.variants/{}/{}-200x0.png", shard, stem)).await.status(), Status::NotFound);
+
+    // Not a real image, so ImageMagick (if installed) fails and the original comes back
+    assert_eq!(app.get(&format!("{}?w=300&h=300&crop", url)).await.into_string().await.unwrap(), bytes);
+
+    app.delete("/problems/1").await;
+    assert!(!cached.exists());
+}
+
+#[rocket::async_test]
+async fn test_split_screenshot() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW3").await;
+
+    let html = app.post_multipart("/logs/1/problems/split", &[("regions", "0,0,0,10")], ("screenshot", b"not an image")).await.into_string().await.unwrap();
+    assert!(html.contains("请在截图上框出 1 到 20 道题。"));
+    let html = app
+        .post_file("/logs/1/problems/split", &[("regions", "0,0,10,10")], "screenshot", ("讲义.pdf", "application/pdf"), b"%PDF-1.4")
+        .await
+        .into_string()
+        .await
+        .unwrap();
+    assert!(html.contains("只能拆分图片"));
+    // Not a real image, so ImageMagick (if installed) can't cut it
+    let html = app.post_multipart("/logs/1/problems/split", &[("regions", "0,0,10,10")], ("screenshot", b"not an image")).await.into_string().await.unwrap();
+    assert!(html.contains("无法裁剪这张图片。"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 0);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM uploads").await, 0);
+
+    // The rest needs ImageMagick to make and cut a real screenshot
+    let shot = std::env::temp_dir().join(format!("zhixi-split-{}.png", Uuid::new_v4()));
+    let made = std::process::Command::new("convert")
+        .args(["-size", "120x80", "gradient:white-black"])
+        .arg(&shot)
+        .status();
+    if !matches!(made, Ok(status) if status.success()) {
+        return;
+    }
+    let bytes = std::fs::read(&shot).unwrap();
+    let _ = std::fs::remove_file(&shot);
+    app.post_multipart("/logs/1/problems/split", &[("regions", "0,0,120,40"), ("regions", "0,40,120,40"), ("categories", "极限")], ("screenshot", &bytes)).await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems WHERE log_item_id = 1").await, 2);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problem_categories").await, 2);
+    let names: Vec<String> = sqlx::query_scalar("SELECT original_filename FROM problems ORDER BY id").fetch_all(app.pool()).await.unwrap();
+    assert_eq!(names, ["shot-1.png", "shot-2.png"]);
+    // Different halves of a gradient, so two separate files
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM uploads").await, 2);
+
+    let urls: Vec<String> = sqlx::query_scalar("SELECT image_url FROM problems").fetch_all(app.pool()).await.unwrap();
+    for url in urls {
+        let _ = std::fs::remove_file(url.trim_start_matches('/'));
+    }
+}
+
+#[rocket::async_test]
+async fn test_upload_original_filename() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Lecture&title=L3").await;
+
+    let bytes = format!("%PDF-1.4 {}", Uuid::new_v4());
+    let response = app
+        .post_file("/logs/1/problems", &[("notes", "n")], "screenshot", ("第三章讲义.pdf", "application/pdf"), bytes.as_bytes())
+        .await;
+    let row = response.into_string().await.unwrap();
+    assert!(row.contains("第三章讲义.pdf"));
+    assert!(!row.contains("<img"));
+
+    let (image_url, original_filename, mime_type): (String, String, String) =
+        sqlx::query_as("SELECT image_url, original_filename, mime_type FROM problems WHERE id = 1")
+            .fetch_one(app.pool())
+            .await
+            .unwrap();
+    assert!(image_url.ends_with(".pdf"));
+    assert_eq!(original_filename, "第三章讲义.pdf");
+    assert_eq!(mime_type, "application/pdf");
+
+    let response = app.get(&image_url).await;
+    assert_eq!(response.content_type(), Some(ContentType::PDF));
+    assert!(response.headers().get_one("Content-Disposition").unwrap().starts_with("inline; "));
+
+    let response = app.get(&format!("{}?download", image_url)).await;
+    assert_eq!(
+        response.headers().get_one("Content-Disposition"),
+        Some("attachment; filename=\"_____.pdf\"; filename*=UTF-8''%E7%AC%AC%E4%B8%89%E7%AB%A0%E8%AE%B2%E4%B9%89.pdf")
+    );
+
+    app.delete("/problems/1").await;
+}
+
+#[rocket::async_test]
+async fn test_link_previews() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+
+    // Already fetched; the other URL points nowhere so its job can never succeed
+    sqlx::query("INSERT INTO link_previews (url, domain, title, favicon_url, status) VALUES ('https://ocw.example.edu/18-01', 'ocw.example.edu', 'Calculus Notes', 'https://ocw.example.edu/favicon.ico', 'ok')")
+        .execute(app.pool())
+        .await
+        .unwrap();
+    let card = app
+        .post_form("/courses/1/logs", "kind=Lecture&title=L1&description=See+https://ocw.example.edu/18-01+and+http://127.0.0.1:9/slides.")
+        .await
+        .into_string()
+        .await
+        .unwrap();
+    assert!(card.contains("Calculus Notes"));
+
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM link_previews").await, 2);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM jobs WHERE kind = 'link_preview' AND payload = 'http://127.0.0.1:9/slides'").await, 1);
+
+    // Editing in a new URL queues it too; unchanged ones aren't fetched again
+    app.post_form("/logs/1", "kind=Lecture&title=L1&description=https://ocw.example.edu/18-01+https://ocw.example.edu/18-02").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM jobs WHERE kind = 'link_preview'").await, 2);
+
+    let page = app.get("/courses/1").await.into_string().await.unwrap();
+    assert!(page.contains("Calculus Notes") && page.contains("ocw.example.edu/favicon.ico"));
+
+    // Only public hosts are fetched, unless `[default.outbound] allow_hosts` lists one
+    let url = serve_file("lecture", "text/html", b"<title>Lecture 4</title>").await;
+    sqlx::query("INSERT INTO link_previews (url, domain) VALUES (?, '127.0.0.1')")
+        .bind(&url)
+        .execute(app.pool())
+        .await
+        .unwrap();
+    let client = crate::outbound::Client::new(&Default::default()).unwrap();
+    assert!(crate::link_preview::fetch(app.pool(), &client, &url).await.is_err());
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM link_previews WHERE domain = '127.0.0.1' AND status = 'failed'").await, 1);
+
+    let config = crate::config::OutboundConfig { allow_hosts: vec!["127.0.0.1".to_string()] };
+    let client = crate::outbound::Client::new(&config).unwrap();
+    crate::link_preview::fetch(app.pool(), &client, &url).await.unwrap();
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM link_previews WHERE title = 'Lecture 4'").await, 1);
+}
+
+#[rocket::async_test]
+async fn test_translation_cache_pruning() {
+    let app = TestApp::authenticated().await;
+    for (text, last_used) in [("极限", "-400 days"), ("导数", "-30 days"), ("积分", "-2 days"), ("级数", "-1 hours")] {
+        sqlx::query("INSERT INTO translations (source_text, translated_text, last_used_at) VALUES (?, ?, datetime('now', ?))")
+            .bind(text)
+            .bind(format!("en:{}", text))
+            .bind(last_used)
+            .execute(app.pool())
+            .await
+            .unwrap();
+    }
+
+    crate::translate::prune(app.pool(), r#"{"max_age_days": 180, "max_entries": 2}"#).await.unwrap();
+    let kept: Vec<String> = sqlx::query_scalar("SELECT source_text FROM translations ORDER BY id")
+        .fetch_all(app.pool())
+        .await
+        .unwrap();
+    assert_eq!(kept, ["积分", "级数"]);
+    // Each run queues the next one a day out
+    assert!(app.scalar("SELECT COUNT(*) FROM jobs WHERE kind = 'prune_translations' AND run_after > datetime('now', '+23 hours')").await >= 1);
+
+    let page = app.get("/dashboard").await.into_string().await.unwrap();
+    assert!(page.contains("翻译缓存：2 条"));
+}
+
+#[rocket::async_test]
+async fn test_course_translation_context() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=MATH+104&title=实分析").await;
+
+    let mut conn = app.pool().acquire().await.unwrap();
+    let course = crate::queries::fetch_course(&mut conn, 1).await.unwrap();
+    assert_eq!(course.translation_context(), "MATH 104 实分析, undergraduate level; use formal academic English with standard textbook terminology");
+
+    app.post_form("/courses/1/translation_context", "subject=real+analysis&level=graduate&style=concise").await;
+    let course = crate::queries::fetch_course(&mut conn, 1).await.unwrap();
+    assert_eq!(course.translation_context(), "real analysis, graduate level; use short, plain English suitable for headings");
+
+    // Unknown options leave the settings alone
+    app.post_form("/courses/1/translation_context", "subject=&level=phd&style=concise").await;
+    assert_eq!(crate::queries::fetch_course(&mut conn, 1).await.unwrap().translation_level, "graduate");
+}
+
+#[rocket::async_test]
+async fn test_missing_translations_filled_after_load() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Lecture&title=第一讲&description=极限的定义").await;
+    let public_id = app.public_id("courses", 1).await;
+    let missing_url = format!("/courses/{}/translate/missing", public_id);
+
+    // Unpublished courses don't take part
+    assert_eq!(app.client.post(missing_url.clone()).dispatch().await.status(), Status::NotFound);
+
+    sqlx::query("UPDATE courses SET is_published = 1, public_slug = 'c' WHERE id = 1").execute(app.pool()).await.unwrap();
+    sqlx::query("INSERT INTO instance_settings (key, value) VALUES ('translation_api_key', 'test-key')").execute(app.pool()).await.unwrap();
+
+    // Rendered straight away, with the description marked for filling in
+    let key = crate::translate::text_key("极限的定义");
+    let page = app.get("/p/c").await.into_string().await.unwrap();
+    assert!(page.contains(&format!("data-untranslated=\"{}\">极限的定义", key)));
+    assert!(page.contains(&format!("hx-post=\"{}\"", missing_url)));
+
+    sqlx::query("INSERT INTO translations (source_text, translated_text) VALUES ('极限的定义', 'Definition of a limit')").execute(app.pool()).await.unwrap();
+    let poller = app.client.post(missing_url.clone()).dispatch().await.into_string().await.unwrap();
+    assert!(poller.contains(&format!("hx-get=\"{}\"", missing_url)));
+
+    // Once the queued job is gone the poll hands back the translation and stops
+    sqlx::query("DELETE FROM jobs WHERE kind = 'translate_missing'").execute(app.pool()).await.unwrap();
+    let response = app.get(&missing_url).await;
+    assert_eq!(response.status().code, 286);
+    let fills = response.into_string().await.unwrap();
+    assert!(fills.contains(&format!("innerHTML:[data-untranslated='{}']\">Definition of a limit", key)));
+
+    let page = app.get("/p/c").await.into_string().await.unwrap();
+    assert!(page.contains("Definition of a limit") && !page.contains("data-untranslated") && !page.contains("hx-post"));
+}
+
+#[rocket::async_test]
+async fn test_public_page_cache() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Lecture&title=L1&date=2026-09-01").await;
+    sqlx::query("UPDATE courses SET is_published = 1, public_slug = 'c' WHERE id = 1").execute(app.pool()).await.unwrap();
+    // Pages of a course changed within the current second aren't cached
+    sqlx::query("UPDATE courses SET updated_at = '2026-01-01 00:00:00'").execute(app.pool()).await.unwrap();
+    sqlx::query("UPDATE log_items SET updated_at = '2025-12-01 00:00:00'").execute(app.pool()).await.unwrap();
+
+    let response = app.get("/p/c").await;
+    assert_eq!(response.headers().get_one("Cache-Control"), Some("public, max-age=60"));
+    assert!(response.into_string().await.unwrap().contains("L1"));
+
+    // A write that doesn't move the course's Last-Modified goes unnoticed
+    // until the ttl runs out
+    sqlx::query("UPDATE log_items SET title = 'Renamed', updated_at = '2025-12-02 00:00:00' WHERE id = 1").execute(app.pool()).await.unwrap();
+    assert!(app.get("/p/c").await.into_string().await.unwrap().contains("L1"));
+    // So do edits to other courses
+    app.post_form("/semesters/1/courses", "code=D&title=U").await;
+    app.post_form("/courses/2/logs", "kind=Lecture&title=D1&date=2026-09-01").await;
+    assert!(app.get("/p/c").await.into_string().await.unwrap().contains("L1"));
+
+    // Any other write counts, whoever made it
+    sqlx::query("UPDATE log_items SET title = 'Retitled' WHERE id = 1").execute(app.pool()).await.unwrap();
+    assert!(app.get("/p/c").await.into_string().await.unwrap().contains("Retitled"));
+    app.post_form("/courses/1/logs", "kind=Lecture&title=L2&date=2026-09-08").await;
+    assert!(app.get("/p/c").await.into_string().await.unwrap().contains("L2"));
+
+    let app = TestApp::configured(|figment| figment.merge(("public_cache.ttl_secs", 0))).await.signed_in().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    sqlx::query("UPDATE courses SET is_published = 1, public_slug = 'c' WHERE id = 1").execute(app.pool()).await.unwrap();
+    assert_eq!(app.get("/p/c").await.headers().get_one("Cache-Control"), Some("no-cache"));
+}
+
+#[rocket::async_test]
+async fn test_content_reports() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_file("/logs/1/problems", &[("body", "第三章习题")], "screenshot", ("", "application/octet-stream"), b"").await;
+    app.post_form("/courses/1/settings", "is_published=on&public_slug=c").await;
+    let public_id = app.public_id("problems", 1).await;
+    let report = format!("/p/c/problems/{}/report", public_id);
+
+    assert!(app.get("/p/c/problems").await.into_string().await.unwrap().contains(&format!("{}?lang=en", report)));
+    assert!(app.get(&format!("{}?lang=zh", report)).await.into_string().await.unwrap().contains("侵犯版权"));
+    assert_eq!(app.post_form(&report, "lang=en&reason=spam").await.status(), Status::BadRequest);
+    assert_eq!(app.post_form("/p/c/problems/nope/report", "lang=en&reason=other").await.status(), Status::NotFound);
+
+    let res = app.post_form(&report, "lang=zh&reason=copyright&details=%20%E6%95%99%E6%9D%90%E6%89%AB%E6%8F%8F%20").await;
+    assert!(res.into_string().await.unwrap().contains("已收到举报"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM content_reports WHERE details = '教材扫描'").await, 1);
+    // One problem can't collect more than MAX_OPEN_REPORTS open reports
+    for _ in 0..12 {
+        app.post_form(&report, "lang=en&reason=other").await;
+    }
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM content_reports").await, 10);
+
+    let page = app.get("/admin/reports").await.into_string().await.unwrap();
+    assert!(page.contains("C · P-001：侵犯版权") && page.contains("教材扫描") && page.contains("第三章习题"));
+    let res = app.post_form("/admin/reports/2/dismiss", "").await;
+    assert_eq!(res.headers().get_one("Location"), Some("/admin/reports"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM content_reports WHERE status = 'dismissed' AND resolved_by = 1").await, 1);
+
+    // Taking the course down closes the rest and the cached page with it
+    assert_eq!(app.get("/p/c/problems").await.status(), Status::Ok);
+    let res = app.post_form("/admin/reports/1/unpublish", "").await;
+    assert_eq!(res.headers().get_one("Location"), Some("/admin/reports"));
+    assert_eq!(app.scalar("SELECT is_published FROM courses WHERE id = 1").await, 0);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM content_reports WHERE status = 'unpublished'").await, 9);
+    assert_eq!(app.get("/p/c/problems").await.status(), Status::NotFound);
+    assert_eq!(app.post_form(&report, "lang=en&reason=other").await.status(), Status::NotFound);
+    let page = app.get("/admin/reports").await.into_string().await.unwrap();
+    assert!(page.contains("课程已下架，关闭了 9 条举报。") && page.contains("没有待处理的举报"));
+
+    sqlx::query("UPDATE users SET is_admin = 0").execute(app.pool()).await.unwrap();
+    assert_eq!(app.get("/admin/reports").await.status(), Status::Forbidden);
+    assert_eq!(app.post_form("/admin/reports/1/dismiss", "").await.status(), Status::Forbidden);
+}
+
+#[rocket::async_test]
+async fn test_public_page_views() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/semesters/1/courses", "code=D&title=U").await;
+    app.post_form("/courses/1/settings", "is_published=on&public_slug=c").await;
+    app.post_form("/courses/2/settings", "is_published=on&public_slug=d").await;
+
+    app.get("/p/c").await;
+    app.get("/p/c/problems").await;
+    app.get("/p/c/zh").await;
+    app.client.get("/p/c").header(Header::new("X-Real-IP", "203.0.113.7")).dispatch().await;
+    app.get("/p/d").await;
+    // Feeds, HEAD requests and missing pages aren't views
+    app.get("/p/c/problems/feed.xml").await;
+    app.client.head("/p/c").dispatch().await;
+    app.get("/p/nope").await;
+
+    let page = app.get("/courses/1/settings").await.into_string().await.unwrap();
+    assert!(page.contains("近 7 天浏览 4 次，约 2 人次访问"));
+    assert_eq!(app.scalar("SELECT views FROM course_views WHERE course_id = 2").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM course_views").await, 2);
+}
+
+#[rocket::async_test]
+async fn test_public_conditional_requests() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    sqlx::query("UPDATE courses SET is_published = 1, public_slug = 'c', updated_at = '2026-01-01 00:00:00' WHERE id = 1").execute(app.pool()).await.unwrap();
+
+    let head = app.client.head("/p/c").dispatch().await;
+    assert_eq!(head.status(), Status::Ok);
+    assert_eq!(head.headers().get_one("Last-Modified"), Some("Thu, 01 Jan 2026 00:00:00 GMT"));
+    assert!(head.into_string().await.unwrap_or_default().is_empty());
+
+    let since = Header::new("If-Modified-Since", "Thu, 01 Jan 2026 00:00:00 GMT");
+    for uri in ["/p/c", "/p/c/problems/feed.xml"] {
+        let response = app.client.get(uri).header(since.clone()).dispatch().await;
+        assert_eq!(response.status(), Status::NotModified);
+        assert_eq!(response.headers().get_one("Last-Modified"), Some("Thu, 01 Jan 2026 00:00:00 GMT"));
+    }
+
+    // A change makes earlier copies stale. Until its second is over there's
+    // no Last-Modified to give out, since another change could follow in it.
+    app.post_form("/courses/1/logs", "kind=Lecture&title=L1&date=2026-09-01").await;
+    let response = app.client.get("/p/c").header(since.clone()).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Last-Modified"), None);
+    assert!(response.into_string().await.unwrap().contains("L1"));
+    let response = app.client.get("/p/c/problems/feed.xml").header(since.clone()).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // Each course has its own
+    sqlx::query("UPDATE log_items SET updated_at = '2026-02-01 12:00:00'").execute(app.pool()).await.unwrap();
+    app.post_form("/semesters/1/courses", "code=D&title=U").await;
+    sqlx::query("UPDATE courses SET is_published = 1, public_slug = 'd', updated_at = '2026-01-01 00:00:00' WHERE id = 2").execute(app.pool()).await.unwrap();
+    let response = app.client.get("/p/c").dispatch().await;
+    assert_eq!(response.headers().get_one("Last-Modified"), Some("Sun, 01 Feb 2026 12:00:00 GMT"));
+    assert_eq!(app.client.get("/p/d").header(since).dispatch().await.status(), Status::NotModified);
+}
+
+#[rocket::async_test]
+async fn test_course_webhook() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Lecture&title=Before&date=2026-09-01").await;
+    app.post_form("/courses/1/settings", "is_published=on&public_slug=c").await;
+
+    app.post_form("/courses/1/webhook", "webhook_url=discord.com%2Fapi%2Fwebhooks").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM courses WHERE webhook_url IS NOT NULL").await, 0);
+
+    let (url, posts) = capture_posts(2).await;
+    app.post_form("/courses/1/webhook", &format!("webhook_url={}", url)).await;
+    let sweep = r#"{"base_url":"https://zhixi.example.com"}"#;
+
+    // The server's own network is off limits, as for any other outbound request
+    let client = crate::outbound::Client::new(&Default::default()).unwrap();
+    crate::webhook::sweep(app.pool(), &client, sweep).await.unwrap();
+    assert_eq!(app.scalar("SELECT webhook_announced FROM courses").await, 0);
+
+    let config = crate::config::OutboundConfig { allow_hosts: vec!["127.0.0.1".to_string()] };
+    let client = crate::outbound::Client::new(&config).unwrap();
+    crate::webhook::sweep(app.pool(), &client, sweep).await.unwrap();
+
+    // Later content is gathered into one message; what came before isn't news
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1&date=2026-09-08").await;
+    app.post_file("/logs/2/problems", &[("body", "2+2")], "screenshot", ("", "application/octet-stream"), b"").await;
+    crate::webhook::sweep(app.pool(), &client, sweep).await.unwrap();
+
+    let posts = posts.await.unwrap();
+    assert!(posts[0].contains("C T 的公开页面已上线：https://zhixi.example.com/p/c"));
+    assert!(posts[1].contains("C 新增 1 条记录、1 道错题：https://zhixi.example.com/p/c") && posts[1].contains("HW1"));
+    assert!(!posts[1].contains("Before"));
+    assert_eq!(app.scalar("SELECT webhook_log_item_id FROM courses").await, 2);
+    assert!(app.scalar("SELECT COUNT(*) FROM jobs WHERE kind = 'course_webhooks'").await >= 1);
+
+    // Unpublishing and publishing again announces it again
+    app.post_form("/courses/1/settings", "public_slug=c").await;
+    crate::webhook::sweep(app.pool(), &client, sweep).await.unwrap();
+    assert_eq!(app.scalar("SELECT webhook_announced FROM courses").await, 0);
+}
+
+#[rocket::async_test]
+async fn test_sitemap_and_noindex() {
+    let app = TestApp::configured(|figment| figment.merge(("public_url", "https://zhixi.example.com"))).await.signed_in().await;
+    app.post_form("/semesters", "name=S").await;
+    for code in ["A", "B", "C"] {
+        app.post_form("/semesters/1/courses", &format!("code={}&title=T", code)).await;
+    }
+    app.post_form("/courses/1/settings", "is_published=on&public_slug=math-1a").await;
+    app.post_form("/courses/2/settings", "is_published=on&public_slug=phys-7a&discourage_indexing=on").await;
+
+    let sitemap = app.get("/sitemap.xml").await.into_string().await.unwrap();
+    assert!(sitemap.contains("<loc>https://zhixi.example.com/p/math-1a</loc>"));
+    assert!(sitemap.contains("<loc>https://zhixi.example.com/p/math-1a/zh/problems</loc>"));
+    assert!(!sitemap.contains("phys-7a"));
+    assert_eq!(sitemap.matches("<url>").count(), 4);
+
+    assert!(!app.get("/p/math-1a").await.into_string().await.unwrap().contains("noindex"));
+    assert!(app.get("/p/phys-7a").await.into_string().await.unwrap().contains("<meta name=\"robots\" content=\"noindex, nofollow\">"));
+
+    let robots = app.get("/robots.txt").await.into_string().await.unwrap();
+    assert!(robots.contains("Sitemap: https://zhixi.example.com/sitemap.xml"));
+}
+
+#[rocket::async_test]
+async fn test_semester_wrapped() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters", "name=T").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/semesters/2/courses", "code=D&title=T").await;
+    for sql in [
+        "INSERT INTO log_items (course_id, kind, title, date) VALUES (1, 'Homework', 'HW1', '2026-10-05'), (1, 'Homework', 'HW2', '2026-10-06'), (2, 'Homework', 'HW1', '2026-10-07')",
+        "INSERT INTO categories (course_id, name) VALUES (1, '极限'), (1, '积分')",
+        "INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, '2026-10-07 09:00:00'), (2, 'q', 1, '2026-10-07 10:00:00'), (3, 'r', 1, '2026-10-07 11:00:00')",
+        "INSERT INTO problem_categories (problem_id, category_id) VALUES (1, 1), (2, 2)",
+        "INSERT INTO study_reviews (user_id, problem_id, grade, reviewed_at) VALUES (1, 1, 3, '2026-10-14 20:00:00'), (1, 1, 3, '2026-10-15 20:00:00'), (1, 2, 3, '2026-10-15 21:00:00')",
+    ] {
+        sqlx::query(sql).execute(app.pool()).await.unwrap();
+    }
+
+    let page = app.get("/semesters/1/wrapped").await.into_string().await.unwrap();
+    assert!(page.contains("<span class=\"block text-xl text-industrial-100\">2</span><span class=\"text-industrial-500\">错题</span>"));
+    assert!(page.contains("复习最多的分类：极限（2 次）"));
+    // HW1, HW2 and both problems fall in the week of Oct 5; Oct 5-7 and 14-15 are two streaks
+    assert!(page.contains("最忙的一周：10月5日 – 10月11日（4 项）"));
+    assert!(page.contains("最长连续：3 天"));
+
+    // Not public until published under a slug
+    assert_eq!(app.get("/wrapped/fall").await.status(), Status::NotFound);
+    app.post_form("/semesters/1/wrapped", "wrapped_published=on&wrapped_slug=").await;
+    assert!(app.get("/semesters/1/wrapped").await.into_string().await.unwrap().contains("公开总结需要先填写地址。"));
+    app.post_form("/semesters/1/wrapped", "wrapped_published=on&wrapped_slug=fall").await;
+    let public = app.get("/wrapped/fall").await.into_string().await.unwrap();
+    assert!(public.contains("<h1 class=\"mt-1 text-3xl font-bold\">S</h1>"));
+    assert!(public.contains("<meta name=\"robots\" content=\"noindex\">"));
+
+    app.post_form("/semesters/2/wrapped", "wrapped_published=on&wrapped_slug=fall").await;
+    assert!(app.get("/semesters/2/wrapped").await.into_string().await.unwrap().contains("这个地址已被其他学期使用。"));
+    app.post_form("/semesters/1/wrapped", "wrapped_slug=fall").await;
+    assert_eq!(app.get("/wrapped/fall").await.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_course_report_reviews_per_day() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/semesters/1/courses", "code=D&title=T").await;
+    for sql in [
+        "INSERT INTO log_items (course_id, kind, title) VALUES (1, 'Homework', 'HW1'), (2, 'Homework', 'HW1')",
+        "INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP), (2, 'q', 1, CURRENT_TIMESTAMP)",
+        // Problem 2 is in the other course
+        "INSERT INTO study_reviews (user_id, problem_id, grade, reviewed_at) VALUES (1, 1, 3, '2026-10-14 20:00:00'), (1, 1, 3, '2026-10-17 08:00:00'), (1, 1, 1, '2026-10-17 09:00:00'), (1, 2, 3, '2026-10-20 09:00:00')",
+    ] {
+        sqlx::query(sql).execute(app.pool()).await.unwrap();
+    }
+
+    let res = app.get("/courses/1/report.pdf").await;
+    assert_eq!(res.content_type(), Some(ContentType::PDF));
+    let pdf = String::from_utf8(res.into_bytes().await.unwrap()).unwrap();
+    let hex = |text: &str| text.encode_utf16().map(|unit| format!("{:04X}", unit)).collect::<String>();
+    assert!(pdf.contains(&hex("每日复习")));
+    assert!(pdf.contains(&hex("共 3 次 · 2 天 · 单日最多 2 次")));
+    assert!(pdf.contains(&hex("10-14")) && pdf.contains(&hex("10-17")) && !pdf.contains(&hex("10-20")));
+}
+
+#[rocket::async_test]
+async fn test_problem_embeds() {
+    let app = TestApp::configured(|figment| figment.merge(("public_url", "https://zhixi.example.com"))).await.signed_in().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/semesters/1/courses", "code=D&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    app.post_form("/courses/1/settings", "is_published=on&public_slug=c").await;
+    for sql in [
+        "INSERT INTO categories (course_id, name) VALUES (1, 'Limits')",
+        "INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP)",
+        "INSERT INTO problem_categories (problem_id, category_id) VALUES (1, 1)",
+    ] {
+        sqlx::query(sql).execute(app.pool()).await.unwrap();
+    }
+    let problem = app.public_id("problems", 1).await;
+
+    let response = app.get(&format!("/p/c/problems/{}/embed", problem)).await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Content-Security-Policy"), Some("frame-ancestors *"));
+    let page = response.into_string().await.unwrap();
+    assert!(page.contains("HW1") && page.contains("<span>Limits</span>") && page.contains("application/json+oembed"));
+    // Only problems of that course, and only while it is published
+    app.post_form("/courses/2/settings", "is_published=on&public_slug=d").await;
+    assert_eq!(app.get(&format!("/p/d/problems/{}/embed", problem)).await.status(), Status::NotFound);
+
+    let url = format!("https%3A%2F%2Fanywhere.example%2Fp%2Fc%2Fproblems%23problem-{}", problem);
+    let embed: serde_json::Value = app.get(&format!("/oembed?url={}&maxwidth=300", url)).await.into_json().await.unwrap();
+    assert_eq!(embed["type"], "rich");
+    assert_eq!(embed["title"], "C — HW1");
+    assert_eq!(embed["width"], 300);
+    assert!(embed["html"].as_str().unwrap().contains(&format!("src=\"https://zhixi.example.com/p/c/problems/{}/embed\"", problem)));
+    assert_eq!(app.get(&format!("/oembed?url={}&format=xml", url)).await.status(), Status::NotImplemented);
+    assert_eq!(app.get("/oembed?url=https%3A%2F%2Fzhixi.example.com%2Fcourses%2F1").await.status(), Status::NotFound);
+
+    app.post_form("/courses/1/settings", "public_slug=c").await;
+    assert_eq!(app.get(&format!("/oembed?url={}", url)).await.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_course_join_by_code() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=MATH&title=T").await;
+    app.post_form("/courses/1/join_code", "role=editor").await;
+    let code = sqlx::query_scalar::<_, String>("SELECT join_code FROM courses WHERE id = 1").fetch_one(app.pool()).await.unwrap();
+    assert!(app.get("/courses/1/settings").await.into_string().await.unwrap().contains(&code));
+
+    sqlx::query("INSERT INTO users (username, password_hash, is_admin) VALUES ('bob', ?, 0)")
+        .bind(bcrypt::hash("pw", 4).unwrap())
+        .execute(app.pool())
+        .await
+        .unwrap();
+    app.post_form("/logout", "").await;
+    app.post_form("/login", "username=bob&password=pw").await;
+
+    let response = app.post_form("/join", "code=nope").await;
+    assert_eq!(response.headers().get_one("Location"), Some("/dashboard"));
+    assert!(app.get("/dashboard").await.into_string().await.unwrap().contains("加入码无效或已停用。"));
+
+    // Codes are matched case-insensitively
+    let response = app.post_form("/join", &format!("code=+{}+", code.to_lowercase())).await;
+    assert_eq!(response.headers().get_one("Location"), Some("/courses/1"));
+    app.post_form("/join", &format!("code={}", code)).await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM course_members WHERE course_id = 1 AND user_id = 2 AND role = 'editor'").await, 1);
+    assert!(app.get("/dashboard").await.into_string().await.unwrap().contains("<a href=\"/courses/1\""));
+
+    // A regenerated code retires the old one but keeps existing members
+    app.post_form("/courses/1/join_code", "role=viewer&regenerate=on").await;
+    assert_ne!(sqlx::query_scalar::<_, String>("SELECT join_code FROM courses WHERE id = 1").fetch_one(app.pool()).await.unwrap(), code);
+    assert_eq!(app.post_form("/join", &format!("code={}", code)).await.headers().get_one("Location"), Some("/dashboard"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM course_members").await, 1);
+
+    // Leaving the course takes it away again
+    app.delete("/courses/1/members/2").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM course_members").await, 0);
+    assert_eq!(app.get("/courses/1").await.status(), Status::NotFound);
+
+    app.post_form("/logout", "").await;
+    app.post_form("/login", "username=alice&password=pw").await;
+    app.post_form("/courses/1/join_code", "role=viewer&disable=on").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM courses WHERE join_code IS NULL").await, 1);
+}
+
+#[rocket::async_test]
+async fn test_records_scoped_to_owner() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=AliceTerm").await;
+    app.post_form("/semesters/1/courses", "code=MATH&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1&date=2026-09-01").await;
+    app.post_form("/courses/1/exams", "title=Midterm&exam_date=2026-10-01").await;
+    sqlx::query("INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP)")
+        .execute(app.pool())
+        .await
+        .unwrap();
+    let public_id = sqlx::query_scalar::<_, String>("SELECT public_id FROM courses WHERE id = 1").fetch_one(app.pool()).await.unwrap();
+
+    sqlx::query("INSERT INTO users (username, password_hash, is_admin) VALUES ('bob', ?, 0)")
+        .bind(bcrypt::hash("pw", 4).unwrap())
+        .execute(app.pool())
+        .await
+        .unwrap();
+    app.post_form("/logout", "").await;
+    app.post_form("/login", "username=bob&password=pw").await;
+
+    // Someone else's records are missing, not forbidden
+    assert!(!app.get("/dashboard").await.into_string().await.unwrap().contains("AliceTerm"));
+    for uri in ["/semesters/1", "/courses/1", "/courses/1/study", "/logs/1", "/exams/1", "/problems/1"] {
+        assert_eq!(app.get(uri).await.status(), Status::NotFound, "{}", uri);
+    }
+    assert_eq!(app.get(&format!("/api/v1/courses/{}/problems", public_id)).await.status(), Status::NotFound);
+    assert_eq!(app.post_form("/semesters/1/courses", "code=X&title=X").await.status(), Status::NotFound);
+    assert_eq!(app.delete("/problems/1").await.status(), Status::NotFound);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM courses").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 1);
+    let session = app.get("/api/v1/study/session").await.into_string().await.unwrap();
+    assert!(session.contains("\"remaining\":0"));
+
+    // bob's own semester is his alone
+    app.post_form("/semesters", "name=BobTerm").await;
+    assert_eq!(app.get("/semesters/2").await.status(), Status::Ok);
+
+    // and his problems can't be linked to alice's
+    app.post_form("/semesters/2/courses", "code=BOB&title=T").await;
+    app.post_form("/courses/2/logs", "kind=Homework&title=BobHW").await;
+    sqlx::query("INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (2, 'p', 1, CURRENT_TIMESTAMP)")
+        .execute(app.pool())
+        .await
+        .unwrap();
+    assert_eq!(app.post_form("/problems/2/links", "kind=variant&linked_problem_id=1").await.status(), Status::NotFound);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problem_links").await, 0);
+    assert!(!app.get("/problems/2/view").await.into_string().await.unwrap().contains("HW1"));
+    app.post_form("/logout", "").await;
+    app.post_form("/login", "username=alice&password=pw").await;
+    let dashboard = app.get("/dashboard").await.into_string().await.unwrap();
+    assert!(dashboard.contains("AliceTerm") && !dashboard.contains("BobTerm"));
+    assert_eq!(app.get("/semesters/2").await.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_viewer_cannot_write() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=MATH&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1&date=2026-09-01").await;
+    app.post_form("/courses/1/exams", "title=Midterm&exam_date=2026-10-01").await;
+    sqlx::query("INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP)")
+        .execute(app.pool())
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO users (username, password_hash, is_admin) VALUES ('bob', ?, 0), ('carol', ?, 0)")
+        .bind(bcrypt::hash("pw", 4).unwrap())
+        .bind(bcrypt::hash("pw", 4).unwrap())
+        .execute(app.pool())
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO course_members (course_id, user_id, role, joined_at) VALUES (1, 2, 'viewer', CURRENT_TIMESTAMP), (1, 3, 'viewer', CURRENT_TIMESTAMP)")
+        .execute(app.pool())
+        .await
+        .unwrap();
+    app.post_form("/logout", "").await;
+    app.post_form("/login", "username=bob&password=pw").await;
+
+    // Reading is fine
+    for uri in ["/courses/1", "/logs/1", "/exams/1", "/problems/1"] {
+        assert_eq!(app.get(uri).await.status(), Status::Ok, "{}", uri);
+    }
+    // Changing anything isn't
+    assert_eq!(app.delete("/courses/1").await.status(), Status::Forbidden);
+    assert_eq!(app.post_form("/courses/1/edit", "code=X&title=X").await.status(), Status::Forbidden);
+    assert_eq!(app.post_form("/courses/1/logs", "kind=Homework&title=HW2").await.status(), Status::Forbidden);
+    assert_eq!(app.post_form("/logs/1", "kind=Homework&title=Renamed").await.status(), Status::Forbidden);
+    assert_eq!(app.delete("/logs/1").await.status(), Status::Forbidden);
+    assert_eq!(app.post_form("/exams/1", "title=Final").await.status(), Status::Forbidden);
+    assert_eq!(app.delete("/exams/1").await.status(), Status::Forbidden);
+    assert_eq!(app.post_form("/problems/1", "notes=mine").await.status(), Status::Forbidden);
+    assert_eq!(app.delete("/problems/1").await.status(), Status::Forbidden);
+    assert_eq!(app.delete("/courses/1/members/3").await.status(), Status::Forbidden);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM courses WHERE code = 'MATH'").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM log_items WHERE title = 'HW1'").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM exams").await, 1);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 1);
+
+    // but discussing the course and leaving it are
+    assert_eq!(app.post_form("/courses/1/discussions", "title=Q&body=When+is+the+exam").await.status(), Status::Ok);
+    assert_eq!(app.delete("/courses/1/members/2").await.status(), Status::Ok);
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM course_members").await, 1);
+}
+
+#[rocket::async_test]
+async fn test_problem_assignment() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1&date=2026-09-01").await;
+    sqlx::query("INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP)")
+        .execute(app.pool())
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO users (username, password_hash, is_admin) VALUES ('bob', ?, 0), ('carol', ?, 0)")
+        .bind(bcrypt::hash("pw", 4).unwrap())
+        .bind(bcrypt::hash("pw", 4).unwrap())
+        .execute(app.pool())
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO course_members (course_id, user_id, role, joined_at) VALUES (1, 2, 'editor', CURRENT_TIMESTAMP)")
+        .execute(app.pool())
+        .await
+        .unwrap();
+
+    // Members and the assigner are offered; carol isn't in the course
+    let page = app.get("/problems/1/view").await.into_string().await.unwrap();
+    assert!(page.contains(">alice</option>") && page.contains(">bob</option>") && !page.contains("carol"));
+    assert_eq!(app.post_form("/problems/1/assign", "user_id=3").await.status(), Status::BadRequest);
+
+    let response = app.post_form("/problems/1/assign", "user_id=2").await;
+    assert_eq!(response.headers().get_one("Location"), Some("/problems/1/view"));
+    assert!(app.get("/problems/1/view").await.into_string().await.unwrap().contains("<option value=\"2\" selected>bob</option>"));
+    assert!(app.get("/assignments").await.into_string().await.unwrap().contains("没有分配给你的题目"));
+
+    app.post_form("/logout", "").await;
+    app.post_form("/login", "username=bob&password=pw").await;
+    let page = app.get("/assignments").await.into_string().await.unwrap();
+    assert!(page.contains("/problems/1") && page.contains("待解答"));
+
+    app.post_form("/problems/1/assign", "user_id=").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems WHERE assigned_to IS NULL").await, 1);
+}
+
+#[rocket::async_test]
+async fn test_problem_links() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Homework&title=HW1").await;
+    sqlx::query("INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP), (1, 'p', 1, CURRENT_TIMESTAMP), (1, 'p', 1, CURRENT_TIMESTAMP)")
+        .execute(app.pool())
+        .await
+        .unwrap();
+
+    // 1 → 2 → 3 in difficulty, 3 also a variant of 1
+    let response = app.post_form("/problems/2/links", "kind=prerequisite&linked_problem_id=1").await;
+    assert_eq!(response.headers().get_one("Location"), Some("/problems/2/view"));
+    app.post_form("/problems/2/links", "kind=follow_up&linked_problem_id=3").await;
+    app.post_form("/problems/3/links", "kind=variant&linked_problem_id=1").await;
+    app.post_form("/problems/1/links", "kind=variant&linked_problem_id=3").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problem_links").await, 3);
+
+    let page = app.get("/problems/2/view").await.into_string().await.unwrap();
+    assert!(page.find("前置</span>错题 P-001").unwrap() < page.find("进阶</span>错题 P-003").unwrap());
+    let page = app.get("/problems/1/view").await.into_string().await.unwrap();
+    assert!(page.contains("进阶</span>错题 P-002 · HW1") && page.contains("变式</span>错题 P-003"));
+
+    assert_eq!(app.post_form("/problems/1/links", "kind=variant&linked_problem_id=1").await.status(), Status::BadRequest);
+    assert_eq!(app.post_form("/problems/1/links", "kind=variant&linked_problem_id=9").await.status(), Status::NotFound);
+    assert_eq!(app.post_form("/problems/1/links", "kind=harder&linked_problem_id=2").await.status(), Status::BadRequest);
+
+    app.delete("/problem-links/1").await;
+    assert!(!app.get("/problems/2/view").await.into_string().await.unwrap().contains("错题 P-001 ·"));
+    // Deleting a problem takes its links with it
+    app.delete("/problems/3").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problem_links").await, 0);
+}
+
+#[rocket::async_test]
+async fn test_log_item_status() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    let item = app.post_form("/courses/1/logs", "kind=Homework&title=HW1&date=2000-01-01").await.into_string().await.unwrap();
+    assert!(item.contains("标记待办"));
+    let item = app.post_form("/courses/1/logs", "kind=Lecture&title=L1").await.into_string().await.unwrap();
+    assert!(!item.contains("/logs/2/status"));
+
+    // Each click steps to the next status
+    assert!(app.post_form("/logs/1/status", "").await.into_string().await.unwrap().contains(">待办</button>"));
+    assert!(app.post_form("/logs/1/status", "").await.into_string().await.unwrap().contains(">进行中</button>"));
+    app.post_form("/logs/2/status", "status=submitted").await;
+    assert_eq!(app.post_form("/logs/2/status", "status=lost").await.status(), Status::BadRequest);
+
+    let page = app.get("/courses/1?status=open").await.into_string().await.unwrap();
+    assert!(page.contains(r#"id="log-1""#) && !page.contains(r#"id="log-2""#));
+    let page = app.get("/courses/1?status=finished").await.into_string().await.unwrap();
+    assert!(!page.contains(r#"id="log-1""#) && page.contains(r#"id="log-2""#));
+    assert!(app.get("/courses/1?status=todo").await.into_string().await.unwrap().contains("没有符合筛选条件的记录"));
+
+    let page = app.get("/dashboard").await.into_string().await.unwrap();
+    assert!(page.contains("待完成") && page.contains(r#"<span class="shrink-0 text-xs text-red-400">进行中 · 2000-01-01</span>"#));
+    assert!(!page.contains("L1"));
+
+    app.post_form("/logs/1/status", "status=").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM log_items WHERE status IS NULL").await, 1);
+    assert!(!app.get("/dashboard").await.into_string().await.unwrap().contains("待完成"));
+}
+
+#[rocket::async_test]
+async fn test_log_item_tags() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    app.post_form("/courses/1/logs", "kind=Lecture&title=L1").await;
+    app.post_form("/courses/1/logs", "kind=Lecture&title=L2").await;
+
+    // Split like categories, normalized, and one spelling per course
+    let chips = app.post_form("/logs/1/tags", "add=%E9%87%8D%E7%82%B9%E3%80%81Exam%2C+%EF%BC%A5xam+").await.into_string().await.unwrap();
+    assert!(chips.contains(">#重点</a>") && chips.contains(">#Exam</a>"));
+    app.post_form("/logs/2/tags", "add=exam").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM log_item_tags WHERE name = 'Exam'").await, 2);
+    assert!(app.get("/logs/2").await.into_string().await.unwrap().contains(">#Exam</a>"));
+
+    let page = app.get("/courses/1?tag=%E9%87%8D%E7%82%B9").await.into_string().await.unwrap();
+    assert!(page.contains(r#"id="log-1""#) && !page.contains(r#"id="log-2""#));
+    let page = app.get("/courses/1?tag=EXAM").await.into_string().await.unwrap();
+    assert!(page.contains(r#"id="log-1""#) && page.contains(r#"id="log-2""#));
+    assert!(app.get("/courses/1?tag=nothing").await.into_string().await.unwrap().contains("没有符合筛选条件的记录"));
+
+    let chips = app.post_form("/logs/1/tags", "remove=%E9%87%8D%E7%82%B9").await.into_string().await.unwrap();
+    assert!(!chips.contains("重点") && chips.contains(">#Exam</a>"));
+    assert_eq!(app.post_form("/logs/9/tags", "add=x").await.status(), Status::NotFound);
+
+    app.delete("/logs/1").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM log_item_tags").await, 1);
+}
+
+#[rocket::async_test]
+async fn test_video_bookmarks() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    let item = app.post_form("/courses/1/logs", "kind=Lecture&title=L1&link=https%3A%2F%2Fyoutu.be%2Fabc").await.into_string().await.unwrap();
+    assert!(item.contains(r#"hx-get="/logs/1/bookmarks""#));
+    let item = app.post_form("/courses/1/logs", "kind=Lecture&title=L2&link=https%3A%2F%2Fexample.com%2Fslides.pdf").await.into_string().await.unwrap();
+    assert!(!item.contains("/bookmarks"));
+
+    app.post_form("/logs/1/bookmarks", "time=1%3A02%3A03&note=ending").await;
+    let list = app.post_form("/logs/1/bookmarks", "time=12%3A05&note=%E5%AE%9A%E4%B9%89").await.into_string().await.unwrap();
+    assert!(list.find(r#"href="https://youtu.be/abc?t=725""#).unwrap() < list.find(r#"href="https://youtu.be/abc?t=3723""#).unwrap());
+    assert!(list.contains(">12:05</a>") && list.contains("定义"));
+
+    let list = app.post_form("/logs/1/bookmarks", "time=soon&note=x").await.into_string().await.unwrap();
+    assert!(list.contains("时间的格式应为"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM log_item_bookmarks").await, 2);
+    assert_eq!(app.get("/logs/9/bookmarks").await.status(), Status::NotFound);
+
+    app.delete("/bookmarks/1").await;
+    assert!(!app.get("/logs/1/bookmarks").await.into_string().await.unwrap().contains("ending"));
+    app.delete("/logs/1").await;
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM log_item_bookmarks").await, 0);
+}
+
+#[rocket::async_test]
+async fn test_course_discussions() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=C&title=T").await;
+    sqlx::query("INSERT INTO users (username, password_hash, is_admin) VALUES ('bob', ?, 0)")
+        .bind(bcrypt::hash("pw", 4).unwrap())
+        .execute(app.pool())
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO course_members (course_id, user_id, role, joined_at) VALUES (1, 2, 'editor', CURRENT_TIMESTAMP)")
+        .execute(app.pool())
+        .await
+        .unwrap();
+
+    assert_eq!(app.post_form("/courses/1/discussions", "title=+&body=x").await.status(), Status::BadRequest);
+    let row = app.post_form("/courses/1/discussions", "title=Midterm&body=Who+takes+limits%3F").await.into_string().await.unwrap();
+    assert!(row.contains("/discussions/1") && !row.contains("有新回复"));
+
+    app.post_form("/logout", "").await;
+    app.post_form("/login", "username=bob&password=pw").await;
+    assert!(app.get("/courses/1/discussions").await.into_string().await.unwrap().contains("有新回复"));
+    let post = app.post_form("/discussions/1/posts", "body=Me").await.into_string().await.unwrap();
+    assert!(post.contains("/discussion_posts/2"));
+    assert!(!app.get("/courses/1/discussions").await.into_string().await.unwrap().contains("有新回复"));
+    assert_eq!(app.delete("/discussions/1").await.status(), Status::Forbidden);
+    assert_eq!(app.delete("/discussion_posts/1").await.status(), Status::Forbidden);
+
+    // alice sees bob's reply as new until she opens the thread
+    app.post_form("/logout", "").await;
+    app.post_form("/login", "username=alice&password=pw").await;
+    assert!(app.get("/courses/1/discussions").await.into_string().await.unwrap().contains("有新回复"));
+    assert!(app.get("/discussions/1").await.into_string().await.unwrap().contains("Who takes limits?"));
+    assert!(!app.get("/courses/1/discussions").await.into_string().await.unwrap().contains("有新回复"));
+
+    // The admin can remove bob's post; deleting the thread clears the rest
+    assert_eq!(app.delete("/discussion_posts/2").await.status(), Status::Ok);
+    assert_eq!(app.delete("/discussions/1").await.status(), Status::Ok);
+    for table in ["discussion_threads", "discussion_posts", "discussion_reads"] {
+        assert_eq!(app.scalar(&format!("SELECT COUNT(*) FROM {}", table)).await, 0);
+    }
+}
+
+#[rocket::async_test]
+async fn test_weekly_digest_settings() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=MATH1A&title=T").await;
+    let date = |days| (chrono::Local::now().date_naive() + chrono::Duration::days(days)).format("%Y-%m-%d").to_string();
+    app.post_form("/courses/1/logs", &format!("kind=Homework&title=HW1&date={}", date(-2))).await;
+    app.post_form("/courses/1/exams", &format!("title=Midterm&exam_date={}", date(3))).await;
+    app.post_form("/courses/1/exams", &format!("title=Final&exam_date={}", date(30))).await;
+
+    let page = app.get("/digest").await.into_string().await.unwrap();
+    assert!(page.contains("MATH1A HW1") && page.contains("MATH1A Midterm") && !page.contains("Final"));
+
+    app.post_form("/digest", "enabled=on&email=not-an-address&weekday=1&hour=8").await;
+    assert!(app.get("/digest").await.into_string().await.unwrap().contains("请输入有效的邮箱地址。"));
+    assert_eq!(app.post_form("/digest", "enabled=on&email=a%40example.com&weekday=8&hour=8").await.status(), Status::BadRequest);
+    app.post_form("/digest", "enabled=on&email=a%40example.com&weekday=5&hour=20").await;
+    let token = sqlx::query_scalar::<_, String>("SELECT unsubscribe_token FROM digest_subscriptions WHERE user_id = 1 AND weekday = 5 AND hour = 20")
+        .fetch_one(app.pool())
+        .await
+        .unwrap();
+
+    // Editing keeps the token that earlier emails link to
+    app.post_form("/digest", "enabled=on&email=b%40example.com&weekday=5&hour=20").await;
+    assert_eq!(app.scalar(&format!("SELECT COUNT(*) FROM digest_subscriptions WHERE email = 'b@example.com' AND unsubscribe_token = '{}'", token)).await, 1);
+
+    // Unsubscribing works signed out, but only once the button is pressed
+    app.post_form("/logout", "").await;
+    let url = format!("/digest/unsubscribe/{}", token);
+    assert!(app.get(&url).await.into_string().await.unwrap().contains("确认退订"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM digest_subscriptions").await, 1);
+    assert!(app.post_form(&url, "").await.into_string().await.unwrap().contains("已退订"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM digest_subscriptions").await, 0);
+}
+
+#[rocket::async_test]
+async fn test_todos_feed() {
+    let app = TestApp::authenticated().await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=MATH+1A&title=T").await;
+    sqlx::query(
+        r#"
+        INSERT INTO log_items (course_id, kind, title, date, status) VALUES
+            (1, 'Homework', 'HW1', '2020-01-01', 'in_progress'),
+            (1, 'Homework', 'HW2', '2999-01-01', NULL),
+            (1, 'Homework', 'HW0', '2020-01-01', NULL),
+            (1, 'Lab', 'Lab1', '2999-01-01', 'submitted')
+        "#,
+    )
+    .execute(app.pool())
+    .await
+    .unwrap();
+    sqlx::query("INSERT INTO problems (log_item_id, description, is_incorrect, created_at) VALUES (1, 'p', 1, CURRENT_TIMESTAMP), (1, 'p', 1, CURRENT_TIMESTAMP)")
+        .execute(app.pool())
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO study_cards (user_id, problem_id, due_at) VALUES (1, 1, CURRENT_TIMESTAMP), (1, 2, datetime('now', '+3 days'))")
+        .execute(app.pool())
+        .await
+        .unwrap();
+
+    // Due reviews and unfinished homework, not old unmarked or submitted items
+    let res = app.get("/todos.ics").await;
+    assert_eq!(res.content_type(), Some(ContentType::Calendar));
+    let ics = res.into_string().await.unwrap();
+    assert!(ics.contains("SUMMARY:复习 MATH 1A：1 道错题\r\n"));
+    assert!(ics.contains("SUMMARY:MATH 1A HW1\r\nDUE;VALUE=DATE:20200101\r\nSTATUS:IN-PROCESS\r\n"));
+    assert!(ics.contains("SUMMARY:MATH 1A HW2\r\nDUE;VALUE=DATE:29990101\r\nSTATUS:NEEDS-ACTION\r\n"));
+    assert!(!ics.contains("HW0") && !ics.contains("Lab1"));
+
+    // Task apps use the token from the account page
+    app.post_form("/account/todos", "").await;
+    let token = sqlx::query_scalar::<_, String>("SELECT todos_token FROM users WHERE id = 1").fetch_one(app.pool()).await.unwrap();
+    assert!(app.get("/account").await.into_string().await.unwrap().contains(&format!("/todos.ics?token={}", token)));
+    app.post_form("/logout", "").await;
+    assert_eq!(app.get("/todos.ics").await.status(), Status::Unauthorized);
+    assert_eq!(app.get("/todos.ics?token=wrong").await.status(), Status::NotFound);
+    let url = format!("/todos.ics?token={}", token);
+    assert!(app.get(&url).await.into_string().await.unwrap().contains("MATH 1A HW1"));
+
+    app.post_form("/login", "username=alice&password=pw").await;
+    app.post_form("/account/todos/off", "").await;
+    assert_eq!(app.get(&url).await.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_telegram_capture() {
+    let app = TestApp::configured(|figment| figment
+        .merge(("telegram.secret_token", "s3cret"))
+        .merge(("telegram.chats", serde_json::json!([{ "chat_id": 42, "username": "alice" }, { "chat_id": 9, "username": "nobody" }]))))
+        .await
+        .signed_in()
+        .await;
+    app.post_form("/semesters", "name=S").await;
+    app.post_form("/semesters/1/courses", "code=MATH+1A&title=T").await;
+
+    let send = |secret: &'static str, body: String| {
+        app.client
+            .post("/integrations/telegram")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Telegram-Bot-Api-Secret-Token", secret))
+            .body(body)
+            .dispatch()
+    };
+    let message = |chat: i64, text: &str| format!(r#"{{"update_id":1,"message":{{"chat":{{"id":{}}},"text":{:?}}}}}"#, chat, text);
+
+    assert_eq!(send("wrong", message(42, "#math1a x")).await.status(), Status::Forbidden);
+    // Other chats, and chats for users that don't exist, are acknowledged but ignored
+    assert_eq!(send("s3cret", message(7, "#math1a x")).await.into_string().await.unwrap(), "{}");
+    assert_eq!(send("s3cret", message(9, "#math1a x")).await.into_string().await.unwrap(), "{}");
+    assert!(send("s3cret", message(42, "#PHYS7A x")).await.into_string().await.unwrap().contains("找不到课程 #PHYS7A"));
+
+    // Only the chat's user's courses are looked at, however new another's is
+    sqlx::query("INSERT INTO users (username, password_hash) VALUES ('bob', 'x')").execute(app.pool()).await.unwrap();
+    sqlx::query("INSERT INTO semesters (user_id, name) VALUES (2, 'Later')").execute(app.pool()).await.unwrap();
+    sqlx::query("INSERT INTO courses (semester_id, code, title) VALUES (2, 'PHYS 7A', 'T')").execute(app.pool()).await.unwrap();
+    assert!(send("s3cret", message(42, "#PHYS7A x")).await.into_string().await.unwrap().contains("找不到课程 #PHYS7A"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM log_items").await, 0);
+
+    let reply = send("s3cret", message(42, "#math1a 期中范围\n到第五章")).await.into_string().await.unwrap();
+    assert!(reply.contains("\"method\":\"sendMessage\"") && reply.contains("\"chat_id\":42"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM log_items WHERE course_id = 1 AND kind = 'Other' AND title = '期中范围' AND description = '到第五章'").await, 1);
+
+    // Photos need the bot token to be downloaded
+    let photo = r##"{"update_id":2,"message":{"chat":{"id":42},"caption":"#MATH1A","photo":[{"file_id":"a"},{"file_id":"b"}]}}"##;
+    assert!(send("s3cret", photo.to_string()).await.into_string().await.unwrap().contains("bot_token"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM problems").await, 0);
+}
@@ -0,0 +1,232 @@
+use rocket_db_pools::sqlx::{self, SqliteConnection, SqlitePool};
+use crate::uploads;
+
+// ========== Integrity Checks ==========
+//
+// The app's connections enforce foreign keys, but the sqlite3 shell doesn't
+// by default, and screenshot files and cached translations aren't foreign
+// keys at all. These checks look for the kinds of drift that
+// have turned up; admins run them from /admin/integrity and operators with
+// `zhixi integrity [--repair]`.
+//
+// Repairs only remove what nothing can reach any more, or unlink a problem
+// from a screenshot file that is gone. Checks run in an order where fixing
+// one can't leave new findings for an earlier one.
+
+/// At most this many rows are listed per check; the count is always exact.
+pub const MAX_EXAMPLES: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Check {
+    OrphanedLogItems,
+    OrphanedProblemCategories,
+    MissingImages,
+    DanglingTranslations,
+}
+
+impl Check {
+    pub const ALL: [Check; 4] = [Check::OrphanedLogItems, Check::OrphanedProblemCategories, Check::MissingImages, Check::DanglingTranslations];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Check::OrphanedLogItems => "所属课程已删除的记录",
+            Check::OrphanedProblemCategories => "指向已删除错题或分类的分类标记",
+            Check::MissingImages => "截图文件缺失的错题",
+            Check::DanglingTranslations => "不再被使用的翻译缓存",
+        }
+    }
+
+    /// What repairing does about it
+    pub fn repair(&self) -> &'static str {
+        match self {
+            Check::OrphanedLogItems => "连同其下的错题一起删除",
+            Check::OrphanedProblemCategories => "删除这些标记",
+            Check::MissingImages => "保留错题，去掉指向缺失文件的链接",
+            Check::DanglingTranslations => "删除这些缓存",
+        }
+    }
+}
+
+pub struct Finding {
+    pub check: Check,
+    pub count: usize,
+    /// Up to MAX_EXAMPLES rows, described for people
+    pub examples: Vec<String>,
+}
+
+impl Finding {
+    pub fn more(&self) -> usize {
+        self.count - self.examples.len()
+    }
+}
+
+// Each check lists (row id, description); problem_categories has no id of
+// its own, so its rows are keyed by problem id and repaired in one statement.
+async fn find(db: &mut SqliteConnection, check: Check) -> Vec<(i64, String)> {
+    match check {
+        Check::OrphanedLogItems => sqlx::query_as::<_, (i64, i64, String)>(
+            "SELECT id, course_id, title FROM log_items WHERE course_id NOT IN (SELECT id FROM courses) ORDER BY id"
+        )
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(id, course_id, title)| (id, format!("记录 #{} {}（课程 #{}）", id, title, course_id)))
+        .collect(),
+
+        Check::OrphanedProblemCategories => sqlx::query_as::<_, (i64, i64)>(
+            r#"
+            SELECT problem_id, category_id FROM problem_categories
+            WHERE problem_id NOT IN (SELECT id FROM problems) OR category_id NOT IN (SELECT id FROM categories)
+            ORDER BY problem_id, category_id
+            "#,
+        )
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(problem_id, category_id)| (problem_id, format!("错题 #{} · 分类 #{}", problem_id, category_id)))
+        .collect(),
+
+        Check::MissingImages => {
+            let rows = sqlx::query_as::<_, (i64, String)>("SELECT id, image_url FROM problems WHERE image_url IS NOT NULL ORDER BY id")
+                .fetch_all(&mut *db)
+                .await
+                .unwrap_or_default();
+            let root = uploads::dir(db).await;
+            let mut missing = Vec::new();
+            for (id, url) in rows {
+                // Links to anywhere but upload storage aren't ours to check
+                let Some(path) = uploads::stored_path(&url) else { continue };
+                if !tokio::fs::try_exists(root.join(path)).await.unwrap_or(true) {
+                    missing.push((id, format!("错题 #{}：{}", id, url)));
+                }
+            }
+            missing
+        }
+
+        Check::DanglingTranslations => sqlx::query_as::<_, (i64, String)>(
+            r#"
+            SELECT id, source_text FROM translations
+            WHERE source_text NOT IN (
+                SELECT description FROM log_items WHERE description IS NOT NULL
+                UNION SELECT name FROM categories
+                UNION SELECT notes FROM problems WHERE notes IS NOT NULL
+                UNION SELECT title FROM exams
+            )
+            ORDER BY id
+            "#,
+        )
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(id, text)| (id, format!("「{}」", text)))
+        .collect(),
+    }
+}
+
+/// Run every check. Only checks with something to report are returned.
+pub async fn scan(db: &mut SqliteConnection) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for check in Check::ALL {
+        let rows = find(db, check).await;
+        if rows.is_empty() {
+            continue;
+        }
+        let count = rows.len();
+        let examples = rows.into_iter().take(MAX_EXAMPLES).map(|(_, text)| text).collect();
+        findings.push(Finding { check, count, examples });
+    }
+    findings
+}
+
+/// Fix everything the checks find. Returns how many rows were repaired.
+pub async fn repair(db: &mut SqliteConnection) -> usize {
+    let mut repaired = 0;
+    for check in Check::ALL {
+        let rows = find(db, check).await;
+        repaired += rows.len();
+        match check {
+            Check::OrphanedLogItems => {
+                for (id, _) in rows {
+                    delete_log_item(db, id).await;
+                }
+            }
+            Check::OrphanedProblemCategories => {
+                let _ = sqlx::query(
+                    "DELETE FROM problem_categories WHERE problem_id NOT IN (SELECT id FROM problems) OR category_id NOT IN (SELECT id FROM categories)"
+                )
+                .execute(&mut *db)
+                .await;
+            }
+            Check::MissingImages => {
+                for (id, _) in rows {
+                    let url: Option<String> = sqlx::query_scalar("SELECT image_url FROM problems WHERE id = ?")
+                        .bind(id)
+                        .fetch_optional(&mut *db)
+                        .await
+                        .unwrap_or(None);
+                    let _ = sqlx::query("UPDATE problems SET image_url = NULL, mime_type = NULL, original_filename = NULL WHERE id = ?")
+                        .bind(id)
+                        .execute(&mut *db)
+                        .await;
+                    // Drops the file's row in `uploads` once nothing else points at it
+                    if let Some(url) = url {
+                        uploads::release(db, &url).await;
+                    }
+                }
+            }
+            Check::DanglingTranslations => {
+                for (id, _) in rows {
+                    let _ = sqlx::query("DELETE FROM translations WHERE id = ?")
+                        .bind(id)
+                        .execute(&mut *db)
+                        .await;
+                }
+            }
+        }
+    }
+    repaired
+}
+
+// A log item and, through ON DELETE CASCADE, its problems, as the delete route
+// would. No history entries: there is no course left to file them under.
+async fn delete_log_item(db: &mut SqliteConnection, id: i64) {
+    let image_urls: Vec<Option<String>> = sqlx::query_scalar("SELECT image_url FROM problems WHERE log_item_id = ?")
+        .bind(id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+    let _ = sqlx::query("DELETE FROM log_items WHERE id = ?")
+        .bind(id)
+        .execute(&mut *db)
+        .await;
+    for url in image_urls.into_iter().flatten() {
+        uploads::release(db, &url).await;
+    }
+}
+
+/// `zhixi integrity [--repair]`: print what the checks find, then fix it if asked.
+pub async fn run(pool: &SqlitePool, repair_found: bool) {
+    let mut conn = pool.acquire().await.expect("database connection");
+    let findings = scan(&mut conn).await;
+    if findings.is_empty() {
+        println!("No problems found.");
+        return;
+    }
+    for finding in &findings {
+        println!("{}: {}", finding.check.label(), finding.count);
+        for example in &finding.examples {
+            println!("  {}", example);
+        }
+        if finding.more() > 0 {
+            println!("  … {} more", finding.more());
+        }
+    }
+    if repair_found {
+        println!("Repaired {} rows.", repair(&mut conn).await);
+    } else {
+        println!("Run with --repair to fix these.");
+    }
+}
@@ -0,0 +1,131 @@
+use rocket::fairing::AdHoc;
+use rocket_db_pools::sqlx::{self, SqliteConnection, SqlitePool};
+use rocket_db_pools::Database;
+use std::time::Duration;
+use crate::config::AppConfig;
+use crate::db::Db;
+use crate::{account, difficulty, digest, link_preview, outbound, page_views, stats, translate, webhook};
+
+// ========== Background Jobs ==========
+//
+// Slow work that shouldn't hold up a request (fetching link previews, ...) is
+// queued in the `jobs` table with `enqueue` and run by a single worker task
+// started at liftoff. A job that fails is retried with growing delays until it
+// has been attempted MAX_ATTEMPTS times; after that it stays in the table with
+// its last error for inspection.
+
+type JobResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_ATTEMPTS: i64 = 3;
+
+/// Queue a job to run as soon as the worker gets to it.
+pub async fn enqueue(db: &mut SqliteConnection, kind: &str, payload: &str) {
+    sqlx::query("INSERT INTO jobs (kind, payload, run_after, created_at) VALUES (?, ?, datetime('now'), CURRENT_TIMESTAMP)")
+        .bind(kind)
+        .bind(payload)
+        .execute(&mut *db)
+        .await
+        .unwrap();
+}
+
+/// Queue a job to run once `delay` has passed.
+pub async fn enqueue_after(db: &mut SqliteConnection, kind: &str, payload: &str, delay: Duration) {
+    sqlx::query("INSERT INTO jobs (kind, payload, run_after, created_at) VALUES (?, ?, datetime('now', ?), CURRENT_TIMESTAMP)")
+        .bind(kind)
+        .bind(payload)
+        .bind(format!("+{} seconds", delay.as_secs()))
+        .execute(&mut *db)
+        .await
+        .unwrap();
+}
+
+/// Whether a job of this kind and payload is still waiting to run (or be retried).
+pub async fn is_queued(db: &mut SqliteConnection, kind: &str, payload: &str) -> bool {
+    sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM jobs WHERE kind = ? AND payload = ? AND attempts < ?")
+        .bind(kind)
+        .bind(payload)
+        .bind(MAX_ATTEMPTS)
+        .fetch_one(&mut *db)
+        .await
+        .unwrap_or(0)
+        > 0
+}
+
+async fn dispatch(pool: &SqlitePool, client: &outbound::Client, tally: &page_views::Tally, kind: &str, payload: &str) -> JobResult {
+    match kind {
+        link_preview::JOB_KIND => link_preview::fetch(pool, client, payload).await,
+        translate::PRUNE_JOB_KIND => translate::prune(pool, payload).await,
+        translate::MISSING_JOB_KIND => translate::translate_missing(pool, payload).await,
+        digest::JOB_KIND => digest::send_due(pool, payload).await,
+        difficulty::JOB_KIND => difficulty::recalibrate(pool, payload).await,
+        stats::JOB_KIND => stats::flush_requests(pool, tally, payload).await,
+        account::JOB_KIND => account::send_verification(pool, payload).await,
+        webhook::JOB_KIND => webhook::sweep(pool, client, payload).await,
+        _ => Err(format!("unknown job kind {:?}", kind).into()),
+    }
+}
+
+// Claim the next due job by bumping its attempt count and pushing run_after out,
+// so a job that crashes the worker mid-run is retried later rather than in a loop
+async fn claim_next(pool: &SqlitePool) -> Option<(i64, String, String, i64)> {
+    let (id, kind, payload, attempts): (i64, String, String, i64) = sqlx::query_as(
+        "SELECT id, kind, payload, attempts FROM jobs WHERE attempts < ? AND run_after <= datetime('now') ORDER BY id LIMIT 1",
+    )
+    .bind(MAX_ATTEMPTS)
+    .fetch_optional(pool)
+    .await
+    .ok()??;
+
+    let backoff = format!("+{} seconds", 60 * 5i64.pow(attempts as u32));
+    sqlx::query("UPDATE jobs SET attempts = attempts + 1, run_after = datetime('now', ?) WHERE id = ?")
+        .bind(backoff)
+        .bind(id)
+        .execute(pool)
+        .await
+        .ok()?;
+    Some((id, kind, payload, attempts))
+}
+
+async fn run(pool: SqlitePool, client: outbound::Client, tally: page_views::Tally) {
+    loop {
+        let Some((id, kind, payload, _)) = claim_next(&pool).await else {
+            rocket::tokio::time::sleep(POLL_INTERVAL).await;
+            continue;
+        };
+
+        match dispatch(&pool, &client, &tally, &kind, &payload).await {
+            Ok(()) => {
+                let _ = sqlx::query("DELETE FROM jobs WHERE id = ?").bind(id).execute(&pool).await;
+            }
+            Err(e) => {
+                let _ = sqlx::query("UPDATE jobs SET last_error = ? WHERE id = ?")
+                    .bind(e.to_string())
+                    .bind(id)
+                    .execute(&pool)
+                    .await;
+            }
+        }
+    }
+}
+
+/// Fairing that starts the job worker once the server is up.
+pub fn worker() -> AdHoc {
+    AdHoc::on_liftoff("Background Jobs", |rocket| {
+        Box::pin(async move {
+            if let Some(db) = Db::fetch(rocket) {
+                let pool = (***db).clone();
+                if let Some(config) = rocket.state::<AppConfig>() {
+                    translate::schedule_pruning(&pool, &config.translation_cache).await;
+                    digest::schedule(&pool, config).await;
+                    webhook::schedule(&pool, config).await;
+                }
+                difficulty::schedule(&pool).await;
+                stats::schedule(&pool).await;
+                let client = rocket.state::<outbound::Client>().cloned().expect("outbound client");
+                let tally = rocket.state::<page_views::Tally>().cloned().expect("page view tally");
+                rocket::tokio::spawn(run(pool, client, tally));
+            }
+        })
+    })
+}
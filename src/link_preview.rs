@@ -0,0 +1,263 @@
+use rocket_db_pools::sqlx::{self, SqliteConnection, SqlitePool};
+use std::time::Duration;
+use crate::jobs;
+use crate::models::LinkPreview;
+use crate::outbound::{self, FetchError};
+
+// ========== Link Previews ==========
+//
+// URLs in log item descriptions get a small preview card (title, domain,
+// favicon) under the description. Saving a description queues a fetch job for
+// every URL not seen before; the job reads the start of the page and records
+// what it finds in `link_previews`. Previews are shared by URL across items.
+// Only public hosts are fetched; see outbound.rs.
+
+pub const JOB_KIND: &str = "link_preview";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Enough for the <head> of any reasonable page
+const MAX_FETCH_BYTES: usize = 256 * 1024;
+const MAX_TITLE_CHARS: usize = 200;
+
+/// http(s) URLs in free text, in order of appearance and without duplicates.
+pub fn extract_urls(text: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for word in text.split(|c: char| c.is_whitespace() || "<>\"'（）「」【】，。".contains(c)) {
+        let Some(start) = word.find("http://").or_else(|| word.find("https://")) else { continue };
+        // Trailing punctuation is almost always sentence punctuation, not part of the URL
+        let url = word[start..].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']']);
+        if domain(url).is_some() && !urls.iter().any(|u| u == url) {
+            urls.push(url.to_string());
+        }
+    }
+    urls
+}
+
+// "https://Example.com:8080/a?b" -> "example.com"
+pub fn domain(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://"))?;
+    let host = rest.split(['/', '?', '#']).next()?;
+    let host = host.rsplit('@').next()?.split(':').next()?;
+    if host.is_empty() { None } else { Some(host.to_ascii_lowercase()) }
+}
+
+// "https://example.com/a/b" -> "https://example.com"
+fn origin(url: &str) -> &str {
+    let scheme_end = url.find("://").map(|i| i + 3).unwrap_or(0);
+    match url[scheme_end..].find(['/', '?', '#']) {
+        Some(i) => &url[..scheme_end + i],
+        None => url,
+    }
+}
+
+/// Record a pending preview and queue a fetch for each new URL in `text`.
+pub async fn queue_previews(db: &mut SqliteConnection, text: &str) {
+    for url in extract_urls(text) {
+        let Some(domain) = domain(&url) else { continue };
+        let inserted = sqlx::query("INSERT OR IGNORE INTO link_previews (url, domain) VALUES (?, ?)")
+            .bind(&url)
+            .bind(&domain)
+            .execute(&mut *db)
+            .await
+            .map(|r| r.rows_affected() > 0)
+            .unwrap_or(false);
+        if inserted {
+            jobs::enqueue(db, JOB_KIND, &url).await;
+        }
+    }
+}
+
+/// Fetched previews for the URLs found in any of `texts`.
+pub async fn previews_for(db: &mut SqliteConnection, texts: &[&str]) -> Vec<LinkPreview> {
+    let mut urls: Vec<String> = Vec::new();
+    for text in texts {
+        for url in extract_urls(text) {
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+    }
+    if urls.is_empty() {
+        return Vec::new();
+    }
+
+    let query = format!(
+        "SELECT * FROM link_previews WHERE status = 'ok' AND url IN ({})",
+        vec!["?"; urls.len()].join(", ")
+    );
+    let mut q = sqlx::query_as::<_, LinkPreview>(&query);
+    for url in &urls {
+        q = q.bind(url);
+    }
+    q.fetch_all(&mut *db).await.unwrap_or_default()
+}
+
+/// Job handler: fetch the page and store its title and favicon.
+pub async fn fetch(pool: &SqlitePool, client: &outbound::Client, url: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let result = async {
+        let mut response = client.get(url, FETCH_TIMEOUT).await?;
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= MAX_FETCH_BYTES {
+                break;
+            }
+        }
+        Ok::<_, FetchError>(String::from_utf8_lossy(&body).into_owned())
+    }
+    .await;
+
+    let html = match result {
+        Ok(html) => html,
+        Err(e) => {
+            sqlx::query("UPDATE link_previews SET status = 'failed', fetched_at = CURRENT_TIMESTAMP WHERE url = ?")
+                .bind(url)
+                .execute(pool)
+                .await?;
+            return Err(e.into());
+        }
+    };
+
+    let (title, favicon) = parse_head(&html);
+    let favicon_url = Some(resolve(url, favicon.as_deref().unwrap_or("/favicon.ico")));
+    sqlx::query("UPDATE link_previews SET title = ?, favicon_url = ?, status = 'ok', fetched_at = CURRENT_TIMESTAMP WHERE url = ?")
+        .bind(title)
+        .bind(favicon_url)
+        .bind(url)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+// Resolve a possibly relative href against the page URL
+fn resolve(page_url: &str, href: &str) -> String {
+    if href.starts_with("http://") || href.starts_with("https://") {
+        href.to_string()
+    } else if let Some(rest) = href.strip_prefix("//") {
+        let scheme = page_url.split("://").next().unwrap_or("https");
+        format!("{}://{}", scheme, rest)
+    } else if href.starts_with('/') {
+        format!("{}{}", origin(page_url), href)
+    } else {
+        let base = match page_url.rfind('/') {
+            Some(i) if i > page_url.find("://").map(|s| s + 2).unwrap_or(0) => &page_url[..i],
+            _ => origin(page_url),
+        };
+        format!("{}/{}", base, href)
+    }
+}
+
+// The page title (og:title preferred) and favicon href from an HTML document
+fn parse_head(html: &str) -> (Option<String>, Option<String>) {
+    // ASCII lowercasing keeps byte offsets, so matches index straight into `html`
+    let lower = html.to_ascii_lowercase();
+    let mut og_title = None;
+    let mut favicon = None;
+
+    for tag in tags(html, &lower, "<meta") {
+        if attr(tag, "property").as_deref() == Some("og:title") {
+            og_title = og_title.or_else(|| attr(tag, "content"));
+        }
+    }
+    for tag in tags(html, &lower, "<link") {
+        let is_icon = attr(tag, "rel").is_some_and(|rel| rel.to_ascii_lowercase().split_whitespace().any(|r| r == "icon"));
+        if is_icon && favicon.is_none() {
+            favicon = attr(tag, "href");
+        }
+    }
+
+    let title_tag = lower.find("<title").and_then(|start| {
+        let open_end = start + lower[start..].find('>')? + 1;
+        let close = open_end + lower[open_end..].find("</title")?;
+        Some(html[open_end..close].to_string())
+    });
+
+    let title = og_title
+        .or(title_tag)
+        .map(|t| decode_entities(&t).split_whitespace().collect::<Vec<_>>().join(" "))
+        .map(|t| t.chars().take(MAX_TITLE_CHARS).collect::<String>())
+        .filter(|t| !t.is_empty());
+    (title, favicon.map(|f| decode_entities(&f)))
+}
+
+// Every `<name ...>` tag in the document (case-insensitive)
+fn tags<'a>(html: &'a str, lower: &str, name: &str) -> Vec<&'a str> {
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(i) = lower[from..].find(name) {
+        let start = from + i;
+        let Some(len) = lower[start..].find('>') else { break };
+        found.push(&html[start..start + len]);
+        from = start + len;
+    }
+    found
+}
+
+// Value of attribute `name` in a tag, quoted or not
+fn attr(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(i) = lower[from..].find(name) {
+        let start = from + i;
+        from = start + name.len();
+        // Must be a whole attribute name followed by '='
+        let before_ok = start > 0 && lower.as_bytes()[start - 1].is_ascii_whitespace();
+        let rest = lower[from..].trim_start();
+        if !before_ok || !rest.starts_with('=') {
+            continue;
+        }
+        let value_start = tag.len() - rest.len() + 1;
+        let value = tag[value_start..].trim_start();
+        return Some(match value.chars().next() {
+            Some(q @ ('"' | '\'')) => value[1..].split(q).next().unwrap_or("").to_string(),
+            _ => value.split_whitespace().next().unwrap_or("").to_string(),
+        });
+    }
+    None
+}
+
+fn decode_entities(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_urls() {
+        assert_eq!(
+            extract_urls("讲义见 https://example.com/notes.pdf，答案（https://a.b/c?d=1）. Also https://example.com/notes.pdf."),
+            vec!["https://example.com/notes.pdf", "https://a.b/c?d=1"]
+        );
+        assert!(extract_urls("no links, ftp://x.y, http://").is_empty());
+    }
+
+    #[test]
+    fn test_parse_head() {
+        let html = r#"<html><HEAD><Title>
+            Lecture 3 &amp; Notes
+        </title><link rel="shortcut icon" href="/static/fav.png"><meta name=x></head>"#;
+        assert_eq!(parse_head(html), (Some("Lecture 3 & Notes".to_string()), Some("/static/fav.png".to_string())));
+
+        let html = r#"<meta property="og:title" content='Video title'><title>YouTube</title><link rel=icon href=//cdn.example.com/i.ico>"#;
+        assert_eq!(parse_head(html), (Some("Video title".to_string()), Some("//cdn.example.com/i.ico".to_string())));
+
+        assert_eq!(parse_head("plain text"), (None, None));
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve("https://example.com/a/b", "/fav.ico"), "https://example.com/fav.ico");
+        assert_eq!(resolve("https://example.com/a/b", "fav.ico"), "https://example.com/a/fav.ico");
+        assert_eq!(resolve("https://example.com", "fav.ico"), "https://example.com/fav.ico");
+        assert_eq!(resolve("http://example.com/a", "//cdn.x/i.ico"), "http://cdn.x/i.ico");
+        assert_eq!(resolve("https://example.com/a", "https://other/i.ico"), "https://other/i.ico");
+    }
+}
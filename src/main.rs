@@ -2,24 +2,77 @@
 
 mod db;
 mod models;
+mod queries;
 mod routes;
 mod auth;
+mod access;
+mod api_limits;
 mod translate;
+mod api;
+mod config;
+mod pdf;
+mod grades;
+mod seed;
+mod review;
+mod cors;
+mod uploads;
+mod jobs;
+mod link_preview;
+mod outbound;
+mod settings;
+mod category_cache;
+mod scan;
+mod zip;
+mod export;
+mod public_cache;
+mod branding;
+mod email;
+mod digest;
+mod todos;
+mod telegram;
+mod integrity;
+mod query_stats;
+mod video;
+mod planner;
+mod difficulty;
+mod embed;
+mod stats;
+mod page_views;
+mod account;
+mod wrapped;
+mod categories;
+mod url_import;
+mod notes_import;
+mod recategorize;
+mod dev;
+mod assets;
+mod webhook;
+
+#[cfg(test)]
+mod integration_tests;
 
 use rocket_db_pools::Database;
 use db::Db;
 
 use rocket::fairing::AdHoc;
+use rocket::{Build, Rocket};
 
-use rocket::fs::FileServer;
-
-#[launch]
-fn rocket() -> _ {
+fn rocket() -> Rocket<Build> {
     rocket::build()
         .attach(Db::init())
+        .attach(AdHoc::config::<config::AppConfig>())
+        .attach(outbound::fairing())
+        .manage(category_cache::CategoryCache::default())
+        .manage(public_cache::PublicPageCache::default())
+        .attach(cors::Cors)
+        .attach(api_limits::RateLimitHeaders)
+        .attach(query_stats::QueryStats)
+        .attach(stats::RequestCounter)
+        .attach(page_views::ViewCounter)
+        .attach(jobs::worker())
         .attach(AdHoc::try_on_ignite("SQLx Migrations", |rocket| async {
             let db = Db::fetch(&rocket).expect("database connection");
-            match sqlx::migrate!().run(&**db).await {
+            match sqlx::migrate!().run(&***db).await {
                 Ok(_) => Ok(rocket),
                 Err(e) => {
                     eprintln!("Failed to initialize SQLx migrations: {}", e);
@@ -27,6 +80,65 @@ fn rocket() -> _ {
                 }
             }
         }))
+        .attach(branding::loader())
+        .attach(dev::fairing())
         .mount("/", routes::routes())
-        .mount("/uploads", FileServer::from("uploads"))
+        .mount("/api/v1", api::routes())
+        .mount("/integrations", telegram::routes())
+        .mount("/static", assets::routes())
+        .register("/", routes::catchers())
+}
+
+#[rocket::main]
+async fn main() -> Result<(), Box<rocket::Error>> {
+    // `zhixi seed`: ignite (connects and migrates the configured database), seed, exit
+    if std::env::args().nth(1).as_deref() == Some("seed") {
+        let rocket = rocket().ignite().await?;
+        let db = Db::fetch(&rocket).expect("database connection");
+        seed::run(db).await;
+        return Ok(());
+    }
+
+    // `zhixi integrity [--repair]`: check the configured database for leftover rows
+    if std::env::args().nth(1).as_deref() == Some("integrity") {
+        let rocket = rocket().ignite().await?;
+        let db = Db::fetch(&rocket).expect("database connection");
+        let repair = std::env::args().any(|arg| arg == "--repair");
+        integrity::run(db, repair).await;
+        return Ok(());
+    }
+
+    // `zhixi normalize-categories`: tidy up category names from before normalization
+    if std::env::args().nth(1).as_deref() == Some("normalize-categories") {
+        let rocket = rocket().ignite().await?;
+        let db = Db::fetch(&rocket).expect("database connection");
+        let category_cache = rocket.state::<category_cache::CategoryCache>().expect("category cache");
+        categories::run(db, category_cache).await;
+        return Ok(());
+    }
+
+    // `zhixi shard-uploads`: move uploads from before content addressing into subdirectories
+    if std::env::args().nth(1).as_deref() == Some("shard-uploads") {
+        let rocket = rocket().ignite().await?;
+        let db = Db::fetch(&rocket).expect("database connection");
+        uploads::run(db).await;
+        return Ok(());
+    }
+
+    // `zhixi rekey`: encrypt the configured database with the key read from stdin
+    if std::env::args().nth(1).as_deref() == Some("rekey") {
+        let rocket = rocket().ignite().await?;
+        let db = Db::fetch(&rocket).expect("database connection");
+        let mut new_key = String::new();
+        std::io::stdin().read_line(&mut new_key).expect("new key on stdin");
+        let figment = rocket.figment().focus("databases.sqlite_logs");
+        match db::rekey(&figment, db, new_key.trim_end_matches(['\r', '\n'])).await {
+            Ok(path) => println!("Re-encrypted {}. Set `key` to the new key before starting zhixi again.", path.display()),
+            Err(e) => eprintln!("Rekey failed: {}", e),
+        }
+        return Ok(());
+    }
+
+    rocket().launch().await?;
+    Ok(())
 }
@@ -7,6 +7,8 @@ pub struct Semester {
     pub id: i64,
     pub name: String,
     pub created_at: String, // Simplified for now, can use chrono if needed
+    pub wrapped_published: bool,
+    pub wrapped_slug: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
@@ -19,6 +21,101 @@ pub struct Course {
     pub is_published: bool,
     pub public_slug: Option<String>,
     pub show_lecture_links: bool,
+    /// Public pages carry noindex and stay out of the sitemap
+    pub discourage_indexing: bool,
+    pub public_theme: String,
+    pub banner_url: Option<String>,
+    pub units: Option<f64>,
+    pub grade: Option<String>,
+    pub color: Option<String>,
+    /// UUIDv7 used instead of `id` in public and API URLs
+    pub public_id: String,
+    /// Subject area given to the LLM when translating, e.g. "real analysis"
+    pub translation_subject: Option<String>,
+    /// Key into TRANSLATION_LEVELS
+    pub translation_level: String,
+    /// Key into TRANSLATION_STYLES
+    pub translation_style: String,
+    /// Entered at /join to become a member; None while joining is off
+    pub join_code: Option<String>,
+    /// Key into COURSE_ROLES, given to everyone who joins by code
+    pub join_role: String,
+    /// The earlier course this one carries on from, e.g. 数学分析(一)
+    pub continues_course_id: Option<i64>,
+    /// Told about publishing and new content; see webhook.rs. Kept out of
+    /// exports, since the URL alone lets anyone post to the channel.
+    #[serde(default, skip_serializing)]
+    pub webhook_url: Option<String>,
+}
+
+// Public page themes: (key, label, accent color)
+pub const PUBLIC_THEMES: &[(&str, &str, &str)] = &[
+    ("default", "默认蓝", "#2563eb"),
+    ("crimson", "校园红", "#b91c1c"),
+    ("forest", "森林绿", "#15803d"),
+    ("violet", "紫罗兰", "#7c3aed"),
+    ("amber", "琥珀", "#b45309"),
+];
+
+pub const DEFAULT_COURSE_COLOR: &str = "#64748b";
+
+// Translation prompt options: (key, label, phrase used in the prompt)
+pub const TRANSLATION_LEVELS: &[(&str, &str, &str)] = &[
+    ("high_school", "高中", "high school"),
+    ("undergraduate", "本科", "undergraduate"),
+    ("graduate", "研究生", "graduate"),
+];
+
+pub const TRANSLATION_STYLES: &[(&str, &str, &str)] = &[
+    ("academic", "学术", "formal academic English with standard textbook terminology"),
+    ("concise", "简洁", "short, plain English suitable for headings"),
+    ("literal", "直译", "a close, literal rendering of the original"),
+];
+
+// Roles a course member can hold: (key, label)
+pub const COURSE_ROLES: &[(&str, &str)] = &[
+    ("viewer", "只读"),
+    ("editor", "可编辑"),
+];
+
+// Why a visitor reports a public problem: (key, English label, Chinese label)
+pub const REPORT_REASONS: &[(&str, &str, &str)] = &[
+    ("copyright", "Copyrighted material", "侵犯版权"),
+    ("personal", "Personal information", "泄露个人信息"),
+    ("inappropriate", "Inappropriate content", "不当内容"),
+    ("other", "Something else", "其他"),
+];
+
+impl Course {
+    pub fn display_color(&self) -> &str {
+        self.color.as_deref().unwrap_or(DEFAULT_COURSE_COLOR)
+    }
+
+    pub fn theme_accent(&self) -> &'static str {
+        PUBLIC_THEMES
+            .iter()
+            .find(|(key, _, _)| *key == self.public_theme)
+            .unwrap_or(&PUBLIC_THEMES[0])
+            .2
+    }
+
+    /// The course description passed to the translation prompt.
+    pub fn translation_context(&self) -> String {
+        let subject = match self.translation_subject.as_deref().map(str::trim) {
+            Some(subject) if !subject.is_empty() => subject.to_string(),
+            _ => format!("{} {}", self.code, self.title),
+        };
+        format!(
+            "{}, {} level; use {}",
+            subject,
+            option_phrase(TRANSLATION_LEVELS, &self.translation_level),
+            option_phrase(TRANSLATION_STYLES, &self.translation_style)
+        )
+    }
+}
+
+fn option_phrase(options: &'static [(&str, &str, &'static str)], key: &str) -> &'static str {
+    options.iter().find(|(k, _, _)| *k == key).unwrap_or(&options[0]).2
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
@@ -31,6 +128,126 @@ pub struct LogItem {
     pub description: Option<String>,
     pub link: Option<String>,
     pub date: Option<String>,
+    // Missing from history snapshots taken before public ids existed
+    #[serde(default)]
+    pub public_id: String,
+    /// One of LOG_ITEM_STATUSES, or None when the item isn't tracked as a task
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+/// Task progress in the order the status toggle steps through it
+pub const LOG_ITEM_STATUSES: &[(&str, &str)] = &[("todo", "待办"), ("in_progress", "进行中"), ("done", "已完成"), ("submitted", "已提交")];
+
+// Kinds offered the status toggle before they have a status
+const TASK_KINDS: &[&str] = &["Homework", "Lab"];
+
+pub fn status_label(status: Option<&str>) -> &'static str {
+    LOG_ITEM_STATUSES.iter().find(|(s, _)| Some(*s) == status).map(|(_, label)| *label).unwrap_or("标记待办")
+}
+
+// A point in a log item's video, see video.rs
+#[derive(Debug, Clone, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct LogItemBookmark {
+    pub id: i64,
+    pub log_item_id: i64,
+    pub seconds: i64,
+    pub note: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct LogItemKind {
+    pub course_id: i64,
+    pub kind: String,
+    pub position: i64,
+    pub enabled: bool,
+}
+
+impl LogItem {
+    pub fn kind_label(&self) -> &'static str {
+        kind_label_zh(&self.kind)
+    }
+
+    /// Shows the status toggle: task kinds, and anything already given a status
+    pub fn is_task(&self) -> bool {
+        self.status.is_some() || TASK_KINDS.contains(&self.kind.as_str())
+    }
+
+    pub fn status_label(&self) -> &'static str {
+        status_label(self.status.as_deref())
+    }
+
+    pub fn status_class(&self) -> &'static str {
+        match self.status.as_deref() {
+            Some("todo") => "bg-industrial-700 text-industrial-200",
+            Some("in_progress") => "bg-yellow-500/20 text-yellow-300",
+            Some("done") => "bg-emerald-500/20 text-emerald-300",
+            Some("submitted") => "bg-blue-500/20 text-blue-300",
+            _ => "border border-dashed border-industrial-600 text-industrial-500",
+        }
+    }
+
+    /// The status after one click of the toggle; past "submitted" it stops tracking
+    pub fn next_status(&self) -> Option<&'static str> {
+        match self.status.as_deref() {
+            None => Some(LOG_ITEM_STATUSES[0].0),
+            Some(status) => LOG_ITEM_STATUSES.iter().skip_while(|(s, _)| *s != status).nth(1).map(|(s, _)| *s),
+        }
+    }
+
+    /// For the course log's status filter: "open" (todo or in progress),
+    /// "finished" (done or submitted), or a single status.
+    pub fn matches_status(&self, filter: &str) -> bool {
+        match (filter, self.status.as_deref()) {
+            ("open", Some(status)) => status == "todo" || status == "in_progress",
+            ("finished", Some(status)) => status == "done" || status == "submitted",
+            (filter, status) => status == Some(filter),
+        }
+    }
+
+    /// Whether the link can carry timestamped bookmarks
+    pub fn has_video_link(&self) -> bool {
+        self.link.as_deref().is_some_and(crate::video::is_video)
+    }
+
+    // One-character badge for the timeline, e.g. "讲" for a lecture
+    pub fn kind_initial(&self) -> char {
+        self.kind_label().chars().next().unwrap_or('?')
+    }
+
+    // Matches the left-border colours of the log item cards
+    pub fn kind_color(&self) -> &'static str {
+        match self.kind.as_str() {
+            "Lecture" => "bg-blue-500",
+            "Discussion" => "bg-emerald-500",
+            "Lab" => "bg-purple-500",
+            "Homework" => "bg-orange-500",
+            "Midterm" => "bg-red-500",
+            "Quiz" => "bg-yellow-500",
+            _ => "bg-gray-500",
+        }
+    }
+}
+
+impl LogItemKind {
+    pub fn label(&self) -> &'static str {
+        kind_label_zh(&self.kind)
+    }
+}
+
+pub fn kind_label_zh(kind: &str) -> &'static str {
+    match kind {
+        "Lecture" => "讲座",
+        "Discussion" => "讨论",
+        "Lab" => "实验",
+        "Homework" => "作业",
+        "Midterm" => "期中",
+        "Quiz" => "测验",
+        "Exam" => "考卷",
+        _ => "其他",
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
@@ -39,6 +256,32 @@ pub struct Category {
     pub id: i64,
     pub course_id: i64,
     pub name: String,
+    /// The enclosing category; None at the top level
+    #[serde(default)]
+    pub parent_id: Option<i64>,
+    /// "微积分/泰勒级数", from the category_paths view
+    #[serde(default)]
+    #[sqlx(default)]
+    pub path: String,
+    /// 0 at the top level
+    #[serde(default)]
+    #[sqlx(default)]
+    pub depth: i64,
+}
+
+// A category of the previous course and the category of the continuing course
+// it carries over into, if any
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CategoryCarryover {
+    pub previous: Category,
+    pub category_id: Option<i64>,
+}
+
+impl CategoryCarryover {
+    pub fn maps_to(&self, category_id: &i64) -> bool {
+        self.category_id == Some(*category_id)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
@@ -49,6 +292,7 @@ pub struct Exam {
     pub title: String,
     pub semester: Option<String>,
     pub link: Option<String>,
+    pub exam_date: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
@@ -60,8 +304,24 @@ pub struct Problem {
     pub description: String,
     pub notes: Option<String>,
     pub image_url: Option<String>,
+    /// The problem typed in, for problems without a screenshot
+    #[serde(default)]
+    pub body: Option<String>,
     pub solution_link: Option<String>,
+    pub answer: Option<String>,
     pub is_incorrect: bool,
+    pub original_filename: Option<String>,
+    pub mime_type: Option<String>,
+    /// Where the image was downloaded from, if it was imported by URL
+    #[serde(default)]
+    pub imported_from: Option<String>,
+    pub public_id: String,
+    /// Numbered within its course, shown as P-042; see `problem_label`
+    #[serde(default)]
+    pub number: Option<i64>,
+    /// The member writing up the solution
+    #[serde(default)]
+    pub assigned_to: Option<i64>,
 }
 
 // Helper struct for joining problems with their categories
@@ -74,10 +334,110 @@ pub struct ProblemWithCategories {
     pub description: String,
     pub notes: Option<String>,
     pub image_url: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub body: Option<String>,
     pub solution_link: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub answer: Option<String>,
     pub category_names: Option<String>, // Comma separated list from group_concat
     pub source_kind: String, // From joined log_item
     pub source_title: String, // From joined log_item
+    #[serde(default)]
+    pub source_url: String, // Deep link to the log item or exam on its course page
+    #[serde(default)]
+    #[sqlx(default)]
+    pub original_filename: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub mime_type: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub imported_from: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub public_id: String,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub number: Option<i64>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub assigned_to: Option<i64>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub assignee_name: Option<String>,
+    /// 0–100 from review history; see difficulty.rs
+    #[serde(default)]
+    #[sqlx(default)]
+    pub difficulty: Option<i64>,
+    /// Past exam a log item's problem also appeared on
+    #[serde(default)]
+    #[sqlx(default)]
+    pub exam_title: Option<String>,
+}
+
+// Screenshots (and uploads from before MIME types were recorded) render inline;
+// anything else, e.g. a PDF handout, is shown as a file link.
+fn is_image_upload(mime_type: &Option<String>) -> bool {
+    mime_type.as_deref().is_none_or(|m| m.starts_with("image/"))
+}
+
+// The original filename, or the stored name for older uploads
+fn upload_file_name<'a>(original_filename: &'a Option<String>, image_url: &'a Option<String>) -> &'a str {
+    match (original_filename, image_url) {
+        (Some(name), _) => name,
+        (None, Some(url)) => url.rsplit('/').next().unwrap_or(url),
+        (None, None) => "",
+    }
+}
+
+/// "P-042": how a problem is referred to within its course
+pub fn problem_label(number: i64) -> String {
+    format!("P-{:03}", number)
+}
+
+/// The number in a problem label as typed in a search: "P-042", "p42" or
+/// "P 42". A bare number isn't taken as a label, so searching "42" still
+/// finds notes mentioning it.
+pub fn parse_problem_label(query: &str) -> Option<i64> {
+    let rest = query.trim().strip_prefix(['P', 'p'])?;
+    let digits = rest.trim_start_matches(['-', ' ']);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+impl ProblemWithCategories {
+    /// "P-042", or "#id" for a problem outside any course
+    pub fn label(&self) -> String {
+        self.number.map_or_else(|| format!("#{}", self.id), problem_label)
+    }
+
+    pub fn is_image(&self) -> bool {
+        is_image_upload(&self.mime_type)
+    }
+
+    pub fn file_name(&self) -> &str {
+        upload_file_name(&self.original_filename, &self.image_url)
+    }
+
+    pub fn has_solution(&self) -> bool {
+        self.answer.is_some() || self.solution_link.as_deref().is_some_and(|link| !link.is_empty())
+    }
+
+    pub fn difficulty_label(&self) -> Option<&'static str> {
+        self.difficulty.map(crate::difficulty::label)
+    }
+
+    /// Each category as "微积分 › 泰勒级数"
+    pub fn category_breadcrumbs(&self) -> Vec<String> {
+        self.category_names
+            .as_deref()
+            .map(|names| names.split(',').map(|path| path.replace('/', " › ")).collect())
+            .unwrap_or_default()
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
@@ -86,6 +446,454 @@ pub struct User {
     pub id: i64,
     pub username: String,
     pub password_hash: String,
+    pub is_admin: bool,
+    pub email: Option<String>,
+    /// Set once the link mailed to `email` is opened
+    pub email_verified: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct Invite {
+    pub code: String,
+    pub created_by: i64,
+    pub created_at: String,
+    pub used_by: Option<i64>,
+    pub used_at: Option<String>,
+    pub used_by_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct CourseMember {
+    pub user_id: i64,
+    pub username: String,
+    pub role: String,
+    pub joined_at: String,
+}
+
+impl CourseMember {
+    pub fn role_label(&self) -> &'static str {
+        COURSE_ROLES.iter().find(|(key, _)| *key == self.role).map_or("只读", |(_, label)| label)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct DiscussionThread {
+    pub id: i64,
+    pub course_id: i64,
+    pub title: String,
+    pub author: String,
+    pub created_at: String,
+    pub post_count: i64,
+    pub last_post_at: String,
+    /// Has posts the current user hasn't seen
+    pub unread: bool,
+    /// Started by the current user
+    pub mine: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct DiscussionPost {
+    pub id: i64,
+    pub thread_id: i64,
+    pub author: String,
+    pub body: String,
+    pub created_at: String,
+    /// Written by the current user
+    pub mine: bool,
+}
+
+// An open report against a problem on a public page, with what the admin
+// needs to judge it
+#[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct ContentReport {
+    pub id: i64,
+    pub course_id: i64,
+    pub course_code: String,
+    pub public_slug: Option<String>,
+    pub is_published: bool,
+    pub problem_public_id: String,
+    pub problem_number: Option<i64>,
+    pub image_url: Option<String>,
+    pub body: Option<String>,
+    pub reason: String,
+    pub details: Option<String>,
+    pub created_at: String,
+}
+
+impl ContentReport {
+    pub fn reason_label(&self) -> &'static str {
+        REPORT_REASONS.iter().find(|(key, _, _)| *key == self.reason).map_or("其他", |(_, _, label)| label)
+    }
+
+    pub fn problem_label(&self) -> String {
+        self.problem_number.map_or_else(|| "题目".to_string(), problem_label)
+    }
+
+    /// Where the problem can be seen, while the course is still published
+    pub fn public_link(&self) -> Option<String> {
+        let slug = self.public_slug.as_deref().filter(|_| self.is_published)?;
+        Some(format!("/p/{}/problems#problem-{}", slug, self.problem_public_id))
+    }
+}
+
+// Opt-in weekly digest email; see digest.rs
+#[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct DigestSubscription {
+    pub user_id: i64,
+    pub email: String,
+    /// 1 = Monday … 7 = Sunday
+    pub weekday: i64,
+    /// Hour of the day, server local time
+    pub hour: i64,
+    pub unsubscribe_token: String,
+    pub last_sent_at: Option<String>,
+}
+
+pub const WEEKDAYS: &[(i64, &str)] = &[(1, "周一"), (2, "周二"), (3, "周三"), (4, "周四"), (5, "周五"), (6, "周六"), (7, "周日")];
+
+// Last-used study page filters, persisted per user per course in user_preferences
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct StudyFilters {
+    pub sources: Vec<String>,
+    pub categories: Vec<i64>,
+    pub sort: String,
+    /// A difficulty::LEVELS key, "unrated", or empty for any
+    #[serde(default)]
+    pub difficulty: String,
+    /// Also list the problems of the courses this one continues
+    #[serde(default)]
+    pub include_previous: bool,
+    /// Only problems from the log items this exam covers (exam_log_items)
+    #[serde(default)]
+    pub exam: Option<i64>,
+    /// A problem label like P-042, or text to find in the notes, problem or
+    /// answer. Only for the request at hand, so never saved.
+    #[serde(default, skip_serializing)]
+    pub search: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct StudyPreset {
+    pub id: i64,
+    pub user_id: i64,
+    pub course_id: i64,
+    pub name: String,
+    pub filters: String,
+}
+
+// A per-category target with its progress filled in by STUDY_GOALS_QUERY
+#[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct StudyGoal {
+    pub id: i64,
+    pub user_id: i64,
+    pub course_id: i64,
+    pub category_id: i64,
+    pub category_name: String,
+    pub target: i64,
+    pub deadline: Option<String>,
+    pub created_at: String,
+    pub progress: i64,
+}
+
+impl StudyGoal {
+    pub fn percent(&self) -> i64 {
+        if self.target <= 0 {
+            return 100;
+        }
+        (self.progress * 100 / self.target).min(100)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.progress >= self.target
+    }
+}
+
+/// A category counts as slow when correct answers in it take this many times
+/// the course's average for correct answers.
+pub const SLOW_CATEGORY_FACTOR: f64 = 1.5;
+
+// Time per graded card in one of a course's categories, from study_reviews
+#[derive(Debug, Clone, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct CategoryTime {
+    pub category_id: i64,
+    pub category_name: String,
+    pub reviews: i64,
+    pub average_ms: f64,
+    /// Over reviews not graded "again"; None if there are none
+    pub correct_average_ms: Option<f64>,
+    /// The same average across all of the course's categories
+    pub course_correct_average_ms: Option<f64>,
+}
+
+impl CategoryTime {
+    pub fn average_label(&self) -> String {
+        duration_label(self.average_ms)
+    }
+
+    pub fn correct_average_label(&self) -> Option<String> {
+        self.correct_average_ms.map(duration_label)
+    }
+
+    /// Slow even when the answer was right
+    pub fn is_slow(&self) -> bool {
+        match (self.correct_average_ms, self.course_correct_average_ms) {
+            (Some(average), Some(course)) => average > course * SLOW_CATEGORY_FACTOR,
+            _ => false,
+        }
+    }
+}
+
+// "45 秒", "2 分 05 秒"
+fn duration_label(ms: f64) -> String {
+    let seconds = (ms / 1000.0).round() as i64;
+    if seconds < 60 {
+        format!("{} 秒", seconds)
+    } else {
+        format!("{} 分 {:02} 秒", seconds / 60, seconds % 60)
+    }
+}
+
+// A dated exam that hasn't happened yet, for the dashboard countdown
+#[derive(Debug, Clone, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct UpcomingExam {
+    pub id: i64,
+    pub course_id: i64,
+    pub course_code: String,
+    pub course_color: Option<String>,
+    pub title: String,
+    pub exam_date: String,
+    pub days_left: i64,
+}
+
+impl UpcomingExam {
+    pub fn display_color(&self) -> &str {
+        self.course_color.as_deref().unwrap_or(DEFAULT_COURSE_COLOR)
+    }
+}
+
+// One problem scheduled on one day of an exam study plan
+#[derive(Debug, Clone, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct PlanEntry {
+    pub problem_id: i64,
+    pub day: String,
+    pub description: String,
+    pub category_names: Option<String>,
+    /// Reviewed since the plan was made
+    pub done: bool,
+}
+
+pub struct PlanDay {
+    pub day: String,
+    /// "10月16日 周五"
+    pub label: String,
+    pub is_today: bool,
+    pub entries: Vec<PlanEntry>,
+}
+
+impl PlanDay {
+    pub fn done(&self) -> usize {
+        self.entries.iter().filter(|e| e.done).count()
+    }
+}
+
+// An unfinished log item for the dashboard's to-do list
+#[derive(Debug, Clone, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct OpenTask {
+    pub id: i64,
+    pub course_id: i64,
+    pub course_code: String,
+    pub course_color: Option<String>,
+    pub title: String,
+    pub date: Option<String>,
+    pub status: String,
+    pub overdue: bool,
+}
+
+impl OpenTask {
+    pub fn display_color(&self) -> &str {
+        self.course_color.as_deref().unwrap_or(DEFAULT_COURSE_COLOR)
+    }
+
+    pub fn status_label(&self) -> &'static str {
+        status_label(Some(&self.status))
+    }
+}
+
+/// Days without review after which a category counts as fully stale; never
+/// reviewed counts the same.
+pub const FOCUS_STALE_DAYS: f64 = 30.0;
+
+// A category worth studying next, for the dashboard's focus suggestions
+#[derive(Debug, Clone, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct FocusSuggestion {
+    pub course_id: i64,
+    pub course_code: String,
+    pub course_color: Option<String>,
+    pub category_id: i64,
+    pub category_name: String,
+    pub problems: i64,
+    pub incorrect: i64,
+    /// Since the user last reviewed a problem in the category; None if never
+    pub days_since_review: Option<f64>,
+}
+
+impl FocusSuggestion {
+    pub fn display_color(&self) -> &str {
+        self.course_color.as_deref().unwrap_or(DEFAULT_COURSE_COLOR)
+    }
+
+    pub fn incorrect_percent(&self) -> i64 {
+        if self.problems == 0 {
+            return 0;
+        }
+        self.incorrect * 100 / self.problems
+    }
+
+    /// Incorrect rate over review recency: high when many problems are wrong
+    /// and the category hasn't been looked at in a while.
+    pub fn score(&self) -> f64 {
+        let days = self.days_since_review.unwrap_or(FOCUS_STALE_DAYS).clamp(0.0, FOCUS_STALE_DAYS);
+        self.incorrect as f64 / self.problems.max(1) as f64 * (days + 1.0)
+    }
+
+    pub fn last_review_label(&self) -> String {
+        match self.days_since_review {
+            None => "从未复习".to_string(),
+            Some(days) if days < 1.0 => "今天复习过".to_string(),
+            Some(days) => format!("{} 天没复习", days as i64),
+        }
+    }
+}
+
+// Another problem linked to the one being viewed
+#[derive(Debug, Clone, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct RelatedProblem {
+    pub link_id: i64,
+    /// "prerequisite" (do it first), "variant" or "follow_up" (do it after)
+    pub relation: String,
+    pub problem_id: i64,
+    #[sqlx(default)]
+    pub number: Option<i64>,
+    pub source_title: String,
+}
+
+impl RelatedProblem {
+    pub fn label(&self) -> String {
+        self.number.map_or_else(|| format!("#{}", self.problem_id), problem_label)
+    }
+
+    pub fn relation_label(&self) -> &'static str {
+        match self.relation.as_str() {
+            "prerequisite" => "前置",
+            "follow_up" => "进阶",
+            _ => "变式",
+        }
+    }
+}
+
+// A user's name for categories called `alias`, in any course
+#[derive(Debug, Clone, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct CategoryAlias {
+    pub id: i64,
+    pub alias: String,
+    pub topic: String,
+}
+
+// One course's category under a cross-course topic
+#[derive(Debug, Clone, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct TopicMember {
+    pub topic: String,
+    pub course_id: i64,
+    pub course_code: String,
+    pub course_color: Option<String>,
+    pub category_id: i64,
+    pub category_name: String,
+    pub problems: i64,
+}
+
+impl TopicMember {
+    pub fn display_color(&self) -> &str {
+        self.course_color.as_deref().unwrap_or(DEFAULT_COURSE_COLOR)
+    }
+}
+
+// Categories that share a name once aliases are resolved
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct StudyTopic {
+    pub name: String,
+    pub members: Vec<TopicMember>,
+}
+
+impl StudyTopic {
+    pub fn problems(&self) -> i64 {
+        self.members.iter().map(|m| m.problems).sum()
+    }
+
+    /// Spans more than one course, which is what the topic is for
+    pub fn is_shared(&self) -> bool {
+        self.members.windows(2).any(|w| w[0].course_id != w[1].course_id)
+    }
+}
+
+// Before/after JSON snapshots of an edited or deleted log item or problem
+#[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct EntityHistory {
+    pub id: i64,
+    pub entity_type: String,
+    pub entity_id: i64,
+    pub course_id: Option<i64>,
+    pub action: String,
+    pub before_json: Option<String>,
+    pub after_json: Option<String>,
+    pub user_id: Option<i64>,
+    pub created_at: String,
+}
+
+// A problem as entity_history stores it: what the history page shows, plus
+// the rest of the row and the category ids, so a revert puts back exactly
+// what was there. Snapshots recorded before these were kept lack them.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ProblemSnapshot {
+    #[serde(flatten)]
+    pub problem: ProblemWithCategories,
+    #[serde(default)]
+    pub is_incorrect: Option<bool>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub category_ids: Option<Vec<i64>>,
+}
+
+// Title and favicon fetched for a URL found in a log item description
+#[derive(Debug, Clone, Deserialize, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct LinkPreview {
+    pub url: String,
+    pub domain: String,
+    pub title: Option<String>,
+    pub favicon_url: Option<String>,
+    pub status: String,
+    pub fetched_at: Option<String>,
 }
 
 // Public-facing structs (not FromRow — constructed in Rust logic)
@@ -93,24 +901,46 @@ pub struct User {
 #[derive(Debug, Clone, Serialize)]
 #[serde(crate = "rocket::serde")]
 pub struct PublicLogItem {
-    pub id: i64,
+    pub public_id: String,
     pub kind: String,
     pub title: String,
     pub description: Option<String>,
     pub date: Option<String>,
     pub link: Option<String>,
+    /// translate::text_key of a description still waiting for translation
+    pub description_key: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(crate = "rocket::serde")]
 pub struct PublicProblem {
-    pub id: i64,
+    pub public_id: String,
+    pub number: Option<i64>,
     pub image_url: Option<String>,
+    pub body: Option<String>,
     pub notes: Option<String>,
     pub category_names: Option<String>,
     pub source_kind: String,
     pub source_title: String,
     pub solution_link: Option<String>,
+    pub original_filename: Option<String>,
+    pub mime_type: Option<String>,
+    /// translate::text_key of notes still waiting for translation
+    pub notes_key: Option<String>,
+}
+
+impl PublicProblem {
+    pub fn label(&self) -> Option<String> {
+        self.number.map(problem_label)
+    }
+
+    pub fn is_image(&self) -> bool {
+        is_image_upload(&self.mime_type)
+    }
+
+    pub fn file_name(&self) -> &str {
+        upload_file_name(&self.original_filename, &self.image_url)
+    }
 }
 
 pub struct CalendarWeek {
@@ -119,3 +949,20 @@ pub struct CalendarWeek {
     pub end_date: String,
     pub items_by_kind: Vec<(String, Vec<PublicLogItem>)>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_problem_labels() {
+        assert_eq!(problem_label(42), "P-042");
+        assert_eq!(problem_label(1234), "P-1234");
+        assert_eq!(parse_problem_label(" P-042 "), Some(42));
+        assert_eq!(parse_problem_label("p42"), Some(42));
+        assert_eq!(parse_problem_label("P 7"), Some(7));
+        assert_eq!(parse_problem_label("42"), None);
+        assert_eq!(parse_problem_label("P-"), None);
+        assert_eq!(parse_problem_label("Picard"), None);
+    }
+}
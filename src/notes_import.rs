@@ -0,0 +1,345 @@
+use rocket::http::ContentType;
+use std::collections::BTreeMap;
+use crate::models::kind_label_zh;
+use crate::zip::{self, ZipError};
+
+// ========== Importing Notes ==========
+//
+// For moving notes kept elsewhere into a course: a ZIP of Markdown files and
+// their images, such as a Notion "Markdown & CSV" export or a zipped Obsidian
+// folder. Each .md file becomes a log item, and each image it embeds a
+// problem on that item with the image as its screenshot. Front matter
+// between `---` lines fills in the log item:
+//
+//     ---
+//     kind: Homework          (or 作业; anything unknown is Other)
+//     date: 2026-09-14
+//     title: HW3              (else the first "# " heading, else the file name)
+//     categories: 积分, 极限   (given to its problems)
+//     ---
+//
+// The rest of the note, less the embedded images, is the description.
+// Image links are resolved relative to the note, inside the archive;
+// Obsidian's `![[name.png]]` may also name an image anywhere in it. Links
+// that don't lead to an image in the archive stay in the text as written.
+
+/// Unpacked size an archive may reach
+pub const MAX_UNPACKED_BYTES: usize = 200 * 1024 * 1024;
+/// Files a note can make problems of, by extension
+const ATTACHMENT_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "pdf"];
+
+pub struct Archive {
+    notes: Vec<(String, String)>,
+    attachments: BTreeMap<String, Vec<u8>>,
+}
+
+pub struct Note {
+    pub kind: String,
+    pub title: String,
+    pub date: Option<String>,
+    pub description: Option<String>,
+    pub categories: Option<String>,
+    pub images: Vec<NoteImage>,
+}
+
+pub struct NoteImage {
+    /// Path in the archive
+    pub path: String,
+    /// Alt text, kept as the problem's notes
+    pub alt: Option<String>,
+}
+
+/// Unpack an uploaded archive, or say what's wrong with it.
+pub fn open(bytes: &[u8]) -> Result<Archive, &'static str> {
+    let entries = zip::read(bytes, MAX_UNPACKED_BYTES).map_err(|e| match e {
+        ZipError::NotZip => "文件不是 ZIP 压缩包。",
+        ZipError::Unsupported => "不支持这个压缩包：可能加密了，或使用了 ZIP64。",
+        ZipError::Corrupt => "压缩包已损坏，无法解压。",
+        ZipError::TooLarge => "压缩包解压后超过 200 MB。",
+    })?;
+
+    let mut notes = Vec::new();
+    let mut attachments = BTreeMap::new();
+    for entry in entries {
+        // macOS resource forks, .obsidian settings and the like
+        if entry.name.split('/').any(|part| part.starts_with('.') || part == "__MACOSX") {
+            continue;
+        }
+        match extension(&entry.name).as_deref() {
+            Some("md") | Some("markdown") => {
+                let text = String::from_utf8_lossy(&entry.data);
+                notes.push((entry.name, text.trim_start_matches('\u{feff}').to_string()));
+            }
+            Some(ext) if ATTACHMENT_EXTENSIONS.contains(&ext) => {
+                attachments.insert(entry.name, entry.data);
+            }
+            _ => {}
+        }
+    }
+    if notes.is_empty() {
+        return Err("压缩包里没有 Markdown 笔记（.md 文件）。");
+    }
+    notes.sort();
+    Ok(Archive { notes, attachments })
+}
+
+impl Archive {
+    /// Paths of the images and PDFs notes can embed
+    pub fn attachments(&self) -> impl Iterator<Item = &str> {
+        self.attachments.keys().map(String::as_str)
+    }
+
+    pub fn attachment(&self, path: &str) -> &[u8] {
+        &self.attachments[path]
+    }
+
+    /// Leave an attachment out, e.g. one the upload scan refuses
+    pub fn remove(&mut self, path: &str) {
+        self.attachments.remove(path);
+    }
+
+    /// The notes in path order, with kinds matched against `kinds`.
+    pub fn notes(&self, kinds: &[&str]) -> Vec<Note> {
+        self.notes.iter().map(|(path, text)| parse_note(path, text, kinds, |link, wiki| self.resolve(path, link, wiki))).collect()
+    }
+
+    fn resolve(&self, note_path: &str, link: &str, wiki: bool) -> Option<String> {
+        let dir = note_path.rsplit_once('/').map_or("", |(dir, _)| dir);
+        if let Some(path) = join(dir, link).filter(|path| self.attachments.contains_key(path)) {
+            return Some(path);
+        }
+        // Obsidian finds a bare name anywhere in the vault, if it's unique
+        if !wiki || link.contains('/') {
+            return None;
+        }
+        let mut found = self.attachments.keys().filter(|path| path.rsplit('/').next() == Some(link));
+        match (found.next(), found.next()) {
+            (Some(path), None) => Some(path.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// MIME type of an attachment, for the upload scan and the problem
+pub fn mime_type(path: &str) -> Option<String> {
+    extension(path)
+        .and_then(|ext| ContentType::from_extension(&ext))
+        .map(|ct| format!("{}/{}", ct.top(), ct.sub()))
+}
+
+/// Final component of an attachment's path, as its original filename
+pub fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn extension(path: &str) -> Option<String> {
+    file_name(path).rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase())
+}
+
+// `link` taken relative to the directory `dir`, with `.` and `..` followed;
+// None if it isn't a path in the archive
+fn join(dir: &str, link: &str) -> Option<String> {
+    if link.contains(':') || link.starts_with('/') {
+        return None;
+    }
+    let mut parts: Vec<&str> = dir.split('/').filter(|p| !p.is_empty()).collect();
+    for part in link.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            _ => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+// Notion writes spaces and non-ASCII names in links as %XX
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// `resolve(link, wiki)` gives the archive path of an embedded image, if
+// there is one there
+fn parse_note(path: &str, text: &str, kinds: &[&str], resolve: impl Fn(&str, bool) -> Option<String>) -> Note {
+    let (front_matter, body) = split_front_matter(text);
+    let field = |key: &str| front_matter.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.as_str()).filter(|v| !v.is_empty());
+
+    let kind = field("kind")
+        .and_then(|kind| kinds.iter().find(|k| k.eq_ignore_ascii_case(kind) || (**k != "Other" && kind_label_zh(k) == kind)))
+        .unwrap_or(&"Other")
+        .to_string();
+    let date = field("date")
+        .and_then(|date| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+        .map(|date| date.format("%Y-%m-%d").to_string());
+    let categories = field("categories").map(|c| c.trim_start_matches('[').trim_end_matches(']').to_string());
+
+    // A leading heading is the title, unless front matter already gave one
+    let mut lines: Vec<&str> = body.lines().collect();
+    let heading = lines.iter().position(|line| !line.trim().is_empty()).filter(|&i| lines[i].starts_with("# "));
+    let title = match (field("title"), heading) {
+        (Some(title), _) => title.to_string(),
+        (None, Some(i)) => lines.remove(i)[2..].trim().to_string(),
+        (None, None) => title_from_path(path),
+    };
+
+    let mut images = Vec::new();
+    let mut description = String::new();
+    for line in lines {
+        description.push_str(&take_images(line, &resolve, &mut images));
+        description.push('\n');
+    }
+    // Lines the images stood on leave gaps
+    while description.contains("\n\n\n") {
+        description = description.replace("\n\n\n", "\n\n");
+    }
+    let description = description.trim();
+
+    Note {
+        kind,
+        title,
+        date,
+        description: (!description.is_empty()).then(|| description.to_string()),
+        categories,
+        images,
+    }
+}
+
+// Front matter as (key, value) pairs, and the text after it
+fn split_front_matter(text: &str) -> (Vec<(String, String)>, &str) {
+    let Some(rest) = text.strip_prefix("---\n").or_else(|| text.strip_prefix("---\r\n")) else {
+        return (Vec::new(), text);
+    };
+    let mut fields = Vec::new();
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        let line = line.trim();
+        if line == "---" {
+            return (fields, &rest[offset..]);
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+            fields.push((key.trim().to_string(), value.to_string()));
+        }
+    }
+    // No closing line: it wasn't front matter
+    (Vec::new(), text)
+}
+
+// "HW3 1a2b….md" (Notion adds a 32-digit id) becomes "HW3"
+fn title_from_path(path: &str) -> String {
+    let name = file_name(path);
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    match stem.rsplit_once(' ') {
+        Some((title, id)) if id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()) => title.to_string(),
+        _ => stem.to_string(),
+    }
+}
+
+// `line` without the images `resolve` finds, which are added to `images`
+fn take_images(line: &str, resolve: &impl Fn(&str, bool) -> Option<String>, images: &mut Vec<NoteImage>) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find("![") {
+        out.push_str(&rest[..start]);
+        let embed = &rest[start..];
+        let found = if let Some(inner) = embed.strip_prefix("![[") {
+            // ![[name.png]] or ![[name.png|300]]
+            inner.find("]]").map(|end| {
+                let name = inner[..end].split('|').next().unwrap_or("").trim();
+                (resolve(name, true), None, 3 + end + 2)
+            })
+        } else {
+            // ![alt](link) or ![alt](<link with spaces> "title")
+            embed.find("](").and_then(|mid| {
+                let target = &embed[mid + 2..];
+                let end = target.find(')')?;
+                let link = target[..end].trim();
+                let link = match link.strip_prefix('<') {
+                    Some(link) => link.split('>').next().unwrap_or(""),
+                    None => link.split(' ').next().unwrap_or(""),
+                };
+                let alt = embed[2..mid].trim();
+                Some((resolve(&percent_decode(link), false), Some(alt.to_string()), mid + 2 + end + 1))
+            })
+        };
+        match found {
+            Some((Some(path), alt, len)) => {
+                // Notion uses the file name as the alt text
+                let alt = alt.filter(|alt| !alt.is_empty() && alt != file_name(&path));
+                images.push(NoteImage { path, alt });
+                rest = &embed[len..];
+            }
+            Some((None, _, len)) => {
+                out.push_str(&embed[..len]);
+                rest = &embed[len..];
+            }
+            None => {
+                out.push_str("![");
+                rest = &embed[2..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: &[&str] = &["Lecture", "Homework", "Other"];
+
+    fn resolve(link: &str, _wiki: bool) -> Option<String> {
+        join("notes", link).filter(|path| path.ends_with(".png"))
+    }
+
+    #[test]
+    fn test_front_matter() {
+        let text = "---\nkind: 作业\ndate: \"2026-09-14\"\ncategories: [积分, 极限]\n---\n# HW3\n\n第 2 题记错了符号。\n";
+        let note = parse_note("notes/hw3.md", text, KINDS, resolve);
+        assert_eq!((note.kind.as_str(), note.title.as_str()), ("Homework", "HW3"));
+        assert_eq!(note.date.as_deref(), Some("2026-09-14"));
+        assert_eq!(note.categories.as_deref(), Some("积分, 极限"));
+        assert_eq!(note.description.as_deref(), Some("第 2 题记错了符号。"));
+
+        let note = parse_note("Week 1 0123456789abcdef0123456789abcdef.md", "---\nkind: Seminar\ndate: Sept 14\n---\n", KINDS, resolve);
+        assert_eq!((note.kind.as_str(), note.title.as_str()), ("Other", "Week 1"));
+        assert_eq!((note.date, note.description), (None, None));
+    }
+
+    #[test]
+    fn test_images() {
+        let text = "# Quiz\n\n![第 3 题](img/q3%20a.png)\n\n![[q4.png|300]] see ![x](https://example.com/a.png)\n\n![](missing.txt)\n";
+        let note = parse_note("notes/quiz.md", text, KINDS, resolve);
+        let images: Vec<(&str, Option<&str>)> = note.images.iter().map(|i| (i.path.as_str(), i.alt.as_deref())).collect();
+        assert_eq!(images, [("notes/img/q3 a.png", Some("第 3 题")), ("notes/q4.png", None)]);
+        assert_eq!(note.description.as_deref(), Some("see ![x](https://example.com/a.png)\n\n![](missing.txt)"));
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(join("a/b", "../img/1.png").as_deref(), Some("a/img/1.png"));
+        assert_eq!(join("", "./1.png").as_deref(), Some("1.png"));
+        assert_eq!(join("a", "../../1.png"), None);
+        assert_eq!(join("a", "data:image/png;base64,AAAA"), None);
+    }
+}
@@ -0,0 +1,265 @@
+use rocket::fairing::AdHoc;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::{Action, Attempt, Policy};
+use reqwest::Url;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::config::{AppConfig, OutboundConfig};
+
+// ========== Fetching User-Supplied URLs ==========
+//
+// Link previews and image imports fetch whatever URL someone pastes, and
+// course webhooks post to one. Left
+// alone, that lets anyone signed in have the server read from its own
+// network: 127.0.0.1, the LAN, or a cloud metadata endpoint such as
+// 169.254.169.254. Client resolves every host itself and only connects to
+// public addresses, for the first request and for each redirect, so a public
+// name that resolves (or redirects) somewhere private is refused as well.
+// Hosts in `[default.outbound] allow_hosts` skip the check, for course sites
+// on the instance's own network. One Client is built at ignite and managed;
+// everything that fetches a user's URL goes through it.
+
+const MAX_REDIRECTS: usize = 5;
+const USER_AGENT: &str = "zhixi";
+
+/// A fetch refused because the host isn't a public address.
+#[derive(Debug)]
+pub struct Blocked(String);
+
+impl std::fmt::Display for Blocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is not a public address", self.0)
+    }
+}
+
+impl std::error::Error for Blocked {}
+
+#[derive(Debug)]
+pub enum FetchError {
+    Blocked(Blocked),
+    Http(reqwest::Error),
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::Blocked(blocked) => blocked.fmt(f),
+            FetchError::Http(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+// Refusals from the resolver or redirect policy come back wrapped in
+// reqwest's own error
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        let mut source = std::error::Error::source(&e);
+        while let Some(inner) = source {
+            if let Some(blocked) = inner.downcast_ref::<Blocked>() {
+                return FetchError::Blocked(Blocked(blocked.0.clone()));
+            }
+            source = inner.source();
+        }
+        FetchError::Http(e)
+    }
+}
+
+#[derive(Clone)]
+pub struct Client {
+    inner: reqwest::Client,
+    allow_hosts: Arc<Vec<String>>,
+}
+
+impl Client {
+    pub fn new(config: &OutboundConfig) -> Result<Client, FetchError> {
+        let allow_hosts: Arc<Vec<String>> = Arc::new(config.allow_hosts.iter().map(|h| h.to_ascii_lowercase()).collect());
+        let redirect_hosts = allow_hosts.clone();
+        let inner = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            // A proxy would resolve the host itself, out of our sight
+            .no_proxy()
+            .dns_resolver(Arc::new(PublicResolver { allow_hosts: allow_hosts.clone() }))
+            .redirect(Policy::custom(move |attempt: Attempt| -> Action {
+                if attempt.previous().len() >= MAX_REDIRECTS {
+                    return attempt.error("too many redirects");
+                }
+                match check_literal(attempt.url(), &redirect_hosts) {
+                    Ok(()) => attempt.follow(),
+                    Err(blocked) => attempt.error(blocked),
+                }
+            }))
+            .build()
+            .map_err(FetchError::Http)?;
+        Ok(Client { inner, allow_hosts })
+    }
+
+    /// GET `url`, which must be http(s) on a public address, giving up after
+    /// `timeout`; HTTP error statuses are errors too. A URL that doesn't parse
+    /// can't be checked, so it's refused along with the rest.
+    pub async fn get(&self, url: &str, timeout: Duration) -> Result<reqwest::Response, FetchError> {
+        let parsed = Url::parse(url).map_err(|_| FetchError::Blocked(Blocked(url.to_string())))?;
+        check_literal(&parsed, &self.allow_hosts).map_err(FetchError::Blocked)?;
+        Ok(self.inner.get(parsed).timeout(timeout).send().await?.error_for_status()?)
+    }
+
+    /// POST `body` as JSON to `url`, checked the same way as `get`.
+    pub async fn post<T: serde::Serialize + ?Sized>(&self, url: &str, body: &T, timeout: Duration) -> Result<reqwest::Response, FetchError> {
+        let parsed = Url::parse(url).map_err(|_| FetchError::Blocked(Blocked(url.to_string())))?;
+        check_literal(&parsed, &self.allow_hosts).map_err(FetchError::Blocked)?;
+        Ok(self.inner.post(parsed).json(body).timeout(timeout).send().await?.error_for_status()?)
+    }
+}
+
+/// Fairing that builds the Client from `[default.outbound]` and manages it.
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Outbound Client", |rocket| async {
+        let config = rocket.state::<AppConfig>().map(|config| config.outbound.clone()).unwrap_or_default();
+        match Client::new(&config) {
+            Ok(client) => Ok(rocket.manage(client)),
+            Err(e) => {
+                eprintln!("Failed to build the outbound HTTP client: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+// Hosts written as an IP address never reach the resolver, so they're checked
+// from the URL
+fn check_literal(url: &Url, allow_hosts: &[String]) -> Result<(), Blocked> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(Blocked(url.to_string()));
+    }
+    let host = url.host_str().unwrap_or("");
+    if allow_hosts.iter().any(|h| h == host) {
+        return Ok(());
+    }
+    match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) if !is_public(ip) => Err(Blocked(host.to_string())),
+        _ => Ok(()),
+    }
+}
+
+struct PublicResolver {
+    allow_hosts: Arc<Vec<String>>,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_ascii_lowercase();
+        let allowed = self.allow_hosts.contains(&host);
+        Box::pin(resolve_public(host, allowed))
+    }
+}
+
+async fn resolve_public(host: String, allowed: bool) -> Result<Addrs, Box<dyn std::error::Error + Send + Sync>> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+        .await?
+        .filter(|addr| allowed || is_public(addr.ip()))
+        .collect();
+    if addrs.is_empty() {
+        return Err(Box::new(Blocked(host)));
+    }
+    Ok(Box::new(addrs.into_iter()))
+}
+
+/// Whether `ip` is on the public internet: not loopback, private, link-local,
+/// shared (CGNAT), multicast or otherwise reserved.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_v4(mapped),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    // NAT64 (64:ff9b::/96) reaches whatever IPv4 address it embeds
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        return is_public_v4(Ipv4Addr::from(((segments[6] as u32) << 16) | segments[7] as u32));
+    }
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local fc00::/7 and link-local fe80::/10
+        || (segments[0] & 0xfe00) == 0xfc00
+        || (segments[0] & 0xffc0) == 0xfe80
+        // Documentation 2001:db8::/32
+        || segments[..2] == [0x2001, 0xdb8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_public() {
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1", "64:ff9b::a9fe:a9fe"] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["93.184.216.34", "8.8.8.8", "2606:4700::1111", "64:ff9b::808:808"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_check_literal() {
+        let check = |url: &str, allow: &[&str]| check_literal(&Url::parse(url).unwrap(), &allow.iter().map(|h| h.to_string()).collect::<Vec<_>>()).is_ok();
+        assert!(!check("http://127.0.0.1:8000/x", &[]));
+        assert!(!check("http://[::1]/x", &[]));
+        // Other spellings of an address are normalized by the URL parser
+        assert!(!check("http://2130706433/x", &[]));
+        assert!(!check("file:///etc/passwd", &[]));
+        assert!(check("http://127.0.0.1:8000/x", &["127.0.0.1"]));
+        assert!(check("https://example.com/x", &[]));
+    }
+
+    #[rocket::async_test]
+    async fn test_refuses_private_hosts() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        // Something that would answer, so only the check stands in the way
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request).await;
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok").await;
+            }
+        });
+
+        let client = Client::new(&OutboundConfig::default()).unwrap();
+        let timeout = Duration::from_secs(5);
+        for url in [format!("http://127.0.0.1:{}/", port), format!("http://localhost:{}/", port), "http://169.254.169.254/latest/meta-data/".to_string()] {
+            assert!(client.get(&url, timeout).await.is_err(), "{}", url);
+        }
+        assert!(matches!(client.get(&format!("http://127.0.0.1:{}/", port), timeout).await, Err(FetchError::Blocked(_))));
+        assert!(matches!(client.post(&format!("http://127.0.0.1:{}/", port), "{}", timeout).await, Err(FetchError::Blocked(_))));
+
+        let config = OutboundConfig { allow_hosts: vec!["127.0.0.1".to_string()] };
+        let client = Client::new(&config).unwrap();
+        assert_eq!(client.get(&format!("http://127.0.0.1:{}/", port), timeout).await.unwrap().text().await.unwrap(), "ok");
+    }
+}
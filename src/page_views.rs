@@ -0,0 +1,172 @@
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::http::{Method, Status};
+use rocket::{Build, Data, Request, Response, Rocket};
+use rocket_db_pools::sqlx::{self, SqliteConnection};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+// ========== Public Page Views ==========
+//
+// Course settings show the owner how many people looked at the public pages
+// in the last week. No cookie is set and nothing that identifies a visitor
+// is stored, so there is nothing to ask consent for.
+//
+// The `ViewCounter` fairing counts each page served from /p/<slug> into the
+// `Tally` it puts in managed state. A visitor is told apart for the day by a SHA-256 of a salt, the date, the
+// course and their IP address. The salt is drawn afresh each (UTC) day, and
+// the salt and hashes are only ever held in memory. The database gets each
+// course's totals per day in `course_views`, added by the request count
+// flush in stats.rs and when the settings page is opened. A restart forgets
+// who has been seen that day, so the visitor count is an estimate.
+
+// The routes counted as page views; feeds, embeds and the report form aren't
+const PAGES: &[&str] = &["/p/<slug>", "/p/<slug>/problems", "/p/<slug>/zh", "/p/<slug>/zh/problems"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Counts {
+    pub views: i64,
+    /// Distinct visitors each day, summed over the days
+    pub visitors: i64,
+}
+
+/// Views counted in memory and not yet written, shared by the fairing, the
+/// settings page and the flush job
+#[derive(Clone, Default)]
+pub struct Tally(Arc<Mutex<Counter>>);
+
+#[derive(Default)]
+struct Counter {
+    /// "YYYY-MM-DD" the salt and `seen` belong to
+    day: String,
+    salt: [u8; 16],
+    seen: BTreeSet<[u8; 32]>,
+    /// (day, slug) -> counts not yet written
+    pending: BTreeMap<(String, String), Counts>,
+}
+
+impl Counter {
+    fn record(&mut self, day: &str, slug: &str, ip: &str) {
+        if self.day != day {
+            self.day = day.to_string();
+            self.salt = *Uuid::new_v4().as_bytes();
+            self.seen.clear();
+        }
+        let visitor: [u8; 32] = Sha256::new()
+            .chain_update(self.salt)
+            .chain_update(format!("{}\0{}\0{}", day, slug, ip))
+            .finalize()
+            .into();
+        let first_visit = self.seen.insert(visitor);
+        let counts = self.pending.entry((day.to_string(), slug.to_string())).or_default();
+        counts.views += 1;
+        if first_visit {
+            counts.visitors += 1;
+        }
+    }
+}
+
+pub struct ViewCounter;
+
+// The method the request came in with. Rocket answers HEAD with the GET
+// route and reports GET from then on.
+struct Requested(Method);
+
+#[rocket::async_trait]
+impl Fairing for ViewCounter {
+    fn info(&self) -> Info {
+        Info { name: "Public Page View Counter", kind: Kind::Ignite | Kind::Request | Kind::Response }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        Ok(rocket.manage(Tally::default()))
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        let method = req.method();
+        req.local_cache(|| Requested(method));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let Requested(method) = req.local_cache(|| Requested(req.method()));
+        if *method != Method::Get || ![Status::Ok, Status::NotModified].contains(&res.status()) {
+            return;
+        }
+        let Some(route) = req.route() else { return };
+        if !PAGES.contains(&route.uri.unmounted_origin.path().as_str()) {
+            return;
+        }
+        let Some(Ok(slug)) = req.param::<&str>(1) else { return };
+        let Some(tally) = req.rocket().state::<Tally>() else { return };
+        let ip = req.client_ip().map(|ip| ip.to_string()).unwrap_or_default();
+        let day = chrono::Utc::now().format("%Y-%m-%d").to_string();
+        tally.0.lock().unwrap().record(&day, slug, &ip);
+    }
+}
+
+/// Add the views counted since the last flush to their courses' daily rows.
+/// Views of a slug that no longer names a course are dropped.
+pub async fn flush(db: &mut SqliteConnection, tally: &Tally) {
+    let pending = std::mem::take(&mut tally.0.lock().unwrap().pending);
+    for ((day, slug), counts) in pending {
+        let written = sqlx::query(
+            r#"
+            INSERT INTO course_views (course_id, day, views, visitors)
+            SELECT id, ?, ?, ? FROM courses WHERE public_slug = ?
+            ON CONFLICT (course_id, day) DO UPDATE SET views = views + excluded.views, visitors = visitors + excluded.visitors
+            "#,
+        )
+        .bind(&day)
+        .bind(counts.views)
+        .bind(counts.visitors)
+        .bind(&slug)
+        .execute(&mut *db)
+        .await;
+        if written.is_err() {
+            // Keep them for the next flush
+            let mut counter = tally.0.lock().unwrap();
+            let kept = counter.pending.entry((day, slug)).or_default();
+            kept.views += counts.views;
+            kept.visitors += counts.visitors;
+        }
+    }
+}
+
+/// Views of a course's public pages over the last seven days, today included.
+pub async fn this_week(db: &mut SqliteConnection, tally: &Tally, course_id: i64) -> Counts {
+    flush(db, tally).await;
+    let (views, visitors) = sqlx::query_as::<_, (i64, i64)>(
+        "SELECT COALESCE(SUM(views), 0), COALESCE(SUM(visitors), 0) FROM course_views WHERE course_id = ? AND day > date('now', '-7 days')"
+    )
+    .bind(course_id)
+    .fetch_one(&mut *db)
+    .await
+    .unwrap_or((0, 0));
+    Counts { views, visitors }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let mut tally = Counter::default();
+        tally.record("2026-10-15", "math-1a", "203.0.113.7");
+        tally.record("2026-10-15", "math-1a", "203.0.113.7");
+        tally.record("2026-10-15", "math-1a", "198.51.100.2");
+        tally.record("2026-10-15", "phys-7a", "203.0.113.7");
+        let counts = |tally: &Counter, day: &str, slug: &str| tally.pending.get(&(day.to_string(), slug.to_string())).copied();
+        assert_eq!(counts(&tally, "2026-10-15", "math-1a"), Some(Counts { views: 3, visitors: 2 }));
+        assert_eq!(counts(&tally, "2026-10-15", "phys-7a"), Some(Counts { views: 1, visitors: 1 }));
+
+        // A new day starts over with a new salt; yesterday's counts wait to be written
+        let salt = tally.salt;
+        tally.record("2026-10-16", "math-1a", "203.0.113.7");
+        assert_ne!(tally.salt, salt);
+        assert_eq!(tally.seen.len(), 1);
+        assert_eq!(counts(&tally, "2026-10-16", "math-1a"), Some(Counts { views: 1, visitors: 1 }));
+        assert_eq!(counts(&tally, "2026-10-15", "math-1a"), Some(Counts { views: 3, visitors: 2 }));
+    }
+}
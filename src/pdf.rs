@@ -0,0 +1,126 @@
+// ========== Minimal PDF Writer ==========
+//
+// Just enough PDF to lay out text and filled rectangles (bar charts) on A4
+// pages. Text uses the predefined Adobe-GB1 CID font STSong-Light with the
+// UniGB-UCS2-H CMap, which every mainstream viewer ships, so Chinese renders
+// without embedding a font file.
+
+pub const PAGE_WIDTH: f32 = 595.0;
+pub const PAGE_HEIGHT: f32 = 842.0;
+
+pub struct PdfDoc {
+    pages: Vec<String>,
+}
+
+impl Default for PdfDoc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PdfDoc {
+    pub fn new() -> Self {
+        PdfDoc { pages: vec![String::new()] }
+    }
+
+    pub fn new_page(&mut self) {
+        self.pages.push(String::new());
+    }
+
+    fn current(&mut self) -> &mut String {
+        self.pages.last_mut().unwrap()
+    }
+
+    /// Draw `text` with its baseline at (x, y), measured from the bottom-left corner.
+    pub fn text(&mut self, x: f32, y: f32, size: f32, text: &str) {
+        let hex = encode_ucs2_hex(text);
+        self.current().push_str(&format!("BT /F1 {} Tf {:.2} {:.2} Td <{}> Tj ET\n", size, x, y, hex));
+    }
+
+    /// Fill a rectangle with a gray level between 0.0 (black) and 1.0 (white).
+    pub fn rect(&mut self, x: f32, y: f32, w: f32, h: f32, gray: f32) {
+        self.current().push_str(&format!("{:.2} g {:.2} {:.2} {:.2} {:.2} re f 0 g\n", gray, x, y, w, h));
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Object layout: 1 catalog, 2 pages, 3 Type0 font, 4 CID font, 5 descriptor,
+        // then a (page, content) pair per page.
+        let page_count = self.pages.len();
+        let mut objects: Vec<String> = Vec::new();
+        objects.push("<< /Type /Catalog /Pages 2 0 R >>".to_string());
+        let kids: Vec<String> = (0..page_count).map(|i| format!("{} 0 R", 6 + i * 2)).collect();
+        objects.push(format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), page_count));
+        objects.push("<< /Type /Font /Subtype /Type0 /BaseFont /STSong-Light /Encoding /UniGB-UCS2-H /DescendantFonts [4 0 R] >>".to_string());
+        objects.push("<< /Type /Font /Subtype /CIDFontType0 /BaseFont /STSong-Light /CIDSystemInfo << /Registry (Adobe) /Ordering (GB1) /Supplement 4 >> /FontDescriptor 5 0 R /DW 1000 /W [1 95 500] >>".to_string());
+        objects.push("<< /Type /FontDescriptor /FontName /STSong-Light /Flags 6 /FontBBox [-25 -254 1000 880] /ItalicAngle 0 /Ascent 880 /Descent -120 /CapHeight 880 /StemV 93 >>".to_string());
+        for (i, content) in self.pages.iter().enumerate() {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH, PAGE_HEIGHT, 7 + i * 2
+            ));
+            objects.push(format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content));
+        }
+
+        let mut out: Vec<u8> = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, obj) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, obj).as_bytes());
+        }
+        let xref_start = out.len();
+        out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        out.extend_from_slice(
+            format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref_start).as_bytes(),
+        );
+        out
+    }
+}
+
+/// Approximate rendered width: CJK glyphs are full-width, ASCII half-width.
+pub fn text_width(text: &str, size: f32) -> f32 {
+    text.chars().map(|c| if c.is_ascii() { size * 0.5 } else { size }).sum()
+}
+
+// UniGB-UCS2-H takes big-endian UCS-2; characters outside the BMP become '?'
+fn encode_ucs2_hex(text: &str) -> String {
+    let mut hex = String::with_capacity(text.len() * 4);
+    for c in text.chars() {
+        let code = if (c as u32) <= 0xFFFF { c as u32 } else { '?' as u32 };
+        hex.push_str(&format!("{:04X}", code));
+    }
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_ucs2_hex() {
+        assert_eq!(encode_ucs2_hex("A"), "0041");
+        assert_eq!(encode_ucs2_hex("作业"), "4F5C4E1A");
+        assert_eq!(encode_ucs2_hex("😀"), "003F");
+    }
+
+    #[test]
+    fn test_xref_offsets_point_at_objects() {
+        let mut doc = PdfDoc::new();
+        doc.text(50.0, 800.0, 12.0, "Report 报告");
+        doc.new_page();
+        doc.rect(50.0, 700.0, 100.0, 10.0, 0.5);
+        let bytes = doc.to_bytes();
+        let text = String::from_utf8(bytes).unwrap();
+
+        let xref_start: usize = text.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse().unwrap();
+        assert!(text[xref_start..].starts_with("xref"));
+        let entries: Vec<&str> = text[xref_start..].lines().skip(3).take_while(|l| l.ends_with(" n ")).collect();
+        assert_eq!(entries.len(), 9);
+        for (i, entry) in entries.iter().enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(text[offset..].starts_with(&format!("{} 0 obj", i + 1)));
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use chrono::{Datelike, Local, NaiveDate};
+use crate::models::WEEKDAYS;
+
+// ========== Exam Study Plans ==========
+//
+// Spreads the problems an exam covers over the days left before it. A fresh
+// plan hands out contiguous runs, so a day stays on one or two categories;
+// problems from missed days are moved one at a time onto whichever remaining
+// day is lightest, so catching up never piles onto tomorrow.
+
+/// The days a plan can use: today up to, not including, the exam.
+pub fn plan_days(today: NaiveDate, exam_date: NaiveDate) -> Vec<NaiveDate> {
+    today.iter_days().take_while(|day| *day < exam_date).collect()
+}
+
+/// How many of `count` problems go on each of `days` days, earlier days
+/// taking the remainder.
+pub fn split_evenly(count: usize, days: usize) -> Vec<usize> {
+    if days == 0 {
+        return Vec::new();
+    }
+    (0..days).map(|i| count / days + usize::from(i < count % days)).collect()
+}
+
+/// The day with the fewest problems, the earliest on a tie.
+pub fn least_loaded(loads: &[usize]) -> Option<usize> {
+    loads.iter().enumerate().min_by_key(|(i, load)| (**load, *i)).map(|(i, _)| i)
+}
+
+/// The study_cards.due_at (UTC, like the review API writes) at which a
+/// problem comes due from the start of `day` in local time.
+pub fn due_at(day: NaiveDate) -> String {
+    let start = day.and_hms_opt(0, 0, 0).unwrap();
+    let utc = start.and_local_timezone(Local).earliest().map(|t| t.naive_utc()).unwrap_or(start);
+    utc.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// "10月16日 周五"
+pub fn day_label(day: NaiveDate) -> String {
+    let weekday = WEEKDAYS[day.weekday().num_days_from_monday() as usize].1;
+    format!("{}月{}日 {}", day.month(), day.day(), weekday)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_plan_days() {
+        let days = plan_days(date("2026-10-16"), date("2026-10-19"));
+        assert_eq!(days, vec![date("2026-10-16"), date("2026-10-17"), date("2026-10-18")]);
+        assert!(plan_days(date("2026-10-16"), date("2026-10-16")).is_empty());
+        assert!(plan_days(date("2026-10-16"), date("2026-10-01")).is_empty());
+    }
+
+    #[test]
+    fn test_split_evenly() {
+        assert_eq!(split_evenly(7, 3), vec![3, 2, 2]);
+        assert_eq!(split_evenly(2, 4), vec![1, 1, 0, 0]);
+        assert_eq!(split_evenly(6, 3), vec![2, 2, 2]);
+        assert!(split_evenly(5, 0).is_empty());
+    }
+
+    #[test]
+    fn test_least_loaded() {
+        assert_eq!(least_loaded(&[2, 1, 1]), Some(1));
+        assert_eq!(least_loaded(&[0, 0]), Some(0));
+        assert_eq!(least_loaded(&[]), None);
+    }
+
+    #[test]
+    fn test_day_label() {
+        assert_eq!(day_label(date("2026-10-16")), "10月16日 周五");
+        assert_eq!(day_label(date("2026-10-18")), "10月18日 周日");
+    }
+}
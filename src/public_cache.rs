@@ -0,0 +1,206 @@
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder};
+use rocket::{Request, Response};
+use sqlx::SqliteConnection;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+// ========== Public Page Cache ==========
+//
+// A published course link tends to be opened by a whole class within minutes.
+// The rendered HTML of the /p/ pages is kept in managed state by path for
+// `[default.public_cache] ttl_secs` and sent with a matching Cache-Control
+// max-age. Pages still waiting on translations are never cached.
+//
+// Each public response carries the Last-Modified of its course (or wrapped
+// semester), the latest updated_at among the rows it is built from; triggers
+// keep those current (see the add_updated_at migration). A client asking
+// If-Modified-Since a date no earlier gets 304 without anything being
+// rendered, so calendar and feed readers polling every few minutes cost next
+// to nothing. A cached page is only served while its course still has the
+// Last-Modified it was rendered at, so a change to one course, by whichever
+// route, leaves the others' pages cached. Another change could follow within the
+// same second, so until that second is over a page goes out without
+// Last-Modified and isn't cached. HEAD requests are answered by the GET
+// routes, minus the body.
+
+#[derive(Default)]
+pub struct PublicPageCache {
+    // Rendered pages with when they were stored and the Last-Modified they
+    // were rendered at
+    pages: RwLock<HashMap<String, (Instant, i64, String)>>,
+}
+
+/// A public response: the body with its caching headers, or 304 Not
+/// Modified when the client's copy is current.
+pub struct PublicPage {
+    body: Option<(ContentType, String)>,
+    cache_control: Option<String>,
+    last_modified: Option<i64>,
+}
+
+impl PublicPage {
+    fn new(html: String, ttl: Duration, last_modified: Option<i64>) -> Self {
+        PublicPage { body: Some((ContentType::HTML, html)), cache_control: Some(cache_control(ttl)), last_modified }
+    }
+
+    fn not_modified(ttl: Duration, last_modified: i64) -> Self {
+        PublicPage { body: None, cache_control: Some(cache_control(ttl)), last_modified: Some(last_modified) }
+    }
+}
+
+fn cache_control(ttl: Duration) -> String {
+    if ttl.is_zero() {
+        "no-cache".to_string()
+    } else {
+        format!("public, max-age={}", ttl.as_secs())
+    }
+}
+
+impl<'r> Responder<'r, 'static> for PublicPage {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = match self.body {
+            Some(body) => body.respond_to(request)?,
+            None => Response::build().status(Status::NotModified).finalize(),
+        };
+        if let Some(cache_control) = self.cache_control {
+            response.set_header(Header::new("Cache-Control", cache_control));
+        }
+        if let Some(date) = self.last_modified.and_then(|secs| chrono::DateTime::from_timestamp(secs, 0)) {
+            response.set_header(Header::new("Last-Modified", date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()));
+        }
+        Ok(response)
+    }
+}
+
+/// The request's If-Modified-Since, in Unix seconds, when it has a valid one
+pub struct IfModifiedSince(Option<i64>);
+
+impl IfModifiedSince {
+    fn is_current(&self, last_modified: i64) -> bool {
+        self.0.is_some_and(|since| since >= last_modified)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfModifiedSince {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let since = request.headers().get_one("If-Modified-Since")
+            .and_then(|value| chrono::DateTime::parse_from_rfc2822(value).ok())
+            .map(|date| date.timestamp());
+        Outcome::Success(IfModifiedSince(since))
+    }
+}
+
+impl PublicPageCache {
+    /// The cached page at `path`, or the one `render` produces.
+    /// `last_modified` is when the page's data last changed, from
+    /// [`course_last_modified`] or [`semester_last_modified`]. `render`
+    /// returns the HTML and whether it may be cached; pages that may not are
+    /// sent without a Last-Modified.
+    pub async fn serve<F, Fut>(&self, path: &str, ttl: Duration, since: &IfModifiedSince, last_modified: Option<i64>, render: F) -> Result<PublicPage, Status>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<(String, bool), Status>>,
+    {
+        let last_modified = settled(last_modified);
+        if let Some(last_modified) = last_modified.filter(|&secs| since.is_current(secs)) {
+            return Ok(PublicPage::not_modified(ttl, last_modified));
+        }
+        let cached = last_modified.and_then(|last_modified| {
+            self.pages.read().unwrap().get(path)
+                .filter(|(stored, rendered_at, _)| stored.elapsed() < ttl && *rendered_at == last_modified)
+                .map(|(_, _, html)| html.clone())
+        });
+        if let Some(html) = cached {
+            return Ok(PublicPage::new(html, ttl, last_modified));
+        }
+
+        let (html, cacheable) = render().await?;
+        if !cacheable {
+            return Ok(PublicPage::new(html, Duration::ZERO, None));
+        }
+        if let Some(last_modified) = last_modified.filter(|_| !ttl.is_zero()) {
+            let mut pages = self.pages.write().unwrap();
+            pages.retain(|_, (stored, _, _)| stored.elapsed() < ttl);
+            pages.insert(path.to_string(), (Instant::now(), last_modified, html.clone()));
+        }
+        Ok(PublicPage::new(html, ttl, last_modified))
+    }
+
+    /// A public response that isn't cached, such as a feed, or 304 when the
+    /// client's copy is current. It carries no caching instructions.
+    pub async fn serve_uncached<F, Fut>(&self, since: &IfModifiedSince, last_modified: Option<i64>, render: F) -> Result<PublicPage, Status>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<(ContentType, String), Status>>,
+    {
+        let last_modified = settled(last_modified);
+        if last_modified.is_some_and(|secs| since.is_current(secs)) {
+            return Ok(PublicPage { body: None, cache_control: None, last_modified });
+        }
+        let body = render().await?;
+        Ok(PublicPage { body: Some(body), cache_control: None, last_modified })
+    }
+
+    /// Drops every cached page, for changes outside any course such as the
+    /// site's branding
+    pub fn clear(&self) {
+        self.pages.write().unwrap().clear();
+    }
+}
+
+// A Last-Modified from the current second may yet be followed by another
+// change within it, which a copy stamped with it would never see
+fn settled(last_modified: Option<i64>) -> Option<i64> {
+    last_modified.filter(|&secs| secs < chrono::Utc::now().timestamp())
+}
+
+/// When the published course at `slug` last changed, in Unix seconds: the
+/// course itself, its log items or its problems. None when there's no such
+/// course.
+pub async fn course_last_modified(db: &mut SqliteConnection, slug: &str) -> Option<i64> {
+    sqlx::query_scalar(
+        r#"
+        WITH course AS (SELECT id, updated_at FROM courses WHERE public_slug = ? AND is_published = 1)
+        SELECT MAX(CAST(strftime('%s', t) AS INTEGER)) FROM (
+            SELECT updated_at AS t FROM course
+            UNION ALL SELECT l.updated_at FROM log_items l JOIN course c ON l.course_id = c.id
+            UNION ALL SELECT p.updated_at FROM problem_sources s JOIN course c ON s.course_id = c.id JOIN problems p ON p.id = s.problem_id
+        )
+        "#
+    )
+    .bind(slug)
+    .fetch_one(&mut *db)
+    .await
+    .unwrap_or(None)
+}
+
+/// When the published wrapped summary at `slug` last changed, in Unix
+/// seconds: the semester, its courses with their log items and problems, or
+/// the reviews of those problems. None when there's no such summary.
+pub async fn semester_last_modified(db: &mut SqliteConnection, slug: &str) -> Option<i64> {
+    sqlx::query_scalar(
+        r#"
+        WITH semester AS (SELECT id, updated_at FROM semesters WHERE wrapped_slug = ? AND wrapped_published = 1),
+        course AS (SELECT c.id, c.updated_at FROM courses c JOIN semester s ON c.semester_id = s.id),
+        problem AS (SELECT p.id, p.updated_at FROM problem_sources ps JOIN course c ON ps.course_id = c.id JOIN problems p ON p.id = ps.problem_id)
+        SELECT MAX(CAST(strftime('%s', t) AS INTEGER)) FROM (
+            SELECT updated_at AS t FROM semester
+            UNION ALL SELECT updated_at FROM course
+            UNION ALL SELECT l.updated_at FROM log_items l JOIN course c ON l.course_id = c.id
+            UNION ALL SELECT updated_at FROM problem
+            UNION ALL SELECT r.reviewed_at FROM study_reviews r JOIN problem p ON r.problem_id = p.id
+        )
+        "#
+    )
+    .bind(slug)
+    .fetch_one(&mut *db)
+    .await
+    .unwrap_or(None)
+}
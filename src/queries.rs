@@ -0,0 +1,263 @@
+use rocket_db_pools::sqlx::{self, SqliteConnection};
+use crate::models::*;
+
+// ========== Shared Queries ==========
+//
+// Lookups that several handlers need, so each SQL string lives in one place.
+// Everything takes a plain SqliteConnection like settings.rs and uploads.rs;
+// a `&mut Connection<Db>` derefs to one. The queries are `query_as!` macros,
+// checked against the schema at compile time. What they were checked against
+// is kept in `.sqlx/`, so a build needs no database; after changing a query
+// or a table one reads, regenerate it (see "Database queries" in the
+// README). Queries put together at run time, such as the study filters,
+// select problem ids and load the rows with `fetch_problems`.
+
+/// Problems with their categories, source and assignee, in the order of
+/// `ids`; ids that don't exist are left out.
+pub async fn fetch_problems(db: &mut SqliteConnection, ids: &[i64]) -> Vec<ProblemWithCategories> {
+    if ids.is_empty() {
+        return Vec::new();
+    }
+    let ids = serde_json::to_string(ids).unwrap_or_default();
+    sqlx::query_as!(
+        ProblemWithCategories,
+        r#"
+        SELECT
+            p.id AS "id!", p.log_item_id, p.exam_id, p.description AS "description!", p.notes, p.image_url, p.body, p.solution_link, p.answer,
+            p.original_filename, p.mime_type, p.imported_from, p.public_id AS "public_id!", p.number, p.assigned_to, p.difficulty,
+            assignee.username AS "assignee_name?",
+            GROUP_CONCAT(COALESCE(cp.path, c.name)) AS "category_names?: String",
+            src.source_kind AS "source_kind!: String", src.source_title AS "source_title!: String",
+            src.source_url AS "source_url!: String", src.exam_title AS "exam_title?: String"
+        FROM json_each(?) ids
+        JOIN problems p ON p.id = ids.value
+        JOIN problem_sources src ON src.problem_id = p.id
+        LEFT JOIN problem_categories pc ON p.id = pc.problem_id
+        LEFT JOIN categories c ON pc.category_id = c.id
+        LEFT JOIN category_paths cp ON cp.id = c.id
+        LEFT JOIN users assignee ON p.assigned_to = assignee.id
+        GROUP BY p.id
+        ORDER BY MIN(ids.key)
+        "#,
+        ids
+    )
+    .fetch_all(&mut *db)
+    .await
+    .unwrap_or_default()
+}
+
+pub async fn fetch_problem_with_categories(db: &mut SqliteConnection, id: i64) -> Option<ProblemWithCategories> {
+    fetch_problems(db, &[id]).await.pop()
+}
+
+pub async fn fetch_log_item_problems(db: &mut SqliteConnection, log_item_id: i64) -> Vec<ProblemWithCategories> {
+    let ids = sqlx::query_scalar!("SELECT id FROM problems WHERE log_item_id = ? ORDER BY id", log_item_id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+    fetch_problems(db, &ids).await
+}
+
+pub async fn fetch_exam_problems(db: &mut SqliteConnection, exam_id: i64) -> Vec<ProblemWithCategories> {
+    let ids = sqlx::query_scalar!("SELECT id FROM problems WHERE exam_id = ? ORDER BY id", exam_id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+    fetch_problems(db, &ids).await
+}
+
+/// Every problem in a course, whether it hangs off a log item or an exam.
+pub async fn fetch_course_problems(db: &mut SqliteConnection, course_id: i64) -> Vec<ProblemWithCategories> {
+    let ids = sqlx::query_scalar!(r#"SELECT problem_id AS "id!: i64" FROM problem_sources WHERE course_id = ? ORDER BY problem_id"#, course_id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+    fetch_problems(db, &ids).await
+}
+
+// `query_as!` only takes string literals, so the columns are spliced in here
+// rather than formatted in. SQLite declares booleans as INTEGER, and sqlx
+// can't always tell a column is never null, hence the overrides.
+macro_rules! select_courses {
+    ($rest:literal, $($arg:expr),*) => {
+        sqlx::query_as!(
+            Course,
+            r#"
+            SELECT
+                id AS "id!", semester_id, code, title, is_published AS "is_published: bool", public_slug,
+                show_lecture_links AS "show_lecture_links: bool", discourage_indexing AS "discourage_indexing: bool",
+                public_theme, banner_url, units, grade, color, public_id AS "public_id!",
+                translation_subject, translation_level, translation_style, join_code, join_role,
+                continues_course_id, webhook_url
+            FROM courses
+            "# + $rest,
+            $($arg),*
+        )
+    };
+}
+
+pub async fn fetch_semester(db: &mut SqliteConnection, id: i64) -> Option<Semester> {
+    sqlx::query_as!(
+        Semester,
+        r#"SELECT id, name, created_at AS "created_at!: String", wrapped_published AS "wrapped_published: bool", wrapped_slug FROM semesters WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(&mut *db)
+        .await
+        .unwrap_or(None)
+}
+
+pub async fn fetch_course(db: &mut SqliteConnection, id: i64) -> Option<Course> {
+    select_courses!("WHERE id = ?", id)
+        .fetch_optional(&mut *db)
+        .await
+        .unwrap_or(None)
+}
+
+pub async fn fetch_course_by_public_id(db: &mut SqliteConnection, public_id: &str) -> Option<Course> {
+    select_courses!("WHERE public_id = ?", public_id)
+        .fetch_optional(&mut *db)
+        .await
+        .unwrap_or(None)
+}
+
+/// A course by its public slug, only while it is published.
+pub async fn fetch_published_course(db: &mut SqliteConnection, slug: &str) -> Option<Course> {
+    select_courses!("WHERE public_slug = ? AND is_published = 1", slug)
+        .fetch_optional(&mut *db)
+        .await
+        .unwrap_or(None)
+}
+
+/// What every course page needs around its own content: the course, its
+/// semester, and the semester's courses for the switcher.
+pub struct CourseContext {
+    pub course: Course,
+    pub semester: Semester,
+    pub courses: Vec<Course>,
+}
+
+pub async fn fetch_course_context(db: &mut SqliteConnection, course_id: i64) -> Option<CourseContext> {
+    let course = fetch_course(db, course_id).await?;
+
+    let semester = fetch_semester(db, course.semester_id).await?;
+
+    let courses = select_courses!("WHERE semester_id = ?", course.semester_id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+
+    Some(CourseContext { course, semester, courses })
+}
+
+/// A course's categories with their paths, each parent followed by its
+/// children; siblings stay in the order they were created.
+pub async fn fetch_categories(db: &mut SqliteConnection, course_id: i64) -> Vec<Category> {
+    let categories = sqlx::query_as!(
+        Category,
+        r#"
+        SELECT c.id AS "id!", c.course_id, c.name, c.parent_id, COALESCE(cp.path, c.name) AS "path!: String", COALESCE(cp.depth, 0) AS "depth!: i64"
+        FROM categories c
+        LEFT JOIN category_paths cp ON cp.id = c.id
+        WHERE c.course_id = ?
+        ORDER BY c.id
+        "#,
+        course_id
+    )
+    .fetch_all(&mut *db)
+    .await
+    .unwrap_or_default();
+    in_tree_order(categories)
+}
+
+// Depth-first from the top level. Categories whose parent isn't in the list
+// (only possible through hand edits) are treated as top level.
+fn in_tree_order(categories: Vec<Category>) -> Vec<Category> {
+    let ids: std::collections::HashSet<i64> = categories.iter().map(|c| c.id).collect();
+    let parent_of = |c: &Category| c.parent_id.filter(|p| ids.contains(p) && *p != c.id);
+
+    let mut ordered = Vec::with_capacity(categories.len());
+    let mut placed = std::collections::HashSet::new();
+    let mut stack: Vec<&Category> = categories.iter().filter(|c| parent_of(c).is_none()).rev().collect();
+    while let Some(category) = stack.pop() {
+        if !placed.insert(category.id) {
+            continue;
+        }
+        ordered.push(category.clone());
+        stack.extend(categories.iter().filter(|c| parent_of(c) == Some(category.id)).rev());
+    }
+    // Anything left sits in a parent loop; keep it rather than hide it
+    ordered.extend(categories.iter().filter(|c| !placed.contains(&c.id)).cloned());
+    ordered
+}
+
+pub async fn fetch_log_item(db: &mut SqliteConnection, id: i64) -> Option<LogItem> {
+    sqlx::query_as!(
+        LogItem,
+        r#"SELECT id AS "id!", course_id, kind, title, description, link, date, public_id AS "public_id!", status FROM log_items WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(&mut *db)
+        .await
+        .unwrap_or(None)
+}
+
+pub async fn fetch_log_item_by_public_id(db: &mut SqliteConnection, public_id: &str) -> Option<LogItem> {
+    sqlx::query_as!(
+        LogItem,
+        r#"SELECT id AS "id!", course_id, kind, title, description, link, date, public_id AS "public_id!", status FROM log_items WHERE public_id = ?"#,
+        public_id
+    )
+    .fetch_optional(&mut *db)
+        .await
+        .unwrap_or(None)
+}
+
+/// A log item's tags, alphabetically
+pub async fn fetch_log_item_tags(db: &mut SqliteConnection, log_item_id: i64) -> Vec<String> {
+    sqlx::query_scalar!("SELECT name FROM log_item_tags WHERE log_item_id = ? ORDER BY name", log_item_id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default()
+}
+
+/// Every tag in a course as (log item id, name), alphabetically by name
+pub async fn fetch_course_log_tags(db: &mut SqliteConnection, course_id: i64) -> Vec<(i64, String)> {
+    sqlx::query!("SELECT t.log_item_id, t.name FROM log_item_tags t JOIN log_items l ON t.log_item_id = l.id WHERE l.course_id = ? ORDER BY t.name, t.log_item_id", course_id)
+        .fetch_all(&mut *db)
+        .await
+        .map(|rows| rows.into_iter().map(|row| (row.log_item_id, row.name)).collect())
+        .unwrap_or_default()
+}
+
+pub async fn fetch_exam(db: &mut SqliteConnection, id: i64) -> Option<Exam> {
+    sqlx::query_as!(
+        Exam,
+        r#"SELECT id, course_id, title, semester, link, exam_date AS "exam_date: String" FROM exams WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(&mut *db)
+        .await
+        .unwrap_or(None)
+}
+
+/// The `public_id` a row was given by its insert trigger (see the
+/// add_public_ids migration). `table` is courses, log_items or problems;
+/// since it varies, this is the one query here checked only at run time.
+pub async fn fetch_public_id(db: &mut SqliteConnection, table: &str, id: i64) -> String {
+    sqlx::query_scalar(&format!("SELECT public_id FROM {} WHERE id = ?", table))
+        .bind(id)
+        .fetch_one(&mut *db)
+        .await
+        .unwrap_or_default()
+}
+
+/// The course number a problem was given by its insert trigger (see the
+/// add_problem_numbers migration)
+pub async fn fetch_problem_number(db: &mut SqliteConnection, id: i64) -> Option<i64> {
+    sqlx::query_scalar!("SELECT number FROM problems WHERE id = ?", id)
+        .fetch_optional(&mut *db)
+        .await
+        .unwrap_or(None)
+        .flatten()
+}
@@ -0,0 +1,106 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header};
+use rocket::{Data, Request, Response};
+use rocket_db_pools::sqlx::{self, SqliteConnection};
+use std::ffi::{c_int, c_uint, c_void};
+use std::io::Cursor;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+// ========== Query Stats ==========
+//
+// A development aid for spotting N+1 query patterns. With
+//
+//   [debug.databases.sqlite_logs]
+//   query_stats = true
+//
+// a debug build reports the SQL statements run while a request was in flight
+// and the time SQLite spent on them, in an `X-DB-Queries: ~14 queries,
+// 3.2 ms` response header and an HTML comment at the end of pages and
+// fragments. Release builds ignore the setting.
+//
+// SQLite reports every finished statement to a profile callback that db.rs
+// installs on each new connection. The callback runs on sqlx's connection
+// thread, which can't tell which request sent the statement, so the counters
+// are process-wide and a figure is only an approximate rate: requests that
+// overlap (HTMX often fires several at once) count each other's queries too,
+// hence the `~`. Load the page on its own for exact numbers.
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static QUERIES: AtomicU64 = AtomicU64::new(0);
+static NANOS: AtomicU64 = AtomicU64::new(0);
+
+const SQLITE_TRACE_PROFILE: c_uint = 0x02;
+
+type TraceCallback = unsafe extern "C" fn(c_uint, *mut c_void, *mut c_void, *mut c_void) -> c_int;
+
+extern "C" {
+    // Part of the SQLite library sqlx links in
+    fn sqlite3_trace_v2(db: *mut c_void, mask: c_uint, callback: Option<TraceCallback>, context: *mut c_void) -> c_int;
+}
+
+// For SQLITE_TRACE_PROFILE the last argument points at the statement's run
+// time in nanoseconds
+unsafe extern "C" fn on_profile(_event: c_uint, _context: *mut c_void, _statement: *mut c_void, nanos: *mut c_void) -> c_int {
+    QUERIES.fetch_add(1, Ordering::Relaxed);
+    NANOS.fetch_add(unsafe { *(nanos as *const i64) }.max(0) as u64, Ordering::Relaxed);
+    0
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Count the statements run on `conn` from now on.
+pub async fn watch(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    let mut handle = conn.lock_handle().await?;
+    // SAFETY: the handle is a live connection, locked for the duration of the
+    // call; the callback only touches statics and needs no context pointer.
+    unsafe {
+        sqlite3_trace_v2(handle.as_raw_handle().as_ptr().cast(), SQLITE_TRACE_PROFILE, Some(on_profile), std::ptr::null_mut());
+    }
+    Ok(())
+}
+
+// The process-wide counters when the request came in
+struct Start {
+    queries: u64,
+    nanos: u64,
+}
+
+fn now() -> Start {
+    Start { queries: QUERIES.load(Ordering::Relaxed), nanos: NANOS.load(Ordering::Relaxed) }
+}
+
+pub struct QueryStats;
+
+#[rocket::async_trait]
+impl Fairing for QueryStats {
+    fn info(&self) -> Info {
+        Info { name: "Query Stats", kind: Kind::Request | Kind::Response }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        if ENABLED.load(Ordering::Relaxed) {
+            req.local_cache(now);
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        if !ENABLED.load(Ordering::Relaxed) {
+            return;
+        }
+        let start = req.local_cache(now);
+        let end = now();
+        let time = Duration::from_nanos(end.nanos - start.nanos);
+        let summary = format!("~{} queries, {:.1} ms", end.queries - start.queries, time.as_secs_f64() * 1000.0);
+        res.set_header(Header::new("X-DB-Queries", summary.clone()));
+
+        if res.content_type() == Some(ContentType::HTML) {
+            if let Ok(body) = res.body_mut().to_string().await {
+                let body = format!("{}<!-- {} -->\n", body, summary);
+                res.set_sized_body(body.len(), Cursor::new(body));
+            }
+        }
+    }
+}
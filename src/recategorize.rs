@@ -0,0 +1,253 @@
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx::{self, FromRow, SqliteConnection};
+use crate::categories;
+use crate::models::problem_label;
+
+// ========== Recategorizing From a Spreadsheet ==========
+//
+// Big category cleanups are easier in a spreadsheet than one problem at a
+// time. /courses/<id>/problems/categories.csv lists every numbered problem in
+// the course with its categories; the same file, edited, goes back to
+// POST /courses/<id>/problems/recategorize. Uploading only shows what would
+// change. Confirming applies all of it in one transaction, and nothing if a
+// problem's categories changed since the preview.
+//
+// The header row names the columns. `number` (P-042 or 42) and `categories`
+// are read and any others, like the exported `source`, are ignored.
+// Categories are separated by commas or 顿号 and nest with a slash, as when
+// editing a problem; an empty cell takes them all off. Problems left out of
+// the file keep theirs.
+
+#[derive(FromRow)]
+struct CourseProblem {
+    id: i64,
+    number: i64,
+    source_title: String,
+    /// Comma separated category paths
+    paths: Option<String>,
+}
+
+impl CourseProblem {
+    fn paths(&self) -> Vec<String> {
+        self.paths.as_deref().map(|p| p.split(',').map(String::from).collect()).unwrap_or_default()
+    }
+}
+
+// One problem (`problem_id` = ?) or all of them (NULL), by number
+const COURSE_PROBLEMS: &str = r#"
+    SELECT p.id, p.number, COALESCE(l.title, e.title, '') AS source_title, GROUP_CONCAT(COALESCE(cp.path, c.name)) AS paths
+    FROM problems p
+    LEFT JOIN log_items l ON p.log_item_id = l.id
+    LEFT JOIN exams e ON p.exam_id = e.id
+    LEFT JOIN problem_categories pc ON pc.problem_id = p.id
+    LEFT JOIN categories c ON pc.category_id = c.id
+    LEFT JOIN category_paths cp ON cp.id = c.id
+    WHERE (l.course_id = ? OR e.course_id = ?) AND p.number IS NOT NULL AND (? IS NULL OR p.id = ?)
+    GROUP BY p.id
+    ORDER BY p.number
+"#;
+
+async fn course_problems(db: &mut SqliteConnection, course_id: i64, problem_id: Option<i64>) -> Vec<CourseProblem> {
+    sqlx::query_as::<_, CourseProblem>(COURSE_PROBLEMS)
+        .bind(course_id)
+        .bind(course_id)
+        .bind(problem_id)
+        .bind(problem_id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default()
+}
+
+/// The course's numbered problems as CSV, with a byte order mark so Excel
+/// reads it as UTF-8.
+pub async fn export(db: &mut SqliteConnection, course_id: i64) -> String {
+    let mut out = String::from("\u{feff}number,source,categories\r\n");
+    for problem in course_problems(db, course_id, None).await {
+        let fields = [problem_label(problem.number), problem.source_title.clone(), problem.paths().join(", ")];
+        out.push_str(&fields.iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// One problem whose categories the upload changes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Change {
+    pub problem_id: i64,
+    pub number: i64,
+    pub source_title: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+impl Change {
+    pub fn label(&self) -> String {
+        problem_label(self.number)
+    }
+
+    pub fn added(&self) -> Vec<&String> {
+        self.after.iter().filter(|path| !contains(&self.before, path)).collect()
+    }
+
+    pub fn removed(&self) -> Vec<&String> {
+        self.before.iter().filter(|path| !contains(&self.after, path)).collect()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub changes: Vec<Change>,
+    pub unchanged: usize,
+    /// Rows that were left out, and why
+    pub skipped: Vec<String>,
+}
+
+/// Compare an uploaded CSV with the course's problems. Err when the file
+/// can't be used at all.
+pub async fn plan(db: &mut SqliteConnection, course_id: i64, csv: &str) -> Result<Plan, String> {
+    let mut records = parse_csv(csv).into_iter();
+    let header = records.next().unwrap_or_default();
+    let column = |name: &str| header.iter().position(|h| h.trim().eq_ignore_ascii_case(name));
+    let (Some(number_col), Some(categories_col)) = (column("number"), column("categories")) else {
+        return Err("CSV 的第一行需要有 number 和 categories 两列。".to_string());
+    };
+
+    let problems = course_problems(db, course_id, None).await;
+    let mut plan = Plan::default();
+    let mut seen = Vec::new();
+    // The header is line 1
+    for (line, record) in (2..).zip(records) {
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        let cell = record.get(number_col).map(|s| s.trim()).unwrap_or_default();
+        let Some(problem) = parse_number(cell).and_then(|n| problems.iter().find(|p| p.number == n)) else {
+            plan.skipped.push(format!("第 {} 行：没有错题 {}", line, cell));
+            continue;
+        };
+        if seen.contains(&problem.id) {
+            plan.skipped.push(format!("第 {} 行：{} 重复出现，只用第一行", line, problem_label(problem.number)));
+            continue;
+        }
+        seen.push(problem.id);
+
+        let before = problem.paths();
+        let after = parse_paths(record.get(categories_col).map(String::as_str).unwrap_or_default());
+        if same(&before, &after) {
+            plan.unchanged += 1;
+        } else {
+            plan.changes.push(Change { problem_id: problem.id, number: problem.number, source_title: problem.source_title.clone(), before, after });
+        }
+    }
+    Ok(plan)
+}
+
+/// Whether `change` was made against the problem's current categories, so
+/// applying it loses nothing made since the preview.
+pub async fn is_current(db: &mut SqliteConnection, course_id: i64, change: &Change) -> bool {
+    course_problems(db, course_id, Some(change.problem_id))
+        .await
+        .first()
+        .is_some_and(|problem| same(&problem.paths(), &change.before))
+}
+
+// Category names match regardless of ASCII case, as in the database
+fn contains(paths: &[String], path: &str) -> bool {
+    paths.iter().any(|p| p.eq_ignore_ascii_case(path))
+}
+
+fn same(a: &[String], b: &[String]) -> bool {
+    a.iter().all(|path| contains(b, path)) && b.iter().all(|path| contains(a, path))
+}
+
+// "P-042", "p42" or "42"
+fn parse_number(cell: &str) -> Option<i64> {
+    let digits = cell.trim_start_matches(['P', 'p']).trim_start_matches('-');
+    digits.parse().ok()
+}
+
+// Each path normalized as categories are stored, without repeats
+fn parse_paths(cell: &str) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    for path in cell.split([',', '\u{3001}']) {
+        let names: Vec<String> = path.split(['/', '\u{ff0f}']).map(categories::normalize_name).filter(|s| !s.is_empty()).collect();
+        let path = names.join("/");
+        if !path.is_empty() && !contains(&paths, &path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// RFC 4180 records: quoted fields may hold commas, doubled quotes and line
+// breaks. A leading byte order mark is dropped.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let text = "\u{feff}number,source,categories\r\nP-001,作业一,\"极限, 导数/链式法则\"\r\n2,\"Quiz \"\"A\"\"\",\n3,\"two\nlines\",积分";
+        assert_eq!(parse_csv(text), vec![
+            vec!["number".to_string(), "source".to_string(), "categories".to_string()],
+            vec!["P-001".to_string(), "作业一".to_string(), "极限, 导数/链式法则".to_string()],
+            vec!["2".to_string(), "Quiz \"A\"".to_string(), String::new()],
+            vec!["3".to_string(), "two\nlines".to_string(), "积分".to_string()],
+        ]);
+        for value in ["极限, 导数", "Quiz \"A\"", "plain"] {
+            assert_eq!(parse_csv(&csv_field(value)), vec![vec![value.to_string()]]);
+        }
+    }
+
+    #[test]
+    fn test_parse_cells() {
+        assert_eq!(parse_number("P-042"), Some(42));
+        assert_eq!(parse_number("7"), Some(7));
+        assert_eq!(parse_number("#7"), None);
+        assert_eq!(parse_paths(" 极限、导数 ／ 链式法则, DP,dp,"), vec!["极限", "导数/链式法则", "DP"]);
+        assert!(parse_paths("").is_empty());
+
+        let change = Change { problem_id: 1, number: 3, source_title: String::new(), before: vec!["DP".to_string(), "极限".to_string()], after: vec!["dp".to_string(), "积分".to_string()] };
+        assert_eq!((change.added(), change.removed()), (vec![&"积分".to_string()], vec![&"极限".to_string()]));
+        assert!(same(&change.before, &["极限".to_string(), "dp".to_string()]));
+    }
+}
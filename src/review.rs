@@ -0,0 +1,51 @@
+// ========== Review Scheduling ==========
+//
+// Grades from the keyboard review flow, Anki-style:
+//   1 = again, 2 = hard, 3 = good, 4 = easy
+// The interval grows multiplicatively with each successful review, up to a
+// year; "again" resets it and brings the card back within the same sitting.
+
+pub const AGAIN_DELAY_MINUTES: i64 = 10;
+/// However easy a card keeps being, it comes back at least once a year.
+pub const MAX_INTERVAL_DAYS: f64 = 365.0;
+
+/// The next review interval in days, given the current interval and a grade.
+/// Returns None for grades outside 1–4.
+pub fn next_interval(current_days: f64, grade: u8) -> Option<f64> {
+    let next = match grade {
+        1 => 0.0,
+        2 => (current_days * 1.2).max(1.0),
+        3 => (current_days * 2.5).max(1.0),
+        4 => (current_days * 3.5).max(4.0),
+        _ => return None,
+    };
+    Some(next.min(MAX_INTERVAL_DAYS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_interval() {
+        assert_eq!(next_interval(0.0, 1), Some(0.0));
+        assert_eq!(next_interval(10.0, 1), Some(0.0));
+        assert_eq!(next_interval(0.0, 2), Some(1.0));
+        assert_eq!(next_interval(0.0, 3), Some(1.0));
+        assert_eq!(next_interval(2.0, 3), Some(5.0));
+        assert_eq!(next_interval(0.0, 4), Some(4.0));
+        assert_eq!(next_interval(2.0, 4), Some(7.0));
+        assert_eq!(next_interval(2.0, 0), None);
+        assert_eq!(next_interval(2.0, 5), None);
+    }
+
+    #[test]
+    fn test_next_interval_saturates() {
+        let mut days = 0.0;
+        for _ in 0..50 {
+            days = next_interval(days, 4).unwrap();
+        }
+        assert_eq!(days, MAX_INTERVAL_DAYS);
+        assert_eq!(next_interval(MAX_INTERVAL_DAYS, 2), Some(MAX_INTERVAL_DAYS));
+    }
+}
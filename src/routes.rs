@@ -1,33 +1,90 @@
-use rocket::form::Form;
+use rocket::form::{self, Form};
 use rocket::fs::TempFile;
-use uuid::Uuid;
 use rocket_db_pools::Connection;
 use rocket_db_pools::sqlx;
 use sqlx::Row;
 use askama::Template;
-use crate::db::Db;
+use crate::db::{self, Db};
 use crate::models::*;
-use crate::auth::AuthUser;
+use crate::auth::{self, AuthUser};
+use crate::access;
+use crate::api_limits;
+use crate::page_views;
 use crate::translate;
-use rocket::http::{Cookie, CookieJar, SameSite, Status};
+use crate::config::AppConfig;
+use crate::pdf::{self, PdfDoc};
+use crate::grades::{self, SemesterSummary};
+use crate::uploads::{self, SavedUpload};
+use crate::settings;
+use crate::link_preview;
+use crate::queries::{self, CourseContext};
+use crate::category_cache::CategoryCache;
+use crate::scan::{self, Rejection};
+use crate::export;
+use crate::public_cache::{self, IfModifiedSince, PublicPage, PublicPageCache};
+use crate::branding::{Branding, BrandingState};
+use crate::digest::{self, Digest};
+use crate::todos;
+use crate::email;
+use crate::integrity;
+use crate::video;
+use crate::planner;
+use crate::difficulty;
+use crate::embed::{self, OEmbed};
+use crate::stats;
+use crate::account;
+use crate::wrapped::{self, SemesterWrapped};
+use crate::categories;
+use crate::outbound;
+use crate::url_import;
+use crate::notes_import;
+use crate::recategorize;
+use crate::webhook;
+use rocket::http::{ContentType, CookieJar, Header, Status};
+use rocket::http::uri::Host;
+use rocket::http::uri::error::PathError;
+use rocket::State;
+use rocket::Request;
+use rocket::data::Limits;
+use rocket::serde::Serialize;
+use rocket::serde::json::Json;
 use bcrypt::{hash, verify, DEFAULT_COST};
-use rocket::response::Redirect;
+use rocket::response::{self, Flash, Redirect, Responder};
+use rocket::request::FlashMessage;
 use chrono::{Datelike, NaiveDate};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use tokio::io::AsyncReadExt;
+use std::path::PathBuf;
+use uuid::Uuid;
+use rocket::fs::NamedFile;
 
 // Templates
 #[derive(Template)]
 #[template(path = "index.html")]
 struct IndexTemplate {
     semesters: Vec<Semester>,
+    joined_courses: Vec<Course>,
+    upcoming_exams: Vec<UpcomingExam>,
+    focus_suggestions: Vec<FocusSuggestion>,
+    open_tasks: Vec<OpenTask>,
+    show_invites: bool,
+    /// Only filled in for admins
+    translation_cache: Option<translate::CacheStats>,
+    join_error: Option<String>,
     user: Option<AuthUser>,
+    brand: Branding,
 }
 
 #[derive(Template)]
 #[template(path = "partials/semester_row.html")]
 struct SemesterRowTemplate {
     semester: Semester,
-    user: Option<AuthUser>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/semester_edit.html")]
+struct SemesterEditTemplate {
+    semester: Semester,
 }
 
 #[derive(Template)]
@@ -35,14 +92,39 @@ struct SemesterRowTemplate {
 struct SemesterTemplate {
     semester: Semester,
     courses: Vec<Course>,
+    summary: SemesterSummary,
+    user: Option<AuthUser>,
+    brand: Branding,
+}
+
+#[derive(Template)]
+#[template(path = "semester_wrapped.html")]
+struct SemesterWrappedTemplate {
+    semester: Semester,
+    wrapped: SemesterWrapped,
+    error: Option<String>,
     user: Option<AuthUser>,
+    brand: Branding,
+}
+
+#[derive(Template)]
+#[template(path = "public/wrapped.html")]
+struct PublicWrappedTemplate {
+    semester: Semester,
+    wrapped: SemesterWrapped,
+    brand: Branding,
 }
 
 #[derive(Template)]
 #[template(path = "partials/course_card.html")]
 struct CourseCardTemplate {
     course: Course,
-    user: Option<AuthUser>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/course_edit.html")]
+struct CourseEditTemplate {
+    course: Course,
 }
 
 #[derive(Template)]
@@ -53,7 +135,107 @@ struct CourseLogTemplate {
     log_items: Vec<LogItem>,
     semester: Semester,
     categories: Vec<Category>,
+    kinds: Vec<LogItemKind>,
+    previews: Vec<LinkPreview>,
+    view: String,
+    // See LogItem::matches_status; None shows everything
+    status_filter: Option<String>,
+    // Only items with this tag; None shows everything
+    tag_filter: Option<String>,
+    // (log item id, tag) for every item in the course
+    tags: Vec<(i64, String)>,
+    problem_counts: Vec<(i64, i64)>,
+    // See log_week_to_print
+    print_week: Option<i64>,
     user: Option<AuthUser>,
+    brand: Branding,
+}
+
+impl CourseLogTemplate {
+    fn status_filter_is(&self, filter: &str) -> bool {
+        self.status_filter.as_deref() == Some(filter)
+    }
+
+    fn link_previews(&self, description: &str) -> Vec<&LinkPreview> {
+        previews_in(&self.previews, description)
+    }
+
+    fn problem_count(&self, log_item_id: &i64) -> i64 {
+        self.problem_counts.iter().find(|(id, _)| id == log_item_id).map(|(_, n)| *n).unwrap_or(0)
+    }
+
+    fn tags_of(&self, log_item_id: &i64) -> Vec<&str> {
+        self.tags.iter().filter(|(id, _)| id == log_item_id).map(|(_, tag)| tag.as_str()).collect()
+    }
+
+    // Each tag used in the course once, for the filter bar
+    fn course_tags(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.tags.iter().map(|(_, tag)| tag.as_str()).collect();
+        names.dedup();
+        names
+    }
+
+    fn tag_filter_is(&self, tag: &str) -> bool {
+        self.tag_filter.as_deref() == Some(tag)
+    }
+
+    // Items grouped by month ("2026 年 9 月"), newest first, undated ones last
+    fn timeline(&self) -> Vec<(String, Vec<&LogItem>)> {
+        let mut groups: Vec<(String, Vec<&LogItem>)> = Vec::new();
+        let mut undated = Vec::new();
+        for item in &self.log_items {
+            let Some(month) = item.date.as_deref().and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok()) else {
+                undated.push(item);
+                continue;
+            };
+            let label = format!("{} 年 {} 月", month.year(), month.month());
+            match groups.last_mut() {
+                Some((last, items)) if *last == label => items.push(item),
+                _ => groups.push((label, vec![item])),
+            }
+        }
+        if !undated.is_empty() {
+            groups.push(("未注明日期".to_string(), undated));
+        }
+        groups
+    }
+}
+
+// One week of the course log laid out for paper
+#[derive(Template)]
+#[template(path = "log_week_print.html")]
+struct LogWeekPrintTemplate {
+    course: Course,
+    week: i64,
+    monday: NaiveDate,
+    items: Vec<(LogItem, Vec<ProblemWithCategories>)>,
+    // The week before and after, when there is one
+    previous_week: Option<i64>,
+    next_week: Option<i64>,
+}
+
+impl LogWeekPrintTemplate {
+    /// "9月28日 – 10月4日"
+    fn date_range(&self) -> String {
+        let sunday = self.monday + chrono::Duration::days(6);
+        format!("{} – {}", self.monday.format("%-m月%-d日"), sunday.format("%-m月%-d日"))
+    }
+}
+
+// The page of a course's static site export; see export.rs
+#[derive(Template)]
+#[template(path = "course_site.html")]
+struct CourseSiteTemplate {
+    course: Course,
+    exported_on: String,
+    log_items: Vec<(LogItem, Vec<ProblemWithCategories>)>,
+    exams: Vec<(Exam, Vec<ProblemWithCategories>)>,
+}
+
+impl CourseSiteTemplate {
+    fn local_url(&self, url: &str) -> String {
+        export::site_url(url)
+    }
 }
 
 #[derive(Template)]
@@ -61,28 +243,87 @@ struct CourseLogTemplate {
 struct LogItemTemplate {
     item: LogItem,
     categories: Vec<Category>,
-    user: Option<AuthUser>,
+    previews: Vec<LinkPreview>,
+    tags: Vec<String>,
+}
+
+impl LogItemTemplate {
+    fn link_previews(&self, description: &str) -> Vec<&LinkPreview> {
+        previews_in(&self.previews, description)
+    }
+
+    // Takes the id like CourseLogTemplate::tags_of, so log_item.html renders under both
+    fn tags_of(&self, _log_item_id: &i64) -> Vec<&str> {
+        self.tags.iter().map(String::as_str).collect()
+    }
+}
+
+// The loaded previews for the URLs in one description, in the order they appear
+fn previews_in<'a>(previews: &'a [LinkPreview], description: &str) -> Vec<&'a LinkPreview> {
+    link_preview::extract_urls(description)
+        .iter()
+        .filter_map(|url| previews.iter().find(|p| &p.url == url))
+        .collect()
 }
 
 #[derive(Template)]
 #[template(path = "partials/log_item_edit.html")]
 struct LogItemEditTemplate {
     item: LogItem,
-    user: Option<AuthUser>,
+    kinds: Vec<LogItemKind>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/log_item_status.html")]
+struct LogItemStatusTemplate {
+    item: LogItem,
+}
+
+#[derive(Template)]
+#[template(path = "partials/log_item_tags.html")]
+struct LogItemTagsTemplate {
+    item: LogItem,
+    tags: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/log_item_bookmarks.html")]
+struct LogItemBookmarksTemplate {
+    item_id: i64,
+    link: String,
+    bookmarks: Vec<LogItemBookmark>,
+    // Why the last bookmark wasn't added
+    error: Option<String>,
+}
+
+impl LogItemBookmarksTemplate {
+    fn link_at(&self, seconds: &i64) -> String {
+        video::link_at(&self.link, *seconds)
+    }
+
+    fn timestamp(&self, seconds: &i64) -> String {
+        video::format_timestamp(*seconds)
+    }
 }
 
 #[derive(Template)]
 #[template(path = "partials/problem_row.html")]
 struct ProblemRowTemplate {
     problem: ProblemWithCategories,
-    user: Option<AuthUser>,
+}
+
+// Takes a problem row's place in the list when its upload is refused
+#[derive(Template)]
+#[template(path = "partials/upload_error.html")]
+struct UploadErrorTemplate {
+    filename: Option<String>,
+    message: String,
 }
 
 #[derive(Template)]
 #[template(path = "partials/problem_edit.html")]
 struct ProblemEditTemplate {
     problem: ProblemWithCategories,
-    user: Option<AuthUser>,
 }
 
 #[derive(Template)]
@@ -92,14 +333,115 @@ struct CourseStudyTemplate {
     courses: Vec<Course>,
     categories: Vec<Category>,
     semester: Semester,
+    filters: StudyFilters,
+    presets: Vec<StudyPreset>,
+    goals: Vec<StudyGoal>,
+    category_times: Vec<CategoryTime>,
+    /// The course this one continues, whose problems can join the list
+    previous_course: Option<Course>,
+    /// Exams with a scope of log items to filter by
+    scoped_exams: Vec<Exam>,
+    user: Option<AuthUser>,
+    brand: Branding,
+}
+
+impl CourseStudyTemplate {
+    fn source_checked(&self, source: &str) -> bool {
+        self.filters.sources.iter().any(|s| s == source)
+    }
+
+    fn category_checked(&self, category_id: &i64) -> bool {
+        self.filters.categories.contains(category_id)
+    }
+
+    fn exam_selected(&self, exam_id: &i64) -> bool {
+        self.filters.exam == Some(*exam_id)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "study_review.html")]
+struct StudyReviewTemplate {
+    // None for the cross-course queue
+    course: Option<Course>,
+    topic: Option<String>,
+    user: Option<AuthUser>,
+    brand: Branding,
+}
+
+impl StudyReviewTemplate {
+    fn back_url(&self) -> String {
+        match &self.course {
+            Some(course) => format!("/courses/{}/study", course.id),
+            None => "/study/topics".to_string(),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "study_topics.html")]
+struct StudyTopicsTemplate {
+    topics: Vec<StudyTopic>,
+    aliases: Vec<CategoryAlias>,
+    // (flash kind, message) left by the last alias change
+    notice: Option<(String, String)>,
+    user: Option<AuthUser>,
+    brand: Branding,
+}
+
+#[derive(Template)]
+#[template(path = "exam_plan.html")]
+struct ExamPlanTemplate {
+    exam: Exam,
+    course: Course,
+    categories: Vec<Category>,
+    // Checked in the form: the exam's coverage
+    selected: Vec<i64>,
+    // Empty until a plan has been generated
+    days: Vec<PlanDay>,
+    // Problems just moved off days that passed without them
+    moved: usize,
+    notice: Option<(String, String)>,
     user: Option<AuthUser>,
+    brand: Branding,
+}
+
+impl ExamPlanTemplate {
+    fn category_checked(&self, category_id: &i64) -> bool {
+        self.selected.contains(category_id)
+    }
+
+    fn total(&self) -> usize {
+        self.days.iter().map(|d| d.entries.len()).sum()
+    }
+
+    fn done(&self) -> usize {
+        self.days.iter().map(|d| d.done()).sum()
+    }
 }
 
 #[derive(Template)]
 #[template(path = "partials/study_problem_list.html")]
 struct StudyProblemListTemplate {
     problems: Vec<ProblemWithCategories>,
-    user: Option<AuthUser>,
+    course_id: i64,
+    // Tells "nothing matches the filters" apart from "nothing recorded yet"
+    course_has_problems: bool,
+    offset: i64,
+    // Where the next batch starts, if there is one
+    next_offset: Option<i64>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/study_preset_chip.html")]
+struct StudyPresetChipTemplate {
+    preset: StudyPreset,
+}
+
+#[derive(Template)]
+#[template(path = "partials/study_goal.html")]
+struct StudyGoalTemplate {
+    goal: StudyGoal,
 }
 
 #[derive(Template)]
@@ -107,6 +449,7 @@ struct StudyProblemListTemplate {
 struct LoginTemplate {
     user: Option<AuthUser>,
     error: Option<String>,
+    brand: Branding,
 }
 
 #[derive(Template)]
@@ -114,6 +457,75 @@ struct LoginTemplate {
 struct RegisterTemplate {
     user: Option<AuthUser>,
     error: Option<String>,
+    needs_invite: bool,
+    invite_code: Option<String>,
+    brand: Branding,
+}
+
+#[derive(Template)]
+#[template(path = "setup.html")]
+struct SetupTemplate {
+    user: Option<AuthUser>,
+    error: Option<String>,
+    username: String,
+    instance_name: String,
+    upload_dir: String,
+    brand: Branding,
+}
+
+#[derive(Template)]
+#[template(path = "site_settings.html")]
+struct SiteSettingsTemplate {
+    // (flash kind, message) left by the last save
+    notice: Option<(String, String)>,
+    api_limits: api_limits::Limits,
+    user: Option<AuthUser>,
+    brand: Branding,
+}
+
+#[derive(Template)]
+#[template(path = "integrity.html")]
+struct IntegrityTemplate {
+    findings: Vec<integrity::Finding>,
+    // (flash kind, message) left by the last repair
+    notice: Option<(String, String)>,
+    user: Option<AuthUser>,
+    brand: Branding,
+}
+
+#[derive(Template)]
+#[template(path = "admin_reports.html")]
+struct ContentReportsTemplate {
+    reports: Vec<ContentReport>,
+    // (flash kind, message) left by the last dismissal or takedown
+    notice: Option<(String, String)>,
+    user: Option<AuthUser>,
+    brand: Branding,
+}
+
+#[derive(Template)]
+#[template(path = "admin_stats.html")]
+struct AdminStatsTemplate {
+    stats: stats::InstanceStats,
+    user: Option<AuthUser>,
+    brand: Branding,
+}
+
+#[derive(Template)]
+#[template(path = "invites.html")]
+struct InvitesTemplate {
+    invites: Vec<Invite>,
+    enabled: bool,
+    base_url: String,
+    user: Option<AuthUser>,
+    brand: Branding,
+}
+
+#[derive(Template)]
+#[template(path = "partials/invite_row.html")]
+struct InviteRowTemplate {
+    invite: Invite,
+    base_url: String,
 }
 
 #[derive(Template)]
@@ -124,7 +536,28 @@ struct CourseExamsTemplate {
     exams: Vec<Exam>,
     semester: Semester,
     categories: Vec<Category>,
+    coverage: Vec<(i64, i64)>,
+    // The course's log items, and (exam_id, log_item_id) pairs for the exams' scopes
+    log_items: Vec<LogItem>,
+    scope: Vec<(i64, i64)>,
+    // The user's study presets, offered as a source of problems for a new exam
+    presets: Vec<StudyPreset>,
     user: Option<AuthUser>,
+    brand: Branding,
+}
+
+impl CourseExamsTemplate {
+    fn covers(&self, exam_id: &i64, category_id: &i64) -> bool {
+        self.coverage.contains(&(*exam_id, *category_id))
+    }
+
+    fn in_scope(&self, exam_id: &i64, log_item_id: &i64) -> bool {
+        self.scope.contains(&(*exam_id, *log_item_id))
+    }
+
+    fn scope_size(&self, exam_id: &i64) -> usize {
+        self.scope.iter().filter(|(id, _)| id == exam_id).count()
+    }
 }
 
 #[derive(Template)]
@@ -132,14 +565,42 @@ struct CourseExamsTemplate {
 struct ExamItemTemplate {
     exam: Exam,
     categories: Vec<Category>,
-    user: Option<AuthUser>,
+    coverage: Vec<(i64, i64)>,
+    log_items: Vec<LogItem>,
+    scope: Vec<(i64, i64)>,
+}
+
+impl ExamItemTemplate {
+    fn covers(&self, exam_id: &i64, category_id: &i64) -> bool {
+        self.coverage.contains(&(*exam_id, *category_id))
+    }
+
+    fn in_scope(&self, exam_id: &i64, log_item_id: &i64) -> bool {
+        self.scope.contains(&(*exam_id, *log_item_id))
+    }
+
+    fn scope_size(&self, exam_id: &i64) -> usize {
+        self.scope.iter().filter(|(id, _)| id == exam_id).count()
+    }
 }
 
 #[derive(Template)]
 #[template(path = "partials/exam_item_edit.html")]
 struct ExamItemEditTemplate {
     exam: Exam,
+}
+
+#[derive(Template)]
+#[template(path = "course_recategorize.html")]
+struct RecategorizeTemplate {
+    course: Course,
+    courses: Vec<Course>,
+    semester: Semester,
+    plan: recategorize::Plan,
+    /// The plan's changes have been made, rather than previewed
+    applied: bool,
     user: Option<AuthUser>,
+    brand: Branding,
 }
 
 #[derive(Template)]
@@ -148,75 +609,461 @@ struct CourseSettingsTemplate {
     course: Course,
     courses: Vec<Course>,
     semester: Semester,
+    themes: &'static [(&'static str, &'static str, &'static str)],
+    translation_levels: &'static [(&'static str, &'static str, &'static str)],
+    translation_styles: &'static [(&'static str, &'static str, &'static str)],
+    course_roles: &'static [(&'static str, &'static str)],
+    kinds: Vec<LogItemKind>,
+    members: Vec<CourseMember>,
+    /// (course id, "semester · code title") this course can continue
+    previous_courses: Vec<(i64, String)>,
+    /// This course's categories, to carry the previous course's into
+    categories: Vec<Category>,
+    carryovers: Vec<CategoryCarryover>,
+    /// Public page views over the last week
+    views: page_views::Counts,
+    error: Option<String>,
     user: Option<AuthUser>,
+    brand: Branding,
+}
+
+impl CourseSettingsTemplate {
+    fn continues(&self, course_id: &i64) -> bool {
+        self.course.continues_course_id == Some(*course_id)
+    }
+
+    fn theme_selected(&self, key: &str) -> bool {
+        self.course.public_theme == key
+    }
+
+    fn level_selected(&self, key: &str) -> bool {
+        self.course.translation_level == key
+    }
+
+    fn style_selected(&self, key: &str) -> bool {
+        self.course.translation_style == key
+    }
+
+    fn join_role_selected(&self, key: &str) -> bool {
+        self.course.join_role == key
+    }
 }
 
 #[derive(Template)]
-#[template(path = "public/calendar.html")]
-struct PublicCalendarTemplate {
+#[template(path = "history.html")]
+struct HistoryTemplate {
     course: Course,
-    weeks: Vec<CalendarWeek>,
-    unscheduled: Vec<PublicLogItem>,
-    active_kinds: Vec<String>,
-    lang: String,
-    base_path: String,
+    courses: Vec<Course>,
+    semester: Semester,
+    heading: String,
+    entries: Vec<HistoryEntryView>,
+    user: Option<AuthUser>,
+    brand: Branding,
 }
 
 #[derive(Template)]
-#[template(path = "public/problems.html")]
-struct PublicProblemsTemplate {
+#[template(path = "problem_detail.html")]
+struct ProblemDetailTemplate {
     course: Course,
-    problems: Vec<PublicProblem>,
-    all_categories: Vec<String>,
-    lang: String,
-    base_path: String,
+    courses: Vec<Course>,
+    semester: Semester,
+    problem: ProblemWithCategories,
+    history: Vec<HistoryEntryView>,
+    /// (user id, username) the problem can be assigned to
+    assignees: Vec<(i64, String)>,
+    /// The course's exams a log item's problem can be put on
+    exams: Vec<Exam>,
+    related: Vec<RelatedProblem>,
+    user: Option<AuthUser>,
+    brand: Branding,
 }
 
-// Forms
-#[derive(FromForm)]
-struct NewSemester {
-    name: String,
+impl ProblemDetailTemplate {
+    fn is_assignee(&self, user_id: &i64) -> bool {
+        self.problem.assigned_to == Some(*user_id)
+    }
 }
 
-#[derive(FromForm)]
-struct NewCourse {
-    code: String,
-    title: String,
+#[derive(Template)]
+#[template(path = "assignments.html")]
+struct AssignmentsTemplate {
+    problems: Vec<ProblemWithCategories>,
+    user: Option<AuthUser>,
+    brand: Branding,
 }
 
-#[derive(FromForm)]
-struct NewLogItem {
-    kind: String,
-    title: String,
-    description: Option<String>,
-    link: Option<String>,
-    date: Option<String>,
+#[derive(Template)]
+#[template(path = "course_discussions.html")]
+struct CourseDiscussionsTemplate {
+    course: Course,
+    courses: Vec<Course>,
+    semester: Semester,
+    threads: Vec<DiscussionThread>,
+    user: Option<AuthUser>,
+    brand: Branding,
 }
 
-#[derive(FromForm)]
-struct UpdateLogItem {
-    kind: String,
-    title: String,
-    description: Option<String>,
-    link: Option<String>,
-    date: Option<String>,
+#[derive(Template)]
+#[template(path = "discussion_thread.html")]
+struct DiscussionThreadTemplate {
+    course: Course,
+    courses: Vec<Course>,
+    semester: Semester,
+    thread: DiscussionThread,
+    posts: Vec<DiscussionPost>,
+    user: Option<AuthUser>,
+    brand: Branding,
 }
 
-#[derive(FromForm)]
-struct NewProblem<'r> {
-    screenshot: TempFile<'r>,
-    notes: Option<String>,
-    categories: Option<String>, // Comma separated
-    solution_link: Option<String>,
+#[derive(Template)]
+#[template(path = "partials/discussion_thread_row.html")]
+struct DiscussionThreadRowTemplate {
+    thread: DiscussionThread,
+}
+
+#[derive(Template)]
+#[template(path = "partials/discussion_post.html")]
+struct DiscussionPostTemplate {
+    post: DiscussionPost,
+}
+
+#[derive(Template)]
+#[template(path = "digest_settings.html")]
+struct DigestSettingsTemplate {
+    subscription: Option<DigestSubscription>,
+    // Without an SMTP host nothing is sent, so the form is only informational
+    email_configured: bool,
+    weekdays: &'static [(i64, &'static str)],
+    hours: Vec<i64>,
+    preview: String,
+    notice: Option<(String, String)>,
+    user: Option<AuthUser>,
+    brand: Branding,
+}
+
+impl DigestSettingsTemplate {
+    fn weekday_selected(&self, weekday: &i64) -> bool {
+        self.subscription.as_ref().map_or(1, |s| s.weekday) == *weekday
+    }
+
+    fn hour_selected(&self, hour: &i64) -> bool {
+        self.subscription.as_ref().map_or(8, |s| s.hour) == *hour
+    }
+}
+
+#[derive(Template)]
+#[template(path = "digest_unsubscribe.html")]
+struct DigestUnsubscribeTemplate {
+    token: String,
+    done: bool,
+    user: Option<AuthUser>,
+    brand: Branding,
+}
+
+#[derive(Template)]
+#[template(path = "account.html")]
+struct AccountTemplate {
+    account: User,
+    // Without an SMTP host no verification link can be sent
+    email_configured: bool,
+    /// The account's /todos.ics link, once it has made one
+    todos_url: Option<String>,
+    notice: Option<(String, String)>,
+    user: Option<AuthUser>,
+    brand: Branding,
+}
+
+#[derive(Template)]
+#[template(path = "account_verify.html")]
+struct AccountVerifyTemplate {
+    token: String,
+    // None until the button is pressed, then whether it worked
+    verified: Option<bool>,
+    user: Option<AuthUser>,
+    brand: Branding,
+}
+
+#[derive(Template)]
+#[template(path = "public/calendar.html")]
+struct PublicCalendarTemplate {
+    course: Course,
+    weeks: Vec<CalendarWeek>,
+    unscheduled: Vec<PublicLogItem>,
+    active_kinds: Vec<String>,
+    lang: String,
+    base_path: String,
+    /// Some strings are shown untranslated; poll for them
+    pending_translations: bool,
+    brand: Branding,
+}
+
+#[derive(Template)]
+#[template(path = "public/problems.html")]
+struct PublicProblemsTemplate {
+    course: Course,
+    problems: Vec<PublicProblem>,
+    all_categories: Vec<String>,
+    lang: String,
+    base_path: String,
+    pending_translations: bool,
+    brand: Branding,
+}
+
+#[derive(Template)]
+#[template(path = "public/problem_embed.html")]
+struct PublicProblemEmbedTemplate {
+    course: Course,
+    problem: PublicProblem,
+    /// Absolute URL of this page, for oEmbed discovery
+    embed_url: String,
+    base_url: String,
+    brand: Branding,
+}
+
+// The report form under a problem card, or the thanks once it is sent
+#[derive(Template)]
+#[template(path = "public/report_form.html")]
+struct PublicReportTemplate {
+    slug: String,
+    public_id: String,
+    lang: String,
+    reasons: &'static [(&'static str, &'static str, &'static str)],
+    sent: bool,
+}
+
+#[derive(Template)]
+#[template(path = "public/translation_poll.html")]
+struct TranslationPollTemplate {
+    public_id: String,
+}
+
+#[derive(Template)]
+#[template(path = "public/translation_fills.html")]
+struct TranslationFillsTemplate {
+    /// (translate::text_key of the source, translation)
+    fills: Vec<(String, String)>,
+}
+
+// Forms
+#[derive(FromForm)]
+struct NewSemester {
+    name: String,
+}
+
+#[derive(FromForm)]
+struct WrappedSettings {
+    wrapped_published: Option<String>,
+    wrapped_slug: Option<String>,
+}
+
+#[derive(FromForm)]
+struct NewCourse {
+    code: String,
+    title: String,
+    #[field(validate = valid_units())]
+    units: Option<f64>,
+}
+
+#[derive(FromForm)]
+struct CourseDetails {
+    code: String,
+    title: String,
+}
+
+#[derive(FromForm)]
+struct CourseColor {
+    color: String,
+}
+
+#[derive(FromForm)]
+struct CourseWebhook {
+    webhook_url: String,
+}
+
+#[derive(FromForm)]
+struct NewThread {
+    title: String,
+    body: String,
+}
+
+#[derive(FromForm)]
+struct NewPost {
+    body: String,
+}
+
+#[derive(FromForm)]
+struct AccountEmail {
+    email: String,
+}
+
+#[derive(FromForm)]
+struct DigestSettings {
+    enabled: Option<String>,
+    email: String,
+    weekday: i64,
+    hour: i64,
+}
+
+#[derive(FromForm)]
+struct AssignProblem {
+    // Empty for "unassigned"
+    user_id: Option<i64>,
+}
+
+#[derive(FromForm)]
+struct AttachProblemToExam {
+    // Empty to take the problem off its exam
+    exam_id: Option<i64>,
+}
+
+#[derive(FromForm)]
+struct LogItemStatusForm {
+    // Omitted to step to the next status; empty to stop tracking
+    status: Option<String>,
+}
+
+#[derive(FromForm)]
+struct LogItemTagsForm {
+    // Tags to add, separated like categories
+    add: Option<String>,
+    // One tag to take off
+    remove: Option<String>,
+}
+
+#[derive(FromForm)]
+struct NewBookmark {
+    // "12:34", "1:02:03" or plain seconds
+    time: String,
+    note: Option<String>,
+}
+
+#[derive(FromForm)]
+struct NewProblemLink {
+    // "variant", "prerequisite" or "follow_up", relative to the problem being viewed
+    kind: String,
+    linked_problem_id: i64,
+}
+
+#[derive(FromForm)]
+struct CourseJoinSettings {
+    role: String,
+    regenerate: Option<String>,
+    disable: Option<String>,
+}
+
+#[derive(FromForm)]
+struct JoinCourse {
+    code: String,
+}
+
+#[derive(FromForm)]
+struct CourseTranslationContext {
+    subject: Option<String>,
+    level: String,
+    style: String,
+}
+
+#[derive(FromForm)]
+struct CourseGrade {
+    #[field(validate = valid_units())]
+    units: Option<f64>,
+    grade: Option<String>,
+}
+
+#[derive(FromForm)]
+struct NewLogItem {
+    kind: String,
+    title: String,
+    description: Option<String>,
+    link: Option<String>,
+    date: Option<String>,
+}
+
+#[derive(FromForm)]
+struct UpdateLogItem {
+    kind: String,
+    title: String,
+    description: Option<String>,
+    link: Option<String>,
+    date: Option<String>,
+}
+
+#[derive(FromForm)]
+struct NewProblem<'r> {
+    screenshot: form::Result<'r, TempFile<'r>>,
+    body: Option<String>, // The problem typed in, when there is no screenshot
+    notes: Option<String>,
+    categories: Option<String>, // Comma separated
+    solution_link: Option<String>,
+    answer: Option<String>,
+}
+
+impl<'r> NewProblem<'r> {
+    /// The screenshot, if one was chosen; a file input left empty still
+    /// arrives, as a zero-length file. Err(413) for one over the `file` limit,
+    /// which `Option<TempFile>` would have dropped without a word.
+    fn screenshot(&mut self) -> Result<Option<&mut TempFile<'r>>, Status> {
+        match &mut self.screenshot {
+            Ok(file) if file.len() > 0 => Ok(Some(file)),
+            Err(errors) if errors.status() == Status::PayloadTooLarge => Err(Status::PayloadTooLarge),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[derive(FromForm)]
+struct NewProblemBatch<'r> {
+    screenshots: Vec<TempFile<'r>>,
+    notes: Option<String>,
+    categories: Option<String>, // Comma separated, shared by every problem in the batch
+    solution_link: Option<String>,
+}
+
+#[derive(FromForm)]
+struct SplitProblem<'r> {
+    screenshot: TempFile<'r>,
+    regions: Vec<String>, // "x,y,width,height" in the screenshot's pixels, one per problem
+    notes: Option<String>,
+    categories: Option<String>, // Comma separated, shared by every problem cut from it
+    solution_link: Option<String>,
+}
+
+#[derive(FromForm)]
+struct ImportProblem {
+    image_link: String, // http(s) URL of an image or PDF to download
+    notes: Option<String>,
+    categories: Option<String>, // Comma separated
+    solution_link: Option<String>,
+    answer: Option<String>,
 }
 
 #[derive(FromForm)]
 struct UpdateProblem {
+    body: Option<String>,
     notes: Option<String>,
     solution_link: Option<String>,
+    answer: Option<String>,
     categories: Option<String>,
 }
 
+#[derive(FromForm)]
+struct NewStudyPreset {
+    name: String,
+    source: Vec<String>,
+    category: Vec<i64>,
+    sort: Option<String>,
+    difficulty: Option<String>,
+    previous: Option<String>,
+    exam: Option<i64>,
+}
+
+#[derive(FromForm)]
+struct NewStudyGoal {
+    category_id: i64,
+    target: i64,
+    deadline: Option<String>,
+}
+
 #[derive(FromForm)]
 struct LoginUser {
     username: String,
@@ -227,6 +1074,16 @@ struct LoginUser {
 struct RegisterUser {
     username: String,
     password: String,
+    invite_code: Option<String>,
+}
+
+#[derive(FromForm)]
+struct SetupInstance {
+    username: String,
+    password: String,
+    instance_name: Option<String>,
+    upload_dir: Option<String>,
+    translation_api_key: Option<String>,
 }
 
 #[derive(FromForm)]
@@ -234,6 +1091,19 @@ struct NewExam {
     title: String,
     semester: Option<String>,
     link: Option<String>,
+    exam_date: Option<String>,
+    /// A study preset whose problems the new exam starts with
+    preset_id: Option<i64>,
+}
+
+#[derive(FromForm)]
+struct ExamCoverage {
+    category: Vec<i64>,
+}
+
+#[derive(FromForm)]
+struct ExamScope {
+    log_item: Vec<i64>,
 }
 
 #[derive(FromForm)]
@@ -241,162 +1111,994 @@ struct UpdateExam {
     title: String,
     semester: Option<String>,
     link: Option<String>,
+    exam_date: Option<String>,
+}
+
+#[derive(FromForm)]
+struct SiteSettings<'r> {
+    instance_name: Option<String>,
+    footer_text: Option<String>,
+    logo: Option<TempFile<'r>>,
+    remove_logo: Option<String>,
+}
+
+#[derive(FromForm)]
+struct ApiLimitsForm {
+    per_minute: Option<u32>,
+    per_day: Option<u32>,
 }
 
 #[derive(FromForm)]
-struct CourseSettings {
+struct CourseSettings<'r> {
     is_published: Option<String>,
     public_slug: Option<String>,
     show_lecture_links: Option<String>,
+    discourage_indexing: Option<String>,
+    public_theme: Option<String>,
+    banner: Option<TempFile<'r>>,
+    remove_banner: Option<String>,
 }
 
-// Shared query for fetching a problem with categories
-const PROBLEM_WITH_CATEGORIES_QUERY: &str = r#"
+// Study goals with progress: distinct problems in the goal's category, or one
+// carried over into it, reviewed since the goal was set, up to and including
+// its deadline day
+const STUDY_GOALS_QUERY: &str = r#"
     SELECT
-        p.id, p.log_item_id, p.exam_id, p.description, p.notes, p.image_url, p.solution_link,
-        GROUP_CONCAT(c.name) as category_names,
-        COALESCE(l.kind, 'Exam') as source_kind,
-        COALESCE(l.title, e.title, '') as source_title
-    FROM problems p
-    LEFT JOIN log_items l ON p.log_item_id = l.id
-    LEFT JOIN exams e ON p.exam_id = e.id
-    LEFT JOIN problem_categories pc ON p.id = pc.problem_id
-    LEFT JOIN categories c ON pc.category_id = c.id
-    WHERE p.id = ?
-    GROUP BY p.id
+        g.id, g.user_id, g.course_id, g.category_id, c.name as category_name, g.target, g.deadline, g.created_at,
+        (
+            SELECT COUNT(DISTINCT r.problem_id)
+            FROM study_reviews r
+            JOIN problem_categories pc ON pc.problem_id = r.problem_id
+            JOIN category_lineage cl ON cl.ancestor_id = pc.category_id
+            WHERE cl.category_id = g.category_id
+              AND r.user_id = g.user_id
+              AND r.reviewed_at >= g.created_at
+              AND (g.deadline IS NULL OR date(r.reviewed_at) <= g.deadline)
+        ) as progress
+    FROM study_goals g
+    JOIN categories c ON g.category_id = c.id
+    WHERE g.user_id = ?
 "#;
 
-// Auth Routes
+// ========== Focus Suggestions ==========
+//
+// The dashboard suggests the categories most in need of study across the
+// courses of the user's newest semester: those with the highest share of problems marked
+// incorrect, weighted by how long ago the user last reviewed any of them
+// (see FocusSuggestion::score). Each links to a study session filtered to it.
 
-#[get("/login")]
-async fn get_login(user: Option<AuthUser>) -> Result<LoginTemplate, Redirect> {
-    if user.is_some() {
-        return Err(Redirect::to("/"));
-    }
-    Ok(LoginTemplate { user: None, error: None })
+const FOCUS_SUGGESTION_COUNT: usize = 3;
+
+async fn focus_suggestions(db: &mut Connection<Db>, user_id: i64) -> Vec<FocusSuggestion> {
+    let mut suggestions = sqlx::query_as::<_, FocusSuggestion>(&format!(
+        r#"
+        SELECT
+            c.id as course_id, c.code as course_code, c.color as course_color, cat.id as category_id, cat.name as category_name,
+            COUNT(*) as problems,
+            SUM(p.is_incorrect) as incorrect,
+            julianday('now') - julianday((
+                SELECT MAX(r.reviewed_at)
+                FROM study_reviews r
+                JOIN problem_categories pc2 ON pc2.problem_id = r.problem_id
+                JOIN category_lineage cl ON cl.ancestor_id = pc2.category_id
+                WHERE cl.category_id = cat.id AND r.user_id = ?
+            )) as days_since_review
+        FROM categories cat
+        JOIN courses c ON cat.course_id = c.id
+        JOIN problem_categories pc ON pc.category_id = cat.id
+        JOIN problems p ON pc.problem_id = p.id
+        WHERE c.id IN ({courses}) AND c.semester_id = (
+            SELECT s.id FROM semesters s JOIN courses c2 ON c2.semester_id = s.id
+            WHERE c2.id IN ({courses})
+            ORDER BY s.created_at DESC, s.id DESC LIMIT 1
+        )
+        GROUP BY cat.id
+        HAVING SUM(p.is_incorrect) > 0
+        "#,
+        courses = access::COURSES
+    ))
+    .bind(user_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_all(&mut ***db)
+    .await
+    .unwrap_or_default();
+
+    suggestions.sort_by(|a, b| b.score().total_cmp(&a.score()).then(b.incorrect.cmp(&a.incorrect)));
+    suggestions.truncate(FOCUS_SUGGESTION_COUNT);
+    suggestions
 }
 
-#[post("/login", data = "<form>")]
-async fn post_login(mut db: Connection<Db>, cookies: &CookieJar<'_>, form: Form<LoginUser>) -> Result<Redirect, LoginTemplate> {
-    let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = ?")
-        .bind(&form.username)
-        .fetch_optional(&mut **db)
+// Time per graded card for each of the course's categories, slowest correct
+// answers first. Reviews under an earlier course's category carried over into
+// one count toward it. Only reviews that recorded a time count.
+const CATEGORY_TIMES_QUERY: &str = r#"
+    SELECT
+        c.id as category_id, c.name as category_name,
+        COUNT(*) as reviews,
+        AVG(r.time_spent_ms) as average_ms,
+        AVG(CASE WHEN r.grade > 1 THEN r.time_spent_ms END) as correct_average_ms,
+        (
+            SELECT AVG(r2.time_spent_ms)
+            FROM study_reviews r2
+            WHERE r2.user_id = r.user_id AND r2.grade > 1 AND r2.time_spent_ms IS NOT NULL
+              AND r2.problem_id IN (
+                  SELECT pc2.problem_id FROM problem_categories pc2
+                  JOIN category_lineage cl2 ON cl2.ancestor_id = pc2.category_id
+                  JOIN categories c2 ON cl2.category_id = c2.id
+                  WHERE c2.course_id = c.course_id
+              )
+        ) as course_correct_average_ms
+    FROM study_reviews r
+    JOIN problem_categories pc ON pc.problem_id = r.problem_id
+    JOIN category_lineage cl ON cl.ancestor_id = pc.category_id
+    JOIN categories c ON cl.category_id = c.id
+    WHERE r.user_id = ? AND c.course_id = ? AND r.time_spent_ms IS NOT NULL
+    GROUP BY c.id
+    ORDER BY correct_average_ms IS NULL, correct_average_ms DESC, c.name
+"#;
+
+// ========== Log Item Kinds ==========
+
+// The course's kinds in display order. Kinds missing from log_item_kinds (all of
+// them for a course that was never configured) follow in ALL_KINDS order, enabled.
+async fn course_log_item_kinds(db: &mut Connection<Db>, course_id: i64) -> Vec<LogItemKind> {
+    let mut kinds = sqlx::query_as::<_, LogItemKind>("SELECT * FROM log_item_kinds WHERE course_id = ? ORDER BY position")
+        .bind(course_id)
+        .fetch_all(&mut ***db)
         .await
-        .unwrap_or(None);
+        .unwrap_or_default();
 
-    if let Some(user) = user {
-        if verify(&form.password, &user.password_hash).unwrap_or(false) {
-            cookies.add_private(
-                Cookie::build(("user_id", user.id.to_string()))
-                    .same_site(SameSite::Lax)
-                    .build()
-            );
-            return Ok(Redirect::to("/"));
+    for kind in ALL_KINDS {
+        if !kinds.iter().any(|k| k.kind == *kind) {
+            kinds.push(LogItemKind {
+                course_id,
+                kind: kind.to_string(),
+                position: kinds.len() as i64,
+                enabled: true,
+            });
         }
     }
+    kinds
+}
 
-    Err(LoginTemplate {
-        user: None,
-        error: Some("Invalid username or password".into())
-    })
+#[derive(FromForm)]
+struct LogItemKindSettings {
+    order: Vec<String>,
+    enabled: Vec<String>,
 }
 
-#[get("/register")]
-async fn get_register(mut db: Connection<Db>, user: Option<AuthUser>) -> Result<RegisterTemplate, Redirect> {
-    if user.is_some() {
-        return Err(Redirect::to("/"));
-    }
-    let has_users: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users)")
-        .fetch_one(&mut **db)
+// ========== User Preferences ==========
+
+async fn get_preference(db: &mut Connection<Db>, user_id: i64, key: &str) -> Option<String> {
+    sqlx::query_scalar("SELECT value FROM user_preferences WHERE user_id = ? AND key = ?")
+        .bind(user_id)
+        .bind(key)
+        .fetch_optional(&mut ***db)
         .await
-        .unwrap_or(true);
-    if has_users {
-        return Err(Redirect::to("/login"));
-    }
-    Ok(RegisterTemplate { user: None, error: None })
+        .unwrap_or(None)
 }
 
-#[post("/register", data = "<form>")]
-async fn post_register(mut db: Connection<Db>, cookies: &CookieJar<'_>, form: Form<RegisterUser>) -> Result<Redirect, RegisterTemplate> {
-    // Block registration if any user already exists
-    let has_users: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users)")
-        .fetch_one(&mut **db)
-        .await
-        .unwrap_or(true);
+async fn set_preference(db: &mut Connection<Db>, user_id: i64, key: &str, value: &str) {
+    let _ = sqlx::query("INSERT OR REPLACE INTO user_preferences (user_id, key, value) VALUES (?, ?, ?)")
+        .bind(user_id)
+        .bind(key)
+        .bind(value)
+        .execute(&mut ***db)
+        .await;
+}
 
-    if has_users {
-        return Err(RegisterTemplate {
-            user: None,
-            error: Some("Registration is closed.".into())
-        });
+fn study_filters_key(course_id: i64) -> String {
+    format!("study_filters:{}", course_id)
+}
+
+const STUDY_SORTS: &[&str] = &["oldest", "newest", "source", "hardest", "easiest"];
+
+// Drop unknown sources/sorts so persisted filters are always safe to replay
+fn sanitize_study_filters(sources: Vec<String>, categories: Vec<i64>, sort: Option<String>, difficulty: Option<String>, include_previous: bool, exam: Option<i64>) -> StudyFilters {
+    StudyFilters {
+        sources: sources
+            .into_iter()
+            .filter(|s| s == "Exam" || ALL_KINDS.contains(&s.as_str()))
+            .collect(),
+        categories,
+        sort: sort
+            .filter(|s| STUDY_SORTS.contains(&s.as_str()))
+            .unwrap_or_else(|| STUDY_SORTS[0].to_string()),
+        difficulty: difficulty
+            .filter(|d| d == "unrated" || difficulty::bounds(d).is_some())
+            .unwrap_or_default(),
+        include_previous,
+        exam,
+        search: String::new(),
     }
+}
 
-    // Check if user exists
-    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE username = ?)")
-        .bind(&form.username)
-        .fetch_one(&mut **db)
-        .await
-        .unwrap_or(false);
+// ========== Problem Helpers ==========
 
-    if exists {
-        return Err(RegisterTemplate {
-            user: None,
-            error: Some("Username already taken".into())
-        });
+// Persist an uploaded file (screenshot, handout PDF, banner) in upload storage, keeping
+// its original name and MIME type. The caller owns one reference to the stored file and
+// must release it when the row pointing at it goes away. Files the deployment's upload
+// scan refuses are never stored.
+async fn save_upload(db: &mut Connection<Db>, config: &AppConfig, file: &mut TempFile<'_>) -> Result<SavedUpload, Rejection> {
+    let (bytes, original_filename, mime_type) = read_upload(config, file).await?;
+    let ext = uploads::extension_for(&bytes, mime_type.as_deref(), original_filename.as_deref());
+    let url = uploads::store(db, &bytes, &ext).await.expect("Unable to store upload");
+
+    Ok(SavedUpload { url, original_filename, mime_type, imported_from: None })
+}
+
+// An upload's contents, cleaned filename and MIME type, once it has passed the
+// configured checks
+async fn read_upload(config: &AppConfig, file: &mut TempFile<'_>) -> Result<(Vec<u8>, Option<String>, Option<String>), Rejection> {
+    let original_filename = file.raw_name()
+        .and_then(|name| uploads::clean_filename(name.dangerous_unsafe_unsanitized_raw().as_str()));
+    let mime_type = file.content_type()
+        .filter(|ct| !ct.is_any())
+        .map(|ct| format!("{}/{}", ct.top(), ct.sub()));
+
+    let mut bytes = Vec::new();
+    file.open()
+        .await
+        .expect("Unable to read upload")
+        .read_to_end(&mut bytes)
+        .await
+        .expect("Unable to read upload");
+    scan::check(&config.upload_scan, &bytes, mime_type.as_deref()).await?;
+
+    Ok((bytes, original_filename, mime_type))
+}
+
+fn upload_error(file: &TempFile<'_>, rejection: Rejection) -> UploadErrorTemplate {
+    let filename = file.raw_name()
+        .and_then(|name| uploads::clean_filename(name.dangerous_unsafe_unsanitized_raw().as_str()));
+    UploadErrorTemplate { filename, message: rejection.message() }
+}
+
+// Release the screenshots of problems that are about to be deleted
+async fn release_problem_images(db: &mut Connection<Db>, image_urls: Vec<Option<String>>) {
+    for url in image_urls.into_iter().flatten() {
+        uploads::release(db, &url).await;
+    }
+}
+
+// Find-or-create each comma/顿号 separated category in the course and link it to the problem.
+// A slash nests categories: "微积分/泰勒级数" links 泰勒级数 under 微积分.
+// Returns the linked category paths in input order.
+async fn link_problem_categories(db: &mut sqlx::SqliteConnection, category_cache: &CategoryCache, problem_id: i64, course_id: i64, cats: &str) -> Vec<String> {
+    let mut processed_cats = Vec::new();
+    for cat_path in cats.split([',', '\u{3001}']).map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let Some((cat_id, path)) = find_or_create_category(db, category_cache, course_id, cat_path).await else {
+            continue;
+        };
+        // "DP, dp" names one category
+        if processed_cats.contains(&path) {
+            continue;
+        }
+
+        sqlx::query("INSERT INTO problem_categories (problem_id, category_id) VALUES (?, ?)")
+            .bind(problem_id)
+            .bind(cat_id)
+            .execute(&mut *db)
+            .await
+            .unwrap();
+
+        processed_cats.push(path);
+    }
+    processed_cats
+}
+
+// The category at `path`, creating any missing level. Returns its id and the
+// path as stored. Each name is normalized (see categories.rs) and matches an
+// existing one regardless of ASCII case. A top-level category whose name is
+// the whole path (made before nesting existed) is used as is.
+async fn find_or_create_category(db: &mut sqlx::SqliteConnection, category_cache: &CategoryCache, course_id: i64, path: &str) -> Option<(i64, String)> {
+    let legacy = sqlx::query_as::<_, (i64, String)>("SELECT id, name FROM categories WHERE course_id = ? AND name = ? COLLATE NOCASE AND parent_id IS NULL")
+        .bind(course_id)
+        .bind(categories::normalize_name(path))
+        .fetch_optional(&mut *db)
+        .await
+        .unwrap();
+    if legacy.is_some() {
+        return legacy;
+    }
+
+    let names: Vec<String> = path.split(['/', '\u{ff0f}']).map(categories::normalize_name).filter(|s| !s.is_empty()).collect();
+    let mut parent_id: Option<i64> = None;
+    let mut stored = Vec::with_capacity(names.len());
+    for name in &names {
+        let existing = sqlx::query_as::<_, (i64, String)>("SELECT id, name FROM categories WHERE course_id = ? AND name = ? COLLATE NOCASE AND parent_id IS ?")
+            .bind(course_id)
+            .bind(name)
+            .bind(parent_id)
+            .fetch_optional(&mut *db)
+            .await
+            .unwrap();
+
+        let (id, name) = match existing {
+            Some(category) => category,
+            None => {
+                // Another request may have made it since the lookup; the
+                // unique index turns that into a no-op update of its row
+                let category = sqlx::query_as::<_, (i64, String)>(
+                    "INSERT INTO categories (course_id, name, parent_id) VALUES (?, ?, ?) ON CONFLICT (course_id, COALESCE(parent_id, 0), name COLLATE NOCASE) DO UPDATE SET name = name RETURNING id, name"
+                )
+                .bind(course_id)
+                .bind(name)
+                .bind(parent_id)
+                .fetch_one(&mut *db)
+                .await
+                .unwrap();
+                category_cache.invalidate(course_id);
+                category
+            }
+        };
+        parent_id = Some(id);
+        stored.push(name);
+    }
+    parent_id.map(|id| (id, stored.join("/")))
+}
+
+// A blank answer (or problem) box means there is none, not an empty one
+fn answer_text(answer: Option<String>) -> Option<String> {
+    answer.filter(|a| !a.trim().is_empty())
+}
+
+// Insert a problem under a log item and link its categories. It has a
+// screenshot, a typed-in body, or both.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn insert_log_problem(
+    db: &mut Connection<Db>,
+    category_cache: &CategoryCache,
+    log_item: &LogItem,
+    upload: Option<SavedUpload>,
+    body: Option<String>,
+    notes: Option<String>,
+    solution_link: Option<String>,
+    answer: Option<String>,
+    categories: Option<&str>,
+) -> ProblemWithCategories {
+    let description = if upload.is_some() { "Screenshot Problem" } else { "Text Problem" };
+    let body = answer_text(body);
+    let answer = answer_text(answer);
+    let (image_url, original_filename, mime_type, imported_from) = match upload {
+        Some(upload) => (Some(upload.url), upload.original_filename, upload.mime_type, upload.imported_from),
+        None => (None, None, None, None),
+    };
+
+    let problem_id = sqlx::query("INSERT INTO problems (log_item_id, description, notes, image_url, body, solution_link, answer, original_filename, mime_type, imported_from, is_incorrect, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 1, CURRENT_TIMESTAMP)")
+        .bind(log_item.id)
+        .bind(description)
+        .bind(&notes)
+        .bind(&image_url)
+        .bind(&body)
+        .bind(&solution_link)
+        .bind(&answer)
+        .bind(&original_filename)
+        .bind(&mime_type)
+        .bind(&imported_from)
+        .execute(&mut ***db)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+
+    let category_names = match categories {
+        Some(cats) => link_problem_categories(db, category_cache, problem_id, log_item.course_id, cats).await.join(","),
+        None => String::new(),
+    };
+
+    ProblemWithCategories {
+        id: problem_id,
+        log_item_id: Some(log_item.id),
+        exam_id: None,
+        description: description.to_string(),
+        notes,
+        image_url,
+        body,
+        solution_link,
+        answer,
+        category_names: if category_names.is_empty() { None } else { Some(category_names) },
+        source_kind: log_item.kind.clone(),
+        source_title: log_item.title.clone(),
+        source_url: format!("/courses/{}#log-{}", log_item.course_id, log_item.id),
+        original_filename,
+        mime_type,
+        imported_from,
+        public_id: queries::fetch_public_id(db, "problems", problem_id).await,
+        number: queries::fetch_problem_number(db, problem_id).await,
+        assigned_to: None,
+        assignee_name: None,
+        difficulty: None,
+        exam_title: None,
+    }
+}
+
+// Auth Routes
+
+#[get("/login")]
+async fn get_login(mut db: Connection<Db>, user: Option<AuthUser>, brand: Branding) -> Result<LoginTemplate, Redirect> {
+    if user.is_some() {
+        return Err(Redirect::to("/"));
+    }
+    if needs_setup(&mut db).await {
+        return Err(Redirect::to("/setup"));
+    }
+    Ok(LoginTemplate { user: None, error: None, brand })
+}
+
+#[post("/login", data = "<form>")]
+async fn post_login(mut db: Connection<Db>, config: &State<AppConfig>, cookies: &CookieJar<'_>, brand: Branding, form: Form<LoginUser>) -> Result<Redirect, LoginTemplate> {
+    // Either the username or a verified email; a username that looks like an
+    // address wins over another account's email
+    let user = sqlx::query_as::<_, User>(
+        "SELECT * FROM users WHERE username = ? OR (email = ? COLLATE NOCASE AND email_verified = 1) ORDER BY username = ? DESC LIMIT 1"
+    )
+    .bind(&form.username)
+    .bind(form.username.trim())
+    .bind(&form.username)
+    .fetch_optional(&mut **db)
+    .await
+    .unwrap_or(None);
+
+    if let Some(user) = user {
+        if verify(&form.password, &user.password_hash).unwrap_or(false) {
+            auth::sign_in(cookies, &config.session, user.id);
+            return Ok(Redirect::to("/"));
+        }
+    }
+
+    Err(LoginTemplate {
+        user: None,
+        error: Some("用户名或密码错误。".into()),
+        brand,
+    })
+}
+
+// Who may register right now: anyone while there are no accounts (that first
+// account becomes the admin; the form sends people to /setup instead), invitees
+// on an invite_only instance, else nobody
+#[derive(PartialEq)]
+enum Registration {
+    FirstUser,
+    InviteOnly,
+    Closed,
+}
+
+async fn registration(db: &mut Connection<Db>, config: &AppConfig) -> Registration {
+    if needs_setup(db).await {
+        Registration::FirstUser
+    } else if config.invite_only {
+        Registration::InviteOnly
+    } else {
+        Registration::Closed
+    }
+}
+
+#[get("/register?<code>")]
+async fn get_register(mut db: Connection<Db>, config: &State<AppConfig>, user: Option<AuthUser>, brand: Branding, code: Option<String>) -> Result<RegisterTemplate, Redirect> {
+    if user.is_some() {
+        return Err(Redirect::to("/"));
+    }
+    match registration(&mut db, config).await {
+        Registration::FirstUser => Err(Redirect::to("/setup")),
+        Registration::Closed => Err(Redirect::to("/login")),
+        mode => Ok(RegisterTemplate { user: None, error: None, needs_invite: mode == Registration::InviteOnly, invite_code: code, brand }),
+    }
+}
+
+#[post("/register", data = "<form>")]
+async fn post_register(mut db: Connection<Db>, config: &State<AppConfig>, cookies: &CookieJar<'_>, brand: Branding, form: Form<RegisterUser>) -> Result<Redirect, RegisterTemplate> {
+    let mode = registration(&mut db, config).await;
+    let needs_invite = mode == Registration::InviteOnly;
+    let invite_code = form.invite_code.as_deref().map(str::trim).filter(|c| !c.is_empty());
+    let error = |message: &str| RegisterTemplate {
+        user: None,
+        error: Some(message.into()),
+        needs_invite,
+        invite_code: invite_code.map(String::from),
+        brand: brand.clone(),
+    };
+
+    if mode == Registration::Closed {
+        return Err(error("Registration is closed."));
+    }
+
+    // Check if user exists
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE username = ?)")
+        .bind(&form.username)
+        .fetch_one(&mut **db)
+        .await
+        .unwrap_or(false);
+
+    if exists {
+        return Err(error("Username already taken"));
+    }
+
+    // Claim the invite before creating the account so two people can't share one code
+    if needs_invite {
+        let claimed = sqlx::query("UPDATE invites SET used_at = CURRENT_TIMESTAMP WHERE code = ? AND used_at IS NULL")
+            .bind(invite_code)
+            .execute(&mut **db)
+            .await
+            .map(|r| r.rows_affected() == 1)
+            .unwrap_or(false);
+        if !claimed {
+            return Err(error("Invalid or already used invite code"));
+        }
     }
 
     let hash = hash(&form.password, DEFAULT_COST).unwrap();
-    let id = sqlx::query("INSERT INTO users (username, password_hash) VALUES (?, ?)")
+    let id = sqlx::query("INSERT INTO users (username, password_hash, is_admin) VALUES (?, ?, ?)")
         .bind(&form.username)
         .bind(hash)
+        .bind(mode == Registration::FirstUser)
         .execute(&mut **db)
         .await;
 
     match id {
         Ok(result) => {
-            cookies.add_private(
-                Cookie::build(("user_id", result.last_insert_rowid().to_string()))
-                    .same_site(SameSite::Lax)
-                    .build()
-            );
+            let user_id = result.last_insert_rowid();
+            if needs_invite {
+                let _ = sqlx::query("UPDATE invites SET used_by = ? WHERE code = ?")
+                    .bind(user_id)
+                    .bind(invite_code)
+                    .execute(&mut **db)
+                    .await;
+            }
+            auth::sign_in(cookies, &config.session, user_id);
             Ok(Redirect::to("/"))
         },
-        Err(_) => Err(RegisterTemplate {
-            user: None,
-            error: Some("Registration failed".into())
-        })
+        Err(_) => {
+            if needs_invite {
+                let _ = sqlx::query("UPDATE invites SET used_at = NULL WHERE code = ? AND used_by IS NULL")
+                    .bind(invite_code)
+                    .execute(&mut **db)
+                    .await;
+            }
+            Err(error("Registration failed"))
+        }
+    }
+}
+
+// ========== First-Run Setup ==========
+//
+// An empty database sends every visitor to /setup, which creates the admin
+// account and records the instance settings in one step.
+
+async fn needs_setup(db: &mut Connection<Db>) -> bool {
+    let has_users: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users)")
+        .fetch_one(&mut ***db)
+        .await
+        .unwrap_or(true);
+    !has_users
+}
+
+#[get("/setup")]
+async fn get_setup(mut db: Connection<Db>, brand: Branding) -> Result<SetupTemplate, Redirect> {
+    if !needs_setup(&mut db).await {
+        return Err(Redirect::to("/login"));
+    }
+    Ok(SetupTemplate {
+        user: None,
+        error: None,
+        username: String::new(),
+        instance_name: settings::DEFAULT_INSTANCE_NAME.to_string(),
+        upload_dir: uploads::DEFAULT_DIR.to_string(),
+        brand,
+    })
+}
+
+#[post("/setup", data = "<form>")]
+async fn post_setup(mut db: Connection<Db>, config: &State<AppConfig>, cookies: &CookieJar<'_>, branding: &State<BrandingState>, form: Form<SetupInstance>) -> Result<Redirect, SetupTemplate> {
+    if !needs_setup(&mut db).await {
+        return Ok(Redirect::to("/login"));
+    }
+
+    let username = form.username.trim();
+    let instance_name = form.instance_name.as_deref().map(str::trim).filter(|n| !n.is_empty()).unwrap_or(settings::DEFAULT_INSTANCE_NAME);
+    let upload_dir = form.upload_dir.as_deref().map(str::trim).filter(|d| !d.is_empty()).unwrap_or(uploads::DEFAULT_DIR);
+    let api_key = form.translation_api_key.as_deref().map(str::trim).filter(|k| !k.is_empty());
+    let error = |message: String| SetupTemplate {
+        user: None,
+        error: Some(message),
+        username: username.to_string(),
+        instance_name: instance_name.to_string(),
+        upload_dir: upload_dir.to_string(),
+        brand: branding.get(),
+    };
+
+    if username.is_empty() || form.password.is_empty() {
+        return Err(error("Username and password are required".into()));
+    }
+    // Fail now rather than on the first screenshot upload
+    if let Err(e) = tokio::fs::create_dir_all(upload_dir).await {
+        return Err(error(format!("Cannot create upload directory {}: {}", upload_dir, e)));
+    }
+
+    // Only succeeds while there are still no users, so two setups can't race
+    let hash = hash(&form.password, DEFAULT_COST).unwrap();
+    let inserted = sqlx::query("INSERT INTO users (username, password_hash, is_admin) SELECT ?, ?, 1 WHERE NOT EXISTS (SELECT 1 FROM users)")
+        .bind(username)
+        .bind(hash)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+    if inserted.rows_affected() == 0 {
+        return Ok(Redirect::to("/login"));
+    }
+    let user_id = inserted.last_insert_rowid();
+
+    // Demo data seeded before there was an account is the admin's
+    sqlx::query("UPDATE semesters SET user_id = ? WHERE user_id IS NULL")
+        .bind(user_id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    settings::set(&mut db, settings::INSTANCE_NAME, instance_name).await;
+    settings::set(&mut db, settings::UPLOAD_DIR, upload_dir).await;
+    if let Some(key) = api_key {
+        settings::set(&mut db, settings::TRANSLATION_API_KEY, key).await;
+    }
+    branding.set(Branding::load(&mut db).await);
+
+    auth::sign_in(cookies, &config.session, user_id);
+    Ok(Redirect::to("/"))
+}
+
+// ========== Invites ==========
+
+async fn is_admin(db: &mut Connection<Db>, user_id: i64) -> bool {
+    sqlx::query_scalar("SELECT is_admin FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(&mut ***db)
+        .await
+        .unwrap_or(None)
+        .unwrap_or(false)
+}
+
+const INVITES_QUERY: &str = "SELECT i.*, u.username as used_by_name FROM invites i LEFT JOIN users u ON i.used_by = u.id";
+
+#[get("/invites")]
+async fn view_invites(mut db: Connection<Db>, user: AuthUser, brand: Branding, config: &State<AppConfig>, host: Option<&Host<'_>>) -> Result<InvitesTemplate, Status> {
+    if !is_admin(&mut db, user.id).await {
+        return Err(Status::Forbidden);
+    }
+    let invites = sqlx::query_as::<_, Invite>(&format!("{} ORDER BY i.created_at DESC, i.code", INVITES_QUERY))
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+    Ok(InvitesTemplate { invites, enabled: config.invite_only, base_url: config.base_url(host.map(|h| h.to_string()).as_deref()), user: Some(user), brand })
+}
+
+#[post("/invites")]
+async fn create_invite(mut db: Connection<Db>, user: AuthUser, config: &State<AppConfig>, host: Option<&Host<'_>>) -> Result<InviteRowTemplate, Status> {
+    if !is_admin(&mut db, user.id).await {
+        return Err(Status::Forbidden);
+    }
+    let code = Uuid::new_v4().simple().to_string()[..12].to_string();
+    sqlx::query("INSERT INTO invites (code, created_by, created_at) VALUES (?, ?, CURRENT_TIMESTAMP)")
+        .bind(&code)
+        .bind(user.id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    let invite = sqlx::query_as::<_, Invite>(&format!("{} WHERE i.code = ?", INVITES_QUERY))
+        .bind(&code)
+        .fetch_one(&mut **db)
+        .await
+        .unwrap();
+    Ok(InviteRowTemplate { invite, base_url: config.base_url(host.map(|h| h.to_string()).as_deref()) })
+}
+
+// Only unused codes can be revoked; used ones stay as a record of who joined
+#[delete("/invites/<code>")]
+async fn delete_invite(mut db: Connection<Db>, user: AuthUser, code: &str) -> Result<String, Status> {
+    if !is_admin(&mut db, user.id).await {
+        return Err(Status::Forbidden);
+    }
+    sqlx::query("DELETE FROM invites WHERE code = ? AND used_at IS NULL")
+        .bind(code)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+    Ok(String::new())
+}
+
+// ========== Site Settings ==========
+//
+// Name, logo and footer line shown on every page. Saving writes them to
+// `instance_settings` and swaps them into the managed `BrandingState`, so the
+// next request already renders with them. Cached public pages show the old
+// ones, so they go too.
+
+#[get("/admin/site")]
+async fn view_site_settings(mut db: Connection<Db>, user: AuthUser, brand: Branding, flash: Option<FlashMessage<'_>>) -> Result<SiteSettingsTemplate, Status> {
+    if !is_admin(&mut db, user.id).await {
+        return Err(Status::Forbidden);
+    }
+    let notice = flash.map(|f| (f.kind().to_string(), f.message().to_string()));
+    let api_limits = api_limits::limits(&mut db).await;
+    Ok(SiteSettingsTemplate { notice, api_limits, user: Some(user), brand })
+}
+
+#[post("/admin/site", data = "<form>")]
+async fn update_site_settings(
+    mut db: Connection<Db>,
+    user: AuthUser,
+    config: &State<AppConfig>,
+    branding: &State<BrandingState>,
+    public_cache: &State<PublicPageCache>,
+    mut form: Form<SiteSettings<'_>>,
+) -> Result<Flash<Redirect>, Status> {
+    if !is_admin(&mut db, user.id).await {
+        return Err(Status::Forbidden);
+    }
+    let instance_name = form.instance_name.as_deref().map(str::trim).unwrap_or("");
+    let footer_text = form.footer_text.as_deref().map(str::trim).unwrap_or("");
+    // An empty value falls back to the default name / no footer
+    settings::set(&mut db, settings::INSTANCE_NAME, instance_name).await;
+    settings::set(&mut db, settings::FOOTER_TEXT, footer_text).await;
+
+    // An empty file input still submits a zero-length part
+    let logo_url = match form.logo.as_mut() {
+        Some(logo) if logo.len() > 0 => match save_upload(&mut db, config, logo).await {
+            Ok(upload) => Some(upload.url),
+            Err(rejection) => return Ok(Flash::error(Redirect::to("/admin/site"), rejection.message())),
+        },
+        _ => None,
+    };
+    if logo_url.is_some() || form.remove_logo.as_deref() == Some("on") {
+        let old_logo = settings::get(&mut db, settings::LOGO_URL).await;
+        settings::set(&mut db, settings::LOGO_URL, logo_url.as_deref().unwrap_or("")).await;
+        if let Some(old_logo) = old_logo {
+            uploads::release(&mut db, &old_logo).await;
+        }
+    }
+
+    branding.set(Branding::load(&mut db).await);
+    public_cache.clear();
+    Ok(Flash::success(Redirect::to("/admin/site"), "站点设置已保存。"))
+}
+
+// Per-account caps on the JSON API; see api_limits.rs. Empty means no limit.
+#[post("/admin/api_limits", data = "<form>")]
+async fn update_api_limits(mut db: Connection<Db>, user: AuthUser, form: Form<ApiLimitsForm>) -> Result<Flash<Redirect>, Status> {
+    if !is_admin(&mut db, user.id).await {
+        return Err(Status::Forbidden);
+    }
+    let limits = api_limits::Limits {
+        per_minute: form.per_minute.filter(|n| *n > 0),
+        per_day: form.per_day.filter(|n| *n > 0),
+    };
+    api_limits::set_limits(&mut db, limits).await;
+    Ok(Flash::success(Redirect::to("/admin/site"), "API 限额已保存。"))
+}
+
+// ========== Data Integrity ==========
+//
+// Runs the checks in integrity.rs; repairing fixes everything they found and
+// comes back to the page with a fresh scan.
+
+#[get("/admin/integrity")]
+async fn view_integrity(mut db: Connection<Db>, user: AuthUser, brand: Branding, flash: Option<FlashMessage<'_>>) -> Result<IntegrityTemplate, Status> {
+    if !is_admin(&mut db, user.id).await {
+        return Err(Status::Forbidden);
+    }
+    let findings = integrity::scan(&mut db).await;
+    let notice = flash.map(|f| (f.kind().to_string(), f.message().to_string()));
+    Ok(IntegrityTemplate { findings, notice, user: Some(user), brand })
+}
+
+#[post("/admin/integrity")]
+async fn repair_integrity(mut db: Connection<Db>, user: AuthUser) -> Result<Flash<Redirect>, Status> {
+    if !is_admin(&mut db, user.id).await {
+        return Err(Status::Forbidden);
+    }
+    let repaired = integrity::repair(&mut db).await;
+    Ok(Flash::success(Redirect::to("/admin/integrity"), format!("已修复 {} 处。", repaired)))
+}
+
+// ========== Instance Stats ==========
+//
+// Usage across the whole instance, gathered in stats.rs.
+
+#[get("/admin/stats")]
+async fn view_stats(mut db: Connection<Db>, user: AuthUser, brand: Branding) -> Result<AdminStatsTemplate, Status> {
+    if !is_admin(&mut db, user.id).await {
+        return Err(Status::Forbidden);
+    }
+    let stats = stats::collect(&mut db).await;
+    Ok(AdminStatsTemplate { stats, user: Some(user), brand })
+}
+
+// ========== Translation Cache Transfer ==========
+//
+// Download the translation cache, or load one (or a glossary) into this
+// instance; see translate.rs for the formats. Both live on the site settings page.
+
+#[derive(FromForm)]
+struct TranslationImport<'r> {
+    file: TempFile<'r>,
+    replace: Option<String>,
+}
+
+#[get("/admin/translations/export")]
+async fn export_translations(mut db: Connection<Db>, user: AuthUser) -> Result<Download, Status> {
+    if !is_admin(&mut db, user.id).await {
+        return Err(Status::Forbidden);
+    }
+    let rows = translate::export(&mut db).await;
+    let body = serde_json::to_vec_pretty(&rows).map_err(|_| Status::InternalServerError)?;
+    let disposition = Header::new("Content-Disposition", uploads::content_disposition(Some("zhixi-translations.json"), true));
+    Ok(Download { body: (ContentType::JSON, body), disposition })
+}
+
+#[post("/admin/translations/import", data = "<form>")]
+async fn import_translations(mut db: Connection<Db>, user: AuthUser, form: Form<TranslationImport<'_>>) -> Result<Flash<Redirect>, Status> {
+    if !is_admin(&mut db, user.id).await {
+        return Err(Status::Forbidden);
+    }
+    let mut bytes = Vec::new();
+    form.file.open()
+        .await
+        .map_err(|_| Status::BadRequest)?
+        .read_to_end(&mut bytes)
+        .await
+        .map_err(|_| Status::BadRequest)?;
+    let Ok(dump) = serde_json::from_slice::<translate::TranslationDump>(&bytes) else {
+        return Ok(Flash::error(Redirect::to("/admin/site"), "文件不是翻译缓存导出或词汇表（JSON）。"));
+    };
+
+    let summary = translate::import(&mut db, dump, form.replace.as_deref() == Some("on")).await;
+    let mut message = format!("已导入翻译：新增 {} 条，覆盖 {} 条，保留原有 {} 条。", summary.added, summary.replaced, summary.kept);
+    if summary.skipped > 0 {
+        message.push_str(&format!("另有 {} 条为空，已跳过。", summary.skipped));
     }
+    Ok(Flash::success(Redirect::to("/admin/site"), message))
 }
 
 #[post("/logout")]
-async fn logout(cookies: &CookieJar<'_>) -> Redirect {
-    cookies.remove_private(Cookie::from("user_id"));
+async fn logout(config: &State<AppConfig>, cookies: &CookieJar<'_>) -> Redirect {
+    auth::sign_out(cookies, &config.session);
     Redirect::to("/login")
 }
 
+// ========== Deleting Courses ==========
+//
+// Everything in a course goes with its row (ON DELETE CASCADE), edit history
+// included: there is no course left to revert into. A semester takes its
+// courses the same way. The screenshots and banners are the one thing left to
+// do by hand: their URLs are read first and released once the rows are gone,
+// all in one transaction.
+
+// The screenshots and banner of a course, for releasing once it's gone
+async fn course_upload_urls(db: &mut sqlx::SqliteConnection, course_id: i64) -> sqlx::Result<Vec<String>> {
+    let mut urls: Vec<Option<String>> = sqlx::query_scalar(
+        r#"
+        SELECT p.image_url FROM problems p
+        LEFT JOIN log_items l ON p.log_item_id = l.id
+        LEFT JOIN exams e ON p.exam_id = e.id
+        WHERE COALESCE(l.course_id, e.course_id) = ?
+        "#,
+    )
+    .bind(course_id)
+    .fetch_all(&mut *db)
+    .await?;
+    let banner_url: Option<String> = sqlx::query_scalar("SELECT banner_url FROM courses WHERE id = ?")
+        .bind(course_id)
+        .fetch_optional(&mut *db)
+        .await?
+        .flatten();
+    urls.push(banner_url);
+    Ok(urls.into_iter().flatten().collect())
+}
+
+// `DELETE FROM courses` or `DELETE FROM semesters` for `id`, releasing the
+// uploads of `course_ids` along with it and their cached categories after
+async fn delete_with_uploads(db: &mut Connection<Db>, category_cache: &CategoryCache, delete: &str, id: i64, course_ids: &[i64]) -> sqlx::Result<()> {
+    let mut tx = db::begin_write(db).await?;
+    let mut urls = Vec::new();
+    for &course_id in course_ids {
+        urls.extend(course_upload_urls(&mut tx, course_id).await?);
+    }
+    sqlx::query(delete).bind(id).execute(&mut *tx).await?;
+    for url in urls {
+        uploads::release(&mut tx, &url).await;
+    }
+    tx.commit().await?;
+    for &course_id in course_ids {
+        category_cache.invalidate(course_id);
+    }
+    Ok(())
+}
+
 // Routes
 
 #[get("/")]
-async fn index(_db: Connection<Db>, user: Option<AuthUser>) -> Redirect {
+async fn index(mut db: Connection<Db>, user: Option<AuthUser>) -> Redirect {
     if user.is_none() {
-         return Redirect::to("/login");
+         return Redirect::to(if needs_setup(&mut db).await { "/setup" } else { "/login" });
     }
     Redirect::to("/dashboard")
 }
 
 #[get("/dashboard")]
-async fn dashboard(mut db: Connection<Db>, user: AuthUser) -> IndexTemplate {
-    let semesters = sqlx::query_as::<_, Semester>("SELECT * FROM semesters ORDER BY created_at DESC")
+async fn dashboard(mut db: Connection<Db>, user: AuthUser, brand: Branding, config: &State<AppConfig>, flash: Option<FlashMessage<'_>>) -> IndexTemplate {
+    let semesters = sqlx::query_as::<_, Semester>("SELECT * FROM semesters WHERE user_id = ? ORDER BY created_at DESC")
+        .bind(user.id)
         .fetch_all(&mut **db)
         .await
         .unwrap_or_default();
-    IndexTemplate { semesters, user: Some(user) }
+
+    // Courses in other people's semesters, joined with a code
+    let joined_courses = sqlx::query_as::<_, Course>(
+        "SELECT c.* FROM courses c JOIN course_members m ON m.course_id = c.id JOIN semesters s ON c.semester_id = s.id WHERE m.user_id = ? AND s.user_id IS NOT ? ORDER BY m.joined_at, c.code"
+    )
+    .bind(user.id)
+    .bind(user.id)
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_default();
+
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let upcoming_exams = sqlx::query_as::<_, UpcomingExam>(&format!(
+        r#"
+        SELECT
+            e.id, e.course_id, c.code as course_code, c.color as course_color, e.title, e.exam_date,
+            CAST(julianday(e.exam_date) - julianday(?) AS INTEGER) as days_left
+        FROM exams e
+        JOIN courses c ON e.course_id = c.id
+        WHERE e.exam_date >= ? AND c.id IN ({})
+        ORDER BY e.exam_date, e.id
+        LIMIT 5
+        "#,
+        access::COURSES
+    ))
+    .bind(&today)
+    .bind(&today)
+    .bind(user.id)
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_default();
+
+    let focus_suggestions = focus_suggestions(&mut db, user.id).await;
+
+    // Unfinished tasks across every course, soonest first
+    let open_tasks = sqlx::query_as::<_, OpenTask>(&format!(
+        r#"
+        SELECT
+            l.id, l.course_id, c.code as course_code, c.color as course_color, l.title, l.date, l.status,
+            COALESCE(l.date < ?, 0) as overdue
+        FROM log_items l
+        JOIN courses c ON l.course_id = c.id
+        WHERE l.status IN ('todo', 'in_progress') AND c.id IN ({})
+        ORDER BY l.date IS NULL, l.date, l.id
+        LIMIT 8
+        "#,
+        access::COURSES
+    ))
+    .bind(&today)
+    .bind(user.id)
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_default();
+
+    let admin = is_admin(&mut db, user.id).await;
+    let show_invites = config.invite_only && admin;
+    let translation_cache = if admin { Some(translate::cache_stats(&mut db).await) } else { None };
+
+    let join_error = flash.map(|f| f.message().to_string());
+
+    IndexTemplate { semesters, joined_courses, upcoming_exams, focus_suggestions, open_tasks, show_invites, translation_cache, join_error, user: Some(user), brand }
 }
 
 #[post("/semesters", data = "<form>")]
 async fn create_semester(mut db: Connection<Db>, user: AuthUser, form: Form<NewSemester>) -> SemesterRowTemplate {
-    let id = sqlx::query("INSERT INTO semesters (name) VALUES (?)")
+    let id = sqlx::query("INSERT INTO semesters (name, user_id) VALUES (?, ?)")
         .bind(&form.name)
+        .bind(user.id)
         .execute(&mut **db)
         .await
         .unwrap()
@@ -406,12 +2108,14 @@ async fn create_semester(mut db: Connection<Db>, user: AuthUser, form: Form<NewS
         id,
         name: form.name.clone(),
         created_at: String::new(),
+        wrapped_published: false,
+        wrapped_slug: None,
     };
-    SemesterRowTemplate { semester, user: Some(user) }
+    SemesterRowTemplate { semester }
 }
 
 #[get("/semesters/<id>")]
-async fn view_semester(mut db: Connection<Db>, user: AuthUser, id: i64) -> SemesterTemplate {
+async fn view_semester(mut db: Connection<Db>, user: AuthUser, brand: Branding, id: i64) -> SemesterTemplate {
     let semester = sqlx::query_as::<_, Semester>("SELECT * FROM semesters WHERE id = ?")
         .bind(id)
         .fetch_one(&mut **db)
@@ -424,69 +2128,371 @@ async fn view_semester(mut db: Connection<Db>, user: AuthUser, id: i64) -> Semes
         .await
         .unwrap_or_default();
 
-    SemesterTemplate { semester, courses, user: Some(user) }
+    let summary = grades::summarize(&courses);
+
+    SemesterTemplate { semester, courses, summary, user: Some(user), brand }
+}
+
+// The dashboard row, for cancelling a rename
+#[get("/semesters/<id>/row")]
+async fn get_semester_row(mut db: Connection<Db>, _user: AuthUser, id: i64) -> Option<SemesterRowTemplate> {
+    let semester = queries::fetch_semester(&mut db, id).await?;
+    Some(SemesterRowTemplate { semester })
+}
+
+#[get("/semesters/<id>/edit")]
+async fn get_edit_semester(mut db: Connection<Db>, _user: AuthUser, id: i64) -> Option<SemesterEditTemplate> {
+    let semester = queries::fetch_semester(&mut db, id).await?;
+    Some(SemesterEditTemplate { semester })
+}
+
+#[post("/semesters/<id>", data = "<form>")]
+async fn update_semester(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Form<NewSemester>) -> Result<SemesterRowTemplate, Status> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Err(Status::BadRequest);
+    }
+
+    sqlx::query("UPDATE semesters SET name = ? WHERE id = ?")
+        .bind(name)
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    let semester = queries::fetch_semester(&mut db, id).await.ok_or(Status::NotFound)?;
+    Ok(SemesterRowTemplate { semester })
+}
+
+// The semester and everything in it, screenshots included; the row swaps out
+// for nothing
+#[delete("/semesters/<id>")]
+async fn delete_semester(mut db: Connection<Db>, _user: AuthUser, category_cache: &State<CategoryCache>, id: i64) -> String {
+    let course_ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM courses WHERE semester_id = ?")
+        .bind(id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+    delete_with_uploads(&mut db, category_cache, "DELETE FROM semesters WHERE id = ?", id, &course_ids).await.unwrap();
+    String::new()
+}
+
+#[derive(Responder)]
+struct Download {
+    body: (ContentType, Vec<u8>),
+    disposition: Header<'static>,
+}
+
+// A zip of the whole term for archiving; see export.rs for the layout
+#[get("/semesters/<id>/export")]
+async fn export_semester(mut db: Connection<Db>, _user: AuthUser, id: i64) -> Result<Download, Status> {
+    let bundle = export::semester_bundle(&mut db, id).await.ok_or(Status::NotFound)?;
+    let filename = format!("zhixi-semester-{}.zip", id);
+    let disposition = Header::new("Content-Disposition", uploads::content_disposition(Some(&filename), true));
+
+    Ok(Download { body: (ContentType::ZIP, bundle), disposition })
+}
+
+// The course as plain HTML pages for keeping after it's over; see export.rs
+#[get("/courses/<id>/export/site")]
+async fn export_course_site(mut db: Connection<Db>, _user: AuthUser, id: i64) -> Result<Download, Status> {
+    let course = queries::fetch_course(&mut db, id).await.ok_or(Status::NotFound)?;
+
+    let items = sqlx::query_as::<_, LogItem>("SELECT * FROM log_items WHERE course_id = ? ORDER BY date DESC, id DESC")
+        .bind(id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+    let mut log_items = Vec::new();
+    for item in items {
+        let problems = queries::fetch_log_item_problems(&mut db, item.id).await;
+        log_items.push((item, problems));
+    }
+
+    let course_exams = sqlx::query_as::<_, Exam>("SELECT * FROM exams WHERE course_id = ? ORDER BY id DESC")
+        .bind(id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+    let mut exams = Vec::new();
+    for exam in course_exams {
+        let problems = queries::fetch_exam_problems(&mut db, exam.id).await;
+        exams.push((exam, problems));
+    }
+
+    let upload_urls: BTreeSet<String> = log_items
+        .iter()
+        .flat_map(|(_, problems)| problems)
+        .chain(exams.iter().flat_map(|(_, problems)| problems))
+        .filter_map(|p| p.image_url.clone())
+        .filter(|url| url.starts_with("/uploads/"))
+        .collect();
+    let page = CourseSiteTemplate {
+        course,
+        exported_on: chrono::Local::now().format("%Y-%m-%d").to_string(),
+        log_items,
+        exams,
+    };
+    let bundle = export::site_bundle(&mut db, &page.render().unwrap(), &upload_urls).await;
+
+    let filename = format!("zhixi-course-{}-site.zip", id);
+    let disposition = Header::new("Content-Disposition", uploads::content_disposition(Some(&filename), true));
+    Ok(Download { body: (ContentType::ZIP, bundle), disposition })
+}
+
+#[get("/semesters/<id>/wrapped")]
+async fn view_semester_wrapped(mut db: Connection<Db>, user: AuthUser, brand: Branding, flash: Option<FlashMessage<'_>>, id: i64) -> Result<SemesterWrappedTemplate, Status> {
+    let semester = sqlx::query_as::<_, Semester>("SELECT * FROM semesters WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut **db)
+        .await
+        .unwrap()
+        .ok_or(Status::NotFound)?;
+    let wrapped = wrapped::collect(&mut db, id).await;
+    let error = flash.map(|f| f.message().to_string());
+
+    Ok(SemesterWrappedTemplate { semester, wrapped, error, user: Some(user), brand })
+}
+
+#[post("/semesters/<id>/wrapped", data = "<form>")]
+async fn update_semester_wrapped(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Form<WrappedSettings>) -> Result<Redirect, Flash<Redirect>> {
+    let published = form.wrapped_published.as_deref() == Some("on");
+    let slug = form.wrapped_slug.as_deref()
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string());
+    let back = format!("/semesters/{}/wrapped", id);
+    if published && slug.is_none() {
+        return Err(Flash::error(Redirect::to(back), "公开总结需要先填写地址。"));
+    }
+
+    let saved = sqlx::query("UPDATE semesters SET wrapped_published = ?, wrapped_slug = ? WHERE id = ?")
+        .bind(published)
+        .bind(&slug)
+        .bind(id)
+        .execute(&mut **db)
+        .await;
+    if saved.is_err() {
+        return Err(Flash::error(Redirect::to(back), "这个地址已被其他学期使用。"));
+    }
+    Ok(Redirect::to(back))
+}
+
+const MAX_UNITS: f64 = 30.0;
+
+// Both course forms take units; anything outside this is a typo
+fn valid_units<'v>(units: &Option<f64>) -> form::Result<'v, ()> {
+    match units {
+        Some(units) if !(0.0..=MAX_UNITS).contains(units) => {
+            Err(form::Error::validation(format!("学分需要在 0 到 {} 之间。", MAX_UNITS)).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+// A form Rocket refused. A bad value is 422 with the message, which htmx
+// forms show in the page's #form-error element and a plain form post shows
+// on its own; anything else, like a body over the size limit, goes to its
+// catcher as usual.
+#[derive(Responder)]
+enum FormRejected {
+    #[response(status = 422)]
+    Invalid(String, Header<'static>, Header<'static>),
+    Other(Status),
+}
+
+impl FormRejected {
+    fn new(errors: &form::Errors<'_>) -> FormRejected {
+        if errors.status() != Status::UnprocessableEntity {
+            return FormRejected::Other(errors.status());
+        }
+        let message = errors.iter()
+            .find_map(|e| match &e.kind {
+                form::error::ErrorKind::Validation(message) => Some(message.to_string()),
+                _ => None,
+            })
+            .unwrap_or_else(|| "表单有误，请检查后重新提交。".to_string());
+        FormRejected::Invalid(message, Header::new("HX-Retarget", "#form-error"), Header::new("HX-Reswap", "innerHTML"))
+    }
 }
 
 #[post("/semesters/<id>/courses", data = "<form>")]
-async fn create_course(mut db: Connection<Db>, user: AuthUser, id: i64, form: Form<NewCourse>) -> CourseCardTemplate {
-    let course_id = sqlx::query("INSERT INTO courses (semester_id, code, title) VALUES (?, ?, ?)")
+async fn create_course(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Result<Form<NewCourse>, form::Errors<'_>>) -> Result<CourseCardTemplate, FormRejected> {
+    let form = form.map_err(|errors| FormRejected::new(&errors))?;
+    let course_id = sqlx::query("INSERT INTO courses (semester_id, code, title, units) VALUES (?, ?, ?, ?)")
         .bind(id)
         .bind(&form.code)
         .bind(&form.title)
+        .bind(form.units)
         .execute(&mut **db)
         .await
         .unwrap()
         .last_insert_rowid();
 
-    let course = Course {
-        id: course_id,
-        semester_id: id,
-        code: form.code.clone(),
-        title: form.title.clone(),
-        is_published: false,
-        public_slug: None,
-        show_lecture_links: false,
-    };
-    CourseCardTemplate { course, user: Some(user) }
+    let course = queries::fetch_course(&mut db, course_id).await.unwrap();
+    Ok(CourseCardTemplate { course })
+}
+
+#[get("/courses/<id>/card")]
+async fn get_course_card(mut db: Connection<Db>, _user: AuthUser, id: i64) -> Option<CourseCardTemplate> {
+    let course = queries::fetch_course(&mut db, id).await?;
+    Some(CourseCardTemplate { course })
+}
+
+#[get("/courses/<id>/edit")]
+async fn get_edit_course(mut db: Connection<Db>, _user: AuthUser, id: i64) -> Option<CourseEditTemplate> {
+    let course = queries::fetch_course(&mut db, id).await?;
+    Some(CourseEditTemplate { course })
 }
 
-#[get("/courses/<id>")]
-async fn view_course_log(mut db: Connection<Db>, user: AuthUser, id: i64) -> CourseLogTemplate {
-    let course = sqlx::query_as::<_, Course>("SELECT * FROM courses WHERE id = ?")
+#[post("/courses/<id>/edit", data = "<form>")]
+async fn update_course(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Form<CourseDetails>) -> Result<CourseCardTemplate, Status> {
+    let (code, title) = (form.code.trim(), form.title.trim());
+    if code.is_empty() || title.is_empty() {
+        return Err(Status::BadRequest);
+    }
+
+    sqlx::query("UPDATE courses SET code = ?, title = ? WHERE id = ?")
+        .bind(code)
+        .bind(title)
         .bind(id)
-        .fetch_one(&mut **db)
+        .execute(&mut **db)
         .await
         .unwrap();
 
-    let semester = sqlx::query_as::<_, Semester>("SELECT * FROM semesters WHERE id = ?")
-        .bind(course.semester_id)
-        .fetch_one(&mut **db)
-        .await
-        .unwrap();
+    let course = queries::fetch_course(&mut db, id).await.ok_or(Status::NotFound)?;
+    Ok(CourseCardTemplate { course })
+}
 
-    let courses = sqlx::query_as::<_, Course>("SELECT * FROM courses WHERE semester_id = ?")
-        .bind(course.semester_id)
-        .fetch_all(&mut **db)
-        .await
-        .unwrap_or_default();
+// See "Deleting Courses" for what goes with it
+#[delete("/courses/<id>")]
+async fn delete_course(mut db: Connection<Db>, _user: AuthUser, category_cache: &State<CategoryCache>, id: i64) -> String {
+    delete_with_uploads(&mut db, category_cache, "DELETE FROM courses WHERE id = ?", id, &[id]).await.unwrap();
+    String::new()
+}
 
-    let log_items = sqlx::query_as::<_, LogItem>("SELECT * FROM log_items WHERE course_id = ? ORDER BY date DESC, id DESC")
+const COURSE_LOG_VIEWS: &[&str] = &["list", "timeline"];
+
+// `?view=` switches between the card list and the timeline; the choice sticks
+#[get("/courses/<id>?<view>&<status>&<tag>")]
+#[allow(clippy::too_many_arguments)]
+async fn view_course_log(
+    mut db: Connection<Db>,
+    user: AuthUser,
+    brand: Branding,
+    category_cache: &State<CategoryCache>,
+    id: i64,
+    view: Option<String>,
+    status: Option<String>,
+    tag: Option<String>,
+) -> CourseLogTemplate {
+    let CourseContext { course, semester, courses } = queries::fetch_course_context(&mut db, id).await.unwrap();
+
+    let mut log_items = sqlx::query_as::<_, LogItem>("SELECT * FROM log_items WHERE course_id = ? ORDER BY date DESC, id DESC")
         .bind(id)
         .fetch_all(&mut **db)
         .await
         .unwrap_or_default();
+    let print_week = log_week_to_print(&log_items, chrono::Local::now().date_naive());
+    let status_filter = status.filter(|s| !s.is_empty());
+    if let Some(filter) = &status_filter {
+        log_items.retain(|item| item.matches_status(filter));
+    }
+    let tags = queries::fetch_course_log_tags(&mut db, id).await;
+    let tag_filter = tag.map(|t| categories::normalize_name(&t)).filter(|t| !t.is_empty());
+    if let Some(filter) = &tag_filter {
+        log_items.retain(|item| tags.iter().any(|(id, tag)| *id == item.id && tag.eq_ignore_ascii_case(filter)));
+    }
+
+    let categories = category_cache.get(&mut db, id).await;
+
+    let kinds = course_log_item_kinds(&mut db, id).await;
+
+    let descriptions: Vec<&str> = log_items.iter().filter_map(|i| i.description.as_deref()).collect();
+    let previews = link_preview::previews_for(&mut db, &descriptions).await;
+
+    let view = match view.filter(|v| COURSE_LOG_VIEWS.contains(&v.as_str())) {
+        Some(view) => {
+            set_preference(&mut db, user.id, "course_log_view", &view).await;
+            view
+        }
+        None => get_preference(&mut db, user.id, "course_log_view").await.unwrap_or_else(|| "list".to_string()),
+    };
+
+    let problem_counts: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT p.log_item_id, COUNT(*) FROM problems p JOIN log_items l ON p.log_item_id = l.id WHERE l.course_id = ? GROUP BY p.log_item_id",
+    )
+    .bind(id)
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_default();
+
+    CourseLogTemplate { course, courses, log_items, semester, categories, kinds, previews, view, status_filter, tag_filter, tags, problem_counts, print_week, user: Some(user), brand }
+}
+
+// ========== Printable Week ==========
+//
+// A week of the course log and the problems under it, laid out to print and
+// bring to office hours. Weeks are numbered as on the public calendar: week 1
+// runs Monday to Sunday around the course's first dated log item. Undated
+// items belong to no week.
+
+fn log_item_date(item: &LogItem) -> Option<NaiveDate> {
+    item.date.as_deref().and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+}
+
+// The Monday week 1 starts on; None while no item has a date
+fn first_week_monday(log_items: &[LogItem]) -> Option<NaiveDate> {
+    let first = log_items.iter().filter_map(log_item_date).min()?;
+    Some(first - chrono::Duration::days(first.weekday().num_days_from_monday() as i64))
+}
+
+// The week the course log's print link opens: the one `today` falls in, or
+// the last dated week once that has passed. None before week 1.
+fn log_week_to_print(log_items: &[LogItem], today: NaiveDate) -> Option<i64> {
+    let monday = first_week_monday(log_items)?;
+    let last = log_items.iter().filter_map(log_item_date).max()?;
+    let day = today.min(last);
+    (day >= monday).then(|| (day - monday).num_days() / 7 + 1)
+}
 
-    let categories = sqlx::query_as::<_, Category>("SELECT * FROM categories WHERE course_id = ?")
+#[get("/courses/<id>/logs/week/<week>/print")]
+async fn print_log_week(mut db: Connection<Db>, _user: AuthUser, id: i64, week: i64) -> Result<LogWeekPrintTemplate, Status> {
+    let course = queries::fetch_course(&mut db, id).await.ok_or(Status::NotFound)?;
+    let log_items = sqlx::query_as::<_, LogItem>("SELECT * FROM log_items WHERE course_id = ? ORDER BY date ASC, id ASC")
         .bind(id)
         .fetch_all(&mut **db)
         .await
         .unwrap_or_default();
 
-    CourseLogTemplate { course, courses, log_items, semester, categories, user: Some(user) }
+    let first_monday = first_week_monday(&log_items).ok_or(Status::NotFound)?;
+    let last_week = log_week_to_print(&log_items, NaiveDate::MAX).unwrap_or(1);
+    if week < 1 || week > last_week {
+        return Err(Status::NotFound);
+    }
+    let monday = first_monday + chrono::Duration::days((week - 1) * 7);
+    let sunday = monday + chrono::Duration::days(6);
+
+    let mut items = Vec::new();
+    for item in log_items {
+        if log_item_date(&item).is_some_and(|date| date >= monday && date <= sunday) {
+            let problems = queries::fetch_log_item_problems(&mut db, item.id).await;
+            items.push((item, problems));
+        }
+    }
+
+    Ok(LogWeekPrintTemplate {
+        course,
+        week,
+        monday,
+        items,
+        previous_week: (week > 1).then_some(week - 1),
+        next_week: (week < last_week).then_some(week + 1),
+    })
 }
 
 #[post("/courses/<id>/logs", data = "<form>")]
-async fn create_log_item(mut db: Connection<Db>, user: AuthUser, id: i64, form: Form<NewLogItem>) -> LogItemTemplate {
+async fn create_log_item(mut db: Connection<Db>, _user: AuthUser, category_cache: &State<CategoryCache>, id: i64, form: Form<NewLogItem>) -> LogItemTemplate {
     let item_id = sqlx::query("INSERT INTO log_items (course_id, kind, title, description, link, date) VALUES (?, ?, ?, ?, ?, ?)")
         .bind(id)
         .bind(&form.kind)
@@ -499,432 +2505,2649 @@ async fn create_log_item(mut db: Connection<Db>, user: AuthUser, id: i64, form:
         .unwrap()
         .last_insert_rowid();
 
-    let item = LogItem {
-        id: item_id,
-        course_id: id,
-        kind: form.kind.clone(),
-        title: form.title.clone(),
-        description: form.description.clone(),
-        link: form.link.clone(),
-        date: form.date.clone(),
+    if let Some(description) = &form.description {
+        link_preview::queue_previews(&mut db, description).await;
+    }
+
+    let item = queries::fetch_log_item(&mut db, item_id).await.unwrap();
+
+    let categories = category_cache.get(&mut db, id).await;
+
+    let previews = link_preview::previews_for(&mut db, &[item.description.as_deref().unwrap_or_default()]).await;
+
+    let tags = queries::fetch_log_item_tags(&mut db, item.id).await;
+
+    LogItemTemplate { item, categories, previews, tags }
+}
+
+#[delete("/logs/<id>")]
+async fn delete_log_item(mut db: Connection<Db>, user: AuthUser, id: i64) -> String {
+    let Some(item) = queries::fetch_log_item(&mut db, id).await else {
+        return String::new();
+    };
+
+    let problem_ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM problems WHERE log_item_id = ?")
+        .bind(id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+
+    record_history(&mut db, user.id, Some(item.course_id), "delete", Some(&item), None).await;
+    record_problem_deletes(&mut db, user.id, item.course_id, &problem_ids).await;
+
+    let image_urls: Vec<Option<String>> = sqlx::query_scalar("SELECT image_url FROM problems WHERE log_item_id = ?")
+        .bind(id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+
+    // Its problems and everything hanging off them go with it (ON DELETE CASCADE)
+    sqlx::query("DELETE FROM log_items WHERE id = ?")
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+    release_problem_images(&mut db, image_urls).await;
+
+    String::new()
+}
+
+#[get("/logs/<id>/edit")]
+async fn get_edit_log_item(mut db: Connection<Db>, _user: AuthUser, id: i64) -> LogItemEditTemplate {
+    let item = queries::fetch_log_item(&mut db, id).await.unwrap();
+    let kinds = course_log_item_kinds(&mut db, item.course_id).await;
+    LogItemEditTemplate { item, kinds }
+}
+
+#[get("/logs/<id>")]
+async fn get_log_item(mut db: Connection<Db>, _user: AuthUser, category_cache: &State<CategoryCache>, id: i64) -> LogItemTemplate {
+    let item = queries::fetch_log_item(&mut db, id).await.unwrap();
+
+    let categories = category_cache.get(&mut db, item.course_id).await;
+
+    let previews = link_preview::previews_for(&mut db, &[item.description.as_deref().unwrap_or_default()]).await;
+
+    let tags = queries::fetch_log_item_tags(&mut db, item.id).await;
+
+    LogItemTemplate { item, categories, previews, tags }
+}
+
+#[post("/logs/<id>", data = "<form>")]
+async fn update_log_item(mut db: Connection<Db>, user: AuthUser, category_cache: &State<CategoryCache>, id: i64, form: Form<UpdateLogItem>) -> LogItemTemplate {
+    let before = queries::fetch_log_item(&mut db, id).await.unwrap();
+
+    sqlx::query("UPDATE log_items SET kind = ?, title = ?, description = ?, link = ?, date = ? WHERE id = ?")
+        .bind(&form.kind)
+        .bind(&form.title)
+        .bind(&form.description)
+        .bind(&form.link)
+        .bind(&form.date)
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    let item = queries::fetch_log_item(&mut db, id).await.unwrap();
+
+    record_history(&mut db, user.id, Some(item.course_id), "update", Some(&before), Some(&item)).await;
+    if let Some(description) = &item.description {
+        link_preview::queue_previews(&mut db, description).await;
+    }
+
+    let categories = category_cache.get(&mut db, item.course_id).await;
+
+    let previews = link_preview::previews_for(&mut db, &[item.description.as_deref().unwrap_or_default()]).await;
+
+    let tags = queries::fetch_log_item_tags(&mut db, item.id).await;
+
+    LogItemTemplate { item, categories, previews, tags }
+}
+
+// The task status chip on a log item. Not recorded in the history: it's
+// progress, not an edit to the item.
+#[post("/logs/<id>/status", data = "<form>")]
+async fn update_log_item_status(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Form<LogItemStatusForm>) -> Result<LogItemStatusTemplate, Status> {
+    let item = queries::fetch_log_item(&mut db, id).await.ok_or(Status::NotFound)?;
+    let status = match form.status.as_deref() {
+        None => item.next_status(),
+        Some("") => None,
+        Some(status) => Some(LOG_ITEM_STATUSES.iter().find(|(s, _)| *s == status).ok_or(Status::BadRequest)?.0),
+    };
+
+    sqlx::query("UPDATE log_items SET status = ? WHERE id = ?")
+        .bind(status)
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    let item = LogItem { status: status.map(str::to_string), ..item };
+    Ok(LogItemStatusTemplate { item })
+}
+
+#[post("/logs/<id>/problems", data = "<form>")]
+async fn create_problem(mut db: Connection<Db>, _user: AuthUser, config: &State<AppConfig>, category_cache: &State<CategoryCache>, id: i64, mut form: Form<NewProblem<'_>>) -> Result<Result<ProblemRowTemplate, UploadErrorTemplate>, Status> {
+    let log_item = queries::fetch_log_item(&mut db, id).await.unwrap();
+    let body = answer_text(form.body.clone());
+
+    // An oversized screenshot goes to the 413 catcher
+    let upload = match form.screenshot()? {
+        Some(file) => match save_upload(&mut db, config, file).await {
+            Ok(upload) => Some(upload),
+            Err(rejection) => return Ok(Err(upload_error(file, rejection))),
+        },
+        None if body.is_some() => None,
+        None => return Ok(Err(UploadErrorTemplate { filename: None, message: "请上传截图或输入题目。".to_string() })),
+    };
+    let problem = insert_log_problem(&mut db, category_cache, &log_item, upload, body, form.notes.clone(), form.solution_link.clone(), form.answer.clone(), form.categories.as_deref()).await;
+
+    Ok(Ok(ProblemRowTemplate { problem }))
+}
+
+#[post("/logs/<id>/problems/bulk", data = "<form>")]
+async fn create_problems_bulk(mut db: Connection<Db>, _user: AuthUser, config: &State<AppConfig>, category_cache: &State<CategoryCache>, id: i64, form: Form<NewProblemBatch<'_>>) -> String {
+    let NewProblemBatch { mut screenshots, notes, categories, solution_link } = form.into_inner();
+
+    let log_item = queries::fetch_log_item(&mut db, id).await.unwrap();
+
+    // One problem per image, all sharing the same notes/solution link/categories.
+    // A refused image gets an error in its place without stopping the rest.
+    let mut html = String::new();
+    for screenshot in screenshots.iter_mut() {
+        let upload = match save_upload(&mut db, config, screenshot).await {
+            Ok(upload) => upload,
+            Err(rejection) => {
+                html.push_str(&upload_error(screenshot, rejection).render().unwrap());
+                continue;
+            }
+        };
+        let problem = insert_log_problem(&mut db, category_cache, &log_item, Some(upload), None, notes.clone(), solution_link.clone(), None, categories.as_deref()).await;
+        let t = ProblemRowTemplate { problem };
+        html.push_str(&t.render().unwrap());
+    }
+    html
+}
+
+// One screenshot holding several questions, cut into one problem per region
+// marked on it in the split form. The screenshot itself isn't kept.
+#[post("/logs/<id>/problems/split", data = "<form>")]
+async fn create_problems_split(mut db: Connection<Db>, _user: AuthUser, config: &State<AppConfig>, category_cache: &State<CategoryCache>, id: i64, form: Form<SplitProblem<'_>>) -> Result<String, UploadErrorTemplate> {
+    let SplitProblem { mut screenshot, regions, notes, categories, solution_link } = form.into_inner();
+    let log_item = queries::fetch_log_item(&mut db, id).await.unwrap();
+
+    let (bytes, original_filename, mime_type) = match read_upload(config, &mut screenshot).await {
+        Ok(upload) => upload,
+        Err(rejection) => return Err(upload_error(&screenshot, rejection)),
+    };
+    let error = |message: &str| UploadErrorTemplate { filename: original_filename.clone(), message: message.to_string() };
+    let regions: Vec<uploads::Crop> = regions.iter().filter_map(|region| uploads::Crop::parse(region)).collect();
+    if regions.is_empty() || regions.len() > uploads::MAX_CROPS {
+        return Err(error(&format!("请在截图上框出 1 到 {} 道题。", uploads::MAX_CROPS)));
+    }
+    let ext = uploads::extension_for(&bytes, mime_type.as_deref(), original_filename.as_deref());
+    if !uploads::is_croppable(&ext) {
+        return Err(error("只能拆分图片，PDF 请逐页截图后再上传。"));
+    }
+    let Ok(pieces) = uploads::crop(&bytes, &ext, &regions).await else {
+        return Err(error("无法裁剪这张图片。"));
+    };
+
+    let mut html = String::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        let url = uploads::store(&mut db, piece, &ext).await.expect("Unable to store upload");
+        // "hw3.png" becomes "hw3-1.png", "hw3-2.png", …
+        let original_filename = original_filename.as_deref().map(|name| match name.rsplit_once('.') {
+            Some((stem, ext)) => format!("{}-{}.{}", stem, i + 1, ext),
+            None => format!("{}-{}", name, i + 1),
+        });
+        let upload = SavedUpload { url, original_filename, mime_type: mime_type.clone(), imported_from: None };
+        let problem = insert_log_problem(&mut db, category_cache, &log_item, Some(upload), None, notes.clone(), solution_link.clone(), None, categories.as_deref()).await;
+        html.push_str(&ProblemRowTemplate { problem }.render().unwrap());
+    }
+    Ok(html)
+}
+
+// A problem whose image is downloaded from a link; see url_import.rs
+#[post("/logs/<id>/problems/url", data = "<form>")]
+async fn import_problem(mut db: Connection<Db>, _user: AuthUser, config: &State<AppConfig>, client: &State<outbound::Client>, category_cache: &State<CategoryCache>, id: i64, form: Form<ImportProblem>) -> Result<ProblemRowTemplate, UploadErrorTemplate> {
+    let ImportProblem { image_link, notes, categories, solution_link, answer } = form.into_inner();
+    let log_item = queries::fetch_log_item(&mut db, id).await.unwrap();
+    let image_link = image_link.trim().to_string();
+
+    let imported = match url_import::fetch(client, &config.upload_scan, &image_link).await {
+        Ok(imported) => imported,
+        Err(e) => return Err(UploadErrorTemplate { filename: Some(image_link), message: e.message() }),
     };
+    let ext = uploads::extension_for(&imported.bytes, Some(&imported.mime_type), imported.original_filename.as_deref());
+    let url = uploads::store(&mut db, &imported.bytes, &ext).await.expect("Unable to store upload");
+    let upload = SavedUpload { url, original_filename: imported.original_filename, mime_type: Some(imported.mime_type), imported_from: Some(image_link) };
+    let problem = insert_log_problem(&mut db, category_cache, &log_item, Some(upload), None, notes, solution_link, answer, categories.as_deref()).await;
+
+    Ok(ProblemRowTemplate { problem })
+}
+
+#[get("/logs/<id>/problems")]
+async fn get_log_problems(mut db: Connection<Db>, _user: AuthUser, id: i64) -> String {
+    let problems = queries::fetch_log_item_problems(&mut db, id).await;
+
+    let mut html = String::new();
+    for p in problems {
+        let t = ProblemRowTemplate { problem: p };
+        html.push_str(&t.render().unwrap());
+    }
+    html
+}
+
+#[get("/courses/<id>/study")]
+async fn view_course_study(mut db: Connection<Db>, user: AuthUser, brand: Branding, category_cache: &State<CategoryCache>, id: i64) -> CourseStudyTemplate {
+    let CourseContext { course, semester, courses } = queries::fetch_course_context(&mut db, id).await.unwrap();
+
+    let categories = category_cache.get(&mut db, id).await;
+
+    // Restore the last-used filters so the working set survives navigation
+    let filters = get_preference(&mut db, user.id, &study_filters_key(id))
+        .await
+        .and_then(|v| serde_json::from_str::<StudyFilters>(&v).ok())
+        .unwrap_or_default();
 
-    let categories = sqlx::query_as::<_, Category>("SELECT * FROM categories WHERE course_id = ?")
+    let presets = sqlx::query_as::<_, StudyPreset>("SELECT * FROM study_presets WHERE user_id = ? AND course_id = ? ORDER BY name")
+        .bind(user.id)
         .bind(id)
         .fetch_all(&mut **db)
         .await
         .unwrap_or_default();
 
-    LogItemTemplate { item, categories, user: Some(user) }
+    let goals = sqlx::query_as::<_, StudyGoal>(&format!("{} AND g.course_id = ? ORDER BY g.deadline IS NULL, g.deadline, g.id", STUDY_GOALS_QUERY))
+        .bind(user.id)
+        .bind(id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+
+    let category_times = sqlx::query_as::<_, CategoryTime>(CATEGORY_TIMES_QUERY)
+        .bind(user.id)
+        .bind(id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+
+    let previous_course = match course.continues_course_id {
+        Some(previous_id) => queries::fetch_course(&mut db, previous_id).await,
+        None => None,
+    };
+
+    let scoped_exams = sqlx::query_as::<_, Exam>(
+        "SELECT * FROM exams WHERE course_id = ? AND id IN (SELECT exam_id FROM exam_log_items) ORDER BY exam_date IS NULL, exam_date, id",
+    )
+    .bind(id)
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_default();
+
+    CourseStudyTemplate { course, courses, categories, semester, filters, presets, goals, category_times, previous_course, scoped_exams, user: Some(user), brand }
+}
+
+// Problems per batch in the study list; the rest load as the list is scrolled
+const STUDY_BATCH_SIZE: i64 = 30;
+
+// " AND p.id IN (...)" keeping problems in any of `count` categories bound
+// after it; a category also matches everything nested under it and every
+// earlier course's category carried over into those
+fn category_filter(count: usize) -> String {
+    format!(
+        r#" AND p.id IN (
+                WITH RECURSIVE selected (id) AS (
+                    SELECT id FROM categories WHERE id IN ({})
+                    UNION
+                    SELECT c2.id FROM categories c2 JOIN selected ON c2.parent_id = selected.id
+                )
+                SELECT pc2.problem_id FROM problem_categories pc2
+                JOIN category_lineage cl ON cl.ancestor_id = pc2.category_id
+                WHERE cl.category_id IN (SELECT id FROM selected)
+            )"#,
+        vec!["?"; count].join(", ")
+    )
+}
+
+// The course and each course it continues, back through earlier semesters;
+// binds the course id
+const COURSE_CHAIN: &str = r#"
+    WITH RECURSIVE chain (id) AS (
+        SELECT ?
+        UNION
+        SELECT c.continues_course_id FROM courses c JOIN chain ON c.id = chain.id WHERE c.continues_course_id IS NOT NULL
+    )
+    SELECT id FROM chain"#;
+
+const STUDY_PROBLEM_IDS: &str = r#"
+    SELECT p.id FROM problems p
+    JOIN problem_sources src ON src.problem_id = p.id
+    LEFT JOIN log_items l ON p.log_item_id = l.id"#;
+
+// The course's problems matching `filters`, in the chosen order
+async fn fetch_study_problems(db: &mut Connection<Db>, id: i64, filters: &StudyFilters, limit: i64, offset: i64) -> Vec<ProblemWithCategories> {
+    let ids = study_problem_ids(db, id, filters, limit, offset).await;
+    queries::fetch_problems(db, &ids).await
+}
+
+// The ids behind fetch_study_problems; a negative limit means no limit
+async fn study_problem_ids(db: &mut Connection<Db>, id: i64, filters: &StudyFilters, limit: i64, offset: i64) -> Vec<i64> {
+    let mut query = if filters.include_previous {
+        format!("{} WHERE src.course_id IN ({})", STUDY_PROBLEM_IDS, COURSE_CHAIN)
+    } else {
+        format!("{} WHERE src.course_id = ?", STUDY_PROBLEM_IDS)
+    };
+
+    // Filter by Source
+    let has_exam = filters.sources.iter().any(|s| s == "Exam");
+    let log_sources: Vec<&String> = filters.sources.iter().filter(|s| *s != "Exam").collect();
+    let log_placeholders = vec!["?"; log_sources.len()].join(", ");
+
+    if has_exam && !log_sources.is_empty() {
+        query.push_str(&format!(" AND (l.kind IN ({}) OR p.exam_id IS NOT NULL)", log_placeholders));
+    } else if has_exam {
+        query.push_str(" AND p.exam_id IS NOT NULL");
+    } else if !log_sources.is_empty() {
+        query.push_str(&format!(" AND l.kind IN ({})", log_placeholders));
+    }
+
+    // Filter by Category
+    if !filters.categories.is_empty() {
+        query.push_str(&category_filter(filters.categories.len()));
+    }
+
+    // Filter by Exam Scope: only problems from the log items the exam covers
+    if filters.exam.is_some() {
+        query.push_str(" AND p.log_item_id IN (SELECT log_item_id FROM exam_log_items WHERE exam_id = ?)");
+    }
+
+    // Filter by Difficulty
+    let bounds = difficulty::bounds(&filters.difficulty);
+    if bounds.is_some() {
+        query.push_str(" AND p.difficulty BETWEEN ? AND ?");
+    } else if filters.difficulty == "unrated" {
+        query.push_str(" AND p.difficulty IS NULL");
+    }
+
+    // Search: "P-042" finds that problem, anything else is looked for in its text
+    let search = filters.search.trim();
+    let number = parse_problem_label(search);
+    if number.is_some() {
+        query.push_str(" AND p.number = ?");
+    } else if !search.is_empty() {
+        query.push_str(" AND instr(lower(COALESCE(p.notes, '') || ' ' || COALESCE(p.body, '') || ' ' || COALESCE(p.answer, '')), lower(?)) > 0");
+    }
+
+    // Unrated problems go last either way
+    query.push_str(match filters.sort.as_str() {
+        "newest" => " ORDER BY p.id DESC",
+        "source" => " ORDER BY src.source_kind, src.source_title, p.id",
+        "hardest" => " ORDER BY p.difficulty IS NULL, p.difficulty DESC, p.id",
+        "easiest" => " ORDER BY p.difficulty IS NULL, p.difficulty, p.id",
+        _ => " ORDER BY p.id",
+    });
+    query.push_str(" LIMIT ? OFFSET ?");
+
+    let mut q = sqlx::query_scalar::<_, i64>(&query).bind(id);
+    for s in &log_sources {
+        q = q.bind(*s);
+    }
+    for c in &filters.categories {
+        q = q.bind(c);
+    }
+    if let Some(exam) = filters.exam {
+        q = q.bind(exam);
+    }
+    if let Some((low, high)) = bounds {
+        q = q.bind(low).bind(high);
+    }
+    if let Some(number) = number {
+        q = q.bind(number);
+    } else if !search.is_empty() {
+        q = q.bind(search);
+    }
+    q.bind(limit)
+        .bind(offset)
+        .fetch_all(&mut ***db)
+        .await
+        .unwrap_or_default()
+}
+
+#[get("/courses/<id>/study/problems?<source>&<category>&<sort>&<difficulty>&<previous>&<exam>&<q>&<offset>")]
+#[allow(clippy::too_many_arguments)]
+async fn filter_study_problems(
+    mut db: Connection<Db>,
+    user: AuthUser,
+    id: i64,
+    source: Option<Vec<String>>,
+    category: Option<Vec<i64>>,
+    sort: Option<String>,
+    difficulty: Option<String>,
+    previous: Option<String>,
+    exam: Option<i64>,
+    q: Option<String>,
+    offset: Option<i64>,
+) -> StudyProblemListTemplate {
+    let mut filters = sanitize_study_filters(source.unwrap_or_default(), category.unwrap_or_default(), sort, difficulty, previous.is_some(), exam);
+    filters.search = q.unwrap_or_default();
+    let offset = offset.unwrap_or(0).max(0);
+
+    // One row past the batch says whether another batch follows
+    let mut problems = fetch_study_problems(&mut db, id, &filters, STUDY_BATCH_SIZE + 1, offset).await;
+    let next_offset = if problems.len() as i64 > STUDY_BATCH_SIZE {
+        problems.truncate(STUDY_BATCH_SIZE as usize);
+        Some(offset + STUDY_BATCH_SIZE)
+    } else {
+        None
+    };
+
+    // Later batches reuse the filters of the first one, which already saved them
+    if offset > 0 {
+        return StudyProblemListTemplate { problems, course_id: id, course_has_problems: true, offset, next_offset };
+    }
+
+    if let Ok(value) = serde_json::to_string(&filters) {
+        set_preference(&mut db, user.id, &study_filters_key(id), &value).await;
+    }
+
+    let course_has_problems = !problems.is_empty()
+        || sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM problems p LEFT JOIN log_items l ON p.log_item_id = l.id LEFT JOIN exams e ON p.exam_id = e.id WHERE l.course_id = ? OR e.course_id = ?",
+        )
+        .bind(id)
+        .bind(id)
+        .fetch_one(&mut **db)
+        .await
+        .unwrap_or(0)
+            > 0;
+
+    StudyProblemListTemplate { problems, course_id: id, course_has_problems, offset, next_offset }
+}
+
+// Screenshots the study page warms the browser cache with, ahead of the batch being shown
+const STUDY_PREFETCH_SIZE: i64 = 6;
+
+// The image URLs of the problems from `offset` on, as the study list shows them,
+// so the page can fetch the next few before they scroll into view. Attachments
+// that aren't images are skipped. The filters aren't saved here.
+#[get("/courses/<id>/study/manifest?<source>&<category>&<sort>&<difficulty>&<previous>&<exam>&<q>&<offset>&<limit>")]
+#[allow(clippy::too_many_arguments)]
+async fn study_manifest(
+    mut db: Connection<Db>,
+    _user: AuthUser,
+    id: i64,
+    source: Option<Vec<String>>,
+    category: Option<Vec<i64>>,
+    sort: Option<String>,
+    difficulty: Option<String>,
+    previous: Option<String>,
+    exam: Option<i64>,
+    q: Option<String>,
+    offset: Option<i64>,
+    limit: Option<i64>,
+) -> Json<Vec<String>> {
+    let mut filters = sanitize_study_filters(source.unwrap_or_default(), category.unwrap_or_default(), sort, difficulty, previous.is_some(), exam);
+    filters.search = q.unwrap_or_default();
+    let offset = offset.unwrap_or(0).max(0);
+    let limit = limit.unwrap_or(STUDY_PREFETCH_SIZE).clamp(0, STUDY_BATCH_SIZE);
+
+    let problems = fetch_study_problems(&mut db, id, &filters, limit, offset).await;
+    let urls = problems
+        .into_iter()
+        .filter(|p| p.is_image())
+        .filter_map(|p| p.image_url.map(|url| format!("{}?h=768", url)))
+        .collect();
+    Json(urls)
+}
+
+// Full-screen keyboard review; the page itself is static and talks to /api/v1/study
+#[get("/courses/<id>/study/review")]
+async fn view_study_review(mut db: Connection<Db>, user: AuthUser, brand: Branding, id: i64) -> Result<StudyReviewTemplate, Status> {
+    let course = queries::fetch_course(&mut db, id).await.ok_or(Status::NotFound)?;
+
+    Ok(StudyReviewTemplate { course: Some(course), topic: None, user: Some(user), brand })
+}
+
+#[post("/courses/<id>/study/presets", data = "<form>")]
+async fn create_study_preset(mut db: Connection<Db>, user: AuthUser, id: i64, form: Form<NewStudyPreset>) -> StudyPresetChipTemplate {
+    let form = form.into_inner();
+    let filters = sanitize_study_filters(form.source, form.category, form.sort, form.difficulty, form.previous.is_some(), form.exam);
+    let filters_json = serde_json::to_string(&filters).unwrap();
+    let name = form.name.trim().to_string();
+
+    let preset_id = sqlx::query("INSERT INTO study_presets (user_id, course_id, name, filters) VALUES (?, ?, ?, ?)")
+        .bind(user.id)
+        .bind(id)
+        .bind(&name)
+        .bind(&filters_json)
+        .execute(&mut **db)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+
+    let preset = StudyPreset {
+        id: preset_id,
+        user_id: user.id,
+        course_id: id,
+        name,
+        filters: filters_json,
+    };
+    StudyPresetChipTemplate { preset }
+}
+
+#[get("/study/presets/<id>")]
+async fn apply_study_preset(mut db: Connection<Db>, user: AuthUser, id: i64) -> Result<Redirect, Status> {
+    let preset = sqlx::query_as::<_, StudyPreset>("SELECT * FROM study_presets WHERE id = ? AND user_id = ?")
+        .bind(id)
+        .bind(user.id)
+        .fetch_optional(&mut **db)
+        .await
+        .unwrap_or(None)
+        .ok_or(Status::NotFound)?;
+
+    // Applying a preset makes it the working set; the study page restores it on load
+    set_preference(&mut db, user.id, &study_filters_key(preset.course_id), &preset.filters).await;
+
+    Ok(Redirect::to(format!("/courses/{}/study", preset.course_id)))
+}
+
+// A focus suggestion's link: study just that category, whatever the filters were
+#[get("/courses/<id>/study/focus/<category_id>")]
+async fn study_focus(mut db: Connection<Db>, user: AuthUser, id: i64, category_id: i64) -> Redirect {
+    let filters = sanitize_study_filters(vec![], vec![category_id], None, None, false, None);
+    if let Ok(value) = serde_json::to_string(&filters) {
+        set_preference(&mut db, user.id, &study_filters_key(id), &value).await;
+    }
+    Redirect::to(format!("/courses/{}/study", id))
+}
+
+#[delete("/study/presets/<id>")]
+async fn delete_study_preset(mut db: Connection<Db>, user: AuthUser, id: i64) -> String {
+    sqlx::query("DELETE FROM study_presets WHERE id = ? AND user_id = ?")
+        .bind(id)
+        .bind(user.id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    String::new()
+}
+
+#[post("/courses/<id>/study/goals", data = "<form>")]
+async fn create_study_goal(mut db: Connection<Db>, user: AuthUser, id: i64, form: Form<NewStudyGoal>) -> Result<StudyGoalTemplate, Status> {
+    let category: Option<i64> = sqlx::query_scalar("SELECT id FROM categories WHERE id = ? AND course_id = ?")
+        .bind(form.category_id)
+        .bind(id)
+        .fetch_optional(&mut **db)
+        .await
+        .unwrap_or(None);
+    let deadline = form.deadline.as_deref().map(str::trim).filter(|d| !d.is_empty());
+    if category.is_none() || form.target < 1 || deadline.is_some_and(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").is_err()) {
+        return Err(Status::BadRequest);
+    }
+
+    let goal_id = sqlx::query("INSERT INTO study_goals (user_id, course_id, category_id, target, deadline, created_at) VALUES (?, ?, ?, ?, ?, CURRENT_TIMESTAMP)")
+        .bind(user.id)
+        .bind(id)
+        .bind(form.category_id)
+        .bind(form.target)
+        .bind(deadline)
+        .execute(&mut **db)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+
+    let goal = sqlx::query_as::<_, StudyGoal>(&format!("{} AND g.id = ?", STUDY_GOALS_QUERY))
+        .bind(user.id)
+        .bind(goal_id)
+        .fetch_one(&mut **db)
+        .await
+        .unwrap();
+    Ok(StudyGoalTemplate { goal })
+}
+
+#[delete("/study/goals/<id>")]
+async fn delete_study_goal(mut db: Connection<Db>, user: AuthUser, id: i64) -> String {
+    sqlx::query("DELETE FROM study_goals WHERE id = ? AND user_id = ?")
+        .bind(id)
+        .bind(user.id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    String::new()
+}
+
+#[get("/problems/<id>/edit")]
+async fn get_edit_problem(mut db: Connection<Db>, _user: AuthUser, id: i64) -> ProblemEditTemplate {
+    let problem = queries::fetch_problem_with_categories(&mut db, id).await.unwrap();
+
+    ProblemEditTemplate { problem }
+}
+
+#[get("/problems/<id>")]
+async fn get_problem_row(mut db: Connection<Db>, _user: AuthUser, id: i64) -> ProblemRowTemplate {
+    let problem = queries::fetch_problem_with_categories(&mut db, id).await.unwrap();
+
+    ProblemRowTemplate { problem }
+}
+
+// Full page for one problem: the screenshot at full size next to everything written about it
+#[get("/problems/<id>/view")]
+async fn view_problem(mut db: Connection<Db>, user: AuthUser, brand: Branding, id: i64) -> Option<ProblemDetailTemplate> {
+    let problem = queries::fetch_problem_with_categories(&mut db, id).await?;
+    let course_id = problem_course_id(&mut db, &problem).await?;
+
+    let CourseContext { course, semester, courses } = queries::fetch_course_context(&mut db, course_id).await?;
+
+    let history = sqlx::query_as::<_, EntityHistory>("SELECT * FROM entity_history WHERE entity_type = 'problem' AND entity_id = ? ORDER BY id DESC LIMIT 10")
+        .bind(id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(history_entry_view)
+        .collect();
+    let assignees = assignable_users(&mut db, course_id, user.id).await;
+    let exams = sqlx::query_as::<_, Exam>("SELECT * FROM exams WHERE course_id = ? ORDER BY exam_date IS NULL, exam_date DESC, id DESC")
+        .bind(course_id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+    let related = related_problems(&mut db, user.id, id).await;
+
+    Some(ProblemDetailTemplate { course, courses, semester, problem, history, assignees, exams, related, user: Some(user), brand })
+}
+
+#[post("/problems/<id>", data = "<form>")]
+async fn update_problem(mut db: Connection<Db>, user: AuthUser, category_cache: &State<CategoryCache>, id: i64, form: Form<UpdateProblem>) -> ProblemRowTemplate {
+    let before = fetch_problem_snapshot(&mut db, id).await;
+
+    sqlx::query("UPDATE problems SET body = ?, notes = ?, solution_link = ?, answer = ? WHERE id = ?")
+        .bind(answer_text(form.body.clone()))
+        .bind(&form.notes)
+        .bind(&form.solution_link)
+        .bind(answer_text(form.answer.clone()))
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    // Get the course_id via log_item or exam
+    let problem_info = sqlx::query_as::<_, Problem>("SELECT * FROM problems WHERE id = ?")
+        .bind(id)
+        .fetch_one(&mut **db)
+        .await
+        .unwrap();
+
+    let course_id: i64 = if let Some(log_item_id) = problem_info.log_item_id {
+        let log_item = queries::fetch_log_item(&mut db, log_item_id).await.unwrap();
+        log_item.course_id
+    } else if let Some(exam_id) = problem_info.exam_id {
+        let exam = queries::fetch_exam(&mut db, exam_id).await.unwrap();
+        exam.course_id
+    } else {
+        panic!("Problem has neither log_item_id nor exam_id");
+    };
+
+    // Clear existing categories for this problem
+    sqlx::query("DELETE FROM problem_categories WHERE problem_id = ?")
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    // Add new categories
+    if let Some(cats) = &form.categories {
+        link_problem_categories(&mut db, category_cache, id, course_id, cats).await;
+    }
+
+    let after = fetch_problem_snapshot(&mut db, id).await.unwrap();
+
+    record_history(&mut db, user.id, Some(course_id), "update", before.as_ref(), Some(&after)).await;
+
+    ProblemRowTemplate { problem: after.problem }
+}
+
+#[delete("/problems/<id>")]
+async fn delete_problem(mut db: Connection<Db>, user: AuthUser, id: i64) -> String {
+    let before = fetch_problem_snapshot(&mut db, id).await;
+    if let Some(before) = &before {
+        let course_id = problem_course_id(&mut db, &before.problem).await;
+        record_history(&mut db, user.id, course_id, "delete", Some(before), None).await;
+    }
+
+    sqlx::query("DELETE FROM problems WHERE id = ?")
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+    release_problem_images(&mut db, before.into_iter().map(|p| p.problem.image_url).collect()).await;
+
+    String::new()
+}
+
+// ========== Cross-Course Topics ==========
+//
+// Courses name the same material differently ("DP" in one, "动态规划" in
+// another). A user's aliases map category names to a topic; categories whose
+// names resolve to the same topic are listed together on /study/topics and
+// reviewed as one queue on /study/review?topic=, which filters through
+// /api/v1/study/session the same way.
+
+#[derive(FromForm)]
+struct NewCategoryAlias {
+    alias: String,
+    topic: String,
+}
+
+// Every category with its resolved topic, grouped into topics by name
+async fn study_topics(db: &mut Connection<Db>, user_id: i64) -> Vec<StudyTopic> {
+    let members = sqlx::query_as::<_, TopicMember>(&format!(
+        r#"
+        SELECT
+            COALESCE(a.topic, c.name) as topic,
+            co.id as course_id, co.code as course_code, co.color as course_color,
+            c.id as category_id, c.name as category_name,
+            COUNT(pc.problem_id) as problems
+        FROM categories c
+        JOIN courses co ON c.course_id = co.id
+        LEFT JOIN category_aliases a ON a.user_id = ? AND a.alias = c.name
+        LEFT JOIN problem_categories pc ON pc.category_id = c.id
+        WHERE co.id IN ({})
+        GROUP BY c.id
+        ORDER BY COALESCE(a.topic, c.name) COLLATE NOCASE, co.code, c.name
+        "#,
+        access::COURSES
+    ))
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_all(&mut ***db)
+    .await
+    .unwrap_or_default();
+
+    let mut topics: Vec<StudyTopic> = Vec::new();
+    for member in members {
+        match topics.last_mut() {
+            Some(topic) if topic.name.to_lowercase() == member.topic.to_lowercase() => topic.members.push(member),
+            _ => topics.push(StudyTopic { name: member.topic.clone(), members: vec![member] }),
+        }
+    }
+    topics
+}
+
+#[get("/study/topics")]
+async fn view_study_topics(mut db: Connection<Db>, user: AuthUser, brand: Branding, flash: Option<FlashMessage<'_>>) -> StudyTopicsTemplate {
+    let topics = study_topics(&mut db, user.id).await;
+    let aliases = sqlx::query_as::<_, CategoryAlias>("SELECT id, alias, topic FROM category_aliases WHERE user_id = ? ORDER BY topic, alias")
+        .bind(user.id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+    let notice = flash.map(|f| (f.kind().to_string(), f.message().to_string()));
+
+    StudyTopicsTemplate { topics, aliases, notice, user: Some(user), brand }
+}
+
+#[get("/study/review?<topic>")]
+async fn view_topic_review(user: AuthUser, brand: Branding, topic: Option<&str>) -> StudyReviewTemplate {
+    let topic = topic.map(str::trim).filter(|t| !t.is_empty()).map(str::to_string);
+    StudyReviewTemplate { course: None, topic, user: Some(user), brand }
+}
+
+#[post("/study/aliases", data = "<form>")]
+async fn create_category_alias(mut db: Connection<Db>, user: AuthUser, form: Form<NewCategoryAlias>) -> Flash<Redirect> {
+    let alias = form.alias.trim();
+    let topic = form.topic.trim();
+    if alias.is_empty() || topic.is_empty() {
+        return Flash::error(Redirect::to("/study/topics"), "分类名和专题名都不能为空。");
+    }
+    if alias.to_lowercase() == topic.to_lowercase() {
+        return Flash::error(Redirect::to("/study/topics"), "分类名和专题名相同，不需要别名。");
+    }
+
+    // Re-pointing an alias replaces it rather than failing on the unique key
+    sqlx::query(
+        r#"
+        INSERT INTO category_aliases (user_id, alias, topic) VALUES (?, ?, ?)
+        ON CONFLICT (user_id, alias) DO UPDATE SET topic = excluded.topic
+        "#,
+    )
+    .bind(user.id)
+    .bind(alias)
+    .bind(topic)
+    .execute(&mut **db)
+    .await
+    .unwrap();
+
+    Flash::success(Redirect::to("/study/topics"), format!("已将「{}」归入「{}」。", alias, topic))
+}
+
+#[delete("/study/aliases/<id>")]
+async fn delete_category_alias(mut db: Connection<Db>, user: AuthUser, id: i64) -> String {
+    sqlx::query("DELETE FROM category_aliases WHERE id = ? AND user_id = ?")
+        .bind(id)
+        .bind(user.id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    String::new()
+}
+
+// ========== History Routes ==========
+//
+// Every update/delete of a log item or problem stores before/after JSON
+// snapshots in entity_history. Reverting writes the "before" snapshot back
+// (re-inserting with the same id if the row was deleted) and is itself
+// recorded, so a revert can be undone the same way.
+
+trait HistoryEntity: Serialize {
+    const ENTITY_TYPE: &'static str;
+    fn entity_id(&self) -> i64;
+}
+
+impl HistoryEntity for LogItem {
+    const ENTITY_TYPE: &'static str = "log_item";
+    fn entity_id(&self) -> i64 {
+        self.id
+    }
+}
+
+impl HistoryEntity for ProblemSnapshot {
+    const ENTITY_TYPE: &'static str = "problem";
+    fn entity_id(&self) -> i64 {
+        self.problem.id
+    }
+}
+
+async fn fetch_problem_snapshot(db: &mut Connection<Db>, id: i64) -> Option<ProblemSnapshot> {
+    let problem = queries::fetch_problem_with_categories(db, id).await?;
+    let (is_incorrect, created_at) = sqlx::query_as::<_, (bool, Option<String>)>("SELECT is_incorrect, created_at FROM problems WHERE id = ?")
+        .bind(id)
+        .fetch_one(&mut ***db)
+        .await
+        .ok()?;
+    let category_ids = sqlx::query_scalar("SELECT category_id FROM problem_categories WHERE problem_id = ? ORDER BY category_id")
+        .bind(id)
+        .fetch_all(&mut ***db)
+        .await
+        .unwrap_or_default();
+    Some(ProblemSnapshot { problem, is_incorrect: Some(is_incorrect), created_at, category_ids: Some(category_ids) })
+}
+
+async fn record_history<T: HistoryEntity>(
+    db: &mut Connection<Db>,
+    user_id: i64,
+    course_id: Option<i64>,
+    action: &str,
+    before: Option<&T>,
+    after: Option<&T>,
+) {
+    let Some(entity_id) = before.or(after).map(|e| e.entity_id()) else {
+        return;
+    };
+    let before_json = before.map(|e| serde_json::to_string(e).unwrap());
+    let after_json = after.map(|e| serde_json::to_string(e).unwrap());
+
+    let _ = sqlx::query("INSERT INTO entity_history (entity_type, entity_id, course_id, action, before_json, after_json, user_id) VALUES (?, ?, ?, ?, ?, ?, ?)")
+        .bind(T::ENTITY_TYPE)
+        .bind(entity_id)
+        .bind(course_id)
+        .bind(action)
+        .bind(before_json)
+        .bind(after_json)
+        .bind(user_id)
+        .execute(&mut ***db)
+        .await;
+}
+
+async fn problem_course_id(db: &mut Connection<Db>, problem: &ProblemWithCategories) -> Option<i64> {
+    sqlx::query_scalar("SELECT COALESCE((SELECT course_id FROM log_items WHERE id = ?), (SELECT course_id FROM exams WHERE id = ?))")
+        .bind(problem.log_item_id)
+        .bind(problem.exam_id)
+        .fetch_one(&mut ***db)
+        .await
+        .unwrap_or(None)
+}
+
+// Snapshot problems before a cascading delete of their log item or exam
+async fn record_problem_deletes(db: &mut Connection<Db>, user_id: i64, course_id: i64, problem_ids: &[i64]) {
+    for &problem_id in problem_ids {
+        if let Some(before) = fetch_problem_snapshot(db, problem_id).await {
+            record_history(db, user_id, Some(course_id), "delete", Some(&before), None).await;
+        }
+    }
+}
+
+struct HistoryChange {
+    label: String,
+    before: String,
+    after: String,
+}
+
+struct HistoryEntryView {
+    id: i64,
+    entity_type: String,
+    entity_id: i64,
+    action: String,
+    created_at: String,
+    summary: String,
+    changes: Vec<HistoryChange>,
+    can_revert: bool,
+}
+
+// Fields shown in the history diff, in display order; ids and joined columns are omitted
+const HISTORY_FIELDS: &[(&str, &str)] = &[
+    ("kind", "类型"),
+    ("title", "标题"),
+    ("date", "日期"),
+    ("description", "描述"),
+    ("link", "链接"),
+    ("image_url", "截图"),
+    ("notes", "笔记"),
+    ("solution_link", "解答链接"),
+    ("answer", "答案"),
+    ("category_names", "分类"),
+];
+
+fn history_value(value: Option<&serde_json::Value>) -> String {
+    match value {
+        None | Some(serde_json::Value::Null) => "—".to_string(),
+        Some(serde_json::Value::String(s)) if s.is_empty() => "—".to_string(),
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn history_entry_view(entry: EntityHistory) -> HistoryEntryView {
+    let parse = |json: &Option<String>| {
+        json.as_deref()
+            .and_then(|j| serde_json::from_str::<serde_json::Value>(j).ok())
+            .unwrap_or(serde_json::Value::Null)
+    };
+    let before = parse(&entry.before_json);
+    let after = parse(&entry.after_json);
+    let snapshot = if before.is_null() { &after } else { &before };
+
+    let summary = match entry.entity_type.as_str() {
+        "log_item" => format!("记录「{}」", history_value(snapshot.get("title"))),
+        _ => format!("错题 #{} · {}", entry.entity_id, history_value(snapshot.get("source_title"))),
+    };
+
+    let mut changes = Vec::new();
+    for (key, label) in HISTORY_FIELDS {
+        if snapshot.get(key).is_none() {
+            continue;
+        }
+        let (b, a) = (before.get(key), after.get(key));
+        // Only list fields that changed; for deletes/restores list everything that was set
+        let show = if before.is_null() || after.is_null() {
+            history_value(b.or(a)) != "—"
+        } else {
+            b != a
+        };
+        if show {
+            changes.push(HistoryChange {
+                label: label.to_string(),
+                before: history_value(b),
+                after: history_value(a),
+            });
+        }
+    }
+
+    HistoryEntryView {
+        id: entry.id,
+        entity_type: entry.entity_type,
+        entity_id: entry.entity_id,
+        action: entry.action,
+        created_at: entry.created_at,
+        summary,
+        changes,
+        can_revert: entry.before_json.is_some(),
+    }
+}
+
+async fn render_history(db: &mut Connection<Db>, user: AuthUser, brand: Branding, course_id: i64, heading: String, entries: Vec<EntityHistory>) -> Option<HistoryTemplate> {
+    let CourseContext { course, semester, courses } = queries::fetch_course_context(db, course_id).await?;
+
+    let entries = entries.into_iter().map(history_entry_view).collect();
+    Some(HistoryTemplate { course, courses, semester, heading, entries, user: Some(user), brand })
+}
+
+#[get("/courses/<id>/history")]
+async fn view_course_history(mut db: Connection<Db>, user: AuthUser, brand: Branding, id: i64) -> Option<HistoryTemplate> {
+    let entries = sqlx::query_as::<_, EntityHistory>("SELECT * FROM entity_history WHERE course_id = ? ORDER BY id DESC LIMIT 200")
+        .bind(id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+
+    render_history(&mut db, user, brand, id, "编辑历史".to_string(), entries).await
+}
+
+#[get("/history/<entity_type>/<entity_id>")]
+async fn view_entity_history(mut db: Connection<Db>, user: AuthUser, brand: Branding, entity_type: &str, entity_id: i64) -> Option<HistoryTemplate> {
+    let entries = sqlx::query_as::<_, EntityHistory>("SELECT * FROM entity_history WHERE entity_type = ? AND entity_id = ? ORDER BY id DESC")
+        .bind(entity_type)
+        .bind(entity_id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+
+    let course_id = entries.first()?.course_id?;
+    if !access::can_open_course(&mut db, user.id, course_id).await {
+        return None;
+    }
+    let heading = match entity_type {
+        "log_item" => "记录历史".to_string(),
+        _ => format!("错题 #{} 历史", entity_id),
+    };
+    render_history(&mut db, user, brand, course_id, heading, entries).await
+}
+
+// A deleted row comes back under its old public id so public links keep working.
+// Snapshots from before public ids have none and get a fresh one from the trigger.
+fn restored_public_id(public_id: &str) -> Option<&str> {
+    Some(public_id).filter(|p| !p.is_empty())
+}
+
+#[post("/history/<id>/revert")]
+async fn revert_history(mut db: Connection<Db>, user: AuthUser, category_cache: &State<CategoryCache>, id: i64) -> Result<Redirect, Status> {
+    let entry = sqlx::query_as::<_, EntityHistory>("SELECT * FROM entity_history WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut **db)
+        .await
+        .unwrap_or(None)
+        .ok_or(Status::NotFound)?;
+    let before_json = entry.before_json.as_deref().ok_or(Status::BadRequest)?;
+
+    match entry.entity_type.as_str() {
+        "log_item" => {
+            let target: LogItem = serde_json::from_str(before_json).map_err(|_| Status::InternalServerError)?;
+            let current = queries::fetch_log_item(&mut db, target.id).await;
+
+            let sql = if current.is_some() {
+                "UPDATE log_items SET course_id = ?, kind = ?, title = ?, description = ?, link = ?, date = ? WHERE id = ?"
+            } else {
+                "INSERT INTO log_items (course_id, kind, title, description, link, date, id, public_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+            };
+            let mut query = sqlx::query(sql)
+                .bind(target.course_id)
+                .bind(&target.kind)
+                .bind(&target.title)
+                .bind(&target.description)
+                .bind(&target.link)
+                .bind(&target.date)
+                .bind(target.id);
+            if current.is_none() {
+                query = query.bind(restored_public_id(&target.public_id));
+            }
+            query.execute(&mut **db).await.unwrap();
+
+            record_history(&mut db, user.id, Some(target.course_id), "revert", current.as_ref(), Some(&target)).await;
+        }
+        "problem" => {
+            let target: ProblemSnapshot = serde_json::from_str(before_json).map_err(|_| Status::InternalServerError)?;
+            let course_id = entry.course_id.ok_or(Status::InternalServerError)?;
+            let current = fetch_problem_snapshot(&mut db, target.problem.id).await;
+            // The log item or exam it belonged to must exist again first
+            if problem_course_id(&mut db, &target.problem).await.is_none() {
+                return Err(Status::Conflict);
+            }
+
+            let p = &target.problem;
+            let sql = if current.is_some() {
+                "UPDATE problems SET log_item_id = ?, exam_id = ?, description = ?, notes = ?, image_url = ?, body = ?, solution_link = ?, answer = ?, original_filename = ?, mime_type = ?, imported_from = ?, number = ?, assigned_to = (SELECT id FROM users WHERE id = ?), difficulty = ?, is_incorrect = COALESCE(?, is_incorrect), created_at = COALESCE(?, created_at) WHERE id = ?"
+            } else {
+                "INSERT INTO problems (log_item_id, exam_id, description, notes, image_url, body, solution_link, answer, original_filename, mime_type, imported_from, number, assigned_to, difficulty, is_incorrect, created_at, id, public_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, (SELECT id FROM users WHERE id = ?), ?, COALESCE(?, 1), COALESCE(?, CURRENT_TIMESTAMP), ?, ?)"
+            };
+            let mut query = sqlx::query(sql)
+                .bind(p.log_item_id)
+                .bind(p.exam_id)
+                .bind(&p.description)
+                .bind(&p.notes)
+                .bind(&p.image_url)
+                .bind(&p.body)
+                .bind(&p.solution_link)
+                .bind(&p.answer)
+                .bind(&p.original_filename)
+                .bind(&p.mime_type)
+                .bind(&p.imported_from)
+                .bind(p.number)
+                .bind(p.assigned_to)
+                .bind(p.difficulty)
+                .bind(target.is_incorrect)
+                .bind(&target.created_at)
+                .bind(p.id);
+            if current.is_none() {
+                query = query.bind(restored_public_id(&p.public_id));
+            }
+            query.execute(&mut **db).await.unwrap();
+
+            let current_image = current.as_ref().and_then(|c| c.problem.image_url.clone());
+            if p.image_url != current_image {
+                if let Some(url) = &p.image_url {
+                    uploads::retain(&mut db, url).await;
+                }
+                if let Some(url) = &current_image {
+                    uploads::release(&mut db, url).await;
+                }
+            }
+
+            sqlx::query("DELETE FROM problem_categories WHERE problem_id = ?")
+                .bind(p.id)
+                .execute(&mut **db)
+                .await
+                .unwrap();
+            match &target.category_ids {
+                // Categories deleted since are left out
+                Some(category_ids) => {
+                    for category_id in category_ids {
+                        sqlx::query("INSERT INTO problem_categories (problem_id, category_id) SELECT ?, id FROM categories WHERE id = ? AND course_id = ?")
+                            .bind(p.id)
+                            .bind(category_id)
+                            .bind(course_id)
+                            .execute(&mut **db)
+                            .await
+                            .unwrap();
+                    }
+                }
+                None => {
+                    if let Some(cats) = &p.category_names {
+                        link_problem_categories(&mut db, category_cache, p.id, course_id, cats).await;
+                    }
+                }
+            }
+
+            let restored = fetch_problem_snapshot(&mut db, p.id).await;
+            record_history(&mut db, user.id, Some(course_id), "revert", current.as_ref(), restored.as_ref()).await;
+        }
+        _ => return Err(Status::BadRequest),
+    }
+
+    Ok(Redirect::to(format!("/history/{}/{}", entry.entity_type, entry.entity_id)))
+}
+
+// ========== Exam Routes ==========
+
+// (exam_id, category_id) pairs from exam_categories for one exam or a whole course
+async fn exam_coverage(db: &mut Connection<Db>, exam_id: Option<i64>, course_id: Option<i64>) -> Vec<(i64, i64)> {
+    sqlx::query_as(
+        r#"
+        SELECT ec.exam_id, ec.category_id
+        FROM exam_categories ec
+        JOIN exams e ON ec.exam_id = e.id
+        WHERE (? IS NULL OR ec.exam_id = ?) AND (? IS NULL OR e.course_id = ?)
+        ORDER BY ec.exam_id, ec.category_id
+        "#,
+    )
+    .bind(exam_id)
+    .bind(exam_id)
+    .bind(course_id)
+    .bind(course_id)
+    .fetch_all(&mut ***db)
+    .await
+    .unwrap_or_default()
+}
+
+// (exam_id, log_item_id) pairs from exam_log_items, like exam_coverage
+async fn exam_scope(db: &mut Connection<Db>, exam_id: Option<i64>, course_id: Option<i64>) -> Vec<(i64, i64)> {
+    sqlx::query_as(
+        r#"
+        SELECT s.exam_id, s.log_item_id
+        FROM exam_log_items s
+        JOIN exams e ON s.exam_id = e.id
+        WHERE (? IS NULL OR s.exam_id = ?) AND (? IS NULL OR e.course_id = ?)
+        ORDER BY s.exam_id, s.log_item_id
+        "#,
+    )
+    .bind(exam_id)
+    .bind(exam_id)
+    .bind(course_id)
+    .bind(course_id)
+    .fetch_all(&mut ***db)
+    .await
+    .unwrap_or_default()
+}
+
+// The log items an exam's scope is picked from, in course order
+async fn exam_scope_log_items(db: &mut Connection<Db>, course_id: i64) -> Vec<LogItem> {
+    sqlx::query_as::<_, LogItem>("SELECT * FROM log_items WHERE course_id = ? ORDER BY date IS NULL, date, id")
+        .bind(course_id)
+        .fetch_all(&mut ***db)
+        .await
+        .unwrap_or_default()
+}
+
+// Blank or malformed dates from the <input type="date"> are stored as NULL
+fn exam_date(raw: &Option<String>) -> Option<String> {
+    raw.as_deref()
+        .map(str::trim)
+        .filter(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").is_ok())
+        .map(String::from)
+}
+
+#[get("/courses/<id>/exams")]
+async fn view_course_exams(mut db: Connection<Db>, user: AuthUser, brand: Branding, category_cache: &State<CategoryCache>, id: i64) -> CourseExamsTemplate {
+    let CourseContext { course, semester, courses } = queries::fetch_course_context(&mut db, id).await.unwrap();
+
+    let exams = sqlx::query_as::<_, Exam>("SELECT * FROM exams WHERE course_id = ? ORDER BY id DESC")
+        .bind(id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+
+    let categories = category_cache.get(&mut db, id).await;
+
+    let coverage = exam_coverage(&mut db, None, Some(id)).await;
+
+    let log_items = exam_scope_log_items(&mut db, id).await;
+    let scope = exam_scope(&mut db, None, Some(id)).await;
+
+    let presets = sqlx::query_as::<_, StudyPreset>("SELECT * FROM study_presets WHERE user_id = ? AND course_id = ? ORDER BY name")
+        .bind(user.id)
+        .bind(id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+
+    CourseExamsTemplate { course, courses, exams, semester, categories, coverage, log_items, scope, presets, user: Some(user), brand }
+}
+
+// A practice exam made from a study preset gets the first problems the preset
+// finds, in its order, leaving out any that are already on another exam
+const PRESET_EXAM_PROBLEMS: i64 = 20;
+
+async fn add_preset_problems(db: &mut Connection<Db>, user_id: i64, course_id: i64, exam_id: i64, preset_id: i64) {
+    let filters = sqlx::query_scalar::<_, String>("SELECT filters FROM study_presets WHERE id = ? AND user_id = ? AND course_id = ?")
+        .bind(preset_id)
+        .bind(user_id)
+        .bind(course_id)
+        .fetch_optional(&mut ***db)
+        .await
+        .unwrap_or(None)
+        .and_then(|v| serde_json::from_str::<StudyFilters>(&v).ok());
+    let Some(mut filters) = filters else { return };
+    // An exam only holds problems from its own course
+    filters.include_previous = false;
+
+    let ids = study_problem_ids(db, course_id, &filters, -1, 0).await;
+    sqlx::query(
+        r#"
+        UPDATE problems SET exam_id = ? WHERE id IN (
+            SELECT p.id FROM json_each(?) ids JOIN problems p ON p.id = ids.value
+            WHERE p.exam_id IS NULL AND p.log_item_id IS NOT NULL
+            ORDER BY ids.key LIMIT ?
+        )
+        "#,
+    )
+    .bind(exam_id)
+    .bind(serde_json::to_string(&ids).unwrap())
+    .bind(PRESET_EXAM_PROBLEMS)
+    .execute(&mut ***db)
+    .await
+    .unwrap();
+}
+
+#[post("/courses/<id>/exams", data = "<form>")]
+async fn create_exam(mut db: Connection<Db>, user: AuthUser, category_cache: &State<CategoryCache>, id: i64, form: Form<NewExam>) -> ExamItemTemplate {
+    let date = exam_date(&form.exam_date);
+    let exam_id = sqlx::query("INSERT INTO exams (course_id, title, semester, link, exam_date) VALUES (?, ?, ?, ?, ?)")
+        .bind(id)
+        .bind(&form.title)
+        .bind(&form.semester)
+        .bind(&form.link)
+        .bind(&date)
+        .execute(&mut **db)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+
+    let exam = Exam {
+        id: exam_id,
+        course_id: id,
+        title: form.title.clone(),
+        semester: form.semester.clone(),
+        link: form.link.clone(),
+        exam_date: date,
+    };
+
+    if let Some(preset_id) = form.preset_id {
+        add_preset_problems(&mut db, user.id, id, exam_id, preset_id).await;
+    }
+
+    let categories = category_cache.get(&mut db, id).await;
+
+    let log_items = exam_scope_log_items(&mut db, id).await;
+
+    ExamItemTemplate { exam, categories, coverage: Vec::new(), log_items, scope: Vec::new() }
+}
+
+#[get("/exams/<id>")]
+async fn get_exam(mut db: Connection<Db>, _user: AuthUser, category_cache: &State<CategoryCache>, id: i64) -> ExamItemTemplate {
+    let exam = queries::fetch_exam(&mut db, id).await.unwrap();
+
+    let categories = category_cache.get(&mut db, exam.course_id).await;
+
+    let coverage = exam_coverage(&mut db, Some(id), None).await;
+
+    let log_items = exam_scope_log_items(&mut db, exam.course_id).await;
+    let scope = exam_scope(&mut db, Some(id), None).await;
+
+    ExamItemTemplate { exam, categories, coverage, log_items, scope }
+}
+
+#[get("/exams/<id>/edit")]
+async fn get_edit_exam(mut db: Connection<Db>, _user: AuthUser, id: i64) -> ExamItemEditTemplate {
+    let exam = queries::fetch_exam(&mut db, id).await.unwrap();
+    ExamItemEditTemplate { exam }
+}
+
+#[post("/exams/<id>", data = "<form>")]
+async fn update_exam(mut db: Connection<Db>, _user: AuthUser, category_cache: &State<CategoryCache>, id: i64, form: Form<UpdateExam>) -> ExamItemTemplate {
+    sqlx::query("UPDATE exams SET title = ?, semester = ?, link = ?, exam_date = ? WHERE id = ?")
+        .bind(&form.title)
+        .bind(&form.semester)
+        .bind(&form.link)
+        .bind(exam_date(&form.exam_date))
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    let exam = queries::fetch_exam(&mut db, id).await.unwrap();
+
+    let categories = category_cache.get(&mut db, exam.course_id).await;
+
+    let coverage = exam_coverage(&mut db, Some(id), None).await;
+
+    let log_items = exam_scope_log_items(&mut db, exam.course_id).await;
+    let scope = exam_scope(&mut db, Some(id), None).await;
+
+    ExamItemTemplate { exam, categories, coverage, log_items, scope }
+}
+
+// Replace the set of categories an exam covers; the checkboxes post on every change
+#[post("/exams/<id>/categories", data = "<form>")]
+async fn update_exam_categories(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Form<ExamCoverage>) -> Status {
+    let Some(course_id) = sqlx::query_scalar::<_, i64>("SELECT course_id FROM exams WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut **db)
+        .await
+        .unwrap_or(None)
+    else {
+        return Status::NotFound;
+    };
+
+    sqlx::query("DELETE FROM exam_categories WHERE exam_id = ?")
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+    for category_id in &form.category {
+        // Only the exam's own course's categories
+        sqlx::query("INSERT OR IGNORE INTO exam_categories (exam_id, category_id) SELECT ?, id FROM categories WHERE id = ? AND course_id = ?")
+            .bind(id)
+            .bind(category_id)
+            .bind(course_id)
+            .execute(&mut **db)
+            .await
+            .unwrap();
+    }
+
+    Status::NoContent
+}
+
+// Replace the log items in an exam's scope, posted like the categories
+#[post("/exams/<id>/log-items", data = "<form>")]
+async fn update_exam_log_items(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Form<ExamScope>) -> Status {
+    let Some(course_id) = sqlx::query_scalar::<_, i64>("SELECT course_id FROM exams WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut **db)
+        .await
+        .unwrap_or(None)
+    else {
+        return Status::NotFound;
+    };
+
+    sqlx::query("DELETE FROM exam_log_items WHERE exam_id = ?")
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+    for log_item_id in &form.log_item {
+        // Only the exam's own course's log items
+        sqlx::query("INSERT OR IGNORE INTO exam_log_items (exam_id, log_item_id) SELECT ?, id FROM log_items WHERE id = ? AND course_id = ?")
+            .bind(id)
+            .bind(log_item_id)
+            .bind(course_id)
+            .execute(&mut **db)
+            .await
+            .unwrap();
+    }
+
+    Status::NoContent
+}
+
+// The categories an exam covers, or, if none are set, those of the problems
+// attached to it
+async fn exam_study_categories(db: &mut Connection<Db>, exam_id: i64) -> Vec<i64> {
+    let categories: Vec<i64> = exam_coverage(db, Some(exam_id), None).await.into_iter().map(|(_, c)| c).collect();
+    if !categories.is_empty() {
+        return categories;
+    }
+    sqlx::query_scalar(
+        "SELECT DISTINCT pc.category_id FROM problem_categories pc JOIN problems p ON pc.problem_id = p.id WHERE p.exam_id = ? ORDER BY pc.category_id",
+    )
+    .bind(exam_id)
+    .fetch_all(&mut ***db)
+    .await
+    .unwrap_or_default()
+}
+
+// Open study mode for an exam's course, filtered to the log items in the
+// exam's scope or, without one, to the categories the exam covers
+#[get("/exams/<id>/study")]
+async fn study_for_exam(mut db: Connection<Db>, user: AuthUser, id: i64) -> Result<Redirect, Status> {
+    let exam = queries::fetch_exam(&mut db, id).await.ok_or(Status::NotFound)?;
+
+    let filters = if exam_scope(&mut db, Some(id), None).await.is_empty() {
+        let categories = exam_study_categories(&mut db, id).await;
+        sanitize_study_filters(Vec::new(), categories, None, None, false, None)
+    } else {
+        sanitize_study_filters(Vec::new(), Vec::new(), None, None, false, Some(id))
+    };
+    set_preference(&mut db, user.id, &study_filters_key(exam.course_id), &serde_json::to_string(&filters).unwrap()).await;
+
+    Ok(Redirect::to(format!("/courses/{}/study", exam.course_id)))
+}
+
+#[delete("/exams/<id>")]
+async fn delete_exam(mut db: Connection<Db>, user: AuthUser, id: i64) -> String {
+    // Problems that were only put on the exam stay with their log items
+    sqlx::query("UPDATE problems SET exam_id = NULL WHERE exam_id = ? AND log_item_id IS NOT NULL")
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    let problem_ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM problems WHERE exam_id = ?")
+        .bind(id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+
+    let course_id: Option<i64> = sqlx::query_scalar("SELECT course_id FROM exams WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut **db)
+        .await
+        .unwrap_or(None);
+    if let Some(course_id) = course_id {
+        record_problem_deletes(&mut db, user.id, course_id, &problem_ids).await;
+    }
+
+    let image_urls: Vec<Option<String>> = sqlx::query_scalar("SELECT image_url FROM problems WHERE exam_id = ?")
+        .bind(id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+
+    // Its problems and category links go with it (ON DELETE CASCADE)
+    sqlx::query("DELETE FROM exams WHERE id = ?")
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+    release_problem_images(&mut db, image_urls).await;
+
+    String::new()
+}
+
+#[post("/exams/<id>/problems", data = "<form>")]
+async fn create_exam_problem(mut db: Connection<Db>, _user: AuthUser, config: &State<AppConfig>, category_cache: &State<CategoryCache>, id: i64, mut form: Form<NewProblem<'_>>) -> Result<Result<ProblemRowTemplate, UploadErrorTemplate>, Status> {
+    let exam = queries::fetch_exam(&mut db, id).await.unwrap();
+    let body = answer_text(form.body.clone());
+
+    // As for log items: a screenshot, or the problem typed in
+    let upload = match form.screenshot()? {
+        Some(file) => match save_upload(&mut db, config, file).await {
+            Ok(upload) => Some(upload),
+            Err(rejection) => return Ok(Err(upload_error(file, rejection))),
+        },
+        None if body.is_some() => None,
+        None => return Ok(Err(UploadErrorTemplate { filename: None, message: "请上传截图或输入题目。".to_string() })),
+    };
+
+    let description = if upload.is_some() { "Screenshot Problem" } else { "Text Problem" };
+    let answer = answer_text(form.answer.clone());
+    let (image_url, original_filename, mime_type) = match upload {
+        Some(upload) => (Some(upload.url), upload.original_filename, upload.mime_type),
+        None => (None, None, None),
+    };
+
+    let problem_id = sqlx::query("INSERT INTO problems (exam_id, description, notes, image_url, body, solution_link, answer, original_filename, mime_type, is_incorrect, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, 1, CURRENT_TIMESTAMP)")
+        .bind(id)
+        .bind(description)
+        .bind(&form.notes)
+        .bind(&image_url)
+        .bind(&body)
+        .bind(&form.solution_link)
+        .bind(&answer)
+        .bind(&original_filename)
+        .bind(&mime_type)
+        .execute(&mut **db)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+
+    let mut category_names = String::new();
+    if let Some(cats) = &form.categories {
+        category_names = link_problem_categories(&mut db, category_cache, problem_id, exam.course_id, cats).await.join(",");
+    }
+
+    let problem = ProblemWithCategories {
+        id: problem_id,
+        log_item_id: None,
+        exam_id: Some(id),
+        description: description.to_string(),
+        notes: form.notes.clone(),
+        image_url,
+        body,
+        solution_link: form.solution_link.clone(),
+        answer,
+        category_names: if category_names.is_empty() { None } else { Some(category_names) },
+        source_kind: "Exam".to_string(),
+        source_title: exam.title.clone(),
+        source_url: format!("/courses/{}/exams#exam-{}", exam.course_id, id),
+        original_filename,
+        mime_type,
+        imported_from: None,
+        public_id: queries::fetch_public_id(&mut db, "problems", problem_id).await,
+        number: queries::fetch_problem_number(&mut db, problem_id).await,
+        assigned_to: None,
+        assignee_name: None,
+        difficulty: None,
+        exam_title: None,
+    };
+
+    Ok(Ok(ProblemRowTemplate { problem }))
+}
+
+// A problem from homework or a quiz that came up again on a past exam can be
+// put on that exam too. It stays under its log item, which remains its
+// source; the exam lists it alongside the problems uploaded there. A problem
+// is on one exam at most: moving it to another means taking it off first,
+// so it never leaves an exam without anyone asking.
+#[post("/problems/<id>/exam", data = "<form>")]
+async fn attach_problem_to_exam(mut db: Connection<Db>, user: AuthUser, id: i64, form: Form<AttachProblemToExam>) -> Result<Redirect, Status> {
+    let problem = queries::fetch_problem_with_categories(&mut db, id).await.ok_or(Status::NotFound)?;
+    if problem.log_item_id.is_none() {
+        return Err(Status::BadRequest);
+    }
+    let course_id = problem_course_id(&mut db, &problem).await.ok_or(Status::NotFound)?;
+    if let Some(exam_id) = form.exam_id {
+        let exam = queries::fetch_exam(&mut db, exam_id).await.ok_or(Status::BadRequest)?;
+        if !access::can_open_course(&mut db, user.id, exam.course_id).await {
+            return Err(Status::NotFound);
+        }
+        if exam.course_id != course_id {
+            return Err(Status::BadRequest);
+        }
+        if problem.exam_id.is_some_and(|current| current != exam_id) {
+            return Err(Status::Conflict);
+        }
+    }
+
+    sqlx::query("UPDATE problems SET exam_id = ? WHERE id = ?")
+        .bind(form.exam_id)
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    Ok(Redirect::to(format!("/problems/{}/view", id)))
+}
+
+#[get("/exams/<id>/problems")]
+async fn get_exam_problems(mut db: Connection<Db>, _user: AuthUser, id: i64) -> String {
+    let problems = queries::fetch_exam_problems(&mut db, id).await;
+
+    let mut html = String::new();
+    for p in problems {
+        let t = ProblemRowTemplate { problem: p };
+        html.push_str(&t.render().unwrap());
+    }
+    html
+}
+
+// ========== Exam Study Plans ==========
+//
+// /exams/<id>/plan spreads the problems in an exam's categories that are due
+// before it, or were never reviewed, over the days that are left (see planner).
+// Each entry's day is written into study_cards as the problem's due date, so
+// the course's review mode serves the plan; a problem counts as done once it
+// has been reviewed after the plan was made. Opening the plan moves problems
+// left undone on past days onto the days still ahead.
+
+#[derive(FromForm)]
+struct NewStudyPlan {
+    category: Vec<i64>,
+}
+
+const PLAN_ENTRIES_QUERY: &str = r#"
+    SELECT
+        spe.problem_id, spe.day, p.description, GROUP_CONCAT(c.name, ', ') as category_names,
+        EXISTS (
+            SELECT 1 FROM study_reviews r
+            WHERE r.user_id = sp.user_id AND r.problem_id = spe.problem_id AND r.reviewed_at >= sp.created_at
+        ) as done
+    FROM study_plan_entries spe
+    JOIN study_plans sp ON spe.plan_id = sp.id
+    JOIN problems p ON spe.problem_id = p.id
+    LEFT JOIN problem_categories pc ON pc.problem_id = p.id
+    LEFT JOIN categories c ON pc.category_id = c.id
+    WHERE spe.plan_id = ?
+    GROUP BY spe.problem_id
+    ORDER BY spe.day, spe.rowid
+"#;
+
+fn parse_exam_date(exam: &Exam) -> Option<NaiveDate> {
+    exam.exam_date.as_deref().and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+}
+
+// Problems in the course (and `categories`, if any) that come due before
+// `exam_date` or have no card yet, grouped by category with wrong answers first
+async fn plan_problems(db: &mut Connection<Db>, user_id: i64, course_id: i64, categories: &[i64], exam_date: NaiveDate) -> Vec<i64> {
+    let mut query = String::from(
+        r#"
+        SELECT p.id
+        FROM problems p
+        LEFT JOIN log_items l ON p.log_item_id = l.id
+        LEFT JOIN exams e ON p.exam_id = e.id
+        LEFT JOIN study_cards sc ON sc.problem_id = p.id AND sc.user_id = ?
+        WHERE (l.course_id = ? OR e.course_id = ?) AND (sc.due_at IS NULL OR sc.due_at < ?)
+        "#,
+    );
+    if !categories.is_empty() {
+        query.push_str(&category_filter(categories.len()));
+    }
+    query.push_str(
+        r#"
+        ORDER BY
+            (SELECT MIN(cp.path) FROM problem_categories pc JOIN category_paths cp ON cp.id = pc.category_id WHERE pc.problem_id = p.id),
+            p.is_incorrect DESC, sc.due_at, p.id
+        "#,
+    );
+
+    let mut q = sqlx::query_scalar::<_, i64>(&query)
+        .bind(user_id)
+        .bind(course_id)
+        .bind(course_id)
+        .bind(planner::due_at(exam_date));
+    for c in categories {
+        q = q.bind(c);
+    }
+    q.fetch_all(&mut ***db).await.unwrap_or_default()
+}
+
+// Put a problem on a day of the plan and make it due then
+async fn schedule_plan_entry(db: &mut Connection<Db>, user_id: i64, plan_id: i64, problem_id: i64, day: NaiveDate) {
+    sqlx::query(
+        r#"
+        INSERT INTO study_plan_entries (plan_id, problem_id, day) VALUES (?, ?, ?)
+        ON CONFLICT (plan_id, problem_id) DO UPDATE SET day = excluded.day
+        "#,
+    )
+    .bind(plan_id)
+    .bind(problem_id)
+    .bind(day.format("%Y-%m-%d").to_string())
+    .execute(&mut ***db)
+    .await
+    .unwrap();
+
+    sqlx::query(
+        r#"
+        INSERT INTO study_cards (user_id, problem_id, due_at) VALUES (?, ?, ?)
+        ON CONFLICT (user_id, problem_id) DO UPDATE SET due_at = excluded.due_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(problem_id)
+    .bind(planner::due_at(day))
+    .execute(&mut ***db)
+    .await
+    .unwrap();
+}
+
+// Move problems left undone on days before `today` onto the lightest of the
+// days still ahead; returns how many moved
+async fn reschedule_missed(db: &mut Connection<Db>, user_id: i64, plan_id: i64, today: NaiveDate, exam_date: Option<NaiveDate>) -> usize {
+    let days = exam_date.map(|d| planner::plan_days(today, d)).unwrap_or_default();
+    if days.is_empty() {
+        return 0;
+    }
+    let entries = sqlx::query_as::<_, PlanEntry>(PLAN_ENTRIES_QUERY)
+        .bind(plan_id)
+        .fetch_all(&mut ***db)
+        .await
+        .unwrap_or_default();
+
+    let today_text = today.format("%Y-%m-%d").to_string();
+    let missed: Vec<i64> = entries.iter().filter(|e| !e.done && e.day < today_text).map(|e| e.problem_id).collect();
+    let mut loads: Vec<usize> = days
+        .iter()
+        .map(|d| {
+            let day = d.format("%Y-%m-%d").to_string();
+            entries.iter().filter(|e| !e.done && e.day == day).count()
+        })
+        .collect();
+
+    for &problem_id in &missed {
+        let Some(i) = planner::least_loaded(&loads) else { break };
+        loads[i] += 1;
+        schedule_plan_entry(db, user_id, plan_id, problem_id, days[i]).await;
+    }
+    missed.len()
+}
+
+#[get("/exams/<id>/plan")]
+async fn view_study_plan(mut db: Connection<Db>, user: AuthUser, brand: Branding, category_cache: &State<CategoryCache>, flash: Option<FlashMessage<'_>>, id: i64) -> Result<ExamPlanTemplate, Status> {
+    let exam = queries::fetch_exam(&mut db, id).await.ok_or(Status::NotFound)?;
+    let course = queries::fetch_course(&mut db, exam.course_id).await.ok_or(Status::NotFound)?;
+    let categories = category_cache.get(&mut db, exam.course_id).await;
+    let selected = exam_study_categories(&mut db, id).await;
+    let today = chrono::Local::now().date_naive();
+
+    let plan_id: Option<i64> = sqlx::query_scalar("SELECT id FROM study_plans WHERE user_id = ? AND exam_id = ?")
+        .bind(user.id)
+        .bind(id)
+        .fetch_optional(&mut **db)
+        .await
+        .unwrap_or(None);
+
+    let mut moved = 0;
+    let mut days: Vec<PlanDay> = Vec::new();
+    if let Some(plan_id) = plan_id {
+        moved = reschedule_missed(&mut db, user.id, plan_id, today, parse_exam_date(&exam)).await;
+
+        let entries = sqlx::query_as::<_, PlanEntry>(PLAN_ENTRIES_QUERY)
+            .bind(plan_id)
+            .fetch_all(&mut **db)
+            .await
+            .unwrap_or_default();
+        for entry in entries {
+            match days.last_mut() {
+                Some(day) if day.day == entry.day => day.entries.push(entry),
+                _ => {
+                    let date = NaiveDate::parse_from_str(&entry.day, "%Y-%m-%d").unwrap_or(today);
+                    days.push(PlanDay { day: entry.day.clone(), label: planner::day_label(date), is_today: date == today, entries: vec![entry] });
+                }
+            }
+        }
+    }
+    let notice = flash.map(|f| (f.kind().to_string(), f.message().to_string()));
+
+    Ok(ExamPlanTemplate { exam, course, categories, selected, days, moved, notice, user: Some(user), brand })
+}
+
+// (Re)generate the plan; it replaces any earlier one for this exam
+#[post("/exams/<id>/plan", data = "<form>")]
+async fn create_study_plan(mut db: Connection<Db>, user: AuthUser, id: i64, form: Form<NewStudyPlan>) -> Result<Flash<Redirect>, Status> {
+    let exam = queries::fetch_exam(&mut db, id).await.ok_or(Status::NotFound)?;
+    let back = Redirect::to(format!("/exams/{}/plan", id));
+    let Some(exam_date) = parse_exam_date(&exam) else {
+        return Ok(Flash::error(back, "先给考试填上日期，才能安排复习计划。"));
+    };
+    let days = planner::plan_days(chrono::Local::now().date_naive(), exam_date);
+    if days.is_empty() {
+        return Ok(Flash::error(back, "考试就在今天或已经过去，没有可以安排的日子了。"));
+    }
+
+    let categories = if form.category.is_empty() { exam_study_categories(&mut db, id).await } else { form.category.clone() };
+    let problems = plan_problems(&mut db, user.id, exam.course_id, &categories, exam_date).await;
+    if problems.is_empty() {
+        return Ok(Flash::error(back, "所选分类里没有考前需要复习的题目。"));
+    }
+
+    sqlx::query("DELETE FROM study_plans WHERE user_id = ? AND exam_id = ?")
+        .bind(user.id)
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+    let plan_id = sqlx::query("INSERT INTO study_plans (user_id, exam_id) VALUES (?, ?)")
+        .bind(user.id)
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+
+    let mut queue = problems.iter();
+    for (day, count) in days.iter().zip(planner::split_evenly(problems.len(), days.len())) {
+        for &problem_id in queue.by_ref().take(count) {
+            schedule_plan_entry(&mut db, user.id, plan_id, problem_id, *day).await;
+        }
+    }
+
+    Ok(Flash::success(back, format!("已把 {} 道题安排到考前 {} 天。", problems.len(), days.len().min(problems.len()))))
+}
+
+// ========== Course Settings Routes ==========
+
+#[get("/courses/<id>/settings")]
+async fn view_course_settings(mut db: Connection<Db>, user: AuthUser, brand: Branding, tally: &State<page_views::Tally>, flash: Option<FlashMessage<'_>>, id: i64) -> CourseSettingsTemplate {
+    let CourseContext { course, semester, courses } = queries::fetch_course_context(&mut db, id).await.unwrap();
+
+    let kinds = course_log_item_kinds(&mut db, id).await;
+    let members = sqlx::query_as::<_, CourseMember>(
+        "SELECT m.user_id, u.username, m.role, m.joined_at FROM course_members m JOIN users u ON m.user_id = u.id WHERE m.course_id = ? ORDER BY m.joined_at, u.username"
+    )
+    .bind(id)
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_default();
+    let previous_courses = sqlx::query_as::<_, (i64, String)>(&format!(
+        "SELECT c.id, s.name || ' · ' || c.code || ' ' || c.title FROM courses c JOIN semesters s ON c.semester_id = s.id WHERE c.id != ? AND c.id IN ({}) ORDER BY s.created_at DESC, c.code",
+        access::COURSES
+    ))
+    .bind(id)
+    .bind(user.id)
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_default();
+    let categories = queries::fetch_categories(&mut db, id).await;
+    let carryovers = match course.continues_course_id {
+        Some(previous_id) => fetch_carryovers(&mut db, id, previous_id).await,
+        None => Vec::new(),
+    };
+    let views = page_views::this_week(&mut db, tally, id).await;
+    let error = flash.map(|f| f.message().to_string());
+
+    CourseSettingsTemplate {
+        course,
+        courses,
+        semester,
+        themes: PUBLIC_THEMES,
+        translation_levels: TRANSLATION_LEVELS,
+        translation_styles: TRANSLATION_STYLES,
+        course_roles: COURSE_ROLES,
+        kinds,
+        members,
+        previous_courses,
+        categories,
+        carryovers,
+        views,
+        error,
+        user: Some(user),
+        brand,
+    }
+}
+
+#[post("/courses/<id>/settings", data = "<form>")]
+async fn update_course_settings(mut db: Connection<Db>, _user: AuthUser, config: &State<AppConfig>, id: i64, mut form: Form<CourseSettings<'_>>) -> Result<Redirect, Flash<Redirect>> {
+    let is_published = form.is_published.as_deref() == Some("on");
+    let show_lecture_links = form.show_lecture_links.as_deref() == Some("on");
+    let discourage_indexing = form.discourage_indexing.as_deref() == Some("on");
+    let slug = form.public_slug.as_deref()
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string());
+    let theme = form.public_theme.as_deref()
+        .filter(|t| PUBLIC_THEMES.iter().any(|(key, _, _)| key == t))
+        .unwrap_or("default")
+        .to_string();
+
+    sqlx::query("UPDATE courses SET is_published = ?, public_slug = ?, show_lecture_links = ?, discourage_indexing = ?, public_theme = ? WHERE id = ?")
+        .bind(is_published)
+        .bind(&slug)
+        .bind(show_lecture_links)
+        .bind(discourage_indexing)
+        .bind(&theme)
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    // An empty file input still submits a zero-length part
+    let banner_url = match form.banner.as_mut() {
+        Some(banner) if banner.len() > 0 => match save_upload(&mut db, config, banner).await {
+            Ok(upload) => Some(upload.url),
+            Err(rejection) => return Err(Flash::error(Redirect::to(format!("/courses/{}/settings", id)), rejection.message())),
+        },
+        _ => None,
+    };
+    if banner_url.is_some() || form.remove_banner.as_deref() == Some("on") {
+        let old_banner: Option<String> = sqlx::query_scalar("SELECT banner_url FROM courses WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&mut **db)
+            .await
+            .unwrap_or(None)
+            .flatten();
+        sqlx::query("UPDATE courses SET banner_url = ? WHERE id = ?")
+            .bind(&banner_url)
+            .bind(id)
+            .execute(&mut **db)
+            .await
+            .unwrap();
+        if let Some(old_banner) = old_banner {
+            uploads::release(&mut db, &old_banner).await;
+        }
+    }
+
+    Ok(Redirect::to(format!("/courses/{}/settings", id)))
+}
+
+#[post("/courses/<id>/grade", data = "<form>")]
+async fn update_course_grade(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Result<Form<CourseGrade>, form::Errors<'_>>) -> Result<Redirect, FormRejected> {
+    let form = form.map_err(|errors| FormRejected::new(&errors))?;
+    let grade = form.grade.as_deref()
+        .map(|g| g.trim().to_uppercase())
+        .filter(|g| !g.is_empty());
+
+    sqlx::query("UPDATE courses SET units = ?, grade = ? WHERE id = ?")
+        .bind(form.units)
+        .bind(&grade)
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    Ok(Redirect::to(format!("/courses/{}/settings", id)))
+}
+
+#[post("/courses/<id>/color", data = "<form>")]
+async fn update_course_color(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Form<CourseColor>) -> Redirect {
+    // Only accept #rrggbb since the value is written into style attributes
+    let color = form.color.trim().to_lowercase();
+    let valid = color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+
+    if valid {
+        sqlx::query("UPDATE courses SET color = ? WHERE id = ?")
+            .bind(&color)
+            .bind(id)
+            .execute(&mut **db)
+            .await
+            .unwrap();
+    }
+
+    Redirect::to(format!("/courses/{}/settings", id))
+}
+
+// An empty URL turns the webhook off
+#[post("/courses/<id>/webhook", data = "<form>")]
+async fn update_course_webhook(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Form<CourseWebhook>) -> Result<Redirect, Flash<Redirect>> {
+    let back = format!("/courses/{}/settings", id);
+    let url = webhook::parse_url(&form.webhook_url).map_err(|message| Flash::error(Redirect::to(back.clone()), message))?;
+    webhook::set_url(&mut db, id, url.as_deref()).await.unwrap();
+    Ok(Redirect::to(back))
+}
+
+#[post("/courses/<id>/translation_context", data = "<form>")]
+async fn update_course_translation_context(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Form<CourseTranslationContext>) -> Redirect {
+    let subject = form.subject.as_deref().map(str::trim).filter(|s| !s.is_empty());
+    let valid = TRANSLATION_LEVELS.iter().any(|(key, _, _)| *key == form.level)
+        && TRANSLATION_STYLES.iter().any(|(key, _, _)| *key == form.style);
+
+    if valid {
+        sqlx::query("UPDATE courses SET translation_subject = ?, translation_level = ?, translation_style = ? WHERE id = ?")
+            .bind(subject)
+            .bind(&form.level)
+            .bind(&form.style)
+            .bind(id)
+            .execute(&mut **db)
+            .await
+            .unwrap();
+    }
+
+    Redirect::to(format!("/courses/{}/settings", id))
+}
+
+#[post("/courses/<id>/kinds", data = "<form>")]
+async fn update_log_item_kinds(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Form<LogItemKindSettings>) -> Redirect {
+    sqlx::query("DELETE FROM log_item_kinds WHERE course_id = ?")
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    let known = form.order.iter().filter(|k| ALL_KINDS.contains(&k.as_str()));
+    for (position, kind) in known.enumerate() {
+        sqlx::query("INSERT OR IGNORE INTO log_item_kinds (course_id, kind, position, enabled) VALUES (?, ?, ?, ?)")
+            .bind(id)
+            .bind(kind)
+            .bind(position as i64)
+            .bind(form.enabled.contains(kind))
+            .execute(&mut **db)
+            .await
+            .unwrap();
+    }
+
+    Redirect::to(format!("/courses/{}/settings", id))
+}
+
+#[post("/courses/<id>/translate")]
+async fn translate_course(mut db: Connection<Db>, _user: AuthUser, id: i64) -> String {
+    let course = queries::fetch_course(&mut db, id).await.unwrap();
+
+    let texts_to_translate = translate::course_texts(&mut db, id).await;
+    if texts_to_translate.is_empty() {
+        return "<span class=\"text-green-400\">No content to translate.</span>".to_string();
+    }
+
+    let results = translate::translate_batch(&mut db, &texts_to_translate, &course).await;
+    let total = results.len();
+
+    format!("<span class=\"text-green-400\">Translated {} items successfully.</span>", total)
+}
+
+// ========== Course Continuation ==========
+//
+// A course can continue one from an earlier semester (数学分析(二) after
+// 数学分析(一)). Linking carries each of the earlier course's categories over
+// into the one with the same path here, creating it if needed; the mapping can
+// then be edited on the settings page. Reviews under a carried-over category
+// count toward the new one (see the category_lineage view), and the study page
+// can list the earlier courses' problems alongside this one's.
+
+#[derive(FromForm)]
+struct ContinuesCourse {
+    // Empty to unlink
+    continues_course_id: Option<i64>,
+}
+
+#[derive(FromForm)]
+struct CategoryCarryoverSettings {
+    // Previous category id -> this course's category id, empty to leave it out
+    carryover: std::collections::HashMap<i64, Option<i64>>,
+}
+
+// The previous course's categories in tree order, each with the category of
+// `course_id` it carries over into
+async fn fetch_carryovers(db: &mut Connection<Db>, course_id: i64, previous_id: i64) -> Vec<CategoryCarryover> {
+    let mapped: std::collections::HashMap<i64, i64> = sqlx::query_as::<_, (i64, i64)>(
+        "SELECT cc.previous_category_id, cc.category_id FROM category_carryovers cc JOIN categories c ON cc.category_id = c.id WHERE c.course_id = ?"
+    )
+    .bind(course_id)
+    .fetch_all(&mut ***db)
+    .await
+    .unwrap_or_default()
+    .into_iter()
+    .collect();
+
+    queries::fetch_categories(db, previous_id)
+        .await
+        .into_iter()
+        .map(|previous| CategoryCarryover { category_id: mapped.get(&previous.id).copied(), previous })
+        .collect()
+}
+
+// Map every category of the previous course that isn't mapped yet to the
+// category at the same path in `course_id`
+async fn carry_over_categories(db: &mut Connection<Db>, category_cache: &CategoryCache, course_id: i64, previous_id: i64) {
+    for carryover in fetch_carryovers(db, course_id, previous_id).await {
+        if carryover.category_id.is_some() {
+            continue;
+        }
+        let Some((category_id, _)) = find_or_create_category(db, category_cache, course_id, &carryover.previous.path).await else {
+            continue;
+        };
+        sqlx::query("INSERT OR REPLACE INTO category_carryovers (previous_category_id, category_id) VALUES (?, ?)")
+            .bind(carryover.previous.id)
+            .bind(category_id)
+            .execute(&mut ***db)
+            .await
+            .unwrap();
+    }
+}
+
+#[post("/courses/<id>/continues", data = "<form>")]
+async fn update_course_continues(mut db: Connection<Db>, user: AuthUser, category_cache: &State<CategoryCache>, id: i64, form: Form<ContinuesCourse>) -> Result<Redirect, Flash<Redirect>> {
+    let settings = format!("/courses/{}/settings", id);
+    let previous_id = form.continues_course_id;
+
+    if let Some(previous_id) = previous_id {
+        if !access::can_open_course(&mut db, user.id, previous_id).await {
+            return Err(Flash::error(Redirect::to(settings), "找不到要接续的课程。"));
+        }
+    }
+
+    // Following the chosen course's own predecessors must not lead back here
+    if let Some(previous_id) = previous_id {
+        let loops: bool = sqlx::query_scalar(&format!("SELECT ? IN ({})", COURSE_CHAIN))
+            .bind(id)
+            .bind(previous_id)
+            .fetch_one(&mut **db)
+            .await
+            .unwrap_or(true);
+        if loops {
+            return Err(Flash::error(Redirect::to(settings), "不能接续这门课程：它本身接续自本课程。"));
+        }
+    }
+
+    sqlx::query("UPDATE courses SET continues_course_id = ? WHERE id = ?")
+        .bind(previous_id)
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+    // Drop what was carried over from a course this one no longer continues
+    sqlx::query(
+        "DELETE FROM category_carryovers WHERE category_id IN (SELECT id FROM categories WHERE course_id = ?) AND previous_category_id NOT IN (SELECT id FROM categories WHERE course_id IS ?)"
+    )
+    .bind(id)
+    .bind(previous_id)
+    .execute(&mut **db)
+    .await
+    .unwrap();
+
+    if let Some(previous_id) = previous_id {
+        carry_over_categories(&mut db, category_cache, id, previous_id).await;
+    }
+
+    Ok(Redirect::to(settings))
+}
+
+#[post("/courses/<id>/carryovers", data = "<form>")]
+async fn update_category_carryovers(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Form<CategoryCarryoverSettings>) -> Redirect {
+    let settings = Redirect::to(format!("/courses/{}/settings", id));
+    let Some(previous_id) = queries::fetch_course(&mut db, id).await.and_then(|c| c.continues_course_id) else {
+        return settings;
+    };
+
+    // Only the previous course's categories, and only into this course's
+    let categories: Vec<i64> = queries::fetch_categories(&mut db, id).await.into_iter().map(|c| c.id).collect();
+    for carryover in fetch_carryovers(&mut db, id, previous_id).await {
+        let Some(chosen) = form.carryover.get(&carryover.previous.id) else {
+            continue;
+        };
+
+        match chosen.filter(|c| categories.contains(c)) {
+            Some(category_id) => sqlx::query("INSERT OR REPLACE INTO category_carryovers (previous_category_id, category_id) VALUES (?, ?)")
+                .bind(carryover.previous.id)
+                .bind(category_id),
+            None => sqlx::query("DELETE FROM category_carryovers WHERE previous_category_id = ?")
+                .bind(carryover.previous.id),
+        }
+        .execute(&mut **db)
+        .await
+        .unwrap();
+    }
+
+    settings
+}
+
+// ========== Importing Notes ==========
+//
+// A ZIP of Markdown notes and their images becomes log items and problems
+// (see notes_import.rs), for people moving over notes kept in Notion or
+// Obsidian. Images go through the same upload checks as screenshots; one
+// that fails them stays a link in its log item's description.
+
+#[derive(FromForm)]
+struct NotesImport<'r> {
+    archive: TempFile<'r>,
+}
+
+#[post("/courses/<id>/import/notes", data = "<form>")]
+async fn import_notes(mut db: Connection<Db>, _user: AuthUser, config: &State<AppConfig>, category_cache: &State<CategoryCache>, id: i64, form: Form<NotesImport<'_>>) -> Result<Redirect, Flash<Redirect>> {
+    let settings = format!("/courses/{}/settings", id);
+    let mut bytes = Vec::new();
+    let read = match form.archive.open().await {
+        Ok(mut file) => file.read_to_end(&mut bytes).await.is_ok(),
+        Err(_) => false,
+    };
+    if !read {
+        return Err(Flash::error(Redirect::to(settings), "无法读取上传的文件。"));
+    }
+    let mut archive = notes_import::open(&bytes).map_err(|message| Flash::error(Redirect::to(settings.clone()), message))?;
+
+    let mut refused = Vec::new();
+    for path in archive.attachments() {
+        let mime_type = notes_import::mime_type(path);
+        if scan::check(&config.upload_scan, archive.attachment(path), mime_type.as_deref()).await.is_err() {
+            refused.push(path.to_string());
+        }
+    }
+    for path in refused {
+        archive.remove(&path);
+    }
+
+    for note in archive.notes(ALL_KINDS) {
+        let item_id = sqlx::query("INSERT INTO log_items (course_id, kind, title, description, link, date) VALUES (?, ?, ?, ?, NULL, ?)")
+            .bind(id)
+            .bind(&note.kind)
+            .bind(&note.title)
+            .bind(&note.description)
+            .bind(&note.date)
+            .execute(&mut **db)
+            .await
+            .unwrap()
+            .last_insert_rowid();
+        if let Some(description) = &note.description {
+            link_preview::queue_previews(&mut db, description).await;
+        }
+
+        let log_item = queries::fetch_log_item(&mut db, item_id).await.unwrap();
+        for image in note.images {
+            let file_name = notes_import::file_name(&image.path).to_string();
+            let mime_type = notes_import::mime_type(&image.path);
+            let bytes = archive.attachment(&image.path);
+            let ext = uploads::extension_for(bytes, mime_type.as_deref(), Some(&file_name));
+            let url = uploads::store(&mut db, bytes, &ext).await.expect("Unable to store upload");
+            let upload = SavedUpload { url, original_filename: Some(file_name), mime_type, imported_from: None };
+            insert_log_problem(&mut db, category_cache, &log_item, Some(upload), None, image.alt, None, None, note.categories.as_deref()).await;
+        }
+    }
+
+    Ok(Redirect::to(format!("/courses/{}", id)))
+}
+
+// ========== Recategorizing Problems ==========
+//
+// The categories CSV round trip for cleanups done in a spreadsheet (see
+// recategorize.rs). The preview's changes wait in the user's preferences
+// until its confirm button posts `apply`; each changed problem gets an edit
+// history entry, so one can still be reverted on its own.
+
+#[derive(FromForm)]
+struct Recategorize<'r> {
+    csv: Option<TempFile<'r>>,
+    apply: bool,
+}
+
+fn recategorize_key(course_id: i64) -> String {
+    format!("recategorize:{}", course_id)
+}
+
+#[get("/courses/<id>/problems/categories.csv")]
+async fn export_problem_categories(mut db: Connection<Db>, _user: AuthUser, id: i64) -> Result<Download, Status> {
+    let course = queries::fetch_course(&mut db, id).await.ok_or(Status::NotFound)?;
+    let body = recategorize::export(&mut db, id).await.into_bytes();
+    let filename = format!("{}-categories.csv", course.code);
+    let disposition = Header::new("Content-Disposition", uploads::content_disposition(Some(&filename), true));
+    Ok(Download { body: (ContentType::CSV, body), disposition })
+}
+
+#[post("/courses/<id>/problems/recategorize", data = "<form>")]
+async fn recategorize_problems(
+    mut db: Connection<Db>,
+    user: AuthUser,
+    brand: Branding,
+    category_cache: &State<CategoryCache>,
+    id: i64,
+    form: Form<Recategorize<'_>>,
+) -> Result<RecategorizeTemplate, Flash<Redirect>> {
+    let settings = format!("/courses/{}/settings", id);
+    let CourseContext { course, semester, courses } = queries::fetch_course_context(&mut db, id).await.unwrap();
+
+    if form.apply {
+        let changes: Vec<recategorize::Change> = get_preference(&mut db, user.id, &recategorize_key(id))
+            .await
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        if changes.is_empty() {
+            return Err(Flash::error(Redirect::to(settings), "没有待应用的修改，请重新上传 CSV。"));
+        }
+        let mut before = Vec::new();
+        for change in &changes {
+            before.extend(fetch_problem_snapshot(&mut db, change.problem_id).await);
+        }
+        if !apply_recategorize(&mut db, category_cache, id, &changes).await {
+            return Err(Flash::error(Redirect::to(settings), "预览之后有错题的分类被改过，没有做任何修改。请重新导出、上传 CSV。"));
+        }
+        sqlx::query("DELETE FROM user_preferences WHERE user_id = ? AND key = ?")
+            .bind(user.id)
+            .bind(recategorize_key(id))
+            .execute(&mut **db)
+            .await
+            .unwrap();
+        for problem in &before {
+            let after = fetch_problem_snapshot(&mut db, problem.problem.id).await;
+            record_history(&mut db, user.id, Some(id), "update", Some(problem), after.as_ref()).await;
+        }
+        let plan = recategorize::Plan { changes, ..Default::default() };
+        return Ok(RecategorizeTemplate { course, courses, semester, plan, applied: true, user: Some(user), brand });
+    }
+
+    let mut bytes = Vec::new();
+    let read = match &form.csv {
+        Some(file) => match file.open().await {
+            Ok(mut file) => file.read_to_end(&mut bytes).await.is_ok(),
+            Err(_) => false,
+        },
+        None => false,
+    };
+    if !read {
+        return Err(Flash::error(Redirect::to(settings), "无法读取上传的文件。"));
+    }
+    let Ok(csv) = String::from_utf8(bytes) else {
+        return Err(Flash::error(Redirect::to(settings), "CSV 需要是 UTF-8 编码。"));
+    };
+    let plan = recategorize::plan(&mut db, id, &csv).await.map_err(|message| Flash::error(Redirect::to(settings.clone()), message))?;
+    set_preference(&mut db, user.id, &recategorize_key(id), &serde_json::to_string(&plan.changes).unwrap()).await;
+
+    Ok(RecategorizeTemplate { course, courses, semester, plan, applied: false, user: Some(user), brand })
+}
+
+// All of `changes`, or none if any problem's categories moved on since the preview
+async fn apply_recategorize(db: &mut Connection<Db>, category_cache: &CategoryCache, course_id: i64, changes: &[recategorize::Change]) -> bool {
+    let Ok(mut tx) = db::begin_write(db).await else { return false };
+    for change in changes {
+        // Dropping `tx` rolls back
+        if !recategorize::is_current(&mut tx, course_id, change).await {
+            return false;
+        }
+        sqlx::query("DELETE FROM problem_categories WHERE problem_id = ?")
+            .bind(change.problem_id)
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        link_problem_categories(&mut tx, category_cache, change.problem_id, course_id, &change.after.join(",")).await;
+    }
+    tx.commit().await.is_ok()
 }
 
-#[delete("/logs/<id>")]
-async fn delete_log_item(mut db: Connection<Db>, _user: AuthUser, id: i64) -> String {
-    let problems = sqlx::query("SELECT id FROM problems WHERE log_item_id = ?")
-        .bind(id)
-        .fetch_all(&mut **db)
-        .await
-        .unwrap_or_default();
+// ========== Course Membership ==========
+//
+// A course's join code lets a TA onboard a whole class at once: anyone signed
+// in enters it on the dashboard (`POST /join`) and becomes a member with the
+// course's join_role. Regenerating the code stops the old one from working;
+// people who already joined stay members until removed here.
 
-    for problem in problems {
-        let problem_id: i64 = problem.try_get("id").unwrap();
-        sqlx::query("DELETE FROM problem_categories WHERE problem_id = ?")
-            .bind(problem_id)
+fn new_join_code() -> String {
+    Uuid::new_v4().simple().to_string()[..8].to_uppercase()
+}
+
+#[post("/courses/<id>/join_code", data = "<form>")]
+async fn update_course_join_code(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Form<CourseJoinSettings>) -> Redirect {
+    if form.disable.as_deref() == Some("on") {
+        sqlx::query("UPDATE courses SET join_code = NULL WHERE id = ?")
+            .bind(id)
             .execute(&mut **db)
             .await
             .unwrap();
+        return Redirect::to(format!("/courses/{}/settings", id));
     }
 
-    sqlx::query("DELETE FROM problems WHERE log_item_id = ?")
+    let role = COURSE_ROLES.iter().map(|(key, _)| *key).find(|key| *key == form.role).unwrap_or("viewer");
+    let current: Option<String> = sqlx::query_scalar("SELECT join_code FROM courses WHERE id = ?")
         .bind(id)
-        .execute(&mut **db)
+        .fetch_optional(&mut **db)
         .await
-        .unwrap();
+        .unwrap_or(None)
+        .flatten();
+    let code = match current {
+        Some(code) if form.regenerate.as_deref() != Some("on") => code,
+        _ => new_join_code(),
+    };
 
-    sqlx::query("DELETE FROM log_items WHERE id = ?")
+    sqlx::query("UPDATE courses SET join_code = ?, join_role = ? WHERE id = ?")
+        .bind(&code)
+        .bind(role)
         .bind(id)
         .execute(&mut **db)
         .await
         .unwrap();
 
-    String::new()
+    Redirect::to(format!("/courses/{}/settings", id))
 }
 
-#[get("/logs/<id>/edit")]
-async fn get_edit_log_item(mut db: Connection<Db>, user: AuthUser, id: i64) -> LogItemEditTemplate {
-    let item = sqlx::query_as::<_, LogItem>("SELECT * FROM log_items WHERE id = ?")
-        .bind(id)
-        .fetch_one(&mut **db)
+#[post("/join", data = "<form>")]
+async fn join_course(mut db: Connection<Db>, user: AuthUser, form: Form<JoinCourse>) -> Result<Redirect, Flash<Redirect>> {
+    let code = form.code.trim().to_uppercase();
+    let course: Option<(i64, String)> = sqlx::query_as("SELECT id, join_role FROM courses WHERE join_code = ?")
+        .bind(&code)
+        .fetch_optional(&mut **db)
         .await
-        .unwrap();
-    LogItemEditTemplate { item, user: Some(user) }
-}
+        .unwrap_or(None);
+    let Some((course_id, role)) = course else {
+        return Err(Flash::error(Redirect::to("/dashboard"), "加入码无效或已停用。"));
+    };
 
-#[get("/logs/<id>")]
-async fn get_log_item(mut db: Connection<Db>, user: AuthUser, id: i64) -> LogItemTemplate {
-    let item = sqlx::query_as::<_, LogItem>("SELECT * FROM log_items WHERE id = ?")
-        .bind(id)
-        .fetch_one(&mut **db)
+    // Joining again keeps the role from the first time
+    sqlx::query("INSERT INTO course_members (course_id, user_id, role, joined_at) VALUES (?, ?, ?, CURRENT_TIMESTAMP) ON CONFLICT (course_id, user_id) DO NOTHING")
+        .bind(course_id)
+        .bind(user.id)
+        .bind(&role)
+        .execute(&mut **db)
         .await
         .unwrap();
 
-    let categories = sqlx::query_as::<_, Category>("SELECT * FROM categories WHERE course_id = ?")
-        .bind(item.course_id)
-        .fetch_all(&mut **db)
-        .await
-        .unwrap_or_default();
-
-    LogItemTemplate { item, categories, user: Some(user) }
+    Ok(Redirect::to(format!("/courses/{}", course_id)))
 }
 
-#[post("/logs/<id>", data = "<form>")]
-async fn update_log_item(mut db: Connection<Db>, user: AuthUser, id: i64, form: Form<UpdateLogItem>) -> LogItemTemplate {
-    sqlx::query("UPDATE log_items SET kind = ?, title = ?, description = ?, link = ?, date = ? WHERE id = ?")
-        .bind(&form.kind)
-        .bind(&form.title)
-        .bind(&form.description)
-        .bind(&form.link)
-        .bind(&form.date)
+#[delete("/courses/<id>/members/<user_id>")]
+async fn remove_course_member(mut db: Connection<Db>, user: AuthUser, id: i64, user_id: i64) -> Result<String, Status> {
+    // A viewer can only leave
+    if user_id != user.id && !access::can_edit_course(&mut db, user.id, id).await {
+        return Err(Status::Forbidden);
+    }
+    sqlx::query("DELETE FROM course_members WHERE course_id = ? AND user_id = ?")
         .bind(id)
+        .bind(user_id)
         .execute(&mut **db)
         .await
         .unwrap();
+    Ok(String::new())
+}
 
-    let item = sqlx::query_as::<_, LogItem>("SELECT * FROM log_items WHERE id = ?")
-        .bind(id)
-        .fetch_one(&mut **db)
-        .await
-        .unwrap();
-
-    let categories = sqlx::query_as::<_, Category>("SELECT * FROM categories WHERE course_id = ?")
-        .bind(item.course_id)
-        .fetch_all(&mut **db)
+// ========== Problem Assignments ==========
+//
+// In a shared course a problem can be handed to one person to write up its
+// solution: a member of the course, or whoever is doing the assigning.
+// /assignments lists everything handed to the current user across courses.
+
+async fn assignable_users(db: &mut Connection<Db>, course_id: i64, user_id: i64) -> Vec<(i64, String)> {
+    sqlx::query_as("SELECT id, username FROM users WHERE id = ? OR id IN (SELECT user_id FROM course_members WHERE course_id = ?) ORDER BY username")
+        .bind(user_id)
+        .bind(course_id)
+        .fetch_all(&mut ***db)
         .await
-        .unwrap_or_default();
-
-    LogItemTemplate { item, categories, user: Some(user) }
+        .unwrap_or_default()
 }
 
-#[post("/logs/<id>/problems", data = "<form>")]
-async fn create_problem(mut db: Connection<Db>, user: AuthUser, id: i64, mut form: Form<NewProblem<'_>>) -> ProblemRowTemplate {
-    let file_name = format!("{}.png", Uuid::new_v4());
-    let file_path = format!("uploads/{}", file_name);
-    form.screenshot.move_copy_to(&file_path).await.expect("Unable to move or copy file");
-    let image_url = format!("/uploads/{}", file_name);
-
-    let description = "Screenshot Problem";
+#[post("/problems/<id>/assign", data = "<form>")]
+async fn assign_problem(mut db: Connection<Db>, user: AuthUser, id: i64, form: Form<AssignProblem>) -> Result<Redirect, Status> {
+    let problem = queries::fetch_problem_with_categories(&mut db, id).await.ok_or(Status::NotFound)?;
+    let course_id = problem_course_id(&mut db, &problem).await.ok_or(Status::NotFound)?;
+    if let Some(assignee) = form.user_id {
+        if !assignable_users(&mut db, course_id, user.id).await.iter().any(|(user_id, _)| *user_id == assignee) {
+            return Err(Status::BadRequest);
+        }
+    }
 
-    let problem_id = sqlx::query("INSERT INTO problems (log_item_id, description, notes, image_url, solution_link, is_incorrect) VALUES (?, ?, ?, ?, ?, 1)")
+    sqlx::query("UPDATE problems SET assigned_to = ? WHERE id = ?")
+        .bind(form.user_id)
         .bind(id)
-        .bind(description)
-        .bind(&form.notes)
-        .bind(&image_url)
-        .bind(&form.solution_link)
         .execute(&mut **db)
         .await
-        .unwrap()
-        .last_insert_rowid();
-
-    let mut category_names = String::new();
-    if let Some(cats) = &form.categories {
-        let log_item = sqlx::query_as::<_, LogItem>("SELECT * FROM log_items WHERE id = ?")
-            .bind(id)
-            .fetch_one(&mut **db)
-            .await
-            .unwrap();
-
-        let mut processed_cats = Vec::new();
-        for cat_name in cats.split(|c| c == ',' || c == '\u{3001}').map(|s| s.trim()).filter(|s| !s.is_empty()) {
-            let cat_id_opt: Option<i64> = sqlx::query_scalar("SELECT id FROM categories WHERE course_id = ? AND name = ?")
-                .bind(log_item.course_id)
-                .bind(cat_name)
-                .fetch_optional(&mut **db)
-                .await
-                .unwrap();
-
-            let cat_id = match cat_id_opt {
-                Some(cid) => cid,
-                None => {
-                    sqlx::query("INSERT INTO categories (course_id, name) VALUES (?, ?)")
-                        .bind(log_item.course_id)
-                        .bind(cat_name)
-                        .execute(&mut **db)
-                        .await
-                        .unwrap()
-                        .last_insert_rowid()
-                }
-            };
-
-            sqlx::query("INSERT INTO problem_categories (problem_id, category_id) VALUES (?, ?)")
-                .bind(problem_id)
-                .bind(cat_id)
-                .execute(&mut **db)
-                .await
-                .unwrap();
-
-            processed_cats.push(cat_name);
-        }
-        category_names = processed_cats.join(",");
-    }
-
-    let problem = ProblemWithCategories {
-        id: problem_id,
-        log_item_id: Some(id),
-        exam_id: None,
-        description: description.to_string(),
-        notes: form.notes.clone(),
-        image_url: Some(image_url),
-        solution_link: form.solution_link.clone(),
-        category_names: if category_names.is_empty() { None } else { Some(category_names) },
-        source_kind: "".to_string(),
-        source_title: "".to_string(),
-    };
+        .unwrap();
 
-    ProblemRowTemplate { problem, user: Some(user) }
+    Ok(Redirect::to(format!("/problems/{}/view", id)))
 }
 
-#[get("/logs/<id>/problems")]
-async fn get_log_problems(mut db: Connection<Db>, _user: AuthUser, id: i64) -> String {
-    let problems = sqlx::query_as::<_, ProblemWithCategories>(
-        r#"
-        SELECT
-            p.id, p.log_item_id, p.exam_id, p.description, p.notes, p.image_url, p.solution_link,
-            GROUP_CONCAT(c.name) as category_names,
-            COALESCE(l.kind, 'Exam') as source_kind,
-            COALESCE(l.title, e.title, '') as source_title
-        FROM problems p
-        LEFT JOIN log_items l ON p.log_item_id = l.id
-        LEFT JOIN exams e ON p.exam_id = e.id
-        LEFT JOIN problem_categories pc ON p.id = pc.problem_id
-        LEFT JOIN categories c ON pc.category_id = c.id
-        WHERE p.log_item_id = ?
-        GROUP BY p.id
-        "#
-    )
-    .bind(id)
+#[get("/assignments")]
+async fn view_assignments(mut db: Connection<Db>, user: AuthUser, brand: Branding) -> AssignmentsTemplate {
+    let ids = sqlx::query_scalar::<_, i64>(&format!(
+        "SELECT p.id FROM problems p JOIN problem_sources src ON src.problem_id = p.id WHERE p.assigned_to = ? AND src.course_id IN ({}) ORDER BY p.id",
+        access::COURSES
+    ))
+    .bind(user.id)
+    .bind(user.id)
     .fetch_all(&mut **db)
     .await
     .unwrap_or_default();
+    let problems = queries::fetch_problems(&mut db, &ids).await;
 
-    let mut html = String::new();
-    for p in problems {
-        let t = ProblemRowTemplate { problem: p, user: None };
-        html.push_str(&t.render().unwrap());
-    }
-    html
+    AssignmentsTemplate { problems, user: Some(user), brand }
 }
 
-#[get("/courses/<id>/study")]
-async fn view_course_study(mut db: Connection<Db>, user: AuthUser, id: i64) -> CourseStudyTemplate {
-    let course = sqlx::query_as::<_, Course>("SELECT * FROM courses WHERE id = ?")
-        .bind(id)
-        .fetch_one(&mut **db)
-        .await
-        .unwrap();
-
-    let semester = sqlx::query_as::<_, Semester>("SELECT * FROM semesters WHERE id = ?")
-        .bind(course.semester_id)
-        .fetch_one(&mut **db)
-        .await
-        .unwrap();
-
-    let courses = sqlx::query_as::<_, Course>("SELECT * FROM courses WHERE semester_id = ?")
-        .bind(course.semester_id)
-        .fetch_all(&mut **db)
-        .await
-        .unwrap_or_default();
-
-    let categories = sqlx::query_as::<_, Category>("SELECT * FROM categories WHERE course_id = ?")
-        .bind(id)
-        .fetch_all(&mut **db)
-        .await
-        .unwrap_or_default();
-
-    CourseStudyTemplate { course, courses, categories, semester, user: Some(user) }
-}
+// ========== Problem Links ==========
+//
+// Problems can be linked as variants of each other or as a prerequisite and
+// its follow-up. Following prerequisite links from the detail page walks a
+// topic from the easiest problem to the hardest.
 
-#[get("/courses/<id>/study/problems?<source>&<category>")]
-async fn filter_study_problems(mut db: Connection<Db>, _user: AuthUser, id: i64, source: Option<Vec<String>>, category: Option<Vec<String>>) -> StudyProblemListTemplate {
-    let mut query = String::from(
+// Only problems the user can open, should a link reach into another account's course
+async fn related_problems(db: &mut Connection<Db>, user_id: i64, problem_id: i64) -> Vec<RelatedProblem> {
+    sqlx::query_as::<_, RelatedProblem>(&format!(
         r#"
         SELECT
-            p.id, p.log_item_id, p.exam_id, p.description, p.notes, p.image_url, p.solution_link,
-            GROUP_CONCAT(c.name) as category_names,
-            COALESCE(l.kind, 'Exam') as source_kind,
-            COALESCE(l.title, e.title, '') as source_title
-        FROM problems p
-        LEFT JOIN log_items l ON p.log_item_id = l.id
-        LEFT JOIN exams e ON p.exam_id = e.id
-        LEFT JOIN problem_categories pc ON p.id = pc.problem_id
-        LEFT JOIN categories c ON pc.category_id = c.id
-        WHERE (l.course_id = ? OR e.course_id = ?)
-        "#
-    );
+            l.id as link_id,
+            CASE WHEN l.kind = 'variant' THEN 'variant' WHEN l.problem_id = ? THEN 'prerequisite' ELSE 'follow_up' END as relation,
+            p.id as problem_id,
+            p.number,
+            src.source_title
+        FROM problem_links l
+        JOIN problems p ON p.id = CASE WHEN l.problem_id = ? THEN l.linked_problem_id ELSE l.problem_id END
+        JOIN problem_sources src ON src.problem_id = p.id
+        WHERE (l.problem_id = ? OR l.linked_problem_id = ?) AND src.course_id IN ({})
+        ORDER BY CASE relation WHEN 'prerequisite' THEN 0 WHEN 'variant' THEN 1 ELSE 2 END, p.id
+        "#,
+        access::COURSES
+    ))
+    .bind(problem_id)
+    .bind(problem_id)
+    .bind(problem_id)
+    .bind(problem_id)
+    .bind(user_id)
+    .fetch_all(&mut ***db)
+    .await
+    .unwrap_or_default()
+}
 
-    // Filter by Source
-    if let Some(sources) = &source {
-        if !sources.is_empty() {
-            let has_exam = sources.iter().any(|s| s == "Exam");
-            let log_sources: Vec<&String> = sources.iter().filter(|s| *s != "Exam").collect();
-
-            if has_exam && !log_sources.is_empty() {
-                query.push_str(" AND (l.kind IN (");
-                for (i, s) in log_sources.iter().enumerate() {
-                    if i > 0 { query.push_str(", "); }
-                    query.push_str(&format!("'{}'", s));
-                }
-                query.push_str(") OR p.exam_id IS NOT NULL)");
-            } else if has_exam {
-                query.push_str(" AND p.exam_id IS NOT NULL");
-            } else {
-                query.push_str(" AND l.kind IN (");
-                for (i, s) in log_sources.iter().enumerate() {
-                    if i > 0 { query.push_str(", "); }
-                    query.push_str(&format!("'{}'", s));
-                }
-                query.push_str(")");
-            }
-        }
+#[post("/problems/<id>/links", data = "<form>")]
+async fn create_problem_link(mut db: Connection<Db>, user: AuthUser, id: i64, form: Form<NewProblemLink>) -> Result<Redirect, Status> {
+    let other = form.linked_problem_id;
+    if id == other {
+        return Err(Status::BadRequest);
     }
-
-    // Filter by Category
-    if let Some(cats) = &category {
-         if !cats.is_empty() {
-             query.push_str(" AND p.id IN (SELECT pc2.problem_id FROM problem_categories pc2 WHERE pc2.category_id IN (");
-             for (i, c) in cats.iter().enumerate() {
-                 if i > 0 { query.push_str(", "); }
-                 query.push_str(c);
-             }
-             query.push_str("))");
-         }
+    // Both ends have to be problems the user can open; the guard only checked `id`
+    access::problem_course(&mut db, id).await.ok_or(Status::NotFound)?;
+    let other_course = access::problem_course(&mut db, other).await.ok_or(Status::NotFound)?;
+    if !access::can_open_course(&mut db, user.id, other_course).await {
+        return Err(Status::NotFound);
     }
 
-    query.push_str(" GROUP BY p.id");
-
-    let problems = sqlx::query_as::<_, ProblemWithCategories>(&query)
-        .bind(id)
-        .bind(id)
-        .fetch_all(&mut **db)
-        .await
-        .unwrap_or_default();
-
-    StudyProblemListTemplate { problems, user: None }
-}
-
-#[get("/problems/<id>/edit")]
-async fn get_edit_problem(mut db: Connection<Db>, user: AuthUser, id: i64) -> ProblemEditTemplate {
-    let problem = sqlx::query_as::<_, ProblemWithCategories>(PROBLEM_WITH_CATEGORIES_QUERY)
-        .bind(id)
-        .fetch_one(&mut **db)
+    // Stored as (problem, prerequisite); variants once, lower id first
+    let (problem_id, linked_problem_id, kind) = match form.kind.as_str() {
+        "variant" => (id.min(other), id.max(other), "variant"),
+        "prerequisite" => (id, other, "prerequisite"),
+        "follow_up" => (other, id, "prerequisite"),
+        _ => return Err(Status::BadRequest),
+    };
+    sqlx::query("INSERT OR IGNORE INTO problem_links (problem_id, linked_problem_id, kind) VALUES (?, ?, ?)")
+        .bind(problem_id)
+        .bind(linked_problem_id)
+        .bind(kind)
+        .execute(&mut **db)
         .await
         .unwrap();
 
-    ProblemEditTemplate { problem, user: Some(user) }
+    Ok(Redirect::to(format!("/problems/{}/view", id)))
 }
 
-#[get("/problems/<id>")]
-async fn get_problem_row(mut db: Connection<Db>, user: AuthUser, id: i64) -> ProblemRowTemplate {
-    let problem = sqlx::query_as::<_, ProblemWithCategories>(PROBLEM_WITH_CATEGORIES_QUERY)
+#[delete("/problem-links/<id>")]
+async fn delete_problem_link(mut db: Connection<Db>, _user: AuthUser, id: i64) -> String {
+    sqlx::query("DELETE FROM problem_links WHERE id = ?")
         .bind(id)
-        .fetch_one(&mut **db)
+        .execute(&mut **db)
         .await
         .unwrap();
 
-    ProblemRowTemplate { problem, user: Some(user) }
+    String::new()
 }
 
-#[post("/problems/<id>", data = "<form>")]
-async fn update_problem(mut db: Connection<Db>, user: AuthUser, id: i64, form: Form<UpdateProblem>) -> ProblemRowTemplate {
-    sqlx::query("UPDATE problems SET notes = ?, solution_link = ? WHERE id = ?")
-        .bind(&form.notes)
-        .bind(&form.solution_link)
-        .bind(id)
-        .execute(&mut **db)
-        .await
-        .unwrap();
+// ========== Log Item Tags ==========
+//
+// Free-form labels on log items ("重点", "需要复习", "考试范围"), apart from
+// the categories problems are filed under. They show as chips on the item,
+// and the course log can be filtered to one with `?tag=`. Like the status
+// chip, tagging isn't recorded in the history.
 
-    // Get the course_id via log_item or exam
-    let problem_info = sqlx::query_as::<_, Problem>("SELECT * FROM problems WHERE id = ?")
-        .bind(id)
-        .fetch_one(&mut **db)
-        .await
-        .unwrap();
+#[post("/logs/<id>/tags", data = "<form>")]
+async fn update_log_item_tags(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Form<LogItemTagsForm>) -> Option<LogItemTagsTemplate> {
+    let item = queries::fetch_log_item(&mut db, id).await?;
 
-    let course_id: i64 = if let Some(log_item_id) = problem_info.log_item_id {
-        let log_item = sqlx::query_as::<_, LogItem>("SELECT * FROM log_items WHERE id = ?")
-            .bind(log_item_id)
-            .fetch_one(&mut **db)
+    if let Some(remove) = &form.remove {
+        sqlx::query("DELETE FROM log_item_tags WHERE log_item_id = ? AND name = ?")
+            .bind(id)
+            .bind(categories::normalize_name(remove))
+            .execute(&mut **db)
             .await
             .unwrap();
-        log_item.course_id
-    } else if let Some(exam_id) = problem_info.exam_id {
-        let exam = sqlx::query_as::<_, Exam>("SELECT * FROM exams WHERE id = ?")
-            .bind(exam_id)
-            .fetch_one(&mut **db)
+    }
+    let added = form.add.as_deref().unwrap_or_default();
+    for name in added.split([',', '\u{3001}']).map(categories::normalize_name).filter(|s| !s.is_empty()) {
+        // Reuse the course's spelling, so "Exam" and "exam" stay one tag
+        let existing: Option<String> = sqlx::query_scalar(
+            "SELECT t.name FROM log_item_tags t JOIN log_items l ON t.log_item_id = l.id WHERE l.course_id = ? AND t.name = ? LIMIT 1",
+        )
+        .bind(item.course_id)
+        .bind(&name)
+        .fetch_optional(&mut **db)
+        .await
+        .unwrap_or(None);
+        sqlx::query("INSERT OR IGNORE INTO log_item_tags (log_item_id, name) VALUES (?, ?)")
+            .bind(id)
+            .bind(existing.unwrap_or(name))
+            .execute(&mut **db)
             .await
             .unwrap();
-        exam.course_id
-    } else {
-        panic!("Problem has neither log_item_id nor exam_id");
-    };
-
-    // Clear existing categories for this problem
-    sqlx::query("DELETE FROM problem_categories WHERE problem_id = ?")
-        .bind(id)
-        .execute(&mut **db)
-        .await
-        .unwrap();
-
-    // Add new categories
-    if let Some(cats) = &form.categories {
-        for cat_name in cats.split(|c| c == ',' || c == '\u{3001}').map(|s| s.trim()).filter(|s| !s.is_empty()) {
-            let cat_id_opt: Option<i64> = sqlx::query_scalar("SELECT id FROM categories WHERE course_id = ? AND name = ?")
-                .bind(course_id)
-                .bind(cat_name)
-                .fetch_optional(&mut **db)
-                .await
-                .unwrap();
+    }
 
-            let cat_id = match cat_id_opt {
-                Some(cid) => cid,
-                None => {
-                    sqlx::query("INSERT INTO categories (course_id, name) VALUES (?, ?)")
-                        .bind(course_id)
-                        .bind(cat_name)
-                        .execute(&mut **db)
-                        .await
-                        .unwrap()
-                        .last_insert_rowid()
-                }
-            };
+    let tags = queries::fetch_log_item_tags(&mut db, id).await;
+    Some(LogItemTagsTemplate { item, tags })
+}
 
-            sqlx::query("INSERT INTO problem_categories (problem_id, category_id) VALUES (?, ?)")
-                .bind(id)
-                .bind(cat_id)
-                .execute(&mut **db)
-                .await
-                .unwrap();
-        }
-    }
+// ========== Video Bookmarks ==========
+//
+// Timestamped notes on a log item whose link is a video. The log item loads
+// them lazily, like its problems; adding one re-renders the list in order.
 
-    let problem = sqlx::query_as::<_, ProblemWithCategories>(PROBLEM_WITH_CATEGORIES_QUERY)
-        .bind(id)
-        .fetch_one(&mut **db)
+async fn log_item_bookmarks(db: &mut Connection<Db>, item: LogItem, error: Option<String>) -> LogItemBookmarksTemplate {
+    let bookmarks = sqlx::query_as::<_, LogItemBookmark>("SELECT id, log_item_id, seconds, note FROM log_item_bookmarks WHERE log_item_id = ? ORDER BY seconds, id")
+        .bind(item.id)
+        .fetch_all(&mut ***db)
         .await
-        .unwrap();
+        .unwrap_or_default();
 
-    ProblemRowTemplate { problem, user: Some(user) }
+    LogItemBookmarksTemplate { item_id: item.id, link: item.link.unwrap_or_default(), bookmarks, error }
 }
 
-#[delete("/problems/<id>")]
-async fn delete_problem(mut db: Connection<Db>, _user: AuthUser, id: i64) -> String {
-    sqlx::query("DELETE FROM problem_categories WHERE problem_id = ?")
+#[get("/logs/<id>/bookmarks")]
+async fn get_log_bookmarks(mut db: Connection<Db>, _user: AuthUser, id: i64) -> Option<LogItemBookmarksTemplate> {
+    let item = queries::fetch_log_item(&mut db, id).await?;
+    Some(log_item_bookmarks(&mut db, item, None).await)
+}
+
+#[post("/logs/<id>/bookmarks", data = "<form>")]
+async fn create_log_bookmark(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Form<NewBookmark>) -> Option<LogItemBookmarksTemplate> {
+    let item = queries::fetch_log_item(&mut db, id).await?;
+    let Some(seconds) = video::parse_timestamp(&form.time) else {
+        let error = "时间的格式应为 分:秒 或 时:分:秒，例如 12:34。".to_string();
+        return Some(log_item_bookmarks(&mut db, item, Some(error)).await);
+    };
+
+    sqlx::query("INSERT INTO log_item_bookmarks (log_item_id, seconds, note) VALUES (?, ?, ?)")
         .bind(id)
+        .bind(seconds)
+        .bind(form.note.as_deref().map(str::trim).unwrap_or(""))
         .execute(&mut **db)
         .await
         .unwrap();
 
-    sqlx::query("DELETE FROM problems WHERE id = ?")
+    Some(log_item_bookmarks(&mut db, item, None).await)
+}
+
+#[delete("/bookmarks/<id>")]
+async fn delete_log_bookmark(mut db: Connection<Db>, _user: AuthUser, id: i64) -> String {
+    sqlx::query("DELETE FROM log_item_bookmarks WHERE id = ?")
         .bind(id)
         .execute(&mut **db)
         .await
@@ -933,276 +5156,504 @@ async fn delete_problem(mut db: Connection<Db>, _user: AuthUser, id: i64) -> Str
     String::new()
 }
 
-// ========== Exam Routes ==========
-
-#[get("/courses/<id>/exams")]
-async fn view_course_exams(mut db: Connection<Db>, user: AuthUser, id: i64) -> CourseExamsTemplate {
-    let course = sqlx::query_as::<_, Course>("SELECT * FROM courses WHERE id = ?")
-        .bind(id)
-        .fetch_one(&mut **db)
-        .await
-        .unwrap();
+// ========== Discussion Routes ==========
+//
+// Threads of plain-text posts under a course, for collaborators to sort out
+// logistics. Opening a thread records the newest post the user has seen in
+// discussion_reads; threads with anything newer get an unread dot in the list.
+// Threads and posts can be deleted by whoever wrote them or by the admin.
 
-    let semester = sqlx::query_as::<_, Semester>("SELECT * FROM semesters WHERE id = ?")
-        .bind(course.semester_id)
-        .fetch_one(&mut **db)
-        .await
-        .unwrap();
+// Bind the current user's id twice, then whatever the appended WHERE needs
+const DISCUSSION_THREADS_QUERY: &str = r#"
+    SELECT
+        t.id, t.course_id, t.title, u.username AS author, t.created_at,
+        COUNT(p.id) AS post_count,
+        COALESCE(MAX(p.created_at), t.created_at) AS last_post_at,
+        COALESCE(MAX(p.id), 0) > COALESCE(r.last_read_post_id, 0) AS unread,
+        t.user_id = ? AS mine
+    FROM discussion_threads t
+    JOIN users u ON t.user_id = u.id
+    LEFT JOIN discussion_posts p ON p.thread_id = t.id
+    LEFT JOIN discussion_reads r ON r.thread_id = t.id AND r.user_id = ?
+"#;
 
-    let courses = sqlx::query_as::<_, Course>("SELECT * FROM courses WHERE semester_id = ?")
-        .bind(course.semester_id)
-        .fetch_all(&mut **db)
-        .await
-        .unwrap_or_default();
+// Bind the current user's id, then whatever the appended WHERE needs
+const DISCUSSION_POSTS_QUERY: &str = "SELECT p.id, p.thread_id, u.username AS author, p.body, p.created_at, p.user_id = ? AS mine FROM discussion_posts p JOIN users u ON p.user_id = u.id";
 
-    let exams = sqlx::query_as::<_, Exam>("SELECT * FROM exams WHERE course_id = ? ORDER BY id DESC")
+async fn fetch_thread(db: &mut Connection<Db>, user_id: i64, id: i64) -> Option<DiscussionThread> {
+    sqlx::query_as::<_, DiscussionThread>(&format!("{} WHERE t.id = ? GROUP BY t.id", DISCUSSION_THREADS_QUERY))
+        .bind(user_id)
+        .bind(user_id)
         .bind(id)
-        .fetch_all(&mut **db)
+        .fetch_optional(&mut ***db)
         .await
-        .unwrap_or_default();
+        .unwrap_or(None)
+}
 
-    let categories = sqlx::query_as::<_, Category>("SELECT * FROM categories WHERE course_id = ?")
-        .bind(id)
-        .fetch_all(&mut **db)
+async fn mark_thread_read(db: &mut Connection<Db>, user_id: i64, thread_id: i64) {
+    sqlx::query(
+        r#"
+        INSERT INTO discussion_reads (user_id, thread_id, last_read_post_id)
+        VALUES (?, ?, (SELECT COALESCE(MAX(id), 0) FROM discussion_posts WHERE thread_id = ?))
+        ON CONFLICT (user_id, thread_id) DO UPDATE SET last_read_post_id = excluded.last_read_post_id
+        "#,
+    )
+    .bind(user_id)
+    .bind(thread_id)
+    .bind(thread_id)
+    .execute(&mut ***db)
+    .await
+    .unwrap();
+}
+
+async fn add_post(db: &mut Connection<Db>, user_id: i64, thread_id: i64, body: &str) -> i64 {
+    let id = sqlx::query("INSERT INTO discussion_posts (thread_id, user_id, body, created_at) VALUES (?, ?, ?, CURRENT_TIMESTAMP)")
+        .bind(thread_id)
+        .bind(user_id)
+        .bind(body)
+        .execute(&mut ***db)
         .await
-        .unwrap_or_default();
+        .unwrap()
+        .last_insert_rowid();
+    // Your own post never shows up as unread
+    mark_thread_read(db, user_id, thread_id).await;
+    id
+}
 
-    CourseExamsTemplate { course, courses, exams, semester, categories, user: Some(user) }
+// Whether `user_id` may delete something written by `author_id`
+async fn can_delete_discussion(db: &mut Connection<Db>, user_id: i64, author_id: i64) -> bool {
+    user_id == author_id || is_admin(db, user_id).await
 }
 
-#[post("/courses/<id>/exams", data = "<form>")]
-async fn create_exam(mut db: Connection<Db>, user: AuthUser, id: i64, form: Form<NewExam>) -> ExamItemTemplate {
-    let exam_id = sqlx::query("INSERT INTO exams (course_id, title, semester, link) VALUES (?, ?, ?, ?)")
+#[get("/courses/<id>/discussions")]
+async fn view_course_discussions(mut db: Connection<Db>, user: AuthUser, brand: Branding, id: i64) -> Option<CourseDiscussionsTemplate> {
+    let CourseContext { course, semester, courses } = queries::fetch_course_context(&mut db, id).await?;
+
+    let threads = sqlx::query_as::<_, DiscussionThread>(&format!(
+        "{} WHERE t.course_id = ? GROUP BY t.id ORDER BY COALESCE(MAX(p.id), 0) DESC, t.id DESC",
+        DISCUSSION_THREADS_QUERY
+    ))
+    .bind(user.id)
+    .bind(user.id)
+    .bind(id)
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_default();
+
+    Some(CourseDiscussionsTemplate { course, courses, semester, threads, user: Some(user), brand })
+}
+
+#[post("/courses/<id>/discussions", data = "<form>")]
+async fn create_thread(mut db: Connection<Db>, user: AuthUser, id: i64, form: Form<NewThread>) -> Result<DiscussionThreadRowTemplate, Status> {
+    let (title, body) = (form.title.trim(), form.body.trim());
+    if title.is_empty() || body.is_empty() {
+        return Err(Status::BadRequest);
+    }
+    queries::fetch_course(&mut db, id).await.ok_or(Status::NotFound)?;
+
+    let thread_id = sqlx::query("INSERT INTO discussion_threads (course_id, user_id, title, created_at) VALUES (?, ?, ?, CURRENT_TIMESTAMP)")
         .bind(id)
-        .bind(&form.title)
-        .bind(&form.semester)
-        .bind(&form.link)
+        .bind(user.id)
+        .bind(title)
         .execute(&mut **db)
         .await
         .unwrap()
         .last_insert_rowid();
+    add_post(&mut db, user.id, thread_id, body).await;
 
-    let exam = Exam {
-        id: exam_id,
-        course_id: id,
-        title: form.title.clone(),
-        semester: form.semester.clone(),
-        link: form.link.clone(),
-    };
+    let thread = fetch_thread(&mut db, user.id, thread_id).await.ok_or(Status::InternalServerError)?;
+    Ok(DiscussionThreadRowTemplate { thread })
+}
 
-    let categories = sqlx::query_as::<_, Category>("SELECT * FROM categories WHERE course_id = ?")
+#[get("/discussions/<id>")]
+async fn view_thread(mut db: Connection<Db>, user: AuthUser, brand: Branding, id: i64) -> Option<DiscussionThreadTemplate> {
+    let thread = fetch_thread(&mut db, user.id, id).await?;
+    let CourseContext { course, semester, courses } = queries::fetch_course_context(&mut db, thread.course_id).await?;
+
+    let posts = sqlx::query_as::<_, DiscussionPost>(&format!("{} WHERE p.thread_id = ? ORDER BY p.id", DISCUSSION_POSTS_QUERY))
+        .bind(user.id)
         .bind(id)
         .fetch_all(&mut **db)
         .await
         .unwrap_or_default();
+    mark_thread_read(&mut db, user.id, id).await;
 
-    ExamItemTemplate { exam, categories, user: Some(user) }
+    Some(DiscussionThreadTemplate { course, courses, semester, thread, posts, user: Some(user), brand })
 }
 
-#[get("/exams/<id>")]
-async fn get_exam(mut db: Connection<Db>, user: AuthUser, id: i64) -> ExamItemTemplate {
-    let exam = sqlx::query_as::<_, Exam>("SELECT * FROM exams WHERE id = ?")
-        .bind(id)
+#[post("/discussions/<id>/posts", data = "<form>")]
+async fn create_post(mut db: Connection<Db>, user: AuthUser, id: i64, form: Form<NewPost>) -> Result<DiscussionPostTemplate, Status> {
+    let body = form.body.trim();
+    if body.is_empty() {
+        return Err(Status::BadRequest);
+    }
+    fetch_thread(&mut db, user.id, id).await.ok_or(Status::NotFound)?;
+
+    let post_id = add_post(&mut db, user.id, id, body).await;
+    let post = sqlx::query_as::<_, DiscussionPost>(&format!("{} WHERE p.id = ?", DISCUSSION_POSTS_QUERY))
+        .bind(user.id)
+        .bind(post_id)
         .fetch_one(&mut **db)
         .await
         .unwrap();
+    Ok(DiscussionPostTemplate { post })
+}
 
-    let categories = sqlx::query_as::<_, Category>("SELECT * FROM categories WHERE course_id = ?")
-        .bind(exam.course_id)
-        .fetch_all(&mut **db)
+#[delete("/discussions/<id>")]
+async fn delete_thread(mut db: Connection<Db>, user: AuthUser, id: i64) -> Result<String, Status> {
+    let author_id: i64 = sqlx::query_scalar("SELECT user_id FROM discussion_threads WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut **db)
         .await
-        .unwrap_or_default();
-
-    ExamItemTemplate { exam, categories, user: Some(user) }
-}
+        .unwrap_or(None)
+        .ok_or(Status::NotFound)?;
+    if !can_delete_discussion(&mut db, user.id, author_id).await {
+        return Err(Status::Forbidden);
+    }
 
-#[get("/exams/<id>/edit")]
-async fn get_edit_exam(mut db: Connection<Db>, user: AuthUser, id: i64) -> ExamItemEditTemplate {
-    let exam = sqlx::query_as::<_, Exam>("SELECT * FROM exams WHERE id = ?")
+    // Posts and read markers go with it (ON DELETE CASCADE)
+    sqlx::query("DELETE FROM discussion_threads WHERE id = ?")
         .bind(id)
-        .fetch_one(&mut **db)
+        .execute(&mut **db)
         .await
         .unwrap();
-    ExamItemEditTemplate { exam, user: Some(user) }
+    Ok(String::new())
 }
 
-#[post("/exams/<id>", data = "<form>")]
-async fn update_exam(mut db: Connection<Db>, user: AuthUser, id: i64, form: Form<UpdateExam>) -> ExamItemTemplate {
-    sqlx::query("UPDATE exams SET title = ?, semester = ?, link = ? WHERE id = ?")
-        .bind(&form.title)
-        .bind(&form.semester)
-        .bind(&form.link)
+#[delete("/discussion_posts/<id>")]
+async fn delete_post(mut db: Connection<Db>, user: AuthUser, id: i64) -> Result<String, Status> {
+    let author_id: i64 = sqlx::query_scalar("SELECT user_id FROM discussion_posts WHERE id = ?")
         .bind(id)
-        .execute(&mut **db)
+        .fetch_optional(&mut **db)
         .await
-        .unwrap();
+        .unwrap_or(None)
+        .ok_or(Status::NotFound)?;
+    if !can_delete_discussion(&mut db, user.id, author_id).await {
+        return Err(Status::Forbidden);
+    }
 
-    let exam = sqlx::query_as::<_, Exam>("SELECT * FROM exams WHERE id = ?")
+    sqlx::query("DELETE FROM discussion_posts WHERE id = ?")
         .bind(id)
-        .fetch_one(&mut **db)
+        .execute(&mut **db)
         .await
         .unwrap();
+    Ok(String::new())
+}
 
-    let categories = sqlx::query_as::<_, Category>("SELECT * FROM categories WHERE course_id = ?")
-        .bind(exam.course_id)
-        .fetch_all(&mut **db)
+// ========== Digest Routes ==========
+//
+// Opting in to the weekly email (see digest.rs) and out of it again. The
+// settings page previews what this week's email would say. The unsubscribe
+// link only removes the subscription once its button is pressed, so mail
+// scanners that follow links don't unsubscribe anyone.
+
+async fn fetch_digest_subscription(db: &mut Connection<Db>, user_id: i64) -> Option<DigestSubscription> {
+    sqlx::query_as::<_, DigestSubscription>("SELECT * FROM digest_subscriptions WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_optional(&mut ***db)
         .await
-        .unwrap_or_default();
-
-    ExamItemTemplate { exam, categories, user: Some(user) }
+        .unwrap_or(None)
 }
 
-#[delete("/exams/<id>")]
-async fn delete_exam(mut db: Connection<Db>, _user: AuthUser, id: i64) -> String {
-    // Cascade delete: problem_categories -> problems -> exam
-    let problems = sqlx::query("SELECT id FROM problems WHERE exam_id = ?")
-        .bind(id)
-        .fetch_all(&mut **db)
+#[get("/digest")]
+async fn view_digest_settings(
+    mut db: Connection<Db>,
+    user: AuthUser,
+    brand: Branding,
+    config: &State<AppConfig>,
+    host: Option<&Host<'_>>,
+    flash: Option<FlashMessage<'_>>,
+) -> DigestSettingsTemplate {
+    let subscription = fetch_digest_subscription(&mut db, user.id).await;
+    let base_url = config.base_url(host.map(|h| h.to_string()).as_deref());
+    let token = subscription.as_ref().map_or("…", |s| s.unsubscribe_token.as_str());
+    let preview = Digest::build(&mut db, user.id, chrono::Local::now().date_naive())
         .await
-        .unwrap_or_default();
+        .render(&base_url, &digest::unsubscribe_url(&base_url, token));
+
+    let notice = flash.map(|f| (f.kind().to_string(), f.message().to_string()));
+    DigestSettingsTemplate {
+        subscription,
+        email_configured: email::is_configured(&config.email),
+        weekdays: WEEKDAYS,
+        hours: (0..24).collect(),
+        preview,
+        notice,
+        user: Some(user),
+        brand,
+    }
+}
 
-    for problem in problems {
-        let problem_id: i64 = problem.try_get("id").unwrap();
-        sqlx::query("DELETE FROM problem_categories WHERE problem_id = ?")
-            .bind(problem_id)
+#[post("/digest", data = "<form>")]
+async fn update_digest_settings(mut db: Connection<Db>, user: AuthUser, form: Form<DigestSettings>) -> Result<Flash<Redirect>, Status> {
+    if form.enabled.as_deref() != Some("on") {
+        sqlx::query("DELETE FROM digest_subscriptions WHERE user_id = ?")
+            .bind(user.id)
             .execute(&mut **db)
             .await
             .unwrap();
+        return Ok(Flash::success(Redirect::to("/digest"), "已关闭邮件周报。"));
+    }
+    if !(1..=7).contains(&form.weekday) || !(0..=23).contains(&form.hour) {
+        return Err(Status::BadRequest);
+    }
+    let address = form.email.trim();
+    if !email::is_valid_address(address) {
+        return Ok(Flash::error(Redirect::to("/digest"), "请输入有效的邮箱地址。"));
     }
 
-    sqlx::query("DELETE FROM problems WHERE exam_id = ?")
-        .bind(id)
+    // The token is kept across edits so links in earlier emails keep working
+    sqlx::query(
+        r#"
+        INSERT INTO digest_subscriptions (user_id, email, weekday, hour, unsubscribe_token) VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT (user_id) DO UPDATE SET email = excluded.email, weekday = excluded.weekday, hour = excluded.hour
+        "#,
+    )
+    .bind(user.id)
+    .bind(address)
+    .bind(form.weekday)
+    .bind(form.hour)
+    .bind(Uuid::new_v4().simple().to_string())
+    .execute(&mut **db)
+    .await
+    .unwrap();
+    Ok(Flash::success(Redirect::to("/digest"), "邮件周报设置已保存。"))
+}
+
+#[get("/digest/unsubscribe/<token>")]
+fn view_digest_unsubscribe(brand: Branding, token: String) -> DigestUnsubscribeTemplate {
+    DigestUnsubscribeTemplate { token, done: false, user: None, brand }
+}
+
+#[post("/digest/unsubscribe/<token>")]
+async fn digest_unsubscribe(mut db: Connection<Db>, brand: Branding, token: String) -> DigestUnsubscribeTemplate {
+    // An unknown token most likely means an earlier click already worked
+    sqlx::query("DELETE FROM digest_subscriptions WHERE unsubscribe_token = ?")
+        .bind(&token)
         .execute(&mut **db)
         .await
         .unwrap();
+    DigestUnsubscribeTemplate { token, done: true, user: None, brand }
+}
 
-    sqlx::query("DELETE FROM exams WHERE id = ?")
-        .bind(id)
+// ========== Account Routes ==========
+//
+// The account email (see account.rs). Like the digest unsubscribe link, the
+// verification link only acts once its button is pressed.
+
+#[get("/account")]
+async fn view_account(mut db: Connection<Db>, user: AuthUser, brand: Branding, config: &State<AppConfig>, host: Option<&Host<'_>>, flash: Option<FlashMessage<'_>>) -> Result<AccountTemplate, Status> {
+    let account = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?")
+        .bind(user.id)
+        .fetch_optional(&mut **db)
+        .await
+        .unwrap()
+        .ok_or(Status::NotFound)?;
+    let todos_token: Option<String> = sqlx::query_scalar("SELECT todos_token FROM users WHERE id = ?")
+        .bind(user.id)
+        .fetch_one(&mut **db)
+        .await
+        .unwrap_or(None);
+    let base_url = config.base_url(host.map(|h| h.to_string()).as_deref());
+    let todos_url = todos_token.map(|token| todos::feed_url(&base_url, &token));
+    let notice = flash.map(|f| (f.kind().to_string(), f.message().to_string()));
+    Ok(AccountTemplate { account, email_configured: email::is_configured(&config.email), todos_url, notice, user: Some(user), brand })
+}
+
+#[post("/account/email", data = "<form>")]
+async fn update_account_email(
+    mut db: Connection<Db>,
+    user: AuthUser,
+    config: &State<AppConfig>,
+    host: Option<&Host<'_>>,
+    form: Form<AccountEmail>,
+) -> Flash<Redirect> {
+    let address = form.email.trim();
+    if address.is_empty() {
+        sqlx::query("UPDATE users SET email = NULL, email_verified = 0, email_token = NULL WHERE id = ?")
+            .bind(user.id)
+            .execute(&mut **db)
+            .await
+            .unwrap();
+        return Flash::success(Redirect::to("/account"), "已移除邮箱。");
+    }
+    if !email::is_valid_address(address) {
+        return Flash::error(Redirect::to("/account"), "请输入有效的邮箱地址。");
+    }
+    let taken: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM users WHERE email = ? COLLATE NOCASE AND email_verified = 1 AND id != ?)")
+        .bind(address)
+        .bind(user.id)
+        .fetch_one(&mut **db)
+        .await
+        .unwrap_or(false);
+    if taken {
+        return Flash::error(Redirect::to("/account"), "这个邮箱已被其他账号使用。");
+    }
+
+    // Saving the same address again just sends a fresh link
+    sqlx::query("UPDATE users SET email = ?, email_verified = 0, email_token = ? WHERE id = ?")
+        .bind(address)
+        .bind(Uuid::new_v4().simple().to_string())
+        .bind(user.id)
         .execute(&mut **db)
         .await
         .unwrap();
+    let base_url = config.base_url(host.map(|h| h.to_string()).as_deref());
+    if account::queue_verification(&mut db, config, base_url, user.id).await {
+        Flash::success(Redirect::to("/account"), format!("验证邮件已发往 {}，打开其中的链接完成验证。", address))
+    } else {
+        Flash::error(Redirect::to("/account"), "邮箱已保存，但本站还没有配置发信服务器，暂时无法验证。")
+    }
+}
 
-    String::new()
+#[get("/account/verify/<token>")]
+fn view_account_verify(user: Option<AuthUser>, brand: Branding, token: String) -> AccountVerifyTemplate {
+    AccountVerifyTemplate { token, verified: None, user, brand }
 }
 
-#[post("/exams/<id>/problems", data = "<form>")]
-async fn create_exam_problem(mut db: Connection<Db>, user: AuthUser, id: i64, mut form: Form<NewProblem<'_>>) -> ProblemRowTemplate {
-    let file_name = format!("{}.png", Uuid::new_v4());
-    let file_path = format!("uploads/{}", file_name);
-    form.screenshot.move_copy_to(&file_path).await.expect("Unable to move or copy file");
-    let image_url = format!("/uploads/{}", file_name);
+#[post("/account/verify/<token>")]
+async fn account_verify(mut db: Connection<Db>, user: Option<AuthUser>, brand: Branding, token: String) -> AccountVerifyTemplate {
+    let verified = account::verify(&mut db, &token).await;
+    AccountVerifyTemplate { token, verified: Some(verified), user, brand }
+}
 
-    let description = "Screenshot Problem";
+// ========== Task Feed Routes ==========
+//
+// /todos.ics for task apps (see todos.rs). The account page makes the
+// token for its link; making a new one cuts off every app using the old.
 
-    let problem_id = sqlx::query("INSERT INTO problems (exam_id, description, notes, image_url, solution_link, is_incorrect) VALUES (?, ?, ?, ?, ?, 1)")
-        .bind(id)
-        .bind(description)
-        .bind(&form.notes)
-        .bind(&image_url)
-        .bind(&form.solution_link)
+#[post("/account/todos")]
+async fn reset_todos_token(mut db: Connection<Db>, user: AuthUser) -> Flash<Redirect> {
+    sqlx::query("UPDATE users SET todos_token = ? WHERE id = ?")
+        .bind(Uuid::new_v4().simple().to_string())
+        .bind(user.id)
         .execute(&mut **db)
         .await
-        .unwrap()
-        .last_insert_rowid();
+        .unwrap();
+    Flash::success(Redirect::to("/account"), "已生成新的待办订阅链接，之前的链接已失效。")
+}
 
-    let mut category_names = String::new();
-    if let Some(cats) = &form.categories {
-        let exam = sqlx::query_as::<_, Exam>("SELECT * FROM exams WHERE id = ?")
-            .bind(id)
-            .fetch_one(&mut **db)
+#[post("/account/todos/off")]
+async fn disable_todos_feed(mut db: Connection<Db>, user: AuthUser) -> Flash<Redirect> {
+    sqlx::query("UPDATE users SET todos_token = NULL WHERE id = ?")
+        .bind(user.id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+    Flash::success(Redirect::to("/account"), "已关闭待办订阅。")
+}
+
+#[get("/todos.ics?<token>")]
+async fn todos_feed(
+    mut db: Connection<Db>,
+    user: Option<AuthUser>,
+    config: &State<AppConfig>,
+    host: Option<&Host<'_>>,
+    token: Option<String>,
+) -> Result<(ContentType, String), Status> {
+    let user_id = match token {
+        Some(token) => sqlx::query_scalar::<_, i64>("SELECT id FROM users WHERE todos_token = ?")
+            .bind(token)
+            .fetch_optional(&mut **db)
             .await
-            .unwrap();
+            .unwrap_or(None)
+            .ok_or(Status::NotFound)?,
+        None => user.ok_or(Status::Unauthorized)?.id,
+    };
 
-        let mut processed_cats = Vec::new();
-        for cat_name in cats.split(|c| c == ',' || c == '\u{3001}').map(|s| s.trim()).filter(|s| !s.is_empty()) {
-            let cat_id_opt: Option<i64> = sqlx::query_scalar("SELECT id FROM categories WHERE course_id = ? AND name = ?")
-                .bind(exam.course_id)
-                .bind(cat_name)
-                .fetch_optional(&mut **db)
-                .await
-                .unwrap();
+    let todos = todos::build(&mut db, user_id, chrono::Local::now().date_naive()).await;
+    let site_name = settings::instance_name(&mut db).await;
+    let base_url = config.base_url(host.map(|h| h.to_string()).as_deref());
+    Ok((ContentType::Calendar, todos::render(&todos, &site_name, &base_url, chrono::Utc::now().naive_utc())))
+}
 
-            let cat_id = match cat_id_opt {
-                Some(cid) => cid,
-                None => {
-                    sqlx::query("INSERT INTO categories (course_id, name) VALUES (?, ?)")
-                        .bind(exam.course_id)
-                        .bind(cat_name)
-                        .execute(&mut **db)
-                        .await
-                        .unwrap()
-                        .last_insert_rowid()
-                }
-            };
+// ========== Report Routes ==========
 
-            sqlx::query("INSERT INTO problem_categories (problem_id, category_id) VALUES (?, ?)")
-                .bind(problem_id)
-                .bind(cat_id)
-                .execute(&mut **db)
-                .await
-                .unwrap();
+const REPORT_MARGIN: f32 = 50.0;
 
-            processed_cats.push(cat_name);
-        }
-        category_names = processed_cats.join(",");
+// Start a new page when fewer than `needed` points remain above the bottom margin
+fn report_ensure_space(doc: &mut PdfDoc, y: f32, needed: f32) -> f32 {
+    if y - needed < REPORT_MARGIN {
+        doc.new_page();
+        pdf::PAGE_HEIGHT - REPORT_MARGIN
+    } else {
+        y
     }
+}
 
-    let problem = ProblemWithCategories {
-        id: problem_id,
-        log_item_id: None,
-        exam_id: Some(id),
-        description: description.to_string(),
-        notes: form.notes.clone(),
-        image_url: Some(image_url),
-        solution_link: form.solution_link.clone(),
-        category_names: if category_names.is_empty() { None } else { Some(category_names) },
-        source_kind: "Exam".to_string(),
-        source_title: "".to_string(),
-    };
+// Horizontal bar chart: one labelled bar per row, scaled to the largest value
+fn report_bar_chart(doc: &mut PdfDoc, mut y: f32, title: &str, rows: &[(String, i64, String)]) -> f32 {
+    y = report_ensure_space(doc, y, 40.0);
+    doc.text(REPORT_MARGIN, y, 14.0, title);
+    y -= 22.0;
 
-    ProblemRowTemplate { problem, user: Some(user) }
-}
+    if rows.is_empty() {
+        doc.text(REPORT_MARGIN, y, 10.0, "暂无数据");
+        return y - 24.0;
+    }
 
-#[get("/exams/<id>/problems")]
-async fn get_exam_problems(mut db: Connection<Db>, _user: AuthUser, id: i64) -> String {
-    let problems = sqlx::query_as::<_, ProblemWithCategories>(
-        r#"
-        SELECT
-            p.id, p.log_item_id, p.exam_id, p.description, p.notes, p.image_url, p.solution_link,
-            GROUP_CONCAT(c.name) as category_names,
-            COALESCE(l.kind, 'Exam') as source_kind,
-            COALESCE(l.title, e.title, '') as source_title
-        FROM problems p
-        LEFT JOIN log_items l ON p.log_item_id = l.id
-        LEFT JOIN exams e ON p.exam_id = e.id
-        LEFT JOIN problem_categories pc ON p.id = pc.problem_id
-        LEFT JOIN categories c ON pc.category_id = c.id
-        WHERE p.exam_id = ?
-        GROUP BY p.id
-        "#
-    )
-    .bind(id)
-    .fetch_all(&mut **db)
-    .await
-    .unwrap_or_default();
+    let label_width = 150.0;
+    let max_bar = pdf::PAGE_WIDTH - REPORT_MARGIN * 2.0 - label_width - 90.0;
+    let max_value = rows.iter().map(|r| r.1).max().unwrap_or(1).max(1) as f32;
+    for (label, value, annotation) in rows {
+        y = report_ensure_space(doc, y, 16.0);
+        let mut label = label.clone();
+        while pdf::text_width(&label, 10.0) > label_width - 8.0 && label.chars().count() > 1 {
+            label.pop();
+        }
+        doc.text(REPORT_MARGIN, y, 10.0, &label);
+        let w = (*value as f32 / max_value * max_bar).max(1.0);
+        doc.rect(REPORT_MARGIN + label_width, y - 2.0, w, 10.0, 0.55);
+        doc.text(REPORT_MARGIN + label_width + w + 6.0, y, 9.0, annotation);
+        y -= 16.0;
+    }
+    y - 14.0
+}
 
-    let mut html = String::new();
-    for p in problems {
-        let t = ProblemRowTemplate { problem: p, user: None };
-        html.push_str(&t.render().unwrap());
+// Column chart of a count per day, one column for every day from the first
+// to the last so quiet stretches show as gaps
+fn report_daily_chart(doc: &mut PdfDoc, mut y: f32, title: &str, days: &BTreeMap<NaiveDate, i64>) -> f32 {
+    let height = 100.0;
+    y = report_ensure_space(doc, y, 40.0);
+    doc.text(REPORT_MARGIN, y, 14.0, title);
+    y -= 22.0;
+
+    let (Some((&first, _)), Some((&last, _))) = (days.first_key_value(), days.last_key_value()) else {
+        doc.text(REPORT_MARGIN, y, 10.0, "暂无数据");
+        return y - 24.0;
+    };
+    y = report_ensure_space(doc, y, height + 40.0);
+    let total: i64 = days.values().sum();
+    let max_value = days.values().copied().max().unwrap_or(1).max(1);
+    doc.text(REPORT_MARGIN, y, 9.0, &format!("共 {} 次 · {} 天 · 单日最多 {} 次", total, days.len(), max_value));
+    y -= 8.0;
+
+    let base = y - height;
+    let width = pdf::PAGE_WIDTH - REPORT_MARGIN * 2.0;
+    let column = width / ((last - first).num_days() + 1) as f32;
+    for (date, value) in days {
+        let x = REPORT_MARGIN + (*date - first).num_days() as f32 * column;
+        let h = (*value as f32 / max_value as f32 * height).max(1.0);
+        doc.rect(x, base, (column * 0.8).max(0.5), h, 0.55);
     }
-    html
+    doc.rect(REPORT_MARGIN, base - 1.0, width, 0.5, 0.0);
+    doc.text(REPORT_MARGIN, base - 12.0, 9.0, &first.format("%m-%d").to_string());
+    let last_label = last.format("%m-%d").to_string();
+    doc.text(REPORT_MARGIN + width - pdf::text_width(&last_label, 9.0), base - 12.0, 9.0, &last_label);
+    base - 36.0
 }
 
-// ========== Course Settings Routes ==========
+fn incorrect_annotation(total: i64, incorrect: i64) -> String {
+    if total == 0 {
+        "0".to_string()
+    } else {
+        format!("{}  (错 {}%)", total, incorrect * 100 / total)
+    }
+}
 
-#[get("/courses/<id>/settings")]
-async fn view_course_settings(mut db: Connection<Db>, user: AuthUser, id: i64) -> CourseSettingsTemplate {
-    let course = sqlx::query_as::<_, Course>("SELECT * FROM courses WHERE id = ?")
-        .bind(id)
-        .fetch_one(&mut **db)
-        .await
-        .unwrap();
+#[get("/courses/<id>/report.pdf")]
+async fn course_report_pdf(mut db: Connection<Db>, user: AuthUser, id: i64) -> Result<(ContentType, Vec<u8>), Status> {
+    let course = queries::fetch_course(&mut db, id).await.ok_or(Status::NotFound)?;
 
     let semester = sqlx::query_as::<_, Semester>("SELECT * FROM semesters WHERE id = ?")
         .bind(course.semester_id)
@@ -1210,112 +5661,250 @@ async fn view_course_settings(mut db: Connection<Db>, user: AuthUser, id: i64) -
         .await
         .unwrap();
 
-    let courses = sqlx::query_as::<_, Course>("SELECT * FROM courses WHERE semester_id = ?")
-        .bind(course.semester_id)
-        .fetch_all(&mut **db)
-        .await
-        .unwrap_or_default();
-
-    CourseSettingsTemplate { course, courses, semester, user: Some(user) }
-}
+    let log_counts = sqlx::query_as::<_, (String, i64)>(
+        "SELECT kind, COUNT(*) FROM log_items WHERE course_id = ? GROUP BY kind ORDER BY COUNT(*) DESC"
+    )
+    .bind(id)
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_default();
 
-#[post("/courses/<id>/settings", data = "<form>")]
-async fn update_course_settings(mut db: Connection<Db>, _user: AuthUser, id: i64, form: Form<CourseSettings>) -> Redirect {
-    let is_published = form.is_published.as_deref() == Some("on");
-    let show_lecture_links = form.show_lecture_links.as_deref() == Some("on");
-    let slug = form.public_slug.as_deref()
-        .map(|s| s.trim())
-        .filter(|s| !s.is_empty())
-        .map(|s| s.to_string());
+    let source_counts = sqlx::query_as::<_, (String, i64, i64)>(
+        r#"
+        SELECT COALESCE(l.kind, 'Exam') as kind, COUNT(*), SUM(p.is_incorrect)
+        FROM problems p
+        LEFT JOIN log_items l ON p.log_item_id = l.id
+        LEFT JOIN exams e ON p.exam_id = e.id
+        WHERE (l.course_id = ? OR e.course_id = ?)
+        GROUP BY kind
+        ORDER BY COUNT(*) DESC
+        "#
+    )
+    .bind(id)
+    .bind(id)
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_default();
 
-    sqlx::query("UPDATE courses SET is_published = ?, public_slug = ?, show_lecture_links = ? WHERE id = ?")
-        .bind(is_published)
-        .bind(&slug)
-        .bind(show_lecture_links)
-        .bind(id)
-        .execute(&mut **db)
-        .await
-        .unwrap();
+    let category_counts = sqlx::query_as::<_, (String, i64, i64)>(
+        r#"
+        SELECT c.name, COUNT(p.id), SUM(p.is_incorrect)
+        FROM categories c
+        JOIN problem_categories pc ON pc.category_id = c.id
+        JOIN problems p ON p.id = pc.problem_id
+        WHERE c.course_id = ?
+        GROUP BY c.id
+        ORDER BY COUNT(p.id) DESC
+        LIMIT 20
+        "#
+    )
+    .bind(id)
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_default();
 
-    Redirect::to(format!("/courses/{}/settings", id))
-}
+    // Date each problem by its log item, falling back to when it was added
+    let dated_problems = sqlx::query_as::<_, (Option<String>, bool)>(
+        r#"
+        SELECT COALESCE(l.date, date(p.created_at)), p.is_incorrect
+        FROM problems p
+        LEFT JOIN log_items l ON p.log_item_id = l.id
+        LEFT JOIN exams e ON p.exam_id = e.id
+        WHERE (l.course_id = ? OR e.course_id = ?)
+        "#
+    )
+    .bind(id)
+    .bind(id)
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_default();
 
-#[post("/courses/<id>/translate")]
-async fn translate_course(mut db: Connection<Db>, _user: AuthUser, id: i64) -> String {
-    let course = sqlx::query_as::<_, Course>("SELECT * FROM courses WHERE id = ?")
-        .bind(id)
-        .fetch_one(&mut **db)
-        .await
-        .unwrap();
+    let mut weekly: BTreeMap<NaiveDate, (i64, i64)> = BTreeMap::new();
+    for (date, is_incorrect) in &dated_problems {
+        if let Some(date) = date.as_deref().and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok()) {
+            let monday = date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64);
+            let entry = weekly.entry(monday).or_insert((0, 0));
+            entry.0 += 1;
+            if *is_incorrect {
+                entry.1 += 1;
+            }
+        }
+    }
 
-    let course_context = format!("{} {}", course.code, course.title);
+    // The reader's own reviews of the course's problems
+    let daily_reviews: BTreeMap<NaiveDate, i64> = sqlx::query_as::<_, (String, i64)>(
+        r#"
+        SELECT date(r.reviewed_at) AS day, COUNT(*)
+        FROM study_reviews r
+        JOIN problem_sources src ON src.problem_id = r.problem_id
+        WHERE r.user_id = ? AND src.course_id = ?
+        GROUP BY day
+        "#
+    )
+    .bind(user.id)
+    .bind(id)
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_default()
+    .into_iter()
+    .filter_map(|(day, n)| Some((NaiveDate::parse_from_str(&day, "%Y-%m-%d").ok()?, n)))
+    .collect();
+
+    let total_logs: i64 = log_counts.iter().map(|r| r.1).sum();
+    let total_problems: i64 = source_counts.iter().map(|r| r.1).sum();
+    let total_incorrect: i64 = source_counts.iter().map(|r| r.2).sum();
+
+    let mut doc = PdfDoc::new();
+    let mut y = pdf::PAGE_HEIGHT - REPORT_MARGIN;
+    doc.text(REPORT_MARGIN, y, 20.0, &format!("{} 学期报告", course.code));
+    y -= 22.0;
+    doc.text(REPORT_MARGIN, y, 11.0, &format!("{} · {} · 生成于 {}", course.title, semester.name, chrono::Local::now().format("%Y-%m-%d")));
+    y -= 30.0;
+    doc.text(REPORT_MARGIN, y, 12.0, &format!("记录 {} 条 · 错题 {} 道 · {}", total_logs, total_problems, incorrect_annotation(total_problems, total_incorrect)));
+    y -= 36.0;
+
+    let rows: Vec<(String, i64, String)> = log_counts
+        .iter()
+        .map(|(kind, n)| (kind_label_zh(kind).to_string(), *n, n.to_string()))
+        .collect();
+    y = report_bar_chart(&mut doc, y, "记录类型", &rows);
 
-    // Collect all texts that need LLM translation
-    let mut texts_to_translate: Vec<String> = Vec::new();
+    let rows: Vec<(String, i64, String)> = source_counts
+        .iter()
+        .map(|(kind, n, wrong)| (kind_label_zh(kind).to_string(), *n, incorrect_annotation(*n, *wrong)))
+        .collect();
+    y = report_bar_chart(&mut doc, y, "错题来源", &rows);
 
-    // Log item descriptions
-    let log_items = sqlx::query_as::<_, LogItem>("SELECT * FROM log_items WHERE course_id = ?")
-        .bind(id)
-        .fetch_all(&mut **db)
-        .await
-        .unwrap_or_default();
+    let rows: Vec<(String, i64, String)> = category_counts
+        .iter()
+        .map(|(name, n, wrong)| (name.clone(), *n, incorrect_annotation(*n, *wrong)))
+        .collect();
+    y = report_bar_chart(&mut doc, y, "分类错题数", &rows);
 
-    for item in &log_items {
-        if let Some(desc) = &item.description {
-            if !desc.is_empty() {
-                texts_to_translate.push(desc.clone());
-            }
-        }
-    }
+    let rows: Vec<(String, i64, String)> = weekly
+        .iter()
+        .map(|(monday, (n, wrong))| (monday.format("%m-%d 周").to_string(), *n, incorrect_annotation(*n, *wrong)))
+        .collect();
+    y = report_bar_chart(&mut doc, y, "每周新增错题", &rows);
 
-    // Category names
-    let categories = sqlx::query_as::<_, Category>("SELECT * FROM categories WHERE course_id = ?")
-        .bind(id)
-        .fetch_all(&mut **db)
-        .await
-        .unwrap_or_default();
+    report_daily_chart(&mut doc, y, "每日复习", &daily_reviews);
 
-    for cat in &categories {
-        texts_to_translate.push(cat.name.clone());
-    }
+    Ok((ContentType::PDF, doc.to_bytes()))
+}
 
-    // Problem notes
-    let problems = sqlx::query_as::<_, Problem>(
-        "SELECT p.* FROM problems p LEFT JOIN log_items l ON p.log_item_id = l.id LEFT JOIN exams e ON p.exam_id = e.id WHERE l.course_id = ? OR e.course_id = ?"
-    )
-        .bind(id)
-        .bind(id)
-        .fetch_all(&mut **db)
-        .await
-        .unwrap_or_default();
+// ========== Upload Routes ==========
+//
+// Uploads go through this route rather than a bare FileServer, so knowing a URL
+// is not enough to fetch the file. A file is served to whoever can see what it
+// belongs to: a problem's screenshot (or a deleted one's, still in its edit
+// history) or a course banner to the people who can open the course, and to
+// anyone once the course is published; the site logo to everyone. Anything
+// else is a 404.
+
+async fn upload_is_readable(db: &mut Connection<Db>, user_id: Option<i64>, url: &str) -> bool {
+    // A NULL user id matches no course, leaving only what's published
+    sqlx::query_scalar::<_, bool>(&format!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM problems p
+            LEFT JOIN log_items l ON p.log_item_id = l.id
+            LEFT JOIN exams e ON p.exam_id = e.id
+            JOIN courses c ON c.id = COALESCE(l.course_id, e.course_id)
+            WHERE p.image_url = ? AND (c.is_published = 1 OR c.id IN ({courses}))
+        ) OR EXISTS (
+            SELECT 1 FROM courses c WHERE c.banner_url = ? AND (c.is_published = 1 OR c.id IN ({courses}))
+        ) OR EXISTS (
+            SELECT 1 FROM entity_history h
+            WHERE h.entity_type = 'problem' AND json_extract(h.before_json, '$.image_url') = ? AND h.course_id IN ({courses})
+        ) OR EXISTS (
+            SELECT 1 FROM instance_settings WHERE key = ? AND value = ?
+        )
+        "#,
+        courses = access::COURSES
+    ))
+    .bind(url)
+    .bind(user_id)
+    .bind(url)
+    .bind(user_id)
+    .bind(url)
+    .bind(user_id)
+    .bind(settings::LOGO_URL)
+    .bind(url)
+    .fetch_one(&mut ***db)
+    .await
+    .unwrap_or(false)
+}
 
-    for problem in &problems {
-        if let Some(notes) = &problem.notes {
-            if !notes.is_empty() {
-                texts_to_translate.push(notes.clone());
-            }
-        }
+// Images and PDFs are shown as the type their bytes say they are; anything
+// else is only ever downloaded, and sandboxed should a browser render it anyway
+struct UploadFile {
+    file: NamedFile,
+    inline_type: Option<ContentType>,
+    filename: Option<String>,
+    download: bool,
+}
+
+impl<'r> Responder<'r, 'static> for UploadFile {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let download = self.download || self.inline_type.is_none();
+        let mut response = self.file.respond_to(request)?;
+        match self.inline_type {
+            Some(content_type) => response.set_header(content_type),
+            None => response.set_header(Header::new("Content-Security-Policy", "default-src 'none'; sandbox")),
+        };
+        response.set_header(Header::new("Content-Disposition", uploads::content_disposition(self.filename.as_deref(), download)));
+        response.set_header(Header::new("X-Content-Type-Options", "nosniff"));
+        Ok(response)
     }
+}
 
-    // Exam titles
-    let exams = sqlx::query_as::<_, Exam>("SELECT * FROM exams WHERE course_id = ?")
-        .bind(id)
-        .fetch_all(&mut **db)
-        .await
-        .unwrap_or_default();
+// `?download` asks for an attachment; either way the original filename is used when known.
+// `?w=&h=&crop` asks for a resized copy of an image (see uploads::variant).
+#[get("/uploads/<path..>?<download>&<w>&<h>&<crop>")]
+async fn serve_upload(
+    mut db: Connection<Db>,
+    user: Option<AuthUser>,
+    path: Result<PathBuf, PathError>,
+    download: bool,
+    w: Option<u32>,
+    h: Option<u32>,
+    crop: bool,
+) -> Result<UploadFile, Status> {
+    // Only files that really live under the upload directory; see uploads::resolve
+    let path = path.map_err(|_| Status::NotFound)?;
+    let root = uploads::dir(&mut db).await;
+    let (path, original) = match uploads::resolve(&root, &path).await {
+        Some(original) => (path, original),
+        // An old link to a file `zhixi shard-uploads` has since moved
+        None => {
+            let sharded = uploads::legacy_shard(&path).ok_or(Status::NotFound)?;
+            let original = uploads::resolve(&root, &sharded).await.ok_or(Status::NotFound)?;
+            (sharded, original)
+        }
+    };
 
-    for exam in &exams {
-        texts_to_translate.push(exam.title.clone());
+    let url = format!("/uploads/{}", path.to_string_lossy());
+    if !upload_is_readable(&mut db, user.map(|u| u.id), &url).await {
+        return Err(Status::NotFound);
     }
 
-    if texts_to_translate.is_empty() {
-        return "<span class=\"text-green-400\">No content to translate.</span>".to_string();
-    }
+    let inline_type = uploads::inline_type(&original).await;
+    let file_path = match uploads::Resize::new(w, h, crop) {
+        Some(resize) => uploads::variant(&root, &path, &resize).await,
+        None => original,
+    };
+    let file = NamedFile::open(file_path)
+        .await
+        .map_err(|_| Status::NotFound)?;
 
-    let results = translate::translate_batch(&mut db, &texts_to_translate, &course_context).await;
-    let total = results.len();
+    // Deduplicated files can back several problems; any of their names will do
+    let filename: Option<String> = sqlx::query_scalar("SELECT original_filename FROM problems WHERE image_url = ? AND original_filename IS NOT NULL LIMIT 1")
+        .bind(&url)
+        .fetch_optional(&mut **db)
+        .await
+        .unwrap_or(None);
 
-    format!("<span class=\"text-green-400\">Translated {} items successfully.</span>", total)
+    Ok(UploadFile { file, inline_type, filename, download })
 }
 
 // ========== Public Routes ==========
@@ -1335,6 +5924,8 @@ fn build_calendar(
     show_lecture_links: bool,
     translations: &std::collections::HashMap<String, String>,
     translate_titles: bool,
+    mark_untranslated: bool,
+    kind_order: &[LogItemKind],
 ) -> (Vec<CalendarWeek>, Vec<PublicLogItem>, Vec<String>) {
     let to_public = |item: &LogItem| -> PublicLogItem {
         let title = if translate_titles {
@@ -1349,19 +5940,23 @@ fn build_calendar(
                 Some(d.clone())
             }
         });
+        let description_key = item.description.as_ref()
+            .filter(|d| mark_untranslated && !d.is_empty() && !translations.contains_key(*d))
+            .map(|d| translate::text_key(d));
         let link = filter_public_link(&item.link, &item.kind, show_lecture_links);
         PublicLogItem {
-            id: item.id,
+            public_id: item.public_id.clone(),
             kind: item.kind.clone(),
             title,
             description,
             date: item.date.clone(),
             link,
+            description_key,
         }
     };
 
     let (dated, undated): (Vec<_>, Vec<_>) = log_items.iter().partition(|i| {
-        i.date.as_ref().map_or(false, |d| !d.is_empty())
+        i.date.as_ref().is_some_and(|d| !d.is_empty())
     });
 
     let unscheduled: Vec<PublicLogItem> = undated.iter().map(|i| to_public(i)).collect();
@@ -1370,7 +5965,7 @@ fn build_calendar(
         return (vec![], unscheduled, vec![]);
     }
 
-    // Parse dates and find epoch
+    // Parse dates
     let mut dated_with_dates: Vec<(&LogItem, NaiveDate)> = Vec::new();
     for item in &dated {
         if let Some(date_str) = &item.date {
@@ -1380,15 +5975,12 @@ fn build_calendar(
         }
     }
 
-    if dated_with_dates.is_empty() {
+    let Some(epoch_monday) = first_week_monday(&log_items) else {
         return (vec![], unscheduled, vec![]);
-    }
+    };
 
     dated_with_dates.sort_by_key(|(_, d)| *d);
 
-    let epoch = dated_with_dates[0].1;
-    let epoch_monday = epoch - chrono::Duration::days(epoch.weekday().num_days_from_monday() as i64);
-
     // Bucket by week
     let mut weeks_map: BTreeMap<u32, std::collections::HashMap<String, Vec<PublicLogItem>>> = BTreeMap::new();
     let mut kind_counts: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
@@ -1410,10 +6002,11 @@ fn build_calendar(
     }
 
     // Determine which kinds have items (for column visibility)
-    let active_kinds: Vec<String> = ALL_KINDS
+    // Disabled kinds still show here if the course already has items of that kind
+    let active_kinds: Vec<String> = kind_order
         .iter()
-        .filter(|k| kind_counts.contains_key(**k))
-        .map(|k| k.to_string())
+        .filter(|k| kind_counts.contains_key(&k.kind))
+        .map(|k| k.kind.clone())
         .collect();
 
     // Map kind to canonical name for non-standard kinds
@@ -1445,15 +6038,18 @@ fn build_calendar(
 }
 
 #[get("/p/<slug>")]
-async fn public_course_calendar(mut db: Connection<Db>, slug: String) -> Result<PublicCalendarTemplate, Status> {
-    let course = sqlx::query_as::<_, Course>(
-        "SELECT * FROM courses WHERE public_slug = ? AND is_published = 1"
-    )
-    .bind(&slug)
-    .fetch_optional(&mut **db)
-    .await
-    .unwrap_or(None)
-    .ok_or(Status::NotFound)?;
+async fn public_course_calendar(mut db: Connection<Db>, cache: &State<PublicPageCache>, config: &State<AppConfig>, brand: Branding, since: IfModifiedSince, slug: String) -> Result<PublicPage, Status> {
+    let path = format!("/p/{}", slug);
+    let last_modified = public_cache::course_last_modified(&mut db, &slug).await;
+    cache.serve(&path, config.public_cache_ttl(), &since, last_modified, || async move {
+        let page = public_calendar_page(db, brand, slug).await?;
+        let html = page.render().map_err(|_| Status::InternalServerError)?;
+        Ok((html, !page.pending_translations))
+    }).await
+}
+
+async fn public_calendar_page(mut db: Connection<Db>, brand: Branding, slug: String) -> Result<PublicCalendarTemplate, Status> {
+    let course = queries::fetch_published_course(&mut db, &slug).await.ok_or(Status::NotFound)?;
 
     let log_items = sqlx::query_as::<_, LogItem>(
         "SELECT * FROM log_items WHERE course_id = ? ORDER BY date ASC, id ASC"
@@ -1481,44 +6077,32 @@ async fn public_course_calendar(mut db: Connection<Db>, slug: String) -> Result<
         }
     }
 
-    let (weeks, unscheduled, active_kinds) = build_calendar(log_items, course.show_lecture_links, &translations, true);
+    // Untranslated descriptions are filled in after the page loads
+    let pending_translations = desc_texts.iter().any(|d| !translations.contains_key(d))
+        && settings::translation_api_key(&mut db).await.is_some();
+
+    let kinds = course_log_item_kinds(&mut db, course.id).await;
+    let (weeks, unscheduled, active_kinds) = build_calendar(log_items, course.show_lecture_links, &translations, true, pending_translations, &kinds);
 
     let base_path = format!("/p/{}", course.public_slug.as_deref().unwrap_or(""));
-    Ok(PublicCalendarTemplate { course, weeks, unscheduled, active_kinds, lang: "en".to_string(), base_path })
+    Ok(PublicCalendarTemplate { course, weeks, unscheduled, active_kinds, lang: "en".to_string(), base_path, pending_translations, brand })
 }
 
 #[get("/p/<slug>/problems")]
-async fn public_course_problems(mut db: Connection<Db>, slug: String) -> Result<PublicProblemsTemplate, Status> {
-    let course = sqlx::query_as::<_, Course>(
-        "SELECT * FROM courses WHERE public_slug = ? AND is_published = 1"
-    )
-    .bind(&slug)
-    .fetch_optional(&mut **db)
-    .await
-    .unwrap_or(None)
-    .ok_or(Status::NotFound)?;
+async fn public_course_problems(mut db: Connection<Db>, cache: &State<PublicPageCache>, config: &State<AppConfig>, brand: Branding, since: IfModifiedSince, slug: String) -> Result<PublicPage, Status> {
+    let path = format!("/p/{}/problems", slug);
+    let last_modified = public_cache::course_last_modified(&mut db, &slug).await;
+    cache.serve(&path, config.public_cache_ttl(), &since, last_modified, || async move {
+        let page = public_problems_page(db, brand, slug).await?;
+        let html = page.render().map_err(|_| Status::InternalServerError)?;
+        Ok((html, !page.pending_translations))
+    }).await
+}
 
-    let raw_problems = sqlx::query_as::<_, ProblemWithCategories>(
-        r#"
-        SELECT
-            p.id, p.log_item_id, p.exam_id, p.description, p.notes, p.image_url, p.solution_link,
-            GROUP_CONCAT(c.name) as category_names,
-            COALESCE(l.kind, 'Exam') as source_kind,
-            COALESCE(l.title, e.title, '') as source_title
-        FROM problems p
-        LEFT JOIN log_items l ON p.log_item_id = l.id
-        LEFT JOIN exams e ON p.exam_id = e.id
-        LEFT JOIN problem_categories pc ON p.id = pc.problem_id
-        LEFT JOIN categories c ON pc.category_id = c.id
-        WHERE (l.course_id = ? OR e.course_id = ?)
-        GROUP BY p.id
-        "#
-    )
-    .bind(course.id)
-    .bind(course.id)
-    .fetch_all(&mut **db)
-    .await
-    .unwrap_or_default();
+async fn public_problems_page(mut db: Connection<Db>, brand: Branding, slug: String) -> Result<PublicProblemsTemplate, Status> {
+    let course = queries::fetch_published_course(&mut db, &slug).await.ok_or(Status::NotFound)?;
+
+    let raw_problems = queries::fetch_course_problems(&mut db, course.id).await;
 
     // Collect texts for cache lookup: notes, category names, source titles
     let mut texts_to_lookup: Vec<String> = Vec::new();
@@ -1549,6 +6133,10 @@ async fn public_course_problems(mut db: Connection<Db>, slug: String) -> Result<
         }
     }
 
+    // Untranslated notes are filled in after the page loads
+    let pending_translations = raw_problems.iter().filter_map(|p| p.notes.as_ref()).any(|n| !n.is_empty() && !t_map.contains_key(n))
+        && settings::translation_api_key(&mut db).await.is_some();
+
     let mut all_categories_set: std::collections::HashSet<String> = std::collections::HashSet::new();
 
     let problems: Vec<PublicProblem> = raw_problems.iter().map(|p| {
@@ -1556,6 +6144,9 @@ async fn public_course_problems(mut db: Connection<Db>, slug: String) -> Result<
         let notes = p.notes.as_ref().and_then(|n| {
             if n.is_empty() { None } else { Some(t_map.get(n).cloned().unwrap_or_else(|| n.clone())) }
         });
+        let notes_key = p.notes.as_ref()
+            .filter(|n| pending_translations && !n.is_empty() && !t_map.contains_key(*n))
+            .map(|n| translate::text_key(n));
 
         // Translate category names
         let category_names = p.category_names.as_ref().map(|cats| {
@@ -1589,13 +6180,18 @@ async fn public_course_problems(mut db: Connection<Db>, slug: String) -> Result<
         });
 
         PublicProblem {
-            id: p.id,
+            public_id: p.public_id.clone(),
+            number: p.number,
             image_url: p.image_url.clone(),
+            body: p.body.clone(),
             notes,
             category_names,
             source_kind: p.source_kind.clone(),
             source_title,
             solution_link,
+            original_filename: p.original_filename.clone(),
+            mime_type: p.mime_type.clone(),
+            notes_key,
         }
     }).collect();
 
@@ -1603,62 +6199,148 @@ async fn public_course_problems(mut db: Connection<Db>, slug: String) -> Result<
     all_categories.sort();
 
     let base_path = format!("/p/{}", course.public_slug.as_deref().unwrap_or(""));
-    Ok(PublicProblemsTemplate { course, problems, all_categories, lang: "en".to_string(), base_path })
+    Ok(PublicProblemsTemplate { course, problems, all_categories, lang: "en".to_string(), base_path, pending_translations, brand })
 }
 
-// ========== Public Routes (Chinese / untranslated) ==========
+async fn published_course_by_public_id(db: &mut Connection<Db>, public_id: &str) -> Result<Course, Status> {
+    queries::fetch_course_by_public_id(db, public_id)
+        .await
+        .filter(|course| course.is_published)
+        .ok_or(Status::NotFound)
+}
 
-#[get("/p/<slug>/zh")]
-async fn public_course_calendar_zh(mut db: Connection<Db>, slug: String) -> Result<PublicCalendarTemplate, Status> {
-    let course = sqlx::query_as::<_, Course>(
-        "SELECT * FROM courses WHERE public_slug = ? AND is_published = 1"
-    )
-    .bind(&slug)
-    .fetch_optional(&mut **db)
-    .await
-    .unwrap_or(None)
-    .ok_or(Status::NotFound)?;
+// Public pages call this on load when they show strings still waiting for a
+// translation. It only queues the work and hands back the poller.
+#[post("/courses/<public_id>/translate/missing")]
+async fn translate_missing(mut db: Connection<Db>, public_id: &str) -> Result<TranslationPollTemplate, Status> {
+    let course = published_course_by_public_id(&mut db, public_id).await?;
+    translate::queue_missing(&mut db, course.id).await;
+    Ok(TranslationPollTemplate { public_id: course.public_id })
+}
 
-    let log_items = sqlx::query_as::<_, LogItem>(
-        "SELECT * FROM log_items WHERE course_id = ? ORDER BY date ASC, id ASC"
+// Nothing (204) while the job is queued; once it has run, the course's
+// translations as out-of-band swaps, with htmx's 286 to stop polling.
+#[get("/courses/<public_id>/translate/missing")]
+async fn poll_missing_translations(mut db: Connection<Db>, public_id: &str) -> Result<(Status, TranslationFillsTemplate), Status> {
+    let course = published_course_by_public_id(&mut db, public_id).await?;
+    if translate::missing_pending(&mut db, course.id).await {
+        return Err(Status::NoContent);
+    }
+
+    let mut texts = translate::course_texts(&mut db, course.id).await;
+    texts.sort();
+    texts.dedup();
+    let cached = translate::lookup_cached_translations(&mut db, &texts).await;
+    let fills = texts
+        .iter()
+        .zip(cached)
+        .filter_map(|(text, translation)| Some((translate::text_key(text), translation?)))
+        .collect();
+    Ok((Status::new(286), TranslationFillsTemplate { fills }))
+}
+
+// A semester's wrapped summary, once published. Shared by link only: it
+// carries noindex and stays out of the sitemap.
+#[get("/wrapped/<slug>")]
+async fn public_semester_wrapped(mut db: Connection<Db>, cache: &State<PublicPageCache>, config: &State<AppConfig>, brand: Branding, since: IfModifiedSince, slug: String) -> Result<PublicPage, Status> {
+    let path = format!("/wrapped/{}", slug);
+    let last_modified = public_cache::semester_last_modified(&mut db, &slug).await;
+    cache.serve(&path, config.public_cache_ttl(), &since, last_modified, || async move {
+        let semester = sqlx::query_as::<_, Semester>("SELECT * FROM semesters WHERE wrapped_slug = ? AND wrapped_published = 1")
+            .bind(&slug)
+            .fetch_optional(&mut **db)
+            .await
+            .unwrap_or(None)
+            .ok_or(Status::NotFound)?;
+        let wrapped = wrapped::collect(&mut db, semester.id).await;
+        let html = PublicWrappedTemplate { semester, wrapped, brand }.render().map_err(|_| Status::InternalServerError)?;
+        Ok((html, true))
+    }).await
+}
+
+// Every page of each published course, except those whose owner asked
+// search engines to stay away
+#[get("/sitemap.xml")]
+async fn sitemap(mut db: Connection<Db>, config: &State<AppConfig>, host: Option<&Host<'_>>) -> (ContentType, String) {
+    let slugs: Vec<String> = sqlx::query_scalar(
+        "SELECT public_slug FROM courses WHERE is_published = 1 AND discourage_indexing = 0 AND public_slug IS NOT NULL ORDER BY public_slug"
     )
-    .bind(course.id)
     .fetch_all(&mut **db)
     .await
     .unwrap_or_default();
 
-    let empty_translations = std::collections::HashMap::new();
-    let (weeks, unscheduled, active_kinds) = build_calendar(log_items, course.show_lecture_links, &empty_translations, false);
+    let host = host.map(|h| h.to_string());
+    let base_url = config.base_url(host.as_deref());
+    let mut urls = String::new();
+    for slug in &slugs {
+        for page in ["", "/problems", "/zh", "/zh/problems"] {
+            let loc = format!("{}/p/{}{}", base_url, slug, page);
+            urls.push_str(&format!("<url><loc>{}</loc></url>\n", xml_escape(&loc)));
+        }
+    }
 
-    let base_path = format!("/p/{}/zh", course.public_slug.as_deref().unwrap_or(""));
-    Ok(PublicCalendarTemplate { course, weeks, unscheduled, active_kinds, lang: "zh".to_string(), base_path })
+    let xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n{}</urlset>\n",
+        urls
+    );
+    (ContentType::XML, xml)
 }
 
-#[get("/p/<slug>/zh/problems")]
-async fn public_course_problems_zh(mut db: Connection<Db>, slug: String) -> Result<PublicProblemsTemplate, Status> {
-    let course = sqlx::query_as::<_, Course>(
-        "SELECT * FROM courses WHERE public_slug = ? AND is_published = 1"
-    )
-    .bind(&slug)
-    .fetch_optional(&mut **db)
-    .await
-    .unwrap_or(None)
-    .ok_or(Status::NotFound)?;
+// Crawlers may read public pages, which is also how they see a discouraged
+// course's noindex; everything else is behind a login anyway
+#[get("/robots.txt")]
+fn robots_txt(config: &State<AppConfig>, host: Option<&Host<'_>>) -> String {
+    let host = host.map(|h| h.to_string());
+    format!("User-agent: *\nAllow: /p/\nDisallow: /\n\nSitemap: {}/sitemap.xml\n", config.base_url(host.as_deref()))
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
 
-    let raw_problems = sqlx::query_as::<_, ProblemWithCategories>(
+// SQLite CURRENT_TIMESTAMP ("YYYY-MM-DD HH:MM:SS", UTC) -> RFC 2822 for RSS
+fn sqlite_timestamp_to_rfc2822(ts: &str) -> Option<String> {
+    chrono::NaiveDateTime::parse_from_str(ts, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|dt| dt.and_utc().to_rfc2822())
+}
+
+#[get("/p/<slug>/problems/feed.xml")]
+async fn public_problems_feed(
+    mut db: Connection<Db>,
+    cache: &State<PublicPageCache>,
+    config: &State<AppConfig>,
+    host: Option<&Host<'_>>,
+    since: IfModifiedSince,
+    slug: String,
+) -> Result<PublicPage, Status> {
+    let last_modified = public_cache::course_last_modified(&mut db, &slug).await;
+    cache.serve_uncached(&since, last_modified, || problems_feed(db, config, host, slug)).await
+}
+
+async fn problems_feed(mut db: Connection<Db>, config: &AppConfig, host: Option<&Host<'_>>, slug: String) -> Result<(ContentType, String), Status> {
+    let course = queries::fetch_published_course(&mut db, &slug).await.ok_or(Status::NotFound)?;
+
+    let rows = sqlx::query(
         r#"
         SELECT
-            p.id, p.log_item_id, p.exam_id, p.description, p.notes, p.image_url, p.solution_link,
+            p.public_id, p.notes, p.created_at,
             GROUP_CONCAT(c.name) as category_names,
-            COALESCE(l.kind, 'Exam') as source_kind,
-            COALESCE(l.title, e.title, '') as source_title
+            src.source_kind, src.source_title, src.source_url
         FROM problems p
+        JOIN problem_sources src ON src.problem_id = p.id
         LEFT JOIN log_items l ON p.log_item_id = l.id
         LEFT JOIN exams e ON p.exam_id = e.id
         LEFT JOIN problem_categories pc ON p.id = pc.problem_id
         LEFT JOIN categories c ON pc.category_id = c.id
         WHERE (l.course_id = ? OR e.course_id = ?)
         GROUP BY p.id
+        ORDER BY p.id DESC
+        LIMIT 50
         "#
     )
     .bind(course.id)
@@ -1667,6 +6349,102 @@ async fn public_course_problems_zh(mut db: Connection<Db>, slug: String) -> Resu
     .await
     .unwrap_or_default();
 
+    let host = host.map(|h| h.to_string());
+    let page_url = format!("{}/p/{}/problems", config.base_url(host.as_deref()), slug);
+
+    let mut items = String::new();
+    for row in &rows {
+        let public_id: String = row.try_get("public_id").unwrap_or_default();
+        let kind: String = row.try_get("source_kind").unwrap_or_default();
+        let source_title: String = row.try_get("source_title").unwrap_or_default();
+        let notes: Option<String> = row.try_get("notes").unwrap_or(None);
+        let category_names: Option<String> = row.try_get("category_names").unwrap_or(None);
+        let created_at: Option<String> = row.try_get("created_at").unwrap_or(None);
+
+        let source = if source_title.is_empty() {
+            kind.clone()
+        } else {
+            translate::translate_title_algorithmic(&kind, &source_title)
+        };
+        let title = match &category_names {
+            Some(cats) => format!("{} — {}", source, cats.replace(',', ", ")),
+            None => source,
+        };
+        let link = format!("{}#problem-{}", page_url, public_id);
+
+        items.push_str("<item>");
+        items.push_str(&format!("<title>{}</title>", xml_escape(&title)));
+        items.push_str(&format!("<link>{}</link>", xml_escape(&link)));
+        items.push_str(&format!("<guid isPermaLink=\"false\">{}-problem-{}</guid>", xml_escape(&slug), public_id));
+        if let Some(notes) = notes.filter(|n| !n.is_empty()) {
+            items.push_str(&format!("<description>{}</description>", xml_escape(&notes)));
+        }
+        if let Some(date) = created_at.as_deref().and_then(sqlite_timestamp_to_rfc2822) {
+            items.push_str(&format!("<pubDate>{}</pubDate>", date));
+        }
+        items.push_str("</item>\n");
+    }
+
+    let xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\"><channel>\n<title>{} — {} problems</title>\n<link>{}</link>\n<description>Newly added problems for {}</description>\n{}</channel></rss>\n",
+        xml_escape(&course.code),
+        xml_escape(&course.title),
+        xml_escape(&page_url),
+        xml_escape(&course.code),
+        items
+    );
+
+    Ok((ContentType::new("application", "rss+xml"), xml))
+}
+
+// ========== Public Routes (Chinese / untranslated) ==========
+
+#[get("/p/<slug>/zh")]
+async fn public_course_calendar_zh(mut db: Connection<Db>, cache: &State<PublicPageCache>, config: &State<AppConfig>, brand: Branding, since: IfModifiedSince, slug: String) -> Result<PublicPage, Status> {
+    let path = format!("/p/{}/zh", slug);
+    let last_modified = public_cache::course_last_modified(&mut db, &slug).await;
+    cache.serve(&path, config.public_cache_ttl(), &since, last_modified, || async move {
+        let page = public_calendar_zh_page(db, brand, slug).await?;
+        let html = page.render().map_err(|_| Status::InternalServerError)?;
+        Ok((html, !page.pending_translations))
+    }).await
+}
+
+async fn public_calendar_zh_page(mut db: Connection<Db>, brand: Branding, slug: String) -> Result<PublicCalendarTemplate, Status> {
+    let course = queries::fetch_published_course(&mut db, &slug).await.ok_or(Status::NotFound)?;
+
+    let log_items = sqlx::query_as::<_, LogItem>(
+        "SELECT * FROM log_items WHERE course_id = ? ORDER BY date ASC, id ASC"
+    )
+    .bind(course.id)
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_default();
+
+    let empty_translations = std::collections::HashMap::new();
+    let kinds = course_log_item_kinds(&mut db, course.id).await;
+    let (weeks, unscheduled, active_kinds) = build_calendar(log_items, course.show_lecture_links, &empty_translations, false, false, &kinds);
+
+    let base_path = format!("/p/{}/zh", course.public_slug.as_deref().unwrap_or(""));
+    Ok(PublicCalendarTemplate { course, weeks, unscheduled, active_kinds, lang: "zh".to_string(), base_path, pending_translations: false, brand })
+}
+
+#[get("/p/<slug>/zh/problems")]
+async fn public_course_problems_zh(mut db: Connection<Db>, cache: &State<PublicPageCache>, config: &State<AppConfig>, brand: Branding, since: IfModifiedSince, slug: String) -> Result<PublicPage, Status> {
+    let path = format!("/p/{}/zh/problems", slug);
+    let last_modified = public_cache::course_last_modified(&mut db, &slug).await;
+    cache.serve(&path, config.public_cache_ttl(), &since, last_modified, || async move {
+        let page = public_problems_zh_page(db, brand, slug).await?;
+        let html = page.render().map_err(|_| Status::InternalServerError)?;
+        Ok((html, !page.pending_translations))
+    }).await
+}
+
+async fn public_problems_zh_page(mut db: Connection<Db>, brand: Branding, slug: String) -> Result<PublicProblemsTemplate, Status> {
+    let course = queries::fetch_published_course(&mut db, &slug).await.ok_or(Status::NotFound)?;
+
+    let raw_problems = queries::fetch_course_problems(&mut db, course.id).await;
+
     let mut all_categories_set: std::collections::HashSet<String> = std::collections::HashSet::new();
 
     let problems: Vec<PublicProblem> = raw_problems.iter().map(|p| {
@@ -1690,13 +6468,18 @@ async fn public_course_problems_zh(mut db: Connection<Db>, slug: String) -> Resu
         });
 
         PublicProblem {
-            id: p.id,
+            public_id: p.public_id.clone(),
+            number: p.number,
             image_url: p.image_url.clone(),
+            body: p.body.clone(),
             notes,
             category_names,
             source_kind: p.source_kind.clone(),
             source_title,
             solution_link,
+            original_filename: p.original_filename.clone(),
+            mime_type: p.mime_type.clone(),
+            notes_key: None,
         }
     }).collect();
 
@@ -1704,7 +6487,264 @@ async fn public_course_problems_zh(mut db: Connection<Db>, slug: String) -> Resu
     all_categories.sort();
 
     let base_path = format!("/p/{}/zh", course.public_slug.as_deref().unwrap_or(""));
-    Ok(PublicProblemsTemplate { course, problems, all_categories, lang: "zh".to_string(), base_path })
+    Ok(PublicProblemsTemplate { course, problems, all_categories, lang: "zh".to_string(), base_path, pending_translations: false, brand })
+}
+
+// ========== Public Embeds ==========
+//
+// See embed.rs. Embeds show the problem untranslated, as on the /zh pages.
+
+// Rocket's Shield sends X-Frame-Options: SAMEORIGIN with every response;
+// browsers let CSP frame-ancestors override it, so any site may frame these
+#[derive(Responder)]
+struct EmbedPage {
+    page: PublicPage,
+    frame_ancestors: Header<'static>,
+}
+
+// One problem of a published course, by public id
+async fn fetch_public_problem(db: &mut Connection<Db>, slug: &str, public_id: &str) -> Result<(Course, PublicProblem), Status> {
+    let course = queries::fetch_published_course(db, slug).await.ok_or(Status::NotFound)?;
+    let id = sqlx::query_scalar!(
+        r#"SELECT p.id AS "id!" FROM problems p JOIN problem_sources src ON src.problem_id = p.id WHERE p.public_id = ? AND src.course_id = ?"#,
+        public_id,
+        course.id
+    )
+    .fetch_optional(&mut ***db)
+    .await
+    .unwrap_or(None)
+    .ok_or(Status::NotFound)?;
+    let p = queries::fetch_problem_with_categories(db, id).await.ok_or(Status::NotFound)?;
+
+    let problem = PublicProblem {
+        public_id: p.public_id,
+        number: p.number,
+        image_url: p.image_url,
+        body: p.body,
+        notes: p.notes.filter(|n| !n.is_empty()),
+        category_names: p.category_names,
+        source_kind: p.source_kind,
+        source_title: p.source_title,
+        solution_link: p.solution_link.filter(|link| link.contains("notes.lnjng.com")),
+        original_filename: p.original_filename,
+        mime_type: p.mime_type,
+        notes_key: None,
+    };
+    Ok((course, problem))
+}
+
+#[get("/p/<slug>/problems/<public_id>/embed")]
+#[allow(clippy::too_many_arguments)]
+async fn public_problem_embed(
+    mut db: Connection<Db>,
+    cache: &State<PublicPageCache>,
+    config: &State<AppConfig>,
+    brand: Branding,
+    host: Option<&Host<'_>>,
+    since: IfModifiedSince,
+    slug: String,
+    public_id: String,
+) -> Result<EmbedPage, Status> {
+    let path = format!("/p/{}/problems/{}/embed", slug, public_id);
+    let base_url = config.base_url(host.map(|h| h.to_string()).as_deref());
+    let embed_url = format!("{}{}", base_url, path);
+    let last_modified = public_cache::course_last_modified(&mut db, &slug).await;
+    let page = cache.serve(&path, config.public_cache_ttl(), &since, last_modified, || async move {
+        let (course, problem) = fetch_public_problem(&mut db, &slug, &public_id).await?;
+        let page = PublicProblemEmbedTemplate { course, problem, embed_url, base_url, brand };
+        let html = page.render().map_err(|_| Status::InternalServerError)?;
+        Ok((html, true))
+    }).await?;
+    Ok(EmbedPage { page, frame_ancestors: Header::new("Content-Security-Policy", "frame-ancestors *") })
+}
+
+// The oEmbed endpoint for links to public problems. Only JSON is offered;
+// a request for XML gets 501 as the spec asks.
+#[get("/oembed?<url>&<format>&<maxwidth>&<maxheight>")]
+#[allow(clippy::too_many_arguments)]
+async fn oembed(
+    mut db: Connection<Db>,
+    config: &State<AppConfig>,
+    brand: Branding,
+    host: Option<&Host<'_>>,
+    url: &str,
+    format: Option<&str>,
+    maxwidth: Option<u32>,
+    maxheight: Option<u32>,
+) -> Result<Json<OEmbed>, Status> {
+    if format.is_some_and(|f| f != "json") {
+        return Err(Status::NotImplemented);
+    }
+    let (slug, public_id) = embed::parse_problem_url(url).ok_or(Status::NotFound)?;
+    let (course, problem) = fetch_public_problem(&mut db, &slug, &public_id).await?;
+
+    let base_url = config.base_url(host.map(|h| h.to_string()).as_deref());
+    let embed_url = format!("{}/p/{}/problems/{}/embed", base_url, slug, problem.public_id);
+    let source = if problem.source_title.is_empty() { problem.source_kind } else { problem.source_title };
+    let title = format!("{} — {}", course.code, source);
+    Ok(Json(OEmbed::rich(title, brand.name, base_url, &embed_url, embed::frame_size(maxwidth, maxheight))))
+}
+
+// ========== Content Reports ==========
+//
+// Anyone reading a public problems page can report a problem, such as a
+// scanned textbook page or a screenshot showing a classmate's name. Reports
+// wait at /admin/reports, where the admin dismisses them or unpublishes the
+// course. Unpublishing closes every open report against the course, and
+// cached copies of an unpublished course's pages are never served, so the
+// pages and their screenshots are gone from the next request on.
+
+/// Open reports one problem can collect, so a script can't flood the queue
+const MAX_OPEN_REPORTS: i64 = 10;
+
+#[derive(FromForm)]
+struct ReportForm {
+    lang: String,
+    reason: String,
+    details: Option<String>,
+}
+
+fn report_lang(lang: Option<&str>) -> String {
+    if lang == Some("zh") { "zh" } else { "en" }.to_string()
+}
+
+#[get("/p/<slug>/problems/<public_id>/report?<lang>")]
+async fn public_report_form(mut db: Connection<Db>, slug: String, public_id: String, lang: Option<&str>) -> Result<PublicReportTemplate, Status> {
+    fetch_public_problem(&mut db, &slug, &public_id).await?;
+    Ok(PublicReportTemplate { slug, public_id, lang: report_lang(lang), reasons: REPORT_REASONS, sent: false })
+}
+
+#[post("/p/<slug>/problems/<public_id>/report", data = "<form>")]
+async fn report_public_problem(mut db: Connection<Db>, slug: String, public_id: String, form: Form<ReportForm>) -> Result<PublicReportTemplate, Status> {
+    let (course, _) = fetch_public_problem(&mut db, &slug, &public_id).await?;
+    if !REPORT_REASONS.iter().any(|(key, _, _)| *key == form.reason) {
+        return Err(Status::BadRequest);
+    }
+    let details: Option<String> = form.details.as_deref()
+        .map(|d| d.trim())
+        .filter(|d| !d.is_empty())
+        .map(|d| d.chars().take(1000).collect());
+
+    sqlx::query(
+        r#"
+        INSERT INTO content_reports (course_id, problem_id, reason, details)
+        SELECT ?, p.id, ?, ? FROM problems p
+        WHERE p.public_id = ?
+          AND (SELECT COUNT(*) FROM content_reports r WHERE r.problem_id = p.id AND r.status = 'open') < ?
+        "#,
+    )
+    .bind(course.id)
+    .bind(&form.reason)
+    .bind(&details)
+    .bind(&public_id)
+    .bind(MAX_OPEN_REPORTS)
+    .execute(&mut **db)
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(PublicReportTemplate { slug, public_id, lang: report_lang(Some(form.lang.as_str())), reasons: REPORT_REASONS, sent: true })
+}
+
+#[get("/admin/reports")]
+async fn view_content_reports(mut db: Connection<Db>, user: AuthUser, brand: Branding, flash: Option<FlashMessage<'_>>) -> Result<ContentReportsTemplate, Status> {
+    if !is_admin(&mut db, user.id).await {
+        return Err(Status::Forbidden);
+    }
+    let reports = sqlx::query_as::<_, ContentReport>(
+        r#"
+        SELECT r.id, r.course_id, c.code AS course_code, c.public_slug, c.is_published,
+               p.public_id AS problem_public_id, p.number AS problem_number, p.image_url, p.body,
+               r.reason, r.details, r.created_at
+        FROM content_reports r
+        JOIN courses c ON r.course_id = c.id
+        JOIN problems p ON r.problem_id = p.id
+        WHERE r.status = 'open'
+        ORDER BY r.created_at ASC, r.id ASC
+        "#,
+    )
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_default();
+    let notice = flash.map(|f| (f.kind().to_string(), f.message().to_string()));
+    Ok(ContentReportsTemplate { reports, notice, user: Some(user), brand })
+}
+
+#[post("/admin/reports/<id>/dismiss")]
+async fn dismiss_content_report(mut db: Connection<Db>, user: AuthUser, id: i64) -> Result<Flash<Redirect>, Status> {
+    if !is_admin(&mut db, user.id).await {
+        return Err(Status::Forbidden);
+    }
+    sqlx::query("UPDATE content_reports SET status = 'dismissed', resolved_at = CURRENT_TIMESTAMP, resolved_by = ? WHERE id = ? AND status = 'open'")
+        .bind(user.id)
+        .bind(id)
+        .execute(&mut **db)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    Ok(Flash::success(Redirect::to("/admin/reports"), "已忽略这条举报。"))
+}
+
+#[post("/admin/reports/<id>/unpublish")]
+async fn unpublish_reported_course(mut db: Connection<Db>, user: AuthUser, id: i64) -> Result<Flash<Redirect>, Status> {
+    if !is_admin(&mut db, user.id).await {
+        return Err(Status::Forbidden);
+    }
+    let course_id: i64 = sqlx::query_scalar("SELECT course_id FROM content_reports WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut **db)
+        .await
+        .unwrap_or(None)
+        .ok_or(Status::NotFound)?;
+
+    sqlx::query("UPDATE courses SET is_published = 0 WHERE id = ?")
+        .bind(course_id)
+        .execute(&mut **db)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let closed = sqlx::query("UPDATE content_reports SET status = 'unpublished', resolved_at = CURRENT_TIMESTAMP, resolved_by = ? WHERE course_id = ? AND status = 'open'")
+        .bind(user.id)
+        .bind(course_id)
+        .execute(&mut **db)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .rows_affected();
+    Ok(Flash::success(Redirect::to("/admin/reports"), format!("课程已下架，关闭了 {} 条举报。", closed)))
+}
+
+// ========== Error Catchers ==========
+//
+// Request bodies over the `[default.limits]` in Rocket.toml are refused with
+// 413 before any handler runs. Uploads come from HTMX forms, so those get the
+// same error fragment a rejected screenshot does (layout.html lets htmx swap
+// it in); anything else gets a line of text.
+
+#[derive(Responder)]
+enum TooLarge {
+    #[response(status = 413)]
+    Fragment(UploadErrorTemplate),
+    #[response(status = 413)]
+    Text(String),
+}
+
+#[catch(413)]
+fn payload_too_large(req: &Request<'_>) -> TooLarge {
+    let limits = req.limits();
+    let message = if req.content_type().is_some_and(|ct| ct.is_form_data()) {
+        let file = limits.get("file").unwrap_or(Limits::FILE);
+        let data_form = limits.get("data-form").unwrap_or(Limits::DATA_FORM);
+        Rejection::TooLarge { max_bytes: file.min(data_form).as_u64() }.message()
+    } else {
+        let form = limits.get("form").unwrap_or(Limits::FORM);
+        format!("提交的内容过长，上限为 {}。", uploads::format_size(form.as_u64()))
+    };
+    if req.headers().get_one("HX-Request") == Some("true") {
+        TooLarge::Fragment(UploadErrorTemplate { filename: None, message })
+    } else {
+        TooLarge::Text(message)
+    }
+}
+
+pub fn catchers() -> Vec<rocket::Catcher> {
+    catchers![payload_too_large]
 }
 
 pub fn routes() -> Vec<rocket::Route> {
@@ -1714,39 +6754,147 @@ pub fn routes() -> Vec<rocket::Route> {
         get_login,
         post_login,
         get_register,
+        get_setup,
+        post_setup,
         post_register,
+        view_invites,
+        create_invite,
+        delete_invite,
+        view_site_settings,
+        update_site_settings,
+        update_api_limits,
+        view_integrity,
+        repair_integrity,
+        view_content_reports,
+        dismiss_content_report,
+        unpublish_reported_course,
+        view_stats,
+        export_translations,
+        import_translations,
         logout,
         create_semester,
         view_semester,
+        get_semester_row,
+        get_edit_semester,
+        update_semester,
+        delete_semester,
+        export_semester,
+        export_course_site,
+        view_semester_wrapped,
+        update_semester_wrapped,
         create_course,
+        get_course_card,
+        get_edit_course,
+        update_course,
+        delete_course,
         view_course_log,
+        print_log_week,
         create_log_item,
         create_problem,
+        create_problems_bulk,
+        create_problems_split,
+        import_problem,
         get_log_problems,
         view_course_study,
         filter_study_problems,
+        study_manifest,
+        view_study_review,
+        view_study_topics,
+        view_topic_review,
+        create_category_alias,
+        delete_category_alias,
+        create_study_preset,
+        apply_study_preset,
+        study_focus,
+        delete_study_preset,
+        create_study_goal,
+        delete_study_goal,
         delete_log_item,
         get_edit_log_item,
         get_log_item,
         update_log_item,
+        update_log_item_status,
+        update_log_item_tags,
         get_edit_problem,
         update_problem,
         get_problem_row,
+        view_problem,
         delete_problem,
+        view_course_history,
+        view_entity_history,
+        revert_history,
         view_course_exams,
         create_exam,
         get_exam,
         get_edit_exam,
         update_exam,
+        update_exam_categories,
+        update_exam_log_items,
+        study_for_exam,
+        view_study_plan,
+        create_study_plan,
         delete_exam,
         create_exam_problem,
         get_exam_problems,
         view_course_settings,
         update_course_settings,
+        update_course_grade,
+        update_course_color,
+        update_course_webhook,
+        update_course_join_code,
+        join_course,
+        remove_course_member,
+        assign_problem,
+        attach_problem_to_exam,
+        create_problem_link,
+        delete_problem_link,
+        get_log_bookmarks,
+        create_log_bookmark,
+        delete_log_bookmark,
+        view_assignments,
+        view_course_discussions,
+        create_thread,
+        view_thread,
+        create_post,
+        delete_thread,
+        delete_post,
+        view_digest_settings,
+        update_digest_settings,
+        view_digest_unsubscribe,
+        digest_unsubscribe,
+        view_account,
+        update_account_email,
+        view_account_verify,
+        account_verify,
+        reset_todos_token,
+        disable_todos_feed,
+        todos_feed,
+        update_course_translation_context,
+        update_log_item_kinds,
+        update_course_continues,
+        update_category_carryovers,
+        import_notes,
+        export_problem_categories,
+        recategorize_problems,
         translate_course,
+        translate_missing,
+        poll_missing_translations,
+        course_report_pdf,
+        serve_upload,
         public_course_calendar,
         public_course_problems,
+        public_problems_feed,
+        public_problem_embed,
+        oembed,
+        public_report_form,
+        report_public_problem,
+        public_semester_wrapped,
         public_course_calendar_zh,
-        public_course_problems_zh
+        public_course_problems_zh,
+        sitemap,
+        robots_txt
     ]
 }
+
+#[cfg(test)]
+mod template_tests;
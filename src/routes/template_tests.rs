@@ -0,0 +1,900 @@
+// ========== Template Snapshot Tests ==========
+//
+// Renders every Template struct with small fixed fixtures and compares the
+// HTML against snapshots in snapshots/. A model field change that silently
+// alters or breaks a page shows up as a snapshot diff; review and accept it
+// with `cargo insta review` (or INSTA_UPDATE=always cargo test).
+
+use super::*;
+use insta::assert_snapshot;
+
+fn user() -> Option<AuthUser> {
+    Some(AuthUser { id: 1 })
+}
+
+fn semester() -> Semester {
+    Semester { id: 1, name: "2026 秋季".to_string(), created_at: "2026-09-01 00:00:00".to_string(), wrapped_published: false, wrapped_slug: None }
+}
+
+fn course() -> Course {
+    Course {
+        id: 1,
+        semester_id: 1,
+        code: "MATH 1A".to_string(),
+        title: "微积分".to_string(),
+        is_published: true,
+        public_slug: Some("math-1a".to_string()),
+        show_lecture_links: true,
+        discourage_indexing: false,
+        public_theme: "forest".to_string(),
+        banner_url: Some("/uploads/banner.png".to_string()),
+        units: Some(4.0),
+        grade: Some("A-".to_string()),
+        color: Some("#2563eb".to_string()),
+        public_id: "0199a3b2-6c00-7a10-8e21-4f5c8d9b1a01".to_string(),
+        translation_subject: Some("单变量微积分".to_string()),
+        translation_level: "undergraduate".to_string(),
+        translation_style: "academic".to_string(),
+        join_code: None,
+        join_role: "viewer".to_string(),
+        continues_course_id: None,
+        webhook_url: None,
+    }
+}
+
+fn other_course() -> Course {
+    Course {
+        id: 2,
+        code: "PHYS 7A".to_string(),
+        title: "物理".to_string(),
+        is_published: false,
+        public_slug: None,
+        show_lecture_links: false,
+        public_theme: "default".to_string(),
+        banner_url: None,
+        units: Some(3.0),
+        grade: None,
+        color: None,
+        public_id: "0199a3b2-6c00-7a10-8e21-4f5c8d9b1a02".to_string(),
+        ..course()
+    }
+}
+
+fn previews() -> Vec<LinkPreview> {
+    vec![LinkPreview {
+        url: "https://ocw.mit.edu/courses/18-01sc".to_string(),
+        domain: "ocw.mit.edu".to_string(),
+        title: Some("Single Variable Calculus | MIT OpenCourseWare".to_string()),
+        favicon_url: Some("https://ocw.mit.edu/favicon.ico".to_string()),
+        status: "ok".to_string(),
+        fetched_at: Some("2026-09-02 10:00:00".to_string()),
+    }]
+}
+
+fn log_items() -> Vec<LogItem> {
+    vec![
+        LogItem {
+            id: 1,
+            course_id: 1,
+            kind: "Lecture".to_string(),
+            title: "第一讲".to_string(),
+            description: Some("函数与极限".to_string()),
+            link: Some("https://drive.google.com/file/d/abc".to_string()),
+            date: Some("2026-09-01".to_string()),
+            public_id: "0199a3b2-6c00-7b20-9e21-4f5c8d9b1a01".to_string(),
+            status: None,
+        },
+        LogItem {
+            id: 2,
+            course_id: 1,
+            kind: "Homework".to_string(),
+            title: "作业一".to_string(),
+            description: None,
+            link: None,
+            date: Some("2026-09-08".to_string()),
+            public_id: "0199a3b2-6c00-7b20-9e21-4f5c8d9b1a02".to_string(),
+            status: Some("in_progress".to_string()),
+        },
+        LogItem {
+            id: 3,
+            course_id: 1,
+            kind: "Other".to_string(),
+            title: "补充材料".to_string(),
+            description: Some("参考 https://ocw.mit.edu/courses/18-01sc 和 https://example.com/pending".to_string()),
+            link: None,
+            date: None,
+            public_id: "0199a3b2-6c00-7b20-9e21-4f5c8d9b1a03".to_string(),
+            status: None,
+        },
+    ]
+}
+
+fn categories() -> Vec<Category> {
+    vec![
+        Category { id: 1, course_id: 1, name: "极限".to_string(), parent_id: None, path: "极限".to_string(), depth: 0 },
+        Category { id: 2, course_id: 1, name: "导数".to_string(), parent_id: None, path: "导数".to_string(), depth: 0 },
+    ]
+}
+
+fn kinds() -> Vec<LogItemKind> {
+    ALL_KINDS
+        .iter()
+        .enumerate()
+        .map(|(i, kind)| LogItemKind { course_id: 1, kind: kind.to_string(), position: i as i64, enabled: *kind != "Lab" })
+        .collect()
+}
+
+fn problem() -> ProblemWithCategories {
+    ProblemWithCategories {
+        id: 1,
+        log_item_id: Some(2),
+        exam_id: None,
+        description: "Screenshot Problem".to_string(),
+        notes: Some("忘了用 sin x / x → 1".to_string()),
+        image_url: Some("/uploads/problem.png".to_string()),
+        body: None,
+        solution_link: Some("https://example.com/solution".to_string()),
+        answer: Some("由 $\\lim_{x \\to 0} \\frac{\\sin x}{x} = 1$，原式 **= 3**。".to_string()),
+        category_names: Some("极限,导数".to_string()),
+        source_kind: "Homework".to_string(),
+        source_title: "作业一".to_string(),
+        source_url: "/courses/1#log-2".to_string(),
+        original_filename: Some("hw1-q3.png".to_string()),
+        mime_type: Some("image/png".to_string()),
+        imported_from: None,
+        public_id: "0199a3b2-6c00-7c30-ae21-4f5c8d9b1a01".to_string(),
+        number: Some(1),
+        assigned_to: None,
+        assignee_name: None,
+        difficulty: None,
+        exam_title: None,
+    }
+}
+
+fn pdf_problem() -> ProblemWithCategories {
+    ProblemWithCategories {
+        id: 2,
+        image_url: Some("/uploads/ab/cdef.pdf".to_string()),
+        original_filename: Some("第三章讲义.pdf".to_string()),
+        mime_type: Some("application/pdf".to_string()),
+        category_names: None,
+        public_id: "0199a3b2-6c00-7c30-ae21-4f5c8d9b1a02".to_string(),
+        number: Some(2),
+        ..problem()
+    }
+}
+
+fn text_problem() -> ProblemWithCategories {
+    ProblemWithCategories {
+        description: "Text Problem".to_string(),
+        image_url: None,
+        body: Some("求 $\\lim_{x \\to 0} \\frac{\\sin 3x}{x}$。".to_string()),
+        original_filename: None,
+        mime_type: None,
+        ..problem()
+    }
+}
+
+fn exam() -> Exam {
+    Exam {
+        id: 1,
+        course_id: 1,
+        title: "期中考试".to_string(),
+        semester: Some("2025 秋季".to_string()),
+        link: Some("https://example.com/midterm.pdf".to_string()),
+        exam_date: Some("2026-10-28".to_string()),
+    }
+}
+
+fn preset() -> StudyPreset {
+    StudyPreset {
+        id: 1,
+        user_id: 1,
+        course_id: 1,
+        name: "考前复习".to_string(),
+        filters: r#"{"sources":["Homework"],"categories":[1],"sort":"newest"}"#.to_string(),
+    }
+}
+
+fn goal() -> StudyGoal {
+    StudyGoal {
+        id: 1,
+        user_id: 1,
+        course_id: 1,
+        category_id: 1,
+        category_name: "极限".to_string(),
+        target: 20,
+        deadline: Some("2026-10-20".to_string()),
+        created_at: "2026-09-15 08:00:00".to_string(),
+        progress: 7,
+    }
+}
+
+#[test]
+fn test_dashboard_and_auth_pages() {
+    assert_snapshot!("index", IndexTemplate { semesters: vec![semester()], joined_courses: vec![], upcoming_exams: vec![], focus_suggestions: vec![], open_tasks: vec![], show_invites: false, translation_cache: None, join_error: None, user: user(), brand: Branding::default() }.render().unwrap());
+    assert_snapshot!("semester_row", SemesterRowTemplate { semester: semester() }.render().unwrap());
+    assert_snapshot!("semester_edit", SemesterEditTemplate { semester: semester() }.render().unwrap());
+    assert_snapshot!("login", LoginTemplate { user: None, error: Some("用户名或密码错误。".to_string()), brand: Branding { name: "数学系自习室".to_string(), ..Branding::default() } }.render().unwrap());
+    assert_snapshot!("register", RegisterTemplate { user: None, error: None, needs_invite: false, invite_code: None, brand: Branding::default() }.render().unwrap());
+    assert_snapshot!("register_invite", RegisterTemplate { user: None, error: Some("Invalid or already used invite code".to_string()), needs_invite: true, invite_code: Some("3f2a9c01b7de".to_string()), brand: Branding::default() }.render().unwrap());
+    assert_snapshot!("setup", SetupTemplate { user: None, error: Some("Username and password are required".to_string()), username: String::new(), instance_name: "智习".to_string(), upload_dir: "uploads".to_string(), brand: Branding::default() }.render().unwrap());
+}
+
+#[test]
+fn test_dashboard_upcoming_exams() {
+    let upcoming = |id, days_left| UpcomingExam {
+        id,
+        course_id: 1,
+        course_code: "MATH1A".to_string(),
+        course_color: None,
+        title: "期中考试".to_string(),
+        exam_date: "2026-10-28".to_string(),
+        days_left,
+    };
+    let page = IndexTemplate { semesters: vec![], joined_courses: vec![], upcoming_exams: vec![upcoming(1, 0), upcoming(2, 5), upcoming(3, 12)], focus_suggestions: vec![], open_tasks: vec![], show_invites: true, translation_cache: Some(translate::CacheStats { entries: 412, bytes: 100_000 }), join_error: None, user: user(), brand: Branding::default() };
+    assert_snapshot!("index_upcoming_exams", page.render().unwrap());
+}
+
+#[test]
+fn test_dashboard_focus_suggestions() {
+    let suggestions = vec![
+        FocusSuggestion {
+            course_id: 1,
+            course_code: "MATH1A".to_string(),
+            course_color: None,
+            category_id: 4,
+            category_name: "极限".to_string(),
+            problems: 5,
+            incorrect: 3,
+            days_since_review: None,
+        },
+        FocusSuggestion {
+            course_id: 2,
+            course_code: "PHYS7A".to_string(),
+            course_color: Some("#0ea5e9".to_string()),
+            category_id: 9,
+            category_name: "动量守恒".to_string(),
+            problems: 8,
+            incorrect: 2,
+            days_since_review: Some(12.4),
+        },
+    ];
+    let page = IndexTemplate { semesters: vec![], joined_courses: vec![], upcoming_exams: vec![], focus_suggestions: suggestions, open_tasks: vec![], show_invites: false, translation_cache: None, join_error: None, user: user(), brand: Branding::default() };
+    assert_snapshot!("index_focus_suggestions", page.render().unwrap());
+
+    let task = |id: i64, status: &str, date: Option<&str>, overdue: bool| OpenTask {
+        id,
+        course_id: 1,
+        course_code: "MATH1A".to_string(),
+        course_color: None,
+        title: format!("作业 {}", id),
+        date: date.map(str::to_string),
+        status: status.to_string(),
+        overdue,
+    };
+    let open_tasks = vec![task(3, "in_progress", Some("2026-09-20"), true), task(5, "todo", None, false)];
+    let page = IndexTemplate { semesters: vec![], joined_courses: vec![], upcoming_exams: vec![], focus_suggestions: vec![], open_tasks, show_invites: false, translation_cache: None, join_error: None, user: user(), brand: Branding::default() };
+    assert_snapshot!("index_open_tasks", page.render().unwrap());
+}
+
+#[test]
+fn test_invite_pages() {
+    let invite = |code: &str, used_by_name: Option<&str>| Invite {
+        code: code.to_string(),
+        created_by: 1,
+        created_at: "2026-10-01 09:00:00".to_string(),
+        used_by: used_by_name.map(|_| 2),
+        used_at: used_by_name.map(|_| "2026-10-02 18:30:00".to_string()),
+        used_by_name: used_by_name.map(String::from),
+    };
+    let page = InvitesTemplate {
+        invites: vec![invite("3f2a9c01b7de", None), invite("a81c55e0f4d2", Some("bob"))],
+        enabled: true,
+        base_url: "https://zhixi.example.com".to_string(),
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("invites", page.render().unwrap());
+}
+
+#[test]
+fn test_site_settings_page() {
+    let page = SiteSettingsTemplate {
+        notice: Some(("success".to_string(), "站点设置已保存。".to_string())),
+        api_limits: api_limits::Limits { per_minute: Some(60), per_day: None },
+        user: user(),
+        brand: Branding {
+            name: "数学系自习室".to_string(),
+            logo_url: Some("/uploads/ab/logo.png".to_string()),
+            footer: Some("数学系 · 2026 秋季学期".to_string()),
+            dev_mode: false,
+        },
+    };
+    assert_snapshot!("site_settings", page.render().unwrap());
+}
+
+#[test]
+fn test_integrity_page() {
+    let findings = vec![
+        integrity::Finding { check: integrity::Check::OrphanedLogItems, count: 1, examples: vec!["记录 #7 作业三（课程 #4）".to_string()] },
+        integrity::Finding {
+            check: integrity::Check::MissingImages,
+            count: 2,
+            examples: vec!["错题 #12：/uploads/ab/cdef.png".to_string(), "错题 #15：/uploads/9f/0123.png".to_string()],
+        },
+    ];
+    let page = IntegrityTemplate { findings, notice: None, user: user(), brand: Branding::default() };
+    assert_snapshot!("integrity", page.render().unwrap());
+}
+
+#[test]
+fn test_content_reports_page() {
+    let reports = vec![ContentReport {
+        id: 3,
+        course_id: 1,
+        course_code: "MATH 1A".to_string(),
+        public_slug: Some("math-1a".to_string()),
+        is_published: true,
+        problem_public_id: "0199a3b2-6c00-7c30-ae21-4f5c8d9b1a01".to_string(),
+        problem_number: Some(1),
+        image_url: Some("/uploads/ab/cdef.png".to_string()),
+        body: None,
+        reason: "personal".to_string(),
+        details: Some("截图里有同学的学号和名字".to_string()),
+        created_at: "2026-10-15 09:30:00".to_string(),
+    }];
+    let notice = Some(("success".to_string(), "已忽略这条举报。".to_string()));
+    let page = ContentReportsTemplate { reports, notice, user: user(), brand: Branding::default() };
+    assert_snapshot!("admin_reports", page.render().unwrap());
+}
+
+#[test]
+fn test_admin_stats_page() {
+    let today = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+    let requests = std::collections::HashMap::from([("2026-10-15".to_string(), 120.0), ("2026-10-16".to_string(), 480.0)]);
+    let costs = std::collections::HashMap::from([("2026-10-16".to_string(), 0.0421)]);
+    let stats = stats::InstanceStats {
+        users: vec![
+            stats::UserActivity {
+                username: "alice".to_string(),
+                is_admin: true,
+                courses: 2,
+                reviews: 35,
+                edits: 12,
+                posts: 4,
+                last_review: Some("2026-10-15 21:04:00".to_string()),
+            },
+            stats::UserActivity { username: "bob".to_string(), is_admin: false, courses: 1, reviews: 0, edits: 0, posts: 1, last_review: None },
+        ],
+        totals: vec![("学期", 1), ("课程", 2), ("记录", 14), ("错题", 37), ("分类", 9), ("考卷", 2), ("复习", 35), ("讨论", 5)],
+        uploads: 3,
+        upload_bytes: 2_400_000,
+        database_bytes: 1_048_576,
+        translation_cache: translate::CacheStats { entries: 42, bytes: 5120 },
+        llm_calls: 2,
+        llm_tokens: 1830,
+        llm_cost: 0.0421,
+        llm_cost_recent: 0.0421,
+        requests_recent: 600,
+        request_chart: stats::daily_bars(today, &requests, |v| format!("{} 次请求", v as i64)),
+        llm_chart: stats::daily_bars(today, &costs, |v| format!("${:.2}", v)),
+    };
+    let page = AdminStatsTemplate { stats, user: user(), brand: Branding::default() };
+    assert_snapshot!("admin_stats", page.render().unwrap());
+}
+
+#[test]
+fn test_semester_pages() {
+    let courses = vec![course(), other_course()];
+    let summary = grades::summarize(&courses);
+    assert_snapshot!("semester", SemesterTemplate { semester: semester(), courses, summary, user: user(), brand: Branding::default() }.render().unwrap());
+    assert_snapshot!("course_card", CourseCardTemplate { course: other_course() }.render().unwrap());
+    assert_snapshot!("course_edit", CourseEditTemplate { course: other_course() }.render().unwrap());
+}
+
+#[test]
+fn test_semester_wrapped_pages() {
+    let wrapped = || SemesterWrapped {
+        log_items: 14,
+        problems: 37,
+        reviews: 52,
+        courses: vec![
+            wrapped::CourseWrapped { code: "MATH 1A".to_string(), color: Some("#2563eb".to_string()), log_items: 9, problems: 30 },
+            wrapped::CourseWrapped { code: "PHYS 7A".to_string(), color: None, log_items: 5, problems: 7 },
+        ],
+        top_category: Some(wrapped::TopCategory { name: "极限".to_string(), reviews: 18 }),
+        busiest_week: Some(wrapped::BusiestWeek { start: NaiveDate::from_ymd_opt(2026, 10, 12).unwrap(), activity: 21 }),
+        active_days: 40,
+        longest_streak: 6,
+    };
+    let semester = Semester { wrapped_published: true, wrapped_slug: Some("2026-fall".to_string()), ..semester() };
+    let page = SemesterWrappedTemplate { semester: semester.clone(), wrapped: wrapped(), error: None, user: user(), brand: Branding::default() };
+    assert_snapshot!("semester_wrapped", page.render().unwrap());
+    let page = PublicWrappedTemplate { semester, wrapped: wrapped(), brand: Branding::default() };
+    assert_snapshot!("public_wrapped", page.render().unwrap());
+}
+
+#[test]
+fn test_course_log_pages() {
+    let page = CourseLogTemplate {
+        course: course(),
+        courses: vec![course(), other_course()],
+        log_items: log_items(),
+        semester: semester(),
+        categories: categories(),
+        kinds: kinds(),
+        previews: previews(),
+        view: "list".to_string(),
+        status_filter: None,
+        tag_filter: None,
+        tags: vec![(1, "重点".to_string()), (3, "重点".to_string()), (2, "需要复习".to_string())],
+        problem_counts: vec![(1, 2)],
+        print_week: Some(3),
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("course_log", page.render().unwrap());
+    let timeline = CourseLogTemplate { view: "timeline".to_string(), ..page };
+    assert_snapshot!("course_log_timeline", timeline.render().unwrap());
+
+    let week = LogWeekPrintTemplate {
+        course: course(),
+        week: 2,
+        monday: NaiveDate::from_ymd_opt(2026, 9, 7).unwrap(),
+        items: vec![(log_items().remove(1), vec![problem(), pdf_problem()])],
+        previous_week: Some(1),
+        next_week: Some(3),
+    };
+    assert_snapshot!("log_week_print", week.render().unwrap());
+
+    let page = CourseSiteTemplate {
+        course: course(),
+        exported_on: "2026-12-20".to_string(),
+        log_items: vec![(log_items().remove(1), vec![problem(), pdf_problem()])],
+        exams: vec![(exam(), vec![])],
+    };
+    assert_snapshot!("course_site", page.render().unwrap());
+
+    let item = log_items().remove(0);
+    assert_snapshot!("log_item", LogItemTemplate { item: item.clone(), categories: categories(), previews: vec![], tags: vec!["重点".to_string()] }.render().unwrap());
+    let bookmarks = LogItemBookmarksTemplate {
+        item_id: 1,
+        link: "https://www.youtube.com/watch?v=abc".to_string(),
+        bookmarks: vec![
+            LogItemBookmark { id: 1, log_item_id: 1, seconds: 95, note: "极限的定义".to_string() },
+            LogItemBookmark { id: 2, log_item_id: 1, seconds: 3723, note: String::new() },
+        ],
+        error: Some("时间的格式应为 分:秒 或 时:分:秒，例如 12:34。".to_string()),
+    };
+    assert_snapshot!("log_item_bookmarks", bookmarks.render().unwrap());
+    assert_snapshot!("log_item_edit", LogItemEditTemplate { item, kinds: kinds() }.render().unwrap());
+    assert_snapshot!("problem_row", ProblemRowTemplate { problem: problem() }.render().unwrap());
+    assert_snapshot!("problem_row_pdf", ProblemRowTemplate { problem: pdf_problem() }.render().unwrap());
+    assert_snapshot!("problem_row_text", ProblemRowTemplate { problem: ProblemWithCategories { exam_id: Some(1), exam_title: Some("期中考试".to_string()), ..text_problem() } }.render().unwrap());
+    assert_snapshot!("upload_error", UploadErrorTemplate { filename: Some("作业.exe".to_string()), message: "不允许上传此类型的文件（application/x-msdownload）。".to_string() }.render().unwrap());
+    assert_snapshot!("problem_edit", ProblemEditTemplate { problem: problem() }.render().unwrap());
+
+    let entry = EntityHistory {
+        id: 3,
+        entity_type: "problem".to_string(),
+        entity_id: 1,
+        course_id: Some(1),
+        action: "update".to_string(),
+        before_json: Some(serde_json::to_string(&ProblemWithCategories { notes: None, ..problem() }).unwrap()),
+        after_json: Some(serde_json::to_string(&problem()).unwrap()),
+        user_id: Some(1),
+        created_at: "2026-09-12 09:30:00".to_string(),
+    };
+    let page = ProblemDetailTemplate {
+        course: course(),
+        courses: vec![course()],
+        semester: semester(),
+        problem: ProblemWithCategories { assigned_to: Some(2), assignee_name: Some("bob".to_string()), exam_id: Some(1), exam_title: Some("期中考试".to_string()), ..problem() },
+        history: vec![history_entry_view(entry)],
+        assignees: vec![(1, "alice".to_string()), (2, "bob".to_string())],
+        exams: vec![exam(), Exam { id: 2, title: "期末考试".to_string(), exam_date: None, ..exam() }],
+        related: vec![
+            RelatedProblem { link_id: 1, relation: "prerequisite".to_string(), problem_id: 3, number: Some(3), source_title: "作业二".to_string() },
+            RelatedProblem { link_id: 2, relation: "follow_up".to_string(), problem_id: 5, number: None, source_title: "期中考试".to_string() },
+        ],
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("problem_detail", page.render().unwrap());
+    let page = ProblemDetailTemplate { problem: pdf_problem(), history: vec![], related: vec![], ..page };
+    assert_snapshot!("problem_detail_pdf", page.render().unwrap());
+
+    let page = AssignmentsTemplate { problems: vec![problem(), ProblemWithCategories { answer: None, solution_link: None, ..pdf_problem() }], user: user(), brand: Branding::default() };
+    assert_snapshot!("assignments", page.render().unwrap());
+}
+
+#[test]
+fn test_study_pages() {
+    let mut categories = categories();
+    categories.push(Category { id: 3, course_id: 1, name: "链式法则".to_string(), parent_id: Some(2), path: "导数/链式法则".to_string(), depth: 1 });
+    let page = CourseStudyTemplate {
+        course: course(),
+        courses: vec![course()],
+        categories,
+        semester: semester(),
+        filters: StudyFilters { sources: vec!["Homework".to_string()], categories: vec![1], sort: "newest".to_string(), difficulty: "hard".to_string(), include_previous: true, exam: Some(1), search: String::new() },
+        presets: vec![preset()],
+        goals: vec![goal()],
+        category_times: vec![
+            CategoryTime {
+                category_id: 1,
+                category_name: "极限".to_string(),
+                reviews: 4,
+                average_ms: 52_000.0,
+                correct_average_ms: Some(75_000.0),
+                course_correct_average_ms: Some(40_000.0),
+            },
+            CategoryTime {
+                category_id: 2,
+                category_name: "导数".to_string(),
+                reviews: 3,
+                average_ms: 20_000.0,
+                correct_average_ms: None,
+                course_correct_average_ms: Some(40_000.0),
+            },
+        ],
+        previous_course: Some(Course { id: 3, code: "MATH 1".to_string(), title: "微积分预备".to_string(), ..course() }),
+        scoped_exams: vec![exam()],
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("course_study", page.render().unwrap());
+    assert_snapshot!("study_problem_list", StudyProblemListTemplate { problems: vec![ProblemWithCategories { difficulty: Some(72), ..problem() }, pdf_problem()], course_id: 1, course_has_problems: true, offset: 0, next_offset: Some(30) }.render().unwrap());
+    assert_snapshot!("study_preset_chip", StudyPresetChipTemplate { preset: preset() }.render().unwrap());
+    assert_snapshot!("study_goal", StudyGoalTemplate { goal: StudyGoal { progress: 25, deadline: None, ..goal() } }.render().unwrap());
+    assert_snapshot!("study_review", StudyReviewTemplate { course: Some(course()), topic: None, user: user(), brand: Branding::default() }.render().unwrap());
+}
+
+#[test]
+fn test_study_topics_page() {
+    let member = |course_id: i64, course_code: &str, course_color: Option<&str>, category_id: i64, category_name: &str, problems: i64| TopicMember {
+        topic: String::new(),
+        course_id,
+        course_code: course_code.to_string(),
+        course_color: course_color.map(str::to_string),
+        category_id,
+        category_name: category_name.to_string(),
+        problems,
+    };
+    let page = StudyTopicsTemplate {
+        topics: vec![
+            StudyTopic { name: "动态规划".to_string(), members: vec![member(2, "CS 61B", None, 9, "动态规划", 2), member(1, "MATH 1A", Some("#2563eb"), 4, "DP", 3)] },
+            StudyTopic { name: "积分".to_string(), members: vec![member(1, "MATH 1A", Some("#2563eb"), 1, "积分", 5)] },
+        ],
+        aliases: vec![CategoryAlias { id: 1, alias: "DP".to_string(), topic: "动态规划".to_string() }],
+        notice: Some(("success".to_string(), "已将「DP」归入「动态规划」。".to_string())),
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("study_topics", page.render().unwrap());
+}
+
+#[test]
+fn test_empty_states() {
+    assert_snapshot!("index_empty", IndexTemplate { semesters: vec![], joined_courses: vec![], upcoming_exams: vec![], focus_suggestions: vec![], open_tasks: vec![], show_invites: false, translation_cache: None, join_error: None, user: user(), brand: Branding::default() }.render().unwrap());
+    let summary = grades::summarize(&[]);
+    assert_snapshot!("semester_empty", SemesterTemplate { semester: semester(), courses: vec![], summary, user: user(), brand: Branding::default() }.render().unwrap());
+    let page = CourseLogTemplate {
+        course: course(),
+        courses: vec![course()],
+        log_items: vec![],
+        semester: semester(),
+        categories: vec![],
+        kinds: kinds(),
+        previews: vec![],
+        view: "list".to_string(),
+        status_filter: None,
+        tag_filter: None,
+        tags: vec![],
+        problem_counts: vec![],
+        print_week: None,
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("course_log_empty", page.render().unwrap());
+    let page = CourseExamsTemplate {
+        course: course(),
+        courses: vec![course()],
+        exams: vec![],
+        semester: semester(),
+        categories: vec![],
+        coverage: vec![],
+        log_items: vec![],
+        scope: vec![],
+        presets: vec![],
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("course_exams_empty", page.render().unwrap());
+    assert_snapshot!("study_problem_list_empty", StudyProblemListTemplate { problems: vec![], course_id: 1, course_has_problems: false, offset: 0, next_offset: None }.render().unwrap());
+    assert_snapshot!("study_problem_list_filtered", StudyProblemListTemplate { problems: vec![], course_id: 1, course_has_problems: true, offset: 0, next_offset: None }.render().unwrap());
+}
+
+#[test]
+fn test_exam_pages() {
+    let page = CourseExamsTemplate {
+        course: course(),
+        courses: vec![course()],
+        exams: vec![exam()],
+        semester: semester(),
+        categories: categories(),
+        coverage: vec![(1, 1)],
+        log_items: log_items(),
+        scope: vec![(1, 1), (1, 2)],
+        presets: vec![preset()],
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("course_exams", page.render().unwrap());
+    assert_snapshot!("exam_item", ExamItemTemplate { exam: exam(), categories: categories(), coverage: vec![], log_items: vec![], scope: vec![] }.render().unwrap());
+    assert_snapshot!("exam_item_edit", ExamItemEditTemplate { exam: exam() }.render().unwrap());
+}
+
+#[test]
+fn test_exam_plan_page() {
+    let entry = |problem_id: i64, day: &str, done: bool| PlanEntry {
+        problem_id,
+        day: day.to_string(),
+        description: format!("错题 {}", problem_id),
+        category_names: if problem_id == 3 { None } else { Some("极限".to_string()) },
+        done,
+    };
+    let page = ExamPlanTemplate {
+        exam: exam(),
+        course: course(),
+        categories: categories(),
+        selected: vec![1],
+        days: vec![
+            PlanDay { day: "2026-10-16".to_string(), label: "10月16日 周五".to_string(), is_today: true, entries: vec![entry(1, "2026-10-16", true), entry(2, "2026-10-16", false)] },
+            PlanDay { day: "2026-10-17".to_string(), label: "10月17日 周六".to_string(), is_today: false, entries: vec![entry(3, "2026-10-17", false)] },
+        ],
+        moved: 1,
+        notice: None,
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("exam_plan", page.render().unwrap());
+}
+
+#[test]
+fn test_discussion_pages() {
+    let thread = DiscussionThread {
+        id: 1,
+        course_id: 1,
+        title: "期中复习分工".to_string(),
+        author: "alice".to_string(),
+        created_at: "2026-10-01 09:00:00".to_string(),
+        post_count: 2,
+        last_post_at: "2026-10-02 18:30:00".to_string(),
+        unread: true,
+        mine: true,
+    };
+    let post = |id: i64, author: &str, body: &str, mine: bool| DiscussionPost {
+        id,
+        thread_id: 1,
+        author: author.to_string(),
+        body: body.to_string(),
+        created_at: format!("2026-10-0{} 18:30:00", id),
+        mine,
+    };
+    let page = CourseDiscussionsTemplate {
+        course: course(),
+        courses: vec![course()],
+        semester: semester(),
+        threads: vec![thread.clone()],
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("course_discussions", page.render().unwrap());
+    let page = DiscussionThreadTemplate {
+        course: course(),
+        courses: vec![course()],
+        semester: semester(),
+        thread,
+        posts: vec![post(1, "alice", "我整理极限，谁来做积分？", true), post(2, "bob", "积分我来。", false)],
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("discussion_thread", page.render().unwrap());
+}
+
+#[test]
+fn test_digest_settings_page() {
+    let page = DigestSettingsTemplate {
+        subscription: Some(DigestSubscription {
+            user_id: 1,
+            email: "alice@example.com".to_string(),
+            weekday: 5,
+            hour: 20,
+            unsubscribe_token: "3f2a9c01b7de".to_string(),
+            last_sent_at: None,
+        }),
+        email_configured: false,
+        weekdays: WEEKDAYS,
+        hours: (0..24).collect(),
+        preview: "alice，你好：\n\n这一周没有新动态。\n".to_string(),
+        notice: Some(("success".to_string(), "邮件周报设置已保存。".to_string())),
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("digest_settings", page.render().unwrap());
+}
+
+#[test]
+fn test_account_pages() {
+    let page = AccountTemplate {
+        account: User {
+            id: 1,
+            username: "alice".to_string(),
+            password_hash: String::new(),
+            is_admin: true,
+            email: Some("alice@example.com".to_string()),
+            email_verified: false,
+        },
+        email_configured: true,
+        todos_url: Some("https://zhixi.example.com/todos.ics?token=3f2a9c01b7de".to_string()),
+        notice: Some(("success".to_string(), "验证邮件已发往 alice@example.com，打开其中的链接完成验证。".to_string())),
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("account", page.render().unwrap());
+
+    let page = AccountVerifyTemplate { token: "3f2a9c01b7de".to_string(), verified: None, user: None, brand: Branding::default() };
+    assert_snapshot!("account_verify", page.render().unwrap());
+}
+
+#[test]
+fn test_settings_and_history_pages() {
+    let member = CourseMember { user_id: 2, username: "bob".to_string(), role: "editor".to_string(), joined_at: "2026-09-03 10:00:00".to_string() };
+    let page = CourseSettingsTemplate {
+        course: Course { join_code: Some("3F2A9C01".to_string()), continues_course_id: Some(3), ..course() },
+        courses: vec![course()],
+        semester: semester(),
+        themes: PUBLIC_THEMES,
+        translation_levels: TRANSLATION_LEVELS,
+        translation_styles: TRANSLATION_STYLES,
+        course_roles: COURSE_ROLES,
+        kinds: kinds(),
+        members: vec![member],
+        previous_courses: vec![(2, "2026 秋季 · PHYS 7A 物理".to_string()), (3, "2026 春季 · MATH 1 微积分预备".to_string())],
+        categories: categories(),
+        carryovers: vec![
+            CategoryCarryover {
+                previous: Category { id: 11, course_id: 3, name: "极限".to_string(), parent_id: None, path: "极限".to_string(), depth: 0 },
+                category_id: Some(1),
+            },
+            CategoryCarryover {
+                previous: Category { id: 12, course_id: 3, name: "数列".to_string(), parent_id: None, path: "数列".to_string(), depth: 0 },
+                category_id: None,
+            },
+        ],
+        views: page_views::Counts { views: 128, visitors: 41 },
+        error: None,
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("course_settings", page.render().unwrap());
+
+    let change = |problem_id: i64, source_title: &str, before: &[&str], after: &[&str]| recategorize::Change {
+        problem_id,
+        number: problem_id,
+        source_title: source_title.to_string(),
+        before: before.iter().map(|s| s.to_string()).collect(),
+        after: after.iter().map(|s| s.to_string()).collect(),
+    };
+    let page = RecategorizeTemplate {
+        course: course(),
+        courses: vec![course()],
+        semester: semester(),
+        plan: recategorize::Plan {
+            changes: vec![change(1, "作业一", &["极限", "导数"], &["极限", "导数/链式法则"]), change(2, "期中考试", &["积分"], &[])],
+            unchanged: 5,
+            skipped: vec!["第 4 行：没有错题 P-099".to_string()],
+        },
+        applied: false,
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("course_recategorize", page.render().unwrap());
+
+    let entry = EntityHistory {
+        id: 1,
+        entity_type: "log_item".to_string(),
+        entity_id: 2,
+        course_id: Some(1),
+        action: "update".to_string(),
+        before_json: Some(serde_json::to_string(&log_items()[1]).unwrap()),
+        after_json: Some(serde_json::to_string(&LogItem { title: "作业二".to_string(), ..log_items()[1].clone() }).unwrap()),
+        user_id: Some(1),
+        created_at: "2026-09-10 12:00:00".to_string(),
+    };
+    let page = HistoryTemplate {
+        course: course(),
+        courses: vec![course()],
+        semester: semester(),
+        heading: "编辑历史".to_string(),
+        entries: vec![history_entry_view(entry)],
+        user: user(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("history", page.render().unwrap());
+}
+
+#[test]
+fn test_public_pages() {
+    let translations = std::collections::HashMap::new();
+    let (weeks, unscheduled, active_kinds) = build_calendar(log_items(), true, &translations, false, false, &kinds());
+    let page = PublicCalendarTemplate {
+        course: course(),
+        weeks,
+        unscheduled,
+        active_kinds,
+        lang: "zh".to_string(),
+        base_path: "/p/math-1a/zh".to_string(),
+        pending_translations: false,
+        brand: Branding::default(),
+    };
+    assert_snapshot!("public_calendar", page.render().unwrap());
+
+    let problems = vec![PublicProblem {
+        public_id: "0199a3b2-6c00-7c30-ae21-4f5c8d9b1a01".to_string(),
+        number: Some(1),
+        image_url: Some("/uploads/problem.png".to_string()),
+        body: None,
+        notes: Some("Forgot sin x / x → 1".to_string()),
+        category_names: Some("Limits".to_string()),
+        source_kind: "Homework".to_string(),
+        source_title: "Homework 1".to_string(),
+        solution_link: None,
+        original_filename: None,
+        mime_type: None,
+        notes_key: None,
+    }, PublicProblem {
+        public_id: "0199a3b2-6c00-7c30-ae21-4f5c8d9b1a02".to_string(),
+        number: Some(2),
+        image_url: Some("/uploads/ab/cdef.pdf".to_string()),
+        body: None,
+        notes: None,
+        category_names: None,
+        source_kind: "Lecture".to_string(),
+        source_title: "Lecture 3".to_string(),
+        solution_link: None,
+        original_filename: Some("lecture3-handout.pdf".to_string()),
+        mime_type: Some("application/pdf".to_string()),
+        notes_key: None,
+    }];
+    let page = PublicProblemEmbedTemplate {
+        course: course(),
+        problem: PublicProblem { category_names: Some("Limits,Series".to_string()), ..problems[0].clone() },
+        embed_url: "https://zhixi.example/p/math-1a/problems/0199a3b2-6c00-7c30-ae21-4f5c8d9b1a01/embed".to_string(),
+        base_url: "https://zhixi.example".to_string(),
+        brand: Branding::default(),
+    };
+    assert_snapshot!("public_problem_embed", page.render().unwrap());
+
+    let page = PublicProblemsTemplate {
+        course: course(),
+        problems,
+        all_categories: vec!["Limits".to_string()],
+        lang: "en".to_string(),
+        base_path: "/p/math-1a".to_string(),
+        pending_translations: false,
+        brand: Branding::default(),
+    };
+    assert_snapshot!("public_problems", page.render().unwrap());
+
+    let form = PublicReportTemplate {
+        slug: "math-1a".to_string(),
+        public_id: "0199a3b2-6c00-7c30-ae21-4f5c8d9b1a01".to_string(),
+        lang: "zh".to_string(),
+        reasons: REPORT_REASONS,
+        sent: false,
+    };
+    assert_snapshot!("public_report_form", form.render().unwrap());
+}
@@ -0,0 +1,189 @@
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use crate::config::UploadScanConfig;
+use crate::uploads;
+
+// ========== Upload Scanning ==========
+//
+// Every upload passes through `check` before it reaches upload storage. What
+// gets checked is set per deployment under `[default.upload_scan]`:
+//
+//   max_bytes = 10485760                  reject anything larger
+//   allowed_types = ["image/*", "application/pdf"]
+//   clamav_socket = "/run/clamav/clamd.ctl"
+//
+// The type check goes by the file's leading bytes where the format is one we
+// recognise, so a renamed executable doesn't pass as a PNG. Files of other
+// formats are judged by the Content-Type the browser sent. With a clamd socket
+// configured, files are streamed to it with INSTREAM; if clamd can't be
+// reached the upload is refused rather than stored unscanned.
+
+const CLAMAV_CHUNK: usize = 64 * 1024;
+const CLAMAV_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Why an upload was refused. `message` is shown to the user.
+#[derive(Debug, PartialEq)]
+pub enum Rejection {
+    TooLarge { max_bytes: u64 },
+    DisallowedType(String),
+    Mismatch { declared: String },
+    Infected(String),
+    ScannerUnavailable,
+}
+
+impl Rejection {
+    pub fn message(&self) -> String {
+        match self {
+            Rejection::TooLarge { max_bytes } => format!("文件过大，上限为 {}。", uploads::format_size(*max_bytes)),
+            Rejection::DisallowedType(mime) => format!("不允许上传此类型的文件（{}）。", mime),
+            Rejection::Mismatch { declared } => format!("文件内容与类型 {} 不符。", declared),
+            Rejection::Infected(signature) => format!("文件未通过病毒扫描（{}）。", signature),
+            Rejection::ScannerUnavailable => "病毒扫描服务暂不可用，请稍后再试。".to_string(),
+        }
+    }
+}
+
+/// Run the configured checks over an upload's contents. `declared` is the
+/// MIME type the client sent, if any.
+pub async fn check(config: &UploadScanConfig, bytes: &[u8], declared: Option<&str>) -> Result<(), Rejection> {
+    if let Some(max_bytes) = config.max_bytes {
+        if bytes.len() as u64 > max_bytes {
+            return Err(Rejection::TooLarge { max_bytes });
+        }
+    }
+
+    if !config.allowed_types.is_empty() {
+        let mime = content_type(bytes, declared)?;
+        if !config.allowed_types.iter().any(|pattern| type_matches(pattern, &mime)) {
+            return Err(Rejection::DisallowedType(mime));
+        }
+    }
+
+    if let Some(socket) = &config.clamav_socket {
+        match tokio::time::timeout(CLAMAV_TIMEOUT, clamav_scan(socket, bytes)).await {
+            Ok(Ok(None)) => {}
+            Ok(Ok(Some(signature))) => return Err(Rejection::Infected(signature)),
+            Ok(Err(e)) => {
+                eprintln!("clamd scan via {} failed: {}", socket, e);
+                return Err(Rejection::ScannerUnavailable);
+            }
+            Err(_) => {
+                eprintln!("clamd scan via {} timed out", socket);
+                return Err(Rejection::ScannerUnavailable);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Formats recognised by their leading bytes.
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if bytes.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else {
+        None
+    }
+}
+
+const SNIFFED_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp", "application/pdf"];
+
+// The type to judge an upload by: what its bytes say, else what the client
+// said. Claiming a sniffable type the bytes don't back up is refused outright.
+fn content_type(bytes: &[u8], declared: Option<&str>) -> Result<String, Rejection> {
+    let declared = declared.map(|d| d.to_ascii_lowercase());
+    match (sniff(bytes), declared) {
+        (Some(sniffed), _) => Ok(sniffed.to_string()),
+        (None, Some(declared)) if SNIFFED_TYPES.contains(&declared.as_str()) => Err(Rejection::Mismatch { declared }),
+        (None, Some(declared)) => Ok(declared),
+        (None, None) => Ok("application/octet-stream".to_string()),
+    }
+}
+
+fn type_matches(pattern: &str, mime: &str) -> bool {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    match pattern.strip_suffix("/*") {
+        Some(top) => mime.split('/').next() == Some(top),
+        None => pattern == "*/*" || pattern == mime,
+    }
+}
+
+// clamd's INSTREAM command: length-prefixed chunks ended by a zero length.
+// Replies "stream: OK" when clean or "stream: <signature> FOUND".
+async fn clamav_scan(socket: &str, bytes: &[u8]) -> std::io::Result<Option<String>> {
+    let mut stream = UnixStream::connect(socket).await?;
+    stream.write_all(b"zINSTREAM\0").await?;
+    for chunk in bytes.chunks(CLAMAV_CHUNK) {
+        stream.write_all(&(chunk.len() as u32).to_be_bytes()).await?;
+        stream.write_all(chunk).await?;
+    }
+    stream.write_all(&0u32.to_be_bytes()).await?;
+
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await?;
+    parse_clamav_reply(&String::from_utf8_lossy(&reply))
+}
+
+fn parse_clamav_reply(reply: &str) -> std::io::Result<Option<String>> {
+    let reply = reply.trim_end_matches(['\0', '\n']).trim();
+    let result = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+    if result == "OK" {
+        Ok(None)
+    } else if let Some(signature) = result.strip_suffix(" FOUND") {
+        Ok(Some(signature.to_string()))
+    } else {
+        Err(std::io::Error::other(format!("unexpected clamd reply: {}", reply)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    fn images_only() -> UploadScanConfig {
+        UploadScanConfig { allowed_types: vec!["image/*".to_string()], ..Default::default() }
+    }
+
+    #[rocket::async_test]
+    async fn test_check_type_policy() {
+        assert_eq!(check(&images_only(), PNG, Some("image/png")).await, Ok(()));
+        // The bytes win over a wrong Content-Type
+        assert_eq!(check(&images_only(), PNG, Some("application/octet-stream")).await, Ok(()));
+        assert_eq!(
+            check(&images_only(), b"%PDF-1.7", Some("image/png")).await,
+            Err(Rejection::DisallowedType("application/pdf".to_string()))
+        );
+        assert_eq!(
+            check(&images_only(), b"MZ\x90\0", Some("image/png")).await,
+            Err(Rejection::Mismatch { declared: "image/png".to_string() })
+        );
+        assert_eq!(check(&UploadScanConfig::default(), b"MZ\x90\0", Some("image/png")).await, Ok(()));
+    }
+
+    #[rocket::async_test]
+    async fn test_check_size_and_unreachable_scanner() {
+        let config = UploadScanConfig { max_bytes: Some(4), ..Default::default() };
+        assert_eq!(check(&config, PNG, None).await, Err(Rejection::TooLarge { max_bytes: 4 }));
+
+        let config = UploadScanConfig { clamav_socket: Some("/nonexistent/clamd.ctl".to_string()), ..Default::default() };
+        assert_eq!(check(&config, PNG, None).await, Err(Rejection::ScannerUnavailable));
+    }
+
+    #[test]
+    fn test_parse_clamav_reply() {
+        assert_eq!(parse_clamav_reply("stream: OK\0").unwrap(), None);
+        assert_eq!(parse_clamav_reply("stream: Eicar-Signature FOUND\0").unwrap().as_deref(), Some("Eicar-Signature"));
+        assert!(parse_clamav_reply("INSTREAM size limit exceeded. ERROR\0").is_err());
+    }
+}
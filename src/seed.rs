@@ -0,0 +1,133 @@
+use rocket_db_pools::sqlx::{self, SqlitePool};
+use crate::uploads;
+
+// ========== Demo Data ==========
+//
+// `zhixi seed` fills an empty instance with one semester's worth of sample
+// data so the UI can be evaluated (or templates worked on) without typing
+// everything in by hand. The sample screenshots are compiled into the binary.
+
+const DEMO_SEMESTER: &str = "示例学期";
+
+const DEMO_IMAGES: &[(&str, &[u8])] = &[
+    ("limit", include_bytes!("../demo/limit.svg")),
+    ("derivative", include_bytes!("../demo/derivative.svg")),
+    ("integral", include_bytes!("../demo/integral.svg")),
+];
+
+// (kind, title, date, description)
+const DEMO_LOG_ITEMS: &[(&str, &str, &str, &str)] = &[
+    ("Lecture", "第一讲", "2026-09-01", "函数与极限的定义"),
+    ("Lecture", "第二讲", "2026-09-03", "极限的运算法则，两个重要极限"),
+    ("Discussion", "讨论一", "2026-09-04", "极限计算练习"),
+    ("Homework", "作业一", "2026-09-08", "极限与连续"),
+    ("Lecture", "第三讲", "2026-09-08", "导数的定义与求导法则"),
+    ("Quiz", "测验一", "2026-09-12", "极限与导数"),
+    ("Homework", "作业二", "2026-09-15", "求导与积分初步"),
+];
+
+// (log item title, image key, notes, categories)
+const DEMO_PROBLEMS: &[(&str, &str, &str, &[&str])] = &[
+    ("作业一", "limit", "忘了用 sin x / x → 1，先凑成 3 · sin(3x) / (3x)。", &["极限"]),
+    ("测验一", "derivative", "乘积法则漏掉了一项。", &["导数", "乘积法则"]),
+    ("作业二", "integral", "分部积分时 u 和 dv 选反了。", &["积分"]),
+];
+
+/// Seed demo data. Does nothing if the demo semester already exists.
+pub async fn run(pool: &SqlitePool) {
+    let existing: Option<i64> = sqlx::query_scalar("SELECT id FROM semesters WHERE name = ?")
+        .bind(DEMO_SEMESTER)
+        .fetch_optional(pool)
+        .await
+        .unwrap();
+    if existing.is_some() {
+        println!("Demo data already present (semester \"{}\"), skipping.", DEMO_SEMESTER);
+        return;
+    }
+
+    // The first account's; seeded before /setup, the admin claims it there
+    let semester_id = sqlx::query("INSERT INTO semesters (name, user_id) VALUES (?, (SELECT MIN(id) FROM users))")
+        .bind(DEMO_SEMESTER)
+        .execute(pool)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+
+    let course_id = sqlx::query("INSERT INTO courses (semester_id, code, title, units, color) VALUES (?, ?, ?, ?, ?)")
+        .bind(semester_id)
+        .bind("MATH 1A")
+        .bind("微积分")
+        .bind(4.0)
+        .bind("#2563eb")
+        .execute(pool)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+
+    for (kind, title, date, description) in DEMO_LOG_ITEMS {
+        sqlx::query("INSERT INTO log_items (course_id, kind, title, description, date) VALUES (?, ?, ?, ?, ?)")
+            .bind(course_id)
+            .bind(kind)
+            .bind(title)
+            .bind(description)
+            .bind(date)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    let mut conn = pool.acquire().await.unwrap();
+    for (log_title, image_key, notes, categories) in DEMO_PROBLEMS {
+        let log_item_id: i64 = sqlx::query_scalar("SELECT id FROM log_items WHERE course_id = ? AND title = ?")
+            .bind(course_id)
+            .bind(log_title)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+
+        let (_, bytes) = DEMO_IMAGES.iter().find(|(key, _)| key == image_key).unwrap();
+        let image_url = uploads::store(&mut conn, bytes, "svg").await.unwrap();
+
+        let problem_id = sqlx::query("INSERT INTO problems (log_item_id, description, notes, image_url, is_incorrect, created_at) VALUES (?, ?, ?, ?, 1, CURRENT_TIMESTAMP)")
+            .bind(log_item_id)
+            .bind("Screenshot Problem")
+            .bind(notes)
+            .bind(&image_url)
+            .execute(pool)
+            .await
+            .unwrap()
+            .last_insert_rowid();
+
+        for name in *categories {
+            let existing: Option<i64> = sqlx::query_scalar("SELECT id FROM categories WHERE course_id = ? AND name = ?")
+                .bind(course_id)
+                .bind(name)
+                .fetch_optional(pool)
+                .await
+                .unwrap();
+            let category_id = match existing {
+                Some(id) => id,
+                None => sqlx::query("INSERT INTO categories (course_id, name) VALUES (?, ?)")
+                    .bind(course_id)
+                    .bind(name)
+                    .execute(pool)
+                    .await
+                    .unwrap()
+                    .last_insert_rowid(),
+            };
+            sqlx::query("INSERT INTO problem_categories (problem_id, category_id) VALUES (?, ?)")
+                .bind(problem_id)
+                .bind(category_id)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+    }
+
+    println!(
+        "Seeded semester \"{}\" with 1 course, {} log items and {} problems.",
+        DEMO_SEMESTER,
+        DEMO_LOG_ITEMS.len(),
+        DEMO_PROBLEMS.len()
+    );
+}
@@ -0,0 +1,45 @@
+use rocket_db_pools::sqlx::{self, SqliteConnection};
+
+// ========== Instance Settings ==========
+//
+// Values the admin picks in the first-run wizard at /setup, stored in
+// `instance_settings`. Every key is optional: an instance set up before the
+// wizard existed simply has no rows and keeps the old defaults.
+
+pub const INSTANCE_NAME: &str = "instance_name";
+pub const UPLOAD_DIR: &str = "upload_dir";
+pub const TRANSLATION_API_KEY: &str = "translation_api_key";
+pub const LOGO_URL: &str = "logo_url";
+pub const FOOTER_TEXT: &str = "footer_text";
+
+pub const DEFAULT_INSTANCE_NAME: &str = "智习";
+
+pub async fn get(db: &mut SqliteConnection, key: &str) -> Option<String> {
+    sqlx::query_scalar("SELECT value FROM instance_settings WHERE key = ?")
+        .bind(key)
+        .fetch_optional(&mut *db)
+        .await
+        .unwrap_or(None)
+        .filter(|v: &String| !v.is_empty())
+}
+
+pub async fn set(db: &mut SqliteConnection, key: &str, value: &str) {
+    let _ = sqlx::query("INSERT OR REPLACE INTO instance_settings (key, value) VALUES (?, ?)")
+        .bind(key)
+        .bind(value)
+        .execute(&mut *db)
+        .await;
+}
+
+pub async fn instance_name(db: &mut SqliteConnection) -> String {
+    get(db, INSTANCE_NAME).await.unwrap_or_else(|| DEFAULT_INSTANCE_NAME.to_string())
+}
+
+/// The OpenRouter key for course translations. `OPENROUTER_API_KEY` in the
+/// environment still takes precedence over the one saved during setup.
+pub async fn translation_api_key(db: &mut SqliteConnection) -> Option<String> {
+    match std::env::var("OPENROUTER_API_KEY") {
+        Ok(key) if !key.is_empty() => Some(key),
+        _ => get(db, TRANSLATION_API_KEY).await,
+    }
+}
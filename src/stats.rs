@@ -0,0 +1,290 @@
+use chrono::{Duration as ChronoDuration, NaiveDate, Utc};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Request, Response};
+use rocket_db_pools::sqlx::{self, FromRow, SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use crate::{jobs, page_views, translate, uploads};
+
+// ========== Instance Stats ==========
+//
+// The admin dashboard at /admin/stats: who uses the instance and how much,
+// what it stores, what LLM translation has cost and how busy it is. Most of
+// it is counted from the data itself. Two things aren't recorded anywhere
+// else, so they get tables of their own:
+//
+// - Requests are counted in memory by the `RequestCounter` fairing and added
+//   to the day's row of `request_counts` by a job every few minutes (and when
+//   the dashboard is opened), so serving a page never costs a write.
+// - Each OpenRouter call is logged to `llm_usage` with the tokens and cost
+//   OpenRouter reports for it.
+
+pub const JOB_KIND: &str = "flush_request_counts";
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Days covered by the dashboard's charts, today included.
+pub const CHART_DAYS: i64 = 30;
+
+static PENDING_REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+pub struct RequestCounter;
+
+#[rocket::async_trait]
+impl Fairing for RequestCounter {
+    fn info(&self) -> Info {
+        Info { name: "Request Counter", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, _req: &'r Request<'_>, _res: &mut Response<'r>) {
+        PENDING_REQUESTS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Add the requests counted since the last flush to today's (UTC) total.
+pub async fn flush(db: &mut SqliteConnection) {
+    let pending = PENDING_REQUESTS.swap(0, Ordering::Relaxed);
+    if pending == 0 {
+        return;
+    }
+    let written = sqlx::query(
+        "INSERT INTO request_counts (day, requests) VALUES (date('now'), ?) ON CONFLICT (day) DO UPDATE SET requests = requests + excluded.requests"
+    )
+    .bind(pending as i64)
+    .execute(&mut *db)
+    .await;
+    if written.is_err() {
+        // Keep them for the next flush
+        PENDING_REQUESTS.fetch_add(pending, Ordering::Relaxed);
+    }
+}
+
+/// Replace any queued flush with one a FLUSH_INTERVAL from now. Called once
+/// at startup; each run queues the next.
+pub async fn schedule(pool: &SqlitePool) {
+    let Ok(mut conn) = pool.acquire().await else { return };
+    let _ = sqlx::query("DELETE FROM jobs WHERE kind = ?")
+        .bind(JOB_KIND)
+        .execute(&mut *conn)
+        .await;
+    jobs::enqueue_after(&mut conn, JOB_KIND, "", FLUSH_INTERVAL).await;
+}
+
+/// Job handler: write out the request count, and the public page views
+/// counted alongside it.
+pub async fn flush_requests(pool: &SqlitePool, tally: &page_views::Tally, payload: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.acquire().await?;
+    jobs::enqueue_after(&mut conn, JOB_KIND, payload, FLUSH_INTERVAL).await;
+    flush(&mut conn).await;
+    page_views::flush(&mut conn, tally).await;
+    Ok(())
+}
+
+/// Log one OpenRouter call from the `usage` object of its response. Fields
+/// it doesn't have count as zero.
+pub async fn record_llm_call(db: &mut SqliteConnection, model: &str, usage: &serde_json::Value) {
+    let _ = sqlx::query("INSERT INTO llm_usage (model, prompt_tokens, completion_tokens, cost, called_at) VALUES (?, ?, ?, ?, CURRENT_TIMESTAMP)")
+        .bind(model)
+        .bind(usage["prompt_tokens"].as_i64().unwrap_or(0))
+        .bind(usage["completion_tokens"].as_i64().unwrap_or(0))
+        .bind(usage["cost"].as_f64().unwrap_or(0.0))
+        .execute(&mut *db)
+        .await;
+}
+
+/// One user's footprint on the instance
+#[derive(Debug, Clone, FromRow)]
+pub struct UserActivity {
+    pub username: String,
+    pub is_admin: bool,
+    /// Courses joined by code
+    pub courses: i64,
+    pub reviews: i64,
+    /// Log item and problem edits, from entity_history
+    pub edits: i64,
+    pub posts: i64,
+    /// When they last reviewed a problem
+    pub last_review: Option<String>,
+}
+
+/// One day's column in a dashboard chart
+#[derive(Debug, Clone, PartialEq)]
+pub struct DayBar {
+    /// "10-16"
+    pub label: String,
+    /// The value for people, e.g. "1,024 次" or "$0.12"
+    pub title: String,
+    /// Height against the busiest day, 0–100
+    pub percent: i64,
+}
+
+/// The CHART_DAYS days up to `today`, oldest first, with `values` by
+/// "YYYY-MM-DD" day; days without a value are zero.
+pub fn daily_bars(today: NaiveDate, values: &HashMap<String, f64>, title: impl Fn(f64) -> String) -> Vec<DayBar> {
+    let days: Vec<NaiveDate> = (0..CHART_DAYS).rev().map(|ago| today - ChronoDuration::days(ago)).collect();
+    let value = |day: &NaiveDate| values.get(&day.format("%Y-%m-%d").to_string()).copied().unwrap_or(0.0);
+    let max = days.iter().map(value).fold(0.0, f64::max);
+    days.iter()
+        .map(|day| {
+            let v = value(day);
+            DayBar {
+                label: day.format("%m-%d").to_string(),
+                title: format!("{} {}", day.format("%m-%d"), title(v)),
+                percent: if max > 0.0 { (v / max * 100.0).round() as i64 } else { 0 },
+            }
+        })
+        .collect()
+}
+
+pub struct InstanceStats {
+    pub users: Vec<UserActivity>,
+    /// (what, how many) across every course
+    pub totals: Vec<(&'static str, i64)>,
+    pub uploads: i64,
+    pub upload_bytes: i64,
+    pub database_bytes: i64,
+    pub translation_cache: translate::CacheStats,
+    pub llm_calls: i64,
+    pub llm_tokens: i64,
+    /// US dollars, all time and over the chart's days
+    pub llm_cost: f64,
+    pub llm_cost_recent: f64,
+    pub requests_recent: i64,
+    pub request_chart: Vec<DayBar>,
+    pub llm_chart: Vec<DayBar>,
+}
+
+impl InstanceStats {
+    pub fn upload_size(&self) -> String {
+        uploads::format_size(self.upload_bytes as u64)
+    }
+
+    pub fn database_size(&self) -> String {
+        uploads::format_size(self.database_bytes as u64)
+    }
+
+    pub fn llm_cost_label(&self) -> String {
+        cost_label(self.llm_cost)
+    }
+
+    pub fn llm_cost_recent_label(&self) -> String {
+        cost_label(self.llm_cost_recent)
+    }
+}
+
+fn cost_label(cost: f64) -> String {
+    format!("${:.2}", cost)
+}
+
+// Entities counted on the dashboard: (label, table)
+const TOTALS: &[(&str, &str)] = &[
+    ("学期", "semesters"),
+    ("课程", "courses"),
+    ("记录", "log_items"),
+    ("错题", "problems"),
+    ("分类", "categories"),
+    ("考卷", "exams"),
+    ("复习", "study_reviews"),
+    ("讨论", "discussion_posts"),
+];
+
+async fn count(db: &mut SqliteConnection, sql: &str) -> i64 {
+    sqlx::query_scalar(sql).fetch_one(&mut *db).await.unwrap_or(0)
+}
+
+// (day, value) rows as a map for daily_bars
+async fn by_day(db: &mut SqliteConnection, sql: &str) -> HashMap<String, f64> {
+    sqlx::query_as::<_, (String, f64)>(sql)
+        .bind(format!("-{} days", CHART_DAYS - 1))
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .collect()
+}
+
+/// Everything on the dashboard. Flushes the request count first so today's
+/// column is current.
+pub async fn collect(db: &mut SqliteConnection) -> InstanceStats {
+    flush(db).await;
+
+    let users = sqlx::query_as::<_, UserActivity>(
+        r#"
+        SELECT
+            u.username, u.is_admin,
+            (SELECT COUNT(*) FROM course_members m WHERE m.user_id = u.id) as courses,
+            (SELECT COUNT(*) FROM study_reviews r WHERE r.user_id = u.id) as reviews,
+            (SELECT COUNT(*) FROM entity_history h WHERE h.user_id = u.id) as edits,
+            (SELECT COUNT(*) FROM discussion_posts p WHERE p.user_id = u.id) as posts,
+            (SELECT MAX(r.reviewed_at) FROM study_reviews r WHERE r.user_id = u.id) as last_review
+        FROM users u
+        ORDER BY reviews DESC, u.username
+        "#,
+    )
+    .fetch_all(&mut *db)
+    .await
+    .unwrap_or_default();
+
+    let mut totals = Vec::with_capacity(TOTALS.len());
+    for (label, table) in TOTALS {
+        totals.push((*label, count(db, &format!("SELECT COUNT(*) FROM {}", table)).await));
+    }
+
+    let (uploads, upload_bytes): (i64, i64) = sqlx::query_as("SELECT COUNT(*), COALESCE(SUM(size), 0) FROM uploads")
+        .fetch_one(&mut *db)
+        .await
+        .unwrap_or((0, 0));
+    let database_bytes = count(db, "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()").await;
+    let translation_cache = translate::cache_stats(db).await;
+
+    let (llm_calls, llm_tokens, llm_cost): (i64, i64, f64) = sqlx::query_as(
+        "SELECT COUNT(*), COALESCE(SUM(prompt_tokens + completion_tokens), 0), COALESCE(SUM(cost), 0.0) FROM llm_usage"
+    )
+    .fetch_one(&mut *db)
+    .await
+    .unwrap_or((0, 0, 0.0));
+
+    let today = Utc::now().date_naive();
+    let requests = by_day(db, "SELECT day, CAST(requests AS REAL) FROM request_counts WHERE day >= date('now', ?)").await;
+    let costs = by_day(db, "SELECT date(called_at), SUM(cost) FROM llm_usage WHERE called_at >= date('now', ?) GROUP BY date(called_at)").await;
+    let requests_recent = requests.values().sum::<f64>() as i64;
+    let llm_cost_recent = costs.values().sum();
+
+    InstanceStats {
+        users,
+        totals,
+        uploads,
+        upload_bytes,
+        database_bytes,
+        translation_cache,
+        llm_calls,
+        llm_tokens,
+        llm_cost,
+        llm_cost_recent,
+        requests_recent,
+        request_chart: daily_bars(today, &requests, |v| format!("{} 次请求", v as i64)),
+        llm_chart: daily_bars(today, &costs, cost_label),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_daily_bars() {
+        let today = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        let values = HashMap::from([("2026-10-16".to_string(), 40.0), ("2026-10-14".to_string(), 10.0), ("2026-08-01".to_string(), 99.0)]);
+        let bars = daily_bars(today, &values, |v| format!("{} 次请求", v));
+
+        assert_eq!(bars.len(), CHART_DAYS as usize);
+        assert_eq!(bars[0].label, "09-17");
+        let last = bars.last().unwrap();
+        assert_eq!((last.label.as_str(), last.title.as_str(), last.percent), ("10-16", "10-16 40 次请求", 100));
+        assert_eq!(bars[bars.len() - 3].percent, 25);
+        assert_eq!(bars[bars.len() - 2].percent, 0);
+
+        assert!(daily_bars(today, &HashMap::new(), |v| v.to_string()).iter().all(|bar| bar.percent == 0));
+    }
+}
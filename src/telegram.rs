@@ -0,0 +1,288 @@
+use rocket::http::uri::Host;
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::{json, Json, Value};
+use rocket::serde::Deserialize;
+use rocket::State;
+use rocket_db_pools::sqlx;
+use rocket_db_pools::Connection;
+use std::time::Duration;
+use crate::access;
+use crate::category_cache::CategoryCache;
+use crate::config::{AppConfig, TelegramChat, TelegramConfig};
+use crate::db::Db;
+use crate::link_preview;
+use crate::models::*;
+use crate::queries;
+use crate::routes::insert_log_problem;
+use crate::scan;
+use crate::uploads::{self, SavedUpload};
+
+// ========== Telegram Capture ==========
+//
+// Forwarding something to the instance's Telegram bot files it under the
+// course named by a hashtag in the message, for collecting from a phone:
+//
+//   photo or file, caption "#MATH1A 第 3 题"  ->  a problem (notes "第 3 题")
+//   text "#MATH1A 期中范围到第五章"           ->  a log item of kind Other
+//
+// Each allowed chat belongs to a user, and only that user's editable courses
+// are looked at. The hashtag is the course code without spaces, in any case;
+// if codes repeat across semesters the newest course wins. Problems go under a per-day
+// "聊天收集" log item in that course and pass the same upload scan as the web
+// form. The bot answers in the chat with what it did.
+//
+// Register the webhook with Telegram's setWebhook, passing
+// url=<public_url>/integrations/telegram and the configured secret_token.
+
+const API_BASE: &str = "https://api.telegram.org";
+const CAPTURE_TITLE: &str = "聊天收集";
+const MAX_TITLE_CHARS: usize = 120;
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Update {
+    message: Option<Message>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Message {
+    chat: Chat,
+    text: Option<String>,
+    caption: Option<String>,
+    /// The same photo in increasing sizes
+    photo: Option<Vec<PhotoSize>>,
+    document: Option<Document>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Chat {
+    id: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct PhotoSize {
+    file_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Document {
+    file_id: String,
+    file_name: Option<String>,
+    mime_type: Option<String>,
+}
+
+/// The X-Telegram-Bot-Api-Secret-Token header, if sent.
+struct SecretToken(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SecretToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(SecretToken(request.headers().get_one("X-Telegram-Bot-Api-Secret-Token").map(String::from)))
+    }
+}
+
+/// Split "#MATH1A 第 3 题" into ("MATH1A", "第 3 题"): the first hashtag and
+/// the text around it.
+fn split_hashtag(text: &str) -> Option<(String, String)> {
+    let word = text.split_whitespace().find(|w| w.starts_with('#') && w.len() > 1)?;
+    let tag = word[1..].trim_end_matches(|c: char| !c.is_alphanumeric()).to_string();
+    if tag.is_empty() {
+        return None;
+    }
+    let rest = text.replacen(word, "", 1);
+    Some((tag, rest.trim().to_string()))
+}
+
+// The user a chat is configured for, if they exist
+async fn chat_user(db: &mut Connection<Db>, chats: &[TelegramChat], chat_id: i64) -> Option<i64> {
+    let chat = chats.iter().find(|chat| chat.chat_id == chat_id)?;
+    sqlx::query_scalar("SELECT id FROM users WHERE username = ?")
+        .bind(&chat.username)
+        .fetch_optional(&mut ***db)
+        .await
+        .unwrap_or(None)
+}
+
+async fn find_course(db: &mut Connection<Db>, user_id: i64, tag: &str) -> Option<Course> {
+    sqlx::query_as::<_, Course>(&format!(
+        "SELECT * FROM courses WHERE REPLACE(UPPER(code), ' ', '') = ? AND id IN ({}) ORDER BY semester_id DESC, id DESC LIMIT 1",
+        access::EDITABLE_COURSES,
+    ))
+        .bind(tag.to_uppercase())
+        .bind(user_id)
+        .fetch_optional(&mut ***db)
+        .await
+        .unwrap_or(None)
+}
+
+// Today's capture log item in the course, created on first use
+async fn capture_log_item(db: &mut Connection<Db>, course_id: i64) -> LogItem {
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let existing: Option<i64> = sqlx::query_scalar("SELECT id FROM log_items WHERE course_id = ? AND kind = 'Other' AND title = ? AND date = ?")
+        .bind(course_id)
+        .bind(CAPTURE_TITLE)
+        .bind(&today)
+        .fetch_optional(&mut ***db)
+        .await
+        .unwrap_or(None);
+    let id = match existing {
+        Some(id) => id,
+        None => sqlx::query("INSERT INTO log_items (course_id, kind, title, date) VALUES (?, 'Other', ?, ?)")
+            .bind(course_id)
+            .bind(CAPTURE_TITLE)
+            .bind(&today)
+            .execute(&mut ***db)
+            .await
+            .unwrap()
+            .last_insert_rowid(),
+    };
+    queries::fetch_log_item(db, id).await.unwrap()
+}
+
+// Fetch a file the bot received: getFile gives its path on Telegram's file server
+async fn download(bot_token: &str, file_id: &str) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+    let info: Value = client
+        .get(format!("{}/bot{}/getFile", API_BASE, bot_token))
+        .query(&[("file_id", file_id)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let path = info["result"]["file_path"].as_str().ok_or("getFile returned no file_path")?;
+    let bytes = client
+        .get(format!("{}/file/bot{}/{}", API_BASE, bot_token, path))
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    Ok(bytes.to_vec())
+}
+
+// A webhook reply that makes the bot send `text` to the chat
+fn reply(chat_id: i64, text: String) -> Json<Value> {
+    Json(json!({ "method": "sendMessage", "chat_id": chat_id, "text": text }))
+}
+
+#[post("/telegram", format = "json", data = "<update>")]
+async fn telegram_webhook(
+    mut db: Connection<Db>,
+    config: &State<AppConfig>,
+    category_cache: &State<CategoryCache>,
+    host: Option<&Host<'_>>,
+    secret: SecretToken,
+    update: Json<Update>,
+) -> Result<Json<Value>, Status> {
+    let TelegramConfig { bot_token, secret_token, chats } = &config.telegram;
+    let Some(expected) = secret_token else {
+        return Err(Status::NotFound);
+    };
+    if secret.0.as_deref() != Some(expected.as_str()) {
+        return Err(Status::Forbidden);
+    }
+    // Anything but a message from a chat with a user is acknowledged and
+    // dropped, since Telegram keeps redelivering updates that get an error
+    let Some(message) = update.into_inner().message else {
+        return Ok(Json(json!({})));
+    };
+    let chat_id = message.chat.id;
+    let Some(user_id) = chat_user(&mut db, chats, chat_id).await else {
+        return Ok(Json(json!({})));
+    };
+    let base_url = config.base_url(host.map(|h| h.to_string()).as_deref());
+
+    let text = message.caption.as_deref().or(message.text.as_deref()).unwrap_or_default();
+    let Some((tag, rest)) = split_hashtag(text) else {
+        return Ok(reply(chat_id, "请在消息里用 #课程代码 标明课程，例如 #MATH1A。".to_string()));
+    };
+    let Some(course) = find_course(&mut db, user_id, &tag).await else {
+        return Ok(reply(chat_id, format!("找不到课程 #{}。", tag)));
+    };
+
+    let file = match (&message.photo, &message.document) {
+        (Some(sizes), _) => sizes.last().map(|p| (p.file_id.clone(), None, Some("image/jpeg".to_string()))),
+        (None, Some(doc)) => Some((doc.file_id.clone(), doc.file_name.as_deref().and_then(uploads::clean_filename), doc.mime_type.clone())),
+        (None, None) => None,
+    };
+
+    let Some((file_id, original_filename, mime_type)) = file else {
+        if rest.is_empty() {
+            return Ok(reply(chat_id, "消息里除了课程标签没有内容。".to_string()));
+        }
+        let (title, description) = match rest.split_once('\n') {
+            Some((first, more)) => (first.trim(), Some(more.trim()).filter(|d| !d.is_empty())),
+            None => (rest.as_str(), None),
+        };
+        let title: String = title.chars().take(MAX_TITLE_CHARS).collect();
+        let item_id = sqlx::query("INSERT INTO log_items (course_id, kind, title, description, date) VALUES (?, 'Other', ?, ?, ?)")
+            .bind(course.id)
+            .bind(&title)
+            .bind(description)
+            .bind(chrono::Local::now().format("%Y-%m-%d").to_string())
+            .execute(&mut **db)
+            .await
+            .unwrap()
+            .last_insert_rowid();
+        if let Some(description) = description {
+            link_preview::queue_previews(&mut db, description).await;
+        }
+        return Ok(reply(chat_id, format!("已记录到 {}：{}\n{}/courses/{}#log-{}", course.code, title, base_url, course.id, item_id)));
+    };
+
+    let Some(bot_token) = bot_token else {
+        return Ok(reply(chat_id, "服务器没有配置 bot_token，无法下载文件。".to_string()));
+    };
+    let bytes = match download(bot_token, &file_id).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("telegram download of {} failed: {}", file_id, e);
+            return Ok(reply(chat_id, "文件下载失败，请稍后再发一次。".to_string()));
+        }
+    };
+    // Telegram's own mime_type is a hint; the scan goes by the bytes where it can
+    let mime_type = mime_type.or_else(|| {
+        original_filename.as_deref()
+            .and_then(|name| name.rsplit_once('.'))
+            .and_then(|(_, ext)| ContentType::from_extension(ext))
+            .map(|ct| format!("{}/{}", ct.top(), ct.sub()))
+    });
+    if let Err(rejection) = scan::check(&config.upload_scan, &bytes, mime_type.as_deref()).await {
+        return Ok(reply(chat_id, rejection.message()));
+    }
+    let ext = uploads::extension_for(&bytes, mime_type.as_deref(), original_filename.as_deref());
+    let url = uploads::store(&mut db, &bytes, &ext).await.map_err(|_| Status::InternalServerError)?;
+
+    let log_item = capture_log_item(&mut db, course.id).await;
+    let notes = Some(rest).filter(|r| !r.is_empty());
+    let upload = SavedUpload { url, original_filename, mime_type, imported_from: None };
+    let problem = insert_log_problem(&mut db, category_cache, &log_item, Some(upload), None, notes, None, None, None).await;
+    Ok(reply(chat_id, format!("已添加到 {} 的错题：{}/problems/{}", course.code, base_url, problem.id)))
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![telegram_webhook]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_hashtag() {
+        assert_eq!(split_hashtag("#MATH1A 第 3 题"), Some(("MATH1A".to_string(), "第 3 题".to_string())));
+        assert_eq!(split_hashtag("期中范围 #cs61b。\n到第五章"), Some(("cs61b".to_string(), "期中范围 \n到第五章".to_string())));
+        assert_eq!(split_hashtag("# 没有标签"), None);
+        assert_eq!(split_hashtag("没有标签"), None);
+    }
+}
@@ -0,0 +1,168 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use rocket_db_pools::sqlx::{self, SqliteConnection};
+use crate::access;
+
+// ========== Task Feed ==========
+//
+// /todos.ics is an iCalendar feed of VTODOs, so Reminders, Tasks.org and
+// other task apps that subscribe to calendars list what is left to do
+// without another integration: one to-do per course with review cards due,
+// and one per log item still to do, i.e. marked 待办 or 进行中, or homework
+// not yet marked and dated today or later. Something done drops out of the
+// feed on the app's next refresh.
+//
+// Task apps can't sign in, so the feed is read with a per-account token in
+// the link (`users.todos_token`), made and reset on the account page. A
+// signed-in browser gets its own feed without one.
+
+pub struct Todo {
+    /// Stable across refreshes, so an app updates the to-do in place
+    pub uid: String,
+    pub summary: String,
+    pub due: Option<NaiveDate>,
+    pub in_progress: bool,
+    /// Path on the site, e.g. /courses/1#log-3
+    pub path: String,
+}
+
+pub async fn build(db: &mut SqliteConnection, user_id: i64, today: NaiveDate) -> Vec<Todo> {
+    let reviews = sqlx::query_as::<_, (i64, String, String, i64)>(&format!(
+        r#"
+        SELECT c.id, c.public_id, c.code, COUNT(*)
+        FROM study_cards s
+        JOIN problems p ON s.problem_id = p.id
+        LEFT JOIN log_items l ON p.log_item_id = l.id
+        LEFT JOIN exams e ON p.exam_id = e.id
+        JOIN courses c ON c.id = COALESCE(l.course_id, e.course_id)
+        WHERE s.user_id = ? AND s.due_at <= datetime('now') AND c.id IN ({})
+        GROUP BY c.id
+        ORDER BY c.code
+        "#,
+        access::COURSES
+    ))
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_all(&mut *db)
+    .await
+    .unwrap_or_default();
+
+    let tasks = sqlx::query_as::<_, (i64, i64, String, String, String, Option<String>, Option<String>)>(&format!(
+        r#"
+        SELECT l.id, c.id, l.public_id, c.code, l.title, l.date, l.status
+        FROM log_items l JOIN courses c ON l.course_id = c.id
+        WHERE (l.status IN ('todo', 'in_progress') OR (l.status IS NULL AND l.kind = 'Homework' AND l.date >= ?))
+            AND c.id IN ({})
+        ORDER BY l.date IS NULL, l.date, l.id
+        "#,
+        access::COURSES
+    ))
+    .bind(today.to_string())
+    .bind(user_id)
+    .fetch_all(&mut *db)
+    .await
+    .unwrap_or_default();
+
+    let mut todos: Vec<Todo> = reviews
+        .into_iter()
+        .map(|(course_id, public_id, code, count)| Todo {
+            uid: format!("{}-review", public_id),
+            summary: format!("复习 {}：{} 道错题", code, count),
+            due: Some(today),
+            in_progress: false,
+            path: format!("/courses/{}/study/review", course_id),
+        })
+        .collect();
+    todos.extend(tasks.into_iter().map(|(id, course_id, public_id, code, title, date, status)| Todo {
+        uid: public_id,
+        summary: format!("{} {}", code, title),
+        due: date.and_then(|d| NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok()),
+        in_progress: status.as_deref() == Some("in_progress"),
+        path: format!("/courses/{}#log-{}", course_id, id),
+    }));
+    todos
+}
+
+/// The feed as an iCalendar document (RFC 5545), with CRLF line endings.
+pub fn render(todos: &[Todo], site_name: &str, base_url: &str, now: NaiveDateTime) -> String {
+    let stamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//zhixi//todos//ZH".to_string(),
+        format!("X-WR-CALNAME:{} 待办", escape(site_name)),
+    ];
+    for todo in todos {
+        lines.push("BEGIN:VTODO".to_string());
+        lines.push(format!("UID:{}", todo.uid));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!("SUMMARY:{}", escape(&todo.summary)));
+        if let Some(due) = todo.due {
+            lines.push(format!("DUE;VALUE=DATE:{}", due.format("%Y%m%d")));
+        }
+        lines.push(format!("STATUS:{}", if todo.in_progress { "IN-PROCESS" } else { "NEEDS-ACTION" }));
+        lines.push(format!("URL:{}{}", base_url, todo.path));
+        lines.push("END:VTODO".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut out = String::new();
+    for line in lines {
+        out.push_str(&fold(&line));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+pub fn feed_url(base_url: &str, token: &str) -> String {
+    format!("{}/todos.ics?token={}", base_url, token)
+}
+
+// TEXT values escape backslashes, semicolons, commas and newlines
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n")
+}
+
+// Lines are at most 75 octets; longer ones continue on lines starting with a
+// space, split between characters so UTF-8 stays whole
+fn fold(line: &str) -> String {
+    let mut out = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let now = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap().and_hms_opt(8, 30, 0).unwrap();
+        let todos = vec![
+            Todo { uid: "c1-review".to_string(), summary: "复习 MATH 1A：7 道错题".to_string(), due: Some(now.date()), in_progress: false, path: "/courses/1/study/review".to_string() },
+            Todo { uid: "l3".to_string(), summary: "MATH 1A Homework 3; parts a, b".to_string(), due: None, in_progress: true, path: "/courses/1#log-3".to_string() },
+        ];
+        let ics = render(&todos, "智习", "https://zhixi.example.com", now);
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.contains("X-WR-CALNAME:智习 待办\r\n"));
+        assert!(ics.contains("UID:c1-review\r\nDTSTAMP:20261016T083000Z\r\nSUMMARY:复习 MATH 1A：7 道错题\r\nDUE;VALUE=DATE:20261016\r\nSTATUS:NEEDS-ACTION\r\n"));
+        assert!(ics.contains("SUMMARY:MATH 1A Homework 3\\; parts a\\, b\r\nSTATUS:IN-PROCESS\r\nURL:https://zhixi.example.com/courses/1#log-3\r\n"));
+        assert!(ics.ends_with("END:VTODO\r\nEND:VCALENDAR\r\n"));
+    }
+
+    #[test]
+    fn test_fold() {
+        let line = format!("SUMMARY:{}", "错".repeat(30));
+        let folded = fold(&line);
+        assert!(folded.split("\r\n").all(|l| l.len() <= 75));
+        assert_eq!(folded.replace("\r\n ", ""), line);
+        assert_eq!(fold("SUMMARY:short"), "SUMMARY:short");
+    }
+}
@@ -1,6 +1,10 @@
-use rocket_db_pools::Connection;
-use rocket_db_pools::sqlx;
-use crate::db::Db;
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx::{self, SqliteConnection, SqlitePool};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use crate::config::TranslationCacheConfig;
+use crate::models::{Course, Exam, LogItem, Problem};
+use crate::{jobs, queries, settings, stats, uploads};
 
 // ========== Algorithmic Title Translation ==========
 
@@ -180,30 +184,47 @@ pub fn translate_title_algorithmic(kind: &str, title: &str) -> String {
 /// Look up cached translations from the database.
 /// Returns a vec of Option<String> in the same order as input texts.
 pub async fn lookup_cached_translations(
-    db: &mut Connection<Db>,
+    db: &mut SqliteConnection,
     texts: &[String],
 ) -> Vec<Option<String>> {
     let mut results = Vec::with_capacity(texts.len());
     for text in texts {
-        let cached: Option<String> = sqlx::query_scalar(
-            "SELECT translated_text FROM translations WHERE source_text = ? AND source_lang = 'zh' AND target_lang = 'en'"
-        )
-        .bind(text)
-        .fetch_optional(&mut ***db)
-        .await
-        .unwrap_or(None);
-        results.push(cached);
+        results.push(cached_translation(db, text).await);
     }
     results
 }
 
+// A cache hit, marking the row as used. last_used_at is only rewritten once a
+// day per row so that rendering a page doesn't turn into a write per string.
+async fn cached_translation(db: &mut SqliteConnection, text: &str) -> Option<String> {
+    let (id, translated, stale): (i64, String, bool) = sqlx::query_as(
+        r#"
+        SELECT id, translated_text, COALESCE(last_used_at < datetime('now', '-1 day'), 1)
+        FROM translations WHERE source_text = ? AND source_lang = 'zh' AND target_lang = 'en'
+        "#
+    )
+    .bind(text)
+    .fetch_optional(&mut *db)
+    .await
+    .unwrap_or(None)?;
+
+    if stale {
+        let _ = sqlx::query("UPDATE translations SET last_used_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(id)
+            .execute(&mut *db)
+            .await;
+    }
+    Some(translated)
+}
+
 /// Translate a batch of texts using LLM (OpenRouter API).
-/// Checks DB cache first, calls API for misses, stores results.
+/// Checks DB cache first, calls API for misses, stores results. The prompt
+/// describes the course by its translation settings.
 /// Returns translated texts in same order as input.
 pub async fn translate_batch(
-    db: &mut Connection<Db>,
+    db: &mut SqliteConnection,
     texts: &[String],
-    course_context: &str,
+    course: &Course,
 ) -> Vec<String> {
     if texts.is_empty() {
         return vec![];
@@ -223,26 +244,20 @@ pub async fn translate_batch(
     let mut misses: Vec<String> = Vec::new();
 
     for text in &unique_texts {
-        let cached: Option<String> = sqlx::query_scalar(
-            "SELECT translated_text FROM translations WHERE source_text = ? AND source_lang = 'zh' AND target_lang = 'en'"
-        )
-        .bind(text)
-        .fetch_optional(&mut ***db)
-        .await
-        .unwrap_or(None);
-
-        if let Some(translation) = cached {
+        if let Some(translation) = cached_translation(db, text).await {
             cache_map.insert(text.clone(), translation);
         } else {
             misses.push(text.clone());
         }
     }
 
-    // Call API for misses (retry up to 3 times)
-    if !misses.is_empty() {
+    // Call API for misses (retry up to 3 times); without a key they stay untranslated
+    let api_key = if misses.is_empty() { None } else { settings::translation_api_key(db).await };
+    if let Some(api_key) = api_key {
+        let course_context = course.translation_context();
         let mut api_result = None;
         for _ in 0..3 {
-            match call_openrouter_translate(&misses, course_context).await {
+            match call_openrouter_translate(db, &api_key, &misses, &course_context).await {
                 Ok(translations) => {
                     api_result = Some(translations);
                     break;
@@ -257,11 +272,11 @@ pub async fn translate_batch(
                 for (source, translated) in misses.iter().zip(translations.iter()) {
                     // Store in DB cache
                     let _ = sqlx::query(
-                        "INSERT OR REPLACE INTO translations (source_text, translated_text, source_lang, target_lang) VALUES (?, ?, 'zh', 'en')"
+                        "INSERT OR REPLACE INTO translations (source_text, translated_text, source_lang, target_lang, created_at, last_used_at) VALUES (?, ?, 'zh', 'en', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)"
                     )
                     .bind(source)
                     .bind(translated)
-                    .execute(&mut ***db)
+                    .execute(&mut *db)
                     .await;
 
                     cache_map.insert(source.clone(), translated.clone());
@@ -293,12 +308,50 @@ pub async fn translate_batch(
         .collect()
 }
 
-/// Call the OpenRouter API to translate a batch of texts.
+/// Everything in a course that gets LLM-translated: log item descriptions,
+/// category names, problem notes and exam titles.
+pub async fn course_texts(db: &mut SqliteConnection, course_id: i64) -> Vec<String> {
+    let mut texts: Vec<String> = Vec::new();
+
+    let log_items = sqlx::query_as::<_, LogItem>("SELECT * FROM log_items WHERE course_id = ?")
+        .bind(course_id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+    texts.extend(log_items.into_iter().filter_map(|item| item.description).filter(|d| !d.is_empty()));
+
+    texts.extend(queries::fetch_categories(db, course_id).await.into_iter().map(|cat| cat.name));
+
+    let problems = sqlx::query_as::<_, Problem>(
+        "SELECT p.* FROM problems p LEFT JOIN log_items l ON p.log_item_id = l.id LEFT JOIN exams e ON p.exam_id = e.id WHERE l.course_id = ? OR e.course_id = ?"
+    )
+        .bind(course_id)
+        .bind(course_id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+    texts.extend(problems.into_iter().filter_map(|p| p.notes).filter(|n| !n.is_empty()));
+
+    let exams = sqlx::query_as::<_, Exam>("SELECT * FROM exams WHERE course_id = ?")
+        .bind(course_id)
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default();
+    texts.extend(exams.into_iter().map(|exam| exam.title));
+
+    texts
+}
+
+const TRANSLATION_MODEL: &str = "google/gemini-2.5-flash";
+
+/// Call the OpenRouter API to translate a batch of texts, logging the call's
+/// usage for the admin stats page.
 async fn call_openrouter_translate(
+    db: &mut SqliteConnection,
+    api_key: &str,
     texts: &[String],
     course_context: &str,
 ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
-    let api_key = std::env::var("OPENROUTER_API_KEY")?;
 
     let numbered: String = texts
         .iter()
@@ -308,7 +361,7 @@ async fn call_openrouter_translate(
         .join("\n");
 
     let prompt = format!(
-        "Translate these Chinese items to English for a university course ({}). \
+        "Translate these Chinese items to English for a course ({}). \
          These are topic descriptions and category names. \
          Return ONLY a JSON array of strings, with exactly {} elements, in the same order:\n{}",
         course_context,
@@ -321,14 +374,18 @@ async fn call_openrouter_translate(
         .post("https://openrouter.ai/api/v1/chat/completions")
         .header("Authorization", format!("Bearer {}", api_key))
         .json(&serde_json::json!({
-            "model": "google/gemini-2.5-flash",
+            "model": TRANSLATION_MODEL,
             "messages": [{"role": "user", "content": prompt}],
-            "temperature": 0.1
+            "temperature": 0.1,
+            "usage": {"include": true}
         }))
         .send()
         .await?;
 
     let body: serde_json::Value = response.json().await?;
+    if !body["usage"].is_null() {
+        stats::record_llm_call(db, TRANSLATION_MODEL, &body["usage"]).await;
+    }
     let content = body["choices"][0]["message"]["content"]
         .as_str()
         .ok_or("No content in response")?;
@@ -345,6 +402,243 @@ async fn call_openrouter_translate(
     Ok(translations)
 }
 
+// ========== Filling Missing Translations ==========
+//
+// English public pages never wait on the LLM. They render with whatever is
+// cached, tag each string still in Chinese with `data-untranslated="<key>"`,
+// and ask for the rest with `POST /courses/<public id>/translate/missing`.
+// That queues one MISSING_JOB_KIND job per course; the page then polls the
+// same URL and, once the job is done, swaps the new translations in by key.
+
+pub const MISSING_JOB_KIND: &str = "translate_missing";
+
+/// Stable short key for a source string, used to find it in the page.
+pub fn text_key(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))[..16].to_string()
+}
+
+/// Queue translating the course's uncached strings, unless a run is already
+/// waiting or there is no API key to translate with.
+pub async fn queue_missing(db: &mut SqliteConnection, course_id: i64) {
+    let payload = course_id.to_string();
+    if jobs::is_queued(db, MISSING_JOB_KIND, &payload).await || settings::translation_api_key(db).await.is_none() {
+        return;
+    }
+    jobs::enqueue(db, MISSING_JOB_KIND, &payload).await;
+}
+
+pub async fn missing_pending(db: &mut SqliteConnection, course_id: i64) -> bool {
+    jobs::is_queued(db, MISSING_JOB_KIND, &course_id.to_string()).await
+}
+
+/// Job handler; the payload is the course id.
+pub async fn translate_missing(pool: &SqlitePool, payload: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let course_id: i64 = payload.parse()?;
+    let mut conn = pool.acquire().await?;
+    let Some(course) = queries::fetch_course(&mut conn, course_id).await else { return Ok(()) };
+
+    let texts = course_texts(&mut conn, course_id).await;
+    let cached = lookup_cached_translations(&mut conn, &texts).await;
+    let misses: Vec<String> = texts.into_iter().zip(cached).filter(|(_, t)| t.is_none()).map(|(text, _)| text).collect();
+    translate_batch(&mut conn, &misses, &course).await;
+    Ok(())
+}
+
+// ========== Translation Cache Retention ==========
+//
+// Every distinct string ever sent to the LLM stays in `translations`, so the
+// table only grows. A daily job drops entries by the rules under
+// `[default.translation_cache]`: anything not read for `max_age_days`, then
+// the least recently used beyond `max_entries`. A dropped string that is
+// needed again is simply translated again.
+
+pub const PRUNE_JOB_KIND: &str = "prune_translations";
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Replace any queued prune job with one that runs now. Called once at
+/// startup; each run queues the next.
+pub async fn schedule_pruning(pool: &SqlitePool, config: &TranslationCacheConfig) {
+    let Ok(mut conn) = pool.acquire().await else { return };
+    let _ = sqlx::query("DELETE FROM jobs WHERE kind = ?")
+        .bind(PRUNE_JOB_KIND)
+        .execute(&mut *conn)
+        .await;
+    jobs::enqueue(&mut conn, PRUNE_JOB_KIND, &serde_json::to_string(config).unwrap()).await;
+}
+
+/// Job handler; the payload is the TranslationCacheConfig to apply.
+pub async fn prune(pool: &SqlitePool, payload: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let config: TranslationCacheConfig = serde_json::from_str(payload)?;
+
+    if config.max_age_days > 0 {
+        sqlx::query("DELETE FROM translations WHERE last_used_at < datetime('now', ?)")
+            .bind(format!("-{} days", config.max_age_days))
+            .execute(pool)
+            .await?;
+    }
+
+    if let Some(max_entries) = config.max_entries {
+        sqlx::query(
+            "DELETE FROM translations WHERE id IN (SELECT id FROM translations ORDER BY last_used_at DESC, id DESC LIMIT -1 OFFSET ?)"
+        )
+        .bind(max_entries as i64)
+        .execute(pool)
+        .await?;
+    }
+
+    let mut conn = pool.acquire().await?;
+    jobs::enqueue_after(&mut conn, PRUNE_JOB_KIND, payload, PRUNE_INTERVAL).await;
+    Ok(())
+}
+
+/// Size of the translation cache, for the admin dashboard.
+pub struct CacheStats {
+    pub entries: i64,
+    /// Stored text only; ignores SQLite's per-row and index overhead.
+    pub bytes: i64,
+}
+
+impl CacheStats {
+    pub fn size_label(&self) -> String {
+        uploads::format_size(self.bytes as u64)
+    }
+}
+
+pub async fn cache_stats(db: &mut SqliteConnection) -> CacheStats {
+    let (entries, bytes): (i64, i64) = sqlx::query_as(
+        "SELECT COUNT(*), COALESCE(SUM(LENGTH(CAST(source_text AS BLOB)) + LENGTH(CAST(translated_text AS BLOB))), 0) FROM translations"
+    )
+    .fetch_one(&mut *db)
+    .await
+    .unwrap_or((0, 0));
+    CacheStats { entries, bytes }
+}
+
+// ========== Cache Export and Import ==========
+//
+// Admins can download the cache from /admin/site and load it into another
+// instance, so a new deployment starts with translations that were already
+// paid for. Imports take either that export (a list of rows) or a plain
+// glossary object mapping Chinese to English:
+//
+//   [{"source_text": "泰勒级数", "translated_text": "Taylor series", "source_lang": "zh", "target_lang": "en"}]
+//   {"泰勒级数": "Taylor series", "极限": "Limits"}
+//
+// Strings already in the cache keep their translation unless the import is
+// told to replace them.
+
+fn default_source_lang() -> String {
+    "zh".to_string()
+}
+
+fn default_target_lang() -> String {
+    "en".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct CachedTranslation {
+    pub source_text: String,
+    pub translated_text: String,
+    #[serde(default = "default_source_lang")]
+    pub source_lang: String,
+    #[serde(default = "default_target_lang")]
+    pub target_lang: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde", untagged)]
+pub enum TranslationDump {
+    Rows(Vec<CachedTranslation>),
+    Glossary(std::collections::BTreeMap<String, String>),
+}
+
+impl TranslationDump {
+    fn into_rows(self) -> Vec<CachedTranslation> {
+        match self {
+            TranslationDump::Rows(rows) => rows,
+            TranslationDump::Glossary(glossary) => glossary
+                .into_iter()
+                .map(|(source_text, translated_text)| CachedTranslation {
+                    source_text,
+                    translated_text,
+                    source_lang: default_source_lang(),
+                    target_lang: default_target_lang(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub replaced: usize,
+    /// Already cached and left alone, including identical entries
+    pub kept: usize,
+    /// Entries with an empty source or translation
+    pub skipped: usize,
+}
+
+/// The whole cache, oldest first.
+pub async fn export(db: &mut SqliteConnection) -> Vec<CachedTranslation> {
+    sqlx::query_as::<_, CachedTranslation>("SELECT source_text, translated_text, source_lang, target_lang FROM translations ORDER BY id")
+        .fetch_all(&mut *db)
+        .await
+        .unwrap_or_default()
+}
+
+/// Add a dump's entries to the cache. Imported rows count as just used, so
+/// retention doesn't drop them before anything has had a chance to read them.
+pub async fn import(db: &mut SqliteConnection, dump: TranslationDump, replace: bool) -> ImportSummary {
+    let mut summary = ImportSummary::default();
+    for row in dump.into_rows() {
+        if row.source_text.trim().is_empty() || row.translated_text.trim().is_empty() {
+            summary.skipped += 1;
+            continue;
+        }
+        let existing: Option<String> = sqlx::query_scalar(
+            "SELECT translated_text FROM translations WHERE source_text = ? AND source_lang = ? AND target_lang = ?"
+        )
+        .bind(&row.source_text)
+        .bind(&row.source_lang)
+        .bind(&row.target_lang)
+        .fetch_optional(&mut *db)
+        .await
+        .unwrap_or(None);
+
+        match existing {
+            None => {
+                let _ = sqlx::query(
+                    "INSERT INTO translations (source_text, translated_text, source_lang, target_lang, created_at, last_used_at) VALUES (?, ?, ?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)"
+                )
+                .bind(&row.source_text)
+                .bind(&row.translated_text)
+                .bind(&row.source_lang)
+                .bind(&row.target_lang)
+                .execute(&mut *db)
+                .await;
+                summary.added += 1;
+            }
+            Some(current) if replace && current != row.translated_text => {
+                let _ = sqlx::query(
+                    "UPDATE translations SET translated_text = ?, last_used_at = CURRENT_TIMESTAMP WHERE source_text = ? AND source_lang = ? AND target_lang = ?"
+                )
+                .bind(&row.translated_text)
+                .bind(&row.source_text)
+                .bind(&row.source_lang)
+                .bind(&row.target_lang)
+                .execute(&mut *db)
+                .await;
+                summary.replaced += 1;
+            }
+            Some(_) => summary.kept += 1,
+        }
+    }
+    summary
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -371,4 +665,23 @@ mod tests {
         // Fallback to original
         assert_eq!(translate_title_algorithmic("Other", "Something else"), "Something else");
     }
+
+    #[test]
+    fn test_translation_dump_formats() {
+        let rows: TranslationDump = serde_json::from_str(
+            r#"[{"source_text": "极限", "translated_text": "Limits"}, {"source_text": "Limits", "translated_text": "极限", "source_lang": "en", "target_lang": "zh"}]"#
+        ).unwrap();
+        assert_eq!(rows.into_rows(), vec![
+            CachedTranslation { source_text: "极限".to_string(), translated_text: "Limits".to_string(), source_lang: "zh".to_string(), target_lang: "en".to_string() },
+            CachedTranslation { source_text: "Limits".to_string(), translated_text: "极限".to_string(), source_lang: "en".to_string(), target_lang: "zh".to_string() },
+        ]);
+
+        let glossary: TranslationDump = serde_json::from_str(r#"{"泰勒级数": "Taylor series", "极限": "Limits"}"#).unwrap();
+        let rows = glossary.into_rows();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|r| r.source_lang == "zh" && r.target_lang == "en"));
+
+        assert!(serde_json::from_str::<TranslationDump>(r#"{"entries": [1, 2]}"#).is_err());
+        assert!(serde_json::from_str::<TranslationDump>(r#"[{"source_text": "极限"}]"#).is_err());
+    }
 }
@@ -0,0 +1,595 @@
+use rocket::http::ContentType;
+use rocket_db_pools::sqlx::{self, SqliteConnection, SqlitePool};
+use sha2::{Digest, Sha256};
+use std::path::{Component, Path, PathBuf};
+use uuid::Uuid;
+use tokio::io::AsyncReadExt;
+use crate::db;
+use crate::scan;
+use crate::settings;
+
+// ========== Upload Storage ==========
+//
+// Uploads are content-addressed: each distinct file is stored once as
+//   uploads/<first 2 hex digits of its SHA-256>/<remaining 62>.<ext>
+// and the `uploads` table counts the rows (problem screenshots, course
+// banners) that point at it. Storing the same screenshot again only bumps the
+// count, and releasing the last reference deletes the file. Files saved before
+// this scheme (uploads/<uuid>.png) have no row and are never deleted; `zhixi
+// shard-uploads` moves them into subdirectories too (see Sharding Old
+// Uploads).
+//
+// The directory on disk is chosen during setup; URLs always use /uploads/.
+
+pub const DEFAULT_DIR: &str = "uploads";
+
+/// Where upload files live on disk, relative to the working directory unless
+/// the admin gave an absolute path during setup.
+pub async fn dir(db: &mut SqliteConnection) -> PathBuf {
+    PathBuf::from(settings::get(db, settings::UPLOAD_DIR).await.unwrap_or_else(|| DEFAULT_DIR.to_string()))
+}
+
+/// A stored upload plus what the user called it. The stored name is just a
+/// hash, so the original filename and MIME type are kept on the problem row.
+pub struct SavedUpload {
+    pub url: String,
+    pub original_filename: Option<String>,
+    pub mime_type: Option<String>,
+    /// The link it was downloaded from, when it wasn't uploaded
+    pub imported_from: Option<String>,
+}
+
+/// The final path component of a client-supplied filename, without control
+/// characters and capped in length.
+pub fn clean_filename(raw: &str) -> Option<String> {
+    let name = raw.rsplit(['/', '\\']).next().unwrap_or("");
+    let name: String = name.chars().filter(|c| !c.is_control()).take(200).collect();
+    let name = name.trim();
+    if name.is_empty() { None } else { Some(name.to_string()) }
+}
+
+/// File extension to store an upload under: from its bytes when they are an
+/// image or PDF (see scan::sniff), otherwise from its MIME type if known,
+/// otherwise from its original filename, otherwise png (screenshots).
+pub fn extension_for(bytes: &[u8], mime_type: Option<&str>, filename: Option<&str>) -> String {
+    let mime_type = scan::sniff(bytes).or(mime_type);
+    let from_mime = mime_type
+        .and_then(ContentType::parse_flexible)
+        .and_then(|ct| ct.extension().map(|e| e.to_string()));
+    let from_name = filename
+        .and_then(|f| f.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_string());
+    from_mime
+        .or(from_name)
+        .map(|e| e.to_ascii_lowercase())
+        .filter(|e| !e.is_empty() && e.len() <= 8 && e.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or_else(|| "png".to_string())
+}
+
+/// A byte count for people: "512 B", "20 KB", "1.5 MB".
+pub fn format_size(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{} KB", bytes / 1024)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+/// Content-Disposition value for serving an upload. Non-ASCII names (most
+/// Chinese filenames) go in the RFC 5987 `filename*` parameter, with an ASCII
+/// approximation in `filename` for old clients.
+pub fn content_disposition(filename: Option<&str>, download: bool) -> String {
+    let kind = if download { "attachment" } else { "inline" };
+    let Some(filename) = filename else { return kind.to_string() };
+
+    let fallback: String = filename
+        .chars()
+        .map(|c| if c.is_ascii_graphic() && c != '"' && c != '\\' || c == ' ' { c } else { '_' })
+        .collect();
+    let mut encoded = String::new();
+    for byte in filename.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    format!("{}; filename=\"{}\"; filename*=UTF-8''{}", kind, fallback, encoded)
+}
+
+/// The type to show an upload as, going by its leading bytes: one of the
+/// image and PDF formats scan::sniff recognises, or None for anything else.
+/// The client's word is never taken for this, since HTML or SVG served from
+/// this origin could run scripts with the viewer's session.
+pub async fn inline_type(path: &Path) -> Option<ContentType> {
+    let file = tokio::fs::File::open(path).await.ok()?;
+    let mut head = Vec::with_capacity(16);
+    file.take(16).read_to_end(&mut head).await.ok()?;
+    scan::sniff(&head).and_then(ContentType::parse_flexible)
+}
+
+/// Path under the upload directory for an upload URL:
+/// "/uploads/ab/cdef….png" -> "ab/cdef….png"
+pub fn stored_path(url: &str) -> Option<&str> {
+    url.strip_prefix("/uploads/")
+}
+
+/// The file on disk for a requested `/uploads/<path>`, or None if there is no
+/// such file or the path could reach anything outside `root`: only plain
+/// names are allowed (no `..`, absolute paths or dotfiles like the staging
+/// and variant dirs), and symlinks must resolve to somewhere under `root`.
+pub async fn resolve(root: &Path, path: &Path) -> Option<PathBuf> {
+    let plain = path.components().all(|c| match c {
+        Component::Normal(name) => !name.to_string_lossy().starts_with('.'),
+        _ => false,
+    });
+    if !plain || path.as_os_str().is_empty() {
+        return None;
+    }
+    let root = tokio::fs::canonicalize(root).await.ok()?;
+    let file = tokio::fs::canonicalize(root.join(path)).await.ok()?;
+    if !file.starts_with(&root) || !tokio::fs::metadata(&file).await.ok()?.is_file() {
+        return None;
+    }
+    Some(file)
+}
+
+/// Store `bytes` and take one reference to it. Returns the public URL.
+pub async fn store(db: &mut SqliteConnection, bytes: &[u8], ext: &str) -> std::io::Result<String> {
+    let hash = format!("{:x}", Sha256::digest(bytes));
+    let path = format!("{}/{}.{}", &hash[..2], &hash[2..], ext);
+    let file_path = dir(db).await.join(&path);
+
+    if !tokio::fs::try_exists(&file_path).await? {
+        let dir = file_path.parent().expect("upload path has a parent");
+        tokio::fs::create_dir_all(dir).await?;
+        // Write under a hidden temp name first so a crash never leaves a truncated file behind
+        let staging = dir.join(format!(".{}.tmp", Uuid::new_v4()));
+        tokio::fs::write(&staging, bytes).await?;
+        tokio::fs::rename(&staging, &file_path).await?;
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO uploads (path, size, ref_count, created_at) VALUES (?, ?, 1, CURRENT_TIMESTAMP)
+        ON CONFLICT (path) DO UPDATE SET ref_count = ref_count + 1
+        "#,
+    )
+    .bind(&path)
+    .bind(bytes.len() as i64)
+    .execute(&mut *db)
+    .await
+    .map_err(std::io::Error::other)?;
+
+    Ok(format!("/uploads/{}", path))
+}
+
+/// Take another reference to an upload that is already stored, e.g. when a
+/// deleted problem is restored from history.
+pub async fn retain(db: &mut SqliteConnection, url: &str) {
+    let Some(path) = stored_path(url) else { return };
+    let _ = sqlx::query("UPDATE uploads SET ref_count = ref_count + 1 WHERE path = ?")
+        .bind(path)
+        .execute(&mut *db)
+        .await;
+}
+
+/// Drop one reference. The file is deleted once nothing refers to it.
+pub async fn release(db: &mut SqliteConnection, url: &str) {
+    let Some(path) = stored_path(url) else { return };
+    let remaining: Option<i64> = sqlx::query_scalar("UPDATE uploads SET ref_count = ref_count - 1 WHERE path = ? RETURNING ref_count")
+        .bind(path)
+        .fetch_optional(&mut *db)
+        .await
+        .unwrap_or(None);
+    if !matches!(remaining, Some(n) if n <= 0) {
+        return;
+    }
+
+    let _ = sqlx::query("DELETE FROM uploads WHERE path = ? AND ref_count <= 0")
+        .bind(path)
+        .execute(&mut *db)
+        .await;
+    let root = dir(db).await;
+    let file_path = root.join(path);
+    let _ = tokio::fs::remove_file(&file_path).await;
+    remove_variants(&root, path).await;
+    // Drop the two-digit shard directory too once it's empty; fails harmlessly otherwise
+    if let Some(dir) = file_path.parent() {
+        let _ = tokio::fs::remove_dir(dir).await;
+    }
+}
+
+// ========== Sharding Old Uploads ==========
+//
+// Uploads from before content addressing sit directly in the upload
+// directory, and tens of thousands of files in one directory make listing,
+// backing up and even opening them slow. `zhixi shard-uploads` moves each
+// into a subdirectory named after its first two characters, as new uploads
+// are stored ("<uuid>.png" -> "<first 2>/<rest>"), and rewrites the problems,
+// banners, logo and history pointing at it. Links to the old location keep
+// working: `legacy_shard` gives the serving route the new one. Running it
+// again only picks up what is left.
+
+/// Where a file that used to sit directly in the upload directory is moved:
+/// "0a1b2c3d-….png" -> "0a/1b2c3d-….png". None for paths with a directory
+/// or names too short to split.
+pub fn legacy_shard(path: &Path) -> Option<PathBuf> {
+    let mut components = path.components();
+    let (Some(Component::Normal(name)), None) = (components.next(), components.next()) else { return None };
+    let name = name.to_str()?;
+    let prefix = name.get(..2).filter(|p| p.chars().all(|c| c.is_ascii_alphanumeric()))?;
+    let rest = &name[2..];
+    if rest.is_empty() || rest.starts_with('.') {
+        return None;
+    }
+    Some(Path::new(prefix).join(rest))
+}
+
+/// What `shard_existing` did
+#[derive(Debug, Default, PartialEq)]
+pub struct ShardReport {
+    pub moved: usize,
+    /// Left in place: the target was taken or the move failed
+    pub skipped: usize,
+}
+
+/// Move every file directly in the upload directory into its shard and
+/// point everything that linked to it at the new URL. Each file is moved and
+/// relinked together or not at all.
+pub async fn shard_existing(db: &mut SqliteConnection) -> std::io::Result<ShardReport> {
+    let root = dir(db).await;
+    let mut report = ShardReport::default();
+    let mut entries = tokio::fs::read_dir(&root).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if !entry.file_type().await?.is_file() {
+            continue;
+        }
+        let old = PathBuf::from(entry.file_name());
+        let Some(new) = legacy_shard(&old) else { continue };
+        let (old_url, new_url) = (format!("/uploads/{}", old.display()), format!("/uploads/{}", new.display()));
+        let target = root.join(&new);
+        if tokio::fs::try_exists(&target).await? {
+            eprintln!("{} already exists, leaving {} alone", target.display(), old_url);
+            report.skipped += 1;
+            continue;
+        }
+
+        tokio::fs::create_dir_all(target.parent().expect("shard has a parent")).await?;
+        tokio::fs::rename(root.join(&old), &target).await?;
+        if let Err(e) = relink(db, &old_url, &new_url).await {
+            eprintln!("{} not moved: {}", old_url, e);
+            tokio::fs::rename(&target, root.join(&old)).await?;
+            report.skipped += 1;
+            continue;
+        }
+        // Made from the old path, so they would never be found or deleted again
+        remove_variants(&root, &old.to_string_lossy()).await;
+        report.moved += 1;
+    }
+    Ok(report)
+}
+
+async fn relink(db: &mut SqliteConnection, old_url: &str, new_url: &str) -> sqlx::Result<()> {
+    let mut tx = db::begin_write(db).await?;
+    for sql in ["UPDATE problems SET image_url = ? WHERE image_url = ?", "UPDATE courses SET banner_url = ? WHERE banner_url = ?"] {
+        sqlx::query(sql).bind(new_url).bind(old_url).execute(&mut *tx).await?;
+    }
+    sqlx::query("UPDATE instance_settings SET value = ? WHERE key = ? AND value = ?")
+        .bind(new_url)
+        .bind(settings::LOGO_URL)
+        .bind(old_url)
+        .execute(&mut *tx)
+        .await?;
+    // Snapshots hold the URL as a JSON string, so restoring one links the new file
+    let (old_json, new_json) = (format!("\"{}\"", old_url), format!("\"{}\"", new_url));
+    sqlx::query("UPDATE entity_history SET before_json = replace(before_json, ?1, ?2), after_json = replace(after_json, ?1, ?2) WHERE instr(before_json, ?1) OR instr(after_json, ?1)")
+        .bind(&old_json)
+        .bind(&new_json)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+/// `zhixi shard-uploads`: run `shard_existing` and say what it did.
+pub async fn run(pool: &SqlitePool) {
+    let mut conn = pool.acquire().await.expect("database connection");
+    match shard_existing(&mut conn).await {
+        Ok(report) => println!("Moved {} uploads into subdirectories, skipped {}.", report.moved, report.skipped),
+        Err(e) => eprintln!("Stopped: {}", e),
+    }
+}
+
+// ========== Resized Variants ==========
+//
+// `?w=&h=` on an image upload serves a smaller copy, made on first request by
+// ImageMagick and kept under <upload dir>/.variants/ next to a mirror of the
+// original's path. `crop` fills the whole w×h box (cutting off the overflow
+// around the center) instead of fitting inside it. Without ImageMagick the
+// original is served, so this is only ever an optimization. Anyone who can
+// see an upload can ask for it resized, so each side is rounded up to one of
+// VARIANT_SIZES and an upload only ever has a handful of variants.
+
+const VARIANT_DIR: &str = ".variants";
+const VARIANT_SIZES: &[u32] = &[100, 200, 400, 800, 1200, 1600, 2048];
+const RESIZABLE: &[&str] = &["png", "jpg", "jpeg", "gif", "webp"];
+
+pub struct Resize {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub crop: bool,
+}
+
+impl Resize {
+    /// None when no size was asked for; sizes are rounded up to the next of
+    /// VARIANT_SIZES, or down to the largest.
+    pub fn new(width: Option<u32>, height: Option<u32>, crop: bool) -> Option<Resize> {
+        let bucket = |v: Option<u32>| v.filter(|&v| v > 0).map(|v| {
+            VARIANT_SIZES.iter().copied().find(|&size| size >= v).unwrap_or(VARIANT_SIZES[VARIANT_SIZES.len() - 1])
+        });
+        let (width, height) = (bucket(width), bucket(height));
+        if width.is_none() && height.is_none() {
+            return None;
+        }
+        // Cropping needs a box to crop to
+        Some(Resize { width, height, crop: crop && width.is_some() && height.is_some() })
+    }
+
+    // "200x0" or "200x150-crop"
+    fn suffix(&self) -> String {
+        let size = format!("{}x{}", self.width.unwrap_or(0), self.height.unwrap_or(0));
+        if self.crop { format!("{}-crop", size) } else { size }
+    }
+
+    // ImageMagick arguments between input and output. Never enlarges when fitting.
+    fn convert_args(&self) -> Vec<String> {
+        let w = self.width.map(|w| w.to_string()).unwrap_or_default();
+        let h = self.height.map(|h| h.to_string()).unwrap_or_default();
+        let mut args = vec!["-auto-orient".to_string(), "-thumbnail".to_string()];
+        if self.crop {
+            args.extend([format!("{}x{}^", w, h), "-gravity".into(), "center".into(), "-extent".into(), format!("{}x{}", w, h)]);
+        } else {
+            args.push(format!("{}x{}>", w, h));
+        }
+        args
+    }
+}
+
+fn variant_path(root: &Path, path: &Path, resize: &Resize) -> Option<PathBuf> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    let stem = path.file_stem()?.to_str()?;
+    let dir = root.join(VARIANT_DIR).join(path.parent()?);
+    Some(dir.join(format!("{}-{}.{}", stem, resize.suffix(), ext)))
+}
+
+/// The file to serve for `path` resized as asked: a cached or freshly made
+/// variant, or the original for non-images and when resizing fails.
+pub async fn variant(root: &Path, path: &Path, resize: &Resize) -> PathBuf {
+    let original = root.join(path);
+    let resizable = path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| RESIZABLE.contains(&e.to_ascii_lowercase().as_str()));
+    let Some(target) = variant_path(root, path, resize).filter(|_| resizable) else { return original };
+
+    if tokio::fs::try_exists(&target).await.unwrap_or(false) {
+        return target;
+    }
+    if !tokio::fs::try_exists(&original).await.unwrap_or(false) {
+        return original;
+    }
+
+    let dir = target.parent().expect("variant path has a parent");
+    if tokio::fs::create_dir_all(dir).await.is_err() {
+        return original;
+    }
+    // Same write-then-rename as `store`, keeping the extension so ImageMagick picks the format
+    let staging = dir.join(format!(".{}.{}", Uuid::new_v4(), target.extension().and_then(|e| e.to_str()).unwrap_or("png")));
+    let status = tokio::process::Command::new("convert")
+        .arg(format!("{}[0]", original.display()))
+        .args(resize.convert_args())
+        .arg(&staging)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await;
+    if matches!(status, Ok(s) if s.success()) && tokio::fs::rename(&staging, &target).await.is_ok() {
+        return target;
+    }
+    let _ = tokio::fs::remove_file(&staging).await;
+    original
+}
+
+// Variants of an upload that is being deleted
+async fn remove_variants(root: &Path, path: &str) {
+    let path = Path::new(path);
+    let (Some(parent), Some(stem)) = (path.parent(), path.file_stem().and_then(|s| s.to_str())) else { return };
+    let dir = root.join(VARIANT_DIR).join(parent);
+    let Ok(mut entries) = tokio::fs::read_dir(&dir).await else { return };
+    let prefix = format!("{}-", stem);
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.file_name().to_string_lossy().starts_with(&prefix) {
+            let _ = tokio::fs::remove_file(entry.path()).await;
+        }
+    }
+    let _ = tokio::fs::remove_dir(&dir).await;
+}
+
+// ========== Cropping ==========
+//
+// Splitting one screenshot of several questions into one image per question.
+// Regions are in pixels of the image as browsers show it, i.e. after any EXIF
+// rotation, which `-auto-orient` applies before cutting. Like variants this
+// needs ImageMagick, but there is no original to fall back to here.
+
+pub const MAX_CROPS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Crop {
+    /// "x,y,width,height", as sent by the split form. None for anything else
+    /// or an empty region.
+    pub fn parse(s: &str) -> Option<Crop> {
+        let numbers: Vec<u32> = s.split(',').map(|n| n.trim().parse().ok()).collect::<Option<_>>()?;
+        match numbers[..] {
+            [x, y, width, height] if width > 0 && height > 0 => Some(Crop { x, y, width, height }),
+            _ => None,
+        }
+    }
+
+    fn convert_args(&self) -> Vec<String> {
+        let geometry = format!("{}x{}+{}+{}", self.width, self.height, self.x, self.y);
+        vec!["-auto-orient".to_string(), "-crop".to_string(), geometry, "+repage".to_string()]
+    }
+}
+
+/// Whether images stored with extension `ext` can be split.
+pub fn is_croppable(ext: &str) -> bool {
+    RESIZABLE.contains(&ext)
+}
+
+/// Cut each region out of `bytes`, an image with extension `ext`, in order.
+pub async fn crop(bytes: &[u8], ext: &str, regions: &[Crop]) -> std::io::Result<Vec<Vec<u8>>> {
+    let dir = std::env::temp_dir().join(format!("zhixi-crop-{}", Uuid::new_v4()));
+    tokio::fs::create_dir_all(&dir).await?;
+    let pieces = crop_in(&dir, bytes, ext, regions).await;
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    pieces
+}
+
+async fn crop_in(dir: &Path, bytes: &[u8], ext: &str, regions: &[Crop]) -> std::io::Result<Vec<Vec<u8>>> {
+    let input = dir.join(format!("input.{}", ext));
+    tokio::fs::write(&input, bytes).await?;
+    let mut pieces = Vec::with_capacity(regions.len());
+    for (i, region) in regions.iter().enumerate() {
+        let output = dir.join(format!("{}.{}", i, ext));
+        let status = tokio::process::Command::new("convert")
+            .arg(format!("{}[0]", input.display()))
+            .args(region.convert_args())
+            .arg(&output)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .await?;
+        if !status.success() {
+            return Err(std::io::Error::other(format!("convert failed with {}", status)));
+        }
+        pieces.push(tokio::fs::read(&output).await?);
+    }
+    Ok(pieces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_filename() {
+        assert_eq!(clean_filename("讲义 3.pdf").as_deref(), Some("讲义 3.pdf"));
+        assert_eq!(clean_filename("C:\\Users\\me\\hw.png").as_deref(), Some("hw.png"));
+        assert_eq!(clean_filename("../../etc/passwd").as_deref(), Some("passwd"));
+        assert_eq!(clean_filename("  \n"), None);
+    }
+
+    #[test]
+    fn test_extension_for() {
+        assert_eq!(extension_for(b"", Some("application/pdf"), Some("notes.bin")), "pdf");
+        assert_eq!(extension_for(b"", None, Some("Handout.PDF")), "pdf");
+        assert_eq!(extension_for(b"", Some("application/x-unknown"), Some("a.tar.gz")), "gz");
+        assert_eq!(extension_for(b"", None, Some("no extension")), "png");
+        assert_eq!(extension_for(b"", None, None), "png");
+        // What the bytes are wins over what the client says they are
+        assert_eq!(extension_for(b"\x89PNG\r\n\x1a\n", Some("text/html"), Some("x.html")), "png");
+    }
+
+    #[test]
+    fn test_resize() {
+        assert!(Resize::new(None, Some(0), true).is_none());
+
+        let fit = Resize::new(Some(400), None, true).unwrap();
+        assert!(!fit.crop);
+        assert_eq!(fit.convert_args(), ["-auto-orient", "-thumbnail", "400x>"]);
+        assert_eq!(
+            variant_path(Path::new("uploads"), Path::new("ab/cdef.PNG"), &fit),
+            Some(PathBuf::from("uploads/.variants/ab/cdef-400x0.png"))
+        );
+
+        let crop = Resize::new(Some(9999), Some(150), true).unwrap();
+        assert_eq!(crop.convert_args(), ["-auto-orient", "-thumbnail", "2048x200^", "-gravity", "center", "-extent", "2048x200"]);
+        assert_eq!(crop.suffix(), "2048x200-crop");
+
+        // Nearby sizes share a variant
+        assert_eq!(Resize::new(Some(201), None, false).unwrap().suffix(), "400x0");
+        assert_eq!(Resize::new(Some(399), None, false).unwrap().suffix(), "400x0");
+    }
+
+    #[test]
+    fn test_crop() {
+        let crop = Crop::parse("12, 340,600,180").unwrap();
+        assert_eq!(crop, Crop { x: 12, y: 340, width: 600, height: 180 });
+        assert_eq!(crop.convert_args(), ["-auto-orient", "-crop", "600x180+12+340", "+repage"]);
+        assert_eq!(Crop::parse("0,0,0,180"), None);
+        assert_eq!(Crop::parse("0,0,600"), None);
+        assert_eq!(Crop::parse("-5,0,600,180"), None);
+        assert_eq!(Crop::parse("0,0,600.5,180"), None);
+
+        assert!(is_croppable("jpg") && !is_croppable("pdf"));
+    }
+
+    #[rocket::async_test]
+    async fn test_resolve() {
+        let base = std::env::temp_dir().join(format!("zhixi-resolve-{}", Uuid::new_v4()));
+        let root = base.join("uploads");
+        std::fs::create_dir_all(root.join("ab")).unwrap();
+        std::fs::create_dir_all(root.join(".variants/ab")).unwrap();
+        std::fs::write(root.join("ab/cdef.png"), b"png").unwrap();
+        std::fs::write(root.join(".variants/ab/cdef-200x0.png"), b"png").unwrap();
+        std::fs::write(base.join("secret.txt"), b"secret").unwrap();
+        std::os::unix::fs::symlink(root.join("ab/cdef.png"), root.join("ab/inside.png")).unwrap();
+        std::os::unix::fs::symlink(base.join("secret.txt"), root.join("ab/outside.png")).unwrap();
+        std::os::unix::fs::symlink(&base, root.join("up")).unwrap();
+
+        assert_eq!(resolve(&root, Path::new("ab/cdef.png")).await, Some(root.canonicalize().unwrap().join("ab/cdef.png")));
+        assert!(resolve(&root, Path::new("ab/inside.png")).await.is_some());
+        assert_eq!(resolve(&root, Path::new("ab/outside.png")).await, None);
+        assert_eq!(resolve(&root, Path::new("up/secret.txt")).await, None);
+        assert_eq!(resolve(&root, Path::new("../secret.txt")).await, None);
+        assert_eq!(resolve(&root, Path::new("ab/../../secret.txt")).await, None);
+        assert_eq!(resolve(&root, &base.join("secret.txt")).await, None);
+        assert_eq!(resolve(&root, Path::new(".variants/ab/cdef-200x0.png")).await, None);
+        assert_eq!(resolve(&root, Path::new("ab")).await, None);
+        assert_eq!(resolve(&root, Path::new("")).await, None);
+        assert_eq!(resolve(&root, Path::new("ab/missing.png")).await, None);
+
+        // A symlinked upload directory is fine as a whole
+        std::os::unix::fs::symlink(&root, base.join("linked")).unwrap();
+        assert!(resolve(&base.join("linked"), Path::new("ab/cdef.png")).await.is_some());
+
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_legacy_shard() {
+        assert_eq!(legacy_shard(Path::new("0a1b2c3d-aaaa.png")), Some(PathBuf::from("0a/1b2c3d-aaaa.png")));
+        assert_eq!(legacy_shard(Path::new("ab/cdef.png")), None);
+        assert_eq!(legacy_shard(Path::new("ab.png")), None);
+        assert_eq!(legacy_shard(Path::new("a")), None);
+        assert_eq!(legacy_shard(Path::new(".staging.tmp")), None);
+    }
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(content_disposition(None, false), "inline");
+        assert_eq!(content_disposition(Some("hw 1.png"), true), "attachment; filename=\"hw 1.png\"; filename*=UTF-8''hw%201.png");
+        assert_eq!(content_disposition(Some("讲义.pdf"), false), "inline; filename=\"__.pdf\"; filename*=UTF-8''%E8%AE%B2%E4%B9%89.pdf");
+    }
+}
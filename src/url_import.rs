@@ -0,0 +1,123 @@
+use std::time::Duration;
+use crate::config::UploadScanConfig;
+use crate::outbound::{self, FetchError};
+use crate::scan::{self, Rejection};
+use crate::uploads;
+
+// ========== Importing Images by URL ==========
+//
+// A problem image can come from a link instead of a file, e.g. a question
+// on a course site: the server downloads it and stores its own copy, so the
+// problem survives the site changing. The download is held to
+// MAX_IMPORT_BYTES (or the upload scan's max_bytes, if smaller) and must be
+// an image or PDF the scan recognises by its leading bytes, whatever the
+// server calls it; after that it goes through the same checks and storage as
+// an uploaded file. The link is kept on the problem as `imported_from`.
+// Only public hosts are fetched; see outbound.rs.
+
+pub const MAX_IMPORT_BYTES: u64 = 20 * 1024 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Why a link couldn't be imported. `message` is shown to the user.
+#[derive(Debug, PartialEq)]
+pub enum ImportError {
+    NotHttp,
+    Blocked,
+    Unreachable,
+    Rejected(Rejection),
+}
+
+impl ImportError {
+    pub fn message(&self) -> String {
+        match self {
+            ImportError::NotHttp => "请填写以 http:// 或 https:// 开头的图片链接。".to_string(),
+            ImportError::Blocked => "不能从本机或内网地址导入，请使用公开的链接。".to_string(),
+            ImportError::Unreachable => "无法下载这个链接，请检查地址或稍后再试。".to_string(),
+            ImportError::Rejected(rejection) => rejection.message(),
+        }
+    }
+}
+
+/// A downloaded file that passed the checks, ready for `uploads::store`.
+pub struct Imported {
+    pub bytes: Vec<u8>,
+    pub original_filename: Option<String>,
+    pub mime_type: String,
+}
+
+/// Download `url` and run it through the upload checks.
+pub async fn fetch(client: &outbound::Client, config: &UploadScanConfig, url: &str) -> Result<Imported, ImportError> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(ImportError::NotHttp);
+    }
+    let max_bytes = config.max_bytes.map_or(MAX_IMPORT_BYTES, |max| max.min(MAX_IMPORT_BYTES));
+
+    let mut response = client.get(url, FETCH_TIMEOUT).await.map_err(|e| {
+        eprintln!("import of {} failed: {}", url, e);
+        match e {
+            FetchError::Blocked(_) => ImportError::Blocked,
+            FetchError::Http(_) => ImportError::Unreachable,
+        }
+    })?;
+
+    // Servers often call images application/octet-stream, but a link to a
+    // web page needn't be downloaded to know it's not an image
+    let declared = response.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or("").trim().to_ascii_lowercase())
+        .filter(|v| !v.is_empty());
+    if let Some(declared) = declared.as_deref() {
+        if !declared.starts_with("image/") && declared != "application/pdf" && declared != "application/octet-stream" {
+            return Err(ImportError::Rejected(Rejection::DisallowedType(declared.to_string())));
+        }
+    }
+    if response.content_length().is_some_and(|len| len > max_bytes) {
+        return Err(ImportError::Rejected(Rejection::TooLarge { max_bytes }));
+    }
+
+    // The length header is optional and can lie, so count as it arrives
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|_| ImportError::Unreachable)? {
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() as u64 > max_bytes {
+            return Err(ImportError::Rejected(Rejection::TooLarge { max_bytes }));
+        }
+    }
+    let Some(mime_type) = scan::sniff(&bytes) else {
+        let shown = declared.unwrap_or_else(|| "未知类型".to_string());
+        return Err(ImportError::Rejected(Rejection::DisallowedType(shown)));
+    };
+    scan::check(config, &bytes, Some(mime_type)).await.map_err(ImportError::Rejected)?;
+
+    Ok(Imported { bytes, original_filename: filename_from_url(url), mime_type: mime_type.to_string() })
+}
+
+// The last path segment: "https://a.edu/hw/q3.png?v=2" -> "q3.png"
+fn filename_from_url(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next()?;
+    let rest = path.split_once("://").map_or(path, |(_, rest)| rest);
+    let (_, path) = rest.split_once('/')?;
+    uploads::clean_filename(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filename_from_url() {
+        assert_eq!(filename_from_url("https://a.edu/hw/q3.png?v=2#top"), Some("q3.png".to_string()));
+        assert_eq!(filename_from_url("https://a.edu/hw/"), None);
+        assert_eq!(filename_from_url("https://a.edu"), None);
+    }
+
+    #[rocket::async_test]
+    async fn test_fetch_needs_http() {
+        let client = outbound::Client::new(&Default::default()).unwrap();
+        let config = UploadScanConfig::default();
+        assert_eq!(fetch(&client, &config, "file:///etc/passwd").await.err(), Some(ImportError::NotHttp));
+        assert_eq!(fetch(&client, &config, "/uploads/ab/cd.png").await.err(), Some(ImportError::NotHttp));
+        assert_eq!(fetch(&client, &config, "http://169.254.169.254/latest/meta-data/").await.err(), Some(ImportError::Blocked));
+    }
+}
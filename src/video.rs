@@ -0,0 +1,104 @@
+use crate::link_preview;
+
+// ========== Video Links ==========
+//
+// A log item whose link is a lecture video can carry timestamped bookmarks
+// (`log_item_bookmarks`). Each renders as a link that opens the video at that
+// point with `?t=<seconds>`, which YouTube and Bilibili both understand.
+
+const VIDEO_HOSTS: &[&str] = &["youtube.com", "youtu.be", "bilibili.com", "b23.tv", "vimeo.com"];
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "webm", "mov", "m4v"];
+
+/// A link to a video site or a video file.
+pub fn is_video(link: &str) -> bool {
+    let Some(host) = link_preview::domain(link) else { return false };
+    if VIDEO_HOSTS.iter().any(|h| host == *h || host.ends_with(&format!(".{}", h))) {
+        return true;
+    }
+    let path = link.split(['?', '#']).next().unwrap_or(link);
+    path.rsplit_once('.').is_some_and(|(_, ext)| VIDEO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// `link` opening at `seconds`, replacing any `t` it already had.
+pub fn link_at(link: &str, seconds: i64) -> String {
+    let (base, fragment) = match link.split_once('#') {
+        Some((base, fragment)) => (base, Some(fragment)),
+        None => (link, None),
+    };
+    let (path, query) = base.split_once('?').unwrap_or((base, ""));
+    let time = format!("t={}", seconds);
+    let mut params: Vec<&str> = query.split('&').filter(|p| !p.is_empty() && !p.starts_with("t=")).collect();
+    params.push(&time);
+
+    let mut url = format!("{}?{}", path, params.join("&"));
+    if let Some(fragment) = fragment {
+        url.push('#');
+        url.push_str(fragment);
+    }
+    url
+}
+
+/// "1:02:03", "12:03" or "95" to seconds. Minutes and seconds after the
+/// first field must be under 60.
+pub fn parse_timestamp(text: &str) -> Option<i64> {
+    let fields: Vec<&str> = text.trim().split([':', '：']).collect();
+    if fields.len() > 3 {
+        return None;
+    }
+    let mut seconds = 0;
+    for (i, field) in fields.iter().enumerate() {
+        let value = field.trim().parse::<u32>().ok()? as i64;
+        if i > 0 && value >= 60 {
+            return None;
+        }
+        seconds = seconds * 60 + value;
+    }
+    Some(seconds)
+}
+
+/// Seconds as "1:02:03", or "2:03" under an hour.
+pub fn format_timestamp(seconds: i64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_video() {
+        assert!(is_video("https://www.youtube.com/watch?v=abc"));
+        assert!(is_video("https://youtu.be/abc"));
+        assert!(is_video("https://www.bilibili.com/video/BV1xx"));
+        assert!(is_video("https://example.com/lectures/03.MP4?token=1"));
+        assert!(!is_video("https://example.com/notes.pdf"));
+        assert!(!is_video("https://notyoutube.com/watch"));
+        assert!(!is_video("not a url"));
+    }
+
+    #[test]
+    fn test_link_at() {
+        assert_eq!(link_at("https://youtu.be/abc", 95), "https://youtu.be/abc?t=95");
+        assert_eq!(link_at("https://www.youtube.com/watch?v=abc&t=10", 95), "https://www.youtube.com/watch?v=abc&t=95");
+        assert_eq!(link_at("https://example.com/a.mp4#x", 3), "https://example.com/a.mp4?t=3#x");
+    }
+
+    #[test]
+    fn test_timestamps() {
+        assert_eq!(parse_timestamp("1:02:03"), Some(3723));
+        assert_eq!(parse_timestamp(" 12：03 "), Some(723));
+        assert_eq!(parse_timestamp("95"), Some(95));
+        assert_eq!(parse_timestamp("1:60"), None);
+        assert_eq!(parse_timestamp("-5"), None);
+        assert_eq!(parse_timestamp("1:2:3:4"), None);
+        assert_eq!(parse_timestamp(""), None);
+        assert_eq!(format_timestamp(3723), "1:02:03");
+        assert_eq!(format_timestamp(95), "1:35");
+        assert_eq!(format_timestamp(0), "0:00");
+    }
+}
@@ -0,0 +1,210 @@
+use rocket::serde::json::json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx::{self, FromRow, SqliteConnection, SqlitePool};
+use std::time::Duration;
+use crate::config::AppConfig;
+use crate::{jobs, outbound};
+use crate::models::kind_label_zh;
+
+// ========== Course Webhooks ==========
+//
+// A course can name an incoming webhook in its settings, e.g. the class
+// Discord or Slack channel. A job sweeps every SWEEP_INTERVAL and posts to it
+// when the course has been published (with the public link) and, after
+// that, when published courses have new log items or problems, so a batch
+// of uploads becomes a single message. Only delivered news is marked as
+// announced (the columns are described in the migration), so a failed post
+// is simply tried again on the next sweep.
+//
+// The message goes out as {"content": …, "text": …}: Discord reads the
+// first, Slack and most other chat webhooks the second. Any course editor
+// picks the URL, so it's posted through the outbound client and only public
+// hosts are reached; see outbound.rs.
+
+pub const JOB_KIND: &str = "course_webhooks";
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const POST_TIMEOUT: Duration = Duration::from_secs(15);
+/// New log items listed by title; the rest are only counted
+const MAX_LISTED: usize = 5;
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct JobConfig {
+    /// For the link to the public page
+    base_url: String,
+}
+
+#[derive(FromRow)]
+struct WebhookCourse {
+    id: i64,
+    code: String,
+    title: String,
+    public_slug: String,
+    webhook_url: String,
+    webhook_announced: bool,
+    webhook_log_item_id: i64,
+    webhook_problem_id: i64,
+}
+
+/// A webhook URL as entered in the course settings: None to turn webhooks
+/// off, an error message for anything but an http(s) link.
+pub fn parse_url(input: &str) -> Result<Option<String>, &'static str> {
+    let url = input.trim();
+    if url.is_empty() {
+        Ok(None)
+    } else if url.starts_with("https://") || url.starts_with("http://") {
+        Ok(Some(url.to_string()))
+    } else {
+        Err("Webhook 地址需以 http:// 或 https:// 开头。")
+    }
+}
+
+/// Point `course_id`'s webhook at `url`. What the course already has counts
+/// as announced, except that a published course gets its "published"
+/// message again, which doubles as a test of the new URL.
+pub async fn set_url(db: &mut SqliteConnection, course_id: i64, url: Option<&str>) -> sqlx::Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE courses SET
+            webhook_url = ?,
+            webhook_announced = 0,
+            webhook_log_item_id = (SELECT COALESCE(MAX(id), 0) FROM log_items),
+            webhook_problem_id = (SELECT COALESCE(MAX(id), 0) FROM problems)
+        WHERE id = ?
+        "#,
+    )
+    .bind(url)
+    .bind(course_id)
+    .execute(&mut *db)
+    .await?;
+    Ok(())
+}
+
+/// Start sweeping at liftoff, replacing the job left from the last run.
+pub async fn schedule(pool: &SqlitePool, config: &AppConfig) {
+    let Ok(mut conn) = pool.acquire().await else { return };
+    let _ = sqlx::query("DELETE FROM jobs WHERE kind = ?")
+        .bind(JOB_KIND)
+        .execute(&mut *conn)
+        .await;
+    let job = JobConfig { base_url: config.base_url(None) };
+    jobs::enqueue(&mut conn, JOB_KIND, &serde_json::to_string(&job).unwrap()).await;
+}
+
+/// Job handler: post whatever each published course with a webhook has to
+/// announce. Failed posts are logged rather than failing the job, so one
+/// dead webhook doesn't hold up the rest.
+pub async fn sweep(pool: &SqlitePool, client: &outbound::Client, payload: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let job: JobConfig = serde_json::from_str(payload)?;
+    let mut conn = pool.acquire().await?;
+    jobs::enqueue_after(&mut conn, JOB_KIND, payload, SWEEP_INTERVAL).await;
+
+    // Publishing again after a break is announced again
+    sqlx::query("UPDATE courses SET webhook_announced = 0 WHERE is_published = 0 AND webhook_announced = 1")
+        .execute(&mut *conn)
+        .await?;
+
+    let courses = sqlx::query_as::<_, WebhookCourse>(
+        r#"
+        SELECT id, code, title, public_slug, webhook_url, webhook_announced, webhook_log_item_id, webhook_problem_id
+        FROM courses WHERE is_published = 1 AND public_slug IS NOT NULL AND webhook_url IS NOT NULL
+        "#,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    for course in courses {
+        if let Err(e) = announce(&mut conn, client, &job.base_url, &course).await {
+            eprintln!("webhook for course {} failed: {}", course.id, e);
+        }
+    }
+    Ok(())
+}
+
+async fn announce(db: &mut SqliteConnection, client: &outbound::Client, base_url: &str, course: &WebhookCourse) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let log_items = sqlx::query_as::<_, (i64, String, String)>("SELECT id, kind, title FROM log_items WHERE course_id = ? AND id > ? ORDER BY id")
+        .bind(course.id)
+        .bind(course.webhook_log_item_id)
+        .fetch_all(&mut *db)
+        .await?;
+    let (problem_count, last_problem_id) = sqlx::query_as::<_, (i64, Option<i64>)>(
+        r#"
+        SELECT COUNT(*), MAX(p.id) FROM problems p
+        LEFT JOIN log_items l ON p.log_item_id = l.id
+        LEFT JOIN exams e ON p.exam_id = e.id
+        WHERE COALESCE(l.course_id, e.course_id) = ? AND p.id > ?
+        "#,
+    )
+    .bind(course.id)
+    .bind(course.webhook_problem_id)
+    .fetch_one(&mut *db)
+    .await?;
+
+    let url = format!("{}/p/{}", base_url, course.public_slug);
+    let message = if !course.webhook_announced {
+        published_message(&course.code, &course.title, &url)
+    } else if log_items.is_empty() && problem_count == 0 {
+        return Ok(());
+    } else {
+        let items: Vec<(&str, &str)> = log_items.iter().map(|(_, kind, title)| (kind.as_str(), title.as_str())).collect();
+        update_message(&course.code, &items, problem_count, &url)
+    };
+
+    client.post(&course.webhook_url, &json!({ "content": message, "text": message }), POST_TIMEOUT).await?;
+
+    // Content from before publishing isn't news to anyone who just got the link
+    let last_log_item_id = log_items.last().map_or(course.webhook_log_item_id, |(id, _, _)| *id);
+    sqlx::query("UPDATE courses SET webhook_announced = 1, webhook_log_item_id = ?, webhook_problem_id = ? WHERE id = ?")
+        .bind(last_log_item_id)
+        .bind(last_problem_id.unwrap_or(course.webhook_problem_id))
+        .bind(course.id)
+        .execute(&mut *db)
+        .await?;
+    Ok(())
+}
+
+fn published_message(code: &str, title: &str, url: &str) -> String {
+    format!("{} {} 的公开页面已上线：{}", code, title, url)
+}
+
+// New log items as (kind, title), oldest first
+fn update_message(code: &str, log_items: &[(&str, &str)], problem_count: i64, url: &str) -> String {
+    let mut counts = Vec::new();
+    if !log_items.is_empty() {
+        counts.push(format!("{} 条记录", log_items.len()));
+    }
+    if problem_count > 0 {
+        counts.push(format!("{} 道错题", problem_count));
+    }
+    let mut message = format!("{} 新增 {}：{}", code, counts.join("、"), url);
+    for (kind, title) in log_items.iter().take(MAX_LISTED) {
+        message.push_str(&format!("\n· {}：{}", kind_label_zh(kind), title));
+    }
+    if log_items.len() > MAX_LISTED {
+        message.push_str(&format!("\n……另有 {} 条", log_items.len() - MAX_LISTED));
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(parse_url("  "), Ok(None));
+        assert_eq!(parse_url(" https://discord.com/api/webhooks/1/abc "), Ok(Some("https://discord.com/api/webhooks/1/abc".to_string())));
+        assert!(parse_url("discord.com/api/webhooks/1/abc").is_err());
+    }
+
+    #[test]
+    fn test_messages() {
+        assert_eq!(published_message("MATH 1A", "微积分", "https://z.example.com/p/math-1a"), "MATH 1A 微积分 的公开页面已上线：https://z.example.com/p/math-1a");
+
+        let message = update_message("MATH 1A", &[("Homework", "HW3")], 2, "https://z.example.com/p/math-1a");
+        assert_eq!(message, "MATH 1A 新增 1 条记录、2 道错题：https://z.example.com/p/math-1a\n· 作业：HW3");
+
+        let items: Vec<(&str, &str)> = (0..7).map(|_| ("Lecture", "L")).collect();
+        let message = update_message("C", &items, 0, "u");
+        assert!(message.starts_with("C 新增 7 条记录：u\n") && message.ends_with("\n……另有 2 条"));
+    }
+}
@@ -0,0 +1,214 @@
+use chrono::{Datelike, Duration, NaiveDate};
+use rocket_db_pools::sqlx::{self, FromRow, SqliteConnection};
+use std::collections::{BTreeMap, BTreeSet};
+use crate::models::DEFAULT_COURSE_COLOR;
+
+// ========== Semester Wrapped ==========
+//
+// A look back at a semester once it is over: how much was logged and
+// reviewed, which category got the most practice, the busiest week and the
+// longest run of days with something done. Shown at /semesters/<id>/wrapped
+// and, once published under a slug, to anyone at /wrapped/<slug>, the way a
+// course's calendar is published at /p/<slug>. It covers everyone's work in
+// the semester's courses and is counted fresh each time.
+
+// The semester's problems, from its log items and its exams
+const SEMESTER_PROBLEMS: &str = r#"
+    WITH semester_problems AS (
+        SELECT p.id, p.created_at
+        FROM problems p
+        LEFT JOIN log_items l ON p.log_item_id = l.id
+        LEFT JOIN exams e ON p.exam_id = e.id
+        JOIN courses c ON c.id = COALESCE(l.course_id, e.course_id)
+        WHERE c.semester_id = ?
+    )
+"#;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct CourseWrapped {
+    pub code: String,
+    pub color: Option<String>,
+    pub log_items: i64,
+    pub problems: i64,
+}
+
+impl CourseWrapped {
+    pub fn display_color(&self) -> &str {
+        self.color.as_deref().unwrap_or(DEFAULT_COURSE_COLOR)
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct TopCategory {
+    pub name: String,
+    pub reviews: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BusiestWeek {
+    /// The Monday it starts on
+    pub start: NaiveDate,
+    /// Log items, problems and reviews dated in it
+    pub activity: usize,
+}
+
+impl BusiestWeek {
+    /// "10月12日 – 10月18日"
+    pub fn label(&self) -> String {
+        let end = self.start + Duration::days(6);
+        format!("{} – {}", self.start.format("%-m月%-d日"), end.format("%-m月%-d日"))
+    }
+}
+
+pub struct SemesterWrapped {
+    pub log_items: i64,
+    pub problems: i64,
+    pub reviews: i64,
+    pub courses: Vec<CourseWrapped>,
+    /// The category whose problems were reviewed most
+    pub top_category: Option<TopCategory>,
+    pub busiest_week: Option<BusiestWeek>,
+    /// Days with anything logged, added or reviewed
+    pub active_days: usize,
+    /// The most of those in a row
+    pub longest_streak: usize,
+}
+
+/// The week with the most of `days` (one entry per thing done); the earliest
+/// wins a tie.
+pub fn busiest_week(days: &[NaiveDate]) -> Option<BusiestWeek> {
+    let mut weeks: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+    for day in days {
+        let monday = *day - Duration::days(day.weekday().num_days_from_monday() as i64);
+        *weeks.entry(monday).or_default() += 1;
+    }
+    weeks
+        .into_iter()
+        .fold(None, |best: Option<BusiestWeek>, (start, activity)| match best {
+            Some(best) if best.activity >= activity => Some(best),
+            _ => Some(BusiestWeek { start, activity }),
+        })
+}
+
+/// Length of the longest run of consecutive days.
+pub fn longest_streak(days: &BTreeSet<NaiveDate>) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for day in days {
+        current = match previous {
+            Some(previous) if *day - previous == Duration::days(1) => current + 1,
+            _ => 1,
+        };
+        longest = longest.max(current);
+        previous = Some(*day);
+    }
+    longest
+}
+
+/// Everything on the wrapped page for `semester_id`.
+pub async fn collect(db: &mut SqliteConnection, semester_id: i64) -> SemesterWrapped {
+    let courses = sqlx::query_as::<_, CourseWrapped>(
+        r#"
+        SELECT
+            c.code, c.color,
+            (SELECT COUNT(*) FROM log_items l WHERE l.course_id = c.id) as log_items,
+            (SELECT COUNT(*) FROM problems p
+                LEFT JOIN log_items l ON p.log_item_id = l.id
+                LEFT JOIN exams e ON p.exam_id = e.id
+                WHERE COALESCE(l.course_id, e.course_id) = c.id) as problems
+        FROM courses c
+        WHERE c.semester_id = ?
+        ORDER BY c.code
+        "#,
+    )
+    .bind(semester_id)
+    .fetch_all(&mut *db)
+    .await
+    .unwrap_or_default();
+
+    let reviews: i64 = sqlx::query_scalar(&format!(
+        "{} SELECT COUNT(*) FROM study_reviews r JOIN semester_problems sp ON r.problem_id = sp.id",
+        SEMESTER_PROBLEMS
+    ))
+    .bind(semester_id)
+    .fetch_one(&mut *db)
+    .await
+    .unwrap_or(0);
+
+    let top_category = sqlx::query_as::<_, TopCategory>(&format!(
+        r#"{}
+        SELECT cat.name, COUNT(*) as reviews
+        FROM study_reviews r
+        JOIN semester_problems sp ON r.problem_id = sp.id
+        JOIN problem_categories pc ON pc.problem_id = r.problem_id
+        JOIN categories cat ON pc.category_id = cat.id
+        GROUP BY cat.id
+        ORDER BY reviews DESC, cat.name
+        LIMIT 1
+        "#,
+        SEMESTER_PROBLEMS
+    ))
+    .bind(semester_id)
+    .fetch_optional(&mut *db)
+    .await
+    .unwrap_or(None);
+
+    let activity: Vec<Option<String>> = sqlx::query_scalar(&format!(
+        r#"{}
+        SELECT date(l.date) FROM log_items l JOIN courses c ON l.course_id = c.id WHERE c.semester_id = ? AND date(l.date) IS NOT NULL
+        UNION ALL
+        SELECT date(created_at) FROM semester_problems WHERE created_at IS NOT NULL
+        UNION ALL
+        SELECT date(r.reviewed_at) FROM study_reviews r JOIN semester_problems sp ON r.problem_id = sp.id
+        "#,
+        SEMESTER_PROBLEMS
+    ))
+    .bind(semester_id)
+    .bind(semester_id)
+    .fetch_all(&mut *db)
+    .await
+    .unwrap_or_default();
+    let days: Vec<NaiveDate> = activity.iter().flatten().filter_map(|day| NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()).collect();
+    let active: BTreeSet<NaiveDate> = days.iter().copied().collect();
+
+    SemesterWrapped {
+        log_items: courses.iter().map(|c| c.log_items).sum(),
+        problems: courses.iter().map(|c| c.problems).sum(),
+        reviews,
+        courses,
+        top_category,
+        busiest_week: busiest_week(&days),
+        active_days: active.len(),
+        longest_streak: longest_streak(&active),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, d).unwrap()
+    }
+
+    #[test]
+    fn test_busiest_week() {
+        // Oct 5 and 12 2026 are Mondays
+        let week = busiest_week(&[day(1), day(5), day(7), day(7), day(12), day(18)]).unwrap();
+        assert_eq!(week, BusiestWeek { start: day(5), activity: 3 });
+        assert_eq!(week.label(), "10月5日 – 10月11日");
+
+        // A tie goes to the earlier week
+        assert_eq!(busiest_week(&[day(13), day(6)]).unwrap().start, day(5));
+        assert_eq!(busiest_week(&[]), None);
+    }
+
+    #[test]
+    fn test_longest_streak() {
+        let days = BTreeSet::from([day(1), day(2), day(4), day(5), day(6), day(9)]);
+        assert_eq!(longest_streak(&days), 3);
+        assert_eq!(longest_streak(&BTreeSet::from([day(30), day(31), day(31) + Duration::days(1)])), 3);
+        assert_eq!(longest_streak(&BTreeSet::new()), 0);
+    }
+}
@@ -0,0 +1,439 @@
+use chrono::{Datelike, Local, Timelike};
+
+// ========== Minimal ZIP Writer ==========
+//
+// Just enough of the ZIP format for export bundles: entries are stored
+// uncompressed (screenshots and PDFs barely compress anyway) with UTF-8 names,
+// and there is no ZIP64, so an archive must stay under 4 GiB. Every unzip
+// tool and OS file manager reads the result.
+
+pub struct ZipWriter {
+    buf: Vec<u8>,
+    central: Vec<u8>,
+    entries: u16,
+    time: u16,
+    date: u16,
+}
+
+impl Default for ZipWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ZipWriter {
+    pub fn new() -> Self {
+        // All entries get the time the archive was started, in DOS format
+        let now = Local::now();
+        let time = ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16;
+        let date = ((now.year().clamp(1980, 2107) - 1980) << 9) as u16 | ((now.month() << 5) | now.day()) as u16;
+        ZipWriter { buf: Vec::new(), central: Vec::new(), entries: 0, time, date }
+    }
+
+    pub fn add(&mut self, name: &str, data: &[u8]) {
+        let offset = self.buf.len() as u32;
+        let crc = crc32(data);
+        let size = data.len() as u32;
+
+        // Local file header
+        self.buf.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        self.push_common(crc, size, name);
+        self.buf.extend_from_slice(name.as_bytes());
+        self.buf.extend_from_slice(data);
+
+        // Central directory record, written out by `finish`
+        let c = &mut self.central;
+        c.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        c.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        let header = &self.buf[offset as usize + 4..offset as usize + 30];
+        c.extend_from_slice(header);
+        c.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        c.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        c.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        c.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        c.extend_from_slice(&offset.to_le_bytes());
+        c.extend_from_slice(name.as_bytes());
+
+        self.entries += 1;
+    }
+
+    // The 26 bytes shared by the local header and the central record
+    fn push_common(&mut self, crc: u32, size: u32, name: &str) {
+        let b = &mut self.buf;
+        b.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        b.extend_from_slice(&0x0800u16.to_le_bytes()); // flags: UTF-8 names
+        b.extend_from_slice(&0u16.to_le_bytes()); // method: stored
+        b.extend_from_slice(&self.time.to_le_bytes());
+        b.extend_from_slice(&self.date.to_le_bytes());
+        b.extend_from_slice(&crc.to_le_bytes());
+        b.extend_from_slice(&size.to_le_bytes()); // compressed
+        b.extend_from_slice(&size.to_le_bytes()); // uncompressed
+        b.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        b.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let offset = self.buf.len() as u32;
+        let size = self.central.len() as u32;
+        self.buf.extend_from_slice(&self.central);
+
+        // End of central directory
+        self.buf.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        self.buf.extend_from_slice(&0u16.to_le_bytes());
+        self.buf.extend_from_slice(&0u16.to_le_bytes());
+        self.buf.extend_from_slice(&self.entries.to_le_bytes());
+        self.buf.extend_from_slice(&self.entries.to_le_bytes());
+        self.buf.extend_from_slice(&size.to_le_bytes());
+        self.buf.extend_from_slice(&offset.to_le_bytes());
+        self.buf.extend_from_slice(&0u16.to_le_bytes());
+        self.buf
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// ========== Minimal ZIP Reader ==========
+//
+// The other direction, for imports: archives made by zip tools, OS file
+// managers and note apps' exports, whose entries are stored or deflated.
+// There is no ZIP64 or encryption, and names are read as UTF-8. The total
+// unpacked size is capped by the caller, checked while inflating, so a small
+// archive can't unpack into an unbounded amount of memory.
+
+pub struct ZipEntry {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ZipError {
+    NotZip,
+    /// Encrypted, ZIP64 or compressed with something other than deflate
+    Unsupported,
+    Corrupt,
+    TooLarge,
+}
+
+/// The files in a ZIP archive, in directory order and without directory
+/// entries, once they unpack to at most `max_bytes` in total.
+pub fn read(bytes: &[u8], max_bytes: usize) -> Result<Vec<ZipEntry>, ZipError> {
+    // The end of central directory record sits before an optional comment
+    let search_from = bytes.len().saturating_sub(22 + 0xffff);
+    let eocd = (search_from..bytes.len().saturating_sub(21))
+        .rev()
+        .find(|&i| bytes[i..i + 4] == 0x06054b50u32.to_le_bytes())
+        .ok_or(ZipError::NotZip)?;
+    let count = u16_at(bytes, eocd + 10)? as usize;
+    let mut pos = u32_at(bytes, eocd + 16)? as usize;
+
+    let mut entries = Vec::new();
+    let mut total = 0usize;
+    for _ in 0..count {
+        if u32_at(bytes, pos)? != 0x02014b50 {
+            return Err(ZipError::Corrupt);
+        }
+        let flags = u16_at(bytes, pos + 8)?;
+        let method = u16_at(bytes, pos + 10)?;
+        let crc = u32_at(bytes, pos + 16)?;
+        let compressed = u32_at(bytes, pos + 20)? as usize;
+        let size = u32_at(bytes, pos + 24)? as usize;
+        let name_len = u16_at(bytes, pos + 28)? as usize;
+        let extra_len = u16_at(bytes, pos + 30)? as usize;
+        let comment_len = u16_at(bytes, pos + 32)? as usize;
+        let offset = u32_at(bytes, pos + 42)? as usize;
+        let name = bytes.get(pos + 46..pos + 46 + name_len).ok_or(ZipError::Corrupt)?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        pos += 46 + name_len + extra_len + comment_len;
+
+        if name.ends_with('/') {
+            continue;
+        }
+        if flags & 1 != 0 || compressed == 0xffff_ffff || size == 0xffff_ffff {
+            return Err(ZipError::Unsupported);
+        }
+        if total + size > max_bytes {
+            return Err(ZipError::TooLarge);
+        }
+
+        // The local header repeats the name and may have its own extra field
+        if u32_at(bytes, offset)? != 0x04034b50 {
+            return Err(ZipError::Corrupt);
+        }
+        let start = offset + 30 + u16_at(bytes, offset + 26)? as usize + u16_at(bytes, offset + 28)? as usize;
+        let raw = bytes.get(start..start + compressed).ok_or(ZipError::Corrupt)?;
+        let data = match method {
+            0 => raw.to_vec(),
+            8 => inflate(raw, max_bytes - total)?,
+            _ => return Err(ZipError::Unsupported),
+        };
+        if data.len() != size || crc32(&data) != crc {
+            return Err(ZipError::Corrupt);
+        }
+        total += size;
+        entries.push(ZipEntry { name, data });
+    }
+    Ok(entries)
+}
+
+fn u16_at(bytes: &[u8], pos: usize) -> Result<u16, ZipError> {
+    let b = bytes.get(pos..pos + 2).ok_or(ZipError::Corrupt)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_at(bytes: &[u8], pos: usize) -> Result<u32, ZipError> {
+    let b = bytes.get(pos..pos + 4).ok_or(ZipError::Corrupt)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// Deflate (RFC 1951), decoding canonical Huffman codes a bit at a time the
+// way zlib's puff.c does: slow next to zlib, but plenty for notes.
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+/// The order code length code lengths are sent in
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u32,
+    len: u32,
+}
+
+impl Bits<'_> {
+    fn take(&mut self, n: u32) -> Result<u32, ZipError> {
+        while self.len < n {
+            let byte = *self.data.get(self.pos).ok_or(ZipError::Corrupt)?;
+            self.pos += 1;
+            self.buf |= (byte as u32) << self.len;
+            self.len += 8;
+        }
+        let value = self.buf & ((1 << n) - 1);
+        self.buf >>= n;
+        self.len -= n;
+        Ok(value)
+    }
+
+    // Stored blocks start on a byte boundary; what's left of the current byte
+    // is padding
+    fn align(&mut self) {
+        self.buf = 0;
+        self.len = 0;
+    }
+}
+
+struct Huffman {
+    /// Number of codes of each length
+    counts: [u16; 16],
+    /// Symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, ZipError> {
+        // Codes of each length follow on from the last code of the length before
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= bits.take(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - count < first {
+                return self.symbols.get((index + code - first) as usize).copied().ok_or(ZipError::Corrupt);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(ZipError::Corrupt)
+    }
+}
+
+fn inflate(data: &[u8], max_bytes: usize) -> Result<Vec<u8>, ZipError> {
+    let mut bits = Bits { data, pos: 0, buf: 0, len: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.take(1)? == 1;
+        match bits.take(2)? {
+            0 => {
+                bits.align();
+                let header = data.get(bits.pos..bits.pos + 4).ok_or(ZipError::Corrupt)?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                if len != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err(ZipError::Corrupt);
+                }
+                let start = bits.pos + 4;
+                let block = data.get(start..start + len as usize).ok_or(ZipError::Corrupt)?;
+                if out.len() + block.len() > max_bytes {
+                    return Err(ZipError::TooLarge);
+                }
+                out.extend_from_slice(block);
+                bits.pos = start + len as usize;
+            }
+            1 => {
+                let mut lengths = [8u8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut bits, &mut out, &literals, &distances, max_bytes)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_tables(&mut bits)?;
+                inflate_block(&mut bits, &mut out, &literals, &distances, max_bytes)?;
+            }
+            _ => return Err(ZipError::Corrupt),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn dynamic_tables(bits: &mut Bits) -> Result<(Huffman, Huffman), ZipError> {
+    let literal_count = bits.take(5)? as usize + 257;
+    let distance_count = bits.take(5)? as usize + 1;
+    let code_length_count = bits.take(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[i] = bits.take(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let total = literal_count + distance_count;
+    let mut lengths = Vec::with_capacity(total);
+    while lengths.len() < total {
+        let (length, repeat) = match code_lengths.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or(ZipError::Corrupt)?, 3 + bits.take(2)?),
+            17 => (0, 3 + bits.take(3)?),
+            _ => (0, 11 + bits.take(7)?),
+        };
+        if lengths.len() + repeat as usize > total {
+            return Err(ZipError::Corrupt);
+        }
+        lengths.resize(lengths.len() + repeat as usize, length);
+    }
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+fn inflate_block(bits: &mut Bits, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman, max_bytes: usize) -> Result<(), ZipError> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let i = symbol - 257;
+            if i >= LENGTH_BASE.len() {
+                return Err(ZipError::Corrupt);
+            }
+            let length = (LENGTH_BASE[i] as u32 + bits.take(LENGTH_EXTRA[i] as u32)?) as usize;
+            let i = distances.decode(bits)? as usize;
+            if i >= DISTANCE_BASE.len() {
+                return Err(ZipError::Corrupt);
+            }
+            let distance = (DISTANCE_BASE[i] as u32 + bits.take(DISTANCE_EXTRA[i] as u32)?) as usize;
+            if distance > out.len() {
+                return Err(ZipError::Corrupt);
+            }
+            // The copy may overlap what it writes, e.g. a run of one byte
+            let from = out.len() - distance;
+            for k in 0..length {
+                out.push(out[from + k]);
+            }
+        }
+        if out.len() > max_bytes {
+            return Err(ZipError::TooLarge);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_archive_layout() {
+        let mut zip = ZipWriter::new();
+        zip.add("manifest.json", b"{}");
+        zip.add("uploads/ab/cd.png", b"png");
+        let bytes = zip.finish();
+
+        assert_eq!(&bytes[..4], b"PK\x03\x04");
+        // Entry data follows the 30-byte header and the name
+        assert_eq!(&bytes[30 + "manifest.json".len()..][..2], b"{}");
+
+        let eocd = &bytes[bytes.len() - 22..];
+        assert_eq!(&eocd[..4], b"PK\x05\x06");
+        assert_eq!(u16::from_le_bytes([eocd[10], eocd[11]]), 2);
+        let cd_offset = u32::from_le_bytes(eocd[16..20].try_into().unwrap()) as usize;
+        assert_eq!(&bytes[cd_offset..cd_offset + 4], b"PK\x01\x02");
+    }
+
+    #[test]
+    fn test_read_round_trip() {
+        let mut zip = ZipWriter::new();
+        zip.add("笔记/HW3.md", b"# HW3");
+        zip.add("img/1.png", &[0; 100]);
+        let bytes = zip.finish();
+
+        let entries = read(&bytes, 1000).unwrap();
+        assert_eq!(entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), ["笔记/HW3.md", "img/1.png"]);
+        assert_eq!(entries[0].data, b"# HW3");
+        assert_eq!(read(&bytes, 50).err(), Some(ZipError::TooLarge));
+        assert_eq!(read(b"# HW3", 1000).err(), Some(ZipError::NotZip));
+    }
+
+    #[test]
+    fn test_inflate() {
+        // Raw deflate of "第一题 " × 5 then "积分 导数 极限\n" × 4, as zlib writes it
+        let data = [
+            0x7b, 0xbe, 0x66, 0xcd, 0x93, 0x1d, 0x0d, 0x2f, 0x17, 0xcd, 0x50, 0x78, 0x4e, 0x2c, 0x6b, 0xf9, 0xfa, 0xa7, 0x1d, 0x6d, 0x0a, 0x4f, 0xd7, 0xef,
+            0x79, 0x36, 0x75, 0x83, 0xc2, 0xb3, 0x79, 0x8d, 0x2f, 0x67, 0x4e, 0xe0, 0xa2, 0x50, 0x10, 0x00,
+        ];
+        let expected = format!("{}{}", "第一题 ".repeat(5), "积分 导数 极限\n".repeat(4));
+        assert_eq!(inflate(&data, 1000).unwrap(), expected.as_bytes());
+        assert_eq!(inflate(&data, 100).err(), Some(ZipError::TooLarge));
+        assert_eq!(inflate(&data[..20], 1000).err(), Some(ZipError::Corrupt));
+    }
+}
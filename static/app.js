@@ -0,0 +1,25 @@
+// Answers are Markdown with $…$ / $$…$$ math. The math is typeset first
+// and swapped back in afterwards so Markdown never mangles its underscores.
+// Without the libraries (offline) the raw text stays readable as is.
+function renderAnswer(el) {
+    if (el.dataset.rendered || !window.marked || !window.DOMPurify || !window.katex) return;
+    const math = [];
+    const source = el.textContent.replace(/\$\$([\s\S]+?)\$\$|\$([^$\n]+?)\$/g, (_, block, inline) => {
+        math.push(katex.renderToString(block ?? inline, { displayMode: block !== undefined, throwOnError: false }));
+        return `@@MATH${math.length - 1}@@`;
+    });
+    el.innerHTML = DOMPurify.sanitize(marked.parse(source)).replace(/@@MATH(\d+)@@/g, (_, i) => math[i]);
+    el.classList.remove('whitespace-pre-wrap');
+    el.dataset.rendered = '1';
+}
+htmx.onLoad((root) => root.querySelectorAll('.answer-body').forEach(renderAnswer));
+
+// Bodies over the configured size limits are refused with 413 before a
+// handler runs, and forms with a bad value with 422; htmx drops error
+// responses by default, so show the message that comes back instead.
+htmx.on('htmx:beforeSwap', (e) => {
+    if (e.detail.xhr.status === 413 || e.detail.xhr.status === 422) {
+        e.detail.shouldSwap = true;
+        e.detail.isError = false;
+    }
+});